@@ -0,0 +1,95 @@
+use std::collections::HashMap;
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion};
+use gossip::bench::{BenchUpdates, BenchView, ContentMessage, HeaderMessage, Message};
+use gossip::{Peer, Update};
+
+fn digests(count: usize) -> Vec<String> {
+    (0..count).map(|i| Update::new(i.to_string().into_bytes()).digest().clone()).collect()
+}
+
+fn peers(ports: std::ops::Range<u16>) -> Vec<Peer> {
+    ports.map(|port| Peer::try_new(&format!("127.0.0.1:{}", port)).unwrap()).collect()
+}
+
+fn header_message(c: &mut Criterion) {
+    let mut message = HeaderMessage::new_request("127.0.0.1:9000".to_owned(), true);
+    message.set_headers(digests(1_000));
+    let mut bytes = Vec::new();
+    message.write_bytes(&mut bytes).unwrap();
+
+    let mut group = c.benchmark_group("header_message_1k_digests");
+    group.bench_function("serialize", |b| b.iter(|| {
+        let mut buffer = Vec::with_capacity(bytes.len());
+        message.write_bytes(&mut buffer).unwrap();
+        buffer
+    }));
+    group.bench_function("deserialize", |b| b.iter(|| HeaderMessage::from_bytes(black_box(&bytes)).unwrap()));
+    group.finish();
+}
+
+fn content_message(c: &mut Criterion) {
+    let content: HashMap<String, Vec<u8>> = digests(100).into_iter().map(|digest| (digest, vec![7; 10 * 1024])).collect();
+    let message = ContentMessage::new_response("127.0.0.1:9000".to_owned(), content);
+    let mut bytes = Vec::new();
+    message.write_bytes(&mut bytes).unwrap();
+
+    let mut group = c.benchmark_group("content_message_100x10KB");
+    group.bench_function("serialize", |b| b.iter(|| {
+        let mut buffer = Vec::with_capacity(bytes.len());
+        message.write_bytes(&mut buffer).unwrap();
+        buffer
+    }));
+    group.bench_function("deserialize", |b| b.iter(|| ContentMessage::from_bytes(black_box(&bytes)).unwrap()));
+    group.finish();
+}
+
+fn view_select(c: &mut Criterion) {
+    // about half of the buffer is already in the view
+    let view = peers(10_000..10_030);
+    let buffer: Vec<Peer> = peers(10_022..10_030).into_iter().chain(peers(20_000..20_007)).collect();
+    c.bench_function("view_select_15_into_30", |b| b.iter_batched(
+        || BenchView::new("127.0.0.1:9000", view.clone()),
+        |mut view| view.select(30, 1, 7, black_box(&buffer)),
+        BatchSize::SmallInput,
+    ));
+}
+
+fn is_new(c: &mut Criterion) {
+    let removed = digests(10_000);
+    let updates = BenchUpdates::new(Vec::new(), removed.clone());
+    let unknown = Update::new(b"unknown".to_vec()).digest().clone();
+    let mut group = c.benchmark_group("is_new_10k_removed");
+    group.bench_function("unknown", |b| b.iter(|| updates.is_new(black_box(&unknown))));
+    group.bench_function("last_removed", |b| b.iter(|| updates.is_new(black_box(&removed[removed.len() - 1]))));
+    group.finish();
+}
+
+fn active_headers_for_push(c: &mut Criterion) {
+    let mut updates = BenchUpdates::new((0..10_000u32).map(|i| i.to_be_bytes().to_vec()).collect(), Vec::new());
+    let mut group = c.benchmark_group("active_headers_for_push_10k_active");
+    group.bench_function("all", |b| b.iter(|| updates.active_headers_for_push(None)));
+    group.bench_function("limited_100", |b| b.iter(|| updates.active_headers_for_push(Some(100))));
+    group.finish();
+}
+
+fn header_response(c: &mut Criterion) {
+    let mut updates = BenchUpdates::new((0..50_000u32).map(|i| i.to_be_bytes().to_vec()).collect(), Vec::new());
+    updates.publish();
+    let mut group = c.benchmark_group("header_response_50k_active");
+    group.bench_function("fresh", |b| b.iter(|| updates.header_response(false)));
+    group.bench_function("cached", |b| b.iter(|| updates.header_response(true)));
+    group.bench_function("fresh_serialized", |b| b.iter(|| {
+        let mut buffer = Vec::new();
+        updates.header_response(false).write_bytes(&mut buffer).unwrap();
+        buffer
+    }));
+    group.bench_function("cached_serialized", |b| b.iter(|| {
+        let mut buffer = Vec::new();
+        updates.header_response(true).write_bytes(&mut buffer).unwrap();
+        buffer
+    }));
+    group.finish();
+}
+
+criterion_group!(benches, header_message, content_message, view_select, is_new, active_headers_for_push, header_response);
+criterion_main!(benches);
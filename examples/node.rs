@@ -0,0 +1,107 @@
+//! Runs a gossip node from the command line.
+//!
+//! Received updates are printed on the standard output, and each line read from the standard input is
+//! submitted as a new update. The node is shut down on Ctrl-C.
+//!
+//! ```text
+//! cargo run --example node -- --bind 0.0.0.0:7000 --advertise 10.0.0.2:7000 --seed 10.0.0.1:7000 --period 1000
+//! ```
+
+use std::error::Error;
+use std::io::BufRead;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use gossip::{GossipConfig, GossipService, Peer, PeerSamplingConfig, Update, UpdateExpirationMode, UpdateHandler};
+
+const USAGE: &str = "Usage: node --bind <address> [--advertise <address>] [--seed <address>]... [--period <milliseconds>]";
+
+/// Command line options of the node
+#[derive(Debug, PartialEq)]
+pub struct Options {
+    /// Address to which the node is bound
+    pub bind: SocketAddr,
+    /// Address at which the peers reach the node, the bind address if not set
+    pub advertise: Option<SocketAddr>,
+    /// Addresses of the peers contacted when joining the network
+    pub seeds: Vec<String>,
+    /// Gossip and peer sampling period (milliseconds)
+    pub period: u64,
+}
+
+/// Parses the command line arguments, without the program name
+pub fn parse_args<I: Iterator<Item = String>>(mut args: I) -> Result<Options, String> {
+    let mut bind = None;
+    let mut advertise = None;
+    let mut seeds = Vec::new();
+    let mut period = 1000;
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or(format!("Missing value for {}", arg));
+        match arg.as_str() {
+            "--bind" => bind = Some(value()?.parse().map_err(|e| format!("Invalid bind address: {}", e))?),
+            "--advertise" => advertise = Some(value()?.parse().map_err(|e| format!("Invalid advertised address: {}", e))?),
+            "--seed" => seeds.push(value()?),
+            "--period" => period = value()?.parse().map_err(|e| format!("Invalid period: {}", e))?,
+            _ => return Err(format!("Unknown argument: {}", arg)),
+        }
+    }
+    let bind: SocketAddr = bind.ok_or("Missing bind address")?;
+    // the peers cannot connect to an unspecified address such as 0.0.0.0
+    if advertise.unwrap_or(bind).ip().is_unspecified() {
+        Err("An --advertise address is required when binding to an unspecified address")?
+    }
+    Ok(Options {
+        bind,
+        advertise,
+        seeds,
+        period,
+    })
+}
+
+/// Prints the received updates
+pub struct PrintingHandler;
+impl UpdateHandler for PrintingHandler {
+    fn on_update(&self, update: Update) {
+        println!("{}", String::from_utf8_lossy(update.content()));
+    }
+}
+
+/// Creates and starts a node with the given options
+pub fn start_node(options: &Options) -> Result<GossipService<PrintingHandler>, Box<dyn Error>> {
+    let mut service = GossipService::new(
+        options.advertise.unwrap_or(options.bind),
+        PeerSamplingConfig::new(true, true, options.period, 20, 2, 8),
+        GossipConfig::new(true, true, options.period, UpdateExpirationMode::MostRecent(1000, 0.5))
+    );
+    service.set_bind_address(options.bind);
+    service.start_with_peers(
+        options.seeds.iter().map(|seed| Peer::try_new(seed)).collect::<Result<_, _>>()?,
+        Box::new(PrintingHandler)
+    )?;
+    Ok(service)
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let options = parse_args(std::env::args().skip(1)).unwrap_or_else(|e| {
+        eprintln!("{}\n{}", e, USAGE);
+        std::process::exit(2);
+    });
+    let service = Arc::new(start_node(&options)?);
+
+    let signaled = Arc::clone(&service);
+    ctrlc::set_handler(move || {
+        if let Err(e) = signaled.shutdown() {
+            eprintln!("Error during shutdown: {}", e);
+        }
+        std::process::exit(0);
+    })?;
+
+    for line in std::io::stdin().lock().lines() {
+        let line = line?;
+        if !line.is_empty() {
+            service.submit(line.into_bytes())?;
+        }
+    }
+    // keep running once the input is closed
+    service.wait_for_shutdown();
+    Ok(())
+}
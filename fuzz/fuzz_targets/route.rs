@@ -0,0 +1,13 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use gossip::fuzzing::FuzzRouter;
+
+thread_local! {
+    static ROUTER: FuzzRouter = FuzzRouter::new();
+}
+
+fuzz_target!(|data: &[u8]| {
+    ROUTER.with(|router| {
+        let _ = router.route(data);
+    });
+});
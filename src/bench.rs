@@ -0,0 +1,98 @@
+//! Access to internal hot paths for the benchmarks of `benches/`, enabled by the `bench-internals` feature.
+//! Not part of the public API.
+
+use std::sync::Arc;
+pub use crate::message::Message;
+pub use crate::message::gossip::{HeaderMessage, ContentMessage};
+use crate::config::UpdateExpirationMode;
+use crate::digest::Blake3Digester;
+use crate::peer::Peer;
+use crate::sampling::View;
+use crate::update::UpdateDecorator;
+
+/// A peer sampling view, see [PeerSamplingService](crate::PeerSamplingService)
+pub struct BenchView {
+    view: View,
+}
+
+impl BenchView {
+    /// Creates a view holding the given peers
+    ///
+    /// # Arguments
+    ///
+    /// * `host_address` - Address of the node
+    /// * `peers` - Peers of the view
+    pub fn new(host_address: &str, peers: Vec<Peer>) -> Self {
+        let mut view = View::new(host_address.to_owned());
+        view.peers = peers;
+        BenchView {
+            view,
+        }
+    }
+
+    /// Merges a received buffer into the view, as done at each exchange. Returns the number of invalid peers.
+    ///
+    /// # Arguments
+    ///
+    /// * `c` - The size of the view
+    /// * `h` - The healing parameter
+    /// * `s` - The swap parameter
+    /// * `buffer` - The view received
+    pub fn select(&mut self, c: usize, h: usize, s: usize, buffer: &[Peer]) -> usize {
+        self.view.select(c, h, s, None, buffer)
+    }
+
+    pub fn peers(&self) -> &[Peer] {
+        &self.view.peers
+    }
+}
+
+/// The active and removed updates of a node, see [UpdateDecorator]
+pub struct BenchUpdates {
+    updates: UpdateDecorator,
+}
+
+impl BenchUpdates {
+    /// Creates the updates of a node, holding the given active updates and the given removed digests
+    ///
+    /// # Arguments
+    ///
+    /// * `active` - Contents of the active updates
+    /// * `removed` - Digests of the removed updates
+    pub fn new(active: Vec<Vec<u8>>, removed: Vec<String>) -> Self {
+        let mut updates = UpdateDecorator::new(UpdateExpirationMode::None, Arc::new(Blake3Digester));
+        for content in active {
+            let update = updates.create_update(content);
+            updates.insert_update(update).expect("Contents must be distinct");
+        }
+        for digest in removed {
+            updates.mark_removed(digest);
+        }
+        BenchUpdates {
+            updates,
+        }
+    }
+
+    pub fn is_new(&self, digest: &str) -> bool {
+        self.updates.is_new(digest)
+    }
+
+    pub fn active_headers_for_push(&mut self, max_headers: Option<usize>) -> Vec<String> {
+        self.updates.active_headers_for_push(max_headers)
+    }
+
+    /// Publishes the digests of the active updates, which header responses then reuse while they do not change
+    pub fn publish(&mut self) {
+        self.updates.publish()
+    }
+
+    /// Builds a header response with the digests of the active updates, reusing the published ones if `cached`
+    pub fn header_response(&self, cached: bool) -> HeaderMessage {
+        let mut response = HeaderMessage::new_response("127.0.0.1:9000".to_owned());
+        match self.updates.cached_headers().filter(|_| cached) {
+            Some(headers) => response.set_shared_headers(headers),
+            None => response.set_headers(self.updates.active_headers()),
+        }
+        response
+    }
+}
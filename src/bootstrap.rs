@@ -0,0 +1,77 @@
+use std::error::Error;
+use std::sync::{Mutex, OnceLock};
+use crate::peer::Peer;
+
+/// Where a node finds the first peers to contact, see [GossipService::start](crate::GossipService::start).
+///
+/// A list of peers converts into a [Static](BootstrapSource::Static) source, and a boxed closure into a
+/// [Closure](BootstrapSource::Closure) source. The source is kept by the peer sampler, which resolves it again
+/// when the node [bootstraps again](crate::GossipService::rebootstrap).
+pub enum BootstrapSource {
+    /// A fixed list of peers; an empty list makes the node wait for connections from other peers
+    Static(Vec<Peer>),
+    /// A closure called at each bootstrap, returning `None` to wait for connections from other peers
+    Closure(Box<dyn Fn() -> Option<Vec<Peer>> + Send>),
+    /// The addresses to which a host name resolves at each bootstrap, with the given port
+    #[cfg(feature = "dns")]
+    Dns(String, u16),
+}
+
+impl BootstrapSource {
+    /// Returns the first peers to contact, `None` if there is none
+    pub fn resolve(&self) -> Result<Option<Vec<Peer>>, Box<dyn Error>> {
+        match self {
+            BootstrapSource::Static(peers) => Ok(Some(peers.clone())),
+            BootstrapSource::Closure(closure) => Ok(closure()),
+            #[cfg(feature = "dns")]
+            BootstrapSource::Dns(host, port) => {
+                use std::net::ToSocketAddrs;
+                use crate::logging::targets::SAMPLING;
+                let peers: Vec<Peer> = match (host.as_str(), *port).to_socket_addrs() {
+                    Ok(addresses) => addresses.map(Peer::from).collect(),
+                    Err(e) => Err(format!("Could not resolve bootstrap host {}: {}", host, e))?,
+                };
+                node_log!(Info, SAMPLING, "Bootstrap host {} resolved to {} peers", host, peers.len());
+                Ok(Some(peers))
+            }
+        }
+    }
+
+    /// Wraps a closure of the former `start` signature, which can only be called once: it is called when the
+    /// source is first resolved, and its result is returned at each following bootstrap.
+    #[deprecated(note = "the closure cannot be called again to bootstrap the node, use a `Fn() + Send` closure")]
+    pub fn once(closure: Box<dyn FnOnce() -> Option<Vec<Peer>> + Send>) -> Self {
+        let closure = Mutex::new(Some(closure));
+        let peers = OnceLock::new();
+        BootstrapSource::Closure(Box::new(move || {
+            peers.get_or_init(|| closure.lock().unwrap().take().and_then(|closure| closure())).clone()
+        }))
+    }
+}
+
+impl Default for BootstrapSource {
+    /// No peer: the node waits for connections from other peers
+    fn default() -> Self {
+        BootstrapSource::Static(Vec::new())
+    }
+}
+
+impl From<Vec<Peer>> for BootstrapSource {
+    fn from(peers: Vec<Peer>) -> Self {
+        BootstrapSource::Static(peers)
+    }
+}
+
+impl From<Box<dyn Fn() -> Option<Vec<Peer>> + Send>> for BootstrapSource {
+    fn from(closure: Box<dyn Fn() -> Option<Vec<Peer>> + Send>) -> Self {
+        BootstrapSource::Closure(closure)
+    }
+}
+
+impl<F> From<Box<F>> for BootstrapSource
+where F: Fn() -> Option<Vec<Peer>> + Send + 'static
+{
+    fn from(closure: Box<F>) -> Self {
+        BootstrapSource::Closure(closure)
+    }
+}
@@ -0,0 +1,87 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpListener, UdpSocket};
+use std::io::Read;
+use std::time::{Duration, Instant};
+use std::collections::HashMap;
+use std::error::Error;
+use crate::update::Update;
+use crate::config::NetworkConfig;
+use crate::message::{Message, MessageType, MASK_MESSAGE_PROTOCOL, MESSAGE_PROTOCOL_CONTENT_MESSAGE};
+use crate::message::gossip::{HeaderMessage, ContentMessage};
+use crate::logging::targets::NETWORK;
+
+/// Broadcasts a single update through an existing node without running a full member node.
+///
+/// A temporary listener is started on an ephemeral port and the digest of the update is pushed to the seed
+/// as a transient peer. The call returns once the seed has requested the content and the content has been
+/// sent to it. The seed must have push enabled, use the default [digest function](crate::Blake3Digester), not belong
+/// to a [cluster](crate::GossipConfig::set_cluster_id), and the update must be new to the seed.
+///
+/// # Arguments
+///
+/// * `seed` - Address of an existing node of the network
+/// * `bytes` - Content of the update
+/// * `timeout` - Maximum time to wait for the seed to request the content
+///
+/// Returns the digest of the update.
+pub fn broadcast_once(seed: SocketAddr, bytes: Vec<u8>, timeout: Duration) -> Result<String, Box<dyn Error>> {
+    let deadline = Instant::now() + timeout;
+    let update = Update::new(bytes);
+
+    // listen on the local interface used to reach the seed; connecting a UDP socket only selects the route,
+    // without sending anything to the seed
+    let unspecified = match seed {
+        SocketAddr::V4(_) => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+        SocketAddr::V6(_) => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+    };
+    let socket = UdpSocket::bind(SocketAddr::new(unspecified, 0))?;
+    socket.connect(seed)?;
+    let local_ip = socket.local_addr()?.ip();
+    let listener = TcpListener::bind(SocketAddr::new(local_ip, 0))?;
+    listener.set_nonblocking(true)?;
+    let address = listener.local_addr()?;
+    node_log!(Info, NETWORK, "Transient listener started at {}", address);
+
+    // push the digest to the seed
+    let mut header_request = HeaderMessage::new_request(address.to_string(), false);
+    header_request.set_headers(vec![update.digest().to_owned()]);
+    crate::network::send(&seed, &header_request, None, &NetworkConfig::default())?;
+
+    // wait for the content request
+    while Instant::now() < deadline {
+        match listener.accept() {
+            Ok((mut stream, _)) => {
+                stream.set_nonblocking(false)?;
+                stream.set_read_timeout(Some(deadline.saturating_duration_since(Instant::now()).max(Duration::from_millis(1))))?;
+                let mut buffer = Vec::new();
+                if let Err(e) = stream.read_to_end(&mut buffer) {
+                    node_log!(Debug, NETWORK, "Could not read connection to transient listener: {:?}", e);
+                    continue;
+                }
+                if buffer.is_empty() || buffer[0] & MASK_MESSAGE_PROTOCOL != MESSAGE_PROTOCOL_CONTENT_MESSAGE {
+                    continue;
+                }
+                let request = match ContentMessage::from_bytes(&buffer[1..]) {
+                    Ok(request) => request,
+                    Err(e) => {
+                        node_log!(Debug, NETWORK, "Could not parse message received by transient listener: {:?}", e);
+                        continue;
+                    }
+                };
+                if *request.message_type() != MessageType::Request {
+                    continue;
+                }
+                let sender = request.sender().parse::<SocketAddr>()?;
+                if request.content().contains_key(update.digest()) {
+                    let mut content = HashMap::new();
+                    content.insert(update.digest().to_owned(), update.content().to_vec());
+                    crate::network::send(&sender, &ContentMessage::new_response(address.to_string(), content), None, &NetworkConfig::default())?;
+                    node_log!(Info, NETWORK, "Update {} sent to {}", update.digest(), sender);
+                    return Ok(update.digest().to_owned());
+                }
+            }
+            Err(ref e) if e.kind() == std::io::ErrorKind::WouldBlock => std::thread::sleep(Duration::from_millis(10)),
+            Err(e) => Err(e)?,
+        }
+    }
+    Err(format!("Seed {} did not request update {} before timeout", seed, update.digest()))?
+}
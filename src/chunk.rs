@@ -0,0 +1,261 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use serde::{Serialize, Deserialize};
+use crate::update::{Update, UpdateHandler};
+use crate::digest::{Blake3Digester, Digester};
+use crate::logging::targets::UPDATES;
+
+/// Prefix identifying the content of a chunk update
+const CHUNK_PREFIX: &[u8] = b"\x00gossip-chunk\x00";
+/// Prefix identifying the content of a manifest update
+const MANIFEST_PREFIX: &[u8] = b"\x00gossip-manifest\x00";
+
+/// Describes how the chunks of a large update are reassembled
+#[derive(Debug, Serialize, Deserialize)]
+struct Manifest {
+    /// Digest of the original content
+    digest: String,
+    /// Digests of the chunk updates, in order
+    chunks: Vec<String>,
+}
+
+/// Splits content into chunk updates followed by a manifest update.
+/// Each returned element is the content of an update to be submitted; the manifest is the last one.
+///
+/// # Arguments
+///
+/// * `bytes` - The original content
+/// * `chunk_size` - The maximum size of each chunk
+pub fn split_into_chunks(bytes: &[u8], chunk_size: usize) -> Vec<Vec<u8>> {
+    split_into_chunks_with(&Blake3Digester, bytes, chunk_size)
+}
+
+/// Splits content into chunk updates followed by a manifest update, as [split_into_chunks], for a network
+/// using another digest function than the default one.
+///
+/// # Arguments
+///
+/// * `digester` - The digest function of the network
+/// * `bytes` - The original content
+/// * `chunk_size` - The maximum size of each chunk
+pub fn split_into_chunks_with(digester: &dyn Digester, bytes: &[u8], chunk_size: usize) -> Vec<Vec<u8>> {
+    let mut contents = Vec::new();
+    let mut manifest = Manifest {
+        digest: digester.digest(bytes),
+        chunks: Vec::new(),
+    };
+    for chunk in bytes.chunks(chunk_size.max(1)) {
+        let mut content = Vec::with_capacity(CHUNK_PREFIX.len() + chunk.len());
+        content.extend_from_slice(CHUNK_PREFIX);
+        content.extend_from_slice(chunk);
+        manifest.chunks.push(digester.digest(&content));
+        contents.push(content);
+    }
+    let mut content = MANIFEST_PREFIX.to_vec();
+    content.append(&mut serde_cbor::to_vec(&manifest).expect("Manifest serialization cannot fail"));
+    contents.push(content);
+    contents
+}
+
+/// A received chunk
+struct PendingChunk {
+    content: Vec<u8>,
+    /// Time of reception, or of the last reassembly the chunk was part of
+    received: Instant,
+    /// Number of pending manifests listing the chunk
+    manifests: usize,
+    /// Whether the chunk was part of a reassembled update
+    reassembled: bool,
+}
+
+/// Chunks and manifests received but not yet reassembled
+struct PendingChunks {
+    /// Chunks by digest. A chunk listed by a pending manifest is kept as long as the manifest; the others are
+    /// kept until the timeout, including those of reassembled updates, which a manifest received later may share.
+    chunks: HashMap<String, PendingChunk>,
+    /// Manifests by original digest, with time of reception
+    manifests: HashMap<String, (Manifest, Instant)>,
+}
+
+impl PendingChunks {
+    /// Adds a manifest, counting it in the chunks it lists
+    fn add_manifest(&mut self, manifest: Manifest) {
+        if self.manifests.contains_key(&manifest.digest) {
+            return;
+        }
+        for chunk in &manifest.chunks {
+            if let Some(pending) = self.chunks.get_mut(chunk) {
+                pending.manifests += 1;
+            }
+        }
+        self.manifests.insert(manifest.digest.clone(), (manifest, Instant::now()));
+    }
+
+    /// Adds a chunk, counting the pending manifests that list it
+    fn add_chunk(&mut self, digest: String, content: Vec<u8>) {
+        let manifests = self.manifests.values().filter(|(manifest, _)| manifest.chunks.contains(&digest)).count();
+        self.chunks.entry(digest).or_insert(PendingChunk {
+            content,
+            received: Instant::now(),
+            manifests,
+            reassembled: false,
+        });
+    }
+
+    /// Removes a manifest, releasing the chunks it lists
+    fn remove_manifest(&mut self, digest: &str) -> Option<Manifest> {
+        let (manifest, _) = self.manifests.remove(digest)?;
+        for chunk in &manifest.chunks {
+            if let Some(pending) = self.chunks.get_mut(chunk) {
+                pending.manifests = pending.manifests.saturating_sub(1);
+            }
+        }
+        Some(manifest)
+    }
+
+    /// Drops the manifests older than the timeout, then the chunks older than the timeout that no pending
+    /// manifest lists
+    fn expire(&mut self, timeout: Duration) {
+        let expired: Vec<String> = self.manifests.iter()
+            .filter(|(_, (_, received))| received.elapsed() >= timeout)
+            .map(|(digest, _)| digest.to_owned())
+            .collect();
+        for digest in expired {
+            self.remove_manifest(&digest);
+        }
+        self.chunks.retain(|_, chunk| chunk.manifests > 0 || chunk.received.elapsed() < timeout);
+    }
+}
+
+/// An [UpdateHandler] adapter that reassembles updates submitted with
+/// [submit_chunked](crate::GossipService::submit_chunked) before passing them to the wrapped handler.
+///
+/// Updates that are not chunked are passed through unchanged. Manifests of incomplete updates are dropped
+/// once they are older than the timeout, and chunks once they are older than the timeout and no pending
+/// manifest lists them.
+pub struct ChunkedUpdateHandler<H> {
+    /// The application handler receiving reassembled updates
+    handler: H,
+    /// Maximum time to wait for all the parts of an update
+    timeout: Duration,
+    /// Parts waiting for reassembly
+    pending: Mutex<PendingChunks>,
+    /// Digest function of the network
+    digester: Arc<dyn Digester>,
+}
+
+impl<H> ChunkedUpdateHandler<H>
+where H: UpdateHandler
+{
+    /// Creates a new adapter
+    ///
+    /// # Arguments
+    ///
+    /// * `handler` - The application handler receiving reassembled updates
+    /// * `timeout` - Maximum time to wait for all the parts of an update
+    pub fn new(handler: H, timeout: Duration) -> Self {
+        Self::new_with_digester(handler, timeout, crate::digest::default_digester())
+    }
+
+    /// Creates a new adapter for a network using another digest function than the default one
+    ///
+    /// # Arguments
+    ///
+    /// * `digester` - The digest function of the network, see [GossipConfig::set_digester](crate::GossipConfig::set_digester)
+    pub fn new_with_digester(handler: H, timeout: Duration, digester: Arc<dyn Digester>) -> Self {
+        ChunkedUpdateHandler {
+            handler,
+            timeout,
+            pending: Mutex::new(PendingChunks {
+                chunks: HashMap::new(),
+                manifests: HashMap::new(),
+            }),
+            digester,
+        }
+    }
+
+    /// Returns the wrapped handler
+    pub fn handler(&self) -> &H {
+        &self.handler
+    }
+
+    /// Returns the number of chunks and manifests waiting for reassembly. The chunks of reassembled updates that
+    /// are kept for the manifests sharing them are not counted, unless a pending manifest lists them.
+    pub fn pending_count(&self) -> usize {
+        let pending = self.pending.lock().unwrap();
+        pending.chunks.values().filter(|chunk| chunk.manifests > 0 || !chunk.reassembled).count() + pending.manifests.len()
+    }
+
+    /// Reassembles the update described by the manifest if all its chunks were received
+    fn try_assemble(&self, pending: &mut PendingChunks, digest: &str) -> Option<Update> {
+        let (manifest, _) = pending.manifests.get(digest)?;
+        if !manifest.chunks.iter().all(|chunk| pending.chunks.contains_key(chunk)) {
+            return None;
+        }
+        let mut content = Vec::new();
+        for chunk in &manifest.chunks {
+            content.extend_from_slice(&pending.chunks[chunk].content[CHUNK_PREFIX.len()..]);
+        }
+        let manifest = pending.remove_manifest(digest)?;
+        let now = Instant::now();
+        for chunk in &manifest.chunks {
+            if let Some(chunk) = pending.chunks.get_mut(chunk) {
+                chunk.received = now;
+                chunk.reassembled = true;
+            }
+        }
+        let update = Update::new_with(self.digester.as_ref(), content);
+        if *update.digest() == manifest.digest {
+            Some(update)
+        }
+        else {
+            node_log!(Warn, UPDATES, "Reassembled content does not match digest {}", manifest.digest);
+            None
+        }
+    }
+}
+
+impl<H> UpdateHandler for ChunkedUpdateHandler<H>
+where H: UpdateHandler
+{
+    fn on_update(&self, update: Update) {
+        let content = update.content();
+        let assembled = if content.starts_with(CHUNK_PREFIX) || content.starts_with(MANIFEST_PREFIX) {
+            let mut pending = self.pending.lock().unwrap();
+            pending.expire(self.timeout);
+
+            if content.starts_with(CHUNK_PREFIX) {
+                let chunk_digest = update.digest().to_owned();
+                pending.add_chunk(chunk_digest.clone(), update.content().to_vec());
+                let digests: Vec<String> = pending.manifests.iter()
+                    .filter(|(_, (manifest, _))| manifest.chunks.contains(&chunk_digest))
+                    .map(|(digest, _)| digest.to_owned())
+                    .collect();
+                // the chunk completes every manifest sharing it
+                digests.iter().filter_map(|digest| self.try_assemble(&mut pending, digest)).collect()
+            }
+            else {
+                match serde_cbor::from_slice::<Manifest>(&content[MANIFEST_PREFIX.len()..]) {
+                    Ok(manifest) => {
+                        let digest = manifest.digest.clone();
+                        pending.add_manifest(manifest);
+                        self.try_assemble(&mut pending, &digest).into_iter().collect()
+                    }
+                    Err(e) => {
+                        node_log!(Warn, UPDATES, "Invalid manifest {}: {:?}", update.digest(), e);
+                        Vec::new()
+                    }
+                }
+            }
+        }
+        else {
+            vec![update]
+        };
+
+        // deliver outside of the lock
+        for update in assembled {
+            self.handler.on_update(update);
+        }
+    }
+}
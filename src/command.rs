@@ -0,0 +1,274 @@
+use std::sync::mpsc::{Receiver, RecvTimeoutError, TryRecvError};
+use std::time::{Duration, Instant};
+use rand::Rng;
+
+/// Commands for the activities of the timer thread of a service, see [Timers]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Command {
+    /// Starts a round immediately, even while paused
+    ForceRound,
+    /// Skips the scheduled rounds until resumed
+    Pause,
+    /// Resumes the scheduled rounds
+    Resume,
+    /// Schedules the next round with a new period (milliseconds), counted from the last round
+    UpdatePeriod(u64),
+    /// Brings the next round forward, once the submissions following this one are coalesced
+    Submitted,
+    /// Stops the activity; the thread stops with the [gossip](Timer::Gossip) activity
+    Shutdown,
+}
+
+/// Time during which submitted updates are coalesced before the round they bring forward
+pub(crate) const SUBMISSION_COALESCING: Duration = Duration::from_millis(10);
+
+/// Activities of the timer thread of a node, each with its own rounds
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Timer {
+    /// The gossip rounds, which own the thread: the thread stops when they are shut down
+    Gossip,
+    /// The rounds of the peer sampling service
+    Sampling,
+    /// The keepalive pings, see [GossipConfig::set_keepalive](crate::GossipConfig::set_keepalive)
+    Keepalive,
+}
+
+/// Runs a round of an activity, forced or once due, and returns the delay until the next one
+pub(crate) type Round = Box<dyn FnMut(bool) -> Duration + Send>;
+
+/// Returns the delay between two rounds of an activity once its period (milliseconds) changed
+pub(crate) type Reschedule = Box<dyn Fn(u64) -> Duration + Send>;
+
+/// An activity of the timer thread and the schedule of its next round
+struct Activity {
+    timer: Timer,
+    round: Round,
+    reschedule: Option<Reschedule>,
+    /// Time of the last round, from which the delay of the next one is counted
+    woken: Instant,
+    delay: Duration,
+    /// Time before which submitted updates bring the next round forward, once coalesced
+    submitted: Option<Instant>,
+    paused: bool,
+}
+
+impl Activity {
+    fn deadline(&self) -> Instant {
+        let due = self.woken + self.delay;
+        self.submitted.map_or(due, |submitted| submitted.min(due))
+    }
+}
+
+/// Runs the rounds of the activities of a node on a single thread, each at its own deadline, and receives their
+/// commands between the rounds
+pub(crate) struct Timers {
+    receiver: Receiver<(Timer, Command)>,
+    activities: Vec<Activity>,
+}
+
+impl Timers {
+    pub fn new(receiver: Receiver<(Timer, Command)>) -> Self {
+        Timers {
+            receiver,
+            activities: Vec::new(),
+        }
+    }
+
+    /// Adds an activity
+    ///
+    /// # Arguments
+    ///
+    /// * `timer` - The activity, to which the commands are sent
+    /// * `first` - Delay until its first round
+    /// * `round` - Runs a round and returns the delay until the next one
+    /// * `reschedule` - Returns the delay between two rounds once the period changed, for the activities whose
+    ///   period can be [updated](Command::UpdatePeriod)
+    pub fn add(&mut self, timer: Timer, first: Duration, round: Round, reschedule: Option<Reschedule>) {
+        self.activities.retain(|activity| activity.timer != timer);
+        self.activities.push(Activity {
+            timer,
+            round,
+            reschedule,
+            woken: Instant::now(),
+            delay: first,
+            submitted: None,
+            paused: false,
+        });
+    }
+
+    /// Runs the rounds of the activities as they are due, until the gossip activity is shut down or the senders of
+    /// the commands are dropped. While an activity is paused, its deadline is ignored until it is resumed.
+    pub fn run(&mut self) {
+        while self.step() {}
+    }
+
+    /// Waits for the next round or command and handles it; returns `false` once the thread must stop
+    fn step(&mut self) -> bool {
+        let next = self.activities.iter().enumerate()
+            .filter(|(_, activity)| !activity.paused)
+            .map(|(index, activity)| (index, activity.deadline()))
+            .min_by_key(|(_, deadline)| *deadline);
+        let command = match next {
+            // commands are still handled when rounds last longer than the period
+            Some((_, deadline)) => match deadline.saturating_duration_since(Instant::now()) {
+                timeout if timeout == Duration::from_millis(0) => self.receiver.try_recv().map_err(|e| match e {
+                    TryRecvError::Empty => RecvTimeoutError::Timeout,
+                    TryRecvError::Disconnected => RecvTimeoutError::Disconnected,
+                }),
+                timeout => self.receiver.recv_timeout(timeout),
+            },
+            None => self.receiver.recv().map_err(|_| RecvTimeoutError::Disconnected),
+        };
+        let (timer, command) = match command {
+            Ok(command) => command,
+            Err(RecvTimeoutError::Timeout) => {
+                if let Some((index, _)) = next {
+                    self.run_round(index, false);
+                }
+                return true;
+            }
+            Err(RecvTimeoutError::Disconnected) => return false,
+        };
+        if (timer, command) == (Timer::Gossip, Command::Shutdown) {
+            return false;
+        }
+        let Some(index) = self.activities.iter().position(|activity| activity.timer == timer) else {
+            return true;
+        };
+        let activity = &mut self.activities[index];
+        match command {
+            Command::ForceRound => self.run_round(index, true),
+            Command::Pause => activity.paused = true,
+            Command::Resume => activity.paused = false,
+            Command::UpdatePeriod(period) => {
+                if let Some(reschedule) = &activity.reschedule {
+                    activity.delay = reschedule(period);
+                }
+            }
+            Command::Submitted => {
+                let coalesced = Instant::now() + SUBMISSION_COALESCING;
+                activity.submitted = Some(activity.submitted.map_or(coalesced, |submitted| submitted.min(coalesced)));
+            }
+            Command::Shutdown => {
+                self.activities.remove(index);
+            }
+        }
+        true
+    }
+
+    fn run_round(&mut self, index: usize, forced: bool) {
+        let activity = &mut self.activities[index];
+        activity.woken = Instant::now();
+        activity.submitted = None;
+        activity.delay = (activity.round)(forced);
+    }
+}
+
+/// Returns the time between two rounds: the period and a random part of the deviation
+///
+/// # Arguments
+///
+/// * `period` - Period of the rounds (milliseconds)
+/// * `deviation` - Maximum deviation from the period (milliseconds)
+pub(crate) fn round_delay(period: u64, deviation: u64) -> Duration {
+    let deviation = if deviation == 0 { 0 } else { rand::thread_rng().gen_range(0, deviation) };
+    Duration::from_millis(period + deviation)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+    use std::sync::mpsc::channel;
+
+    /// Returns a round recording whether it was forced, and run again after the delay
+    fn recorded(rounds: &Arc<Mutex<Vec<(Timer, bool)>>>, timer: Timer, delay: Duration) -> Round {
+        let rounds = Arc::clone(rounds);
+        Box::new(move |forced| {
+            rounds.lock().unwrap().push((timer, forced));
+            delay
+        })
+    }
+
+    #[test]
+    fn commands_reach_their_activity() {
+        let (sender, receiver) = channel();
+        let rounds = Arc::new(Mutex::new(Vec::new()));
+        let mut timers = Timers::new(receiver);
+        timers.add(Timer::Gossip, Duration::from_secs(60), recorded(&rounds, Timer::Gossip, Duration::from_secs(60)), Some(Box::new(Duration::from_millis)));
+        timers.add(Timer::Sampling, Duration::from_secs(60), recorded(&rounds, Timer::Sampling, Duration::from_secs(60)), None);
+
+        sender.send((Timer::Sampling, Command::ForceRound)).unwrap();
+        assert!(timers.step());
+        assert_eq!(vec![(Timer::Sampling, true)], *rounds.lock().unwrap());
+        // the gossip round is due once rescheduled with the short period
+        sender.send((Timer::Gossip, Command::UpdatePeriod(10))).unwrap();
+        assert!(timers.step());
+        assert!(timers.step());
+        assert_eq!((Timer::Gossip, false), rounds.lock().unwrap()[1]);
+
+        // the shutdown of another activity only removes it
+        sender.send((Timer::Sampling, Command::Shutdown)).unwrap();
+        assert!(timers.step());
+        assert_eq!(1, timers.activities.len());
+        sender.send((Timer::Sampling, Command::ForceRound)).unwrap();
+        assert!(timers.step());
+        assert_eq!(2, rounds.lock().unwrap().len());
+        // commands are handled even if a deadline has passed
+        timers.activities[0].delay = Duration::from_millis(0);
+        sender.send((Timer::Gossip, Command::Shutdown)).unwrap();
+        assert!(!timers.step());
+        drop(sender);
+        assert!(!timers.step());
+    }
+
+    #[test]
+    fn submissions_are_coalesced_into_one_round() {
+        let (sender, receiver) = channel();
+        let rounds = Arc::new(Mutex::new(Vec::new()));
+        let mut timers = Timers::new(receiver);
+        timers.add(Timer::Gossip, Duration::from_secs(60), recorded(&rounds, Timer::Gossip, Duration::from_secs(60)), None);
+        let started = Instant::now();
+        sender.send((Timer::Gossip, Command::Submitted)).unwrap();
+        let delayed = sender.clone();
+        std::thread::spawn(move || {
+            std::thread::sleep(SUBMISSION_COALESCING / 2);
+            delayed.send((Timer::Gossip, Command::Submitted)).unwrap();
+        });
+        while rounds.lock().unwrap().is_empty() {
+            assert!(timers.step());
+        }
+        let elapsed = started.elapsed();
+        assert!(elapsed >= SUBMISSION_COALESCING && elapsed < SUBMISSION_COALESCING * 5, "round after {:?}", elapsed);
+        // the second submission did not postpone the round, and was coalesced into it
+        assert!(timers.receiver.try_recv().is_err());
+        assert_eq!(None, timers.activities[0].submitted);
+    }
+
+    #[test]
+    fn paused_rounds_wait_for_resume() {
+        let (sender, receiver) = channel();
+        let rounds = Arc::new(Mutex::new(Vec::new()));
+        let mut timers = Timers::new(receiver);
+        timers.add(Timer::Keepalive, Duration::from_millis(0), recorded(&rounds, Timer::Keepalive, Duration::from_millis(0)), None);
+        assert!(timers.step());
+        assert_eq!(vec![(Timer::Keepalive, false)], *rounds.lock().unwrap());
+
+        sender.send((Timer::Keepalive, Command::Pause)).unwrap();
+        assert!(timers.step());
+        let delayed = sender.clone();
+        std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(50));
+            delayed.send((Timer::Keepalive, Command::ForceRound)).unwrap();
+            delayed.send((Timer::Keepalive, Command::Resume)).unwrap();
+        });
+        // the past deadline is ignored while paused, forced rounds are not
+        let started = Instant::now();
+        assert!(timers.step());
+        assert!(started.elapsed() >= Duration::from_millis(50));
+        assert_eq!((Timer::Keepalive, true), rounds.lock().unwrap()[1]);
+        assert!(timers.step());
+        assert!(timers.step());
+        assert_eq!((Timer::Keepalive, false), rounds.lock().unwrap()[2]);
+    }
+}
@@ -1,15 +1,50 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use crate::digest::Digester;
+use crate::fetch::FetchPolicy;
+use crate::peer::Peer;
+
+/// Default quarantine of departed peers, in sampling periods
+const DEFAULT_LEAVE_QUARANTINE_PERIODS: u64 = 10;
+/// Default maximum number of peers in an exchanged buffer
+const DEFAULT_MAX_VIEW_MESSAGE_PEERS: usize = 4096;
+/// Minimum number of gossip rounds during which an update expiring after a duration should be pushed
+const MIN_ROUNDS_BEFORE_EXPIRATION: u64 = 2;
+/// Ratio between the sampling and gossip periods above which gossip may run without peers for a long time
+const MAX_SAMPLING_TO_GOSSIP_RATIO: u64 = 100;
+
 /// The peer sampling parameters
 ///
 /// See: [Gossip-based Peer Sampling](https://infoscience.epfl.ch/record/109297/files/all.pdf)
 #[derive(Clone)]
+#[cfg_attr(feature = "config-serde", derive(serde::Serialize, serde::Deserialize), serde(default, deny_unknown_fields))]
 pub struct PeerSamplingConfig {
     push: bool,
     pull: bool,
+    #[cfg_attr(feature = "config-serde", serde(with = "crate::node_config::millis"))]
     sampling_period: u64,
+    #[cfg_attr(feature = "config-serde", serde(with = "crate::node_config::millis"))]
     sampling_deviation: u64,
     view_size: usize,
     healing_factor: usize,
     swapping_factor: usize,
+    max_peer_age: Option<u16>,
+    #[cfg_attr(feature = "config-serde", serde(with = "crate::node_config::option_millis", skip_serializing_if = "Option::is_none"))]
+    graceful_leave: Option<u64>,
+    #[cfg_attr(feature = "config-serde", serde(with = "crate::node_config::millis"))]
+    leave_quarantine: u64,
+    max_view_message_peers: usize,
+    max_incoming_buffer: Option<usize>,
+    max_peer_queue: Option<usize>,
+    queue_new_peers_immediately: bool,
+    initial_jitter: f64,
+    #[cfg_attr(feature = "config-serde", serde(skip_serializing_if = "Option::is_none"))]
+    metadata: Option<Vec<u8>>,
+    #[cfg_attr(feature = "config-serde", serde(skip_serializing_if = "Option::is_none"))]
+    static_peers: Option<Vec<Peer>>,
+    advertise_self: bool,
+    #[cfg_attr(feature = "config-serde", serde(skip_serializing_if = "Option::is_none"))]
+    size_estimation: Option<u32>,
 }
 
 impl PeerSamplingConfig {
@@ -32,6 +67,18 @@ impl PeerSamplingConfig {
             view_size,
             healing_factor,
             swapping_factor,
+            max_peer_age: None,
+            graceful_leave: None,
+            leave_quarantine: sampling_period * DEFAULT_LEAVE_QUARANTINE_PERIODS,
+            max_view_message_peers: DEFAULT_MAX_VIEW_MESSAGE_PEERS,
+            max_incoming_buffer: None,
+            max_peer_queue: None,
+            queue_new_peers_immediately: false,
+            initial_jitter: 0.0,
+            metadata: None,
+            static_peers: None,
+            advertise_self: true,
+            size_estimation: None,
         }
     }
 
@@ -48,6 +95,34 @@ impl PeerSamplingConfig {
             view_size,
             healing_factor,
             swapping_factor,
+            max_peer_age: None,
+            graceful_leave: None,
+            leave_quarantine: sampling_period * DEFAULT_LEAVE_QUARANTINE_PERIODS,
+            max_view_message_peers: DEFAULT_MAX_VIEW_MESSAGE_PEERS,
+            max_incoming_buffer: None,
+            max_peer_queue: None,
+            queue_new_peers_immediately: false,
+            initial_jitter: 0.0,
+            metadata: None,
+            static_peers: None,
+            advertise_self: true,
+            size_estimation: None,
+        }
+    }
+
+    /// Creates a configuration without peer sampling: the view is the given list of peers, to which the peers
+    /// returned at start are added, and it never changes. No view is exchanged with other nodes.
+    /// This suits small clusters whose members are known in advance.
+    ///
+    /// # Arguments
+    ///
+    /// * `peers` - The peers of the node
+    pub fn static_view(peers: Vec<Peer>) -> Self {
+        PeerSamplingConfig {
+            push: false,
+            pull: false,
+            static_peers: Some(peers),
+            ..PeerSamplingConfig::default()
         }
     }
 
@@ -78,6 +153,181 @@ impl PeerSamplingConfig {
     pub fn is_push(&self) -> bool {
         self.push
     }
+
+    /// Returns how views are exchanged, `None` if they are never exchanged
+    pub fn mode(&self) -> Option<DisseminationMode> {
+        DisseminationMode::from_flags(self.push, self.pull)
+    }
+
+    /// Sets the maximum age (in cycles) of peers in the view; older peers are removed at each
+    /// view selection, independently of the healing factor
+    ///
+    /// # Arguments
+    ///
+    /// * `max_peer_age` - The maximum age, or `None` for no limit
+    pub fn set_max_peer_age(&mut self, max_peer_age: Option<u16>) {
+        self.max_peer_age = max_peer_age;
+    }
+
+    pub fn max_peer_age(&self) -> Option<u16> {
+        self.max_peer_age
+    }
+
+    /// Enables notifying the peers of the view when the node shuts down, so that they remove it
+    /// without waiting for it to age out
+    ///
+    /// # Arguments
+    ///
+    /// * `graceful_leave` - The time budget (milliseconds) for notifying peers, or `None` to leave silently
+    pub fn set_graceful_leave(&mut self, graceful_leave: Option<u64>) {
+        self.graceful_leave = graceful_leave;
+    }
+
+    pub fn graceful_leave(&self) -> Option<u64> {
+        self.graceful_leave
+    }
+
+    /// Sets for how long (milliseconds) a peer that announced its departure is kept out of the view,
+    /// so that it is not reinserted by buffers still containing it. Defaults to ten sampling periods.
+    /// Since leave messages are not authenticated, a departure is only accepted from the host of the peer.
+    ///
+    /// # Arguments
+    ///
+    /// * `leave_quarantine` - The quarantine period
+    pub fn set_leave_quarantine(&mut self, leave_quarantine: u64) {
+        self.leave_quarantine = leave_quarantine;
+    }
+
+    pub fn leave_quarantine(&self) -> u64 {
+        self.leave_quarantine
+    }
+
+    /// Sets the maximum number of peers in a buffer exchanged with another node. Buffers built
+    /// by the node are truncated to this size and larger incoming buffers are rejected.
+    ///
+    /// # Arguments
+    ///
+    /// * `max_view_message_peers` - The maximum number of peers, including the sender
+    pub fn set_max_view_message_peers(&mut self, max_view_message_peers: usize) {
+        self.max_view_message_peers = max_view_message_peers;
+    }
+
+    pub fn max_view_message_peers(&self) -> usize {
+        self.max_view_message_peers
+    }
+
+    /// Sets the maximum number of peers of a received buffer that are merged into the view; the peers
+    /// beyond it are dropped before merging. Defaults to half the view size, which is the size of the
+    /// buffers built by the protocol.
+    ///
+    /// # Arguments
+    ///
+    /// * `max_incoming_buffer` - The maximum number of peers merged from a buffer, including the sender
+    pub fn set_max_incoming_buffer(&mut self, max_incoming_buffer: usize) {
+        self.max_incoming_buffer = Some(max_incoming_buffer);
+    }
+
+    pub fn max_incoming_buffer(&self) -> usize {
+        self.max_incoming_buffer.unwrap_or(self.view_size / 2)
+    }
+
+    /// Sets the maximum number of new peers queued for the application, see
+    /// [PeerSamplingService::get_peers](crate::PeerSamplingService::get_peers). When the view changes faster than
+    /// the queue is consumed, the oldest queued peers are dropped. Defaults to the view size.
+    ///
+    /// # Arguments
+    ///
+    /// * `max_peer_queue` - The maximum number of queued peers
+    pub fn set_max_peer_queue(&mut self, max_peer_queue: usize) {
+        self.max_peer_queue = Some(max_peer_queue);
+    }
+
+    pub fn max_peer_queue(&self) -> usize {
+        self.max_peer_queue.unwrap_or(self.view_size)
+    }
+
+    /// Sets whether the peers that enter the view are queued for the application right away. By default, they are
+    /// queued only if they are still in the view at the end of the next sampling cycle, so that the peers learned
+    /// from a flood of buffers, which may leave the view as fast as they entered it, are not selected first by the
+    /// gossip rounds. Peers that are not queued yet can still be selected at random from the view.
+    ///
+    /// # Arguments
+    ///
+    /// * `queue_new_peers_immediately` - Whether new peers are queued as soon as they enter the view
+    pub fn set_queue_new_peers_immediately(&mut self, queue_new_peers_immediately: bool) {
+        self.queue_new_peers_immediately = queue_new_peers_immediately;
+    }
+
+    pub fn queue_new_peers_immediately(&self) -> bool {
+        self.queue_new_peers_immediately
+    }
+
+    /// Delays the first sampling round after start by a random fraction of the sampling period, so that
+    /// nodes started together do not all contact their initial peers at the same time
+    ///
+    /// # Arguments
+    ///
+    /// * `initial_jitter` - The maximum delay, as a fraction of the sampling period between 0 and 1
+    pub fn set_initial_jitter(&mut self, initial_jitter: f64) {
+        self.initial_jitter = initial_jitter.clamp(0.0, 1.0);
+    }
+
+    pub fn initial_jitter(&self) -> f64 {
+        self.initial_jitter
+    }
+
+    /// Sets the application-defined metadata advertised with the address of the node in peer sampling
+    /// buffers, for instance its role or version. Returns an error if the metadata is longer than
+    /// [MAX_PEER_METADATA](crate::MAX_PEER_METADATA) bytes.
+    ///
+    /// # Arguments
+    ///
+    /// * `metadata` - The metadata
+    pub fn set_metadata(&mut self, metadata: Vec<u8>) -> Result<(), Box<dyn std::error::Error>> {
+        if metadata.len() > crate::peer::MAX_PEER_METADATA {
+            Err(format!("Peer metadata must not be longer than {} bytes", crate::peer::MAX_PEER_METADATA))?
+        }
+        self.metadata = Some(metadata);
+        Ok(())
+    }
+
+    pub fn metadata(&self) -> Option<&[u8]> {
+        self.metadata.as_deref()
+    }
+
+    /// Returns the peers of a [static view](Self::static_view), `None` if peer sampling is enabled
+    pub fn static_peers(&self) -> Option<&[Peer]> {
+        self.static_peers.as_deref()
+    }
+
+    /// Sets whether the node puts its own address in the buffers it sends, which is how other nodes add it to their
+    /// views. A node that does not advertise itself still merges the buffers it receives, but never appears in the
+    /// views of other nodes, which suits nodes that can connect to others but cannot be reached. Such a node only
+    /// receives updates by pulling them. Enabled by default. A node always stops advertising itself once its
+    /// shutdown is requested, so that its last exchanges do not refresh it in the views of its peers.
+    pub fn set_advertise_self(&mut self, advertise_self: bool) {
+        self.advertise_self = advertise_self;
+    }
+
+    pub fn advertise_self(&self) -> bool {
+        self.advertise_self
+    }
+
+    /// Sets whether the node estimates the number of nodes of the network, see
+    /// [GossipService::estimated_cluster_size](crate::GossipService::estimated_cluster_size). The estimate is
+    /// aggregated by the view exchanges, which must be pulled, and restarts every `epoch_rounds` sampling rounds so
+    /// that it follows the nodes joining and leaving. Disabled by default.
+    ///
+    /// # Arguments
+    ///
+    /// * `epoch_rounds` - The number of sampling rounds of an estimate, or `None` to disable the estimation
+    pub fn set_size_estimation(&mut self, epoch_rounds: Option<u32>) {
+        self.size_estimation = epoch_rounds.filter(|rounds| *rounds > 0);
+    }
+
+    pub fn size_estimation(&self) -> Option<u32> {
+        self.size_estimation
+    }
 }
 
 impl Default for PeerSamplingConfig {
@@ -89,18 +339,159 @@ impl Default for PeerSamplingConfig {
             sampling_deviation: 0,
             view_size: 30,
             healing_factor: 3,
-            swapping_factor: 12
+            swapping_factor: 12,
+            max_peer_age: None,
+            graceful_leave: None,
+            leave_quarantine: 60000 * DEFAULT_LEAVE_QUARANTINE_PERIODS,
+            max_view_message_peers: DEFAULT_MAX_VIEW_MESSAGE_PEERS,
+            max_incoming_buffer: None,
+            max_peer_queue: None,
+            queue_new_peers_immediately: false,
+            initial_jitter: 0.0,
+            metadata: None,
+            static_peers: None,
+            advertise_self: true,
+            size_estimation: None,
         }
     }
 }
 
+/// Default number of threads reading incoming connections
+const DEFAULT_LISTENER_WORKERS: usize = 4;
+/// Default number of accepted connections waiting for a reader thread
+const DEFAULT_ACCEPT_BACKLOG: usize = 256;
+/// Default time (milliseconds) within which an accepted connection must deliver its whole message
+const DEFAULT_CONNECTION_DEADLINE: u64 = 5_000;
+/// Default number of connections of a single IP address being read or waiting for a reader thread
+const DEFAULT_MAX_CONNECTIONS_PER_IP: usize = 64;
+/// Default number of received peer sampling and header messages waiting for their handling thread
+const DEFAULT_MESSAGE_QUEUE_CAPACITY: usize = 1024;
+/// Default number of received content messages waiting for the content handling thread
+const DEFAULT_CONTENT_QUEUE_CAPACITY: usize = 256;
+/// Default time (milliseconds) during which a listener worker waits for room in a full message queue
+const DEFAULT_QUEUE_TIMEOUT: u64 = 100;
+
+/// Default number of violations after which a peer sending invalid data is quarantined
+const DEFAULT_QUARANTINE_THRESHOLD: u32 = 5;
+
+/// Default length (milliseconds) of the quarantine of a peer sending invalid data
+const DEFAULT_QUARANTINE_COOLDOWN: u64 = 60_000;
+
+/// Default number of peers sending mismatching content after which a digest is no longer requested
+const DEFAULT_MAX_DIGEST_MISMATCHES: u32 = 3;
+
+/// Default time (milliseconds) during which a digest is not requested again from a peer that sent mismatching content
+const DEFAULT_MISMATCH_COOLDOWN: u64 = 60_000;
+
+/// Default time (milliseconds) during which a digest declined by the fetch policy is not submitted to it again
+const DEFAULT_DECLINED_FETCH_TTL: u64 = 30_000;
+
+/// Default time (milliseconds) during which an update delivered in order waits for the updates before it
+const DEFAULT_GAP_TIMEOUT: u64 = 5_000;
+
+/// Default time (milliseconds) after which an update whose delivery failed is delivered again, doubled at each attempt
+const DEFAULT_DELIVERY_RETRY_DELAY: u64 = 1_000;
+
+/// Default time (milliseconds) during which the digest of an expired update is advertised as a tombstone
+const DEFAULT_TOMBSTONE_WINDOW: u64 = 60_000;
+
+/// Default number of keepalive pings left unanswered in a row after which a peer is unreachable
+const DEFAULT_KEEPALIVE_MAX_MISSED: u32 = 3;
+
+/// Default time (milliseconds) between the isolation of the node and each attempt to bootstrap again
+const DEFAULT_REBOOTSTRAP_DELAY: u64 = 10_000;
+
+/// Default factor applied to the history of the peers at each gossip round
+const DEFAULT_USEFULNESS_DECAY: f64 = 0.95;
+
 /// The gossip parameters
+#[derive(Clone)]
+#[cfg_attr(feature = "config-serde", derive(serde::Serialize, serde::Deserialize), serde(default, deny_unknown_fields))]
 pub struct GossipConfig {
     push: bool,
     pull: bool,
+    #[cfg_attr(feature = "config-serde", serde(with = "crate::node_config::millis"))]
     gossip_period: u64,
+    #[cfg_attr(feature = "config-serde", serde(with = "crate::node_config::millis"))]
     gossip_deviation: u64,
     update_expiration: UpdateExpirationMode,
+    expiration_start: ExpirationStart,
+    listener_workers: usize,
+    accept_backlog: usize,
+    #[cfg_attr(feature = "config-serde", serde(with = "crate::node_config::millis"))]
+    connection_deadline: u64,
+    max_connections_per_ip: usize,
+    sampling_queue_capacity: usize,
+    header_queue_capacity: usize,
+    content_queue_capacity: usize,
+    #[cfg_attr(feature = "config-serde", serde(with = "crate::node_config::millis"))]
+    queue_timeout: u64,
+    #[cfg_attr(feature = "config-serde", serde(with = "crate::node_config::option_millis", skip_serializing_if = "Option::is_none"))]
+    in_flight_timeout: Option<u64>,
+    #[cfg_attr(feature = "config-serde", serde(with = "crate::node_config::option_millis", skip_serializing_if = "Option::is_none"))]
+    content_request_timeout: Option<u64>,
+    #[cfg_attr(feature = "config-serde", serde(skip_serializing_if = "Option::is_none"))]
+    cluster_id: Option<u8>,
+    #[cfg_attr(feature = "config-serde", serde(skip_serializing_if = "Option::is_none"))]
+    cluster_size: Option<usize>,
+    pull_fanout: usize,
+    #[cfg_attr(feature = "config-serde", serde(skip_serializing_if = "Option::is_none"))]
+    max_headers_per_push: Option<usize>,
+    verify_content_digests: bool,
+    accept_unrequested_content: bool,
+    quarantine_threshold: u32,
+    #[cfg_attr(feature = "config-serde", serde(with = "crate::node_config::millis"))]
+    quarantine_cooldown: u64,
+    max_digest_mismatches: u32,
+    #[cfg_attr(feature = "config-serde", serde(with = "crate::node_config::millis"))]
+    mismatch_cooldown: u64,
+    #[cfg_attr(feature = "config-serde", serde(skip_serializing_if = "Option::is_none"))]
+    cluster_name: Option<String>,
+    network: NetworkConfig,
+    queue_before_start: bool,
+    eager_first_push: bool,
+    refresh_expired: bool,
+    #[cfg_attr(feature = "config-serde", serde(skip_serializing_if = "Option::is_none"))]
+    origin_id: Option<String>,
+    suppress_own_origin_redelivery: bool,
+    strict_checks: bool,
+    #[cfg_attr(feature = "config-serde", serde(skip, default = "crate::digest::default_digester"))]
+    digester: Arc<dyn Digester>,
+    #[cfg_attr(feature = "config-serde", serde(skip))]
+    fetch_policy: Option<Arc<dyn FetchPolicy>>,
+    #[cfg_attr(feature = "config-serde", serde(with = "crate::node_config::millis"))]
+    declined_fetch_ttl: u64,
+    relay_headers: bool,
+    idle_rounds: u32,
+    idle_multiplier: u32,
+    empty_view_multiplier: u32,
+    #[cfg_attr(feature = "config-serde", serde(with = "crate::node_config::millis"))]
+    max_idle_period: u64,
+    initial_sync: SyncPolicy,
+    empty_pushes: bool,
+    uniform_peer_selection: bool,
+    useful_peer_bias: f64,
+    usefulness_decay: f64,
+    ordered_delivery: bool,
+    #[cfg_attr(feature = "config-serde", serde(with = "crate::node_config::millis"))]
+    gap_timeout: u64,
+    acknowledged_delivery: bool,
+    #[cfg_attr(feature = "config-serde", serde(with = "crate::node_config::millis"))]
+    delivery_retry_delay: u64,
+    max_tombstones: usize,
+    #[cfg_attr(feature = "config-serde", serde(with = "crate::node_config::millis"))]
+    tombstone_window: u64,
+    revocations: bool,
+    #[cfg_attr(feature = "config-serde", serde(skip_serializing_if = "Option::is_none"))]
+    epoch_file: Option<PathBuf>,
+    outbound_only: bool,
+    #[cfg_attr(feature = "config-serde", serde(with = "crate::node_config::millis"))]
+    keepalive_interval: u64,
+    keepalive_max_missed: u32,
+    #[cfg_attr(feature = "config-serde", serde(with = "crate::node_config::option_millis", skip_serializing_if = "Option::is_none"))]
+    isolation_window: Option<u64>,
+    #[cfg_attr(feature = "config-serde", serde(with = "crate::node_config::millis"))]
+    rebootstrap_delay: u64,
 }
 
 impl GossipConfig {
@@ -119,6 +510,61 @@ impl GossipConfig {
             gossip_period,
             gossip_deviation: 0,
             update_expiration,
+            expiration_start: ExpirationStart::OnInsert,
+            listener_workers: DEFAULT_LISTENER_WORKERS,
+            accept_backlog: DEFAULT_ACCEPT_BACKLOG,
+            connection_deadline: DEFAULT_CONNECTION_DEADLINE,
+            max_connections_per_ip: DEFAULT_MAX_CONNECTIONS_PER_IP,
+            sampling_queue_capacity: DEFAULT_MESSAGE_QUEUE_CAPACITY,
+            header_queue_capacity: DEFAULT_MESSAGE_QUEUE_CAPACITY,
+            content_queue_capacity: DEFAULT_CONTENT_QUEUE_CAPACITY,
+            queue_timeout: DEFAULT_QUEUE_TIMEOUT,
+            in_flight_timeout: None,
+            content_request_timeout: None,
+            cluster_id: None,
+            cluster_size: None,
+            pull_fanout: 1,
+            max_headers_per_push: None,
+            verify_content_digests: true,
+            accept_unrequested_content: false,
+            quarantine_threshold: DEFAULT_QUARANTINE_THRESHOLD,
+            quarantine_cooldown: DEFAULT_QUARANTINE_COOLDOWN,
+            max_digest_mismatches: DEFAULT_MAX_DIGEST_MISMATCHES,
+            mismatch_cooldown: DEFAULT_MISMATCH_COOLDOWN,
+            cluster_name: None,
+            network: NetworkConfig::default(),
+            queue_before_start: true,
+            eager_first_push: false,
+            refresh_expired: false,
+            origin_id: None,
+            suppress_own_origin_redelivery: false,
+            strict_checks: false,
+            digester: crate::digest::default_digester(),
+            fetch_policy: None,
+            declined_fetch_ttl: DEFAULT_DECLINED_FETCH_TTL,
+            relay_headers: false,
+            idle_rounds: 0,
+            idle_multiplier: 2,
+            empty_view_multiplier: 1,
+            max_idle_period: 0,
+            initial_sync: SyncPolicy::None,
+            empty_pushes: false,
+            uniform_peer_selection: false,
+            useful_peer_bias: 0.0,
+            usefulness_decay: DEFAULT_USEFULNESS_DECAY,
+            ordered_delivery: false,
+            gap_timeout: DEFAULT_GAP_TIMEOUT,
+            acknowledged_delivery: false,
+            delivery_retry_delay: DEFAULT_DELIVERY_RETRY_DELAY,
+            max_tombstones: 0,
+            revocations: false,
+            epoch_file: None,
+            outbound_only: false,
+            keepalive_interval: 0,
+            keepalive_max_missed: DEFAULT_KEEPALIVE_MAX_MISSED,
+            isolation_window: None,
+            rebootstrap_delay: DEFAULT_REBOOTSTRAP_DELAY,
+            tombstone_window: DEFAULT_TOMBSTONE_WINDOW,
         }
     }
 
@@ -133,6 +579,61 @@ impl GossipConfig {
             gossip_period,
             gossip_deviation,
             update_expiration,
+            expiration_start: ExpirationStart::OnInsert,
+            listener_workers: DEFAULT_LISTENER_WORKERS,
+            accept_backlog: DEFAULT_ACCEPT_BACKLOG,
+            connection_deadline: DEFAULT_CONNECTION_DEADLINE,
+            max_connections_per_ip: DEFAULT_MAX_CONNECTIONS_PER_IP,
+            sampling_queue_capacity: DEFAULT_MESSAGE_QUEUE_CAPACITY,
+            header_queue_capacity: DEFAULT_MESSAGE_QUEUE_CAPACITY,
+            content_queue_capacity: DEFAULT_CONTENT_QUEUE_CAPACITY,
+            queue_timeout: DEFAULT_QUEUE_TIMEOUT,
+            in_flight_timeout: None,
+            content_request_timeout: None,
+            cluster_id: None,
+            cluster_size: None,
+            pull_fanout: 1,
+            max_headers_per_push: None,
+            verify_content_digests: true,
+            accept_unrequested_content: false,
+            quarantine_threshold: DEFAULT_QUARANTINE_THRESHOLD,
+            quarantine_cooldown: DEFAULT_QUARANTINE_COOLDOWN,
+            max_digest_mismatches: DEFAULT_MAX_DIGEST_MISMATCHES,
+            mismatch_cooldown: DEFAULT_MISMATCH_COOLDOWN,
+            cluster_name: None,
+            network: NetworkConfig::default(),
+            queue_before_start: true,
+            eager_first_push: false,
+            refresh_expired: false,
+            origin_id: None,
+            suppress_own_origin_redelivery: false,
+            strict_checks: false,
+            digester: crate::digest::default_digester(),
+            fetch_policy: None,
+            declined_fetch_ttl: DEFAULT_DECLINED_FETCH_TTL,
+            relay_headers: false,
+            idle_rounds: 0,
+            idle_multiplier: 2,
+            empty_view_multiplier: 1,
+            max_idle_period: 0,
+            initial_sync: SyncPolicy::None,
+            empty_pushes: false,
+            uniform_peer_selection: false,
+            useful_peer_bias: 0.0,
+            usefulness_decay: DEFAULT_USEFULNESS_DECAY,
+            ordered_delivery: false,
+            gap_timeout: DEFAULT_GAP_TIMEOUT,
+            acknowledged_delivery: false,
+            delivery_retry_delay: DEFAULT_DELIVERY_RETRY_DELAY,
+            max_tombstones: 0,
+            revocations: false,
+            epoch_file: None,
+            outbound_only: false,
+            keepalive_interval: 0,
+            keepalive_max_missed: DEFAULT_KEEPALIVE_MAX_MISSED,
+            isolation_window: None,
+            rebootstrap_delay: DEFAULT_REBOOTSTRAP_DELAY,
+            tombstone_window: DEFAULT_TOMBSTONE_WINDOW,
         }
     }
     pub fn is_push(&self) -> bool {
@@ -141,6 +642,10 @@ impl GossipConfig {
     pub fn is_pull(&self) -> bool {
         self.pull
     }
+    /// Returns how updates are disseminated, `None` if neither push nor pull is enabled
+    pub fn mode(&self) -> Option<DisseminationMode> {
+        DisseminationMode::from_flags(self.push, self.pull)
+    }
     pub fn gossip_period(&self) -> u64 {
         self.gossip_period
     }
@@ -150,6 +655,760 @@ impl GossipConfig {
     pub fn update_expiration(&self) -> &UpdateExpirationMode {
         &self.update_expiration
     }
+
+    /// Sets the length of each gossip period (milliseconds)
+    pub fn set_gossip_period(&mut self, gossip_period: u64) {
+        self.gossip_period = gossip_period;
+    }
+
+    /// Sets the maximum value of the random value added to the gossip period (milliseconds)
+    pub fn set_gossip_deviation(&mut self, gossip_deviation: u64) {
+        self.gossip_deviation = gossip_deviation;
+    }
+
+    /// Sets whether the node pushes its content and pulls content from other nodes
+    pub fn set_push_pull(&mut self, push: bool, pull: bool) {
+        self.push = push;
+        self.pull = pull;
+    }
+
+    /// Sets the strategy for update expiration, see [UpdateExpirationMode]
+    pub fn set_update_expiration(&mut self, update_expiration: UpdateExpirationMode) {
+        self.update_expiration = update_expiration;
+    }
+
+    /// Sets when the duration of updates expiring after a [duration](UpdateExpirationMode::DurationMillis) starts,
+    /// see [ExpirationStart]. Only applies to the updates inserted from then on.
+    pub fn set_expiration_start(&mut self, expiration_start: ExpirationStart) {
+        self.expiration_start = expiration_start;
+    }
+
+    pub fn expiration_start(&self) -> ExpirationStart {
+        self.expiration_start
+    }
+
+    /// Cross-checks the gossip parameters with the peer sampling parameters, and returns the combinations
+    /// that are valid but are likely to prevent the dissemination of updates
+    ///
+    /// # Arguments
+    ///
+    /// * `sampling_config` - The peer sampling parameters of the node
+    pub fn check(&self, sampling_config: &PeerSamplingConfig) -> Vec<ConfigWarning> {
+        let mut warnings = Vec::new();
+        let round = self.gossip_period + self.gossip_deviation;
+        if let UpdateExpirationMode::DurationMillis(expiration) = self.update_expiration {
+            if expiration < (round * MIN_ROUNDS_BEFORE_EXPIRATION) as u128 {
+                warnings.push(ConfigWarning::ExpirationShorterThanRounds { expiration: expiration as u64, gossip_period: round });
+            }
+        }
+        if sampling_config.static_peers().is_none() && sampling_config.sampling_period() > self.gossip_period * MAX_SAMPLING_TO_GOSSIP_RATIO {
+            warnings.push(ConfigWarning::SlowSampling { sampling_period: sampling_config.sampling_period(), gossip_period: self.gossip_period });
+        }
+        // a sampling service exchanging no views keeps the bootstrap peers, whatever the gossip mode
+        if let (Some(gossip), Some(sampling)) = (self.mode(), sampling_config.mode()) {
+            if gossip != sampling {
+                warnings.push(ConfigWarning::ModeMismatch { gossip, sampling });
+            }
+        }
+        if self.outbound_only && sampling_config.advertise_self() {
+            warnings.push(ConfigWarning::OutboundOnlyAdvertised);
+        }
+        warnings
+    }
+
+    /// Checks that the parameters are consistent
+    pub fn validate(&self) -> Result<(), Box<dyn std::error::Error>> {
+        if self.gossip_period == 0 {
+            Err("Gossip period must be positive")?
+        }
+        if !self.push && !self.pull {
+            Err("At least one of push and pull must be enabled")?
+        }
+        if let UpdateExpirationMode::MostRecent(_, margin) = self.update_expiration {
+            if margin.is_nan() || margin < 0.0 {
+                Err("Margin of most recent expiration must not be negative")?
+            }
+        }
+        if self.initial_sync != SyncPolicy::None && !self.pull {
+            Err("Initial sync requires pull to be enabled")?
+        }
+        Ok(())
+    }
+
+    /// Sets the number of threads reading incoming connections concurrently
+    ///
+    /// # Arguments
+    ///
+    /// * `listener_workers` - The number of reader threads, at least one
+    pub fn set_listener_workers(&mut self, listener_workers: usize) {
+        self.listener_workers = listener_workers.max(1);
+    }
+
+    pub fn listener_workers(&self) -> usize {
+        self.listener_workers
+    }
+
+    /// Sets the number of accepted connections waiting for a reader thread; connections accepted
+    /// while the backlog is full are closed without being read
+    ///
+    /// # Arguments
+    ///
+    /// * `accept_backlog` - The maximum number of waiting connections
+    pub fn set_accept_backlog(&mut self, accept_backlog: usize) {
+        self.accept_backlog = accept_backlog;
+    }
+
+    pub fn accept_backlog(&self) -> usize {
+        self.accept_backlog
+    }
+
+    /// Bounds the resources a single peer can hold in the listener. A connection that has not delivered its whole
+    /// message within the deadline from its acceptance, including the time it waited for a reader thread, extended by
+    /// the time the bytes it delivered take at 64 KiB per second, is closed
+    /// and counts as a violation of its IP address, see [set_quarantine](Self::set_quarantine). Connections of an IP
+    /// address beyond the maximum, being read or waiting for a reader thread, are closed without being read.
+    ///
+    /// # Arguments
+    ///
+    /// * `deadline` - The time (milliseconds) within which a connection must be read, at least one
+    /// * `max_per_ip` - The maximum number of connections of an IP address, at least one
+    pub fn set_connection_limits(&mut self, deadline: u64, max_per_ip: usize) {
+        self.connection_deadline = deadline.max(1);
+        self.max_connections_per_ip = max_per_ip.max(1);
+    }
+
+    pub fn connection_deadline(&self) -> u64 {
+        self.connection_deadline
+    }
+
+    pub fn max_connections_per_ip(&self) -> usize {
+        self.max_connections_per_ip
+    }
+
+    /// Sets the number of received messages of each protocol waiting for their handling thread. A listener worker
+    /// finding a queue full waits for the [queue timeout](Self::set_queue_timeout), and then drops the message.
+    ///
+    /// # Arguments
+    ///
+    /// * `sampling` - The maximum number of waiting peer sampling messages, at least one
+    /// * `header` - The maximum number of waiting header messages, at least one
+    /// * `content` - The maximum number of waiting content messages, at least one
+    pub fn set_queue_capacities(&mut self, sampling: usize, header: usize, content: usize) {
+        self.sampling_queue_capacity = sampling.max(1);
+        self.header_queue_capacity = header.max(1);
+        self.content_queue_capacity = content.max(1);
+    }
+
+    pub fn sampling_queue_capacity(&self) -> usize {
+        self.sampling_queue_capacity
+    }
+
+    pub fn header_queue_capacity(&self) -> usize {
+        self.header_queue_capacity
+    }
+
+    pub fn content_queue_capacity(&self) -> usize {
+        self.content_queue_capacity
+    }
+
+    /// Sets the time during which a listener worker waits for room in a full message queue before dropping the message
+    ///
+    /// # Arguments
+    ///
+    /// * `queue_timeout` - The timeout (milliseconds)
+    pub fn set_queue_timeout(&mut self, queue_timeout: u64) {
+        self.queue_timeout = queue_timeout;
+    }
+
+    pub fn queue_timeout(&self) -> u64 {
+        self.queue_timeout
+    }
+
+    /// Sets the time after which a header exchange with a peer that did not complete is abandoned. No new
+    /// exchange is initiated with a peer while the previous one is in flight: until the peer answers a pull, or
+    /// until a push that expects no answer is sent. Defaults to twice the gossip period, deviation included.
+    ///
+    /// # Arguments
+    ///
+    /// * `in_flight_timeout` - The timeout (milliseconds)
+    pub fn set_in_flight_timeout(&mut self, in_flight_timeout: u64) {
+        self.in_flight_timeout = Some(in_flight_timeout);
+    }
+
+    pub fn in_flight_timeout(&self) -> u64 {
+        self.in_flight_timeout.unwrap_or(2 * (self.gossip_period + self.gossip_deviation))
+    }
+
+    /// Sets the time after which a content request that was not answered is abandoned. The content is then
+    /// requested from another peer that advertised the digest if one is known, otherwise from the next peer
+    /// advertising it. Defaults to the gossip period, deviation included.
+    ///
+    /// # Arguments
+    ///
+    /// * `content_request_timeout` - The timeout (milliseconds)
+    pub fn set_content_request_timeout(&mut self, content_request_timeout: u64) {
+        self.content_request_timeout = Some(content_request_timeout);
+    }
+
+    pub fn content_request_timeout(&self) -> u64 {
+        self.content_request_timeout.unwrap_or(self.gossip_period + self.gossip_deviation)
+    }
+
+    /// Sets the cluster of the node. Messages are stamped with the cluster id, and only the messages of the
+    /// same cluster are accepted. Required for nodes sharing a [SharedListener](crate::SharedListener).
+    ///
+    /// # Arguments
+    ///
+    /// * `cluster_id` - The cluster id
+    pub fn set_cluster_id(&mut self, cluster_id: u8) {
+        self.cluster_id = Some(cluster_id);
+    }
+
+    pub fn cluster_id(&self) -> Option<u8> {
+        self.cluster_id
+    }
+
+    /// Sets the number of nodes in the cluster, used to estimate the coverage of the updates. Without it, the
+    /// size of the view of the node is used instead, which underestimates the size of large clusters.
+    ///
+    /// # Arguments
+    ///
+    /// * `cluster_size` - The number of nodes, the local node included
+    pub fn set_cluster_size(&mut self, cluster_size: usize) {
+        self.cluster_size = Some(cluster_size);
+    }
+
+    pub fn cluster_size(&self) -> Option<usize> {
+        self.cluster_size
+    }
+
+    /// Sets the name of the cluster of the node, carried in peer sampling and header messages. Messages from
+    /// nodes of other clusters are dropped, and their peers are never merged into the view; nodes without
+    /// cluster name accept and are accepted by every cluster.
+    ///
+    /// # Arguments
+    ///
+    /// * `cluster_name` - The cluster name
+    pub fn set_cluster_name(&mut self, cluster_name: String) {
+        self.cluster_name = Some(cluster_name);
+    }
+
+    pub fn cluster_name(&self) -> Option<&str> {
+        self.cluster_name.as_deref()
+    }
+
+    /// Sets the number of distinct peers probed for their headers at each round when pull is enabled, 1 by
+    /// default. The content of a digest advertised by several peers is requested from the first responder
+    /// only, and from another one if it is not delivered in time, see [set_content_request_timeout](Self::set_content_request_timeout).
+    ///
+    /// # Arguments
+    ///
+    /// * `pull_fanout` - The number of peers, at least 1
+    pub fn set_pull_fanout(&mut self, pull_fanout: usize) {
+        self.pull_fanout = pull_fanout.max(1);
+    }
+
+    pub fn pull_fanout(&self) -> usize {
+        self.pull_fanout
+    }
+
+    /// Limits the number of headers pushed at each round; all the active headers are pushed by default, up to
+    /// [MAX_MESSAGE_DIGESTS](crate::MAX_MESSAGE_DIGESTS), which also bounds the headers of a pull response.
+    /// New updates are pushed first during their first rounds, then the active updates are pushed in turn
+    /// so that all of them are advertised over several rounds. With [UpdateExpirationMode::PushCount],
+    /// only the rounds in which an update is pushed are counted.
+    ///
+    /// # Arguments
+    ///
+    /// * `max_headers_per_push` - The maximum number of headers
+    pub fn set_max_headers_per_push(&mut self, max_headers_per_push: usize) {
+        self.max_headers_per_push = Some(max_headers_per_push);
+    }
+
+    pub fn max_headers_per_push(&self) -> Option<usize> {
+        self.max_headers_per_push
+    }
+
+    /// Sets whether the digest of received content is computed and compared with the advertised digest,
+    /// which is the default. Skipping verification saves the hashing of large updates in trusted
+    /// environments, but content that does not match its digest is then accepted.
+    ///
+    /// # Arguments
+    ///
+    /// * `verify_content_digests` - Whether received content is verified
+    pub fn set_verify_content_digests(&mut self, verify_content_digests: bool) {
+        self.verify_content_digests = verify_content_digests;
+    }
+
+    pub fn verify_content_digests(&self) -> bool {
+        self.verify_content_digests
+    }
+
+    /// Sets whether the content responses may insert updates that the node did not request. By default, only the
+    /// digests requested from the sender of a response are inserted, including the requests that timed out, the
+    /// other entries are ignored and count as a violation of the sender, see [set_quarantine](Self::set_quarantine).
+    /// Accepting them suits peers that push their content without waiting for the request.
+    ///
+    /// # Arguments
+    ///
+    /// * `accept_unrequested_content` - Whether the updates not requested are inserted
+    pub fn set_accept_unrequested_content(&mut self, accept_unrequested_content: bool) {
+        self.accept_unrequested_content = accept_unrequested_content;
+    }
+
+    pub fn accept_unrequested_content(&self) -> bool {
+        self.accept_unrequested_content
+    }
+
+    /// Sets when peers sending invalid data are quarantined: content not matching its digest, content that was not
+    /// requested or oversized peer sampling buffers. After `threshold` violations, no exchange is initiated with the peer, its
+    /// messages are ignored and it is removed from the view for `cooldown` milliseconds; violations older
+    /// than the cooldown are forgotten. Defaults to 5 violations and one minute; a threshold of zero
+    /// disables quarantine.
+    ///
+    /// The violations of a peer are accounted at the IP address its connection came from, with the port it
+    /// reports: a node reporting the address of a peer of another host cannot get that peer quarantined.
+    ///
+    /// The connections that miss the [connection deadline](Self::set_connection_limits) are counted separately,
+    /// against their IP address rather than a peer address, with the same threshold and cooldown: once an IP
+    /// address is quarantined, the listener refuses the connections of all the peers sharing it, such as the nodes
+    /// of a host or behind a NAT.
+    ///
+    /// # Arguments
+    ///
+    /// * `threshold` - The number of violations
+    /// * `cooldown` - The length of the quarantine (milliseconds)
+    pub fn set_quarantine(&mut self, threshold: u32, cooldown: u64) {
+        self.quarantine_threshold = threshold;
+        self.quarantine_cooldown = cooldown;
+    }
+
+    pub fn quarantine_threshold(&self) -> u32 {
+        self.quarantine_threshold
+    }
+
+    pub fn quarantine_cooldown(&self) -> u64 {
+        self.quarantine_cooldown
+    }
+
+    /// Sets how received content not matching its digest is handled. The digest is not requested again from the
+    /// peer that sent the content for `cooldown` milliseconds, and is requested from another peer that advertised
+    /// it, if any. After mismatches from `max_mismatches` distinct peers, the digest is poisoned: it is treated as
+    /// expired and never requested again. Only the peers whose reported address matches the IP their connection
+    /// came from are counted, so that a node cannot poison a digest by reporting the addresses of other peers.
+    /// Defaults to 3 peers and one minute; a maximum of zero disables poisoning.
+    ///
+    /// # Arguments
+    ///
+    /// * `max_mismatches` - The number of peers whose mismatches poison the digest
+    /// * `cooldown` - The time during which the digest is not requested from the same peer (milliseconds)
+    pub fn set_mismatch_handling(&mut self, max_mismatches: u32, cooldown: u64) {
+        self.max_digest_mismatches = max_mismatches;
+        self.mismatch_cooldown = cooldown;
+    }
+
+    pub fn max_digest_mismatches(&self) -> u32 {
+        self.max_digest_mismatches
+    }
+
+    pub fn mismatch_cooldown(&self) -> u64 {
+        self.mismatch_cooldown
+    }
+
+    /// Sets the options of the sockets of the node, see [NetworkConfig]. The options of the listener are
+    /// applied when the service is started.
+    pub fn set_network(&mut self, network: NetworkConfig) {
+        self.network = network;
+    }
+
+    pub fn network(&self) -> &NetworkConfig {
+        &self.network
+    }
+
+    /// Sets whether updates submitted before the service is started are queued until start, with a warning,
+    /// or rejected with a [NotStarted](crate::NotStarted) error. Enabled by default.
+    pub fn set_queue_before_start(&mut self, queue_before_start: bool) {
+        self.queue_before_start = queue_before_start;
+    }
+
+    pub fn queue_before_start(&self) -> bool {
+        self.queue_before_start
+    }
+
+    /// Sets whether submitting an update starts a gossip round right away, instead of waiting for the next
+    /// scheduled round. Submissions within a few milliseconds share the same round, which is otherwise a scheduled
+    /// round: it is skipped while paused, and the next one is due one period later. Disabled by default.
+    pub fn set_eager_first_push(&mut self, eager_first_push: bool) {
+        self.eager_first_push = eager_first_push;
+    }
+
+    pub fn eager_first_push(&self) -> bool {
+        self.eager_first_push
+    }
+
+    /// Sets whether [submit_or_refresh](crate::GossipService::submit_or_refresh) inserts again an update that
+    /// expired and whose digest is still remembered, instead of refusing it. Its tombstone, if any, stops being
+    /// advertised, but the peers that remember the digest do not fetch the update again. Disabled by default.
+    pub fn set_refresh_expired(&mut self, refresh_expired: bool) {
+        self.refresh_expired = refresh_expired;
+    }
+
+    pub fn refresh_expired(&self) -> bool {
+        self.refresh_expired
+    }
+
+    /// Sets the identifier with which the node tags the updates it submits, see [Update::origin](crate::Update::origin).
+    /// It is carried along with the content of the updates, and should stay the same when the node restarts, unlike
+    /// its address if it is not fixed. None by default: submitted updates are not tagged.
+    pub fn set_origin_id(&mut self, origin_id: Option<String>) {
+        self.origin_id = origin_id;
+    }
+
+    pub fn origin_id(&self) -> Option<&str> {
+        self.origin_id.as_deref()
+    }
+
+    /// Sets whether the updates tagged with the [origin id](Self::set_origin_id) of the node are kept from the
+    /// handler. Such updates come back from the peers when the node restarts without the updates it submitted;
+    /// they are still stored and relayed. The tag is not covered by the digest, so a peer can keep an update from
+    /// the handler by tagging it. Disabled by default.
+    pub fn set_suppress_own_origin_redelivery(&mut self, suppress_own_origin_redelivery: bool) {
+        self.suppress_own_origin_redelivery = suppress_own_origin_redelivery;
+    }
+
+    pub fn suppress_own_origin_redelivery(&self) -> bool {
+        self.suppress_own_origin_redelivery
+    }
+
+    /// Sets whether the service refuses to start, or a configuration change is rejected, when [check](Self::check)
+    /// reports a warning. Defaults to `false`: warnings are only logged.
+    pub fn set_strict_checks(&mut self, strict_checks: bool) {
+        self.strict_checks = strict_checks;
+    }
+
+    pub fn strict_checks(&self) -> bool {
+        self.strict_checks
+    }
+
+    /// Sets the function computing the digests of updates, [BLAKE3](crate::Blake3Digester) by default.
+    /// All the nodes of a network must use the same function.
+    ///
+    /// # Arguments
+    ///
+    /// * `digester` - The digest function
+    pub fn set_digester(&mut self, digester: Arc<dyn Digester>) {
+        self.digester = digester;
+    }
+
+    pub fn digester(&self) -> &Arc<dyn Digester> {
+        &self.digester
+    }
+
+    /// Sets the policy deciding which advertised updates are downloaded, see [FetchPolicy]. All of them are
+    /// downloaded by default.
+    ///
+    /// # Arguments
+    ///
+    /// * `fetch_policy` - The policy, `None` to download every update
+    pub fn set_fetch_policy(&mut self, fetch_policy: Option<Arc<dyn FetchPolicy>>) {
+        self.fetch_policy = fetch_policy;
+    }
+
+    pub fn fetch_policy(&self) -> Option<&Arc<dyn FetchPolicy>> {
+        self.fetch_policy.as_ref()
+    }
+
+    /// Sets the time (milliseconds) during which a digest declined by the fetch policy is not submitted to it
+    /// again; a changed policy applies to the declined digests after this time. Defaults to 30 seconds.
+    pub fn set_declined_fetch_ttl(&mut self, declined_fetch_ttl: u64) {
+        self.declined_fetch_ttl = declined_fetch_ttl;
+    }
+
+    pub fn declined_fetch_ttl(&self) -> u64 {
+        self.declined_fetch_ttl
+    }
+
+    /// Sets whether the node relays the digests advertised by its peers whose content it does not hold, because
+    /// it declined it or did not receive it yet. Relayed digests are pushed along with the active ones, and the
+    /// content requests for them are answered with the address of the peer that advertised them, to which the
+    /// requesting node sends its request. Disabled by default.
+    pub fn set_relay_headers(&mut self, relay_headers: bool) {
+        self.relay_headers = relay_headers;
+    }
+
+    pub fn relay_headers(&self) -> bool {
+        self.relay_headers
+    }
+
+    /// Sets how the gossip period is stretched while nothing new happens. After `idle_rounds` rounds during which
+    /// no update was inserted and no new digest was learned, each further idle round multiplies the period by
+    /// `multiplier`, up to `max_period`. The base period applies again as soon as an update is submitted or a new
+    /// digest is advertised to the node. Disabled by default.
+    ///
+    /// # Arguments
+    ///
+    /// * `idle_rounds` - Number of idle rounds before the period is stretched, zero to disable
+    /// * `multiplier` - Factor applied to the period after each further idle round
+    /// * `max_period` - Maximum length (milliseconds) of the stretched period
+    pub fn set_idle_backoff(&mut self, idle_rounds: u32, multiplier: u32, max_period: u64) {
+        self.idle_rounds = idle_rounds;
+        self.idle_multiplier = multiplier;
+        self.max_idle_period = max_period;
+    }
+
+    pub fn idle_rounds(&self) -> u32 {
+        self.idle_rounds
+    }
+
+    pub fn idle_multiplier(&self) -> u32 {
+        self.idle_multiplier
+    }
+
+    pub fn max_idle_period(&self) -> u64 {
+        self.max_idle_period
+    }
+
+    /// Sets how the gossip period is stretched while the view is empty, as for an isolated first seed waiting for
+    /// other nodes to join. The rounds finding no peer are then initiated every `multiplier` periods, and the base
+    /// period applies again as soon as the view holds a peer. Disabled by default.
+    ///
+    /// # Arguments
+    ///
+    /// * `multiplier` - Factor applied to the period while the view is empty, 1 to disable
+    pub fn set_empty_view_backoff(&mut self, multiplier: u32) {
+        self.empty_view_multiplier = multiplier.max(1);
+    }
+
+    pub fn empty_view_multiplier(&self) -> u32 {
+        self.empty_view_multiplier
+    }
+
+    /// Sets whether the node fetches the active updates of its bootstrap peers before it is ready, see [SyncPolicy].
+    /// The initial sync requires pull, and the bootstrap peers must have pull enabled to answer it. Disabled by default.
+    pub fn set_initial_sync(&mut self, initial_sync: SyncPolicy) {
+        self.initial_sync = initial_sync;
+    }
+
+    pub fn initial_sync(&self) -> SyncPolicy {
+        self.initial_sync
+    }
+
+    /// Sets whether a header request is still sent at each round when there is nothing to push and no pull probe to
+    /// send, as in push-only mode without active updates. Such a request carries nothing and is not answered, so it
+    /// is skipped by default and counted in [GossipStats::skipped_empty_pushes](crate::GossipStats::skipped_empty_pushes).
+    pub fn set_empty_pushes(&mut self, empty_pushes: bool) {
+        self.empty_pushes = empty_pushes;
+    }
+
+    pub fn empty_pushes(&self) -> bool {
+        self.empty_pushes
+    }
+
+    /// Sets how the peers of each gossip round are selected. By default, they are taken from the sampler with
+    /// [get_peers](crate::PeerSampler::get_peers), which returns the peers newly added to the view first, so that
+    /// new peers are contacted soon. With uniform selection, they are chosen uniformly at random from the view
+    /// with [sample_peers](crate::PeerSampler::sample_peers), whatever the order in which they were added.
+    pub fn set_uniform_peer_selection(&mut self, uniform_peer_selection: bool) {
+        self.uniform_peer_selection = uniform_peer_selection;
+    }
+
+    pub fn uniform_peer_selection(&self) -> bool {
+        self.uniform_peer_selection
+    }
+
+    /// Biases the selection of the peers of each gossip round toward the peers from which new digests were recently
+    /// learned. In a stable cluster, exchanges with some peers may bring nothing new for many rounds while others
+    /// have plenty. The peers chosen by the sampler are followed, except that in a fraction of the rounds, the peer
+    /// of the view with the most new digests per exchange receives the pushed headers. The history of each peer is
+    /// multiplied by the decay at each round, so that the bias does not outlive the usefulness of a peer. Disabled
+    /// by default, see [GossipService::peer_info](crate::GossipService::peer_info).
+    ///
+    /// # Arguments
+    ///
+    /// * `bias` - Fraction of the rounds preferring the most useful peer, between 0 (disabled) and 1
+    /// * `decay` - Factor applied to the history of the peers at each round, between 0 and 1
+    pub fn set_useful_peer_bias(&mut self, bias: f64, decay: f64) {
+        self.useful_peer_bias = bias.clamp(0.0, 1.0);
+        self.usefulness_decay = decay.clamp(0.0, 1.0);
+    }
+
+    pub fn useful_peer_bias(&self) -> f64 {
+        self.useful_peer_bias
+    }
+
+    pub fn usefulness_decay(&self) -> f64 {
+        self.usefulness_decay
+    }
+
+    /// Sets whether the updates submitted by each node are delivered in submission order. The updates submitted
+    /// by the node are numbered, and received updates are held back until the updates of the same origin numbered
+    /// before them are delivered, or for `gap_timeout` milliseconds, after which the missing updates are skipped,
+    /// see [UpdateHandler::on_sequence_gap](crate::UpdateHandler::on_sequence_gap). Updates without number bypass
+    /// the ordering. The digest of a numbered update covers its number, so that a peer relaying it cannot renumber
+    /// it; each submission is then a distinct update, even of a content submitted before. The origins that have not
+    /// delivered an update for a while are forgotten, and start over as if they were new.
+    /// Disabled by default, with a gap timeout of 5 seconds.
+    ///
+    /// # Arguments
+    ///
+    /// * `ordered_delivery` - Whether updates are numbered at submission and delivered in order
+    /// * `gap_timeout` - The time during which an update waits for the missing updates before it (milliseconds)
+    pub fn set_ordered_delivery(&mut self, ordered_delivery: bool, gap_timeout: u64) {
+        self.ordered_delivery = ordered_delivery;
+        self.gap_timeout = gap_timeout;
+    }
+
+    pub fn ordered_delivery(&self) -> bool {
+        self.ordered_delivery
+    }
+
+    pub fn gap_timeout(&self) -> u64 {
+        self.gap_timeout
+    }
+
+    /// Sets whether the deliveries must be acknowledged by the handler. With acknowledged delivery, updates are
+    /// delivered with [UpdateHandler::try_on_update](crate::UpdateHandler::try_on_update), and an update is
+    /// recorded as delivered only when the call succeeds. A failed delivery is attempted again after
+    /// `retry_delay` milliseconds, doubled at each attempt up to one minute, as long as the update is active and
+    /// the handler is not replaced. Disabled by default, with a retry delay of 1 second.
+    ///
+    /// # Arguments
+    ///
+    /// * `acknowledged_delivery` - Whether updates are delivered again until the handler acknowledges them
+    /// * `retry_delay` - The time after which the first failed delivery is attempted again (milliseconds)
+    pub fn set_acknowledged_delivery(&mut self, acknowledged_delivery: bool, retry_delay: u64) {
+        self.acknowledged_delivery = acknowledged_delivery;
+        self.delivery_retry_delay = retry_delay;
+    }
+
+    pub fn acknowledged_delivery(&self) -> bool {
+        self.acknowledged_delivery
+    }
+
+    pub fn delivery_retry_delay(&self) -> u64 {
+        self.delivery_retry_delay
+    }
+
+    /// Sets the tombstones advertised in header messages: the digests of the updates that expired during the
+    /// last `tombstone_window` milliseconds, at most `max_tombstones` of them. A node receiving a tombstone
+    /// removes the update if it is active, and otherwise never requests it, so that an update expired by one
+    /// node is not disseminated again by nodes that missed its expiration. Received tombstones are advertised in
+    /// turn. At most `max_tombstones` tombstones of a received message are applied, and only when the address
+    /// reported by its sender matches the IP its connection came from. Disabled by default, with a window of 1 minute.
+    ///
+    /// # Arguments
+    ///
+    /// * `max_tombstones` - The maximum number of tombstones in a header message, zero to disable them
+    /// * `tombstone_window` - The time during which an expired digest is advertised (milliseconds)
+    pub fn set_tombstones(&mut self, max_tombstones: usize, tombstone_window: u64) {
+        self.max_tombstones = max_tombstones;
+        self.tombstone_window = tombstone_window;
+    }
+
+    pub fn max_tombstones(&self) -> usize {
+        self.max_tombstones
+    }
+
+    pub fn tombstone_window(&self) -> u64 {
+        self.tombstone_window
+    }
+
+    /// Sets whether the node understands the updates revoking other updates, see
+    /// [GossipService::revoke](crate::GossipService::revoke). A node receiving a revocation removes the revoked
+    /// update if it is active, and otherwise does not insert it when it arrives, provided that the update was
+    /// submitted with the [origin id](Self::set_origin_id) of the revocation. The revocation is disseminated like any
+    /// update but never delivered to the handler, and expires after ten minutes if the updates never expire. A node
+    /// that does not enable revocations disseminates them without applying them, so all the nodes of the cluster
+    /// should enable them. Disabled by default.
+    ///
+    /// # Arguments
+    ///
+    /// * `revocations` - Whether revocations are submitted and applied
+    pub fn set_revocations(&mut self, revocations: bool) {
+        self.revocations = revocations;
+    }
+
+    pub fn revocations(&self) -> bool {
+        self.revocations
+    }
+
+    /// Sets the file storing the epoch of the node, incremented at each start so that a restarted node can be told
+    /// apart, see [GossipStats::epoch](crate::GossipStats::epoch). The service fails to start if the file cannot be
+    /// read or written. Without a file, the epoch is 1 once started.
+    ///
+    /// # Arguments
+    ///
+    /// * `epoch_file` - Path of the file, created at the first start
+    pub fn set_epoch_file(&mut self, epoch_file: impl Into<PathBuf>) {
+        self.epoch_file = Some(epoch_file.into());
+    }
+
+    pub fn epoch_file(&self) -> Option<&Path> {
+        self.epoch_file.as_deref()
+    }
+
+    /// Sets whether the node only opens outbound connections, for nodes behind a firewall that refuses incoming
+    /// connections. No listener is started: the requests of the node ask their receiver to write its responses on
+    /// the connection of the request, which the node reads before closing it. Since its peers cannot connect to it,
+    /// the node should not advertise itself, see [PeerSamplingConfig::set_advertise_self].
+    ///
+    /// # Arguments
+    ///
+    /// * `outbound_only` - Whether the node starts no listener
+    pub fn set_outbound_only(&mut self, outbound_only: bool) {
+        self.outbound_only = outbound_only;
+    }
+
+    pub fn outbound_only(&self) -> bool {
+        self.outbound_only
+    }
+
+    /// Sets the keepalive probes, which tell idle peers from dead ones when the gossip period is long: every
+    /// `keepalive_interval` milliseconds, a ping is sent to a peer of the view not heard from during the interval,
+    /// and a peer that leaves `max_missed` pings in a row unanswered is removed from the view, see
+    /// [PeerSampler::report_unreachable](crate::PeerSampler::report_unreachable). Disabled by default, with 3
+    /// missed pings.
+    ///
+    /// # Arguments
+    ///
+    /// * `keepalive_interval` - Time between two pings (milliseconds), zero to disable the probes
+    /// * `max_missed` - Number of pings left unanswered in a row after which a peer is removed, at least one
+    pub fn set_keepalive(&mut self, keepalive_interval: u64, max_missed: u32) {
+        self.keepalive_interval = keepalive_interval;
+        self.keepalive_max_missed = max_missed.max(1);
+    }
+
+    pub fn keepalive_interval(&self) -> u64 {
+        self.keepalive_interval
+    }
+
+    pub fn keepalive_max_missed(&self) -> u32 {
+        self.keepalive_max_missed
+    }
+
+    /// Sets the detection of the isolation of the node: when the messages sent to every peer of a non-empty view
+    /// fail for `window` milliseconds, the node is [isolated](crate::HealthReport::network_isolated), which is
+    /// recorded in the [errors](crate::GossipService::recent_errors) and reported to the
+    /// [observer](crate::RoundObserver::on_network_isolated). The node then [bootstraps again](crate::GossipService::rebootstrap)
+    /// every `rebootstrap_delay` milliseconds until a message is exchanged with a peer of the view. Disabled by default, with a
+    /// delay of 10 seconds.
+    ///
+    /// # Arguments
+    ///
+    /// * `window` - Time during which all the sends fail before the node is isolated (milliseconds), `None` to disable the detection
+    /// * `rebootstrap_delay` - Time between the isolation and each attempt to bootstrap again (milliseconds)
+    pub fn set_isolation_detection(&mut self, window: Option<u64>, rebootstrap_delay: u64) {
+        self.isolation_window = window;
+        self.rebootstrap_delay = rebootstrap_delay;
+    }
+
+    pub fn isolation_window(&self) -> Option<u64> {
+        self.isolation_window
+    }
+
+    pub fn rebootstrap_delay(&self) -> u64 {
+        self.rebootstrap_delay
+    }
 }
 
 impl Default for GossipConfig {
@@ -159,58 +1418,303 @@ impl Default for GossipConfig {
             pull: true,
             gossip_period: 1000,
             gossip_deviation: 0,
-            update_expiration: UpdateExpirationMode::None
+            update_expiration: UpdateExpirationMode::None,
+            expiration_start: ExpirationStart::OnInsert,
+            listener_workers: DEFAULT_LISTENER_WORKERS,
+            accept_backlog: DEFAULT_ACCEPT_BACKLOG,
+            connection_deadline: DEFAULT_CONNECTION_DEADLINE,
+            max_connections_per_ip: DEFAULT_MAX_CONNECTIONS_PER_IP,
+            sampling_queue_capacity: DEFAULT_MESSAGE_QUEUE_CAPACITY,
+            header_queue_capacity: DEFAULT_MESSAGE_QUEUE_CAPACITY,
+            content_queue_capacity: DEFAULT_CONTENT_QUEUE_CAPACITY,
+            queue_timeout: DEFAULT_QUEUE_TIMEOUT,
+            in_flight_timeout: None,
+            content_request_timeout: None,
+            cluster_id: None,
+            cluster_size: None,
+            pull_fanout: 1,
+            max_headers_per_push: None,
+            verify_content_digests: true,
+            accept_unrequested_content: false,
+            quarantine_threshold: DEFAULT_QUARANTINE_THRESHOLD,
+            quarantine_cooldown: DEFAULT_QUARANTINE_COOLDOWN,
+            max_digest_mismatches: DEFAULT_MAX_DIGEST_MISMATCHES,
+            mismatch_cooldown: DEFAULT_MISMATCH_COOLDOWN,
+            cluster_name: None,
+            network: NetworkConfig::default(),
+            queue_before_start: true,
+            eager_first_push: false,
+            refresh_expired: false,
+            origin_id: None,
+            suppress_own_origin_redelivery: false,
+            strict_checks: false,
+            digester: crate::digest::default_digester(),
+            fetch_policy: None,
+            declined_fetch_ttl: DEFAULT_DECLINED_FETCH_TTL,
+            relay_headers: false,
+            idle_rounds: 0,
+            idle_multiplier: 2,
+            empty_view_multiplier: 1,
+            max_idle_period: 0,
+            initial_sync: SyncPolicy::None,
+            empty_pushes: false,
+            uniform_peer_selection: false,
+            useful_peer_bias: 0.0,
+            usefulness_decay: DEFAULT_USEFULNESS_DECAY,
+            ordered_delivery: false,
+            gap_timeout: DEFAULT_GAP_TIMEOUT,
+            acknowledged_delivery: false,
+            delivery_retry_delay: DEFAULT_DELIVERY_RETRY_DELAY,
+            max_tombstones: 0,
+            revocations: false,
+            epoch_file: None,
+            outbound_only: false,
+            keepalive_interval: 0,
+            keepalive_max_missed: DEFAULT_KEEPALIVE_MAX_MISSED,
+            isolation_window: None,
+            rebootstrap_delay: DEFAULT_REBOOTSTRAP_DELAY,
+            tombstone_window: DEFAULT_TOMBSTONE_WINDOW,
+        }
+    }
+}
+
+/// The options of the sockets of a node.
+///
+/// `TCP_NODELAY` is applied with the standard library. Address reuse and keepalive require the `socket2`
+/// feature; without it, the listener reuses addresses as the standard library does (on Unix but not on
+/// Windows) and no keepalive is configured.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "config-serde", derive(serde::Serialize, serde::Deserialize), serde(default, deny_unknown_fields))]
+pub struct NetworkConfig {
+    reuse_address: bool,
+    nodelay: bool,
+    #[cfg_attr(feature = "config-serde", serde(with = "crate::node_config::option_millis", skip_serializing_if = "Option::is_none"))]
+    keepalive: Option<u64>,
+    reply_address: ReplyAddress,
+    reply_in_stream: bool,
+}
+
+impl NetworkConfig {
+    /// Sets whether the listener sets `SO_REUSEADDR`, so that a node can be restarted on its port while
+    /// connections of the previous run are still closing. Enabled by default.
+    pub fn set_reuse_address(&mut self, reuse_address: bool) {
+        self.reuse_address = reuse_address;
+    }
+
+    pub fn reuse_address(&self) -> bool {
+        self.reuse_address
+    }
+
+    /// Sets whether outbound connections set `TCP_NODELAY`, so that small messages such as headers are
+    /// sent without delay. Enabled by default.
+    pub fn set_nodelay(&mut self, nodelay: bool) {
+        self.nodelay = nodelay;
+    }
+
+    pub fn nodelay(&self) -> bool {
+        self.nodelay
+    }
+
+    /// Sets the idle time after which keepalive probes are sent on outbound connections. Disabled by default.
+    ///
+    /// # Arguments
+    ///
+    /// * `keepalive` - The idle time (milliseconds), or `None` to disable keepalive
+    pub fn set_keepalive(&mut self, keepalive: Option<u64>) {
+        self.keepalive = keepalive;
+    }
+
+    pub fn keepalive(&self) -> Option<u64> {
+        self.keepalive
+    }
+
+    /// Sets the address to which responses are sent, given the address a message reports for its sender and the
+    /// address its connection came from. Defaults to [ReplyAddress::SelfReported].
+    pub fn set_reply_address(&mut self, reply_address: ReplyAddress) {
+        self.reply_address = reply_address;
+    }
+
+    pub fn reply_address(&self) -> ReplyAddress {
+        self.reply_address
+    }
+
+    /// Sets whether the requests of the node ask their receiver to write its responses on the connection of the
+    /// request instead of connecting back to the node, which halves the number of connections of an exchange. The
+    /// receivers that do not know the flag ignore it and connect back as before. Disabled by default; always the
+    /// case for an [outbound-only](GossipConfig::set_outbound_only) node.
+    pub fn set_reply_in_stream(&mut self, reply_in_stream: bool) {
+        self.reply_in_stream = reply_in_stream;
+    }
+
+    pub fn reply_in_stream(&self) -> bool {
+        self.reply_in_stream
+    }
+}
+
+impl Default for NetworkConfig {
+    fn default() -> Self {
+        NetworkConfig {
+            reuse_address: true,
+            nodelay: true,
+            keepalive: None,
+            reply_address: ReplyAddress::SelfReported,
+            reply_in_stream: false,
+        }
+    }
+}
+
+/// Address to which a node sends the responses to a message, see [NetworkConfig::set_reply_address].
+///
+/// The sender of a message reports its address in the message, while its connection comes from the address
+/// observed by the receiver. The observed port is the ephemeral port of the connection, so only the observed IP
+/// is compared to the reported one: they differ when the sender is behind a NAT, misconfigured, or lying.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "config-serde", derive(serde::Serialize, serde::Deserialize), serde(rename_all = "snake_case"))]
+pub enum ReplyAddress {
+    /// Responses are sent to the address reported by the sender
+    SelfReported,
+    /// Responses are sent to the observed IP, on the port reported by the sender
+    ObservedIp,
+    /// Messages whose reported IP differs from the observed one are dropped
+    RejectMismatch,
+}
+
+/// A combination of parameters that is valid but likely to prevent the dissemination of updates,
+/// see [GossipConfig::check]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConfigWarning {
+    /// Updates expire after a duration (milliseconds) that does not cover two gossip rounds, and may expire before being pushed
+    ExpirationShorterThanRounds { expiration: u64, gossip_period: u64 },
+    /// Peer sampling is so slow compared to gossip that gossip may run with an empty view for many rounds
+    SlowSampling { sampling_period: u64, gossip_period: u64 },
+    /// Updates and views are not disseminated the same way, so that the peers sampled may not exchange updates with the node
+    ModeMismatch { gossip: DisseminationMode, sampling: DisseminationMode },
+    /// The node accepts no connection but advertises itself, so that its peers fail to connect to it
+    OutboundOnlyAdvertised,
+}
+
+impl std::fmt::Display for ConfigWarning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigWarning::ExpirationShorterThanRounds { expiration, gossip_period } =>
+                write!(f, "updates expire after {} ms, which does not cover {} gossip rounds of {} ms", expiration, MIN_ROUNDS_BEFORE_EXPIRATION, gossip_period),
+            ConfigWarning::SlowSampling { sampling_period, gossip_period } =>
+                write!(f, "sampling period of {} ms is more than {} times the gossip period of {} ms", sampling_period, MAX_SAMPLING_TO_GOSSIP_RATIO, gossip_period),
+            ConfigWarning::ModeMismatch { gossip, sampling } =>
+                write!(f, "gossip runs in {} mode but peer sampling in {} mode", gossip, sampling),
+            ConfigWarning::OutboundOnlyAdvertised =>
+                write!(f, "the node is outbound-only but advertises itself to its peers"),
+        }
+    }
+}
+
+/// How a node exchanges data with its peers, derived from the push and pull flags of a configuration
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DisseminationMode {
+    /// The node only sends its data to peers
+    Push,
+    /// The node only requests data from peers
+    Pull,
+    /// The node sends its data to peers and requests theirs
+    PushPull,
+}
+
+impl DisseminationMode {
+    fn from_flags(push: bool, pull: bool) -> Option<Self> {
+        match (push, pull) {
+            (true, true) => Some(DisseminationMode::PushPull),
+            (true, false) => Some(DisseminationMode::Push),
+            (false, true) => Some(DisseminationMode::Pull),
+            (false, false) => None,
+        }
+    }
+}
+
+impl std::fmt::Display for DisseminationMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DisseminationMode::Push => write!(f, "push"),
+            DisseminationMode::Pull => write!(f, "pull"),
+            DisseminationMode::PushPull => write!(f, "push-pull"),
         }
     }
 }
 
 /// Strategy for update expiration
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "config-serde", derive(serde::Serialize, serde::Deserialize), serde(tag = "mode", content = "value", rename_all = "snake_case", deny_unknown_fields))]
 pub enum UpdateExpirationMode {
     /// Updates never expire
     None,
     /// Updates expire after the specified duration (milliseconds)
-    DurationMillis(u128),
+    DurationMillis(#[cfg_attr(feature = "config-serde", serde(with = "crate::node_config::millis_u128"))] u128),
     /// Updates expire after being pushed the specified number of times
     PushCount(u64),
     /// Only the specified count of the most recent updates
     MostRecent(usize, f64),
 }
 
+/// Event from which the duration of updates expiring after a [duration](UpdateExpirationMode::DurationMillis) is counted
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "config-serde", derive(serde::Serialize, serde::Deserialize), serde(rename_all = "snake_case"))]
+pub enum ExpirationStart {
+    /// The update is inserted, i.e. submitted locally or received from a peer
+    OnInsert,
+    /// The digest of the update is first sent to a peer in a header message
+    OnFirstPush,
+    /// The content of the update is first sent to a peer that requested it
+    OnFirstDelivery,
+}
+
+/// Whether a node fetches the active updates of its bootstrap peers when it starts, see [GossipConfig::set_initial_sync]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "config-serde", derive(serde::Serialize, serde::Deserialize), serde(tag = "policy", rename_all = "snake_case", deny_unknown_fields))]
+pub enum SyncPolicy {
+    /// The node is ready as soon as it is started
+    None,
+    /// The node probes the peers of its view at start for their headers at each gossip period, and fetches the new
+    /// updates, until a round brings no new digest or the timeout (milliseconds) elapses. Gossip rounds start
+    /// afterwards, and the node is not [ready](crate::HealthReport::ready) before.
+    FromBootstrap {
+        #[cfg_attr(feature = "config-serde", serde(with = "crate::node_config::millis"))]
+        timeout: u64,
+    },
+}
+
 pub enum UpdateExpirationValue {
     None,
-    DurationMillis(std::time::Instant, u128),
+    /// Start of the duration, `None` until it starts, and duration
+    DurationMillis(Option<std::time::Instant>, u128),
     PushCount(u64),
-    MostRecent(std::time::Instant),
+    MostRecent,
 }
 impl UpdateExpirationValue {
-    pub fn new(expiration_mode: UpdateExpirationMode) -> Self {
+    /// Creates the expiration of a new update; a duration starts at `start` if it is set
+    pub fn new(expiration_mode: UpdateExpirationMode, start: Option<std::time::Instant>) -> Self {
         match expiration_mode {
             UpdateExpirationMode::None => UpdateExpirationValue::None,
             UpdateExpirationMode::PushCount(count) => UpdateExpirationValue::PushCount(count),
-            UpdateExpirationMode::DurationMillis(ms) => UpdateExpirationValue::DurationMillis(std::time::Instant::now(), ms),
-            UpdateExpirationMode::MostRecent(_, _) => UpdateExpirationValue::MostRecent(std::time::Instant::now()),
+            UpdateExpirationMode::DurationMillis(ms) => UpdateExpirationValue::DurationMillis(start, ms),
+            UpdateExpirationMode::MostRecent(_, _) => UpdateExpirationValue::MostRecent,
         }
     }
 
     pub fn increase_push_count(&mut self) {
-        match self {
-            // increase push count
-            UpdateExpirationValue::PushCount(ref mut count) => {
-                if *count > 0 {
-                    *count -= 1
-                }
-            },
-            _ => (),
+        // increase push count
+        if let UpdateExpirationValue::PushCount(ref mut count) = self {
+            if *count > 0 {
+                *count -= 1
+            }
         }
     }
 
-    pub fn has_expired(&self) -> bool {
+    /// Returns whether the update has expired at the time `now`, read from the clock of the updates
+    pub fn has_expired(&self, now: std::time::Instant) -> bool {
         match self {
             UpdateExpirationValue::None => false,
             UpdateExpirationValue::PushCount(count) => *count == 0,
-            UpdateExpirationValue::DurationMillis(start, ttl) => start.elapsed().as_millis() >= *ttl,
-            UpdateExpirationValue::MostRecent(_) => false,
+            UpdateExpirationValue::DurationMillis(start, ttl) => start.is_some_and(|start| now.saturating_duration_since(start).as_millis() >= *ttl),
+            UpdateExpirationValue::MostRecent => false,
         }
     }
 }
\ No newline at end of file
@@ -0,0 +1,57 @@
+/// Estimates the probability that an update reached every node of the cluster.
+///
+/// The estimate follows the infect-and-die model: every node that received the update pushes its digest as many
+/// times as the local node did, each time to a peer chosen at random. A node then misses all the pushes with
+/// probability `e^-pushes`, and the update reaches the `cluster_size - 1` other nodes with probability
+/// `(1 - e^-pushes)^(cluster_size - 1)`, close to `e^(-e^-c)` when `pushes = ln(cluster_size) + c`.
+///
+/// # Arguments
+///
+/// * `cluster_size` - Number of nodes in the cluster, the local node included
+/// * `pushes` - Number of rounds in which the local node pushed the digest of the update
+pub(crate) fn coverage_probability(cluster_size: usize, pushes: u64) -> f64 {
+    let missed = (-(pushes as f64)).exp();
+    (1.0 - missed).powf(cluster_size.saturating_sub(1) as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn single_node_is_covered() {
+        assert_eq!(1.0, coverage_probability(1, 0));
+        assert_eq!(1.0, coverage_probability(0, 0));
+        assert_eq!(1.0, coverage_probability(1, 5));
+    }
+
+    #[test]
+    fn unpushed_update_covers_nothing() {
+        assert_eq!(0.0, coverage_probability(2, 0));
+        assert_eq!(0.0, coverage_probability(1000, 0));
+    }
+
+    #[test]
+    fn coverage_grows_with_pushes_and_shrinks_with_size() {
+        for pushes in 1..30 {
+            assert!(coverage_probability(100, pushes) > coverage_probability(100, pushes - 1));
+            assert!(coverage_probability(100, pushes) < coverage_probability(50, pushes));
+        }
+        assert!(coverage_probability(100, 30) > 0.999_999);
+        // two peers, one push: each misses it with probability 1/e
+        let expected = (1.0 - (-1.0f64).exp()).powi(2);
+        assert!((coverage_probability(3, 1) - expected).abs() < 1e-12);
+    }
+
+    #[test]
+    fn large_clusters_follow_the_double_exponential() {
+        // with ln(n) + c pushes, every node is reached with probability e^(-e^-c)
+        let size = 100_000;
+        for c in [0.0f64, 1.0, 2.0, 4.0] {
+            let pushes = (size as f64).ln() + c;
+            let estimate = coverage_probability(size, pushes.round() as u64);
+            let limit = (-(-(pushes.round() - (size as f64).ln())).exp()).exp();
+            assert!((estimate - limit).abs() < 1e-3, "c = {}: {} vs {}", c, estimate, limit);
+        }
+    }
+}
@@ -0,0 +1,43 @@
+use std::sync::Arc;
+
+/// Hexadecimal digest identifying the content of an update
+pub type Digest = String;
+
+/// Function computing the digests of update contents.
+///
+/// All the nodes of a network must use the same function: content whose digest does not match the advertised
+/// one is dropped.
+pub trait Digester: Send + Sync {
+    /// Computes the digest of the content
+    ///
+    /// # Arguments
+    ///
+    /// * `content` - Content of an update
+    fn digest(&self, content: &[u8]) -> Digest;
+}
+
+/// BLAKE3 digests, used by default
+pub struct Blake3Digester;
+
+impl Digester for Blake3Digester {
+    fn digest(&self, content: &[u8]) -> Digest {
+        blake3::hash(content).to_hex().to_string()
+    }
+}
+
+/// SHA-256 digests
+#[cfg(feature = "sha256")]
+pub struct Sha256Digester;
+
+#[cfg(feature = "sha256")]
+impl Digester for Sha256Digester {
+    fn digest(&self, content: &[u8]) -> Digest {
+        use sha2::Digest as _;
+        format!("{:x}", sha2::Sha256::digest(content))
+    }
+}
+
+/// Returns the digest function used by default
+pub(crate) fn default_digester() -> Arc<dyn Digester> {
+    Arc::new(Blake3Digester)
+}
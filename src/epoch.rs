@@ -0,0 +1,38 @@
+use std::path::Path;
+
+/// Returns the epoch following the one stored in the file, and stores it. A missing file counts as epoch zero.
+///
+/// The new epoch is written to a temporary file renamed over the previous one, so that an interrupted write leaves
+/// the previous epoch in place.
+pub(crate) fn next_epoch(path: &Path) -> Result<u64, Box<dyn std::error::Error>> {
+    let previous = match std::fs::read_to_string(path) {
+        Ok(content) => content.trim().parse::<u64>()
+            .map_err(|e| format!("Invalid epoch in {}: {}", path.display(), e))?,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => 0,
+        Err(e) => Err(format!("Could not read epoch from {}: {}", path.display(), e))?,
+    };
+    let epoch = previous + 1;
+    let temporary = path.with_extension("tmp");
+    std::fs::write(&temporary, epoch.to_string())
+        .and_then(|()| std::fs::rename(&temporary, path))
+        .map_err(|e| format!("Could not store epoch in {}: {}", path.display(), e))?;
+    Ok(epoch)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn epoch_increments_at_each_call() {
+        let path = std::env::temp_dir().join(format!("gossip-epoch-{}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(1, next_epoch(&path).unwrap());
+        assert_eq!(2, next_epoch(&path).unwrap());
+        assert_eq!("2", std::fs::read_to_string(&path).unwrap());
+
+        std::fs::write(&path, "not an epoch").unwrap();
+        assert!(next_epoch(&path).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
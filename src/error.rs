@@ -0,0 +1,164 @@
+use std::fmt;
+use std::net::SocketAddr;
+
+/// Error returned when the listener of a node cannot be bound to its address
+#[derive(Debug)]
+pub struct BindError {
+    /// The address that could not be bound
+    address: SocketAddr,
+    /// The underlying I/O error
+    source: std::io::Error,
+}
+
+impl BindError {
+    pub(crate) fn new(address: SocketAddr, source: std::io::Error) -> Self {
+        BindError {
+            address,
+            source,
+        }
+    }
+
+    /// Returns the address that could not be bound
+    pub fn address(&self) -> &SocketAddr {
+        &self.address
+    }
+
+    /// Returns the kind of the underlying I/O error, e.g. [std::io::ErrorKind::AddrInUse]
+    pub fn kind(&self) -> std::io::ErrorKind {
+        self.source.kind()
+    }
+}
+
+impl fmt::Display for BindError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "could not bind listener at {}: {}", self.address, self.source)
+    }
+}
+
+impl std::error::Error for BindError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.source)
+    }
+}
+
+/// Error returned when an operation requires the service to be started
+#[derive(Debug)]
+pub struct NotStarted;
+
+impl fmt::Display for NotStarted {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "the service is not started")
+    }
+}
+
+impl std::error::Error for NotStarted {}
+
+/// Error returned when a service or a [PeerSampler](crate::PeerSampler) is started a second time
+#[derive(Debug)]
+pub struct AlreadyStarted;
+
+impl fmt::Display for AlreadyStarted {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "the service was already started")
+    }
+}
+
+impl std::error::Error for AlreadyStarted {}
+
+/// Error returned by [UpdateHandler::try_on_update](crate::UpdateHandler::try_on_update) when an update could
+/// not be processed, so that it is delivered again later
+#[derive(Debug)]
+pub struct HandlerError {
+    /// Why the update could not be processed
+    reason: String,
+}
+
+impl HandlerError {
+    pub fn new(reason: impl Into<String>) -> Self {
+        HandlerError {
+            reason: reason.into(),
+        }
+    }
+
+    /// Returns why the update could not be processed
+    pub fn reason(&self) -> &str {
+        &self.reason
+    }
+}
+
+impl fmt::Display for HandlerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "the update handler failed: {}", self.reason)
+    }
+}
+
+impl std::error::Error for HandlerError {}
+
+/// Error returned by [Peer::try_new](crate::Peer::try_new) when an address is not a socket address
+#[derive(Debug)]
+pub struct AddressError {
+    /// The invalid address
+    address: String,
+}
+
+impl AddressError {
+    pub(crate) fn new(address: &str) -> Self {
+        AddressError {
+            address: address.to_owned(),
+        }
+    }
+
+    /// Returns the invalid address
+    pub fn address(&self) -> &str {
+        &self.address
+    }
+}
+
+impl fmt::Display for AddressError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid peer address {:?}: expected an IP address and a port", self.address)
+    }
+}
+
+impl std::error::Error for AddressError {}
+
+/// Error returned by [GossipService::submit_batch](crate::GossipService::submit_batch) when a batch could not be
+/// submitted entirely
+#[derive(Debug)]
+pub struct BatchError {
+    /// Digests of the updates of the batch inserted before the failure
+    inserted: Vec<String>,
+    /// Why the rest of the batch was not submitted
+    source: Box<dyn std::error::Error + Send + Sync>,
+}
+
+impl BatchError {
+    pub(crate) fn new(inserted: Vec<String>, source: impl Into<Box<dyn std::error::Error + Send + Sync>>) -> Self {
+        BatchError {
+            inserted,
+            source: source.into(),
+        }
+    }
+
+    /// Returns the digests of the updates of the batch inserted before the failure, which are broadcast
+    pub fn inserted(&self) -> &[String] {
+        &self.inserted
+    }
+
+    /// Returns the cause of the failure, e.g. [NotStarted]
+    pub(crate) fn into_source(self) -> Box<dyn std::error::Error + Send + Sync> {
+        self.source
+    }
+}
+
+impl fmt::Display for BatchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "batch submission failed after {} updates: {}", self.inserted.len(), self.source)
+    }
+}
+
+impl std::error::Error for BatchError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(self.source.as_ref())
+    }
+}
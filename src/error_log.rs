@@ -0,0 +1,65 @@
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+/// Number of recent errors kept by a service, see [GossipService::recent_errors](crate::GossipService::recent_errors)
+pub const MAX_RECENT_ERRORS: usize = 100;
+
+/// Stage of the protocols at which an error occurred
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorCategory {
+    /// A message could not be sent to a peer
+    Send,
+    /// A message could not be read from a connection
+    Receive,
+    /// A received message or address could not be parsed
+    Parse,
+    /// A received message could not be handled
+    Handler,
+}
+
+/// An error of the protocol threads
+#[derive(Debug, Clone)]
+pub struct ErrorRecord {
+    /// Time at which the error occurred
+    pub time: SystemTime,
+    /// Stage at which the error occurred
+    pub category: ErrorCategory,
+    /// Address of the peer involved, if known
+    pub peer: Option<String>,
+    /// Description of the error
+    pub message: String,
+}
+
+/// The most recent errors of the protocol threads, oldest first
+pub(crate) struct ErrorLog {
+    records: Mutex<VecDeque<ErrorRecord>>,
+}
+
+impl ErrorLog {
+    pub fn new() -> Self {
+        ErrorLog {
+            records: Mutex::new(VecDeque::with_capacity(MAX_RECENT_ERRORS)),
+        }
+    }
+
+    /// Records an error, forgetting the oldest one if the log is full
+    pub fn record(&self, category: ErrorCategory, peer: Option<&str>, message: String) {
+        let record = ErrorRecord {
+            time: SystemTime::now(),
+            category,
+            peer: peer.map(str::to_owned),
+            message,
+        };
+        let mut records = self.records.lock().unwrap();
+        if records.len() == MAX_RECENT_ERRORS {
+            records.pop_front();
+        }
+        records.push_back(record);
+    }
+
+    /// Returns the recorded errors, oldest first
+    pub fn recent(&self) -> Vec<ErrorRecord> {
+        self.records.lock().unwrap().iter().cloned().collect()
+    }
+}
@@ -0,0 +1,210 @@
+use std::error::Error;
+use serde::{Serialize, Deserialize};
+use crate::digest::{Blake3Digester, Digester};
+
+/// State of the size estimation of a node, exchanged with the peer sampling messages.
+///
+/// The nodes average their values at each exchange: the leader of an epoch, the node whose address has the
+/// lowest digest, starts it with a value of 1 and the other nodes with 0, so that all the values converge to
+/// the inverse of the number of nodes.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct SizeEstimate {
+    /// Number of the estimation, increased at the end of each epoch
+    epoch: u64,
+    /// Digest of the address of the leader known to the sender
+    leader: String,
+    /// Share of the value of the leader held by the sender
+    value: f64,
+}
+
+impl SizeEstimate {
+    /// Checks that a received state is within the limits of the messages, and holds a share of a value
+    pub fn check_limits(&self) -> Result<(), Box<dyn Error>> {
+        crate::message::check_digests("leader", std::iter::once(&self.leader))?;
+        if !(0.0..=1.0).contains(&self.value) {
+            Err(format!("Invalid size estimation value: {}", self.value))?
+        }
+        Ok(())
+    }
+}
+
+/// Number of epochs by which a peer may be ahead of a node that already exchanged its state. A state further ahead
+/// is ignored, so that a peer cannot push the node to the last epochs.
+const MAX_EPOCH_JUMP: u64 = 2;
+
+/// Estimates the number of nodes of the network by averaging values with the peers, see
+/// [PeerSamplingConfig::set_size_estimation](crate::PeerSamplingConfig::set_size_estimation)
+pub(crate) struct SizeEstimator {
+    /// Digest of the address of the node, with which it competes for the leadership of each epoch
+    key: String,
+    /// Number of sampling rounds of an epoch
+    epoch_rounds: u32,
+    /// Number of sampling rounds of the node in the current epoch
+    rounds: u32,
+    /// Current state of the node
+    state: SizeEstimate,
+    /// Estimate of the last completed epoch
+    estimate: Option<f64>,
+    /// Whether the node merged the state of a peer, after which it follows the epochs of its peers one by one
+    joined: bool,
+}
+
+impl SizeEstimator {
+    /// Creates an estimator starting the first epoch
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - Address of the node
+    /// * `epoch_rounds` - Number of sampling rounds of an epoch
+    pub fn new(address: &str, epoch_rounds: u32) -> Self {
+        let key = Self::key(address);
+        SizeEstimator {
+            state: SizeEstimate { epoch: 0, leader: key.clone(), value: 1.0 },
+            key,
+            epoch_rounds,
+            rounds: 0,
+            estimate: None,
+            joined: false,
+        }
+    }
+
+    /// Returns the key of the node at an address
+    fn key(address: &str) -> String {
+        Blake3Digester.digest(address.as_bytes())
+    }
+
+    /// Returns the state to send to a peer
+    pub fn state(&self) -> SizeEstimate {
+        self.state.clone()
+    }
+
+    /// Returns the number of nodes estimated at the end of the last epoch, if any
+    pub fn estimate(&self) -> Option<f64> {
+        self.estimate
+    }
+
+    /// Counts a sampling round of the node, starting the next epoch after the last round of the current one
+    pub fn round(&mut self) {
+        self.rounds += 1;
+        if self.rounds >= self.epoch_rounds {
+            self.start_epoch(self.state.epoch.saturating_add(1));
+        }
+    }
+
+    /// Records the estimate of the current epoch, unless the node has not heard of its leader yet or joined the
+    /// epoch too late for its value to converge, and starts another epoch as its own leader
+    fn start_epoch(&mut self, epoch: u64) {
+        if self.state.value > 0.0 && 2 * self.rounds >= self.epoch_rounds {
+            self.estimate = Some(1.0 / self.state.value);
+        }
+        self.state = SizeEstimate { epoch, leader: self.key.clone(), value: 1.0 };
+        self.rounds = 0;
+    }
+
+    /// Averages the value of the node with the one of a peer. Both nodes merge the state the other sent before
+    /// the exchange, so that they end with the same value. A node joining the network adopts the epoch of its first
+    /// peer; afterwards, a state more than [MAX_EPOCH_JUMP] epochs ahead of the node is ignored.
+    ///
+    /// # Arguments
+    ///
+    /// * `sender` - Address of the peer
+    /// * `remote` - State of the peer before the exchange
+    pub fn merge(&mut self, sender: &str, remote: &SizeEstimate) {
+        let ahead = remote.epoch.saturating_sub(self.state.epoch);
+        if remote.epoch == u64::MAX || (self.joined && ahead > MAX_EPOCH_JUMP) {
+            return;
+        }
+        self.joined = true;
+        let remote = if remote.epoch < self.state.epoch {
+            // the peer joins the current epoch when it receives the state of the node
+            SizeEstimate { epoch: self.state.epoch, leader: Self::key(sender), value: 1.0 }
+        }
+        else {
+            if remote.epoch > self.state.epoch {
+                self.start_epoch(remote.epoch);
+            }
+            remote.clone()
+        };
+        // only the values of the leader with the lowest key are kept
+        let leader = self.state.leader.as_str().min(remote.leader.as_str()).to_owned();
+        let local = if self.state.leader == leader { self.state.value } else { 0.0 };
+        let remote = if remote.leader == leader { remote.value } else { 0.0 };
+        self.state.leader = leader;
+        self.state.value = (local + remote) / 2.0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::Rng;
+
+    #[test]
+    fn averaging_converges_to_the_number_of_nodes() {
+        let addresses: Vec<String> = (0..20).map(|i| format!("10.0.0.{}:9000", i)).collect();
+        let mut nodes: Vec<SizeEstimator> = addresses.iter().map(|address| SizeEstimator::new(address, 40)).collect();
+        let mut rng = rand::thread_rng();
+        for _ in 0..40 {
+            for i in 0..nodes.len() {
+                let j = (i + rng.gen_range(1, nodes.len())) % nodes.len();
+                let (request, response) = (nodes[i].state(), nodes[j].state());
+                nodes[j].merge(&addresses[i], &request);
+                nodes[i].merge(&addresses[j], &response);
+                nodes[i].round();
+            }
+        }
+        for node in &nodes {
+            let estimate = node.estimate().unwrap();
+            assert!((estimate - 20.0).abs() < 2.0, "estimate {}", estimate);
+        }
+    }
+
+    #[test]
+    fn late_peer_joins_the_current_epoch() {
+        let mut node = SizeEstimator::new("10.0.0.1:9000", 2);
+        node.round();
+        node.round();
+        assert_eq!(Some(1.0), node.estimate());
+        let mut late = SizeEstimator::new("10.0.0.2:9000", 2);
+
+        // both nodes end up in epoch 1 with the same value, whichever merges first
+        let (request, response) = (late.state(), node.state());
+        node.merge("10.0.0.2:9000", &request);
+        late.merge("10.0.0.1:9000", &response);
+        assert_eq!(node.state(), late.state());
+        assert_eq!(0.5, node.state().value);
+        assert_eq!(1, late.state().epoch);
+        // the late peer had no round to estimate the previous epoch
+        assert_eq!(None, late.estimate());
+    }
+
+    #[test]
+    fn epochs_far_ahead_are_ignored() {
+        let mut node = SizeEstimator::new("10.0.0.1:9000", 2);
+        let peer = SizeEstimator::new("10.0.0.2:9000", 2);
+        node.merge("10.0.0.2:9000", &peer.state());
+        let state = node.state();
+
+        let far_ahead = |epoch| SizeEstimate { epoch, leader: Blake3Digester.digest(b"forged"), value: 1.0 };
+        node.merge("10.0.0.3:9000", &far_ahead(u64::MAX));
+        node.merge("10.0.0.3:9000", &far_ahead(MAX_EPOCH_JUMP + 1));
+        assert_eq!(state, node.state());
+        node.merge("10.0.0.3:9000", &far_ahead(MAX_EPOCH_JUMP));
+        assert_eq!(MAX_EPOCH_JUMP, node.state().epoch);
+
+        // the last epoch is never exceeded
+        node.state.epoch = u64::MAX;
+        node.round();
+        node.round();
+        assert_eq!(u64::MAX, node.state().epoch);
+    }
+
+    #[test]
+    fn joining_node_adopts_the_epoch_of_its_first_peer() {
+        let mut node = SizeEstimator::new("10.0.0.1:9000", 2);
+        let mut peer = SizeEstimator::new("10.0.0.2:9000", 2);
+        peer.state.epoch = 1_000;
+        node.merge("10.0.0.2:9000", &peer.state());
+        assert_eq!(1_000, node.state().epoch);
+    }
+}
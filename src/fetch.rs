@@ -0,0 +1,146 @@
+use std::collections::{BTreeMap, HashMap};
+use std::net::SocketAddr;
+use std::time::Instant;
+use crate::memory::Usage;
+
+/// Maximum number of declined digests remembered by a node
+pub const MAX_DECLINED_DIGESTS: usize = 10_000;
+
+/// Maximum number of timed out content requests remembered by a node
+pub(crate) const MAX_TIMED_OUT_REQUESTS: usize = 10_000;
+
+/// Decides which advertised updates a node downloads, see [GossipConfig::set_fetch_policy](crate::GossipConfig::set_fetch_policy).
+///
+/// A declined digest is neither requested nor announced to [UpdateHandler::on_header](crate::UpdateHandler::on_header),
+/// and the policy is not consulted again for it until the [declined fetch TTL](crate::GossipConfig::set_declined_fetch_ttl)
+/// has elapsed. Declined updates are not marked expired; a node only advertises the updates whose content it holds,
+/// unless it [relays headers](crate::GossipConfig::set_relay_headers).
+pub trait FetchPolicy: Send + Sync {
+    /// Returns whether the content of an advertised update should be requested. Called from the header handling
+    /// thread, without holding any lock of the service.
+    ///
+    /// # Arguments
+    ///
+    /// * `digest` - The digest of the advertised update
+    /// * `from` - The address of the peer that advertised it
+    fn should_fetch(&self, digest: &str, from: &str) -> bool;
+}
+
+/// Digests declined by the fetch policy, with the time at which they were declined
+pub(crate) struct DeclinedDigests {
+    declined: HashMap<String, Instant>,
+}
+
+impl DeclinedDigests {
+    pub fn new() -> Self {
+        DeclinedDigests {
+            declined: HashMap::new(),
+        }
+    }
+
+    /// Returns whether the digest was declined less than `ttl` milliseconds ago
+    pub fn is_declined(&self, digest: &str, ttl: u64) -> bool {
+        self.declined.get(digest).is_some_and(|declined| declined.elapsed().as_millis() < ttl as u128)
+    }
+
+    /// Records a declined digest, forgetting the digests declined more than `ttl` milliseconds ago and,
+    /// if there are still too many of them, the oldest one
+    pub fn decline(&mut self, digest: String, ttl: u64) {
+        if self.declined.len() >= MAX_DECLINED_DIGESTS {
+            self.declined.retain(|_, declined| declined.elapsed().as_millis() < ttl as u128);
+        }
+        if self.declined.len() >= MAX_DECLINED_DIGESTS {
+            let oldest = self.declined.iter()
+                .min_by_key(|(_, declined)| **declined)
+                .map(|(digest, _)| digest.to_owned());
+            if let Some(oldest) = oldest {
+                self.declined.remove(&oldest);
+            }
+        }
+        self.declined.insert(digest, Instant::now());
+    }
+
+    pub fn clear(&mut self) {
+        self.declined.clear();
+    }
+
+    pub fn memory(&self) -> Usage {
+        Usage::of_strings(self.declined.keys())
+    }
+}
+
+/// Content requests that were not answered in time, with the peer to which each was sent, so that the late response
+/// of a slow peer is not taken for content it was never asked for
+pub(crate) struct TimedOutRequests {
+    /// Number of each recorded request, in the order in which they timed out
+    timed_out: HashMap<(String, SocketAddr), u64>,
+    /// Recorded requests by number, the oldest first
+    order: BTreeMap<u64, (String, SocketAddr)>,
+    recorded: u64,
+}
+
+impl TimedOutRequests {
+    pub fn new() -> Self {
+        TimedOutRequests {
+            timed_out: HashMap::new(),
+            order: BTreeMap::new(),
+            recorded: 0,
+        }
+    }
+
+    /// Records a request of the digest to the peer that timed out, forgetting the oldest one if there are too many
+    pub fn record(&mut self, digest: String, peer: SocketAddr) {
+        self.take(&digest, peer);
+        if self.timed_out.len() >= MAX_TIMED_OUT_REQUESTS {
+            if let Some((_, oldest)) = self.order.pop_first() {
+                self.timed_out.remove(&oldest);
+            }
+        }
+        self.recorded += 1;
+        self.order.insert(self.recorded, (digest.clone(), peer));
+        self.timed_out.insert((digest, peer), self.recorded);
+    }
+
+    /// Forgets a timed out request of the digest to the peer, and returns whether there was one
+    pub fn take(&mut self, digest: &str, peer: SocketAddr) -> bool {
+        match self.timed_out.remove(&(digest.to_owned(), peer)) {
+            Some(number) => {
+                self.order.remove(&number);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.timed_out.clear();
+        self.order.clear();
+    }
+
+    pub fn memory(&self) -> Usage {
+        Usage::of_strings(self.timed_out.keys().map(|(digest, _)| digest))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn oldest_timed_out_request_is_forgotten_first() {
+        let peer: SocketAddr = "127.0.0.1:9000".parse().unwrap();
+        let mut requests = TimedOutRequests::new();
+        for digest in 0..MAX_TIMED_OUT_REQUESTS {
+            requests.record(digest.to_string(), peer);
+        }
+        // recorded again, the first request is now the most recent
+        requests.record("0".to_owned(), peer);
+        requests.record("new".to_owned(), peer);
+
+        assert!(requests.take("0", peer));
+        assert!(!requests.take("1", peer));
+        assert!(requests.take("2", peer));
+        assert!(requests.take("new", peer));
+        assert_eq!(requests.timed_out.len(), requests.order.len());
+    }
+}
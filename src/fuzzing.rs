@@ -0,0 +1,64 @@
+//! Entry points of the parsers of received data for the targets of `fuzz/`, enabled by the `fuzzing` feature.
+//! Not part of the public API.
+
+use std::error::Error;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use crate::error_log::ErrorLog;
+use crate::message::Message;
+use crate::inbox::{inbox, InboxReceiver, Kind};
+use crate::message::gossip::{ContentMessage, HeaderMessage};
+use crate::message::sampling::PeerSamplingMessage;
+use crate::network::{Dispatcher, MessageQueues, Router};
+
+/// Capacity of the queues of the dispatcher, drained after each message
+const QUEUE_CAPACITY: usize = 8;
+
+pub fn parse_sampling_message(bytes: &[u8]) -> Result<(), Box<dyn Error>> {
+    PeerSamplingMessage::from_bytes(bytes).map(drop)
+}
+
+pub fn parse_header_message(bytes: &[u8]) -> Result<(), Box<dyn Error>> {
+    HeaderMessage::from_bytes(bytes).map(drop)
+}
+
+pub fn parse_content_message(bytes: &[u8]) -> Result<(), Box<dyn Error>> {
+    ContentMessage::from_bytes(bytes).map(drop)
+}
+
+/// Splits the responses read on the connection of a request, see [read_replies](crate::network::read_replies).
+/// Returns the number of responses.
+pub fn split_replies(bytes: &[u8]) -> Result<usize, Box<dyn Error>> {
+    crate::network::split_replies(bytes).map(|replies| replies.len())
+}
+
+/// The router of a listener, dispatching the messages of a node without cluster id to queues that no thread reads
+pub struct FuzzRouter {
+    router: Router,
+    receiver: InboxReceiver,
+}
+
+impl FuzzRouter {
+    pub fn new() -> Self {
+        let capacities = [Kind::Header, Kind::Content, Kind::Ping, Kind::Sampling].map(|kind| (kind, QUEUE_CAPACITY));
+        let (sender, receiver) = inbox(&capacities, &[]);
+        let dispatcher = Dispatcher::new(Arc::new(Mutex::new(None)), sender, Arc::new(ErrorLog::new()), Arc::new(MessageQueues::default()), Duration::ZERO);
+        FuzzRouter {
+            router: Router::single(None, dispatcher),
+            receiver,
+        }
+    }
+
+    /// Dispatches a message as received on the wire, and drops the queued messages
+    pub fn route(&self, bytes: &[u8]) -> Result<(), Box<dyn Error>> {
+        let result = self.router.route(bytes, None, None);
+        while self.receiver.try_recv().is_some() {}
+        result
+    }
+}
+
+impl Default for FuzzRouter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
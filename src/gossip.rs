@@ -1,38 +1,611 @@
-use std::thread::JoinHandle;
-use std::sync::atomic::AtomicBool;
-use std::sync::{Arc, Mutex, RwLock};
-use std::net::SocketAddr;
-use std::sync::mpsc::{Sender, Receiver};
-use std::collections::HashMap;
+use std::thread::{JoinHandle, ThreadId};
+use std::sync::atomic::{AtomicBool, AtomicU64};
+use std::sync::{Arc, Condvar, Mutex, PoisonError, RwLock};
+use std::net::{IpAddr, SocketAddr, TcpListener};
+use std::sync::mpsc::{Sender, SyncSender, Receiver, RecvTimeoutError, TrySendError};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::collections::hash_map::Entry;
 use std::error::Error;
-use rand::Rng;
-use crate::config::GossipConfig;
+use std::time::{Duration, Instant, SystemTime};
+use rand::seq::SliceRandom;
+use crate::config::{GossipConfig, ConfigWarning, ExpirationStart, SyncPolicy};
 use crate::PeerSamplingConfig;
 use crate::sampling::PeerSamplingService;
-use crate::update::{Update, UpdateHandler, UpdateDecorator};
-use crate::message::gossip::{HeaderMessage, ContentMessage};
-use crate::message::{NoopMessage, MessageType};
+use crate::fetch::{DeclinedDigests, TimedOutRequests};
+use crate::relay::RelayedDigests;
+use crate::view_export::ViewSummary;
+use crate::update_export::StoredUpdate;
+use crate::view_stats::SamplingStats;
+use crate::usefulness::{PeerInfo, PeerUsefulness};
+use crate::sampler::{MessageHandler, PeerSampler, SamplerContext, SamplerRound, SamplerTasks, SamplingState, StaticSampler, ViewPublisher};
+use crate::update::{BatchItem, OriginSequence, ShutdownReason, SubmitOutcome, Update, UpdateCounters, UpdateHandler, UpdateDecorator, UpdateState};
+use crate::message::gossip::{HeaderMessage, ContentMessage, PingMessage, PongMessage};
+use crate::message::{NoopMessage, MessageType, MAX_DIGEST_LENGTH, MAX_MESSAGE_DIGESTS};
 use crate::peer::Peer;
 use crate::message::sampling::PeerSamplingMessage;
+use crate::stats::GossipStats;
+use crate::observer::{ObserverSlot, RoundObserver};
+use crate::coverage::coverage_probability;
+use crate::command::{round_delay, Command, Round, Timer, Timers};
+use crate::inbox::{inbox, Inbound, InboxReceiver, InboxSender, Kind};
+use crate::warning::{RateLimitedWarning, WARNING_INTERVAL};
+use crate::bootstrap::BootstrapSource;
+use crate::error::{AlreadyStarted, BatchError, NotStarted};
+use crate::health::{HealthReport, JoinState};
+use crate::listener::SharedListener;
+use crate::network::{ConnectionLimits, Dispatcher, MessageQueues, ReplyReaders, Router};
+use crate::quarantine::{DigestMismatches, Quarantine};
+use crate::snapshot::Snapshot;
+use crate::error_log::{ErrorCategory, ErrorLog, ErrorRecord};
+use crate::traffic::{PeerTraffic, TrafficLog};
+use crate::wire_trace::WireTracer;
+use crate::ordering::{OrderedDelivery, Released};
+use crate::memory::{MemoryReport, Usage, UsageCounter};
+use crate::keepalive::Keepalive;
+use crate::round::{cluster_size, probe_window, report_removed, GossipRound};
+use crate::logging::NodeScope;
+use crate::logging::targets::{GOSSIP, MONITOR, NETWORK, UPDATES};
 
 /// The gossip service
 pub struct GossipService<T> {
     /// Socket address of the node
     address: SocketAddr,
-    /// Peer sampling service
-    peer_sampling_service: Arc<Mutex<PeerSamplingService>>,
+    /// Socket address to which the listener is bound
+    bind_address: SocketAddr,
+    /// Address the listener was bound to while it runs, with the port chosen by the system when binding to port 0
+    listener_address: Mutex<Option<SocketAddr>>,
+    /// Peer sampling service maintaining the view
+    peer_sampling_service: Arc<Mutex<Box<dyn PeerSampler>>>,
     /// Configuration for gossip
-    gossip_config: Arc<GossipConfig>,
-    /// Shutdown requested flag
-    shutdown: Arc<AtomicBool>,
-    /// Thread handles
-    activities: Vec<JoinHandle<()>>,
+    gossip_config: Arc<RwLock<GossipConfig>>,
+    /// Lifecycle of the service
+    state: Mutex<ServiceState>,
+    /// Notified when the service is shut down
+    stopped: Condvar,
+    /// Commands of the activities of the timer thread, while it runs
+    commands: Mutex<Option<Sender<(Timer, Command)>>>,
+    /// Shutdown requested flag for the listener
+    listener_shutdown: Arc<AtomicBool>,
+    /// Set when an update is inserted or a new digest is learned, ends the idle backoff of the gossip rounds
+    activity: Arc<AtomicBool>,
+    /// Set while the node is isolated from the peers of its view
+    isolated: Arc<AtomicBool>,
+    /// Handle of the timer thread, running the gossip rounds, the rounds of the sampler and the keepalive pings
+    timers: Mutex<Option<JoinHandle<()>>>,
+    /// Listener shared with services of other clusters, used instead of a listener of its own
+    shared_listener: Option<Arc<SharedListener>>,
+    /// Cluster for which the service is registered on the shared listener
+    shared_cluster: Mutex<Option<u8>>,
+    /// Handle of the listener thread
+    listener: Mutex<Option<JoinHandle<()>>>,
+    /// Handle of the thread handling received messages and delivering updates
+    dispatcher: Mutex<Option<JoinHandle<()>>>,
     /// Active and expired updates
     updates: Arc<RwLock<UpdateDecorator>>,
-    /// Application callback for receiving new updates
-    update_handler: Arc<Mutex<Option<Box<T>>>>,
+    /// Updates submitted before the service was started, inserted when it starts
+    queued: Mutex<Vec<Update>>,
+    /// Number of the last update submitted with ordered delivery
+    submitted_numbers: AtomicU64,
+    /// Peers imported before the service was started, used instead of the initialization closure
+    imported_view: Mutex<Option<Vec<Peer>>>,
+    /// Updates imported before the service was started, inserted when it starts
+    imported_updates: Mutex<Vec<StoredUpdate>>,
+    /// Handles the peer sampling messages on the message handling thread, once the sampler is started; the
+    /// messages received before wait in their queue
+    sampling_handler: Arc<Mutex<Option<MessageHandler>>>,
+    /// Peers of the view, published by the peer sampling service
+    published_peers: Arc<Snapshot<Peer>>,
+    /// Digests of the active updates, published after each change of the updates
+    published_digests: Arc<Snapshot<String>>,
+    /// Application callback for receiving new updates. When both are locked, [updates](Self::updates) are locked first.
+    update_handler: Arc<Mutex<Option<RegisteredHandler<T>>>>,
+    /// Generation of the last registered handler
+    handler_generation: AtomicU64,
+    /// Queues of the message handling thread, which also delivers the updates to the application, while it runs
+    inbox: Mutex<Option<InboxSender>>,
+    /// Peers to which a pull probe was sent and whose header response is expected
+    header_probes: Arc<Mutex<HashMap<SocketAddr, Instant>>>,
+    /// Peers with which a header exchange was initiated and has not completed yet
+    in_flight: Arc<Mutex<HashMap<SocketAddr, Instant>>>,
+    /// Time at which new digests were first advertised to the node
+    digests_seen: Arc<Mutex<DigestsSeen>>,
+    /// Content requests sent for new digests and not answered yet
+    content_requests: Arc<Mutex<HashMap<String, ContentRequest>>>,
+    /// Content requests that were not answered in time, whose late response is still accepted
+    timed_out: Arc<Mutex<TimedOutRequests>>,
+    /// Digests recently declined by the fetch policy
+    declined: Arc<Mutex<DeclinedDigests>>,
+    /// Digests whose received content did not match, with the peers that sent it
+    mismatches: Arc<Mutex<DigestMismatches>>,
+    /// Digests advertised by peers whose content is not stored, relayed to other peers
+    relayed: Arc<Mutex<RelayedDigests>>,
+    /// Recent exchanges with each peer and new digests learned from it
+    usefulness: Arc<Mutex<PeerUsefulness>>,
+    /// Activity counters
+    stats: Arc<Mutex<GossipStats>>,
+    /// Recent errors of the protocol threads, shared with the peer sampling service
+    errors: Arc<ErrorLog>,
+    /// Traffic with each peer, shared with the peer sampling service and the listener
+    traffic: Arc<TrafficLog>,
+    /// IP of the node as last observed by a peer that answered a pull probe
+    observed_ip: Arc<Mutex<Option<IpAddr>>>,
+    /// Peers sending invalid data, shared with the peer sampling service
+    quarantine: Arc<Mutex<Quarantine>>,
+    /// Observer of the protocol rounds
+    observer: ObserverSlot,
+    /// Time at which a message was last received
+    last_received: Arc<Mutex<Option<Instant>>>,
+    /// State of the queues between the listener and the message handling thread
+    queues: Arc<MessageQueues>,
+    /// Inconsistencies detected between the configurations at start or on change
+    config_warnings: Mutex<Vec<ConfigWarning>>,
+    /// Progress of the initial sync, shared with the timer thread
+    initial_sync: Arc<InitialSync>,
+    /// Updates queued for delivery and not taken from the queue yet
+    pending_deliveries: Arc<UsageCounter>,
+    /// Failed deliveries waiting to be attempted again, recorded by the message handling thread
+    retried_deliveries: Arc<UsageCounter>,
+    /// Number of times the node was started, see [GossipConfig::set_epoch_file]
+    epoch: AtomicU64,
+    /// Time at which the node was started
+    started: Mutex<Option<(Instant, SystemTime)>>,
 }
 
+/// Lifecycle of a [GossipService]
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ServiceState {
+    /// Not started yet
+    Created,
+    /// Listening, without having joined the network
+    Listening,
+    /// Joined the network and not shut down
+    Running,
+    /// Shutting down: the threads are being stopped, without the state locked
+    Stopping,
+    /// Shut down
+    Stopped,
+}
+
+/// The application handler with the generation of its registration
+struct RegisteredHandler<T> {
+    generation: u64,
+    /// Shared with the threads calling the handler, which call it without holding the lock of the registered handler
+    handler: Arc<HandlerCell<T>>,
+}
+
+/// An application handler called by several threads, one at a time
+struct HandlerCell<T> {
+    handler: Mutex<Box<T>>,
+    /// Thread calling the handler, if any, with the shutdown notification deferred until its call returns
+    caller: Mutex<(Option<ThreadId>, Option<ShutdownReason>)>,
+}
+
+impl<T: UpdateHandler> HandlerCell<T> {
+    fn new(handler: Box<T>) -> Self {
+        HandlerCell {
+            handler: Mutex::new(handler),
+            caller: Mutex::new((None, None)),
+        }
+    }
+
+    /// Calls the handler once the calls of the other threads returned. A handler that panicked is still called.
+    fn call<R>(&self, call: impl FnOnce(&T) -> R) -> R {
+        let handler = self.handler.lock().unwrap_or_else(PoisonError::into_inner);
+        self.caller.lock().unwrap_or_else(PoisonError::into_inner).0 = Some(std::thread::current().id());
+        let result = call(&handler);
+        let deferred = {
+            let mut caller = self.caller.lock().unwrap_or_else(PoisonError::into_inner);
+            caller.0 = None;
+            caller.1.take()
+        };
+        if let Some(reason) = deferred {
+            handler.on_shutdown(reason);
+        }
+        result
+    }
+
+    /// Calls [UpdateHandler::on_shutdown] once the call in progress, if any, returns. When the handler is dropped
+    /// from one of its own calls, such as a callback replacing it, it is notified when that call returns.
+    fn shutdown(&self, reason: ShutdownReason) {
+        {
+            let mut caller = self.caller.lock().unwrap_or_else(PoisonError::into_inner);
+            if caller.0 == Some(std::thread::current().id()) {
+                caller.1 = Some(reason);
+                return;
+            }
+        }
+        self.handler.lock().unwrap_or_else(PoisonError::into_inner).on_shutdown(reason);
+    }
+}
+
+/// An update to be delivered to the handler of the given generation
+pub(crate) struct Delivery {
+    generation: u64,
+    update: Update,
+}
+
+impl std::fmt::Debug for Delivery {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Delivery")
+            .field("generation", &self.generation)
+            .field("digest", &self.update.digest())
+            .finish()
+    }
+}
+
+impl Delivery {
+    /// Returns the number of bytes owned by the delivery
+    fn size(&self) -> usize {
+        self.update.digest().len() + self.update.content().len()
+    }
+}
+
+/// Deliveries that failed with [acknowledged delivery](GossipConfig::set_acknowledged_delivery), attempted
+/// again after a delay doubled at each failure
+struct DeliveryRetries {
+    /// Delay before the first attempt after a failure
+    retry_delay: Duration,
+    /// Failed deliveries, with the time of their next attempt
+    pending: Vec<(Instant, Delivery)>,
+    /// Number of failed deliveries of each digest
+    failures: HashMap<String, u32>,
+}
+
+impl DeliveryRetries {
+    fn new(retry_delay: Duration) -> Self {
+        DeliveryRetries {
+            retry_delay,
+            pending: Vec::new(),
+            failures: HashMap::new(),
+        }
+    }
+
+    /// Schedules a failed delivery, and returns the delay after which it is attempted again
+    fn schedule(&mut self, delivery: Delivery) -> Duration {
+        let failures = self.failures.entry(delivery.update.digest().to_owned()).or_insert(0);
+        *failures += 1;
+        let delay = self.retry_delay.saturating_mul(1 << (*failures - 1).min(16)).min(MAX_DELIVERY_RETRY_DELAY);
+        self.pending.push((Instant::now() + delay, delivery));
+        delay
+    }
+
+    /// Forgets the failures of a digest that was acknowledged or expired
+    fn forget(&mut self, digest: &str) {
+        self.failures.remove(digest);
+    }
+
+    /// Returns the sizes of the deliveries waiting for an attempt and of the recorded failures
+    fn memory(&self) -> Usage {
+        let pending = Usage::of_sizes(self.pending.iter().map(|(_, delivery)| delivery.size()));
+        let failures = Usage::of_strings(self.failures.keys());
+        Usage {
+            count: pending.count,
+            bytes: pending.bytes + failures.bytes,
+        }
+    }
+
+    /// Returns the time of the next attempt, if any delivery failed
+    fn next_due(&self) -> Option<Instant> {
+        self.pending.iter().map(|(due, _)| *due).min()
+    }
+
+    /// Removes and returns the deliveries to attempt again
+    fn take_due(&mut self, now: Instant) -> Vec<Delivery> {
+        let (due, pending) = std::mem::take(&mut self.pending).into_iter().partition(|(due, _)| *due <= now);
+        self.pending = pending;
+        due.into_iter().map(|(_, delivery)| delivery).collect()
+    }
+}
+
+/// Delivers the updates to the application handler from the message handling thread, in the order of their origin
+/// with [ordered delivery](GossipConfig::set_ordered_delivery), and attempts the failed deliveries again with
+/// [acknowledged delivery](GossipConfig::set_acknowledged_delivery)
+struct UpdateDelivery<T> {
+    update_handler: Arc<Mutex<Option<RegisteredHandler<T>>>>,
+    updates: Arc<RwLock<UpdateDecorator>>,
+    stats: Arc<Mutex<GossipStats>>,
+    errors: Arc<ErrorLog>,
+    /// Updates queued for delivery and not taken from the queue yet
+    pending_deliveries: Arc<UsageCounter>,
+    /// Failed deliveries waiting to be attempted again
+    retried_deliveries: Arc<UsageCounter>,
+    /// Updates held until the missing updates of their origin arrive, with ordered delivery
+    ordering: Option<OrderedDelivery<Delivery>>,
+    acknowledged: bool,
+    /// Origin of the updates submitted by the node, which are not delivered to it
+    own_origin: Option<String>,
+    retries: DeliveryRetries,
+}
+
+impl<T: UpdateHandler> UpdateDelivery<T> {
+    /// Delivers an update taken from the queue, unless it waits for the missing updates of its origin
+    fn push(&mut self, delivery: Delivery) {
+        self.pending_deliveries.remove(delivery.size());
+        let released = match (self.ordering.as_mut(), delivery.update.origin_sequence().cloned()) {
+            (Some(ordering), Some(sequence)) => ordering.push(&sequence.origin, sequence.number, delivery),
+            _ => vec![Released::Item(delivery)],
+        };
+        self.deliver_all(released);
+    }
+
+    /// Delivers the held updates that stopped waiting for the missing ones, and attempts the failed deliveries
+    /// that are due again
+    fn expire(&mut self, now: Instant) {
+        if self.next_deadline().is_none_or(|deadline| deadline > now) {
+            return;
+        }
+        let mut released = self.ordering.as_mut().map(|ordering| ordering.expire(now)).unwrap_or_default();
+        // retried updates that expired in the meantime are not delivered again
+        for delivery in self.retries.take_due(now) {
+            if self.updates.read().unwrap().is_active(delivery.update.digest()) {
+                self.stats.lock().unwrap().delivery_retries += 1;
+                released.push(Released::Item(delivery));
+            }
+            else {
+                self.retries.forget(delivery.update.digest());
+            }
+        }
+        self.deliver_all(released);
+    }
+
+    /// Returns the time at which the first held update stops waiting for the missing ones, or a failed delivery
+    /// is attempted again
+    fn next_deadline(&self) -> Option<Instant> {
+        self.ordering.as_ref().and_then(OrderedDelivery::next_deadline).into_iter()
+            .chain(self.retries.next_due())
+            .min()
+    }
+
+    /// Delivers the held updates, so that they are not lost at shutdown
+    fn drain(&mut self) {
+        let released = self.ordering.as_mut().map(OrderedDelivery::drain).unwrap_or_default();
+        self.deliver_all(released);
+    }
+
+    fn deliver_all(&mut self, released: Vec<Released<Delivery>>) {
+        for released in released {
+            self.deliver(released);
+        }
+        self.retried_deliveries.set(self.retries.memory());
+    }
+
+    fn deliver(&mut self, released: Released<Delivery>) {
+        // the handler is called without the lock of the registered handler, which it may replace
+        let registered = self.update_handler.lock().unwrap().as_ref()
+            .map(|registered| (registered.generation, Arc::clone(&registered.handler)));
+        match (released, registered) {
+            (Released::Item(delivery), Some((generation, handler))) if generation == delivery.generation => {
+                // revocations are consumed by the service, after the ordering so that they leave no gap
+                if delivery.update.is_revocation() {
+                    if self.acknowledged {
+                        self.updates.write().unwrap().mark_acknowledged(delivery.update.digest(), delivery.generation);
+                    }
+                    return;
+                }
+                // the updates submitted by the node itself come back after a restart
+                if self.own_origin.is_some() && delivery.update.origin() == self.own_origin.as_deref() {
+                    node_log!(Debug, UPDATES, "Not delivering update {} submitted by the node", delivery.update.digest());
+                    self.stats.lock().unwrap().suppressed_own_updates += 1;
+                    if self.acknowledged {
+                        self.updates.write().unwrap().mark_acknowledged(delivery.update.digest(), delivery.generation);
+                    }
+                    return;
+                }
+                if !self.acknowledged {
+                    handler.call(|handler| handler.on_update(delivery.update));
+                    return;
+                }
+                let digest = delivery.update.digest().to_owned();
+                if self.updates.read().unwrap().is_acknowledged(&digest, delivery.generation) {
+                    self.retries.forget(&digest);
+                    return;
+                }
+                match handler.call(|handler| handler.try_on_update(delivery.update.clone())) {
+                    Ok(()) => {
+                        self.retries.forget(&digest);
+                        self.updates.write().unwrap().mark_acknowledged(&digest, delivery.generation);
+                    },
+                    Err(e) => {
+                        let delay = self.retries.schedule(delivery);
+                        node_log!(Warn, UPDATES, "Delivery of update {} failed, retrying in {:?}: {}", digest, delay, e);
+                        self.errors.record(ErrorCategory::Handler, None, format!("delivery of update {} failed: {}", digest, e));
+                    },
+                }
+            },
+            (Released::Item(delivery), _) => {
+                node_log!(Debug, UPDATES, "Dropping update {} for replaced handler", delivery.update.digest());
+                self.retries.forget(delivery.update.digest());
+            },
+            (Released::Gap { origin, skipped }, registered) => {
+                node_log!(Warn, UPDATES, "Updates {:?} of {} did not arrive in time, delivering the next ones", skipped, origin);
+                if let Some((_, handler)) = registered {
+                    handler.call(|handler| handler.on_sequence_gap(&origin, skipped));
+                }
+            }
+        }
+    }
+}
+
+/// A content request waiting for its response
+pub(crate) struct ContentRequest {
+    /// Peer to which the request was sent
+    pub(crate) peer: SocketAddr,
+    /// Time at which the request was sent
+    pub(crate) sent: Instant,
+    /// Another peer that advertised the digest, to which the request is sent if the first does not deliver
+    pub(crate) fallback: Option<SocketAddr>,
+}
+
+/// Progress of the initial sync of a started service, see [SyncPolicy]
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum SyncState {
+    /// The updates of the bootstrap peers are being fetched
+    Pending,
+    /// A round brought no new digest, or no sync was required
+    Complete,
+    /// The timeout elapsed before a round brought no new digest
+    TimedOut,
+    /// The service was shut down during the sync
+    Aborted,
+}
+
+/// State of the initial sync, with the condition notified when it ends
+struct InitialSync {
+    state: Mutex<SyncState>,
+    ended: Condvar,
+}
+
+impl InitialSync {
+    fn new() -> Self {
+        InitialSync {
+            state: Mutex::new(SyncState::Pending),
+            ended: Condvar::new(),
+        }
+    }
+
+    fn state(&self) -> SyncState {
+        *self.state.lock().unwrap()
+    }
+
+    fn set(&self, state: SyncState) {
+        *self.state.lock().unwrap() = state;
+        self.ended.notify_all();
+    }
+
+    /// Blocks until the sync is no longer pending and returns how it ended
+    fn wait(&self) -> SyncState {
+        let state = self.state.lock().unwrap();
+        *self.ended.wait_while(state, |state| *state == SyncState::Pending).unwrap()
+    }
+}
+
+/// Rounds of the initial sync: the peers of the view at start are probed for their digests at each round, until a
+/// round brings no new digest or the sync times out
+struct SyncRounds {
+    peers: Vec<SocketAddr>,
+    /// Time at which the sync times out
+    deadline: Instant,
+    /// Peers probed by the last round, with the number of digests learned before it
+    probed: Option<(Vec<SocketAddr>, u64)>,
+}
+
+impl SyncRounds {
+    fn new(peers: Vec<SocketAddr>, timeout: u64) -> Self {
+        SyncRounds {
+            peers,
+            deadline: Instant::now() + Duration::from_millis(timeout),
+            probed: None,
+        }
+    }
+
+    /// Probes the peers for a new round and returns the delay until the next one, or returns how the sync ended
+    fn step(&mut self, round: &GossipRound<TrafficLog>, gossip_config: &GossipConfig) -> Result<Duration, SyncState> {
+        if self.peers.is_empty() {
+            return Err(SyncState::Complete);
+        }
+        if let Some((probed, learned)) = self.probed.take() {
+            // the sync is complete once a peer answered without new digest and all contents arrived
+            let answered = {
+                let header_probes = round.header_probes.lock().unwrap();
+                probed.iter().any(|peer_address| !header_probes.contains_key(peer_address))
+            };
+            let fetched = round.content_requests.lock().unwrap().is_empty();
+            if answered && fetched && round.stats.lock().unwrap().digests_learned == learned {
+                return Err(SyncState::Complete);
+            }
+            if Instant::now() >= self.deadline {
+                return Err(SyncState::TimedOut);
+            }
+        }
+        let learned = round.stats.lock().unwrap().digests_learned;
+        let mut probed = Vec::new();
+        for peer_address in &self.peers {
+            let message = HeaderMessage::new_request(round.node_address.clone(), true)
+                .with_cluster(gossip_config.cluster_name().map(str::to_owned));
+            round.header_probes.lock().unwrap().insert(*peer_address, Instant::now());
+            match round.sender.send(peer_address, &message, gossip_config.cluster_id(), gossip_config.network()) {
+                Ok(_) => probed.push(*peer_address),
+                Err(e) => {
+                    node_log!(Warn, GOSSIP, "Could not probe {} for initial sync: {:?}", peer_address, e);
+                    round.errors.record(ErrorCategory::Send, Some(&peer_address.to_string()), format!("header request: {}", e));
+                    round.header_probes.lock().unwrap().remove(peer_address);
+                }
+            }
+        }
+        self.probed = Some((probed, learned));
+        let round_end = (Instant::now() + Duration::from_millis(gossip_config.gossip_period())).min(self.deadline);
+        Ok(round_end.saturating_duration_since(Instant::now()))
+    }
+}
+
+/// Resolves the bootstrap source kept by the sampler again and adds its peers to the view. The source is resolved
+/// without the sampler locked, as it may wait for the network.
+fn bootstrap_again(peer_sampling: &Mutex<Box<dyn PeerSampler>>) -> Result<(), Box<dyn Error>> {
+    let source = peer_sampling.lock().unwrap().bootstrap_source();
+    let Some(source) = source else {
+        return Ok(());
+    };
+    let peers = source.lock().unwrap().resolve()?.unwrap_or_default();
+    node_log!(Info, GOSSIP, "Bootstrapping again from {} peers", peers.len());
+    peer_sampling.lock().unwrap().merge_peers(peers);
+    Ok(())
+}
+
+/// Time (milliseconds) after which an advertised digest whose content never arrived is forgotten
+const DIGEST_SEEN_RETENTION: u128 = 60_000;
+
+/// Maximum number of advertised digests whose content is awaited
+const MAX_DIGESTS_SEEN: usize = 10_000;
+
+/// Time at which new digests were first advertised, forgotten in the order they were advertised
+#[derive(Default)]
+struct DigestsSeen {
+    seen: HashMap<String, Instant>,
+    order: VecDeque<(Instant, String)>,
+}
+
+impl DigestsSeen {
+    /// Records a digest advertised now and returns whether it was not advertised before
+    fn insert(&mut self, digest: &str) -> bool {
+        while let Some((seen, _)) = self.order.front() {
+            if seen.elapsed().as_millis() < DIGEST_SEEN_RETENTION && self.order.len() < MAX_DIGESTS_SEEN {
+                break;
+            }
+            let (seen, oldest) = self.order.pop_front().unwrap();
+            // the digest may have arrived, and been advertised again, since
+            if self.seen.get(&oldest) == Some(&seen) {
+                self.seen.remove(&oldest);
+            }
+        }
+        match self.seen.entry(digest.to_owned()) {
+            Entry::Vacant(entry) => {
+                let now = Instant::now();
+                entry.insert(now);
+                self.order.push_back((now, digest.to_owned()));
+                true
+            }
+            Entry::Occupied(_) => false,
+        }
+    }
+
+    /// Forgets a digest whose content arrived and returns when it was first advertised
+    fn remove(&mut self, digest: &str) -> Option<Instant> {
+        self.seen.remove(digest)
+    }
+
+    fn digests(&self) -> impl Iterator<Item = &String> {
+        self.seen.keys()
+    }
+
+    fn clear(&mut self) {
+        self.seen.clear();
+        self.order.clear();
+    }
+}
+
+/// Longest time after which a failed delivery is attempted again
+const MAX_DELIVERY_RETRY_DELAY: Duration = Duration::from_secs(60);
+
 impl<T> GossipService<T>
 where T: UpdateHandler + 'static + Send
 {
@@ -40,311 +613,3154 @@ where T: UpdateHandler + 'static + Send
     ///
     /// # Arguments
     ///
-    /// * `address` - Socket address of the node
-    /// * `peer_sampling_config` - Configuration for peer sampling, see [PeerSamplingConfig]
-    /// * `gossip_config` - Configuration for gossiping, see [GossipConfig]
-    pub fn new(address: SocketAddr, peer_sampling_config: PeerSamplingConfig, gossip_config: GossipConfig) -> GossipService<T> {
-        GossipService{
-            address,
-            peer_sampling_service: Arc::new(Mutex::new(PeerSamplingService::new(address, peer_sampling_config))),
-            updates: Arc::new(RwLock::new(UpdateDecorator::new(gossip_config.update_expiration().clone()))),
-            gossip_config: Arc::new(gossip_config),
-            shutdown: Arc::new(AtomicBool::new(false)),
-            activities: Vec::new(),
-            update_handler: Arc::new(Mutex::new(None)),
+    /// * `address` - Socket address of the node
+    /// * `peer_sampling_config` - Configuration for peer sampling, see [PeerSamplingConfig]
+    /// * `gossip_config` - Configuration for gossiping, see [GossipConfig]
+    pub fn new(address: SocketAddr, peer_sampling_config: PeerSamplingConfig, gossip_config: GossipConfig) -> GossipService<T> {
+        let observer: ObserverSlot = Arc::new(RwLock::new(None));
+        let stats = Arc::new(Mutex::new(GossipStats::default()));
+        let quarantine = Arc::new(Mutex::new(Quarantine::new(gossip_config.quarantine_threshold(), gossip_config.quarantine_cooldown())));
+        let errors = Arc::new(ErrorLog::new());
+        let traffic = Arc::new(TrafficLog::new());
+        let sampler: Box<dyn PeerSampler> = if peer_sampling_config.static_peers().is_some() {
+            Box::new(StaticSampler::from_config(peer_sampling_config))
+        }
+        else {
+            let mut peer_sampling_service = PeerSamplingService::new(address, peer_sampling_config, gossip_config.cluster_id(), gossip_config.cluster_name().map(str::to_owned), Arc::clone(&observer), Arc::clone(&stats), Arc::clone(&quarantine));
+            peer_sampling_service.set_network(gossip_config.network().clone());
+            peer_sampling_service.set_error_log(Arc::clone(&errors));
+            peer_sampling_service.set_traffic(Arc::clone(&traffic));
+            Box::new(peer_sampling_service)
+        };
+        let mut service = Self::assemble(address, sampler, gossip_config, observer, stats, quarantine, errors);
+        service.traffic = traffic;
+        service
+    }
+
+    /// Creates a gossiping service whose view is maintained by the given sampler instead of the
+    /// [Gossip-based Peer Sampling](https://infoscience.epfl.ch/record/109297/files/all.pdf) service, see [PeerSampler]
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - Socket address of the node
+    /// * `sampler` - The view maintenance algorithm
+    /// * `gossip_config` - Configuration for gossiping, see [GossipConfig]
+    pub fn with_sampler(address: SocketAddr, sampler: Box<dyn PeerSampler>, gossip_config: GossipConfig) -> GossipService<T> {
+        let quarantine = Arc::new(Mutex::new(Quarantine::new(gossip_config.quarantine_threshold(), gossip_config.quarantine_cooldown())));
+        Self::assemble(address, sampler, gossip_config, Arc::new(RwLock::new(None)), Arc::new(Mutex::new(GossipStats::default())), quarantine, Arc::new(ErrorLog::new()))
+    }
+
+    fn assemble(address: SocketAddr, sampler: Box<dyn PeerSampler>, gossip_config: GossipConfig, observer: ObserverSlot, stats: Arc<Mutex<GossipStats>>, quarantine: Arc<Mutex<Quarantine>>, errors: Arc<ErrorLog>) -> GossipService<T> {
+        let mut updates = UpdateDecorator::new(gossip_config.update_expiration().clone(), Arc::clone(gossip_config.digester()));
+        updates.set_expiration_start(gossip_config.expiration_start());
+        updates.set_tombstones(gossip_config.max_tombstones(), Duration::from_millis(gossip_config.tombstone_window()));
+        GossipService{
+            address,
+            bind_address: address,
+            listener_address: Mutex::new(None),
+            published_peers: Arc::new(Snapshot::new()),
+            published_digests: updates.published_digests(),
+            peer_sampling_service: Arc::new(Mutex::new(sampler)),
+            updates: Arc::new(RwLock::new(updates)),
+            queued: Mutex::new(Vec::new()),
+            submitted_numbers: AtomicU64::new(0),
+            imported_view: Mutex::new(None),
+            imported_updates: Mutex::new(Vec::new()),
+            sampling_handler: Arc::new(Mutex::new(None)),
+            gossip_config: Arc::new(RwLock::new(gossip_config)),
+            state: Mutex::new(ServiceState::Created),
+            stopped: Condvar::new(),
+            commands: Mutex::new(None),
+            listener_shutdown: Arc::new(AtomicBool::new(false)),
+            activity: Arc::new(AtomicBool::new(false)),
+            isolated: Arc::new(AtomicBool::new(false)),
+            timers: Mutex::new(None),
+            shared_listener: None,
+            shared_cluster: Mutex::new(None),
+            listener: Mutex::new(None),
+            dispatcher: Mutex::new(None),
+            update_handler: Arc::new(Mutex::new(None)),
+            handler_generation: AtomicU64::new(0),
+            inbox: Mutex::new(None),
+            header_probes: Arc::new(Mutex::new(HashMap::new())),
+            in_flight: Arc::new(Mutex::new(HashMap::new())),
+            digests_seen: Arc::new(Mutex::new(DigestsSeen::default())),
+            content_requests: Arc::new(Mutex::new(HashMap::new())),
+            timed_out: Arc::new(Mutex::new(TimedOutRequests::new())),
+            declined: Arc::new(Mutex::new(DeclinedDigests::new())),
+            mismatches: Arc::new(Mutex::new(DigestMismatches::new())),
+            relayed: Arc::new(Mutex::new(RelayedDigests::new())),
+            usefulness: Arc::new(Mutex::new(PeerUsefulness::new())),
+            stats,
+            errors,
+            traffic: Arc::new(TrafficLog::new()),
+            observed_ip: Arc::new(Mutex::new(None)),
+            quarantine,
+            observer,
+            last_received: Arc::new(Mutex::new(None)),
+            queues: Arc::new(MessageQueues::default()),
+            config_warnings: Mutex::new(Vec::new()),
+            initial_sync: Arc::new(InitialSync::new()),
+            pending_deliveries: Arc::new(UsageCounter::default()),
+            retried_deliveries: Arc::new(UsageCounter::default()),
+            epoch: AtomicU64::new(0),
+            started: Mutex::new(None),
+        }
+    }
+
+    /// Creates a gossiping service receiving its messages through a listener shared with services of other clusters.
+    /// The address of the node is the address of the shared listener, and the gossip configuration must set a
+    /// [cluster id](GossipConfig::set_cluster_id) that no other service of the listener uses.
+    ///
+    /// # Arguments
+    ///
+    /// * `listener` - The shared listener
+    /// * `peer_sampling_config` - Configuration for peer sampling, see [PeerSamplingConfig]
+    /// * `gossip_config` - Configuration for gossiping, see [GossipConfig]
+    pub fn new_shared(listener: Arc<SharedListener>, peer_sampling_config: PeerSamplingConfig, gossip_config: GossipConfig) -> GossipService<T> {
+        let mut service = Self::new(*listener.address(), peer_sampling_config, gossip_config);
+        service.shared_listener = Some(listener);
+        service
+    }
+
+    /// Create a gossip service with default configurations
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - Socket address of the node
+    pub fn new_with_defaults(address: SocketAddr) -> Self {
+        Self::new(address, PeerSamplingConfig::default(), GossipConfig::default())
+    }
+
+    /// Creates a gossiping service from a node configuration.
+    /// The peers to contact at start are provided by [NodeConfig::bootstrap_peers](crate::NodeConfig::bootstrap_peers).
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The node configuration
+    #[cfg(feature = "config-serde")]
+    pub fn from_config(config: crate::NodeConfig) -> Self {
+        let (bind_address, public_address, peer_sampling_config, gossip_config) = config.into_parts();
+        let mut service = Self::new(public_address, peer_sampling_config, gossip_config);
+        service.bind_address = bind_address;
+        service
+    }
+
+    /// Returns the node address
+    pub fn address(&self) -> &SocketAddr {
+        &self.address
+    }
+
+    /// Binds the listener to a different address from the node address, which remains the identity of the node in
+    /// the messages it sends and in the views of its peers. Behind a router forwarding an external port to another
+    /// internal port, the node address is the external one and the bind address the internal one.
+    /// Takes effect at the next [start](Self::start).
+    ///
+    /// # Arguments
+    ///
+    /// * `bind_address` - Address to which the listener is bound; port 0 lets the system choose a port
+    pub fn set_bind_address(&mut self, bind_address: SocketAddr) {
+        self.bind_address = bind_address;
+    }
+
+    /// Returns the address to which the listener is bound, the node address unless set with
+    /// [set_bind_address](Self::set_bind_address)
+    pub fn bind_address(&self) -> &SocketAddr {
+        &self.bind_address
+    }
+
+    /// Returns the address on which the listener accepts connections while the service runs, with the port chosen
+    /// by the system if the bind address has port 0. `None` if the service is not running or shares a listener.
+    pub fn listener_address(&self) -> Option<SocketAddr> {
+        *self.listener_address.lock().unwrap()
+    }
+
+    /// Returns a list of the node's peer. The list is published by the peer sampling service after each change
+    /// of the view and is read without waiting for the protocol threads. It is empty before start and after
+    /// shutdown, see [sampling_state](Self::sampling_state).
+    pub fn peers(&self) -> Arc<Vec<Peer>> {
+        self.published_peers.load()
+    }
+
+    /// Returns the view of the node as a JSON document listing the address, age and staleness of each peer,
+    /// for diagnostics
+    pub fn export_view(&self) -> Result<String, Box<dyn Error>> {
+        crate::view_export::export(&self.address, &self.peers())
+    }
+
+    /// Seeds the view with a document produced by [export_view](Self::export_view), for example to set up tests.
+    /// The imported peers are given to the peer sampling service at start instead of the peers of the bootstrap
+    /// source, which is not resolved before the node [bootstraps again](Self::rebootstrap).
+    ///
+    /// Returns the number of imported peers, at most the view size. An error is returned if the service is
+    /// already started or if an address is invalid.
+    ///
+    /// # Arguments
+    ///
+    /// * `json` - The exported view; the address of the node itself is skipped
+    pub fn import_view(&self, json: &str) -> Result<usize, Box<dyn Error>> {
+        let _node = NodeScope::enter(self.address);
+        let state = self.state.lock().unwrap();
+        if *state != ServiceState::Created {
+            Err("The view can only be imported before the service is started")?
+        }
+        let view_size = self.peer_sampling_service.lock().unwrap().config().map(PeerSamplingConfig::view_size);
+        let peers = crate::view_export::import(&self.address, json, view_size)?;
+        let count = peers.len();
+        self.imported_view.lock().unwrap().replace(peers);
+        Ok(count)
+    }
+
+    /// Resolves the bootstrap source given to [start](Self::start) again and adds its peers to the view, for example
+    /// after the node was partitioned from the network. A [static view](PeerSamplingConfig::static_view) is not changed.
+    ///
+    /// An error is returned if the service is not running or if the source cannot be resolved.
+    pub fn rebootstrap(&self) -> Result<(), Box<dyn Error>> {
+        let _node = NodeScope::enter(self.address);
+        if *self.state.lock().unwrap() != ServiceState::Running {
+            Err("The service is not running")?
+        }
+        bootstrap_again(&self.peer_sampling_service)
+    }
+
+    /// Returns the number of peers of the view and the distribution of their ages
+    pub fn view_summary(&self) -> ViewSummary {
+        ViewSummary::new(&self.peers())
+    }
+
+    /// Returns the digests of the active updates. The list is published after each change of the updates and
+    /// is read without waiting for the protocol threads.
+    pub fn active_digests(&self) -> Arc<Vec<String>> {
+        self.published_digests.load()
+    }
+
+    /// Returns a random peer of the view matching the predicate, for instance on the [metadata](Peer::metadata)
+    /// advertised by the peers
+    ///
+    /// # Arguments
+    ///
+    /// * `predicate` - Condition on the peer
+    pub fn get_peer_where<P: Fn(&Peer) -> bool>(&self, predicate: P) -> Option<Peer> {
+        let peers = self.published_peers.load();
+        let matching: Vec<&Peer> = peers.iter().filter(|peer| predicate(peer)).collect();
+        matching.choose(&mut rand::thread_rng()).map(|peer| (*peer).clone())
+    }
+
+    /// Returns a copy of the current gossip configuration
+    pub fn gossip_config(&self) -> GossipConfig {
+        self.gossip_config.read().unwrap().clone()
+    }
+
+    /// Changes the gossip configuration of a running node. The change is validated before being applied.
+    ///
+    /// The push/pull modes and the new gossip period apply from the next round. A new expiration mode applies to
+    /// the updates inserted afterwards: active updates keep the expiration they were inserted with. The listener
+    /// parameters only apply at the next start.
+    ///
+    /// # Arguments
+    ///
+    /// * `f` - Closure modifying the configuration
+    pub fn update_config<F>(&self, f: F) -> Result<(), Box<dyn Error>>
+    where F: FnOnce(&mut GossipConfig)
+    {
+        let _node = NodeScope::enter(self.address);
+        let mut gossip_config = self.gossip_config.write().unwrap();
+        let mut new_config = gossip_config.clone();
+        f(&mut new_config);
+        new_config.validate()?;
+        if !Arc::ptr_eq(gossip_config.digester(), new_config.digester()) {
+            Err("The digest function cannot be changed while running")?
+        }
+        if gossip_config.cluster_id() != new_config.cluster_id() || gossip_config.cluster_name() != new_config.cluster_name() {
+            Err("The cluster cannot be changed while running")?
+        }
+        if new_config.strict_checks() {
+            Self::check_strict(&Self::check_sampler(&new_config, self.peer_sampling_service.lock().unwrap().as_ref()))?;
+        }
+        let mut updates = self.updates.write().unwrap();
+        updates.set_expiration_mode(new_config.update_expiration().clone());
+        updates.set_expiration_start(new_config.expiration_start());
+        updates.set_tombstones(new_config.max_tombstones(), Duration::from_millis(new_config.tombstone_window()));
+        drop(updates);
+        self.quarantine.lock().unwrap().configure(new_config.quarantine_threshold(), new_config.quarantine_cooldown());
+        let period = Some(new_config.gossip_period()).filter(|period| *period != gossip_config.gossip_period());
+        *gossip_config = new_config;
+        drop(gossip_config);
+        if let Some(period) = period {
+            // the next round is scheduled again with the new period
+            self.send_command(Command::UpdatePeriod(period));
+        }
+        self.check_configs();
+        node_log!(Info, GOSSIP, "Gossip configuration updated");
+        Ok(())
+    }
+
+    /// Starts a gossip round immediately, also while the rounds are [paused](Self::pause) or the node is idle.
+    /// The next round is scheduled one period later.
+    ///
+    /// A [NotStarted] error is returned if the service is not running.
+    pub fn trigger_gossip_round(&self) -> Result<(), Box<dyn Error>> {
+        if self.send_command(Command::ForceRound) {
+            Ok(())
+        }
+        else {
+            Err(NotStarted)?
+        }
+    }
+
+    /// Starts a peer sampling round immediately, if the sampler exchanges views, see [PeerSampler::trigger_round].
+    ///
+    /// A [NotStarted] error is returned if the service is not running.
+    pub fn trigger_sampling_round(&self) -> Result<(), Box<dyn Error>> {
+        if *self.state.lock().unwrap() != ServiceState::Running {
+            Err(NotStarted)?
+        }
+        self.peer_sampling_service.lock().unwrap().trigger_round();
+        Ok(())
+    }
+
+    /// Stops initiating gossip and peer sampling rounds until [resume](Self::resume) is called. Received messages
+    /// are still handled, and rounds can still be [triggered](Self::trigger_gossip_round).
+    ///
+    /// A [NotStarted] error is returned if the service is not running.
+    pub fn pause(&self) -> Result<(), Box<dyn Error>> {
+        self.set_paused(true)
+    }
+
+    /// Resumes the rounds stopped by [pause](Self::pause)
+    ///
+    /// A [NotStarted] error is returned if the service is not running.
+    pub fn resume(&self) -> Result<(), Box<dyn Error>> {
+        self.set_paused(false)
+    }
+
+    fn set_paused(&self, paused: bool) -> Result<(), Box<dyn Error>> {
+        if !self.send_command(if paused { Command::Pause } else { Command::Resume }) {
+            Err(NotStarted)?
+        }
+        self.peer_sampling_service.lock().unwrap().set_paused(paused);
+        Ok(())
+    }
+
+    /// Sends a command to the gossip rounds of the timer thread; returns `false` if it is not running
+    fn send_command(&self, command: Command) -> bool {
+        match self.commands.lock().unwrap().as_ref() {
+            Some(commands) => commands.send((Timer::Gossip, command)).is_ok(),
+            None => false,
+        }
+    }
+
+    /// Returns the inconsistencies between the gossip and peer sampling configurations that were detected
+    /// when the service was started or its configuration was last changed, see [GossipConfig::check]
+    pub fn config_warnings(&self) -> Vec<ConfigWarning> {
+        self.config_warnings.lock().unwrap().clone()
+    }
+
+    /// Cross-checks the configurations and logs the detected inconsistencies
+    fn check_configs(&self) {
+        let warnings = Self::check_sampler(&self.gossip_config.read().unwrap(), self.peer_sampling_service.lock().unwrap().as_ref());
+        for warning in &warnings {
+            node_log!(Warn, MONITOR, "Configuration: {}", warning);
+        }
+        *self.config_warnings.lock().unwrap() = warnings;
+    }
+
+    /// Cross-checks the gossip configuration with the configuration of the sampler, if it has one
+    fn check_sampler(gossip_config: &GossipConfig, sampler: &dyn PeerSampler) -> Vec<ConfigWarning> {
+        sampler.config().map(|sampling_config| gossip_config.check(sampling_config)).unwrap_or_default()
+    }
+
+    /// Turns the detected inconsistencies into an error, for configurations with strict checks
+    fn check_strict(warnings: &[ConfigWarning]) -> Result<(), Box<dyn Error>> {
+        if !warnings.is_empty() {
+            let warnings: Vec<String> = warnings.iter().map(ToString::to_string).collect();
+            Err(format!("Inconsistent configuration: {}", warnings.join("; ")))?
+        }
+        Ok(())
+    }
+
+    /// Returns a summary of the liveness and readiness of the node
+    pub fn health(&self) -> HealthReport {
+        let seconds_since = |instant: Option<Instant>| instant.map(|instant| instant.elapsed().as_secs());
+        let listener_bound = match &self.shared_listener {
+            Some(shared_listener) => self.shared_cluster.lock().unwrap().is_some() && shared_listener.is_running(),
+            None => self.listener.lock().unwrap().as_ref().is_some_and(|handle| !handle.is_finished()),
+        };
+        let state = *self.state.lock().unwrap();
+        // each value is copied under its own lock, so that frequent probes do not hold up the other threads
+        let (sampling_running, sampling_ready, last_exchange, sampling_mode, sampling_stats, sampling_state) = {
+            let peer_sampling = self.peer_sampling_service.lock().unwrap();
+            (
+                peer_sampling.is_running(),
+                peer_sampling.is_ready(),
+                peer_sampling.last_exchange(),
+                peer_sampling.config().and_then(PeerSamplingConfig::mode),
+                peer_sampling.sampling_stats(),
+                peer_sampling.state(),
+            )
+        };
+        let (active_updates, expired_updates) = {
+            let updates = self.updates.read().unwrap();
+            (updates.active_count(), updates.removed_count())
+        };
+        let (outbound_only, gossip_mode) = {
+            let gossip_config = self.gossip_config.read().unwrap();
+            (gossip_config.outbound_only(), gossip_config.mode())
+        };
+        let dispatching = self.dispatcher.lock().unwrap().as_ref().is_some_and(|handle| !handle.is_finished());
+        let timing = self.timers.lock().unwrap().as_ref().is_some_and(|handle| !handle.is_finished());
+        let last_received = *self.last_received.lock().unwrap();
+        let started = self.started.lock().unwrap().map(|(started, _)| started);
+
+        let handling = (listener_bound || outbound_only) && dispatching;
+        HealthReport {
+            running: match state {
+                ServiceState::Listening => handling,
+                ServiceState::Running => handling && timing && sampling_running,
+                _ => false,
+            },
+            listener_bound,
+            ready: state == ServiceState::Running && sampling_ready && self.initial_sync.state() != SyncState::Pending,
+            peer_count: self.published_peers.load().len(),
+            seconds_since_last_successful_exchange: seconds_since(last_exchange),
+            seconds_since_last_received_message: seconds_since(last_received),
+            active_updates,
+            expired_updates,
+            gossip_mode,
+            sampling_mode,
+            degraded: self.queues.disconnected.load(std::sync::atomic::Ordering::SeqCst),
+            sampling_stats,
+            epoch: self.epoch.load(std::sync::atomic::Ordering::SeqCst),
+            uptime_seconds: seconds_since(started),
+            sampling_state,
+            join_state: Self::join_state_of(state),
+            network_isolated: self.isolated.load(std::sync::atomic::Ordering::SeqCst),
+        }
+    }
+
+    /// Returns whether the service is listening and whether it joined the network
+    pub fn join_state(&self) -> JoinState {
+        Self::join_state_of(*self.state.lock().unwrap())
+    }
+
+    fn join_state_of(state: ServiceState) -> JoinState {
+        match state {
+            ServiceState::Created => JoinState::NotStarted,
+            ServiceState::Listening => JoinState::ListeningOnly,
+            ServiceState::Running => JoinState::Joined,
+            ServiceState::Stopping | ServiceState::Stopped => JoinState::ShutDown,
+        }
+    }
+
+    /// Returns whether the peer sampling service was started or shut down. The [peers](Self::peers) are empty
+    /// unless it is [running](SamplingState::Running).
+    pub fn sampling_state(&self) -> SamplingState {
+        self.peer_sampling_service.lock().unwrap().state()
+    }
+
+    /// Blocks until the initial sync of a started service ends, see [GossipConfig::set_initial_sync]. Returns `true`
+    /// if a round brought no new digest or no sync was required, and `false` if the timeout elapsed first.
+    ///
+    /// A [NotStarted] error is returned if the service was never started, and an error is returned if it is shut
+    /// down during the sync.
+    pub fn wait_initial_sync(&self) -> Result<bool, Box<dyn Error>> {
+        if *self.state.lock().unwrap() == ServiceState::Created {
+            Err(NotStarted)?
+        }
+        match self.initial_sync.wait() {
+            SyncState::Aborted => Err("The service was shut down during the initial sync")?,
+            state => Ok(state == SyncState::Complete),
+        }
+    }
+
+    /// Returns the addresses of the peers currently quarantined for sending invalid data, followed by the IP
+    /// addresses whose connections are refused for being too slow, see [GossipConfig::set_quarantine]
+    pub fn quarantined_peers(&self) -> Vec<String> {
+        self.quarantine.lock().unwrap().quarantined()
+    }
+
+    /// Returns the most recent errors of the protocol threads, at most [MAX_RECENT_ERRORS](crate::MAX_RECENT_ERRORS), oldest first
+    pub fn recent_errors(&self) -> Vec<ErrorRecord> {
+        self.errors.recent()
+    }
+
+    /// Returns the messages and bytes exchanged with each peer over the last minute, the peers with the most
+    /// bytes first. Received messages are counted for the address advertised by their sender, and at most
+    /// [MAX_TRAFFIC_PEERS](crate::MAX_TRAFFIC_PEERS) peers are tracked.
+    pub fn traffic(&self) -> Vec<PeerTraffic> {
+        self.traffic.traffic()
+    }
+
+    /// Installs a tracer called with the raw bytes of every message sent by the protocol threads, before it is
+    /// written, and of every message received, before it is parsed; `None` removes it. Can be changed while
+    /// running. Without tracer, the messages are not copied or formatted. See [hexdump_tracer](crate::hexdump_tracer)
+    /// for a tracer writing to a file.
+    ///
+    /// # Arguments
+    ///
+    /// * `tracer` - Called with the direction, the address of the peer and the bytes of each message
+    pub fn set_wire_trace(&self, tracer: Option<WireTracer>) {
+        self.traffic.set_wire_trace(tracer);
+    }
+
+    /// Returns the number of nodes of the network estimated at the end of the last estimation epoch, see
+    /// [PeerSamplingConfig::set_size_estimation]. `None` until the first epoch ends, or if the sampler does not
+    /// estimate the size of the network.
+    pub fn estimated_cluster_size(&self) -> Option<f64> {
+        self.peer_sampling_service.lock().unwrap().estimated_cluster_size()
+    }
+
+    /// Returns what the node knows about each peer of its view, in particular the number of new digests learned
+    /// from it per gossip exchange, see [GossipConfig::set_useful_peer_bias], and the last time it was heard from,
+    /// see [GossipConfig::set_keepalive]
+    pub fn peer_info(&self) -> Vec<PeerInfo> {
+        self.usefulness.lock().unwrap().peer_info(&self.published_peers.load(), |peer| self.traffic.last_seen(peer))
+    }
+
+    /// Returns the indicators of the quality of the view: its average age, its churn, the number of distinct peers
+    /// it held recently and the cycles without exchange, see [SamplingStats]. `None` if the sampler does not
+    /// exchange views.
+    pub fn sampling_stats(&self) -> Option<SamplingStats> {
+        self.peer_sampling_service.lock().unwrap().sampling_stats()
+    }
+
+    /// Returns the number of elements and the approximate size of the structures of the service that grow with the
+    /// traffic, see [MemoryReport]. Meant to check, in long runs, that they stay bounded.
+    pub fn memory_report(&self) -> MemoryReport {
+        let mut report = MemoryReport::default();
+        self.updates.read().unwrap().memory(&mut report);
+        report.queued_updates = Usage::of_sizes(self.queued.lock().unwrap().iter()
+            .map(|update| update.digest().len() + update.content().len()));
+        report.pending_deliveries = self.pending_deliveries.usage();
+        report.delivery_retries = self.retried_deliveries.usage();
+        report.queued_messages = Usage {
+            count: self.queued(Kind::Header) + self.queued(Kind::Content),
+            bytes: 0,
+        };
+        report.content_requests = Usage::of_strings(self.content_requests.lock().unwrap().keys());
+        report.timed_out_requests = self.timed_out.lock().unwrap().memory();
+        report.header_exchanges = Usage::of_sizes(self.header_probes.lock().unwrap().keys()
+            .chain(self.in_flight.lock().unwrap().keys())
+            .map(|_| std::mem::size_of::<SocketAddr>()));
+        report.digests_seen = Usage::of_strings(self.digests_seen.lock().unwrap().digests());
+        report.declined_digests = self.declined.lock().unwrap().memory();
+        report.relayed_digests = self.relayed.lock().unwrap().memory();
+        report.digest_mismatches = self.mismatches.lock().unwrap().memory();
+        report.quarantine = self.quarantine.lock().unwrap().memory();
+        report.traffic_peers = self.traffic.memory();
+        report.peer_histories = self.usefulness.lock().unwrap().memory();
+        report.sampler = self.peer_sampling_service.lock().unwrap().memory_report();
+        report
+    }
+
+    /// Returns the number of items of a kind waiting for the message handling thread
+    fn queued(&self, kind: Kind) -> usize {
+        self.inbox.lock().unwrap().as_ref().map_or(0, |inbox| inbox.len(kind))
+    }
+
+    /// Returns the IP from which the last peer that answered a pull probe saw the node connect, if any. Behind a
+    /// NAT, this is the external IP of the node, which it may advertise as its public address.
+    pub fn observed_ip(&self) -> Option<IpAddr> {
+        *self.observed_ip.lock().unwrap()
+    }
+
+    /// Returns a snapshot of the activity counters
+    pub fn stats(&self) -> GossipStats {
+        let mut stats = self.stats.lock().unwrap().clone();
+        stats.epoch = self.epoch.load(std::sync::atomic::Ordering::SeqCst);
+        if let Some((started, started_at)) = *self.started.lock().unwrap() {
+            stats.started_at = Some(started_at);
+            stats.uptime = started.elapsed();
+        }
+        stats.pending_content_requests = self.content_requests.lock().unwrap().len() as u64;
+        stats.queued_header_messages = self.queued(Kind::Header) as u64;
+        stats.queued_content_messages = self.queued(Kind::Content) as u64;
+        stats.dropped_messages = self.queues.dropped.load(std::sync::atomic::Ordering::SeqCst);
+        stats.slow_connections = self.queues.slow_connections.load(std::sync::atomic::Ordering::SeqCst);
+        stats.refused_connections = self.queues.refused_connections.load(std::sync::atomic::Ordering::SeqCst);
+        stats.connections_opened = self.traffic.connections_opened();
+        stats
+    }
+
+    /// Registers an observer called at the end of each gossip and peer sampling round, replacing any previous one
+    ///
+    /// # Arguments
+    ///
+    /// * `observer` - The observer, or `None` to remove the current one
+    pub fn set_round_observer(&self, observer: Option<Arc<dyn RoundObserver + Send + Sync>>) {
+        *self.observer.write().unwrap() = observer;
+    }
+
+    /// Starts the gossip protocol and related threads: the node [listens](Self::start_listening) and
+    /// [joins](Self::join) the network from the bootstrap source.
+    ///
+    /// The listener is bound before any thread is started: if the address is not available, a [BindError](crate::BindError)
+    /// is returned. A service created with [new_shared](Self::new_shared) registers on the shared listener instead.
+    /// If any other stage fails, the stages that were already started are stopped before returning the error.
+    ///
+    /// Nothing is started either if the gossip configuration is invalid, for example with neither push nor pull, or
+    /// if it has [strict checks](GossipConfig::set_strict_checks) and is inconsistent with the peer sampling configuration.
+    /// A service is started once: an [AlreadyStarted] error is returned if it is running or was shut down.
+    ///
+    /// # Arguments
+    ///
+    /// * `bootstrap` - The first peers to contact, see [BootstrapSource]; a list of peers or a boxed closure
+    /// * `update_handler` - Application callback for receiving new updates
+    pub fn start(&mut self, bootstrap: impl Into<BootstrapSource>, update_handler: Box<T>) -> Result<(), Box<dyn Error>> {
+        self.start_listening(update_handler)?;
+        self.join_network(bootstrap.into())
+    }
+
+    /// Binds the listener and starts the threads handling the received messages, without contacting any peer, for
+    /// example so that the port is reserved and health checks pass before the bootstrap peers are known. The node
+    /// takes part in the peer sampling and gossip rounds once it [joins](Self::join) the network.
+    ///
+    /// Until then, gossip messages are handled as usual: header requests are answered, the content of the pushed
+    /// headers is fetched and delivered to the handler, and content requests are answered. Peer sampling messages
+    /// are queued, up to the [sampling queue capacity](GossipConfig::set_queue_capacities), and handled once the node
+    /// joins; the [join state](Self::join_state) is [ListeningOnly](JoinState::ListeningOnly).
+    ///
+    /// The errors are those of [start](Self::start).
+    ///
+    /// # Arguments
+    ///
+    /// * `update_handler` - Application callback for receiving new updates
+    pub fn start_listening(&mut self, update_handler: Box<T>) -> Result<(), Box<dyn Error>> {
+        let _node = NodeScope::enter(self.address);
+        if *self.state.lock().unwrap() != ServiceState::Created {
+            Err(AlreadyStarted)?
+        }
+
+        // a configuration that cannot disseminate updates is refused before anything is bound
+        self.gossip_config.read().unwrap().validate()?;
+        self.check_configs();
+        if self.gossip_config.read().unwrap().strict_checks() {
+            Self::check_strict(&self.config_warnings())?;
+        }
+        let epoch_file = self.gossip_config.read().unwrap().epoch_file().map(std::path::Path::to_path_buf);
+        let epoch = match epoch_file {
+            Some(epoch_file) => crate::epoch::next_epoch(&epoch_file)?,
+            None => 1,
+        };
+
+        // bind first so that nothing is started if the address is not available
+        let listener = match &self.shared_listener {
+            _ if self.gossip_config.read().unwrap().outbound_only() => None,
+            Some(_) if self.gossip_config.read().unwrap().cluster_id().is_none() => Err("A cluster id is required to share a listener")?,
+            Some(_) => None,
+            None => Some(crate::network::bind(&self.bind_address, self.gossip_config.read().unwrap().network())?),
+        };
+
+        let generation = self.register_handler(update_handler);
+
+        // the imported updates are inserted before the service is listening, so that a failure aborts the start
+        if let Err(e) = self.start_handlers(listener).and_then(|_| self.insert_imported(generation)) {
+            node_log!(Error, GOSSIP, "Error during start, stopping started activities: {:?}", e);
+            self.abort_start();
+            return Err(e);
+        }
+        *self.state.lock().unwrap() = ServiceState::Listening;
+        self.epoch.store(epoch, std::sync::atomic::Ordering::SeqCst);
+        self.started.lock().unwrap().replace((Instant::now(), SystemTime::now()));
+        node_log!(Info, GOSSIP, "Listening in epoch {}", epoch);
+        // the expiration of the queued updates starts now
+        let queued = std::mem::take(&mut *self.queued.lock().unwrap());
+        if !queued.is_empty() {
+            node_log!(Info, GOSSIP, "Inserting {} updates submitted before start", queued.len());
+            let mut updates = self.updates.write().unwrap();
+            for update in queued {
+                // the service is started, an update that cannot be inserted does not stop it
+                if updates.is_new(update.digest()) {
+                    let digest = update.digest().to_owned();
+                    if let Err(e) = updates.insert_update(update) {
+                        node_log!(Warn, UPDATES, "Skipping update {} submitted before start: {}", digest, e);
+                    }
+                }
+            }
+            updates.publish();
+        }
+        Ok(())
+    }
+
+    /// Joins the network from the given peers: starts the peer sampling and gossip rounds of a service that is
+    /// [listening](Self::start_listening). If the service already joined, the peers are added to the view instead,
+    /// unless it is a [static view](PeerSamplingConfig::static_view).
+    ///
+    /// A [NotStarted] error is returned if the service is not listening. If a stage fails, the service is stopped
+    /// as if [start](Self::start) had failed, and can be started again.
+    ///
+    /// # Arguments
+    ///
+    /// * `peers` - The first peers to contact; the node waits for connections from other peers if there is none
+    pub fn join(&mut self, peers: Vec<Peer>) -> Result<(), Box<dyn Error>> {
+        let _node = NodeScope::enter(self.address);
+        let state = *self.state.lock().unwrap();
+        match state {
+            ServiceState::Listening => self.join_network(peers.into()),
+            ServiceState::Running => {
+                self.peer_sampling_service.lock().unwrap().merge_peers(peers);
+                Ok(())
+            }
+            ServiceState::Created | ServiceState::Stopping | ServiceState::Stopped => Err(NotStarted)?,
+        }
+    }
+
+    /// Starts the peer sampling and gossip rounds of a listening service, or stops the service if a stage fails
+    fn join_network(&mut self, bootstrap: BootstrapSource) -> Result<(), Box<dyn Error>> {
+        let _node = NodeScope::enter(self.address);
+        if let Err(e) = self.start_rounds(bootstrap) {
+            node_log!(Error, GOSSIP, "Error while joining, stopping started activities: {:?}", e);
+            self.abort_start();
+            *self.state.lock().unwrap() = ServiceState::Created;
+            return Err(e);
+        }
+        *self.state.lock().unwrap() = ServiceState::Running;
+        node_log!(Info, GOSSIP, "Joined the network");
+        Ok(())
+    }
+
+    /// Starts the gossip protocol, contacting the given peers first, see [start](Self::start)
+    ///
+    /// # Arguments
+    ///
+    /// * `peers` - The first peers to contact
+    /// * `update_handler` - Application callback for receiving new updates
+    pub fn start_with_peers(&mut self, peers: Vec<Peer>, update_handler: Box<T>) -> Result<(), Box<dyn Error>> {
+        self.start(peers, update_handler)
+    }
+
+    /// Starts the gossip protocol without contacting any peer: the node waits for connections from other peers,
+    /// see [start](Self::start)
+    ///
+    /// # Arguments
+    ///
+    /// * `update_handler` - Application callback for receiving new updates
+    pub fn start_isolated(&mut self, update_handler: Box<T>) -> Result<(), Box<dyn Error>> {
+        self.start(Vec::new(), update_handler)
+    }
+
+    /// Starts the thread handling the received messages and the listener
+    fn start_handlers(&mut self, listener: Option<TcpListener>) -> Result<(), Box<dyn Error>> {
+        let (sampling_capacity, header_capacity, content_capacity) = {
+            let gossip_config = self.gossip_config.read().unwrap();
+            (gossip_config.sampling_queue_capacity(), gossip_config.header_queue_capacity(), gossip_config.content_queue_capacity())
+        };
+        // a handling thread stopped during a previous run no longer degrades the service
+        self.queues.disconnected.store(false, std::sync::atomic::Ordering::SeqCst);
+        // the peer sampling messages wait in their queue until the sampler is started, when the node joins
+        let capacities = [
+            (Kind::Header, header_capacity),
+            (Kind::Content, content_capacity),
+            (Kind::Ping, header_capacity),
+            (Kind::Sampling, sampling_capacity),
+        ];
+        let (sender, receiver) = inbox(&capacities, &[Kind::Sampling]);
+        self.sampling_handler.lock().unwrap().take();
+        self.inbox.lock().unwrap().replace(sender.detached());
+
+        // start message handler
+        self.start_message_handler(receiver, sender.detached())?;
+        // start TCP listener
+        self.start_network_listener(listener, sender)?;
+        Ok(())
+    }
+
+    /// Starts the peer sampling and gossip rounds
+    fn start_rounds(&mut self, bootstrap: BootstrapSource) -> Result<(), Box<dyn Error>> {
+        let inbox = self.inbox.lock().unwrap().clone().ok_or("The service is not listening")?;
+        // start peer sampling, from the imported view if any
+        let initial_peers = match self.imported_view.lock().unwrap().take() {
+            Some(peers) => Some(peers),
+            None => bootstrap.resolve()?,
+        };
+        let (command_sender, command_receiver) = std::sync::mpsc::channel();
+        let (tasks, slots) = SamplerTasks::new(command_sender.clone());
+        let sampling_capacity = self.gossip_config.read().unwrap().sampling_queue_capacity();
+        let (message_sender, messages) = std::sync::mpsc::sync_channel(sampling_capacity);
+        let context = SamplerContext {
+            address: self.address,
+            initial_peers,
+            bootstrap: Arc::new(Mutex::new(bootstrap)),
+            messages,
+            tasks,
+            view: ViewPublisher::new(Arc::clone(&self.published_peers)),
+        };
+        self.peer_sampling_service.lock().unwrap().init(context)?;
+        // the messages waiting for the sampler are handled from now on
+        let handler = slots.take_handler().unwrap_or_else(|| self.sampling_forwarder(message_sender));
+        self.sampling_handler.lock().unwrap().replace(handler);
+        inbox.release(Kind::Sampling);
+        // start gossiping
+        self.start_timers(command_sender, command_receiver, slots.take_rounds())?;
+        Ok(())
+    }
+
+    /// Returns the handler sending the peer sampling messages on the channel of a sampler that does not handle them
+    /// on the message handling thread, see [SamplerContext::messages]
+    fn sampling_forwarder(&self, sender: SyncSender<PeerSamplingMessage>) -> MessageHandler {
+        let queues = Arc::clone(&self.queues);
+        let errors = Arc::clone(&self.errors);
+        Box::new(move |message| match sender.try_send(message) {
+            Ok(()) => (),
+            Err(TrySendError::Full(_)) => {
+                node_log!(Warn, NETWORK, "Queue of peer sampling messages is full, dropping message");
+                queues.dropped.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            }
+            Err(TrySendError::Disconnected(_)) => {
+                if !queues.disconnected.swap(true, std::sync::atomic::Ordering::SeqCst) {
+                    node_log!(Error, NETWORK, "Sampler dropped its peer sampling messages, service is degraded");
+                    errors.record(ErrorCategory::Handler, None, "receiver of peer sampling messages was dropped".to_owned());
+                }
+            }
+        })
+    }
+
+    /// Stops the activities started by a failed call to [start](Self::start) or [join](Self::join).
+    /// The message handling thread terminates once the message senders are dropped, with the failed stage or the listener.
+    /// The sampler is reset, so that the service can be started again.
+    fn abort_start(&self) {
+        self.stop_activities();
+        self.peer_sampling_service.lock().unwrap().reset();
+        self.drop_handler(ShutdownReason::StartFailed);
+        self.listener_shutdown.store(false, std::sync::atomic::Ordering::SeqCst);
+    }
+
+    /// Stops the started activities in order: the timer thread initiating exchanges first, then the listener, and
+    /// finally the thread handling the messages already received, which terminates once its queues are drained.
+    /// The handles are taken out of their mutexes before the threads are joined, so that a thread reading them, for
+    /// instance through [health](Self::health), does not block the shutdown.
+    ///
+    /// Returns `false` if a thread could not be joined.
+    fn stop_activities(&self) -> bool {
+        let mut success = true;
+
+        // stop initiating exchanges
+        if let Some(commands) = self.commands.lock().unwrap().take() {
+            // the thread also stops if it has already dropped the receiver
+            let _ = commands.send((Timer::Gossip, Command::Shutdown));
+        }
+        let timers = self.timers.lock().unwrap().take();
+        success &= Self::join_thread(timers);
+        success &= self.peer_sampling_service.lock().unwrap().stop_sampling().is_ok();
+
+        // stop accepting connections
+        if let (Some(shared_listener), Some(cluster_id)) = (&self.shared_listener, self.shared_cluster.lock().unwrap().take()) {
+            // drops the message senders
+            shared_listener.unregister(cluster_id);
+        }
+        // drops the message senders once the responses being read are dispatched
+        self.traffic.set_reply_readers(None);
+        let listener = self.listener.lock().unwrap().take();
+        let listener_address = self.listener_address.lock().unwrap().take();
+        if let Some(handle) = listener {
+            self.listener_shutdown.store(true, std::sync::atomic::Ordering::SeqCst);
+            // wake up the listener on the port it is bound to, which differs from the node address behind a port forwarding
+            let wake_address = crate::network::local_address(listener_address.unwrap_or(self.bind_address));
+            if let Err(e) = crate::network::send(&wake_address, &NoopMessage, None, self.gossip_config.read().unwrap().network()) {
+                node_log!(Debug, NETWORK, "Could not notify listener: {:?}", e);
+            }
+            success &= Self::join_thread(Some(handle));
+        }
+
+        // drain the queues of the message handler: the listener dropped the message senders
+        self.inbox.lock().unwrap().take();
+        let dispatcher = self.dispatcher.lock().unwrap().take();
+        success &= Self::join_thread(dispatcher);
+        self.sampling_handler.lock().unwrap().take();
+        success &= self.peer_sampling_service.lock().unwrap().shutdown().is_ok();
+        node_log!(Info, GOSSIP, "All thread terminated");
+        success
+    }
+
+    /// Waits for the termination of a thread, if it was started; returns `false` if the thread panicked
+    fn join_thread(handle: Option<JoinHandle<()>>) -> bool {
+        match handle.map(|handle| handle.join()) {
+            Some(Err(e)) => {
+                node_log!(Error, GOSSIP, "Error during thread join: {:?}", e);
+                false
+            }
+            _ => true,
+        }
+    }
+
+    /// Starts the thread handling the received messages, taking the messages of each kind in turn, and delivering
+    /// the updates to the application handler. The messages of a kind are handled in the order in which they were
+    /// queued, and the updates are delivered in the order of their origin with ordered delivery.
+    ///
+    /// # Arguments
+    ///
+    /// * `receiver` - Queues of the messages and the updates to deliver
+    /// * `inbox` - Queues the updates to deliver, from the handler of the content messages
+    fn start_message_handler(&mut self, receiver: InboxReceiver, inbox: InboxSender) -> Result<(), Box<dyn Error>> {
+        let mut handle_header = self.header_handler();
+        let mut handle_content = self.content_handler(inbox);
+        let mut handle_ping = self.ping_handler();
+        let sampling_handler = Arc::clone(&self.sampling_handler);
+        let mut delivery = {
+            let gossip_config = self.gossip_config.read().unwrap();
+            UpdateDelivery {
+                update_handler: Arc::clone(&self.update_handler),
+                updates: Arc::clone(&self.updates),
+                stats: Arc::clone(&self.stats),
+                errors: Arc::clone(&self.errors),
+                pending_deliveries: Arc::clone(&self.pending_deliveries),
+                retried_deliveries: Arc::clone(&self.retried_deliveries),
+                ordering: gossip_config.ordered_delivery().then(|| OrderedDelivery::new(Duration::from_millis(gossip_config.gap_timeout()))),
+                acknowledged: gossip_config.acknowledged_delivery(),
+                own_origin: gossip_config.origin_id().filter(|_| gossip_config.suppress_own_origin_redelivery()).map(str::to_owned),
+                retries: DeliveryRetries::new(Duration::from_millis(gossip_config.delivery_retry_delay())),
+            }
+        };
+        let node = self.address;
+        let handle = std::thread::Builder::new().name(format!("{} - message handler", self.address)).spawn(move|| {
+            crate::logging::set_node(node);
+            node_log!(Info, NETWORK, "Started message handling thread");
+            loop {
+                // wake up when the first held update stops waiting for the missing ones, or a delivery is retried
+                match receiver.recv_until(delivery.next_deadline()) {
+                    Ok(Inbound::Header(message)) => handle_header(message),
+                    Ok(Inbound::Content(message)) => handle_content(message),
+                    Ok(Inbound::Ping(message)) => handle_ping(message),
+                    Ok(Inbound::Sampling(message)) => {
+                        // the peer sampling messages are only released once the sampler is started
+                        if let Some(handler) = sampling_handler.lock().unwrap().as_mut() {
+                            handler(message);
+                        }
+                    }
+                    Ok(Inbound::Delivery(update)) => delivery.push(update),
+                    Err(RecvTimeoutError::Timeout) => (),
+                    Err(RecvTimeoutError::Disconnected) => break,
+                }
+                // the held updates and the retries are also due while messages keep arriving
+                delivery.expire(Instant::now());
+            }
+            delivery.drain();
+            node_log!(Info, NETWORK, "Message handling thread exiting");
+        })?;
+        self.dispatcher.lock().unwrap().replace(handle);
+        Ok(())
+    }
+
+    /// Returns the handler of the keepalive pings, answered with a pong, called from the message handling thread
+    fn ping_handler(&self) -> impl FnMut(PingMessage) + Send + 'static {
+        let gossip_config_arc = Arc::clone(&self.gossip_config);
+        let address = self.address.to_string();
+        let traffic = Arc::clone(&self.traffic);
+        move |message: PingMessage| {
+            let (cluster_id, network) = {
+                let gossip_config = gossip_config_arc.read().unwrap();
+                (gossip_config.cluster_id(), gossip_config.network().clone())
+            };
+            let Some(sender_address) = message.sender().parse::<SocketAddr>().ok()
+                .and_then(|sender| crate::network::reply_address(sender, message.source(), network.reply_address())) else {
+                node_log!(Debug, NETWORK, "Dropping ping from {} received from {:?}", message.sender(), message.source());
+                return;
+            };
+            if let Err(e) = traffic.respond(message.reply_stream(), &sender_address, &PongMessage::new(address.clone()), cluster_id, &network) {
+                node_log!(Debug, GOSSIP, "Could not answer the ping of {}: {:?}", sender_address, e);
+            }
+        }
+    }
+
+    /// Returns the handler of the header messages, called from the message handling thread
+    fn header_handler(&self) -> impl FnMut(HeaderMessage) + Send + 'static {
+        let gossip_config_arc = Arc::clone(&self.gossip_config);
+        let address = self.address.to_string();
+        let updates_arc = Arc::clone(&self.updates);
+        let header_probes_arc = Arc::clone(&self.header_probes);
+        let in_flight_arc = Arc::clone(&self.in_flight);
+        let digests_seen_arc = Arc::clone(&self.digests_seen);
+        let content_requests_arc = Arc::clone(&self.content_requests);
+        let timed_out_arc = Arc::clone(&self.timed_out);
+        let declined_arc = Arc::clone(&self.declined);
+        let mismatches_arc = Arc::clone(&self.mismatches);
+        let relayed_arc = Arc::clone(&self.relayed);
+        let usefulness_arc = Arc::clone(&self.usefulness);
+        let activity = Arc::clone(&self.activity);
+        let stats_arc = Arc::clone(&self.stats);
+        let errors_arc = Arc::clone(&self.errors);
+        let traffic = Arc::clone(&self.traffic);
+        let quarantine_arc = Arc::clone(&self.quarantine);
+        let observed_ip_arc = Arc::clone(&self.observed_ip);
+        let update_callback_arc = Arc::clone(&self.update_handler);
+        move |message: HeaderMessage| {
+            let gossip_config = gossip_config_arc.read().unwrap().clone();
+
+            if !crate::message::same_cluster(gossip_config.cluster_name(), message.cluster()) {
+                node_log!(Debug, NETWORK, "Dropping header message from {} of cluster {:?}", message.sender(), message.cluster());
+                stats_arc.lock().unwrap().foreign_cluster_messages += 1;
+                return;
+            }
+            if quarantine_arc.lock().unwrap().is_quarantined(&crate::network::observed_sender(message.sender(), message.source())) {
+                node_log!(Debug, NETWORK, "Ignoring header message from quarantined peer {}", message.sender());
+                stats_arc.lock().unwrap().quarantined_messages += 1;
+                return;
+            }
+
+            if let Ok(advertised_address) = message.sender().parse::<SocketAddr>() {
+                let Some(sender_address) = crate::network::reply_address(advertised_address, message.source(), gossip_config.network().reply_address()) else {
+                    node_log!(Warn, NETWORK, "Dropping header message from {} received from {:?}", advertised_address, message.source());
+                    stats_arc.lock().unwrap().sender_mismatches += 1;
+                    return;
+                };
+
+                // tombstones are only taken from a sender whose reported address matches its connection, and no
+                // more of them than are advertised
+                let tombstones = &message.tombstones()[..message.tombstones().len().min(gossip_config.max_tombstones())];
+                let verified = crate::network::observed_sender(message.sender(), message.source()) == message.sender();
+                if !tombstones.is_empty() && !verified {
+                    node_log!(Debug, UPDATES, "Ignoring tombstones from {} received from {:?}", advertised_address, message.source());
+                }
+                else if !tombstones.is_empty() {
+                    let mut updates = updates_arc.write().unwrap();
+                    let tombstoned = updates.apply_tombstones(tombstones);
+                    if tombstoned > 0 {
+                        node_log!(Debug, UPDATES, "Removed {} updates expired by {}", tombstoned, sender_address);
+                        updates.publish();
+                        stats_arc.lock().unwrap().tombstoned_updates += tombstoned as u64;
+                        let mut relayed = relayed_arc.lock().unwrap();
+                        for digest in tombstones {
+                            relayed.remove(digest);
+                        }
+                    }
+                }
+
+                let updates = updates_arc.read().unwrap();
+                // digests sent in a header response, whose expiration may start
+                let mut pushed = None;
+
+                let process = match message.message_type() {
+                    MessageType::Request => {
+                        // Respond with message headers if pull is enabled and the sender asked for them, even
+                        // without active updates so that the sender knows the exchange is complete
+                        if gossip_config.is_pull() {
+                            if message.wants_response() {
+                                let mut response = HeaderMessage::new_response(address.clone())
+                                    .with_cluster(gossip_config.cluster_name().map(str::to_owned));
+                                let mut headers = match updates.cached_headers() {
+                                    Some(headers) => {
+                                        stats_arc.lock().unwrap().header_cache_hits += 1;
+                                        headers
+                                    }
+                                    None => Arc::new(updates.active_headers()),
+                                };
+                                // a message never carries more headers than its receiver accepts: a random
+                                // subset of them is sent, so that all of them are advertised over the responses
+                                if headers.len() > MAX_MESSAGE_DIGESTS {
+                                    let subset = headers.choose_multiple(&mut rand::thread_rng(), MAX_MESSAGE_DIGESTS).cloned().collect();
+                                    headers = Arc::new(subset);
+                                }
+                                response.set_shared_headers(Arc::clone(&headers));
+                                response.set_tombstones(updates.tombstones());
+                                response.set_observed(message.source());
+                                match traffic.respond(message.reply_stream(), &sender_address, &response, gossip_config.cluster_id(), gossip_config.network()) {
+                                    Ok(written) => {
+                                        node_log!(Trace, GOSSIP, "Sent header response - {} bytes to {:?}", written, sender_address);
+                                        stats_arc.lock().unwrap().header_responses_sent += 1;
+                                        if gossip_config.expiration_start() == ExpirationStart::OnFirstPush && !response.headers().is_empty() {
+                                            pushed = Some(headers);
+                                        }
+                                    },
+                                    Err(e) => {
+                                        node_log!(Error, GOSSIP, "Error sending header response: {:?}", e);
+                                        errors_arc.record(ErrorCategory::Send, Some(message.sender()), format!("header response: {}", e));
+                                    }
+                                }
+                            }
+                            else {
+                                stats_arc.lock().unwrap().suppressed_header_responses += 1;
+                            }
+                        }
+                        gossip_config.is_push()
+                    }
+                    MessageType::Response => {
+                        // the exchange initiated with the sender is complete
+                        in_flight_arc.lock().unwrap().remove(&advertised_address);
+                        // Only accept responses to a pull probe that is still outstanding
+                        let probe = header_probes_arc.lock().unwrap().remove(&advertised_address);
+                        let solicited = gossip_config.is_pull() && probe
+                            .is_some_and(|sent| sent.elapsed().as_millis() <= probe_window(&gossip_config));
+                        if let Some(observed) = message.observed().filter(|_| solicited).and_then(|observed| observed.parse::<SocketAddr>().ok()) {
+                            observed_ip_arc.lock().unwrap().replace(observed.ip());
+                        }
+                        if !solicited {
+                            node_log!(Debug, GOSSIP, "Ignoring unsolicited header response from {}", sender_address);
+                            stats_arc.lock().unwrap().ignored_header_responses += 1;
+                        }
+                        solicited
+                    }
+                };
+
+                // Process message if (request and push enabled) or (solicited response and pull enabled)
+                let mut new_digests = HashMap::new();
+                if process {
+                    message.headers().iter().chain(message.relayed()).for_each(|digest| {
+                        if updates.is_new(digest) {
+                            node_log!(Debug, GOSSIP, "New digest: {}", digest);
+                            new_digests.insert(digest.to_owned(), vec![]);
+                        }
+                        else {
+                            node_log!(Trace, GOSSIP, "Duplicate digest: {}", digest);
+                        }
+                    });
+                }
+                drop(updates);
+                if let Some(digests) = pushed {
+                    updates_arc.write().unwrap().start_expiration(&digests);
+                }
+
+                if !new_digests.is_empty() {
+                    // the content is not requested again from a peer that recently sent mismatching content
+                    let cooldown = gossip_config.mismatch_cooldown();
+                    let mismatches = mismatches_arc.lock().unwrap();
+                    let observed = crate::network::observed_sender(message.sender(), message.source());
+                    new_digests.retain(|digest, _| !mismatches.is_failed(digest, &observed, cooldown));
+                }
+                if gossip_config.relay_headers() && !new_digests.is_empty() {
+                    let mut relayed = relayed_arc.lock().unwrap();
+                    for digest in new_digests.keys() {
+                        relayed.record(digest, sender_address);
+                    }
+                }
+                if let Some(policy) = gossip_config.fetch_policy().filter(|_| !new_digests.is_empty()) {
+                    let ttl = gossip_config.declined_fetch_ttl();
+                    {
+                        let declined = declined_arc.lock().unwrap();
+                        new_digests.retain(|digest, _| !declined.is_declined(digest, ttl));
+                    }
+                    // the policy is consulted outside of the locks
+                    let refused: Vec<String> = new_digests.keys()
+                        .filter(|digest| !policy.should_fetch(digest, message.sender()))
+                        .cloned()
+                        .collect();
+                    if !refused.is_empty() {
+                        node_log!(Debug, GOSSIP, "Declined {} digests from {}", refused.len(), sender_address);
+                        stats_arc.lock().unwrap().declined_fetches += refused.len() as u64;
+                        let mut declined = declined_arc.lock().unwrap();
+                        for digest in refused {
+                            new_digests.remove(&digest);
+                            declined.decline(digest, ttl);
+                        }
+                    }
+                }
+                if !new_digests.is_empty() {
+                    activity.store(true, std::sync::atomic::Ordering::SeqCst);
+                    // only request the digests that are not already requested from another peer
+                    let timeout = gossip_config.content_request_timeout() as u128;
+                    let now = Instant::now();
+                    let mut content_requests = content_requests_arc.lock().unwrap();
+                    let mut pending = 0;
+                    new_digests.retain(|digest, _| match content_requests.get_mut(digest) {
+                        Some(request) if request.sent.elapsed().as_millis() <= timeout => {
+                            if request.peer != sender_address && request.fallback.is_none() {
+                                request.fallback = Some(sender_address);
+                            }
+                            pending += 1;
+                            false
+                        }
+                        previous => {
+                            if let Some(previous) = previous {
+                                stats_arc.lock().unwrap().content_request_timeouts += 1;
+                                timed_out_arc.lock().unwrap().record(digest.to_owned(), previous.peer);
+                            }
+                            content_requests.insert(digest.to_owned(), ContentRequest { peer: sender_address, sent: now, fallback: None });
+                            true
+                        }
+                    });
+                    drop(content_requests);
+                    if pending > 0 {
+                        node_log!(Debug, GOSSIP, "{} new digests from {} are already requested", pending, sender_address);
+                    }
+                }
+                if !new_digests.is_empty() {
+                    let mut digests_seen = digests_seen_arc.lock().unwrap();
+                    let first_seen: Vec<&String> = new_digests.keys()
+                        .filter(|digest| digests_seen.insert(digest))
+                        .collect();
+                    drop(digests_seen);
+                    stats_arc.lock().unwrap().digests_learned += first_seen.len() as u64;
+                    usefulness_arc.lock().unwrap().record_new_digests(&crate::network::observed_sender(message.sender(), message.source()), first_seen.len());
+
+                    // announce the digests to the application before their content is requested
+                    // without holding the lock of the handler, so that a slow announcement does not hold deliveries
+                    if !first_seen.is_empty() {
+                        let handler = update_callback_arc.lock().unwrap().as_ref().map(|registered| Arc::clone(&registered.handler));
+                        if let Some(handler) = handler {
+                            handler.call(|handler| {
+                                for digest in first_seen {
+                                    handler.on_header(digest, message.sender());
+                                }
+                            });
+                        }
+                    }
+
+                    let content_request = ContentMessage::new_request(address.clone(), new_digests);
+                    match traffic.respond(message.reply_stream(), &sender_address, &content_request, gossip_config.cluster_id(), gossip_config.network()) {
+                        Ok(written) => node_log!(Trace, GOSSIP, "Sent content request - {} bytes to {:?}", written, sender_address),
+                        Err(e) => {
+                            node_log!(Error, GOSSIP, "Error content request response: {:?}", e);
+                            errors_arc.record(ErrorCategory::Send, Some(message.sender()), format!("content request: {}", e));
+                        }
+                    }
+                }
+            }
+            else {
+                node_log!(Error, NETWORK, "Could not parse sender address {}", message.sender());
+                errors_arc.record(ErrorCategory::Parse, Some(message.sender()), "invalid sender address".to_owned());
+            }
+        }
+    }
+
+    /// Moves to the peers that advertised them the pending content requests of the digests relayed by the sender
+    /// of a response, and returns the digests to request from each of these peers. Only the digests whose pending
+    /// request was sent to the sender are requested again.
+    fn redirect_requests(address: &str, sender_address: SocketAddr, redirects: HashMap<String, String>, content_requests: &mut HashMap<String, ContentRequest>) -> HashMap<SocketAddr, HashMap<String, Vec<u8>>> {
+        let mut requests: HashMap<SocketAddr, HashMap<String, Vec<u8>>> = HashMap::new();
+        let now = Instant::now();
+        for (digest, provider) in redirects {
+            let Ok(provider) = provider.parse::<SocketAddr>() else {
+                continue;
+            };
+            if provider == sender_address || provider.to_string() == address {
+                continue;
+            }
+            if let Some(request) = content_requests.get_mut(&digest).filter(|request| request.peer == sender_address) {
+                request.peer = provider;
+                request.sent = now;
+                requests.entry(provider).or_default().insert(digest, vec![]);
+            }
+        }
+        requests
+    }
+
+    /// Registers a new application handler and returns its generation; the previous handler, if any, is notified
+    /// that it is replaced
+    fn register_handler(&self, handler: Box<T>) -> u64 {
+        let generation = self.handler_generation.fetch_add(1, std::sync::atomic::Ordering::SeqCst) + 1;
+        let previous = self.update_handler.lock().unwrap().replace(RegisteredHandler { generation, handler: Arc::new(HandlerCell::new(handler)) });
+        if let Some(previous) = previous {
+            previous.handler.shutdown(ShutdownReason::Replaced);
+        }
+        generation
+    }
+
+    /// Notifies the application handler, if any, before dropping it. The notification waits for the call of the
+    /// handler in progress, if any, so that the handler is not called concurrently; it is notified even if a
+    /// delivery panicked.
+    fn drop_handler(&self, reason: ShutdownReason) {
+        let registered = self.update_handler.lock().unwrap_or_else(PoisonError::into_inner).take();
+        if let Some(registered) = registered {
+            registered.handler.shutdown(reason);
+        }
+    }
+
+    /// Returns the name of a thread of the service that stopped while the service was running, if any
+    fn stopped_thread(&self) -> Option<String> {
+        let name = |handle: &JoinHandle<()>| handle.is_finished().then(|| handle.thread().name().unwrap_or("unnamed").to_owned());
+        let timers = self.timers.lock().unwrap();
+        let listener = self.listener.lock().unwrap();
+        let dispatcher = self.dispatcher.lock().unwrap();
+        let stopped = timers.iter()
+            .chain(listener.iter())
+            .chain(dispatcher.iter())
+            .find_map(name);
+        stopped
+    }
+
+    /// Replaces the application handler.
+    ///
+    /// Updates received from then on are delivered to the new handler. If `replay` is set, the currently
+    /// active updates are also delivered to it, see [replay_active](Self::replay_active).
+    ///
+    /// # Arguments
+    ///
+    /// * `update_handler` - Application callback for receiving new updates
+    /// * `replay` - Whether the active updates should be delivered to the new handler
+    pub fn set_update_handler(&self, update_handler: Box<T>, replay: bool) -> Result<(), Box<dyn Error>> {
+        self.register_handler(update_handler);
+        if replay {
+            self.replay_active()?;
+        }
+        Ok(())
+    }
+
+    /// Returns the active updates as a CBOR document listing the content, digest and origin of each update and whether
+    /// the current handler received it, or acknowledged it with [acknowledged delivery](GossipConfig::set_acknowledged_delivery).
+    /// The application can persist the document and [import](Self::import_updates) it after a restart.
+    pub fn export_updates(&self) -> Result<Vec<u8>, Box<dyn Error>> {
+        let acknowledged = self.gossip_config.read().unwrap().acknowledged_delivery();
+        let stored = {
+            let updates = self.updates.read().unwrap();
+            let generation = self.update_handler.lock().unwrap().as_ref().map(|registered| registered.generation);
+            updates.stored_updates(generation, acknowledged)
+        };
+        crate::update_export::export(stored)
+    }
+
+    /// Restores the updates of a document produced by [export_updates](Self::export_updates), for example when the
+    /// node restarts. The updates are inserted when the service starts, and the updates received from peers that the
+    /// handler had not received, or not acknowledged, are delivered to the handler given to [start](Self::start).
+    /// The expiration of the imported updates starts again.
+    ///
+    /// Returns the number of imported updates. An error is returned if the service is already started or if the
+    /// digest of an update does not match its content.
+    ///
+    /// # Arguments
+    ///
+    /// * `document` - The exported updates
+    pub fn import_updates(&self, document: &[u8]) -> Result<usize, Box<dyn Error>> {
+        let _node = NodeScope::enter(self.address);
+        let state = self.state.lock().unwrap();
+        if *state != ServiceState::Created {
+            Err("The updates can only be imported before the service is started")?
+        }
+        let digester = Arc::clone(self.gossip_config.read().unwrap().digester());
+        let stored = crate::update_export::import(document, digester.as_ref())?;
+        let count = stored.len();
+        self.imported_updates.lock().unwrap().extend(stored);
+        Ok(count)
+    }
+
+    /// Inserts the imported updates and queues those received from peers that the handler of the given generation
+    /// is not done with. The imported updates are kept if they cannot be queued, for the next start.
+    fn insert_imported(&self, generation: u64) -> Result<(), Box<dyn Error>> {
+        let imported = self.imported_updates.lock().unwrap().clone();
+        if imported.is_empty() {
+            return Ok(());
+        }
+        let inbox = self.inbox.lock().unwrap().clone().ok_or(NotStarted)?;
+        let mut undelivered = Vec::new();
+        {
+            let mut updates = self.updates.write().unwrap();
+            for stored in imported {
+                let digest = stored.update.digest().to_owned();
+                if !updates.is_new(&digest) {
+                    continue;
+                }
+                let received = stored.provider.is_some();
+                if let Err(e) = updates.insert_update_from(stored.update.clone(), stored.provider) {
+                    node_log!(Warn, UPDATES, "Skipping imported update {}: {}", digest, e);
+                    continue;
+                }
+                if stored.delivered {
+                    updates.mark_delivered(&digest, generation);
+                    updates.mark_acknowledged(&digest, generation);
+                }
+                // as when they are submitted, local updates are not delivered
+                else if received {
+                    updates.mark_delivered(&digest, generation);
+                    undelivered.push(stored.update);
+                }
+            }
+            updates.publish();
+        }
+        node_log!(Info, UPDATES, "Imported updates inserted, {} queued for delivery", undelivered.len());
+        self.queue_deliveries(&inbox, generation, undelivered)?;
+        self.imported_updates.lock().unwrap().clear();
+        Ok(())
+    }
+
+    /// Queues updates for delivery to the handler of the given generation on the message handling thread
+    fn queue_deliveries(&self, inbox: &InboxSender, generation: u64, updates: Vec<Update>) -> Result<(), Box<dyn Error>> {
+        for update in updates {
+            let delivery = Delivery { generation, update };
+            self.pending_deliveries.add(delivery.size());
+            if inbox.try_send(Inbound::Delivery(delivery)).is_err() {
+                Err(NotStarted)?
+            }
+        }
+        Ok(())
+    }
+
+    /// Delivers to the current handler, in insertion order, every active update it has not received yet,
+    /// including the updates submitted locally. Deliveries happen on the message handling thread.
+    ///
+    /// Returns the number of updates queued for delivery.
+    pub fn replay_active(&self) -> Result<usize, Box<dyn Error>> {
+        let _node = NodeScope::enter(self.address);
+        let inbox = self.inbox.lock().unwrap().clone().ok_or(NotStarted)?;
+        let acknowledged = self.gossip_config.read().unwrap().acknowledged_delivery();
+        // the handler is locked after the updates, as by the content handler, so that the updates received meanwhile
+        // are either queued for the same generation or taken here
+        let (generation, updates) = {
+            let mut updates = self.updates.write().unwrap();
+            let generation = self.update_handler.lock().unwrap().as_ref()
+                .map(|registered| registered.generation)
+                .ok_or("No update handler registered")?;
+            (generation, updates.take_undelivered(generation, acknowledged))
+        };
+        let count = updates.len();
+        self.queue_deliveries(&inbox, generation, updates)?;
+        node_log!(Debug, UPDATES, "Queued {} updates for replay", count);
+        Ok(count)
+    }
+
+    /// Returns the handler of the content messages, called from the message handling thread
+    fn content_handler(&self, inbox: InboxSender) -> impl FnMut(ContentMessage) + Send + 'static {
+        let address = self.address.to_string();
+        let updates_arc = Arc::clone(&self.updates);
+        let update_callback_arc = Arc::clone(&self.update_handler);
+        let in_flight_arc = Arc::clone(&self.in_flight);
+        let digests_seen_arc = Arc::clone(&self.digests_seen);
+        let content_requests_arc = Arc::clone(&self.content_requests);
+        let timed_out_arc = Arc::clone(&self.timed_out);
+        let relayed_arc = Arc::clone(&self.relayed);
+        let mismatches_arc = Arc::clone(&self.mismatches);
+        let activity = Arc::clone(&self.activity);
+        let stats_arc = Arc::clone(&self.stats);
+        let errors_arc = Arc::clone(&self.errors);
+        let traffic = Arc::clone(&self.traffic);
+        let quarantine_arc = Arc::clone(&self.quarantine);
+        let peer_sampling_arc = Arc::clone(&self.peer_sampling_service);
+        let gossip_config_arc = Arc::clone(&self.gossip_config);
+        let cluster_id = self.gossip_config.read().unwrap().cluster_id();
+        let network = self.gossip_config.read().unwrap().network().clone();
+        let digester = Arc::clone(self.gossip_config.read().unwrap().digester());
+        let observer = Arc::clone(&self.observer);
+        let published_peers = Arc::clone(&self.published_peers);
+        let pending_deliveries = Arc::clone(&self.pending_deliveries);
+        move |mut message: ContentMessage| {
+            // the violations of the sender are accounted at the IP its connection came from, which verifies the
+            // address it reports when the IP matches
+            let observed = crate::network::observed_sender(message.sender(), message.source());
+            let sender_verified = observed == message.sender();
+            if quarantine_arc.lock().unwrap().is_quarantined(&observed) {
+                node_log!(Debug, NETWORK, "Ignoring content message from quarantined peer {}", message.sender());
+                stats_arc.lock().unwrap().quarantined_messages += 1;
+                return;
+            }
+            // the address reported by the sender, and the one it is answered at
+            let addresses = match message.sender().parse::<SocketAddr>() {
+                Ok(sender_address) => match crate::network::reply_address(sender_address, message.source(), network.reply_address()) {
+                    Some(peer_address) => Some((sender_address, peer_address)),
+                    None => {
+                        node_log!(Warn, NETWORK, "Dropping content message from {} received from {:?}", sender_address, message.source());
+                        stats_arc.lock().unwrap().sender_mismatches += 1;
+                        return;
+                    }
+                },
+                Err(_) => None,
+            };
+
+            match message.message_type() {
+                MessageType::Request => {
+                    if let Some((sender_address, peer_address)) = addresses {
+                        let reply = message.take_reply_stream();
+                        // the exchange initiated with the sender is complete
+                        in_flight_arc.lock().unwrap().remove(&sender_address);
+                        let updates = updates_arc.read().unwrap();
+                        let mut requested_updates = HashMap::new();
+                        let mut sequences = HashMap::new();
+                        let mut origins = HashMap::new();
+                        let mut revocations = HashSet::new();
+                        let mut missing = Vec::new();
+                        for digest in message.take_content().into_keys() {
+                            if let Some(update) = updates.get_update(&digest) {
+                                if let Some(sequence) = update.origin_sequence() {
+                                    sequences.insert(digest.to_owned(), sequence.clone());
+                                }
+                                if let Some(origin) = update.origin() {
+                                    origins.insert(digest.to_owned(), origin.to_owned());
+                                }
+                                if update.is_revocation() {
+                                    revocations.insert(digest.to_owned());
+                                }
+                                requested_updates.insert(digest.to_owned(), update.content().to_vec());
+                            }
+                            else {
+                                missing.push(digest);
+                            }
+                        }
+                        drop(updates);
+                        if !requested_updates.is_empty() {
+                            updates_arc.write().unwrap().record_request(requested_updates.keys(), &peer_address.to_string());
+                        }
+                        // point the requester to the peers that advertised the relayed digests
+                        let mut redirects = HashMap::new();
+                        if !missing.is_empty() && gossip_config_arc.read().unwrap().relay_headers() {
+                            let relayed = relayed_arc.lock().unwrap();
+                            for digest in missing {
+                                if let Some(provider) = relayed.provider(&digest).filter(|provider| *provider != peer_address) {
+                                    redirects.insert(digest, provider.to_string());
+                                }
+                            }
+                        }
+                        if !requested_updates.is_empty() || !redirects.is_empty() {
+                            let delivered: Vec<String> = match gossip_config_arc.read().unwrap().expiration_start() {
+                                ExpirationStart::OnFirstDelivery => requested_updates.keys().cloned().collect(),
+                                _ => Vec::new(),
+                            };
+                            let mut response = ContentMessage::new_response(address.clone(), requested_updates);
+                            response.set_redirects(redirects);
+                            response.set_sequences(sequences);
+                            response.set_origins(origins);
+                            response.set_revocations(revocations);
+                            match traffic.respond(reply.as_ref(), &peer_address, &response, cluster_id, &network) {
+                                Ok(written) => {
+                                    node_log!(Trace, GOSSIP, "Sent content response - {} bytes to {:?}", written, peer_address);
+                                    if !delivered.is_empty() {
+                                        updates_arc.write().unwrap().start_expiration(&delivered);
+                                    }
+                                },
+                                Err(e) => {
+                                    node_log!(Error, GOSSIP, "Error content response: {:?}", e);
+                                    errors_arc.record(ErrorCategory::Send, Some(&peer_address.to_string()), format!("content response: {}", e));
+                                }
+                            }
+                        }
+                    }
+                }
+                MessageType::Response => {
+                    let redirects = message.take_redirects();
+                    if let (false, Some((_, peer_address))) = (redirects.is_empty(), addresses) {
+                        let requests = Self::redirect_requests(&address, peer_address, redirects, &mut content_requests_arc.lock().unwrap());
+                        for (provider, digests) in requests {
+                            node_log!(Debug, GOSSIP, "Requesting {} digests relayed by {} from {}", digests.len(), message.sender(), provider);
+                            stats_arc.lock().unwrap().redirected_content_requests += digests.len() as u64;
+                            let content_request = ContentMessage::new_request(address.clone(), digests);
+                            if let Err(e) = traffic.send(&provider, &content_request, cluster_id, &network) {
+                                node_log!(Error, GOSSIP, "Error sending content request: {:?}", e);
+                                errors_arc.record(ErrorCategory::Send, Some(&provider.to_string()), format!("content request: {}", e));
+                            }
+                        }
+                    }
+                    if !message.is_empty() {
+                        let bytes: usize = message.iter().map(|(_, content)| content.len()).sum();
+                        node_log!(Trace, GOSSIP, "Content response from {} - {} updates, {} bytes", message.sender(), message.content_ref().len(), bytes);
+                        stats_arc.lock().unwrap().content_bytes_received += bytes as u64;
+                        let mut sequences = message.take_sequences();
+                        let mut origins = message.take_origins();
+                        let mut revocations = message.take_revocations();
+                        let (provider, _, content) = message.into_parts();
+                        let (verify, max_mismatches, accept_unrequested) = {
+                            let gossip_config = gossip_config_arc.read().unwrap();
+                            (gossip_config.verify_content_digests(), gossip_config.max_digest_mismatches(), gossip_config.accept_unrequested_content())
+                        };
+                        let mut quarantined_until = None;
+
+                        // keep the new updates that were requested from the sender only, including the requests
+                        // that timed out, with the other peers that advertised them
+                        let sender_address = addresses.map(|(_, peer_address)| peer_address);
+                        let mut fallbacks = HashMap::new();
+                        let mut unrequested = 0;
+                        let mut late = 0;
+                        let received: Vec<(String, Vec<u8>)> = {
+                            let updates = updates_arc.read().unwrap();
+                            let mut content_requests = content_requests_arc.lock().unwrap();
+                            let mut timed_out = timed_out_arc.lock().unwrap();
+                            content.into_iter()
+                                .filter(|(digest, _)| {
+                                    let requested = match (content_requests.get(digest), sender_address) {
+                                        (Some(request), Some(sender_address)) if request.peer == sender_address => true,
+                                        (_, Some(sender_address)) if timed_out.take(digest, sender_address) => {
+                                            late += 1;
+                                            true
+                                        }
+                                        _ => false,
+                                    };
+                                    // a known update may answer a request that was also sent to another peer
+                                    if !requested && !accept_unrequested && updates.is_new(digest) {
+                                        unrequested += 1;
+                                        return false;
+                                    }
+                                    if let Some(request) = content_requests.remove(digest) {
+                                        if let Some(fallback) = request.fallback {
+                                            fallbacks.insert(digest.to_owned(), fallback);
+                                        }
+                                    }
+                                    updates.is_new(digest)
+                                })
+                                .collect()
+                        };
+                        if late > 0 {
+                            node_log!(Debug, UPDATES, "Accepting {} updates requested from {} after the request timed out", late, provider);
+                        }
+                        if unrequested > 0 {
+                            node_log!(Warn, UPDATES, "Ignoring {} updates not requested from {}", unrequested, provider);
+                            stats_arc.lock().unwrap().unrequested_content_ignored += unrequested;
+                            quarantined_until = quarantine_arc.lock().unwrap().record_violation(&observed);
+                        }
+                        let mut poisoned = Vec::new();
+                        let mut retries: HashMap<SocketAddr, HashMap<String, Vec<u8>>> = HashMap::new();
+
+                        // verify the digests before locking the updates, so that the other handlers are not
+                        // blocked while large contents are hashed
+                        let mut verified = Vec::with_capacity(received.len());
+                        for (digest, content) in received {
+                            let sequence = sequences.remove(&digest);
+                            let origin = origins.remove(&digest);
+                            let revocation = revocations.remove(&digest);
+                            if !verify {
+                                let mut update = Update::with_digest(content, digest);
+                                update.set_origin_sequence(sequence);
+                                update.set_origin(origin);
+                                update.set_revocation(revocation);
+                                verified.push(update);
+                                continue;
+                            }
+                            // the digest of a revocation covers its origin, which is therefore verified as well
+                            let update = if revocation {
+                                Update::new_revocation(digester.as_ref(), content, origin)
+                            }
+                            else {
+                                let mut update = Update::new_sequenced(digester.as_ref(), content, sequence);
+                                update.set_origin(origin);
+                                update
+                            };
+                            if digest == *update.digest() {
+                                verified.push(update);
+                            }
+                            else {
+                                node_log!(Warn, UPDATES, "Digests did not match: {} <> {}", digest, update.digest());
+                                stats_arc.lock().unwrap().digest_verification_failures += 1;
+                                quarantined_until = quarantined_until.or(quarantine_arc.lock().unwrap().record_violation(&observed));
+                                let mismatches = mismatches_arc.lock().unwrap().record(&digest, &observed, sender_verified);
+                                if max_mismatches > 0 && mismatches >= max_mismatches {
+                                    node_log!(Warn, UPDATES, "Digest {} is poisoned after mismatches from {} peers", digest, mismatches);
+                                    poisoned.push(digest);
+                                }
+                                else if let Some(fallback) = fallbacks.remove(&digest) {
+                                    // request the content from another peer that advertised it
+                                    content_requests_arc.lock().unwrap().insert(digest.clone(), ContentRequest { peer: fallback, sent: Instant::now(), fallback: None });
+                                    retries.entry(fallback).or_default().insert(digest, vec![]);
+                                }
+                            }
+                        }
+
+                        let mut updates = updates_arc.write().unwrap();
+                        if !poisoned.is_empty() {
+                            let mut mismatches = mismatches_arc.lock().unwrap();
+                            for digest in poisoned {
+                                mismatches.forget(&digest);
+                                updates.mark_removed(digest);
+                                stats_arc.lock().unwrap().poisoned_digests += 1;
+                            }
+                        }
+                        for update in verified {
+                            let digest = update.digest().to_owned();
+                            if !updates.is_new(&digest) {
+                                continue;
+                            }
+                            if updates.take_revoked(&update) {
+                                node_log!(Info, UPDATES, "Update {} was revoked before it was received", digest);
+                                stats_arc.lock().unwrap().revoked_updates += 1;
+                                continue;
+                            }
+                            node_log!(Info, UPDATES, "New update received: {}", digest);
+                            match updates.insert_update_from(update.clone(), Some(provider.clone())) {
+                                Ok(()) => {
+                                    activity.store(true, std::sync::atomic::Ordering::SeqCst);
+                                    relayed_arc.lock().unwrap().remove(&digest);
+                                    if let Some(seen) = digests_seen_arc.lock().unwrap().remove(&digest) {
+                                        stats_arc.lock().unwrap().propagation_histogram.record(seen.elapsed());
+                                    }
+                                    // only the origin of an update revokes it
+                                    let revoked = update.revoked().zip(update.origin())
+                                        .filter(|_| gossip_config_arc.read().unwrap().revocations());
+                                    if let Some((revoked, origin)) = revoked {
+                                        if updates.apply_revocation(revoked, origin) {
+                                            node_log!(Info, UPDATES, "Update {} revoked by {}", revoked, digest);
+                                            stats_arc.lock().unwrap().revoked_updates += 1;
+                                        }
+                                    }
+                                    // insert OK, queue update for the current handler
+                                    let generation = update_callback_arc.lock().unwrap().as_ref().map(|registered| registered.generation);
+                                    if let Some(generation) = generation {
+                                        updates.mark_delivered(&digest, generation);
+                                        let queued = Delivery { generation, update };
+                                        pending_deliveries.add(queued.size());
+                                        if inbox.try_send(Inbound::Delivery(queued)).is_err() {
+                                            node_log!(Debug, UPDATES, "Update delivery stopped");
+                                        }
+                                    }
+                                    else {
+                                        node_log!(Warn, UPDATES, "No update handler found");
+                                    }
+                                },
+                                Err(e) => {
+                                    node_log!(Error, UPDATES, "Could not add update: {:?}", e);
+                                    errors_arc.record(ErrorCategory::Handler, Some(&provider), format!("could not add update: {}", e));
+                                }
+                            }
+                        }
+                        let unpushed = updates.clear_expired();
+                        stats_arc.lock().unwrap().updates_expired_unpushed += unpushed as u64;
+                        let removed = updates.take_removed();
+                        updates.publish();
+                        drop(updates);
+                        report_removed(removed, cluster_size(&gossip_config_arc.read().unwrap(), &published_peers), &stats_arc, &observer);
+                        if let Some(until) = quarantined_until {
+                            peer_sampling_arc.lock().unwrap().quarantine(&observed, until);
+                        }
+                        for (peer_address, digests) in retries {
+                            node_log!(Debug, GOSSIP, "Requesting {} mismatched digests from {}", digests.len(), peer_address);
+                            let content_request = ContentMessage::new_request(address.clone(), digests);
+                            if let Err(e) = traffic.send(&peer_address, &content_request, cluster_id, &network) {
+                                node_log!(Error, GOSSIP, "Error sending content request: {:?}", e);
+                                errors_arc.record(ErrorCategory::Send, Some(&peer_address.to_string()), format!("content request: {}", e));
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn start_network_listener(&mut self, listener: Option<TcpListener>, inbox: InboxSender) -> Result<(), Box<dyn Error>> {
+        let (workers, backlog, limits, cluster_id, queue_timeout, outbound_only, reply_in_stream) = {
+            let gossip_config = self.gossip_config.read().unwrap();
+            (gossip_config.listener_workers(), gossip_config.accept_backlog(), ConnectionLimits::from_config(&gossip_config), gossip_config.cluster_id(),
+             gossip_config.queue_timeout(), gossip_config.outbound_only(), gossip_config.network().reply_in_stream())
+        };
+        let dispatcher = Dispatcher::new(Arc::clone(&self.last_received), inbox, Arc::clone(&self.errors), Arc::clone(&self.queues), Duration::from_millis(queue_timeout))
+            .with_traffic(Arc::clone(&self.traffic))
+            .with_quarantine(Arc::clone(&self.quarantine));
+        let router = match (listener, &self.shared_listener, cluster_id) {
+            // the messages are only received on the connections opened by the node
+            _ if outbound_only => {
+                node_log!(Info, NETWORK, "Outbound-only, no listener started");
+                Arc::new(Router::single(cluster_id, dispatcher))
+            }
+            (Some(listener), _, _) => {
+                self.listener_address.lock().unwrap().replace(listener.local_addr()?);
+                let router = Arc::new(Router::single(cluster_id, dispatcher));
+                let handle = crate::network::listen(listener, Arc::clone(&self.listener_shutdown), workers, backlog, limits, Arc::clone(&router))?;
+                self.listener.lock().unwrap().replace(handle);
+                router
+            }
+            (None, Some(shared_listener), Some(cluster_id)) => {
+                shared_listener.register(cluster_id, dispatcher)?;
+                self.shared_cluster.lock().unwrap().replace(cluster_id);
+                shared_listener.router()
+            }
+            _ => Err("No listener to receive messages")?,
+        };
+        // the responses read on the connections of the node are dispatched as the messages of the listener, by as many
+        // threads as the listener reads connections with
+        if outbound_only || reply_in_stream {
+            self.traffic.set_reply_readers(Some(ReplyReaders::start(router, limits.deadline, workers, backlog)?));
+        }
+        Ok(())
+    }
+
+    /// Returns the gossip round of the service, sending its messages with the given sender
+    fn gossip_round<S>(&self, sender: Arc<S>) -> GossipRound<S> {
+        GossipRound {
+            node_address: self.address.to_string(),
+            peer_sampling: Arc::clone(&self.peer_sampling_service),
+            updates: Arc::clone(&self.updates),
+            header_probes: Arc::clone(&self.header_probes),
+            in_flight: Arc::clone(&self.in_flight),
+            content_requests: Arc::clone(&self.content_requests),
+            timed_out: Arc::clone(&self.timed_out),
+            relayed: Arc::clone(&self.relayed),
+            usefulness: Arc::clone(&self.usefulness),
+            quarantine: Arc::clone(&self.quarantine),
+            published_peers: Arc::clone(&self.published_peers),
+            stats: Arc::clone(&self.stats),
+            errors: Arc::clone(&self.errors),
+            observer: Arc::clone(&self.observer),
+            sender,
+            no_peer_warning: RateLimitedWarning::new(WARNING_INTERVAL),
+        }
+    }
+
+    /// Starts the thread running the rounds of the node, each at its own deadline: the gossip rounds, the keepalive
+    /// pings if enabled, and the rounds registered by the sampler, if any
+    ///
+    /// # Arguments
+    ///
+    /// * `commands` - Sends the commands of the rounds, kept to [send](Self::send_command) those of the gossip rounds
+    /// * `receiver` - Receives the commands of the rounds
+    /// * `sampling` - Rounds of the sampler, with the delay until the first one
+    fn start_timers(&mut self, commands: Sender<(Timer, Command)>, receiver: Receiver<(Timer, Command)>, sampling: Option<(Duration, SamplerRound)>) -> Result<(), Box<dyn Error>> {
+        let mut timers = Timers::new(receiver);
+        let (first, round) = self.gossip_rounds();
+        let gossip_config_arc = Arc::clone(&self.gossip_config);
+        let reschedule = move |period| round_delay(period, gossip_config_arc.read().unwrap().gossip_deviation());
+        timers.add(Timer::Gossip, first, round, Some(Box::new(reschedule)));
+        if let Some((first, round)) = self.keepalive_rounds() {
+            timers.add(Timer::Keepalive, first, round, None);
+        }
+        if let Some((first, mut round)) = sampling {
+            timers.add(Timer::Sampling, first, Box::new(move |_| round()), None);
+        }
+        let initial_sync = Arc::clone(&self.initial_sync);
+        let node = self.address;
+        let handle = std::thread::Builder::new().name(format!("{} - timers", self.address)).spawn(move || {
+            crate::logging::set_node(node);
+            node_log!(Info, GOSSIP, "Timer thread started");
+            timers.run();
+            if initial_sync.state() == SyncState::Pending {
+                node_log!(Info, GOSSIP, "Initial sync ended: {:?}", SyncState::Aborted);
+                initial_sync.set(SyncState::Aborted);
+            }
+            node_log!(Info, GOSSIP, "Timer thread exiting");
+        })?;
+        self.timers.lock().unwrap().replace(handle);
+        self.commands.lock().unwrap().replace(commands);
+        Ok(())
+    }
+
+    /// Returns the gossip rounds, preceded by the rounds of the initial sync if any, with the delay until the first one
+    fn gossip_rounds(&self) -> (Duration, Round) {
+        let gossip_config_arc = Arc::clone(&self.gossip_config);
+        let peer_sampling_arc = Arc::clone(&self.peer_sampling_service);
+        let usefulness_arc = Arc::clone(&self.usefulness);
+        let activity = Arc::clone(&self.activity);
+        let stats_arc = Arc::clone(&self.stats);
+        let errors_arc = Arc::clone(&self.errors);
+        let traffic = Arc::clone(&self.traffic);
+        let observer = Arc::clone(&self.observer);
+        let published_peers = Arc::clone(&self.published_peers);
+        let isolated = Arc::clone(&self.isolated);
+        isolated.store(false, std::sync::atomic::Ordering::SeqCst);
+        // the updates are fetched from the peers of the view at start, before the first round
+        let mut sync = match self.gossip_config.read().unwrap().initial_sync() {
+            SyncPolicy::None => None,
+            SyncPolicy::FromBootstrap { timeout } => {
+                let sync_peers: Vec<SocketAddr> = self.published_peers.load().iter()
+                    .filter_map(|peer| peer.socket_addr().ok())
+                    .collect();
+                node_log!(Info, GOSSIP, "Initial sync from {} peers", sync_peers.len());
+                Some(SyncRounds::new(sync_peers, timeout))
+            }
+        };
+        let initial_sync = Arc::clone(&self.initial_sync);
+        initial_sync.set(if sync.is_some() { SyncState::Pending } else { SyncState::Complete });
+        let mut round = self.gossip_round(Arc::clone(&self.traffic));
+        let mut digests_learned = 0;
+        // rounds since the last activity, and time of the last round
+        let mut idle_rounds = 0;
+        let mut effective_period = gossip_config_arc.read().unwrap().gossip_period();
+        let mut last_round = Instant::now();
+        // whether the last round found no peer
+        let mut without_peer = false;
+        // time of the next attempt to bootstrap again while the node is isolated
+        let mut next_rebootstrap = Instant::now();
+        let first = match sync {
+            Some(_) => Duration::ZERO,
+            None => {
+                let gossip_config = gossip_config_arc.read().unwrap();
+                round_delay(gossip_config.gossip_period(), gossip_config.gossip_deviation())
+            }
+        };
+        let rounds = move |forced: bool| {
+            // read the configuration at each round so that changes apply to the next round
+            let gossip_config = gossip_config_arc.read().unwrap().clone();
+            let woken = Instant::now();
+            let delay = round_delay(gossip_config.gossip_period(), gossip_config.gossip_deviation());
+
+            if let Some(rounds) = sync.as_mut() {
+                let outcome = match rounds.step(&round, &gossip_config) {
+                    Ok(delay) => return delay,
+                    Err(outcome) => outcome,
+                };
+                match outcome {
+                    SyncState::TimedOut => node_log!(Warn, GOSSIP, "Initial sync timed out"),
+                    _ => node_log!(Info, GOSSIP, "Initial sync ended: {:?}", outcome),
+                }
+                initial_sync.set(outcome);
+                sync = None;
+                last_round = Instant::now();
+                next_rebootstrap = Instant::now();
+                return delay;
+            }
+
+            // the node is isolated once the sends to every peer of a non-empty view failed during the window,
+            // and until a message is exchanged with a peer of the view
+            let view = published_peers.load();
+            let failing_since = traffic.failing_since(&view);
+            match gossip_config.isolation_window() {
+                Some(window) if failing_since.is_some_and(|since| since.elapsed() >= Duration::from_millis(window)) => {
+                    let peer_count = view.len();
+                    let rebootstrap_delay = Duration::from_millis(gossip_config.rebootstrap_delay());
+                    if peer_count > 0 && !isolated.swap(true, std::sync::atomic::Ordering::SeqCst) {
+                        node_log!(Warn, GOSSIP, "Network isolated: no peer of the view reached for {} ms", window);
+                        errors_arc.record(ErrorCategory::Send, None, format!("network isolated from the {} peers of the view", peer_count));
+                        stats_arc.lock().unwrap().network_isolations += 1;
+                        next_rebootstrap = woken + rebootstrap_delay;
+                        if let Some(observer) = crate::observer::current(&observer) {
+                            observer.on_network_isolated(peer_count);
+                        }
+                    }
+                    else if isolated.load(std::sync::atomic::Ordering::SeqCst) && woken >= next_rebootstrap {
+                        node_log!(Info, GOSSIP, "Bootstrapping again after the isolation of the node");
+                        stats_arc.lock().unwrap().isolation_rebootstraps += 1;
+                        if let Err(e) = bootstrap_again(&peer_sampling_arc) {
+                            node_log!(Warn, GOSSIP, "Could not bootstrap again: {}", e);
+                        }
+                        next_rebootstrap = woken + rebootstrap_delay;
+                    }
+                }
+                _ => {
+                    if (failing_since.is_none() || gossip_config.isolation_window().is_none())
+                        && isolated.swap(false, std::sync::atomic::Ordering::SeqCst) {
+                        node_log!(Info, GOSSIP, "Network recovered from isolation");
+                        if let Some(observer) = crate::observer::current(&observer) {
+                            observer.on_network_recovered();
+                        }
+                    }
+                }
+            }
+
+            // while idle, rounds are skipped until the stretched period has elapsed, unless forced
+            if activity.swap(false, std::sync::atomic::Ordering::SeqCst) || gossip_config.idle_rounds() == 0 {
+                idle_rounds = 0;
+                effective_period = gossip_config.gossip_period();
+            }
+            else if !forced && last_round.elapsed() < Duration::from_millis(effective_period) {
+                return delay;
+            }
+            // while the view is empty, rounds are skipped until the stretched period has elapsed or a peer appears
+            let empty_view_period = effective_period.saturating_mul(gossip_config.empty_view_multiplier() as u64);
+            if without_peer && !forced && published_peers.load().is_empty() && last_round.elapsed() < Duration::from_millis(empty_view_period) {
+                return delay;
+            }
+            last_round = Instant::now();
+            let mut summary = round.run(&gossip_config);
+            without_peer = summary.peer.is_none();
+
+            if gossip_config.idle_rounds() > 0 {
+                idle_rounds += 1;
+                if idle_rounds > gossip_config.idle_rounds() {
+                    effective_period = effective_period.saturating_mul(gossip_config.idle_multiplier() as u64)
+                        .min(gossip_config.max_idle_period())
+                        .max(gossip_config.gossip_period());
+                }
+            }
+
+            let learned = {
+                let mut stats = stats_arc.lock().unwrap();
+                stats.effective_gossip_period = effective_period;
+                stats.digests_learned
+            };
+            summary.new_digests = learned - digests_learned;
+            digests_learned = learned;
+            usefulness_arc.lock().unwrap().decay(gossip_config.usefulness_decay());
+            if let Some(observer) = crate::observer::current(&observer) {
+                observer.on_gossip_round(summary);
+            }
+            delay
+        };
+        node_log!(Info, GOSSIP, "Gossip rounds started");
+        (first, Box::new(rounds))
+    }
+
+    /// Returns the keepalive rounds, if keepalive probes are enabled, with the delay until the first one: each
+    /// round pings a peer of the view that was not heard from recently, and removes from the view the peers that
+    /// left the pings unanswered
+    fn keepalive_rounds(&self) -> Option<(Duration, Round)> {
+        let (interval, max_missed) = {
+            let gossip_config = self.gossip_config.read().unwrap();
+            (gossip_config.keepalive_interval(), gossip_config.keepalive_max_missed())
+        };
+        if interval == 0 {
+            return None;
+        }
+        let gossip_config_arc = Arc::clone(&self.gossip_config);
+        let node_address = self.address.to_string();
+        let peer_sampling_arc = Arc::clone(&self.peer_sampling_service);
+        let published_peers = Arc::clone(&self.published_peers);
+        let stats_arc = Arc::clone(&self.stats);
+        let traffic = Arc::clone(&self.traffic);
+        let interval = Duration::from_millis(interval);
+        let mut keepalive = Keepalive::new(interval, max_missed);
+        let rounds = move |_forced: bool| {
+            let round = keepalive.round(&published_peers.load(), |peer| traffic.last_seen(peer), Instant::now());
+            for peer in round.unreachable {
+                node_log!(Warn, GOSSIP, "Peer {} left {} pings unanswered, removing it from the view", peer, max_missed);
+                stats_arc.lock().unwrap().unreachable_peers += 1;
+                peer_sampling_arc.lock().unwrap().report_unreachable(&peer);
+            }
+            let Some(peer) = round.probe else {
+                return interval;
+            };
+            match peer.parse::<SocketAddr>() {
+                Ok(peer_address) => {
+                    let (cluster_id, network) = {
+                        let gossip_config = gossip_config_arc.read().unwrap();
+                        (gossip_config.cluster_id(), gossip_config.network().clone())
+                    };
+                    match traffic.send(&peer_address, &PingMessage::new(node_address.clone()), cluster_id, &network) {
+                        Ok(_) => stats_arc.lock().unwrap().keepalive_pings_sent += 1,
+                        Err(e) => node_log!(Debug, GOSSIP, "Could not ping {}: {:?}", peer, e),
+                    }
+                }
+                Err(_) => node_log!(Error, GOSSIP, "Could not parse peer address {}", peer),
+            }
+            // a ping that could not be sent is left unanswered as well
+            keepalive.ping_sent(&peer, Instant::now());
+            interval
+        };
+        node_log!(Info, GOSSIP, "Keepalive rounds started");
+        Some((interval, Box::new(rounds)))
+    }
+
+    /// Submits a message for broadcast by the gossip protocol.
+    ///
+    /// Before the service is started, the message is queued and inserted at start, when its expiration starts, or
+    /// a [NotStarted] error is returned if [queuing](GossipConfig::set_queue_before_start) is disabled.
+    ///
+    /// # Arguments
+    ///
+    /// * `bytes` - Content of the message
+    pub fn submit(&self, bytes: Vec<u8>) -> Result<(), Box<dyn Error>> {
+        let update = self.updates.read().unwrap().create_update(bytes);
+        if self.insert_submitted(update)?.1 {
+            Ok(())
+        }
+        else {
+            Err("Message already active or expired")?
+        }
+    }
+
+    /// Submits a message for broadcast as [submit](Self::submit) does, or restarts the expiration of the update if it
+    /// is already active, for example to keep a heartbeat circulating: its push count or its duration starts over,
+    /// see [UpdateExpirationMode]. An update that expired is refused, unless
+    /// [refreshing expired updates](GossipConfig::set_refresh_expired) is enabled, in which case it is inserted again.
+    /// An update inserted by another thread between the refresh and the insertion is reported as
+    /// [inserted](SubmitOutcome::Inserted), since its expiration has just started.
+    ///
+    /// # Arguments
+    ///
+    /// * `bytes` - Content of the message
+    pub fn submit_or_refresh(&self, bytes: Vec<u8>) -> Result<SubmitOutcome, Box<dyn Error>> {
+        let update = self.updates.read().unwrap().create_update(bytes);
+        let digest = update.digest().to_owned();
+        {
+            let mut updates = self.updates.write().unwrap();
+            if updates.refresh(&digest) {
+                drop(updates);
+                node_log!(Debug, UPDATES, "Refreshed update {}", digest);
+                self.activity.store(true, std::sync::atomic::Ordering::SeqCst);
+                return Ok(SubmitOutcome::Refreshed(digest));
+            }
+            if self.gossip_config.read().unwrap().refresh_expired() && updates.forget_removed(&digest) {
+                node_log!(Debug, UPDATES, "Inserting expired update {} again", digest);
+            }
+        }
+        let (digest, inserted) = self.insert_submitted(update)?;
+        if inserted {
+            return Ok(SubmitOutcome::Inserted(digest));
+        }
+        // decided with the updates locked once, since another thread may insert or expire the update meanwhile
+        let updates = self.updates.read().unwrap();
+        if updates.is_active(&digest) {
+            // inserted concurrently, after the refresh was attempted: its expiration has just started
+            Ok(SubmitOutcome::Inserted(digest))
+        }
+        else if updates.is_expired(&digest) {
+            Err("Message already expired")?
+        }
+        else {
+            // queued until start
+            Ok(SubmitOutcome::Refreshed(digest))
+        }
+    }
+
+    /// Submits a batch of messages for broadcast, as [submit](Self::submit) would submit each of them, but inserting
+    /// them all at once: the updates are locked once, and the batch counts as a single submission for the
+    /// [eager first push](GossipConfig::set_eager_first_push). Duplicates, including messages repeated in the batch,
+    /// do not abort the batch and are reported as such.
+    ///
+    /// # Arguments
+    ///
+    /// * `contents` - Contents of the messages
+    pub fn submit_batch(&self, contents: Vec<Vec<u8>>) -> Result<Vec<BatchItem>, BatchError> {
+        let updates: Vec<Update> = {
+            let updates = self.updates.read().unwrap();
+            contents.into_iter().map(|bytes| updates.create_update(bytes)).collect()
+        };
+        Ok(self.insert_submitted_batch(updates, true)?.into_iter()
+            .map(|(digest, inserted)| if inserted { BatchItem::Inserted(digest) } else { BatchItem::Duplicate(digest) })
+            .collect())
+    }
+
+    /// Revokes an update across the cluster: the update is removed if it is active, and a revocation is submitted,
+    /// which the nodes receiving it apply in turn. Revocations are best effort: a node that misses the revocation
+    /// before it expires keeps the update until it expires as usual. Requires
+    /// [revocations](GossipConfig::set_revocations) to be enabled, and an [origin id](GossipConfig::set_origin_id):
+    /// the other nodes only honour the revocation of an update submitted with the same origin id.
+    ///
+    /// # Arguments
+    ///
+    /// * `digest` - Digest of the update to revoke
+    pub fn revoke(&self, digest: &str) -> Result<(), Box<dyn Error>> {
+        let _node = NodeScope::enter(self.address);
+        let (origin, digester) = {
+            let gossip_config = self.gossip_config.read().unwrap();
+            if !gossip_config.revocations() {
+                Err("Revocations are disabled")?
+            }
+            let Some(origin) = gossip_config.origin_id() else {
+                Err("Revocations require an origin id")?
+            };
+            (origin.to_owned(), Arc::clone(gossip_config.digester()))
+        };
+        if digest.is_empty() || digest.len() > MAX_DIGEST_LENGTH {
+            Err(format!("Invalid digest {:?}", digest))?
+        }
+        {
+            let mut updates = self.updates.write().unwrap();
+            let applied = updates.apply_tombstones(&[digest.to_owned()]);
+            self.stats.lock().unwrap().revoked_updates += applied as u64;
+            updates.publish();
+        }
+        node_log!(Info, UPDATES, "Revoking update {}", digest);
+        // revocations are not numbered, so that ordered delivery does not wait for them
+        let update = Update::new_revocation(digester.as_ref(), digest.as_bytes().to_vec(), Some(origin));
+        let revoked = match self.insert_submitted_batch(vec![update], false) {
+            Ok(mut inserted) => inserted.remove(0).1,
+            Err(e) => return Err(e.into_source()),
+        };
+        if revoked {
+            Ok(())
+        }
+        else {
+            Err("Update already revoked")?
+        }
+    }
+
+    /// Inserts a submitted update, or queues it if the service is not started yet. Returns the digest of the update,
+    /// which covers its number with ordered delivery, and whether the update is new.
+    fn insert_submitted(&self, update: Update) -> Result<(String, bool), Box<dyn Error>> {
+        match self.insert_submitted_batch(vec![update], true) {
+            Ok(mut inserted) => Ok(inserted.remove(0)),
+            Err(e) => Err(e.into_source()),
+        }
+    }
+
+    /// Inserts submitted updates with the updates locked once, or queues them if the service is not started yet.
+    /// Returns the digest of each update and whether it is new. With ordered delivery, the new updates are numbered
+    /// unless `numbered` is false, and the digest of a numbered update covers its number.
+    fn insert_submitted_batch(&self, batch: Vec<Update>, numbered: bool) -> Result<Vec<(String, bool)>, BatchError> {
+        let _node = NodeScope::enter(self.address);
+        let (ordered, origin, digester) = {
+            let gossip_config = self.gossip_config.read().unwrap();
+            (gossip_config.ordered_delivery(), gossip_config.origin_id().map(str::to_owned), Arc::clone(gossip_config.digester()))
+        };
+        // numbered once known to be new, with the queue or the updates locked, so that numbers follow insertions;
+        // the digest of a numbered update covers its number
+        let tag = |update: Update| {
+            let mut update = match ordered && numbered {
+                true => {
+                    let number = self.submitted_numbers.fetch_add(1, std::sync::atomic::Ordering::SeqCst) + 1;
+                    update.into_sequenced(digester.as_ref(), OriginSequence { origin: self.address.to_string(), number })
+                }
+                false => update,
+            };
+            update.set_origin(origin.clone());
+            update
+        };
+        // keep the state locked so that the updates are not queued after the queue is emptied by start
+        let state = self.state.lock().unwrap();
+        if *state == ServiceState::Created {
+            if !self.gossip_config.read().unwrap().queue_before_start() {
+                return Err(BatchError::new(Vec::new(), NotStarted));
+            }
+            let mut queued = self.queued.lock().unwrap();
+            return Ok(batch.into_iter().map(|update| {
+                if queued.iter().any(|queued| queued.digest() == update.digest()) {
+                    return (update.digest().to_owned(), false);
+                }
+                let update = tag(update);
+                let digest = update.digest().to_owned();
+                node_log!(Warn, UPDATES, "Service not started, update {} is queued until start", digest);
+                queued.push(update);
+                (digest, true)
+            }).collect());
+        }
+        drop(state);
+
+        let mut updates = self.updates.write().unwrap();
+        let mut new = Vec::with_capacity(batch.len());
+        let mut inserted = Vec::new();
+        let mut failure = None;
+        for update in batch {
+            if !updates.is_new(update.digest()) {
+                new.push((update.digest().to_owned(), false));
+                continue;
+            }
+            let update = tag(update);
+            node_log!(Info, UPDATES, "New update for submission: {}", update.digest());
+            let digest = update.digest().to_owned();
+            if let Err(e) = updates.insert_update(update) {
+                failure = Some(e.to_string());
+                break;
+            }
+            inserted.push(digest.clone());
+            new.push((digest, true));
+        }
+        if !inserted.is_empty() {
+            updates.publish();
+            drop(updates);
+            self.activity.store(true, std::sync::atomic::Ordering::SeqCst);
+            if self.gossip_config.read().unwrap().eager_first_push() {
+                self.send_command(Command::Submitted);
+            }
+        }
+        match failure {
+            Some(e) => Err(BatchError::new(inserted, e)),
+            None => Ok(new),
+        }
+    }
+
+    /// Submits a large message for broadcast as several chunk updates followed by a manifest update.
+    /// Receivers reassemble the message with a [ChunkedUpdateHandler](crate::ChunkedUpdateHandler). A chunk shared
+    /// with a message submitted earlier is disseminated again, even if its update expired. With ordered delivery,
+    /// only the manifest is numbered, since it lists the chunks by the digests of their contents.
+    ///
+    /// # Arguments
+    ///
+    /// * `bytes` - Content of the message
+    /// * `chunk_size` - Maximum size of each chunk
+    pub fn submit_chunked(&self, bytes: Vec<u8>, chunk_size: usize) -> Result<(), Box<dyn Error>> {
+        let _node = NodeScope::enter(self.address);
+        node_log!(Info, UPDATES, "New chunked update for submission: {} bytes", bytes.len());
+        let digester = Arc::clone(self.gossip_config.read().unwrap().digester());
+        let mut contents = crate::chunk::split_into_chunks_with(digester.as_ref(), &bytes, chunk_size);
+        let manifest = contents.pop();
+        for content in contents {
+            let update = self.updates.read().unwrap().create_update(content);
+            // a chunk shared with an earlier update is disseminated again for the manifest listing it
+            {
+                let mut updates = self.updates.write().unwrap();
+                if !updates.refresh(update.digest()) {
+                    updates.forget_removed(update.digest());
+                }
+            }
+            if let Err(e) = self.insert_submitted_batch(vec![update], false) {
+                return Err(e.into_source());
+            }
+        }
+        if let Some(manifest) = manifest {
+            let update = self.updates.read().unwrap().create_update(manifest);
+            self.insert_submitted(update)?;
         }
+        Ok(())
     }
 
-    /// Create a gossip service with default configurations
+    // for testing
+    pub fn is_active(&self, bytes: Vec<u8>) -> bool {
+        let updates = self.updates.read().unwrap();
+        updates.is_active(updates.create_update(bytes).digest())
+    }
+    pub fn is_expired(&self, bytes: Vec<u8>) -> bool {
+        let updates = self.updates.read().unwrap();
+        updates.is_expired(updates.create_update(bytes).digest())
+    }
+
+    /// Returns the state of the update with the given digest, including the peer from which an active update
+    /// was received, or `None` if the update is unknown
     ///
     /// # Arguments
     ///
-    /// * `address` - Socket address of the node
-    pub fn new_with_defaults(address: SocketAddr) -> Self {
-        Self::new(address, PeerSamplingConfig::default(), GossipConfig::default())
+    /// * `digest` - Digest of the update
+    pub fn update_state(&self, digest: &str) -> Option<UpdateState> {
+        self.updates.read().unwrap().update_state(digest)
     }
 
-    /// Returns the node address
-    pub fn address(&self) -> &SocketAddr {
-        &self.address
+    /// Returns the estimated probability that an active or recently expired update reached every node of the
+    /// cluster, `None` if its counters are not known. The estimate assumes that every node pushed the digest of the
+    /// update as many times as this node did, see [set_cluster_size](GossipConfig::set_cluster_size).
+    ///
+    /// # Arguments
+    ///
+    /// * `digest` - Digest of the update
+    pub fn coverage_estimate(&self, digest: &str) -> Option<f64> {
+        let pushes = self.updates.read().unwrap().update_counters(digest)?.pushes;
+        let cluster_size = cluster_size(&self.gossip_config.read().unwrap(), &self.published_peers);
+        Some(coverage_probability(cluster_size, pushes))
+    }
+
+    /// Returns the dissemination counters of an active update, or of a recently expired one, `None` if they are
+    /// not known. The counters of the most recently expired updates are kept, at most a thousand.
+    ///
+    /// # Arguments
+    ///
+    /// * `digest` - Digest of the update
+    pub fn update_counters(&self, digest: &str) -> Option<UpdateCounters> {
+        self.updates.read().unwrap().update_counters(digest)
     }
 
-    /// Returns a list of the node's peer
-    pub fn peers(&self) -> Vec<Peer> {
-        self.peer_sampling_service.lock().unwrap().peers()
+    /// Returns the dissemination counters of the active updates in insertion order, followed by those of the
+    /// recently expired updates, see [update_counters](Self::update_counters)
+    pub fn update_stats(&self) -> Vec<(String, UpdateCounters)> {
+        self.updates.read().unwrap().all_counters()
     }
 
-    /// Starts the gossip protocol and related threads
+    /// Returns, in reception order, the digests of the active updates whose content was first received from
+    /// the given peer. The peer is the immediate sender, not the node at which the update was submitted.
     ///
     /// # Arguments
     ///
-    /// * `peer_sampling_init` - Closure for retrieving the address of the first peer to contact
-    /// * `update_handler` - Application callback for receiving new updates
-    pub fn start(&mut self, peer_sampling_init: Box<dyn FnOnce() -> Option<Vec<Peer>>>, update_handler: Box<T>) -> Result<(), Box<dyn Error>> {
+    /// * `peer` - Address of the peer
+    pub fn updates_from(&self, peer: &str) -> Vec<String> {
+        self.updates.read().unwrap().updates_from(peer)
+    }
 
-        self.update_handler.lock().unwrap().replace(update_handler);
+    /// Blocks until the service is shut down from another thread, for instance by a signal handler.
+    /// Returns immediately if the service is not running.
+    pub fn wait_for_shutdown(&self) {
+        let state = self.state.lock().unwrap();
+        let _stopped = self.stopped.wait_while(state, |state| matches!(*state, ServiceState::Listening | ServiceState::Running | ServiceState::Stopping)).unwrap();
+    }
+
+    /// Terminates the gossip protocol and related threads.
+    /// If graceful leave is enabled in [PeerSamplingConfig], the peers of the view are notified first.
+    ///
+    /// The timer thread initiating exchanges is stopped first, then the listener, then the thread handling the
+    /// messages already received; the updates and the handler are cleared last. Once the threads are stopped, and
+    /// before the handler is dropped, the handler and the round observer are notified, see
+    /// [UpdateHandler::on_shutdown]: with [ShutdownReason::Failed] if a thread of the service had stopped, and
+    /// [ShutdownReason::Requested] otherwise. Returns a [NotStarted] error if the service was never
+    /// started, and does nothing if it was already shut down.
+    ///
+    /// The service can be shut down from another thread, for instance a signal handler, while it is in use;
+    /// concurrent calls wait for the first one to complete. The handler may still call the service, for instance
+    /// to submit an update, while its thread is being stopped.
+    pub fn shutdown(&self) -> Result<(), Box<dyn Error>> {
+        let _node = NodeScope::enter(self.address);
+        let mut state = self.state.lock().unwrap();
+        match *state {
+            ServiceState::Created => Err(NotStarted)?,
+            ServiceState::Stopped => return Ok(()),
+            ServiceState::Stopping => {
+                let _stopped = self.stopped.wait_while(state, |state| *state == ServiceState::Stopping).unwrap();
+                return Ok(());
+            }
+            ServiceState::Listening | ServiceState::Running => (),
+        }
+        // the state is not kept locked while the threads are joined and the handler notified, as they may call
+        // the service, for instance to submit an update
+        *state = ServiceState::Stopping;
+        drop(state);
+        node_log!(Info, GOSSIP, "Shutdown requested");
+        let reason = match self.stopped_thread() {
+            Some(thread) => {
+                node_log!(Warn, GOSSIP, "Thread {} stopped before shutdown", thread);
+                ShutdownReason::Failed(thread)
+            }
+            None => ShutdownReason::Requested,
+        };
+        self.peer_sampling_service.lock().unwrap().leave();
+
+        let success = self.stop_activities();
 
-        // message receiver for peer sampling messages
-        let (tx_sampling, rx_sampling) = std::sync::mpsc::channel::<PeerSamplingMessage>();
+        // clear state
+        self.drop_handler(reason.clone());
+        if let Some(observer) = crate::observer::current(&self.observer) {
+            observer.on_shutdown(reason);
+        }
         {
-            // start peer sampling
-            self.peer_sampling_service.lock().unwrap().init(peer_sampling_init, rx_sampling);
-        }
-        // message receiver for header messages
-        let (tx_header, rx_header) = std::sync::mpsc::channel::<HeaderMessage>();
-        // message receiver for content messages
-        let (tx_content, rx_content) = std::sync::mpsc::channel::<ContentMessage>();
-
-        // start message header handler
-        self.start_message_header_handler(rx_header).expect("Error starting message header handler");
-        // start message content handler
-        self.start_message_content_handler(rx_content).expect("Error starting message content handler");
-        // start TCP listener
-        self.start_network_listener(tx_sampling, tx_header, tx_content).expect(&format!("Error setting up listener at {:?}", self.address));
-        // start gossiping
-        self.start_gossip_activity().expect("Error starting gossip activity");
-        Ok(())
+            let mut updates = self.updates.write().unwrap();
+            updates.clear();
+            updates.publish();
+        }
+        self.header_probes.lock().unwrap().clear();
+        self.in_flight.lock().unwrap().clear();
+        self.digests_seen.lock().unwrap().clear();
+        self.content_requests.lock().unwrap().clear();
+        self.timed_out.lock().unwrap().clear();
+        self.declined.lock().unwrap().clear();
+        self.mismatches.lock().unwrap().clear();
+        self.relayed.lock().unwrap().clear();
+        self.usefulness.lock().unwrap().clear();
+        self.quarantine.lock().unwrap().clear();
+        *self.state.lock().unwrap() = ServiceState::Stopped;
+        self.stopped.notify_all();
+
+        if success {
+            Ok(())
+        }
+        else {
+            Err("Error occurred during shutdown")?
+        }
     }
+}
 
-    fn start_message_header_handler(&mut self, receiver: Receiver<HeaderMessage>) -> Result<(), Box<dyn Error>> {
-        let gossip_config_arc = Arc::clone(&self.gossip_config);
-        let address = self.address.to_string();
-        let updates_arc = Arc::clone(&self.updates);
-        let handle = std::thread::Builder::new().name(format!("{} - header receiver", address)).spawn(move|| {
-            log::info!("Started message header handling thread");
-            while let Ok(message) = receiver.recv() {
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+    use std::time::Duration;
+    use crate::UpdateExpirationMode;
+    use crate::config::NetworkConfig;
+    use crate::message::{Message, MASK_MESSAGE_PROTOCOL, MESSAGE_PROTOCOL_CONTENT_MESSAGE, MESSAGE_PROTOCOL_HEADER_MESSAGE};
 
-                if let Ok(sender_address) = message.sender().parse::<SocketAddr>() {
+    struct NoopHandler;
+    impl UpdateHandler for NoopHandler {
+        fn on_update(&self, _update: Update) {}
+    }
 
-                    let updates = updates_arc.read().unwrap();
+    type Times = Arc<Mutex<Vec<Instant>>>;
 
-                    // Response with message headers if pull is enabled
-                    if gossip_config_arc.is_pull() && updates.active_count() > 0 && *message.message_type() == MessageType::Request {
-                        let mut response = HeaderMessage::new_response(address.clone());
-                        response.set_headers(updates.active_headers());
-                        match crate::network::send(&sender_address, Box::new(response)) {
-                            Ok(written) => log::trace!("Sent header response - {} bytes to {:?}", written, sender_address),
-                            Err(e) => log::error!("Error sending header response: {:?}", e)
-                        }
-                    }
+    /// Peer answering header requests after a delay; returns the times at which requests were received
+    /// and responses were sent
+    fn start_slow_peer(address: &'static str, delay: Duration) -> (Times, Times) {
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let responses = Arc::new(Mutex::new(Vec::new()));
+        let listener = TcpListener::bind(address).unwrap();
+        let (requests_arc, responses_arc) = (Arc::clone(&requests), Arc::clone(&responses));
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut buffer = Vec::new();
+                if stream.and_then(|mut stream| stream.read_to_end(&mut buffer)).is_err() || buffer.is_empty() {
+                    continue;
+                }
+                if buffer[0] & MASK_MESSAGE_PROTOCOL != MESSAGE_PROTOCOL_HEADER_MESSAGE {
+                    continue;
+                }
+                let request = HeaderMessage::from_bytes(&buffer[1..]).unwrap();
+                requests_arc.lock().unwrap().push(Instant::now());
+                let responses = Arc::clone(&responses_arc);
+                std::thread::spawn(move || {
+                    std::thread::sleep(delay);
+                    responses.lock().unwrap().push(Instant::now());
+                    let _ = crate::network::send(&request.sender().parse().unwrap(), &HeaderMessage::new_response(address.to_owned()), None, &NetworkConfig::default());
+                });
+            }
+        });
+        (requests, responses)
+    }
 
-                    // Process message if (request and push enabled) or (response and pull enabled)
-                    if *message.message_type() == MessageType::Request && gossip_config_arc.is_push() || *message.message_type() == MessageType::Response && gossip_config_arc.is_pull() {
+    #[test]
+    fn one_exchange_in_flight_per_peer() {
+        let (requests, responses) = start_slow_peer("127.0.0.1:9111", Duration::from_millis(300));
+        let mut gossip_config = GossipConfig::new(true, true, 50, UpdateExpirationMode::None);
+        gossip_config.set_in_flight_timeout(5000);
+        let mut service = GossipService::new(
+            "127.0.0.1:9110".parse().unwrap(),
+            PeerSamplingConfig::new(true, true, 100, 10, 1, 4),
+            gossip_config
+        );
+        service.start_with_peers(vec![Peer::try_new("127.0.0.1:9111").unwrap()], Box::new(NoopHandler)).unwrap();
+        service.submit(b"update".to_vec()).unwrap();
+        std::thread::sleep(Duration::from_millis(1500));
+        service.shutdown().unwrap();
 
-                        let mut new_digests = HashMap::new();
-                        message.headers().iter().for_each(|digest| {
-                            if updates.is_new(digest) {
-                                log::debug!("New digest: {}", digest);
-                                new_digests.insert(digest.to_owned(), vec![]);
-                            }
-                            else {
-                                log::trace!("Duplicate digest: {}", digest);
-                            }
-                        });
-                        if new_digests.len() > 0 {
-                            let content_request = ContentMessage::new_request(address.clone(), new_digests);
-                            match crate::network::send(&sender_address, Box::new(content_request)) {
-                                Ok(written) => log::trace!("Sent content request - {} bytes to {:?}", written, sender_address),
-                                Err(e) => log::error!("Error content request response: {:?}", e)
-                            }
+        let requests = requests.lock().unwrap();
+        let responses = responses.lock().unwrap();
+        assert!(requests.len() >= 2 && requests.len() <= 6, "{} requests", requests.len());
+        // each request after the first one follows the response to the previous one
+        for (i, request) in requests.iter().enumerate().skip(1) {
+            assert!(*request > responses[i - 1]);
+        }
+        assert!(service.stats().skipped_header_rounds > 0);
+    }
+
+    /// Peer advertising a single update and dropping the first request for its content; returns the number
+    /// of content requests received
+    fn start_lossy_peer(address: &'static str, content: &'static [u8]) -> Arc<Mutex<usize>> {
+        let content_requests = Arc::new(Mutex::new(0));
+        let listener = TcpListener::bind(address).unwrap();
+        let content_requests_arc = Arc::clone(&content_requests);
+        std::thread::spawn(move || {
+            let digest = Update::new(content.to_vec()).digest().to_owned();
+            for stream in listener.incoming() {
+                let mut buffer = Vec::new();
+                if stream.and_then(|mut stream| stream.read_to_end(&mut buffer)).is_err() || buffer.is_empty() {
+                    continue;
+                }
+                match buffer[0] & MASK_MESSAGE_PROTOCOL {
+                    MESSAGE_PROTOCOL_HEADER_MESSAGE => {
+                        let request = HeaderMessage::from_bytes(&buffer[1..]).unwrap();
+                        let mut response = HeaderMessage::new_response(address.to_owned());
+                        response.set_headers(vec![digest.clone()]);
+                        let _ = crate::network::send(&request.sender().parse().unwrap(), &response, None, &NetworkConfig::default());
+                    }
+                    MESSAGE_PROTOCOL_CONTENT_MESSAGE => {
+                        let request = ContentMessage::from_bytes(&buffer[1..]).unwrap();
+                        let mut content_requests = content_requests_arc.lock().unwrap();
+                        *content_requests += 1;
+                        if *content_requests > 1 {
+                            let response = ContentMessage::new_response(address.to_owned(), HashMap::from([(digest.clone(), content.to_vec())]));
+                            let _ = crate::network::send(&request.sender().parse().unwrap(), &response, None, &NetworkConfig::default());
                         }
                     }
-                }
-                else {
-                    log::error!("Could not parse sender address {}", message.sender());
+                    _ => (),
                 }
             }
-            log::info!("Message header handling thread exiting");
-        }).unwrap();
-        self.activities.push(handle);
-        Ok(())
+        });
+        content_requests
     }
 
-    fn start_message_content_handler(&mut self, receiver: Receiver<ContentMessage>) -> Result<(), Box<dyn Error>> {
-        let address = self.address.to_string();
-        let updates_arc = Arc::clone(&self.updates);
-        let update_callback_arc = Arc::clone(&self.update_handler);
-        let handle = std::thread::Builder::new().name(format!("{} - content receiver", address)).spawn(move|| {
-            log::info!("Started message content handling thread");
-            while let Ok(message) = receiver.recv() {
+    #[test]
+    fn lost_content_response_is_requested_again() {
+        let content_requests = start_lossy_peer("127.0.0.1:9121", b"lost once");
+        let mut gossip_config = GossipConfig::new(false, true, 50, UpdateExpirationMode::None);
+        gossip_config.set_content_request_timeout(300);
+        let mut service = GossipService::new(
+            "127.0.0.1:9120".parse().unwrap(),
+            PeerSamplingConfig::new(true, true, 100, 10, 1, 4),
+            gossip_config
+        );
+        service.start_with_peers(vec![Peer::try_new("127.0.0.1:9121").unwrap()], Box::new(NoopHandler)).unwrap();
 
-                match message.message_type() {
-                    MessageType::Request => {
-                        if let Ok(peer_address) = message.sender().parse::<SocketAddr>() {
-                            let updates = updates_arc.read().unwrap();
-                            let mut requested_updates = HashMap::new();
-                            for (digest, _) in message.content() {
-                                if let Some(update) = updates.get_update(&digest) {
-                                    requested_updates.insert(digest.to_owned(), update.content().to_vec());
-                                }
-                            }
-                            if requested_updates.len() > 0{
-                                let response = ContentMessage::new_response(address.clone(), requested_updates);
-                                match crate::network::send(&peer_address, Box::new(response)) {
-                                    Ok(written) => log::trace!("Sent content response - {} bytes to {:?}", written, peer_address),
-                                    Err(e) => log::error!("Error content response: {:?}", e)
-                                }
-                            }
+        std::thread::sleep(Duration::from_millis(150));
+        // the digest keeps being advertised, but is not requested again before the timeout
+        assert_eq!(1, *content_requests.lock().unwrap());
+        assert_eq!(1, service.stats().pending_content_requests);
+
+        let deadline = Instant::now() + Duration::from_secs(3);
+        while !service.is_active(b"lost once".to_vec()) {
+            assert!(Instant::now() < deadline, "update was not delivered");
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        let stats = service.stats();
+        service.shutdown().unwrap();
+
+        assert_eq!(2, *content_requests.lock().unwrap());
+        assert_eq!(1, stats.content_request_timeouts);
+        assert_eq!(0, stats.pending_content_requests);
+    }
+
+    /// Starts a peer advertising one update in its first header response, and answering each content request
+    /// after the given delay
+    fn start_late_provider(address: &'static str, content: &'static [u8], delay: Duration) {
+        let listener = TcpListener::bind(address).unwrap();
+        std::thread::spawn(move || {
+            let digest = Update::new(content.to_vec()).digest().to_owned();
+            let mut advertised = false;
+            for stream in listener.incoming() {
+                let mut buffer = Vec::new();
+                if stream.and_then(|mut stream| stream.read_to_end(&mut buffer)).is_err() || buffer.is_empty() {
+                    continue;
+                }
+                match buffer[0] & MASK_MESSAGE_PROTOCOL {
+                    MESSAGE_PROTOCOL_HEADER_MESSAGE => {
+                        let request = HeaderMessage::from_bytes(&buffer[1..]).unwrap();
+                        let mut response = HeaderMessage::new_response(address.to_owned());
+                        if !advertised {
+                            response.set_headers(vec![digest.clone()]);
+                            advertised = true;
                         }
+                        let _ = crate::network::send(&request.sender().parse().unwrap(), &response, None, &NetworkConfig::default());
                     }
-                    MessageType::Response => {
-                        if message.len() > 0 {
-                            let mut updates = updates_arc.write().unwrap();
-                            for (digest, content) in message.content() {
-                                if updates.is_new(&digest) {
-                                    let update = Update::new(content.clone());
-                                    if digest == *update.digest() {
-                                        log::info!("New update received: {}", update.digest());
-                                        match updates.insert_update(update) {
-                                            Ok(()) => {
-                                                // insert OK, notify update handler
-                                                let mutex = update_callback_arc.lock().unwrap();
-                                                if let Some(callback) = mutex.as_ref() {
-                                                    let update = Update::new(content);
-                                                    callback.on_update(update);
-                                                }
-                                                else {
-                                                    log::warn!("No update handler found");
-                                                }
-                                            },
-                                            Err(e) => log::error!("Could not add update: {:?}", e),
-                                        }
-                                    }
-                                    else {
-                                        log::warn!("Digests did not match: {} <> {}", digest, update.digest());
-                                    }
-                                }
-                            }
-                            updates.clear_expired();
-                        }
+                    MESSAGE_PROTOCOL_CONTENT_MESSAGE => {
+                        let request = ContentMessage::from_bytes(&buffer[1..]).unwrap();
+                        let response = ContentMessage::new_response(address.to_owned(), HashMap::from([(digest.clone(), content.to_vec())]));
+                        std::thread::spawn(move || {
+                            std::thread::sleep(delay);
+                            let _ = crate::network::send(&request.sender().parse().unwrap(), &response, None, &NetworkConfig::default());
+                        });
                     }
+                    _ => (),
                 }
             }
-        }).unwrap();
-        self.activities.push(handle);
-        Ok(())
+        });
     }
 
-    fn start_network_listener(&mut self, peer_sampling_sender: Sender<PeerSamplingMessage>, header_sender: Sender<HeaderMessage>, content_sender: Sender<ContentMessage>) -> Result<(), Box<dyn Error>> {
-        let handle = crate::network::listen(self.address(), Arc::clone(&self.shutdown), peer_sampling_sender, header_sender, content_sender)?;
-        self.activities.push(handle);
-        Ok(())
+    #[test]
+    fn late_content_response_is_accepted() {
+        start_late_provider("127.0.0.1:9193", b"late", Duration::from_millis(400));
+        let mut gossip_config = GossipConfig::new(false, true, 50, UpdateExpirationMode::None);
+        gossip_config.set_content_request_timeout(100);
+        gossip_config.set_quarantine(1, 60_000);
+        let mut service = GossipService::new(
+            "127.0.0.1:9192".parse().unwrap(),
+            PeerSamplingConfig::new(true, true, 100, 10, 1, 4),
+            gossip_config
+        );
+        service.start_with_peers(vec![Peer::try_new("127.0.0.1:9193").unwrap()], Box::new(NoopHandler)).unwrap();
+
+        let deadline = Instant::now() + Duration::from_secs(3);
+        while !service.is_active(b"late".to_vec()) {
+            assert!(Instant::now() < deadline, "update was not delivered");
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        let stats = service.stats();
+        let memory = service.memory_report();
+        service.shutdown().unwrap();
+
+        // the response arrived after the timeout, but the peer was asked for the digest
+        assert_eq!(1, stats.content_request_timeouts);
+        assert_eq!(0, stats.unrequested_content_ignored);
+        assert_eq!(0, memory.quarantine.count);
     }
 
-    fn start_gossip_activity(&mut self) -> Result<(), Box<dyn Error>> {
-        let gossip_config_arc = Arc::clone(&self.gossip_config);
-        let node_address = self.address.to_string();
-        let shutdown_requested = Arc::clone(&self.shutdown);
-        let peer_sampling_arc = Arc::clone(&self.peer_sampling_service);
-        let updates_arc = Arc::clone(&self.updates);
-        let handle = std::thread::Builder::new().name(format!("{} - gossip activity", self.address().to_string())).spawn(move ||{
-            log::info!("Gossip thread started");
-            loop {
-                if shutdown_requested.load(std::sync::atomic::Ordering::SeqCst) {
-                    break;
-                }
-
-                let deviation =
-                    if gossip_config_arc.gossip_deviation() == 0 { 0 }
-                    else { rand::thread_rng().gen_range(0, gossip_config_arc.gossip_deviation()) };
-                let sleep = gossip_config_arc.gossip_period() + deviation;
-                std::thread::sleep(std::time::Duration::from_millis(sleep));
-
-                let mut peer_sampling_service = peer_sampling_arc.lock().unwrap();
-                if let Some(peer) = peer_sampling_service.get_peer() {
-                    if let Ok(peer_address) = peer.address().parse::<SocketAddr>() {
-                        drop(peer_sampling_service);
-                        let mut message = HeaderMessage::new_request(node_address.to_string());
-                        if gossip_config_arc.is_push() {
-                            // send active headers
-                            let mut updates = updates_arc.write().unwrap();
-
-                            if updates.active_count() > 0 {
-                                let active_headers = updates.active_headers_for_push();
-                                message.set_headers(active_headers);
-                                updates.clear_expired();
-                            }
-                        }
-                        else {
-                            // will send empty headers to trigger response
-                        }
+    #[test]
+    fn peer_sending_mismatched_content_is_quarantined() {
+        let sender = "127.0.0.1:9131";
+        let mut gossip_config = GossipConfig::new(true, true, 100, UpdateExpirationMode::None);
+        gossip_config.set_quarantine(3, 500);
+        // the contents are sent without request
+        gossip_config.set_accept_unrequested_content(true);
+        let mut service = GossipService::new(
+            "127.0.0.1:9130".parse().unwrap(),
+            PeerSamplingConfig::new(true, true, 100, 10, 1, 4),
+            gossip_config
+        );
+        service.start_with_peers(vec![Peer::try_new(sender).unwrap()], Box::new(NoopHandler)).unwrap();
+        let send_content = |digest: String, content: &[u8]| {
+            let response = ContentMessage::new_response(sender.to_owned(), HashMap::from([(digest, content.to_vec())]));
+            crate::network::send(&"127.0.0.1:9130".parse().unwrap(), &response, None, &NetworkConfig::default()).unwrap();
+            std::thread::sleep(Duration::from_millis(50));
+        };
+
+        for i in 0..3 {
+            send_content(format!("forged digest {}", i), b"forged content");
+        }
+        assert_eq!(vec![sender.to_owned()], service.quarantined_peers());
+        assert_eq!(3, service.stats().digest_verification_failures);
+        assert!(service.peers().iter().all(|peer| peer.address() != sender));
 
-                        log::debug!("Will send header request with {:?}", message.headers());
+        // valid content is ignored until the end of the quarantine
+        let valid = Update::new(b"valid content".to_vec());
+        send_content(valid.digest().to_owned(), valid.content());
+        assert!(!service.is_active(valid.content().to_vec()));
+        assert!(service.stats().quarantined_messages > 0);
 
-                        // TODO: check expiration after sending
-                        match crate::network::send(&peer_address, Box::new(message)) {
-                            Ok(written) => log::trace!("Sent header request - {} bytes to {:?}", written, peer_address),
-                            Err(e) => log::error!("Error sending header request: {:?}", e)
-                        }
-                    }
+        std::thread::sleep(Duration::from_millis(500));
+        assert!(service.quarantined_peers().is_empty());
+        send_content(valid.digest().to_owned(), valid.content());
+        assert!(service.is_active(valid.content().to_vec()));
+
+        service.shutdown().unwrap();
+    }
+
+    #[test]
+    fn pull_response_headers_are_limited_to_what_a_message_carries() {
+        let mut service: GossipService<NoopHandler> = GossipService::new(
+            "127.0.0.1:9194".parse().unwrap(),
+            PeerSamplingConfig::new(true, true, 100, 10, 1, 4),
+            GossipConfig::new(false, true, 60_000, UpdateExpirationMode::None)
+        );
+        service.start_isolated(Box::new(NoopHandler)).unwrap();
+        service.submit_batch((0..=MAX_MESSAGE_DIGESTS).map(|i| i.to_string().into_bytes()).collect()).unwrap();
+
+        let requester = TcpListener::bind("127.0.0.1:9195").unwrap();
+        let request = HeaderMessage::new_request("127.0.0.1:9195".to_owned(), true);
+        crate::network::send(&"127.0.0.1:9194".parse().unwrap(), &request, None, &NetworkConfig::default()).unwrap();
+        let mut buffer = Vec::new();
+        requester.incoming().next().unwrap().unwrap().read_to_end(&mut buffer).unwrap();
+        service.shutdown().unwrap();
+
+        let response = HeaderMessage::from_bytes(&buffer[1..]).unwrap();
+        assert_eq!(MAX_MESSAGE_DIGESTS, response.headers().iter().collect::<std::collections::HashSet<_>>().len());
+    }
+
+    #[test]
+    fn received_tombstones_are_limited_and_verified() {
+        let mut gossip_config = GossipConfig::new(true, true, 100, UpdateExpirationMode::None);
+        gossip_config.set_tombstones(2, 60_000);
+        let mut service: GossipService<NoopHandler> = GossipService::new(
+            "127.0.0.1:9159".parse().unwrap(),
+            PeerSamplingConfig::new(true, true, 100, 10, 1, 4),
+            gossip_config
+        );
+        service.start_isolated(Box::new(NoopHandler)).unwrap();
+        let send_tombstones = |sender: &str, tombstones: &[&str]| {
+            let mut request = HeaderMessage::new_request(sender.to_owned(), false);
+            request.set_tombstones(tombstones.iter().map(|digest| digest.to_string()).collect());
+            crate::network::send(&"127.0.0.1:9159".parse().unwrap(), &request, None, &NetworkConfig::default()).unwrap();
+        };
+
+        // a node reporting the address of a peer of another host
+        send_tombstones("192.0.2.1:9169", &["forged"]);
+        send_tombstones("127.0.0.1:9169", &["first", "second", "third"]);
+        let deadline = Instant::now() + Duration::from_secs(3);
+        while service.stats().tombstoned_updates < 2 {
+            assert!(Instant::now() < deadline, "tombstones were not applied");
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        let states: Vec<_> = ["forged", "first", "second", "third"].iter().map(|digest| service.update_state(digest)).collect();
+        service.shutdown().unwrap();
+
+        assert_eq!(vec![None, Some(UpdateState::Expired), Some(UpdateState::Expired), None], states);
+    }
+
+    #[test]
+    fn forged_sender_is_quarantined_at_its_own_ip() {
+        // a peer of another host, which the forging node impersonates
+        let impersonated = "192.0.2.1:9133";
+        let mut gossip_config = GossipConfig::new(true, true, 100, UpdateExpirationMode::None);
+        gossip_config.set_quarantine(3, 60_000);
+        gossip_config.set_accept_unrequested_content(true);
+        let mut service = GossipService::new(
+            "127.0.0.1:9132".parse().unwrap(),
+            PeerSamplingConfig::new(true, true, 100, 10, 1, 4),
+            gossip_config
+        );
+        service.start_isolated(Box::new(NoopHandler)).unwrap();
+
+        for i in 0..3 {
+            let response = ContentMessage::new_response(impersonated.to_owned(), HashMap::from([(format!("forged digest {}", i), b"forged content".to_vec())]));
+            crate::network::send(&"127.0.0.1:9132".parse().unwrap(), &response, None, &NetworkConfig::default()).unwrap();
+        }
+        let deadline = Instant::now() + Duration::from_secs(3);
+        while service.stats().digest_verification_failures < 3 {
+            assert!(Instant::now() < deadline, "forged content was not received");
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        let quarantined = service.quarantined_peers();
+        service.shutdown().unwrap();
+
+        // the violations are accounted at the IP the forged messages came from
+        assert_eq!(vec!["127.0.0.1:9133".to_owned()], quarantined);
+    }
+
+    /// Peer advertising a single update and sending corrupted content for it; returns the number of content
+    /// requests received
+    fn start_corrupting_peer(address: &'static str, content: &'static [u8]) -> Arc<Mutex<usize>> {
+        let content_requests = Arc::new(Mutex::new(0));
+        let listener = TcpListener::bind(address).unwrap();
+        let content_requests_arc = Arc::clone(&content_requests);
+        std::thread::spawn(move || {
+            let digest = Update::new(content.to_vec()).digest().to_owned();
+            for stream in listener.incoming() {
+                let mut buffer = Vec::new();
+                if stream.and_then(|mut stream| stream.read_to_end(&mut buffer)).is_err() || buffer.is_empty() {
+                    continue;
                 }
-                else {
-                    log::warn!("No peer found for gossiping");
+                match buffer[0] & MASK_MESSAGE_PROTOCOL {
+                    MESSAGE_PROTOCOL_HEADER_MESSAGE => {
+                        let request = HeaderMessage::from_bytes(&buffer[1..]).unwrap();
+                        let mut response = HeaderMessage::new_response(address.to_owned());
+                        response.set_headers(vec![digest.clone()]);
+                        let _ = crate::network::send(&request.sender().parse().unwrap(), &response, None, &NetworkConfig::default());
+                    }
+                    MESSAGE_PROTOCOL_CONTENT_MESSAGE => {
+                        let request = ContentMessage::from_bytes(&buffer[1..]).unwrap();
+                        *content_requests_arc.lock().unwrap() += 1;
+                        let corrupted = [content, b" corrupted"].concat();
+                        let response = ContentMessage::new_response(address.to_owned(), HashMap::from([(digest.clone(), corrupted)]));
+                        let _ = crate::network::send(&request.sender().parse().unwrap(), &response, None, &NetworkConfig::default());
+                    }
+                    _ => (),
                 }
             }
-            log::info!("Gossip thread exiting");
-        }).unwrap();
+        });
+        content_requests
+    }
+
+    fn start_pulling_node(address: &str, contacts: &[&str], max_mismatches: u32, cooldown: u64) -> GossipService<NoopHandler> {
+        let mut gossip_config = GossipConfig::new(false, true, 50, UpdateExpirationMode::None);
+        gossip_config.set_mismatch_handling(max_mismatches, cooldown);
+        let mut service = GossipService::new(
+            address.parse().unwrap(),
+            PeerSamplingConfig::new(true, true, 100, 10, 1, 4),
+            gossip_config
+        );
+        service.start_with_peers(contacts.iter().map(|contact| Peer::try_new(contact).unwrap()).collect(), Box::new(NoopHandler)).unwrap();
+        service
+    }
 
-        self.activities.push(handle);
+    #[test]
+    fn mismatched_content_is_fetched_from_another_peer() {
+        let content_requests = start_corrupting_peer("127.0.0.1:9152", b"intact");
+        let service = start_pulling_node("127.0.0.1:9150", &["127.0.0.1:9152"], 5, 60_000);
 
-        Ok(())
+        let deadline = Instant::now() + Duration::from_secs(3);
+        while service.stats().digest_verification_failures == 0 {
+            assert!(Instant::now() < deadline, "corrupted content was not received");
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        // the digest keeps being advertised, but is not requested again from the same peer
+        std::thread::sleep(Duration::from_millis(300));
+        assert_eq!(1, *content_requests.lock().unwrap());
+        assert!(!service.is_active(b"intact".to_vec()));
+
+        // another peer advertising the update joins
+        let mut honest = GossipService::new(
+            "127.0.0.1:9151".parse().unwrap(),
+            PeerSamplingConfig::new(true, true, 100, 10, 1, 4),
+            GossipConfig::new(false, true, 50, UpdateExpirationMode::None)
+        );
+        honest.submit(b"intact".to_vec()).unwrap();
+        honest.start_with_peers(vec![Peer::try_new("127.0.0.1:9150").unwrap()], Box::new(NoopHandler)).unwrap();
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !service.is_active(b"intact".to_vec()) {
+            assert!(Instant::now() < deadline, "update was not fetched from the other peer");
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        let stats = service.stats();
+        service.shutdown().unwrap();
+        honest.shutdown().unwrap();
+
+        assert_eq!(1, stats.digest_verification_failures);
+        assert_eq!(0, stats.poisoned_digests);
+        assert_eq!(1, *content_requests.lock().unwrap());
     }
 
-    /// Submits a message for broadcast by the gossip protocol
-    ///
-    /// # Arguments
-    ///
-    /// * `bytes` - Content of the message
-    pub fn submit(&self, bytes: Vec<u8>) -> Result<(), Box<dyn Error>> {
-        let update = Update::new(bytes);
-        let mut updates = self.updates.write().unwrap();
-        if updates.is_new(update.digest()) {
-            log::info!("New update for submission: {}", update.digest());
-            updates.insert_update(update)?;
-            Ok(())
+    #[test]
+    fn digest_mismatched_by_several_peers_is_poisoned() {
+        let first_requests = start_corrupting_peer("127.0.0.1:9155", b"poisoned");
+        let second_requests = start_corrupting_peer("127.0.0.1:9156", b"poisoned");
+        let service = start_pulling_node("127.0.0.1:9154", &["127.0.0.1:9155", "127.0.0.1:9156"], 2, 60_000);
+        let digest = Update::new(b"poisoned".to_vec()).digest().to_owned();
+
+        let deadline = Instant::now() + Duration::from_secs(3);
+        while service.stats().poisoned_digests == 0 {
+            assert!(Instant::now() < deadline, "digest was not poisoned");
+            std::thread::sleep(Duration::from_millis(10));
         }
-        else {
-            Err("Message already active or expired")?
+        // a poisoned digest is treated as expired and not requested anymore
+        std::thread::sleep(Duration::from_millis(300));
+        let stats = service.stats();
+        assert_eq!(Some(UpdateState::Expired), service.update_state(&digest));
+        service.shutdown().unwrap();
+
+        assert_eq!(2, stats.digest_verification_failures);
+        assert_eq!(1, stats.poisoned_digests);
+        assert_eq!(1, *first_requests.lock().unwrap());
+        assert_eq!(1, *second_requests.lock().unwrap());
+    }
+
+    #[test]
+    fn digest_mismatched_by_a_single_peer_is_not_poisoned() {
+        let content_requests = start_corrupting_peer("127.0.0.1:9158", b"retried");
+        let service = start_pulling_node("127.0.0.1:9157", &["127.0.0.1:9158"], 2, 100);
+
+        // the digest is requested again from the peer after each cooldown
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while service.stats().digest_verification_failures < 3 {
+            assert!(Instant::now() < deadline, "corrupted content was not requested again");
+            std::thread::sleep(Duration::from_millis(10));
         }
+        let stats = service.stats();
+        service.shutdown().unwrap();
+
+        assert_eq!(0, stats.poisoned_digests);
+        assert!(*content_requests.lock().unwrap() >= 3);
     }
 
-    // for testing
-    pub fn is_active(&self, bytes: Vec<u8>) -> bool {
-        self.updates.read().unwrap().is_active(Update::new(bytes).digest())
+    #[test]
+    fn no_message_is_dispatched_after_shutdown() {
+        let mut service: GossipService<NoopHandler> = GossipService::new(
+            "127.0.0.1:9140".parse().unwrap(),
+            PeerSamplingConfig::new(true, true, 100, 10, 1, 4),
+            GossipConfig::new(true, true, 100, UpdateExpirationMode::None)
+        );
+        service.start_isolated(Box::new(NoopHandler)).unwrap();
+
+        // connect to the node in a tight loop while it is shut down
+        let stop = Arc::new(AtomicBool::new(false));
+        let senders: Vec<_> = (0..4).map(|_| {
+            let stop = Arc::clone(&stop);
+            std::thread::spawn(move || {
+                while !stop.load(std::sync::atomic::Ordering::SeqCst) {
+                    let request = HeaderMessage::new_request("127.0.0.1:9141".to_owned(), false);
+                    let _ = crate::network::send(&"127.0.0.1:9140".parse().unwrap(), &request, None, &NetworkConfig::default());
+                }
+            })
+        }).collect();
+        std::thread::sleep(Duration::from_millis(100));
+        service.shutdown().unwrap();
+        let last_received = *service.last_received.lock().unwrap();
+        std::thread::sleep(Duration::from_millis(100));
+        stop.store(true, std::sync::atomic::Ordering::SeqCst);
+        for sender in senders {
+            sender.join().unwrap();
+        }
+
+        assert!(last_received.is_some());
+        assert_eq!(last_received, *service.last_received.lock().unwrap());
     }
-    pub fn is_expired(&self, bytes: Vec<u8>) -> bool {
-        self.updates.read().unwrap().is_expired(Update::new(bytes).digest())
+
+    #[test]
+    fn responses_follow_reply_address_policy() {
+        use crate::config::ReplyAddress;
+        // the requester connects from 127.0.0.1 but reports an address on 127.0.0.2
+        let cases = [(9160, ReplyAddress::SelfReported, Some("127.0.0.2")), (9162, ReplyAddress::ObservedIp, Some("127.0.0.1")), (9164, ReplyAddress::RejectMismatch, None)];
+        for (port, policy, expected) in cases {
+            let listeners: Vec<TcpListener> = ["127.0.0.1", "127.0.0.2"].iter()
+                .map(|ip| TcpListener::bind((*ip, port + 1)).unwrap())
+                .collect();
+            let address: SocketAddr = format!("127.0.0.1:{}", port).parse().unwrap();
+            let mut network = NetworkConfig::default();
+            network.set_reply_address(policy);
+            let mut gossip_config = GossipConfig::new(true, true, 100, UpdateExpirationMode::None);
+            gossip_config.set_network(network);
+            let mut service = GossipService::new(address, PeerSamplingConfig::static_view(Vec::new()), gossip_config);
+            service.start_isolated(Box::new(NoopHandler)).unwrap();
+
+            let request = HeaderMessage::new_request(format!("127.0.0.2:{}", port + 1), true);
+            crate::network::send(&address, &request, None, &NetworkConfig::default()).unwrap();
+            std::thread::sleep(Duration::from_millis(100));
+
+            for listener in &listeners {
+                listener.set_nonblocking(true).unwrap();
+                let answered = listener.accept().is_ok();
+                let ip = listener.local_addr().unwrap().ip().to_string();
+                assert_eq!(expected == Some(ip.as_str()), answered, "{:?} on {}", policy, ip);
+            }
+            assert_eq!(expected.is_none() as u64, service.stats().sender_mismatches);
+            service.shutdown().unwrap();
+        }
     }
 
-    /// Terminates the gossip protocol and related threads
-    pub fn shutdown(&mut self) -> Result<(), Box<dyn Error>> {
-        self.update_handler.lock().unwrap().take();
-        self.shutdown.store(true, std::sync::atomic::Ordering::SeqCst);
-        log::info!("Shutdown requested");
-        if let Ok(_) = crate::network::send(self.address(), Box::new(NoopMessage)) {
-            // shutdown request sent
+    #[test]
+    fn node_learns_its_observed_ip() {
+        let mut responder = GossipService::new(
+            "127.0.0.1:9170".parse().unwrap(),
+            PeerSamplingConfig::static_view(Vec::new()),
+            GossipConfig::new(true, true, 100, UpdateExpirationMode::None)
+        );
+        responder.start_isolated(Box::new(NoopHandler)).unwrap();
+        let mut node = GossipService::new(
+            "127.0.0.1:9171".parse().unwrap(),
+            PeerSamplingConfig::static_view(vec![Peer::try_new("127.0.0.1:9170").unwrap()]),
+            GossipConfig::new(true, true, 100, UpdateExpirationMode::None)
+        );
+        assert!(node.observed_ip().is_none());
+        node.start_isolated(Box::new(NoopHandler)).unwrap();
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while node.observed_ip().is_none() {
+            assert!(Instant::now() < deadline, "no probe was answered");
+            std::thread::sleep(Duration::from_millis(10));
         }
-        let mut error = false;
-        self.activities.drain(..).for_each(move|handle| {
-            if let Err(e) = handle.join() {
-                log::error!("Error during thread join: {:?}", e);
-                error = true;
+        assert_eq!(Some("127.0.0.1".parse().unwrap()), node.observed_ip());
+
+        node.shutdown().unwrap();
+        responder.shutdown().unwrap();
+    }
+
+    /// Peer advertising updates in its first header response, so that they are requested once, and answering each
+    /// content request with the requested contents and `extra` updates that were not requested
+    fn start_overfilling_peer(address: &'static str, contents: &'static [&'static [u8]], extra: usize) {
+        let listener = TcpListener::bind(address).unwrap();
+        std::thread::spawn(move || {
+            let advertised: HashMap<String, Vec<u8>> = contents.iter()
+                .map(|content| (Update::new(content.to_vec()).digest().to_owned(), content.to_vec()))
+                .collect();
+            let mut advertising = true;
+            for stream in listener.incoming() {
+                let mut buffer = Vec::new();
+                if stream.and_then(|mut stream| stream.read_to_end(&mut buffer)).is_err() || buffer.is_empty() {
+                    continue;
+                }
+                match buffer[0] & MASK_MESSAGE_PROTOCOL {
+                    MESSAGE_PROTOCOL_HEADER_MESSAGE => {
+                        let request = HeaderMessage::from_bytes(&buffer[1..]).unwrap();
+                        let mut response = HeaderMessage::new_response(address.to_owned());
+                        if advertising {
+                            response.set_headers(advertised.keys().cloned().collect());
+                            advertising = false;
+                        }
+                        let _ = crate::network::send(&request.sender().parse().unwrap(), &response, None, &NetworkConfig::default());
+                    }
+                    MESSAGE_PROTOCOL_CONTENT_MESSAGE => {
+                        let request = ContentMessage::from_bytes(&buffer[1..]).unwrap();
+                        let mut content: HashMap<String, Vec<u8>> = request.content_ref().keys()
+                            .filter_map(|digest| advertised.get(digest).map(|content| (digest.to_owned(), content.clone())))
+                            .collect();
+                        for i in 0..extra {
+                            let update = Update::new(format!("unrequested {}", i).into_bytes());
+                            content.insert(update.digest().to_owned(), update.content().to_vec());
+                        }
+                        let response = ContentMessage::new_response(address.to_owned(), content);
+                        let _ = crate::network::send(&request.sender().parse().unwrap(), &response, None, &NetworkConfig::default());
+                    }
+                    _ => (),
+                }
             }
         });
-        log::info!("All thread terminated");
+    }
 
-        // terminate peer sampling
-        self.peer_sampling_service.lock().unwrap().shutdown()?;
+    #[test]
+    fn unrequested_content_is_ignored() {
+        start_overfilling_peer("127.0.0.1:9191", &[b"first", b"second", b"third"], 3000);
+        let service = start_pulling_node("127.0.0.1:9190", &["127.0.0.1:9191"], 5, 60_000);
 
-        // clear updates
-        self.updates.write().unwrap().clear();
+        let deadline = Instant::now() + Duration::from_secs(3);
+        while service.stats().unrequested_content_ignored == 0 {
+            assert!(Instant::now() < deadline, "content was not received");
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        std::thread::sleep(Duration::from_millis(100));
+        let stats = service.stats();
+        assert!(service.is_active(b"first".to_vec()));
+        assert!(service.is_active(b"second".to_vec()));
+        assert!(service.is_active(b"third".to_vec()));
+        assert!(!service.is_active(b"unrequested 0".to_vec()));
+        assert_eq!(3, service.active_digests().len());
+        assert_eq!(3000, stats.unrequested_content_ignored);
+        assert_eq!(0, stats.pending_content_requests);
+        service.shutdown().unwrap();
+    }
 
-        if error {
-            Err("Error occurred during shutdown")?
+    /// Records the numbers of the delivered updates, and the gaps as negative numbers
+    struct SequenceRecorder(Arc<Mutex<Vec<i64>>>);
+    impl UpdateHandler for SequenceRecorder {
+        fn on_update(&self, update: Update) {
+            self.0.lock().unwrap().push(update.origin_sequence().map_or(0, |sequence| sequence.number as i64));
         }
-        else {
-            Ok(())
+        fn on_sequence_gap(&self, _origin: &str, skipped: std::ops::Range<u64>) {
+            self.0.lock().unwrap().extend(skipped.map(|number| -(number as i64)));
         }
     }
-}
 
+    #[test]
+    fn updates_are_delivered_in_submission_order() {
+        let address: SocketAddr = "127.0.0.1:9180".parse().unwrap();
+        let mut gossip_config = GossipConfig::new(true, true, 100, UpdateExpirationMode::None);
+        gossip_config.set_ordered_delivery(true, 300);
+        // the contents are sent without request
+        gossip_config.set_accept_unrequested_content(true);
+        let mut service = GossipService::new(address, PeerSamplingConfig::static_view(Vec::new()), gossip_config);
+        let delivered = Arc::new(Mutex::new(Vec::new()));
+        service.start_isolated(Box::new(SequenceRecorder(Arc::clone(&delivered)))).unwrap();
+
+        // the contents arrive out of order, and the fifth never does
+        let send_content = |number: u64| {
+            let sequence = (number > 0).then(|| OriginSequence { origin: "127.0.0.1:9182".to_owned(), number });
+            let update = Update::new_sequenced(&crate::digest::Blake3Digester, format!("update {}", number).into_bytes(), sequence.clone());
+            let mut response = ContentMessage::new_response("127.0.0.1:9181".to_owned(), HashMap::from([(update.digest().to_owned(), update.content().to_vec())]));
+            if let Some(sequence) = sequence {
+                response.set_sequences(HashMap::from([(update.digest().to_owned(), sequence)]));
+            }
+            crate::network::send(&address, &response, None, &NetworkConfig::default()).unwrap();
+            std::thread::sleep(Duration::from_millis(20));
+        };
+        for number in [3, 1, 0, 2, 7, 4, 6] {
+            send_content(number);
+        }
+        // updates without number are not held back
+        assert_eq!(vec![1, 0, 2, 3, 4], *delivered.lock().unwrap());
+
+        std::thread::sleep(Duration::from_millis(400));
+        assert_eq!(vec![1, 0, 2, 3, 4, -5, 6, 7], *delivered.lock().unwrap());
+        send_content(8);
+        assert_eq!(8, *delivered.lock().unwrap().last().unwrap());
+
+        service.shutdown().unwrap();
+    }
+
+    #[test]
+    fn updates_whose_content_is_dropped_are_delivered_in_order() {
+        let start = |address: &str, peer: &str, handler: SequenceRecorder| {
+            let mut gossip_config = GossipConfig::new(true, true, 100, UpdateExpirationMode::None);
+            gossip_config.set_ordered_delivery(true, 5_000);
+            let mut service = GossipService::new(
+                address.parse().unwrap(),
+                PeerSamplingConfig::static_view(vec![Peer::try_new(peer).unwrap()]),
+                gossip_config
+            );
+            service.start_isolated(Box::new(handler)).unwrap();
+            service
+        };
+        let a = start("127.0.0.1:9186", "127.0.0.1:9187", SequenceRecorder(Arc::new(Mutex::new(Vec::new()))));
+        let delivered = Arc::new(Mutex::new(Vec::new()));
+        let b = start("127.0.0.1:9187", "127.0.0.1:9186", SequenceRecorder(Arc::clone(&delivered)));
+
+        // the first contents of the third and sixth updates received by the second node are dropped
+        let digest = |number: u64| Update::new_sequenced(
+            &crate::digest::Blake3Digester,
+            format!("update {}", number).into_bytes(),
+            Some(OriginSequence { origin: "127.0.0.1:9186".to_owned(), number })
+        ).digest().to_owned();
+        let dropped = Mutex::new(vec![digest(3), digest(6)]);
+        b.traffic.set_interceptor(Some(Box::new(move |message: &ContentMessage| {
+            let mut dropped = dropped.lock().unwrap();
+            let before = dropped.len();
+            dropped.retain(|digest| !message.content_ref().contains_key(digest));
+            dropped.len() == before
+        })));
+
+        for number in 1..=10 {
+            a.submit(format!("update {}", number).into_bytes()).unwrap();
+            std::thread::sleep(Duration::from_millis(150));
+        }
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while delivered.lock().unwrap().len() < 10 {
+            assert!(Instant::now() < deadline, "updates were not delivered: {:?}", delivered.lock().unwrap());
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!((1..=10).collect::<Vec<i64>>(), *delivered.lock().unwrap());
+        assert!(b.stats().content_request_timeouts > 0);
+
+        b.shutdown().unwrap();
+        a.shutdown().unwrap();
+    }
+
+    #[test]
+    fn renumbered_update_does_not_match_its_digest() {
+        let address: SocketAddr = "127.0.0.1:9188".parse().unwrap();
+        let mut gossip_config = GossipConfig::new(true, true, 100, UpdateExpirationMode::None);
+        gossip_config.set_ordered_delivery(true, 60_000);
+        gossip_config.set_accept_unrequested_content(true);
+        let mut service = GossipService::new(address, PeerSamplingConfig::static_view(Vec::new()), gossip_config);
+        let delivered = Arc::new(Mutex::new(Vec::new()));
+        service.start_isolated(Box::new(SequenceRecorder(Arc::clone(&delivered)))).unwrap();
+
+        // a relay renumbers the first update of its origin as the third
+        let sequence = |number: u64| OriginSequence { origin: "127.0.0.1:9189".to_owned(), number };
+        let update = Update::new_sequenced(&crate::digest::Blake3Digester, b"update 1".to_vec(), Some(sequence(1)));
+        let mut response = ContentMessage::new_response("127.0.0.1:9189".to_owned(), HashMap::from([(update.digest().to_owned(), update.content().to_vec())]));
+        response.set_sequences(HashMap::from([(update.digest().to_owned(), sequence(3))]));
+        crate::network::send(&address, &response, None, &NetworkConfig::default()).unwrap();
+
+        let deadline = Instant::now() + Duration::from_secs(3);
+        while service.stats().digest_verification_failures == 0 {
+            assert!(Instant::now() < deadline, "content was not received");
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        assert!(!service.updates.read().unwrap().is_active(update.digest()));
+        assert!(delivered.lock().unwrap().is_empty());
+        service.shutdown().unwrap();
+    }
+
+    /// Peer of a release that does not know the flag asking for the responses on the connection of the request: it
+    /// closes the connection without writing on it and connects back to the sender. It advertises a single update;
+    /// returns whether the requests it received carried the flag.
+    fn start_legacy_peer(address: &'static str, content: &'static [u8]) -> Arc<Mutex<Vec<bool>>> {
+        let flags = Arc::new(Mutex::new(Vec::new()));
+        let listener = TcpListener::bind(address).unwrap();
+        let flags_arc = Arc::clone(&flags);
+        std::thread::spawn(move || {
+            let digest = Update::new(content.to_vec()).digest().to_owned();
+            for stream in listener.incoming() {
+                let mut buffer = Vec::new();
+                if stream.and_then(|mut stream| stream.read_to_end(&mut buffer)).is_err() || buffer.is_empty() {
+                    continue;
+                }
+                flags_arc.lock().unwrap().push(buffer[0] & crate::message::MESSAGE_FLAG_REPLY_IN_STREAM != 0);
+                match buffer[0] & MASK_MESSAGE_PROTOCOL {
+                    MESSAGE_PROTOCOL_HEADER_MESSAGE => {
+                        let request = HeaderMessage::from_bytes(&buffer[1..]).unwrap();
+                        let mut response = HeaderMessage::new_response(address.to_owned());
+                        response.set_headers(vec![digest.clone()]);
+                        let _ = crate::network::send(&request.sender().parse().unwrap(), &response, None, &NetworkConfig::default());
+                    }
+                    MESSAGE_PROTOCOL_CONTENT_MESSAGE => {
+                        let request = ContentMessage::from_bytes(&buffer[1..]).unwrap();
+                        let response = ContentMessage::new_response(address.to_owned(), HashMap::from([(digest.clone(), content.to_vec())]));
+                        let _ = crate::network::send(&request.sender().parse().unwrap(), &response, None, &NetworkConfig::default());
+                    }
+                    _ => (),
+                }
+            }
+        });
+        flags
+    }
+
+    #[test]
+    fn legacy_receiver_answers_by_connecting_back() {
+        let flags = start_legacy_peer("127.0.0.1:9197", b"from a legacy peer");
+        let mut network = NetworkConfig::default();
+        network.set_reply_in_stream(true);
+        let mut gossip_config = GossipConfig::new(false, true, 50, UpdateExpirationMode::None);
+        gossip_config.set_network(network);
+        let mut service = GossipService::new(
+            "127.0.0.1:9196".parse().unwrap(),
+            PeerSamplingConfig::static_view(vec![Peer::try_new("127.0.0.1:9197").unwrap()]),
+            gossip_config
+        );
+        service.start_isolated(Box::new(NoopHandler)).unwrap();
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !service.is_active(b"from a legacy peer".to_vec()) {
+            assert!(Instant::now() < deadline, "the update of the legacy peer was not received");
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        // the requests asked for the responses on their connection, which the legacy peer closed without a response
+        let flags = flags.lock().unwrap();
+        assert!(!flags.is_empty() && flags.iter().all(|flag| *flag));
+        assert!(!service.recent_errors().iter().any(|error| error.category == ErrorCategory::Receive), "{:?}", service.recent_errors());
+        service.shutdown().unwrap();
+    }
+}
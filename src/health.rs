@@ -0,0 +1,62 @@
+use crate::config::DisseminationMode;
+use crate::view_stats::SamplingStats;
+use crate::sampler::SamplingState;
+
+/// Summary of the liveness and readiness of a node, see [GossipService::health](crate::GossipService::health)
+#[derive(Debug, Clone)]
+pub struct HealthReport {
+    /// Whether the service is started and all its threads are alive
+    pub running: bool,
+    /// Whether the listener is accepting connections, never the case for an [outbound-only](crate::GossipConfig::set_outbound_only) node
+    pub listener_bound: bool,
+    /// Whether the node takes part in the network: a peer sampling exchange occurred, or the node was
+    /// started without bootstrap peers or with a static view
+    pub ready: bool,
+    /// Number of peers in the view
+    pub peer_count: usize,
+    /// Seconds since a buffer from another peer was last merged into the view
+    pub seconds_since_last_successful_exchange: Option<u64>,
+    /// Seconds since a message was last received
+    pub seconds_since_last_received_message: Option<u64>,
+    /// Number of active updates
+    pub active_updates: usize,
+    /// Number of expired updates whose digest is still remembered
+    pub expired_updates: usize,
+    /// How updates are disseminated, `None` if neither push nor pull is enabled
+    pub gossip_mode: Option<DisseminationMode>,
+    /// How views are exchanged, `None` if the view is never exchanged
+    pub sampling_mode: Option<DisseminationMode>,
+    /// Whether a message handling thread stopped while the listener was still dispatching to it; the messages
+    /// it should handle are dropped
+    pub degraded: bool,
+    /// Indicators of the quality of the view, `None` if the sampler does not exchange views, see
+    /// [GossipService::sampling_stats](crate::GossipService::sampling_stats)
+    pub sampling_stats: Option<SamplingStats>,
+    /// Number of times the node was started, see [GossipStats::epoch](crate::GossipStats::epoch)
+    pub epoch: u64,
+    /// Seconds since the node was started, `None` before start
+    pub uptime_seconds: Option<u64>,
+    /// Whether the peer sampling service was started or shut down, see
+    /// [GossipService::sampling_state](crate::GossipService::sampling_state)
+    pub sampling_state: SamplingState,
+    /// Whether the node is listening and whether it joined the network, see
+    /// [GossipService::start_listening](crate::GossipService::start_listening)
+    pub join_state: JoinState,
+    /// Whether the messages sent to every peer of the view have been failing for the isolation window, see
+    /// [GossipConfig::set_isolation_detection](crate::GossipConfig::set_isolation_detection)
+    pub network_isolated: bool,
+}
+
+/// Lifecycle of a [GossipService](crate::GossipService) with respect to the network, see
+/// [GossipService::join_state](crate::GossipService::join_state)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JoinState {
+    /// Not started yet
+    NotStarted,
+    /// Listening and handling the received messages, without peer sampling and gossip rounds
+    ListeningOnly,
+    /// Listening and taking part in the peer sampling and gossip rounds
+    Joined,
+    /// Shut down
+    ShutDown,
+}
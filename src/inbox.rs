@@ -0,0 +1,296 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Condvar, Mutex};
+use std::sync::mpsc::{RecvTimeoutError, TrySendError};
+use std::time::Instant;
+use crate::message::gossip::{HeaderMessage, ContentMessage, PingMessage};
+use crate::message::sampling::PeerSamplingMessage;
+use crate::gossip::Delivery;
+
+/// Kinds of the items waiting for the message handling thread of a node, each waiting in its own queue
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Kind {
+    Header,
+    Content,
+    Ping,
+    Sampling,
+    Delivery,
+}
+
+/// Number of kinds of items
+const KINDS: usize = 5;
+
+impl Kind {
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+/// An item waiting for the message handling thread of a node
+#[derive(Debug)]
+pub(crate) enum Inbound {
+    Header(HeaderMessage),
+    Content(ContentMessage),
+    Ping(PingMessage),
+    Sampling(PeerSamplingMessage),
+    /// An update to deliver to the handler
+    Delivery(Delivery),
+}
+
+impl Inbound {
+    pub fn kind(&self) -> Kind {
+        match self {
+            Inbound::Header(_) => Kind::Header,
+            Inbound::Content(_) => Kind::Content,
+            Inbound::Ping(_) => Kind::Ping,
+            Inbound::Sampling(_) => Kind::Sampling,
+            Inbound::Delivery(_) => Kind::Delivery,
+        }
+    }
+}
+
+/// Queues of the items waiting for the message handling thread of a node. The thread takes them from each kind in
+/// turn, so that a burst of messages of one kind does not delay the messages of the other kinds.
+struct Inbox {
+    state: Mutex<InboxState>,
+    available: Condvar,
+    /// Maximum number of waiting items of each kind
+    capacities: [usize; KINDS],
+}
+
+struct InboxState {
+    /// Waiting items of each kind
+    queues: [VecDeque<Inbound>; KINDS],
+    /// Kinds with waiting items, in the order in which they are served
+    turns: VecDeque<Kind>,
+    /// Kinds whose items wait until they are released
+    held: [bool; KINDS],
+    /// Number of senders keeping the inbox open
+    senders: usize,
+    /// Whether the receiver was dropped
+    disconnected: bool,
+}
+
+impl InboxState {
+    /// Gives a kind its turn, if it has waiting items and is not held
+    fn schedule(&mut self, kind: Kind) {
+        if !self.held[kind.index()] && !self.queues[kind.index()].is_empty() && !self.turns.contains(&kind) {
+            self.turns.push_back(kind);
+        }
+    }
+}
+
+/// Creates the queues of the message handling thread of a node, bounded for each kind. The items of the held kinds
+/// wait until they are [released](InboxSender::release).
+///
+/// # Arguments
+///
+/// * `capacities` - Maximum number of waiting items of each kind, unbounded for the kinds not given
+/// * `held` - Kinds whose items are not handed to the receiver before they are released
+pub(crate) fn inbox(capacities: &[(Kind, usize)], held: &[Kind]) -> (InboxSender, InboxReceiver) {
+    let mut bounds = [usize::MAX; KINDS];
+    for (kind, capacity) in capacities {
+        bounds[kind.index()] = *capacity;
+    }
+    let mut state = InboxState {
+        queues: Default::default(),
+        turns: VecDeque::new(),
+        held: [false; KINDS],
+        senders: 1,
+        disconnected: false,
+    };
+    for kind in held {
+        state.held[kind.index()] = true;
+    }
+    let inbox = Arc::new(Inbox {
+        state: Mutex::new(state),
+        available: Condvar::new(),
+        capacities: bounds,
+    });
+    (InboxSender { inbox: Arc::clone(&inbox), counted: true }, InboxReceiver { inbox })
+}
+
+/// Queues items for the message handling thread. As with a channel, the receiver stops waiting once the senders are
+/// dropped, apart from the [detached](Self::detached) ones.
+pub(crate) struct InboxSender {
+    inbox: Arc<Inbox>,
+    /// Whether the sender keeps the inbox open
+    counted: bool,
+}
+
+impl InboxSender {
+    /// Queues an item, or returns it if the queue of its kind is full or the receiver was dropped
+    // the item is handed back as by a channel, for the sender to retry
+    #[allow(clippy::result_large_err)]
+    pub fn try_send(&self, item: Inbound) -> Result<(), TrySendError<Inbound>> {
+        let kind = item.kind();
+        let mut state = self.inbox.state.lock().unwrap();
+        if state.disconnected {
+            return Err(TrySendError::Disconnected(item));
+        }
+        if state.queues[kind.index()].len() >= self.inbox.capacities[kind.index()] {
+            return Err(TrySendError::Full(item));
+        }
+        state.queues[kind.index()].push_back(item);
+        state.schedule(kind);
+        self.inbox.available.notify_one();
+        Ok(())
+    }
+
+    /// Returns the number of waiting items of a kind
+    pub fn len(&self, kind: Kind) -> usize {
+        self.inbox.state.lock().unwrap().queues[kind.index()].len()
+    }
+
+    /// Hands the waiting items of a held kind to the receiver, and the items queued from then on
+    pub fn release(&self, kind: Kind) {
+        let mut state = self.inbox.state.lock().unwrap();
+        state.held[kind.index()] = false;
+        state.schedule(kind);
+        self.inbox.available.notify_one();
+    }
+
+    /// Returns a sender that does not keep the inbox open, for the items queued by the receiving thread itself or
+    /// until the receiver stops
+    pub fn detached(&self) -> InboxSender {
+        InboxSender { inbox: Arc::clone(&self.inbox), counted: false }
+    }
+}
+
+impl Clone for InboxSender {
+    fn clone(&self) -> Self {
+        if self.counted {
+            self.inbox.state.lock().unwrap().senders += 1;
+        }
+        InboxSender { inbox: Arc::clone(&self.inbox), counted: self.counted }
+    }
+}
+
+impl Drop for InboxSender {
+    fn drop(&mut self) {
+        if self.counted {
+            let mut state = self.inbox.state.lock().unwrap();
+            state.senders -= 1;
+            if state.senders == 0 {
+                self.inbox.available.notify_all();
+            }
+        }
+    }
+}
+
+/// Takes the items of the message handling thread from each kind in turn
+pub(crate) struct InboxReceiver {
+    inbox: Arc<Inbox>,
+}
+
+impl InboxReceiver {
+    /// Waits for the item of the kind whose turn it is, until the deadline if any. Once the senders are dropped, the
+    /// waiting items are still returned, and then [Disconnected](RecvTimeoutError::Disconnected); the items of the
+    /// kinds that are still held are dropped.
+    pub fn recv_until(&self, deadline: Option<Instant>) -> Result<Inbound, RecvTimeoutError> {
+        let mut state = self.inbox.state.lock().unwrap();
+        loop {
+            if let Some(kind) = state.turns.pop_front() {
+                let item = state.queues[kind.index()].pop_front().expect("a kind with a turn has waiting items");
+                state.schedule(kind);
+                return Ok(item);
+            }
+            if state.senders == 0 {
+                return Err(RecvTimeoutError::Disconnected);
+            }
+            state = match deadline {
+                Some(deadline) => {
+                    let timeout = deadline.saturating_duration_since(Instant::now());
+                    if timeout.is_zero() {
+                        return Err(RecvTimeoutError::Timeout);
+                    }
+                    self.inbox.available.wait_timeout(state, timeout).unwrap().0
+                }
+                None => self.inbox.available.wait(state).unwrap(),
+            };
+        }
+    }
+
+    /// Returns the item of the kind whose turn it is, if any
+    #[cfg(any(test, feature = "fuzzing"))]
+    pub fn try_recv(&self) -> Option<Inbound> {
+        self.recv_until(Some(Instant::now())).ok()
+    }
+}
+
+impl Drop for InboxReceiver {
+    fn drop(&mut self) {
+        let mut state = self.inbox.state.lock().unwrap();
+        state.disconnected = true;
+        state.queues.iter_mut().for_each(VecDeque::clear);
+        state.turns.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header(sender: &str) -> Inbound {
+        Inbound::Header(HeaderMessage::new_request(sender.to_owned(), false))
+    }
+
+    fn ping(sender: &str) -> Inbound {
+        Inbound::Ping(PingMessage::new(sender.to_owned()))
+    }
+
+    fn sender_of(item: Inbound) -> String {
+        match item {
+            Inbound::Header(message) => message.sender().to_owned(),
+            Inbound::Ping(message) => message.sender().to_owned(),
+            Inbound::Sampling(message) => message.sender().to_owned(),
+            item => panic!("Unexpected item {:?}", item),
+        }
+    }
+
+    #[test]
+    fn kinds_are_served_in_turn() {
+        let (sender, receiver) = inbox(&[(Kind::Header, 3)], &[]);
+        for sender_address in ["h1", "h2", "h3"] {
+            sender.try_send(header(sender_address)).unwrap();
+        }
+        assert!(matches!(sender.try_send(header("h4")), Err(TrySendError::Full(_))));
+        sender.try_send(ping("p1")).unwrap();
+        assert_eq!(3, sender.len(Kind::Header));
+
+        // the ping queued after the headers does not wait for all of them
+        let received: Vec<String> = (0..4).map(|_| sender_of(receiver.try_recv().unwrap())).collect();
+        assert_eq!(vec!["h1", "p1", "h2", "h3"], received);
+        assert!(receiver.try_recv().is_none());
+    }
+
+    #[test]
+    fn held_kinds_wait_until_released() {
+        let (sender, receiver) = inbox(&[], &[Kind::Sampling]);
+        sender.try_send(Inbound::Sampling(PeerSamplingMessage::new_request("s1".to_owned(), None))).unwrap();
+        sender.try_send(ping("p1")).unwrap();
+        assert_eq!("p1", sender_of(receiver.try_recv().unwrap()));
+        assert!(receiver.try_recv().is_none());
+        sender.release(Kind::Sampling);
+        assert_eq!("s1", sender_of(receiver.try_recv().unwrap()));
+    }
+
+    #[test]
+    fn receiver_drains_the_queues_once_the_senders_are_dropped() {
+        let (sender, receiver) = inbox(&[], &[]);
+        let detached = sender.detached();
+        let clone = sender.clone();
+        drop(sender);
+        // the clone keeps the inbox open
+        assert_eq!(Err(RecvTimeoutError::Timeout), receiver.recv_until(Some(Instant::now())).map(drop));
+        clone.try_send(ping("p1")).unwrap();
+        drop(clone);
+        // the detached sender still queues items, but does not keep the receiver waiting
+        detached.try_send(ping("p2")).unwrap();
+        assert_eq!("p1", sender_of(receiver.recv_until(None).unwrap()));
+        assert_eq!("p2", sender_of(receiver.recv_until(None).unwrap()));
+        assert_eq!(Err(RecvTimeoutError::Disconnected), receiver.recv_until(None).map(drop));
+
+        drop(receiver);
+        assert!(matches!(detached.try_send(ping("p3")), Err(TrySendError::Disconnected(_))));
+    }
+}
@@ -0,0 +1,157 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use rand::seq::SliceRandom;
+use crate::peer::Peer;
+
+/// A ping waiting for its pong
+struct Probe {
+    /// Time at which the last ping was sent
+    sent: Instant,
+    /// Pings left unanswered in a row, the last one included once it has been waited for an interval
+    missed: u32,
+    /// Whether the last ping is counted in the missed pings
+    expired: bool,
+}
+
+/// What a keepalive round does
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct KeepaliveRound {
+    /// Peers that left the maximum number of pings unanswered in a row, which are forgotten
+    pub unreachable: Vec<String>,
+    /// Peer to ping, if a peer of the view was not heard from for an interval
+    pub probe: Option<String>,
+}
+
+/// Liveness probes of the peers of the view, see [GossipConfig::set_keepalive](crate::GossipConfig::set_keepalive).
+/// Owned by the keepalive rounds of the timer thread: the only shared state is the time at which each peer was last heard from.
+pub(crate) struct Keepalive {
+    interval: Duration,
+    max_missed: u32,
+    probes: HashMap<String, Probe>,
+}
+
+impl Keepalive {
+    /// Creates the probes of a node
+    ///
+    /// # Arguments
+    ///
+    /// * `interval` - Time after which a peer not heard from is pinged, and a ping without pong counts as missed
+    /// * `max_missed` - Number of pings left unanswered in a row after which a peer is unreachable
+    pub fn new(interval: Duration, max_missed: u32) -> Self {
+        Keepalive {
+            interval,
+            max_missed: max_missed.max(1),
+            probes: HashMap::new(),
+        }
+    }
+
+    /// Counts the pings left unanswered for an interval, and selects the peer to ping: a peer that already missed a
+    /// ping if any, so that an unreachable peer is detected within a few intervals, or else a peer not heard from for
+    /// an interval, at random.
+    ///
+    /// # Arguments
+    ///
+    /// * `view` - The peers of the view
+    /// * `last_seen` - Returns the time at which a peer was last heard from
+    /// * `now` - Time of the round
+    pub fn round<F>(&mut self, view: &[Peer], last_seen: F, now: Instant) -> KeepaliveRound
+    where F: Fn(&str) -> Option<Instant>
+    {
+        let interval = self.interval;
+        let max_missed = self.max_missed;
+        let mut round = KeepaliveRound::default();
+        self.probes.retain(|peer, probe| {
+            if !view.iter().any(|known| known.address() == peer) || last_seen(peer).is_some_and(|seen| seen >= probe.sent) {
+                return false;
+            }
+            if !probe.expired && now.saturating_duration_since(probe.sent) >= interval {
+                probe.expired = true;
+                probe.missed += 1;
+            }
+            if probe.missed >= max_missed {
+                round.unreachable.push(peer.to_owned());
+                return false;
+            }
+            true
+        });
+
+        let suspects: Vec<&str> = self.probes.iter()
+            .filter(|(_, probe)| probe.expired)
+            .map(|(peer, _)| peer.as_str())
+            .collect();
+        round.probe = match suspects.choose(&mut rand::thread_rng()) {
+            Some(suspect) => Some(suspect.to_string()),
+            None => {
+                let idle: Vec<&Peer> = view.iter()
+                    .filter(|peer| !self.probes.contains_key(peer.address()))
+                    .filter(|peer| last_seen(peer.address()).is_none_or(|seen| now.saturating_duration_since(seen) >= interval))
+                    .collect();
+                idle.choose(&mut rand::thread_rng()).map(|peer| peer.address().to_owned())
+            }
+        };
+        round
+    }
+
+    /// Records the ping sent to a peer
+    pub fn ping_sent(&mut self, peer: &str, now: Instant) {
+        let probe = self.probes.entry(peer.to_owned()).or_insert(Probe { sent: now, missed: 0, expired: false });
+        probe.sent = now;
+        probe.expired = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn view(addresses: &[&str]) -> Vec<Peer> {
+        addresses.iter().map(|address| Peer::try_new(address).unwrap()).collect()
+    }
+
+    #[test]
+    fn peers_heard_from_recently_are_not_pinged() {
+        let mut keepalive = Keepalive::new(Duration::from_millis(100), 2);
+        let now = Instant::now();
+        let view = view(&["127.0.0.1:1", "127.0.0.1:2"]);
+        let round = keepalive.round(&view, |peer| (peer == "127.0.0.1:1").then_some(now), now);
+        assert_eq!(Some("127.0.0.1:2".to_owned()), round.probe);
+        keepalive.ping_sent("127.0.0.1:2", now);
+        // the ping is waited for an interval
+        assert_eq!(KeepaliveRound::default(), keepalive.round(&view, |peer| (peer == "127.0.0.1:1").then_some(now), now + Duration::from_millis(50)));
+        // the pong ends the probe
+        let pong = now + Duration::from_millis(60);
+        assert_eq!(KeepaliveRound::default(), keepalive.round(&view, |_| Some(pong), now + Duration::from_millis(90)));
+        assert!(keepalive.probes.is_empty());
+    }
+
+    #[test]
+    fn peer_missing_pings_in_a_row_is_unreachable() {
+        let mut keepalive = Keepalive::new(Duration::from_millis(100), 2);
+        let start = Instant::now();
+        let view = view(&["127.0.0.1:1", "127.0.0.1:2"]);
+        let seen = |peer: &str| (peer == "127.0.0.1:1").then_some(start + Duration::from_secs(10));
+        keepalive.ping_sent("127.0.0.1:2", start);
+
+        // the first missed ping makes the peer the next to be pinged
+        let round = keepalive.round(&view, seen, start + Duration::from_millis(100));
+        assert_eq!(KeepaliveRound { unreachable: Vec::new(), probe: Some("127.0.0.1:2".to_owned()) }, round);
+        keepalive.ping_sent("127.0.0.1:2", start + Duration::from_millis(100));
+        let round = keepalive.round(&view, seen, start + Duration::from_millis(150));
+        assert_eq!(KeepaliveRound::default(), round);
+
+        let round = keepalive.round(&view, seen, start + Duration::from_millis(200));
+        assert_eq!(vec!["127.0.0.1:2".to_owned()], round.unreachable);
+        assert!(keepalive.probes.is_empty());
+    }
+
+    #[test]
+    fn probes_of_peers_leaving_the_view_are_forgotten() {
+        let mut keepalive = Keepalive::new(Duration::from_millis(100), 1);
+        let now = Instant::now();
+        keepalive.ping_sent("127.0.0.1:1", now);
+        let round = keepalive.round(&view(&["127.0.0.1:2"]), |_| None, now + Duration::from_millis(200));
+        assert!(round.unreachable.is_empty());
+        assert_eq!(Some("127.0.0.1:2".to_owned()), round.probe);
+        assert!(keepalive.probes.is_empty());
+    }
+}
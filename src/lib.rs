@@ -1,13 +1,83 @@
+#[macro_use]
+mod logging;
 mod update;
 mod peer;
 mod sampling;
+mod sampler;
 mod message;
 mod config;
 mod network;
 mod gossip;
+mod stats;
+mod broadcast;
+mod error;
+mod chunk;
+mod observer;
+mod health;
+mod digest;
+mod listener;
+mod quarantine;
+mod snapshot;
+mod error_log;
+mod fetch;
+mod relay;
+mod view_export;
+mod update_export;
+mod clock;
+mod coverage;
+mod bootstrap;
+mod command;
+mod inbox;
+mod traffic;
+mod estimate;
+mod ordering;
+mod wire_trace;
+mod view_stats;
+mod usefulness;
+mod warning;
+mod revocation;
+mod memory;
+mod epoch;
+mod keepalive;
+mod round;
+#[cfg(test)]
+mod wire_compat;
+#[cfg(feature = "bench-internals")]
+#[doc(hidden)]
+pub mod bench;
+#[cfg(feature = "fuzzing")]
+#[doc(hidden)]
+pub mod fuzzing;
+#[cfg(feature = "config-serde")]
+mod node_config;
 
-pub use crate::config::{PeerSamplingConfig, GossipConfig, UpdateExpirationMode};
-pub use crate::peer::Peer;
-pub use crate::update::{Update, UpdateHandler};
+pub use crate::config::{PeerSamplingConfig, GossipConfig, NetworkConfig, UpdateExpirationMode, ExpirationStart, ConfigWarning, DisseminationMode, SyncPolicy, ReplyAddress};
+pub use crate::peer::{Peer, MAX_PEER_METADATA};
+pub use crate::update::{Update, BatchItem, OriginSequence, RemovalReason, ShutdownReason, SubmitOutcome, UpdateCounters, UpdateHandler, UpdateState, MAX_TRACKED_REQUESTERS};
 pub use crate::gossip::GossipService;
+pub use crate::bootstrap::BootstrapSource;
+pub use crate::listener::SharedListener;
+pub use crate::stats::{GossipStats, LatencyHistogram, LATENCY_BUCKETS_MILLIS};
+pub use crate::broadcast::broadcast_once;
+pub use crate::error::{AddressError, AlreadyStarted, BatchError, BindError, HandlerError, NotStarted};
+pub use crate::chunk::{ChunkedUpdateHandler, split_into_chunks, split_into_chunks_with};
+pub use crate::digest::{Digest, Digester, Blake3Digester};
+pub use crate::observer::{ExpirySummary, RoundObserver, RoundSummary, SamplingSummary};
+pub use crate::health::{HealthReport, JoinState};
+pub use crate::memory::{MemoryReport, SamplerMemory, Usage};
+pub use crate::logging::targets as log_targets;
+pub use crate::traffic::{MessageTraffic, PeerTraffic, TrafficCounters, MAX_TRAFFIC_PEERS, TRAFFIC_WINDOW};
+pub use crate::view_export::ViewSummary;
+pub use crate::view_stats::{SamplingStats, SAMPLING_STATS_WINDOW};
+pub use crate::usefulness::PeerInfo;
+pub use crate::wire_trace::{Direction, WireTracer, hexdump_tracer};
+pub use crate::error_log::{ErrorCategory, ErrorRecord, MAX_RECENT_ERRORS};
+pub use crate::sampler::{PeerSampler, RoundControl, SamplerContext, SamplerTasks, SamplingState, ViewPublisher, StaticSampler};
+pub use crate::fetch::{FetchPolicy, MAX_DECLINED_DIGESTS};
+pub use crate::message::sampling::PeerSamplingMessage;
+pub use crate::message::{MessageType, MAX_ADDRESS_LENGTH, MAX_DIGEST_LENGTH, MAX_MESSAGE_DIGESTS};
 
+#[cfg(feature = "config-serde")]
+pub use crate::node_config::NodeConfig;
+#[cfg(feature = "sha256")]
+pub use crate::digest::Sha256Digester;
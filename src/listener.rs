@@ -0,0 +1,103 @@
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::JoinHandle;
+use std::error::Error;
+use crate::message::NoopMessage;
+use crate::network::{ConnectionLimits, Dispatcher, Router};
+use crate::config::{GossipConfig, NetworkConfig};
+use crate::logging::targets::NETWORK;
+
+/// A TCP listener shared by several gossip services of the same process.
+///
+/// Each service sharing the listener belongs to its own cluster, set with
+/// [GossipConfig::set_cluster_id](crate::GossipConfig::set_cluster_id), and received messages are routed
+/// to the service of the cluster they are stamped with. Services are created with
+/// [GossipService::new_shared](crate::GossipService::new_shared), register when started and unregister when shut down.
+pub struct SharedListener {
+    /// Address to which the listener is bound
+    address: SocketAddr,
+    /// Dispatchers of the registered services
+    router: Arc<Router>,
+    /// Flag used to stop the listener
+    shutdown: Arc<AtomicBool>,
+    /// Listener thread
+    handle: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl SharedListener {
+    /// Binds the listener and starts accepting connections
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - Bind address, which is also the address of the services sharing the listener
+    /// * `workers` - Number of threads reading connections
+    /// * `backlog` - Maximum number of accepted connections waiting for a reader thread
+    /// * `config` - Configuration whose [connection limits](GossipConfig::set_connection_limits) apply to the
+    ///   connections of the listener
+    pub fn bind(address: SocketAddr, workers: usize, backlog: usize, config: &GossipConfig) -> Result<Self, Box<dyn Error>> {
+        let listener = crate::network::bind(&address, &NetworkConfig::default())?;
+        // with port 0, the services sharing the listener are reached on the port chosen by the system
+        let address = listener.local_addr()?;
+        let router = Arc::new(Router::default());
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let handle = crate::network::listen(listener, Arc::clone(&shutdown), workers, backlog, ConnectionLimits::from_config(config), Arc::clone(&router))?;
+        Ok(SharedListener {
+            address,
+            router,
+            shutdown,
+            handle: Mutex::new(Some(handle)),
+        })
+    }
+
+    /// Returns the address to which the listener is bound
+    pub fn address(&self) -> &SocketAddr {
+        &self.address
+    }
+
+    /// Returns whether the listener is accepting connections
+    pub fn is_running(&self) -> bool {
+        self.handle.lock().unwrap().as_ref().is_some_and(|handle| !handle.is_finished())
+    }
+
+    /// Routes the messages of a cluster to a service, failing if another service is registered for it
+    pub(crate) fn register(&self, cluster_id: u8, dispatcher: Dispatcher) -> Result<(), Box<dyn Error>> {
+        self.router.register(Some(cluster_id), dispatcher)
+    }
+
+    /// Returns the router of the listener, which also dispatches the responses read on the connections of the services
+    pub(crate) fn router(&self) -> Arc<Router> {
+        Arc::clone(&self.router)
+    }
+
+    /// Stops routing the messages of a cluster
+    pub(crate) fn unregister(&self, cluster_id: u8) {
+        self.router.unregister(Some(cluster_id));
+    }
+
+    /// Stops accepting connections and waits for the messages being read to be dispatched.
+    /// Does nothing if the listener was already shut down.
+    pub fn shutdown(&self) -> Result<(), Box<dyn Error>> {
+        // the handle is not kept locked while the listener is joined
+        let handle = self.handle.lock().unwrap().take();
+        if let Some(handle) = handle {
+            self.shutdown.store(true, Ordering::SeqCst);
+            // wake up the listener
+            if let Err(e) = crate::network::send(&crate::network::local_address(self.address), &NoopMessage, None, &NetworkConfig::default()) {
+                node_log!(Debug, NETWORK, "Could not notify listener: {:?}", e);
+            }
+            if let Err(e) = handle.join() {
+                Err(format!("Error joining shared listener: {:?}", e))?
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Drop for SharedListener {
+    fn drop(&mut self) {
+        if let Err(e) = self.shutdown() {
+            node_log!(Error, NETWORK, "{}", e);
+        }
+    }
+}
@@ -0,0 +1,92 @@
+//! Log targets of the subsystems, and the address of the node prefixed to the log messages.
+
+use std::cell::RefCell;
+use std::fmt;
+
+/// Targets of the log messages of each subsystem, so that they can be filtered separately
+pub mod targets {
+    /// View maintenance: peer sampling exchanges, bootstrap, departures and imported views
+    pub const SAMPLING: &str = "gossip::sampling";
+    /// Gossip rounds: header and content exchanges with the peers, initial sync and lifecycle of the service
+    pub const GOSSIP: &str = "gossip::gossip";
+    /// Listener, connections and dispatch of the received messages
+    pub const NETWORK: &str = "gossip::network";
+    /// Submission, insertion, expiration and delivery of the updates
+    pub const UPDATES: &str = "gossip::updates";
+    /// Configuration warnings and diagnostics such as the wire trace
+    pub const MONITOR: &str = "gossip::monitor";
+}
+
+thread_local! {
+    /// Address of the node on whose behalf the thread logs
+    static NODE: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+/// Prefixes the messages logged afterwards by the current thread with the address of the node; called at the start
+/// of the threads of a node
+pub(crate) fn set_node(address: impl ToString) {
+    NODE.with(|node| *node.borrow_mut() = Some(address.to_string()));
+}
+
+/// Returns the address of the node of the current thread, if any
+pub(crate) fn current_node() -> Option<String> {
+    NODE.with(|node| node.borrow().clone())
+}
+
+/// Prefixes the messages logged by the current thread with the address of the node until dropped; used by the
+/// methods of a service called from the application threads
+pub(crate) struct NodeScope {
+    previous: Option<String>,
+}
+
+impl NodeScope {
+    pub fn enter(address: impl ToString) -> Self {
+        NodeScope {
+            previous: NODE.with(|node| node.borrow_mut().replace(address.to_string())),
+        }
+    }
+}
+
+impl Drop for NodeScope {
+    fn drop(&mut self) {
+        let previous = self.previous.take();
+        NODE.with(|node| *node.borrow_mut() = previous);
+    }
+}
+
+/// Writes the address of the node of the current thread as `[address] `, nothing if the thread logs for no node
+pub(crate) struct NodePrefix;
+
+impl fmt::Display for NodePrefix {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        NODE.with(|node| match node.borrow().as_deref() {
+            Some(address) => write!(f, "[{}] ", address),
+            None => Ok(()),
+        })
+    }
+}
+
+/// Logs a message under the target of a subsystem, prefixed with the address of the node of the current thread
+///
+/// `node_log!(Info, GOSSIP, "Started {}", name)`
+macro_rules! node_log {
+    ($level:ident, $target:expr, $($arg:tt)+) => {
+        log::log!(target: $target, log::Level::$level, "{}{}", $crate::logging::NodePrefix, format_args!($($arg)+))
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scope_restores_the_previous_node() {
+        assert_eq!("", NodePrefix.to_string());
+        set_node("127.0.0.1:9000");
+        {
+            let _scope = NodeScope::enter("127.0.0.1:9001");
+            assert_eq!("[127.0.0.1:9001] ", NodePrefix.to_string());
+        }
+        assert_eq!("[127.0.0.1:9000] ", NodePrefix.to_string());
+    }
+}
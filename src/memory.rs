@@ -0,0 +1,163 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use crate::peer::Peer;
+
+/// Approximate overhead (bytes) of an entry of a collection, in addition to the size of its strings and contents
+const ENTRY_OVERHEAD: usize = 48;
+
+/// Number of elements of a structure and its approximate size, see [MemoryReport]
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Usage {
+    /// Number of elements
+    pub count: usize,
+    /// Approximate size (bytes) of the elements, including the strings and contents they own
+    pub bytes: usize,
+}
+
+impl Usage {
+    /// Returns the usage of elements owning the given numbers of bytes
+    pub(crate) fn of_sizes(sizes: impl IntoIterator<Item = usize>) -> Self {
+        sizes.into_iter().fold(Usage::default(), |usage, size| Usage {
+            count: usage.count + 1,
+            bytes: usage.bytes + ENTRY_OVERHEAD + size,
+        })
+    }
+
+    /// Returns the usage of elements identified by strings, such as digests or addresses
+    pub(crate) fn of_strings<'a>(strings: impl IntoIterator<Item = &'a String>) -> Self {
+        Self::of_sizes(strings.into_iter().map(String::len))
+    }
+
+    /// Returns the usage of peers
+    pub(crate) fn of_peers<'a>(peers: impl IntoIterator<Item = &'a Peer>) -> Self {
+        Self::of_sizes(peers.into_iter().map(|peer| peer.address().len() + peer.metadata().map_or(0, <[u8]>::len)))
+    }
+}
+
+/// Sizes of the structures maintained by a [PeerSampler](crate::PeerSampler), see [MemoryReport]
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SamplerMemory {
+    /// Peers of the view
+    pub view: Usage,
+    /// Peers newly added to the view and not yet returned to the application
+    pub queued_peers: Usage,
+    /// Peers that left the network and are kept out of the view
+    pub departed_peers: Usage,
+    /// Pull requests waiting for their response
+    pub pull_requests: Usage,
+}
+
+/// Sizes of the structures of a service that grow with the traffic, see
+/// [GossipService::memory_report](crate::GossipService::memory_report).
+///
+/// Each of them is bounded, by a maximum number of elements, a retention time or the size of the view, except the
+/// active updates, bounded by the expiration mode, and the updates waiting for their delivery, which grow if the handler
+/// is slower than the updates arrive.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MemoryReport {
+    /// Active updates, with their contents
+    pub active_updates: Usage,
+    /// Digests of the expired updates, kept so that they are not requested again
+    pub removed_digests: Usage,
+    /// Digests of the recently expired updates advertised as tombstones
+    pub tombstones: Usage,
+    /// Revocations of the updates not received yet
+    pub pending_revocations: Usage,
+    /// Counters of the recently expired updates
+    pub expired_counters: Usage,
+    /// Entries of the queues ordering the active updates by expiration
+    pub expiration_queues: Usage,
+    /// Updates submitted before start
+    pub queued_updates: Usage,
+    /// Updates waiting for their delivery by the message handling thread
+    pub pending_deliveries: Usage,
+    /// Failed deliveries waiting to be attempted again
+    pub delivery_retries: Usage,
+    /// Received messages waiting for the message handling thread, counted only: their size is not recorded
+    pub queued_messages: Usage,
+    /// Content requests waiting for their response
+    pub content_requests: Usage,
+    /// Content requests that timed out, whose late response is still accepted
+    pub timed_out_requests: Usage,
+    /// Header exchanges waiting for their response
+    pub header_exchanges: Usage,
+    /// Time at which the digests not received yet were first advertised
+    pub digests_seen: Usage,
+    /// Digests declined by the fetch policy
+    pub declined_digests: Usage,
+    /// Digests relayed without their content
+    pub relayed_digests: Usage,
+    /// Digests whose received content did not match
+    pub digest_mismatches: Usage,
+    /// Peers with recorded violations or in quarantine
+    pub quarantine: Usage,
+    /// Peers whose traffic is recorded
+    pub traffic_peers: Usage,
+    /// Peers whose usefulness is recorded
+    pub peer_histories: Usage,
+    /// Structures of the peer sampler, `None` if it does not report them
+    pub sampler: Option<SamplerMemory>,
+}
+
+impl MemoryReport {
+    /// Returns the approximate size (bytes) of all the reported structures
+    pub fn total_bytes(&self) -> usize {
+        let sampler = self.sampler.as_ref().map_or(0, |sampler| {
+            sampler.view.bytes + sampler.queued_peers.bytes + sampler.departed_peers.bytes + sampler.pull_requests.bytes
+        });
+        [
+            self.active_updates, self.removed_digests, self.tombstones, self.pending_revocations, self.expired_counters,
+            self.expiration_queues, self.queued_updates, self.pending_deliveries, self.delivery_retries, self.queued_messages,
+            self.content_requests, self.timed_out_requests, self.header_exchanges, self.digests_seen, self.declined_digests,
+            self.relayed_digests, self.digest_mismatches, self.quarantine, self.traffic_peers, self.peer_histories,
+        ].iter().map(|usage| usage.bytes).sum::<usize>() + sampler
+    }
+}
+
+/// Usage of a queue whose elements are counted as they enter and leave it
+#[derive(Default)]
+pub(crate) struct UsageCounter {
+    count: AtomicUsize,
+    bytes: AtomicUsize,
+}
+
+impl UsageCounter {
+    pub fn add(&self, size: usize) {
+        self.count.fetch_add(1, Ordering::SeqCst);
+        self.bytes.fetch_add(ENTRY_OVERHEAD + size, Ordering::SeqCst);
+    }
+
+    pub fn remove(&self, size: usize) {
+        self.count.fetch_sub(1, Ordering::SeqCst);
+        self.bytes.fetch_sub(ENTRY_OVERHEAD + size, Ordering::SeqCst);
+    }
+
+    /// Replaces the usage, for queues owned by a single thread
+    pub fn set(&self, usage: Usage) {
+        self.count.store(usage.count, Ordering::SeqCst);
+        self.bytes.store(usage.bytes, Ordering::SeqCst);
+    }
+
+    pub fn usage(&self) -> Usage {
+        Usage {
+            count: self.count.load(Ordering::SeqCst),
+            bytes: self.bytes.load(Ordering::SeqCst),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn usage_counts_strings_and_overhead() {
+        let digests = vec!["ab".to_owned(), "cde".to_owned()];
+        assert_eq!(Usage { count: 2, bytes: 2 * ENTRY_OVERHEAD + 5 }, Usage::of_strings(&digests));
+
+        let counter = UsageCounter::default();
+        counter.add(10);
+        counter.add(20);
+        counter.remove(10);
+        assert_eq!(Usage { count: 1, bytes: ENTRY_OVERHEAD + 20 }, counter.usage());
+    }
+}
@@ -10,6 +10,19 @@ pub const MESSAGE_PROTOCOL_SAMPLING_MESSAGE: u8 = 0x10; // 0b00010000
 pub const MESSAGE_PROTOCOL_HEADER_MESSAGE: u8   = 0x20; // 0b00100000
 pub const MESSAGE_PROTOCOL_CONTENT_MESSAGE: u8  = 0x40; // 0b01000000
 pub const MESSAGE_PROTOCOL_NOOP_MESSAGE: u8     = 0x80; // 0b10000000
+pub const MESSAGE_PROTOCOL_PING_MESSAGE: u8     = 0x30; // 0b00110000
+pub const MESSAGE_PROTOCOL_PONG_MESSAGE: u8     = 0x50; // 0b01010000
+// When set, the protocol byte is followed by the cluster id
+pub const MESSAGE_FLAG_CLUSTER: u8              = 0x01; // 0b00000001
+// When set, the receiver writes its responses on the connection of the message instead of connecting to the sender
+pub const MESSAGE_FLAG_REPLY_IN_STREAM: u8      = 0x02; // 0b00000010
+
+/// Maximum length (bytes) of the address of the sender of a message, and of the peer addresses it contains
+pub const MAX_ADDRESS_LENGTH: usize = 256;
+/// Maximum length (bytes) of a digest in a message
+pub const MAX_DIGEST_LENGTH: usize = 128;
+/// Maximum number of digests in a gossip message, for each of its lists
+pub const MAX_MESSAGE_DIGESTS: usize = 65_536;
 
 /// The message type. [MessageType::Request] is used to advertise the node data or request advertised data;
 /// [MessageType::Response] is used to advertise back in response to a request, or provide the requested data.
@@ -19,18 +32,67 @@ pub enum MessageType {
     Response = 2,
 }
 
+/// Returns whether a message of the remote cluster is accepted by a node of the local cluster.
+/// Nodes or messages without cluster name are compatible with every cluster.
+pub fn same_cluster(local: Option<&str>, remote: Option<&str>) -> bool {
+    match (local, remote) {
+        (Some(local), Some(remote)) => local == remote,
+        _ => true,
+    }
+}
+
+/// Checks that an address of a message is within [MAX_ADDRESS_LENGTH]
+///
+/// # Arguments
+///
+/// * `kind` - What the address is, for the error message
+/// * `address` - The address
+pub(crate) fn check_address(kind: &str, address: &str) -> Result<(), Box<dyn Error>> {
+    if address.len() > MAX_ADDRESS_LENGTH {
+        Err(format!("The {} address is longer than {} bytes", kind, MAX_ADDRESS_LENGTH))?
+    }
+    Ok(())
+}
+
+/// Checks that a list of digests of a message is within [MAX_MESSAGE_DIGESTS], and each of them within [MAX_DIGEST_LENGTH]
+///
+/// # Arguments
+///
+/// * `kind` - What the digests are, for the error message
+/// * `digests` - The digests
+pub(crate) fn check_digests<'a, I>(kind: &str, digests: I) -> Result<(), Box<dyn Error>>
+where I: ExactSizeIterator<Item = &'a String>
+{
+    if digests.len() > MAX_MESSAGE_DIGESTS {
+        Err(format!("More than {} {} digests in message", MAX_MESSAGE_DIGESTS, kind))?
+    }
+    for digest in digests {
+        if digest.len() > MAX_DIGEST_LENGTH {
+            Err(format!("A {} digest is longer than {} bytes", kind, MAX_DIGEST_LENGTH))?
+        }
+    }
+    Ok(())
+}
+
 /// Message trait with generic implementation for serialization and deserialization
 pub trait Message {
 
     /// The message protocol, used for serialization/deserialization
     fn protocol(&self) -> u8;
 
-    /// Serializes message for sending over the wire
-    fn as_bytes(&self) -> Result<Vec<u8>, Box<dyn Error>>
+    /// Checks the limits on the strings and lists of the message, which are enforced before it is sent and after
+    /// it is received
+    fn check_limits(&self) -> Result<(), Box<dyn Error>> {
+        Ok(())
+    }
+
+    /// Serializes message for sending over the wire, appending it to the buffer
+    fn write_bytes(&self, buffer: &mut Vec<u8>) -> Result<(), Box<dyn Error>>
     where Self: Serialize
     {
-        match serde_cbor::to_vec(&self) {
-            Ok(bytes) => Ok(bytes),
+        self.check_limits()?;
+        match serde_cbor::to_writer(buffer, &self) {
+            Ok(()) => Ok(()),
             Err(e) => Err(e)?,
         }
     }
@@ -40,7 +102,10 @@ pub trait Message {
     where Self: Sized + Deserialize<'a>
     {
         match serde_cbor::from_slice::<Self>(bytes) {
-            Ok(m) => Ok(m),
+            Ok(m) => {
+                m.check_limits()?;
+                Ok(m)
+            }
             Err(e) => Err(e)?
         }
     }
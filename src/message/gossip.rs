@@ -1,6 +1,11 @@
 use serde::{Serialize, Deserialize};
-use crate::message::{Message, MESSAGE_PROTOCOL_HEADER_MESSAGE, MESSAGE_PROTOCOL_CONTENT_MESSAGE, MessageType};
-use std::collections::HashMap;
+use crate::message::{self, Message, MESSAGE_PROTOCOL_HEADER_MESSAGE, MESSAGE_PROTOCOL_CONTENT_MESSAGE, MESSAGE_PROTOCOL_PING_MESSAGE, MESSAGE_PROTOCOL_PONG_MESSAGE, MessageType};
+use crate::update::OriginSequence;
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use crate::network::ReplyStream;
 
 /// A message containing the digests of all the active updates on a node.
 /// It is used to advertise the updates present at each node.
@@ -8,23 +13,65 @@ use std::collections::HashMap;
 pub struct HeaderMessage {
     sender: String,
     message_type: MessageType,
-    headers: Vec<String>,
+    /// Shared with the list of the active updates when it did not change since it was last sent
+    headers: Arc<Vec<String>>,
+    /// Whether the sender of a request expects the headers of the receiver in response (pull).
+    /// Missing in messages from older nodes, which always expected a response.
+    #[serde(default = "default_wants_response")]
+    wants_response: bool,
+    /// Name of the cluster of the sender
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    cluster: Option<String>,
+    /// Digests advertised to the sender whose content it does not hold
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    relayed: Vec<String>,
+    /// Address from which the receiver of a request saw it come, reported back in its response
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    observed: Option<String>,
+    /// Digests of updates recently expired by the sender, or advertised to it as expired
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    tombstones: Vec<String>,
+    /// Address of the connection the message was received from, set by the receiver
+    #[serde(skip)]
+    source: Option<SocketAddr>,
+    /// Connection on which the sender reads the responses, set by the receiver
+    #[serde(skip)]
+    reply: Option<ReplyStream>,
+}
+fn default_wants_response() -> bool {
+    true
 }
 impl HeaderMessage {
-    pub fn new_request(sender: String) -> Self {
-        Self::new(sender, MessageType::Request)
+    pub fn new_request(sender: String, wants_response: bool) -> Self {
+        Self::new(sender, MessageType::Request, wants_response)
     }
     pub fn new_response(sender: String) -> Self {
-        Self::new(sender, MessageType::Response)
+        Self::new(sender, MessageType::Response, false)
     }
-    fn new(sender: String, message_type: MessageType) -> Self {
+    fn new(sender: String, message_type: MessageType, wants_response: bool) -> Self {
         HeaderMessage {
             sender,
             message_type,
-            headers: Vec::new()
+            headers: Arc::new(Vec::new()),
+            wants_response,
+            cluster: None,
+            relayed: Vec::new(),
+            observed: None,
+            tombstones: Vec::new(),
+            source: None,
+            reply: None,
         }
     }
+    /// Sets the name of the cluster of the sender
+    pub fn with_cluster(mut self, cluster: Option<String>) -> Self {
+        self.cluster = cluster;
+        self
+    }
     pub fn set_headers(&mut self, headers: Vec<String>) {
+        self.headers = Arc::new(headers)
+    }
+    /// Sets the headers without copying them, see [UpdateDecorator::cached_headers](crate::update::UpdateDecorator::cached_headers)
+    pub(crate) fn set_shared_headers(&mut self, headers: Arc<Vec<String>>) {
         self.headers = headers
     }
     pub fn sender(&self) -> &str {
@@ -36,11 +83,62 @@ impl HeaderMessage {
     pub fn headers(&self) -> &Vec<String> {
         &self.headers
     }
+    pub fn set_relayed(&mut self, relayed: Vec<String>) {
+        self.relayed = relayed
+    }
+    pub fn relayed(&self) -> &Vec<String> {
+        &self.relayed
+    }
+    pub fn set_tombstones(&mut self, tombstones: Vec<String>) {
+        self.tombstones = tombstones
+    }
+    pub fn tombstones(&self) -> &Vec<String> {
+        &self.tombstones
+    }
+    pub fn wants_response(&self) -> bool {
+        self.wants_response
+    }
+    pub fn cluster(&self) -> Option<&str> {
+        self.cluster.as_deref()
+    }
+    /// Sets the address from which the request answered by the message was received
+    pub fn set_observed(&mut self, observed: Option<SocketAddr>) {
+        self.observed = observed.map(|observed| observed.to_string())
+    }
+    pub fn observed(&self) -> Option<&str> {
+        self.observed.as_deref()
+    }
+    pub(crate) fn set_source(&mut self, source: Option<SocketAddr>) {
+        self.source = source
+    }
+    pub(crate) fn set_reply_stream(&mut self, reply: Option<ReplyStream>) {
+        self.reply = reply
+    }
+    /// Returns the connection on which the sender reads the responses, if it asked for them to be written on the
+    /// connection of the message
+    pub(crate) fn reply_stream(&self) -> Option<&ReplyStream> {
+        self.reply.as_ref()
+    }
+    /// Returns the address of the connection the message was received from, if it was received from the network
+    pub fn source(&self) -> Option<SocketAddr> {
+        self.source
+    }
 }
 impl Message for HeaderMessage {
     fn protocol(&self) -> u8 {
         MESSAGE_PROTOCOL_HEADER_MESSAGE
     }
+
+    fn check_limits(&self) -> Result<(), Box<dyn Error>> {
+        message::check_address("sender", &self.sender)?;
+        message::check_digests("header", self.headers.iter())?;
+        message::check_digests("relayed", self.relayed.iter())?;
+        message::check_digests("tombstone", self.tombstones.iter())?;
+        if let Some(observed) = &self.observed {
+            message::check_address("observed", observed)?;
+        }
+        Ok(())
+    }
 }
 
 /// A message that is either a request for updates ([MessageType::Request]) or a response
@@ -50,6 +148,24 @@ pub struct ContentMessage {
     sender: String,
     message_type: MessageType,
     content: HashMap<String, Vec<u8>>,
+    /// Peers to ask for the requested digests whose content the sender of a response does not hold
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    redirects: HashMap<String, String>,
+    /// Origins and numbers of the updates submitted with ordered delivery, by digest
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    sequences: HashMap<String, OriginSequence>,
+    /// Origin ids of the updates submitted by nodes with an origin id, by digest
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    origins: HashMap<String, String>,
+    /// Digests of the updates that revoke the update whose digest is their content
+    #[serde(default, skip_serializing_if = "HashSet::is_empty")]
+    revocations: HashSet<String>,
+    /// Address of the connection the message was received from, set by the receiver
+    #[serde(skip)]
+    source: Option<SocketAddr>,
+    /// Connection on which the sender reads the responses, set by the receiver
+    #[serde(skip)]
+    reply: Option<ReplyStream>,
 }
 impl ContentMessage {
     pub fn new_request(sender: String, content: HashMap<String, Vec<u8>>) -> Self {
@@ -63,6 +179,12 @@ impl ContentMessage {
             sender,
             message_type,
             content,
+            redirects: HashMap::new(),
+            sequences: HashMap::new(),
+            origins: HashMap::new(),
+            revocations: HashSet::new(),
+            source: None,
+            reply: None,
         }
     }
     pub fn sender(&self) -> &str {
@@ -71,17 +193,223 @@ impl ContentMessage {
     pub fn message_type(&self) -> &MessageType {
         &self.message_type
     }
+    pub(crate) fn set_source(&mut self, source: Option<SocketAddr>) {
+        self.source = source
+    }
+    pub(crate) fn set_reply_stream(&mut self, reply: Option<ReplyStream>) {
+        self.reply = reply
+    }
+    /// Takes the connection on which the sender reads the responses, which stays open until dropped
+    pub(crate) fn take_reply_stream(&mut self) -> Option<ReplyStream> {
+        self.reply.take()
+    }
+    /// Returns the address of the connection the message was received from, if it was received from the network
+    pub fn source(&self) -> Option<SocketAddr> {
+        self.source
+    }
 
-    pub fn len(&self) -> usize {
-        self.content.len()
+    pub fn is_empty(&self) -> bool {
+        self.content.is_empty()
+    }
+    pub fn set_redirects(&mut self, redirects: HashMap<String, String>) {
+        self.redirects = redirects
+    }
+    /// Returns the peers to ask for digests, leaving none in the message
+    pub fn take_redirects(&mut self) -> HashMap<String, String> {
+        std::mem::take(&mut self.redirects)
+    }
+    pub fn set_sequences(&mut self, sequences: HashMap<String, OriginSequence>) {
+        self.sequences = sequences
+    }
+    /// Returns the origins and numbers of the updates, leaving none in the message
+    pub fn take_sequences(&mut self) -> HashMap<String, OriginSequence> {
+        std::mem::take(&mut self.sequences)
+    }
+    pub fn set_origins(&mut self, origins: HashMap<String, String>) {
+        self.origins = origins
+    }
+    /// Returns the origin ids of the updates, leaving none in the message
+    pub fn take_origins(&mut self) -> HashMap<String, String> {
+        std::mem::take(&mut self.origins)
+    }
+    pub fn set_revocations(&mut self, revocations: HashSet<String>) {
+        self.revocations = revocations
+    }
+    /// Returns the digests of the revocations, leaving none in the message
+    pub fn take_revocations(&mut self) -> HashSet<String> {
+        std::mem::take(&mut self.revocations)
     }
     /// Returns the content of the message. Moves the message to avoid copying its content.
     pub fn content(self) -> HashMap<String, Vec<u8>> {
         self.content
     }
+    /// Returns the content of the message, by digest
+    pub fn content_ref(&self) -> &HashMap<String, Vec<u8>> {
+        &self.content
+    }
+    /// Returns an iterator over the digests and contents of the message
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &[u8])> {
+        self.content.iter().map(|(digest, content)| (digest.as_str(), content.as_slice()))
+    }
+    /// Returns the content of the message, leaving none in the message
+    pub fn take_content(&mut self) -> HashMap<String, Vec<u8>> {
+        std::mem::take(&mut self.content)
+    }
+    /// Returns the sender, type and content of the message
+    pub fn into_parts(self) -> (String, MessageType, HashMap<String, Vec<u8>>) {
+        (self.sender, self.message_type, self.content)
+    }
 }
 impl Message for ContentMessage {
     fn protocol(&self) -> u8 {
         MESSAGE_PROTOCOL_CONTENT_MESSAGE
     }
+
+    fn check_limits(&self) -> Result<(), Box<dyn Error>> {
+        message::check_address("sender", &self.sender)?;
+        message::check_digests("content", self.content.keys())?;
+        message::check_digests("redirected", self.redirects.keys())?;
+        for provider in self.redirects.values() {
+            message::check_address("provider", provider)?;
+        }
+        message::check_digests("sequenced", self.sequences.keys())?;
+        for sequence in self.sequences.values() {
+            message::check_address("origin", &sequence.origin)?;
+        }
+        message::check_digests("originated", self.origins.keys())?;
+        for origin in self.origins.values() {
+            message::check_address("origin id", origin)?;
+        }
+        message::check_digests("revocation", self.revocations.iter())?;
+        Ok(())
+    }
+}
+
+/// A keepalive probe, answered with a [PongMessage], see
+/// [GossipConfig::set_keepalive](crate::GossipConfig::set_keepalive)
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PingMessage {
+    sender: String,
+    /// Address of the connection the message was received from, set by the receiver
+    #[serde(skip)]
+    source: Option<SocketAddr>,
+    /// Connection on which the sender reads the responses, set by the receiver
+    #[serde(skip)]
+    reply: Option<ReplyStream>,
+}
+impl PingMessage {
+    pub fn new(sender: String) -> Self {
+        PingMessage {
+            sender,
+            source: None,
+            reply: None,
+        }
+    }
+    pub fn sender(&self) -> &str {
+        &self.sender
+    }
+    pub(crate) fn set_source(&mut self, source: Option<SocketAddr>) {
+        self.source = source
+    }
+    pub(crate) fn set_reply_stream(&mut self, reply: Option<ReplyStream>) {
+        self.reply = reply
+    }
+    pub(crate) fn reply_stream(&self) -> Option<&ReplyStream> {
+        self.reply.as_ref()
+    }
+    /// Returns the address of the connection the message was received from, if it was received from the network
+    pub fn source(&self) -> Option<SocketAddr> {
+        self.source
+    }
+}
+impl Message for PingMessage {
+    fn protocol(&self) -> u8 {
+        MESSAGE_PROTOCOL_PING_MESSAGE
+    }
+
+    fn check_limits(&self) -> Result<(), Box<dyn Error>> {
+        message::check_address("sender", &self.sender)
+    }
+}
+
+/// The answer to a [PingMessage], which only shows that its sender is alive
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PongMessage {
+    sender: String,
+}
+impl PongMessage {
+    pub fn new(sender: String) -> Self {
+        PongMessage {
+            sender,
+        }
+    }
+    pub fn sender(&self) -> &str {
+        &self.sender
+    }
+}
+impl Message for PongMessage {
+    fn protocol(&self) -> u8 {
+        MESSAGE_PROTOCOL_PONG_MESSAGE
+    }
+
+    fn check_limits(&self) -> Result<(), Box<dyn Error>> {
+        message::check_address("sender", &self.sender)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use crate::message::MessageType;
+    use super::ContentMessage;
+
+    fn response() -> ContentMessage {
+        ContentMessage::new_response("127.0.0.1:9000".to_owned(), HashMap::from([
+            ("digest-1".to_owned(), b"first".to_vec()),
+            ("digest-2".to_owned(), b"second".to_vec()),
+        ]))
+    }
+
+    #[test]
+    fn content_is_borrowed() {
+        let message = response();
+        assert_eq!(2, message.content_ref().len());
+        assert_eq!(b"first".as_slice(), message.content_ref()["digest-1"].as_slice());
+        // the message is still usable
+        assert_eq!("127.0.0.1:9000", message.sender());
+    }
+
+    #[test]
+    fn content_is_iterated() {
+        let message = response();
+        let mut content: Vec<(&str, &[u8])> = message.iter().collect();
+        content.sort();
+        assert_eq!(vec![("digest-1", b"first".as_slice()), ("digest-2", b"second".as_slice())], content);
+        assert!(!message.is_empty());
+    }
+
+    #[test]
+    fn content_is_taken() {
+        let mut message = response();
+        let content = message.take_content();
+        assert_eq!(2, content.len());
+        assert!(message.is_empty());
+        assert!(message.take_content().is_empty());
+        assert_eq!("127.0.0.1:9000", message.sender());
+    }
+
+    #[test]
+    fn message_is_split_into_parts() {
+        let (sender, message_type, content) = response().into_parts();
+        assert_eq!("127.0.0.1:9000", sender);
+        assert!(matches!(message_type, MessageType::Response));
+        assert_eq!(b"second".as_slice(), content["digest-2"].as_slice());
+    }
+
+    #[test]
+    fn content_is_still_moved_out() {
+        // the consuming accessor is kept for compatibility
+        let content: fn(ContentMessage) -> HashMap<String, Vec<u8>> = ContentMessage::content;
+        assert_eq!(2, content(response()).len());
+    }
 }
@@ -1,6 +1,10 @@
 use std::fmt::Debug;
+use std::error::Error;
+use std::net::SocketAddr;
+use crate::network::ReplyStream;
 use serde::{Serialize, Deserialize};
 use crate::peer::Peer;
+use crate::estimate::SizeEstimate;
 use crate::message::{self, Message, MESSAGE_PROTOCOL_SAMPLING_MESSAGE};
 
 /// A peer sampling protocol message
@@ -12,27 +16,60 @@ pub struct PeerSamplingMessage {
     message_type: message::MessageType,
     /// The view of the sender
     view: Option<Vec<Peer>>,
+    /// Whether the sender is leaving the network
+    #[serde(default)]
+    leaving: bool,
+    /// Name of the cluster of the sender
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    cluster: Option<String>,
+    /// State of the size estimation of the sender, if it estimates the size of the network
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    estimate: Option<SizeEstimate>,
+    /// Address of the connection the message was received from, set by the receiver
+    #[serde(skip)]
+    source: Option<SocketAddr>,
+    /// Connection on which the sender reads the responses, set by the receiver
+    #[serde(skip)]
+    reply: Option<ReplyStream>,
 }
 
 impl PeerSamplingMessage {
-    /// Creates a new message of type [MessageType::Request] containing a view
+    /// Creates a new message of type [MessageType::Request](message::MessageType::Request) containing a view
     pub fn new_request(sender: String, view: Option<Vec<Peer>>) -> Self {
         Self::new(sender, message::MessageType::Request, view)
     }
 
-    /// Creates a new message of type [MessageType::Response] containing a view
+    /// Creates a new message of type [MessageType::Response](message::MessageType::Response) containing a view
     pub fn new_response(sender: String, view: Option<Vec<Peer>>) -> Self {
         Self::new(sender, message::MessageType::Response, view)
     }
 
+    /// Creates a new message of type [MessageType::Request](message::MessageType::Request) notifying that the sender is leaving the network
+    pub fn new_leave(sender: String) -> Self {
+        let mut message = Self::new(sender, message::MessageType::Request, None);
+        message.leaving = true;
+        message
+    }
+
     fn new(sender: String, message_type: message::MessageType, view: Option<Vec<Peer>>) -> Self {
         Self {
             sender,
             message_type,
-            view
+            view,
+            leaving: false,
+            cluster: None,
+            estimate: None,
+            source: None,
+            reply: None,
         }
     }
 
+    /// Sets the name of the cluster of the sender
+    pub fn with_cluster(mut self, cluster: Option<String>) -> Self {
+        self.cluster = cluster;
+        self
+    }
+
     /// Returns the message sender
     pub fn sender(&self) -> &str {
         &self.sender
@@ -47,10 +84,62 @@ impl PeerSamplingMessage {
     pub fn view(&self) -> &Option<Vec<Peer>> {
         &self.view
     }
+
+    /// Returns whether the sender is leaving the network
+    pub fn is_leaving(&self) -> bool {
+        self.leaving
+    }
+
+    /// Returns the name of the cluster of the sender
+    pub fn cluster(&self) -> Option<&str> {
+        self.cluster.as_deref()
+    }
+
+    /// Sets the state of the size estimation of the sender
+    pub(crate) fn with_estimate(mut self, estimate: Option<SizeEstimate>) -> Self {
+        self.estimate = estimate;
+        self
+    }
+
+    pub(crate) fn estimate(&self) -> Option<&SizeEstimate> {
+        self.estimate.as_ref()
+    }
+
+    pub(crate) fn set_source(&mut self, source: Option<SocketAddr>) {
+        self.source = source;
+    }
+
+    pub(crate) fn set_reply_stream(&mut self, reply: Option<ReplyStream>) {
+        self.reply = reply;
+    }
+
+    /// Returns the connection on which the sender reads the responses, if it asked for them to be written on the
+    /// connection of the message
+    pub(crate) fn reply_stream(&self) -> Option<&ReplyStream> {
+        self.reply.as_ref()
+    }
+
+    /// Returns the address of the connection the message was received from, if it was received from the network.
+    /// Its IP may differ from the one of the [sender](Self::sender) behind a NAT, and its port is the ephemeral
+    /// port of the connection.
+    pub fn source(&self) -> Option<SocketAddr> {
+        self.source
+    }
 }
 
 impl Message for PeerSamplingMessage {
     fn protocol(&self) -> u8 {
         MESSAGE_PROTOCOL_SAMPLING_MESSAGE
     }
+
+    fn check_limits(&self) -> Result<(), Box<dyn Error>> {
+        message::check_address("sender", &self.sender)?;
+        for peer in self.view.iter().flatten() {
+            message::check_address("peer", peer.address())?;
+        }
+        if let Some(estimate) = &self.estimate {
+            estimate.check_limits()?;
+        }
+        Ok(())
+    }
 }
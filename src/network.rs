@@ -1,13 +1,40 @@
-use std::net::{SocketAddr, TcpStream};
-use std::io::{Write, Read};
+use std::net::{IpAddr, Shutdown, SocketAddr, TcpStream, TcpListener};
+use std::io::{ErrorKind, Write, Read};
 use std::thread::JoinHandle;
-use std::sync::Arc;
+use std::sync::{Arc, Condvar, Mutex, RwLock};
+use std::collections::{HashMap, VecDeque};
+use std::collections::hash_map::Entry;
 use std::error::Error;
+use std::time::{Duration, Instant};
 use serde::Serialize;
-use std::sync::mpsc::Sender;
-use crate::message::{Message, MASK_MESSAGE_PROTOCOL, MESSAGE_PROTOCOL_SAMPLING_MESSAGE, MESSAGE_PROTOCOL_HEADER_MESSAGE, MESSAGE_PROTOCOL_CONTENT_MESSAGE, MESSAGE_PROTOCOL_NOOP_MESSAGE};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{SyncSender, TrySendError};
+use crate::message::{Message, MASK_MESSAGE_PROTOCOL, MESSAGE_PROTOCOL_SAMPLING_MESSAGE, MESSAGE_PROTOCOL_HEADER_MESSAGE, MESSAGE_PROTOCOL_CONTENT_MESSAGE, MESSAGE_PROTOCOL_NOOP_MESSAGE, MESSAGE_PROTOCOL_PING_MESSAGE, MESSAGE_PROTOCOL_PONG_MESSAGE, MESSAGE_FLAG_CLUSTER, MESSAGE_FLAG_REPLY_IN_STREAM};
 use crate::message::sampling::PeerSamplingMessage;
-use crate::message::gossip::{HeaderMessage, ContentMessage};
+use crate::message::gossip::{HeaderMessage, ContentMessage, PingMessage, PongMessage};
+use crate::inbox::{Inbound, InboxSender};
+use crate::error::BindError;
+use crate::config::{GossipConfig, NetworkConfig, ReplyAddress};
+use crate::error_log::{ErrorCategory, ErrorLog};
+use crate::quarantine::Quarantine;
+use crate::traffic::TrafficLog;
+use crate::logging::targets::NETWORK;
+
+/// Initial capacity of the buffer of a serialized message, enough for the messages without updates
+const WIRE_BUFFER_CAPACITY: usize = 256;
+
+/// Interval at which a listener worker retries to queue a message while the queue is full
+const QUEUE_RETRY_INTERVAL: Duration = Duration::from_millis(1);
+
+/// Size of the chunks in which a connection is read
+const READ_CHUNK_SIZE: usize = 4096;
+
+/// Transfer rate (bytes per second) at which the bytes received on a connection extend its deadline, so that a large
+/// message sent steadily is not cut off while a peer dripping bytes still is
+const MIN_TRANSFER_RATE: u64 = 64 * 1024;
+
+/// Size of the length preceding each response written on the connection of a request
+const REPLY_LENGTH_SIZE: usize = 4;
 
 /// Sends a message to the specified address
 ///
@@ -15,90 +42,1197 @@ use crate::message::gossip::{HeaderMessage, ContentMessage};
 ///
 /// * `address` - Address of the recipient
 /// * `message` - Message implementing the [Message] trait
-pub fn send<M>(address: &SocketAddr, message: Box<M>) -> Result<usize, Box<dyn Error>>
+/// * `cluster_id` - Cluster of the sender, if any
+/// * `network` - Options of the connection
+pub fn send<M>(address: &SocketAddr, message: &M, cluster_id: Option<u8>, network: &NetworkConfig) -> Result<usize, Box<dyn Error>>
+where M: Message + Serialize
+{
+    send_traced(address, message, cluster_id, network, None)
+}
+
+/// Callback receiving the bytes of a message before they are written
+pub(crate) type WriteTrace<'a> = &'a dyn Fn(&[u8]);
+
+/// Sends a message to the specified address, handing its bytes to the tracer before they are written
+///
+/// # Arguments
+///
+/// * `address` - Address of the recipient
+/// * `message` - Message implementing the [Message] trait
+/// * `cluster_id` - Cluster of the sender, if any
+/// * `network` - Options of the connection
+/// * `trace` - Called with the bytes of the message, if any
+pub(crate) fn send_traced<M>(address: &SocketAddr, message: &M, cluster_id: Option<u8>, network: &NetworkConfig, trace: Option<WriteTrace>) -> Result<usize, Box<dyn Error>>
+where M: Message + Serialize
+{
+    // connect first, so that no time is spent serializing a message for a peer that is not listening
+    let mut stream = connect(address, network, None)?;
+    let bytes = to_wire_bytes(message, cluster_id)?;
+    if let Some(trace) = trace {
+        trace(&bytes);
+    }
+    stream.write_all(&bytes)?;
+    Ok(bytes.len())
+}
+
+/// Sends a message to the specified address, failing if the connection or the write takes longer than the timeout
+///
+/// # Arguments
+///
+/// * `address` - Address of the recipient
+/// * `message` - Message implementing the [Message] trait
+/// * `cluster_id` - Cluster of the sender, if any
+/// * `network` - Options of the connection
+/// * `timeout` - Maximum time for connecting, and then for writing
+pub fn send_with_timeout<M>(address: &SocketAddr, message: &M, cluster_id: Option<u8>, network: &NetworkConfig, timeout: Duration) -> Result<usize, Box<dyn Error>>
+where M: Message + Serialize
+{
+    let mut stream = connect(address, network, Some(timeout))?;
+    let bytes = to_wire_bytes(message, cluster_id)?;
+    stream.set_write_timeout(Some(timeout))?;
+    stream.write_all(&bytes)?;
+    Ok(bytes.len())
+}
+
+/// Sends a request whose receiver writes its responses on the same connection, see
+/// [GossipConfig::set_outbound_only]. The connection is returned once the request is written and the writing half of
+/// the connection closed, so that the receiver reads the request to its end; the responses are read with [read_replies].
+///
+/// # Arguments
+///
+/// * `address` - Address of the recipient
+/// * `message` - Message implementing the [Message] trait
+/// * `cluster_id` - Cluster of the sender, if any
+/// * `network` - Options of the connection
+/// * `trace` - Called with the bytes of the message, if any
+pub(crate) fn send_for_replies<M>(address: &SocketAddr, message: &M, cluster_id: Option<u8>, network: &NetworkConfig, trace: Option<WriteTrace>) -> Result<(usize, TcpStream), Box<dyn Error>>
 where M: Message + Serialize
 {
-    match message.as_bytes() {
-        Ok(mut bytes) => {
-            // insert protocol byte for deserialization
-            bytes.insert(0, message.protocol());
-            let written = TcpStream::connect(address)?.write(&bytes)?;
-            Ok(written)
+    let mut stream = connect(address, network, None)?;
+    let mut bytes = to_wire_bytes(message, cluster_id)?;
+    bytes[0] |= MESSAGE_FLAG_REPLY_IN_STREAM;
+    if let Some(trace) = trace {
+        trace(&bytes);
+    }
+    stream.write_all(&bytes)?;
+    stream.shutdown(Shutdown::Write)?;
+    Ok((bytes.len(), stream))
+}
+
+/// Connection of a request whose sender reads the responses on it, handed along with the request to its handling
+/// thread. Each response is written preceded by its length (4 bytes, big endian), and the connection is closed when
+/// the request is dropped.
+#[derive(Debug)]
+pub(crate) struct ReplyStream(TcpStream);
+
+impl ReplyStream {
+    /// Writes a response on the connection, handing its bytes to the tracer before they are written
+    ///
+    /// # Arguments
+    ///
+    /// * `message` - Message implementing the [Message] trait
+    /// * `cluster_id` - Cluster of the sender, if any
+    /// * `trace` - Called with the bytes of the message, if any
+    pub fn send_traced<M>(&self, message: &M, cluster_id: Option<u8>, trace: Option<WriteTrace>) -> Result<usize, Box<dyn Error>>
+    where M: Message + Serialize
+    {
+        let bytes = to_wire_bytes(message, cluster_id)?;
+        if let Some(trace) = trace {
+            trace(&bytes);
         }
-        Err(e) => {
-            log::error!("Could not serialize message");
-            Err(e)?
+        let mut stream = &self.0;
+        stream.write_all(&(bytes.len() as u32).to_be_bytes())?;
+        stream.write_all(&bytes)?;
+        Ok(bytes.len())
+    }
+}
+
+/// Splits the bytes read on the connection of a request into the responses written on it
+pub(crate) fn split_replies(mut buffer: &[u8]) -> Result<Vec<&[u8]>, Box<dyn Error>> {
+    let mut replies = Vec::new();
+    while !buffer.is_empty() {
+        if buffer.len() < REPLY_LENGTH_SIZE {
+            Err("Truncated length of response")?
+        }
+        let (length, rest) = buffer.split_at(REPLY_LENGTH_SIZE);
+        let length = u32::from_be_bytes([length[0], length[1], length[2], length[3]]) as usize;
+        if length == 0 || rest.len() < length {
+            Err(format!("Invalid response of {} bytes, {} bytes left", length, rest.len()))?
         }
+        let (reply, rest) = rest.split_at(length);
+        replies.push(reply);
+        buffer = rest;
+    }
+    Ok(replies)
+}
+
+/// Opens an outbound connection with the configured options
+///
+/// # Arguments
+///
+/// * `address` - Address of the recipient
+/// * `network` - Options of the connection
+/// * `timeout` - Maximum time for connecting, if any
+fn connect(address: &SocketAddr, network: &NetworkConfig, timeout: Option<Duration>) -> std::io::Result<TcpStream> {
+    let stream = match timeout {
+        Some(timeout) => TcpStream::connect_timeout(address, timeout)?,
+        None => TcpStream::connect(address)?,
+    };
+    stream.set_nodelay(network.nodelay())?;
+    #[cfg(feature = "socket2")]
+    if let Some(keepalive) = network.keepalive() {
+        let keepalive = socket2::TcpKeepalive::new().with_time(Duration::from_millis(keepalive));
+        socket2::SockRef::from(&stream).set_tcp_keepalive(&keepalive)?;
+    }
+    Ok(stream)
+}
+
+/// Serializes a message, preceded by its protocol byte and cluster id for deserialization
+pub(crate) fn to_wire_bytes<M>(message: &M, cluster_id: Option<u8>) -> Result<Vec<u8>, Box<dyn Error>>
+where M: Message + Serialize
+{
+    // the header is written first so that the serialized message never has to be shifted
+    let mut bytes = Vec::with_capacity(WIRE_BUFFER_CAPACITY);
+    match cluster_id {
+        Some(cluster_id) => bytes.extend_from_slice(&[message.protocol() | MESSAGE_FLAG_CLUSTER, cluster_id]),
+        None => bytes.push(message.protocol()),
+    }
+    if let Err(e) = message.write_bytes(&mut bytes) {
+        node_log!(Error, NETWORK, "Could not serialize message");
+        Err(e)?
+    }
+    Ok(bytes)
+}
+
+/// Returns the address to which the responses to a message are sent, or `None` if the message must be dropped
+///
+/// # Arguments
+///
+/// * `sender` - Address reported by the sender of the message
+/// * `source` - Address of the connection the message was received from, if it was received from the network
+/// * `policy` - How a mismatch between the two addresses is handled
+pub(crate) fn reply_address(sender: SocketAddr, source: Option<SocketAddr>, policy: ReplyAddress) -> Option<SocketAddr> {
+    // the ports never match, the observed one being the ephemeral port of the connection
+    let Some(source) = source.filter(|source| source.ip() != sender.ip()) else {
+        return Some(sender);
+    };
+    match policy {
+        ReplyAddress::SelfReported => Some(sender),
+        ReplyAddress::ObservedIp => Some(SocketAddr::new(source.ip(), sender.port())),
+        ReplyAddress::RejectMismatch => None,
+    }
+}
+
+/// Returns the address against which the messages of a sender are accounted, for the quarantine and the digest
+/// mismatches: the port reported by the sender on the IP its connection was observed from, so that a node cannot
+/// have its messages accounted to a peer of another host. A sender whose address cannot be parsed, or whose message
+/// was not received from the network, is accounted under the address it reports.
+///
+/// # Arguments
+///
+/// * `sender` - Address reported by the sender of the message
+/// * `source` - Address of the connection the message was received from, if it was received from the network
+pub(crate) fn observed_sender(sender: &str, source: Option<SocketAddr>) -> String {
+    match (sender.parse::<SocketAddr>(), source) {
+        (Ok(sender), Some(source)) => SocketAddr::new(source.ip(), sender.port()).to_string(),
+        _ => sender.to_owned(),
     }
 }
 
-/// Starts listening to TCP connections
+/// Returns an address on which a listener bound to the given address can be reached from the local host: the
+/// loopback address replaces an unspecified IP such as `0.0.0.0`
+pub(crate) fn local_address(bound: SocketAddr) -> SocketAddr {
+    match bound.ip() {
+        IpAddr::V4(ip) if ip.is_unspecified() => SocketAddr::new(IpAddr::V4(std::net::Ipv4Addr::LOCALHOST), bound.port()),
+        IpAddr::V6(ip) if ip.is_unspecified() => SocketAddr::new(IpAddr::V6(std::net::Ipv6Addr::LOCALHOST), bound.port()),
+        _ => bound,
+    }
+}
+
+/// Binds a TCP listener to the specified address
 ///
 /// # Arguments
 ///
 /// * `address` - Bind address
+/// * `network` - Options of the listener
+pub fn bind(address: &SocketAddr, network: &NetworkConfig) -> Result<TcpListener, BindError> {
+    bind_socket(address, network).map_err(|e| BindError::new(*address, e))
+}
+
+#[cfg(feature = "socket2")]
+fn bind_socket(address: &SocketAddr, network: &NetworkConfig) -> std::io::Result<TcpListener> {
+    use socket2::{Domain, Protocol, Socket, Type};
+    let socket = Socket::new(Domain::for_address(*address), Type::STREAM, Some(Protocol::TCP))?;
+    socket.set_reuse_address(network.reuse_address())?;
+    socket.bind(&(*address).into())?;
+    // same backlog as the standard library
+    socket.listen(128)?;
+    Ok(socket.into())
+}
+
+#[cfg(not(feature = "socket2"))]
+fn bind_socket(address: &SocketAddr, _network: &NetworkConfig) -> std::io::Result<TcpListener> {
+    TcpListener::bind(address)
+}
+
+/// State of the message queues of a service, shared by its dispatcher and its listener
+#[derive(Default)]
+pub(crate) struct MessageQueues {
+    /// Number of messages dropped because their queue stayed full
+    pub dropped: AtomicU64,
+    /// Set when a message is dispatched to a handling thread that has stopped
+    pub disconnected: AtomicBool,
+    /// Number of connections closed because they were not read within the connection deadline
+    pub slow_connections: AtomicU64,
+    /// Number of connections closed without being read because their IP address had too many connections or was quarantined
+    pub refused_connections: AtomicU64,
+}
+
+/// Bounds on the connections of the peers, see [GossipConfig::set_connection_limits](crate::GossipConfig::set_connection_limits)
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct ConnectionLimits {
+    /// Time within which a connection must deliver its whole message
+    pub deadline: Duration,
+    /// Maximum number of connections of an IP address being read or waiting for a worker
+    pub max_per_ip: usize,
+}
+
+impl ConnectionLimits {
+    pub fn from_config(config: &GossipConfig) -> Self {
+        ConnectionLimits {
+            deadline: Duration::from_millis(config.connection_deadline()),
+            max_per_ip: config.max_connections_per_ip(),
+        }
+    }
+}
+
+/// Number of connections of each IP address being read or waiting for a worker
+type ConnectionCounts = Arc<Mutex<HashMap<IpAddr, usize>>>;
+
+/// A connection counted for its IP address, until it is dropped
+struct ConnectionSlot {
+    counts: ConnectionCounts,
+    ip: IpAddr,
+}
+
+impl ConnectionSlot {
+    /// Counts a connection of an IP address, unless the address already has the maximum number of connections
+    fn acquire(counts: &ConnectionCounts, ip: IpAddr, max_per_ip: usize) -> Option<Self> {
+        let mut current = counts.lock().unwrap();
+        let count = current.entry(ip).or_insert(0);
+        if *count >= max_per_ip {
+            return None;
+        }
+        *count += 1;
+        Some(ConnectionSlot { counts: Arc::clone(counts), ip })
+    }
+}
+
+impl Drop for ConnectionSlot {
+    fn drop(&mut self) {
+        let mut counts = self.counts.lock().unwrap();
+        if let Entry::Occupied(mut entry) = counts.entry(self.ip) {
+            *entry.get_mut() -= 1;
+            if *entry.get() == 0 {
+                entry.remove();
+            }
+        }
+    }
+}
+
+/// Accepted connections waiting for a listener worker. The workers take them from each IP address in turn, so that
+/// a peer opening many connections does not delay the connections of the other peers.
+struct FairQueue<T> {
+    state: Mutex<FairQueueState<T>>,
+    available: Condvar,
+    /// Maximum number of waiting items, besides those handed over to idle workers
+    capacity: usize,
+}
+
+struct FairQueueState<T> {
+    /// Waiting items of each IP address
+    queues: HashMap<Option<IpAddr>, VecDeque<T>>,
+    /// IP addresses with waiting items, in the order in which they are served
+    turns: VecDeque<Option<IpAddr>>,
+    /// Number of waiting items
+    len: usize,
+    /// Number of workers waiting for an item
+    idle: usize,
+    closed: bool,
+}
+
+impl<T> FairQueue<T> {
+    fn new(capacity: usize) -> Self {
+        FairQueue {
+            state: Mutex::new(FairQueueState { queues: HashMap::new(), turns: VecDeque::new(), len: 0, idle: 0, closed: false }),
+            available: Condvar::new(),
+            capacity,
+        }
+    }
+
+    /// Queues an item of an IP address, or returns it if the queue is full
+    fn push(&self, ip: Option<IpAddr>, item: T) -> Result<(), T> {
+        let mut state = self.state.lock().unwrap();
+        if state.len >= self.capacity + state.idle {
+            return Err(item);
+        }
+        let queue = state.queues.entry(ip).or_default();
+        queue.push_back(item);
+        if queue.len() == 1 {
+            state.turns.push_back(ip);
+        }
+        state.len += 1;
+        self.available.notify_one();
+        Ok(())
+    }
+
+    /// Waits for the item of the IP address whose turn it is; returns `None` once the queue is closed and empty
+    fn pop(&self) -> Option<T> {
+        let mut state = self.state.lock().unwrap();
+        loop {
+            if let Some(ip) = state.turns.pop_front() {
+                let queue = state.queues.get_mut(&ip).expect("an IP address with a turn has waiting items");
+                let item = queue.pop_front();
+                if queue.is_empty() {
+                    state.queues.remove(&ip);
+                }
+                else {
+                    state.turns.push_back(ip);
+                }
+                state.len -= 1;
+                return item;
+            }
+            if state.closed {
+                return None;
+            }
+            state.idle += 1;
+            state = self.available.wait(state).unwrap();
+            state.idle -= 1;
+        }
+    }
+
+    /// Stops the workers once the waiting items are taken
+    fn close(&self) {
+        self.state.lock().unwrap().closed = true;
+        self.available.notify_all();
+    }
+}
+
+/// Dispatches the messages of a service to its message handling thread
+pub struct Dispatcher {
+    /// Updated with the time at which a message is received
+    last_received: Arc<Mutex<Option<Instant>>>,
+    /// Queues of the message handling thread
+    inbox: InboxSender,
+    /// Recent errors of the service
+    errors: Arc<ErrorLog>,
+    /// Lengths of the queues and counters of the undelivered messages
+    queues: Arc<MessageQueues>,
+    /// Time during which a full queue is waited for before dropping a message
+    queue_timeout: Duration,
+    /// Counts the received messages of each peer
+    traffic: Arc<TrafficLog>,
+    /// Quarantine in which the IP addresses of slow connections are recorded, if any
+    quarantine: Option<Arc<Mutex<Quarantine>>>,
+}
+
+impl Dispatcher {
+    /// Creates a new dispatcher
+    ///
+    /// # Arguments
+    ///
+    /// * `last_received` - Updated with the time at which a message is received
+    /// * `inbox` - Queues of the message handling thread, to which the messages are dispatched
+    /// * `errors` - Records the messages that cannot be parsed, and the handling thread if it stopped
+    /// * `queues` - Lengths of the queues and counters of the undelivered messages
+    /// * `queue_timeout` - Time during which a full queue is waited for before dropping a message
+    pub(crate) fn new(last_received: Arc<Mutex<Option<Instant>>>, inbox: InboxSender, errors: Arc<ErrorLog>, queues: Arc<MessageQueues>, queue_timeout: Duration) -> Self {
+        Dispatcher {
+            last_received,
+            inbox,
+            errors,
+            queues,
+            queue_timeout,
+            traffic: Arc::new(TrafficLog::new()),
+            quarantine: None,
+        }
+    }
+
+    /// Sets the log in which the received messages are counted for their sender
+    pub(crate) fn with_traffic(mut self, traffic: Arc<TrafficLog>) -> Self {
+        self.traffic = traffic;
+        self
+    }
+
+    /// Sets the quarantine in which the IP addresses of slow connections are recorded as violations, and whose
+    /// quarantined IP addresses are refused by the listener
+    pub(crate) fn with_quarantine(mut self, quarantine: Arc<Mutex<Quarantine>>) -> Self {
+        self.quarantine = Some(quarantine);
+        self
+    }
+
+    /// Parses a message and hands it to its handling thread. Only the messages that cannot be parsed are
+    /// returned as errors: a message that cannot be queued is dropped, see [queue](Self::queue).
+    ///
+    /// # Arguments
+    ///
+    /// * `protocol` - Protocol of the message
+    /// * `payload` - The serialized message
+    /// * `buffer` - The message as received on the wire, counted in the traffic of its sender and traced
+    /// * `source` - Address of the connection the message was received from, handed along with the message
+    /// * `reply` - Connection on which the sender reads the responses, handed along with the message
+    fn dispatch(&self, protocol: u8, payload: &[u8], buffer: &[u8], source: Option<SocketAddr>, reply: Option<ReplyStream>) -> Result<(), Box<dyn Error>> {
+        if let Some(source) = &source {
+            self.traffic.trace_received(source, buffer);
+        }
+        self.parse_and_send(protocol, payload, buffer.len(), source, reply).inspect_err(|e| self.errors.record(ErrorCategory::Parse, None, e.to_string()))
+    }
+
+    fn parse_and_send(&self, protocol: u8, payload: &[u8], length: usize, source: Option<SocketAddr>, reply: Option<ReplyStream>) -> Result<(), Box<dyn Error>> {
+        let queued = match protocol {
+            MESSAGE_PROTOCOL_SAMPLING_MESSAGE => {
+                let mut message = PeerSamplingMessage::from_bytes(payload)?;
+                message.set_source(source);
+                message.set_reply_stream(reply);
+                self.traffic.record_received(&observed_sender(message.sender(), source), protocol, length);
+                self.queue(Inbound::Sampling(message), "peer sampling")
+            }
+            MESSAGE_PROTOCOL_CONTENT_MESSAGE => {
+                let mut message = ContentMessage::from_bytes(payload)?;
+                message.set_source(source);
+                message.set_reply_stream(reply);
+                self.traffic.record_received(&observed_sender(message.sender(), source), protocol, length);
+                #[cfg(test)]
+                if !self.traffic.intercept(&message) {
+                    return Ok(());
+                }
+                self.queue(Inbound::Content(message), "content")
+            }
+            MESSAGE_PROTOCOL_HEADER_MESSAGE => {
+                let mut message = HeaderMessage::from_bytes(payload)?;
+                message.set_source(source);
+                message.set_reply_stream(reply);
+                self.traffic.record_received(&observed_sender(message.sender(), source), protocol, length);
+                self.queue(Inbound::Header(message), "header")
+            }
+            MESSAGE_PROTOCOL_PING_MESSAGE => {
+                let mut message = PingMessage::from_bytes(payload)?;
+                message.set_source(source);
+                message.set_reply_stream(reply);
+                self.traffic.record_received(&observed_sender(message.sender(), source), protocol, length);
+                self.queue(Inbound::Ping(message), "ping")
+            }
+            // the time at which the sender was last heard from is all that a pong brings
+            MESSAGE_PROTOCOL_PONG_MESSAGE => {
+                let message = PongMessage::from_bytes(payload)?;
+                self.traffic.record_received(&observed_sender(message.sender(), source), protocol, length);
+                true
+            }
+            _ => Err(format!("Unknown protocol: {}", protocol))?
+        };
+        if queued {
+            self.last_received.lock().unwrap().replace(Instant::now());
+        }
+        Ok(())
+    }
+
+    /// Hands a message to the handling thread, and returns whether it was queued. While the queue of its kind is
+    /// full, the listener worker retries until the queue timeout and then drops the message. A message whose
+    /// handling thread has stopped is dropped as well, and the service is marked degraded: the handling thread only
+    /// stops after the listener, unless it fails.
+    ///
+    /// # Arguments
+    ///
+    /// * `message` - The parsed message
+    /// * `kind` - Name of the queue, for logging
+    fn queue(&self, mut message: Inbound, kind: &str) -> bool {
+        let deadline = Instant::now() + self.queue_timeout;
+        loop {
+            match self.inbox.try_send(message) {
+                Ok(()) => break true,
+                Err(TrySendError::Full(returned)) if Instant::now() < deadline => {
+                    message = returned;
+                    std::thread::sleep(QUEUE_RETRY_INTERVAL);
+                }
+                Err(TrySendError::Full(_)) => {
+                    node_log!(Warn, NETWORK, "Queue of {} messages is full, dropping message", kind);
+                    self.queues.dropped.fetch_add(1, Ordering::SeqCst);
+                    break false;
+                }
+                Err(TrySendError::Disconnected(_)) => {
+                    if !self.queues.disconnected.swap(true, Ordering::SeqCst) {
+                        node_log!(Error, NETWORK, "Handling thread of {} messages has stopped, service is degraded", kind);
+                        self.errors.record(ErrorCategory::Handler, None, format!("handling thread of {} messages has stopped", kind));
+                    }
+                    break false;
+                }
+            }
+        }
+    }
+}
+
+/// Routes received messages to the dispatcher of their cluster
+#[derive(Default)]
+pub struct Router {
+    /// Dispatchers by cluster id; messages without cluster id go to the `None` entry
+    routes: RwLock<HashMap<Option<u8>, Dispatcher>>,
+    /// Recent errors of the service of a single dispatcher, which also records the connections that cannot be read
+    errors: Option<Arc<ErrorLog>>,
+    /// Counters of the service of a single dispatcher, which also counts the slow and refused connections
+    queues: Option<Arc<MessageQueues>>,
+    /// Quarantine of the service of a single dispatcher, if any
+    quarantine: Option<Arc<Mutex<Quarantine>>>,
+}
+
+impl Router {
+    /// Creates a router with a single dispatcher
+    ///
+    /// # Arguments
+    ///
+    /// * `cluster_id` - Cluster of the messages to dispatch
+    /// * `dispatcher` - The dispatcher
+    pub fn single(cluster_id: Option<u8>, dispatcher: Dispatcher) -> Self {
+        let router = Router {
+            errors: Some(Arc::clone(&dispatcher.errors)),
+            queues: Some(Arc::clone(&dispatcher.queues)),
+            quarantine: dispatcher.quarantine.clone(),
+            ..Router::default()
+        };
+        router.routes.write().unwrap().insert(cluster_id, dispatcher);
+        router
+    }
+
+    /// Adds the dispatcher of a cluster, failing if the cluster already has one
+    pub fn register(&self, cluster_id: Option<u8>, dispatcher: Dispatcher) -> Result<(), Box<dyn Error>> {
+        match self.routes.write().unwrap().entry(cluster_id) {
+            Entry::Occupied(_) => Err(format!("Cluster {:?} is already registered", cluster_id))?,
+            Entry::Vacant(entry) => {
+                entry.insert(dispatcher);
+                Ok(())
+            }
+        }
+    }
+
+    /// Removes the dispatcher of a cluster, which drops its message senders
+    pub fn unregister(&self, cluster_id: Option<u8>) {
+        self.routes.write().unwrap().remove(&cluster_id);
+    }
+
+    /// Returns whether connections of an IP address are refused because it is quarantined
+    fn is_quarantined(&self, ip: &IpAddr) -> bool {
+        self.quarantine.as_ref().is_some_and(|quarantine| quarantine.lock().unwrap().is_ip_quarantined(ip))
+    }
+
+    fn record_refused_connection(&self) {
+        if let Some(queues) = &self.queues {
+            queues.refused_connections.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    /// Counts a connection closed at the deadline, as a violation of its IP address
+    fn record_slow_connection(&self, peer: Option<SocketAddr>) {
+        if let Some(queues) = &self.queues {
+            queues.slow_connections.fetch_add(1, Ordering::SeqCst);
+        }
+        if let (Some(quarantine), Some(peer)) = (&self.quarantine, peer) {
+            quarantine.lock().unwrap().record_connection_violation(peer.ip());
+        }
+    }
+
+    /// Records a connection that could not be read
+    fn record_receive_error(&self, peer: Option<SocketAddr>, error: String) {
+        if let Some(errors) = &self.errors {
+            let peer = peer.map(|peer| peer.to_string());
+            errors.record(ErrorCategory::Receive, peer.as_deref(), error);
+        }
+    }
+
+    /// Dispatches a received message, along with the address of the connection it was received from and the
+    /// connection on which its sender reads the responses, if any
+    pub(crate) fn route(&self, buffer: &[u8], source: Option<SocketAddr>, reply: Option<ReplyStream>) -> Result<(), Box<dyn Error>> {
+        let flags = *buffer.first().ok_or("Empty message")?;
+        let protocol = flags & MASK_MESSAGE_PROTOCOL;
+        if protocol == MESSAGE_PROTOCOL_NOOP_MESSAGE {
+            return Ok(());
+        }
+        let (cluster_id, payload) = if flags & MESSAGE_FLAG_CLUSTER != 0 {
+            (Some(*buffer.get(1).ok_or("Missing cluster id")?), &buffer[2..])
+        }
+        else {
+            (None, &buffer[1..])
+        };
+        match self.routes.read().unwrap().get(&cluster_id) {
+            Some(dispatcher) => dispatcher.dispatch(protocol, payload, buffer, source, reply),
+            None => {
+                node_log!(Debug, NETWORK, "Dropping message for unknown cluster {:?}", cluster_id);
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Starts listening to TCP connections.
+///
+/// Accepted connections are queued and read by a pool of worker threads, which take the connections of each IP address
+/// in turn. When the queue is full, new connections are closed without being read, as are the connections of an IP
+/// address that already has the maximum number of connections queued or being read, or that is quarantined. A
+/// connection that has not delivered its whole message at the deadline, counted from its acceptance and extended by the
+/// time its bytes take at [MIN_TRANSFER_RATE], is closed, and its message dropped; a connection that waited for a
+/// worker until the deadline is closed without being read, and counted as refused.
+///
+/// Once shutdown is requested, the listener is woken up by a connection of its own: the connection that wakes it up
+/// is still queued, so that a peer connection accepted at that time is not lost, and the listener stops accepting.
+/// Connections not accepted yet are reset when the listener is closed. The workers drain the queue, reading and
+/// dispatching the messages of the accepted connections, and the listener thread terminates once they are done.
+///
+/// # Arguments
+///
+/// * `listener` - Bound listener, see [bind]
 /// * `shutdown` - Flag used to check for a shutdown request
-/// * `peer_sampling_sender` - Used to dispatch peer sampling messages
-/// * `header_sender` - Used to dispatch gossip header messages
-/// * `content_sender` - Used to dispatch gossip content messages
-pub fn listen(address: &SocketAddr, shutdown: Arc<std::sync::atomic::AtomicBool>, peer_sampling_sender: Sender<PeerSamplingMessage>, header_sender: Sender<HeaderMessage>, content_sender: Sender<ContentMessage>) -> std::io::Result<JoinHandle<()>> {
-
-    let listener = std::net::TcpListener::bind(address)?;
-    log::info!("Listener started at {}", address);
-    Ok(std::thread::Builder::new().name(format!("{} - gossip listener", address)).spawn(move || {
-        log::info!("Started listener thread");
-        // TODO: handle hanging connections where peer connect but does not write
+/// * `workers` - Number of threads reading connections
+/// * `backlog` - Maximum number of accepted connections waiting for a worker
+/// * `limits` - Deadline of the connections and maximum number of connections of an IP address
+/// * `router` - Used to dispatch the received messages
+pub(crate) fn listen(listener: TcpListener, shutdown: Arc<std::sync::atomic::AtomicBool>, workers: usize, backlog: usize, limits: ConnectionLimits, router: Arc<Router>) -> std::io::Result<JoinHandle<()>> {
+
+    let address = listener.local_addr()?;
+    // the threads log for the node starting the listener, or for the listener itself if it is shared
+    let node = crate::logging::current_node().unwrap_or_else(|| address.to_string());
+    let connection_counts = ConnectionCounts::default();
+
+    // start workers
+    let streams = Arc::new(FairQueue::<(TcpStream, Instant, Option<ConnectionSlot>)>::new(backlog));
+    let mut worker_handles = Vec::with_capacity(workers);
+    for worker in 0..workers.max(1) {
+        let streams = Arc::clone(&streams);
+        let router = Arc::clone(&router);
+        let node = node.clone();
+        let handle = std::thread::Builder::new().name(format!("{} - listener worker {}", address, worker)).spawn(move || {
+            crate::logging::set_node(node);
+            // the slot is released once the connection is read
+            while let Some((stream, accepted, _slot)) = streams.pop() {
+                // the wait is not a violation of the peer
+                if accepted.elapsed() >= limits.deadline {
+                    node_log!(Warn, NETWORK, "Closing connection that waited for a worker until the deadline");
+                    router.record_refused_connection();
+                    continue;
+                }
+                read_stream(stream, &router, accepted, limits.deadline);
+            }
+        })?;
+        worker_handles.push(handle);
+    }
+
+    node_log!(Info, NETWORK, "Listener started at {}", address);
+    std::thread::Builder::new().name(format!("{} - gossip listener", address)).spawn(move || {
+        crate::logging::set_node(node);
+        node_log!(Info, NETWORK, "Started listener thread");
         for incoming_stream in listener.incoming() {
+            match incoming_stream {
+                Ok(stream) => {
+                    let ip = stream.peer_addr().ok().map(|peer| peer.ip());
+                    let slot = match ip {
+                        Some(ip) if router.is_quarantined(&ip) => Err(format!("{} is quarantined", ip)),
+                        Some(ip) => ConnectionSlot::acquire(&connection_counts, ip, limits.max_per_ip)
+                            .map(Some)
+                            .ok_or_else(|| format!("{} has too many connections", ip)),
+                        None => Ok(None),
+                    };
+                    let slot = match slot {
+                        Ok(slot) => slot,
+                        Err(reason) => {
+                            node_log!(Warn, NETWORK, "Closing connection: {}", reason);
+                            router.record_refused_connection();
+                            // the connection may be the one waking up the listener
+                            if shutdown.load(std::sync::atomic::Ordering::SeqCst) {
+                                node_log!(Info, NETWORK, "Shutdown requested");
+                                break;
+                            }
+                            continue;
+                        }
+                    };
+                    if streams.push(ip, (stream, Instant::now(), slot)).is_err() {
+                        node_log!(Warn, NETWORK, "Connection backlog is full, closing connection");
+                    }
+                }
+                Err(e) => node_log!(Warn, NETWORK, "Connection failed: {}", e),
+            }
 
-            // check for shutdown request
+            // check for shutdown request, once the accepted connection is queued
             if shutdown.load(std::sync::atomic::Ordering::SeqCst) {
-                log::info!("Shutdown requested");
+                node_log!(Info, NETWORK, "Shutdown requested");
                 break;
             }
+        }
 
-            // TODO: handle in new thread or worker
-            // handle request
-            match incoming_stream {
-                Ok(mut stream) => {
-                    let mut buf = Vec::new();
-                    match stream.read_to_end(&mut buf) {
-                        Ok(read) => {
-                            if read > 0 {
-                                match handle_message(buf, &peer_sampling_sender, &header_sender, &content_sender) {
-                                    Ok(()) => log::trace!("Message parsed successfully"),
-                                    Err(e) => log::error!("{:?}", e),
-                                }
-                            }
-                        },
-                        Err(e) => log::error!("Error receiving data: {:?}", e),
+        // reset the connections that were not accepted, and let the workers drain the queue
+        drop(listener);
+        streams.close();
+        for handle in worker_handles {
+            if let Err(e) = handle.join() {
+                node_log!(Error, NETWORK, "Error joining listener worker: {:?}", e);
+            }
+        }
+        node_log!(Info, NETWORK, "Listener thread exiting");
+    })
+}
+
+/// Reads a message from an accepted connection and dispatches it, unless the connection is not read within the deadline
+/// counted from its acceptance
+fn read_stream(mut stream: TcpStream, router: &Router, accepted: Instant, deadline: Duration) {
+    let peer = stream.peer_addr().ok();
+    match read_until(&mut stream, accepted + deadline) {
+        Ok(buf) => {
+            if !buf.is_empty() {
+                // the responses are written on the connection within the deadline
+                let reply = match buf[0] & MESSAGE_FLAG_REPLY_IN_STREAM != 0 {
+                    true => stream.set_write_timeout(Some(deadline)).ok().map(|()| ReplyStream(stream)),
+                    false => None,
+                };
+                match router.route(&buf, peer, reply) {
+                    Ok(()) => node_log!(Trace, NETWORK, "Message parsed successfully"),
+                    Err(e) => node_log!(Error, NETWORK, "{:?}", e),
+                }
+            }
+        },
+        Err(e) => {
+            node_log!(Error, NETWORK, "Error receiving data: {:?}", e);
+            if e.kind() == ErrorKind::TimedOut {
+                router.record_slow_connection(peer);
+            }
+            router.record_receive_error(peer, e.to_string());
+        }
+    }
+}
+
+/// Threads reading the responses written on the connections of the requests of a node, see [send_for_replies]. A
+/// connection that finds every thread busy and the queue full is read by the thread that sent its request, which
+/// bounds the number of threads without dropping responses. The threads stop once the readers are dropped.
+pub(crate) struct ReplyReaders {
+    sender: SyncSender<TcpStream>,
+    router: Arc<Router>,
+    deadline: Duration,
+}
+
+impl ReplyReaders {
+    /// Starts the threads reading the responses
+    ///
+    /// # Arguments
+    ///
+    /// * `router` - Dispatcher of the responses
+    /// * `deadline` - Time within which the receivers close the connections
+    /// * `workers` - Number of reader threads, at least one
+    /// * `backlog` - Number of connections waiting for a reader thread
+    pub fn start(router: Arc<Router>, deadline: Duration, workers: usize, backlog: usize) -> std::io::Result<Self> {
+        let node = crate::logging::current_node();
+        let (sender, receiver) = std::sync::mpsc::sync_channel::<TcpStream>(backlog);
+        let receiver = Arc::new(Mutex::new(receiver));
+        for worker in 0..workers.max(1) {
+            let receiver = Arc::clone(&receiver);
+            let router = Arc::clone(&router);
+            let node = node.clone();
+            std::thread::Builder::new().name(format!("{} - reply reader {}", node.as_deref().unwrap_or_default(), worker)).spawn(move || {
+                if let Some(node) = node {
+                    crate::logging::set_node(node);
+                }
+                loop {
+                    // release the queue before reading
+                    let stream = receiver.lock().unwrap().recv();
+                    match stream {
+                        Ok(stream) => read_replies(stream, &router, deadline),
+                        Err(_) => break,
                     }
                 }
-                Err(e) => log::warn!("Connection failed: {}", e),
+            })?;
+        }
+        Ok(ReplyReaders { sender, router, deadline })
+    }
+
+    /// Reads the responses written on the connection of a request, on a reader thread if one is available or queued
+    /// for one, on the calling thread otherwise
+    pub fn read(&self, stream: TcpStream) {
+        match self.sender.try_send(stream) {
+            Ok(()) => (),
+            Err(TrySendError::Full(stream)) | Err(TrySendError::Disconnected(stream)) => read_replies(stream, &self.router, self.deadline),
+        }
+    }
+}
+
+/// Reads the responses written on the connection of a request and dispatches them, unless the connection is not
+/// closed within the deadline, see [send_for_replies]
+pub(crate) fn read_replies(mut stream: TcpStream, router: &Router, deadline: Duration) {
+    let peer = stream.peer_addr().ok();
+    let buf = match read_until(&mut stream, Instant::now() + deadline) {
+        Ok(buf) => buf,
+        Err(e) => {
+            node_log!(Warn, NETWORK, "Error receiving responses: {:?}", e);
+            router.record_receive_error(peer, e.to_string());
+            return;
+        }
+    };
+    match split_replies(&buf) {
+        Ok(replies) => for reply in replies {
+            if let Err(e) = router.route(reply, peer, None) {
+                node_log!(Error, NETWORK, "{:?}", e);
             }
+        },
+        Err(e) => {
+            node_log!(Error, NETWORK, "Error reading responses: {:?}", e);
+            router.record_receive_error(peer, e.to_string());
+        }
+    }
+}
+
+/// Reads a connection to its end, failing with [ErrorKind::TimedOut] if the end is not reached by the deadline,
+/// extended by the time the bytes received take at [MIN_TRANSFER_RATE]: the read timeout only bounds the wait for
+/// each chunk, not the time the peer takes to send them all
+fn read_until(stream: &mut TcpStream, deadline: Instant) -> std::io::Result<Vec<u8>> {
+    let mut buf = Vec::new();
+    let mut chunk = [0; READ_CHUNK_SIZE];
+    loop {
+        let transfer = Duration::from_micros((buf.len() as u64).saturating_mul(1_000_000) / MIN_TRANSFER_RATE);
+        let remaining = (deadline + transfer).saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return Err(std::io::Error::new(ErrorKind::TimedOut, format!("connection deadline exceeded after {} bytes", buf.len())));
+        }
+        stream.set_read_timeout(Some(remaining))?;
+        match stream.read(&mut chunk) {
+            Ok(0) => return Ok(buf),
+            Ok(read) => buf.extend_from_slice(&chunk[..read]),
+            Err(e) if e.kind() == ErrorKind::Interrupted => (),
+            // the read timed out, which the deadline check reports
+            Err(e) if e.kind() == ErrorKind::WouldBlock || e.kind() == ErrorKind::TimedOut => (),
+            Err(e) => return Err(e),
         }
-        log::info!("Listener thread exiting");
-    }).unwrap())
+    }
 }
 
-fn handle_message(buffer: Vec<u8>, peer_sampling_sender: &Sender<PeerSamplingMessage>, header_sender: &Sender<HeaderMessage>, content_sender: &Sender<ContentMessage>) -> Result<(), Box<dyn Error>> {
-    let protocol = buffer[0] & MASK_MESSAGE_PROTOCOL;
-    match protocol {
-        MESSAGE_PROTOCOL_NOOP_MESSAGE => Ok(()),
-        MESSAGE_PROTOCOL_SAMPLING_MESSAGE => {
-            let message = PeerSamplingMessage::from_bytes(&buffer[1..])?;
-            peer_sampling_sender.send(message)?;
-            Ok(())
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::inbox::{inbox, InboxReceiver, Kind};
+
+    fn router() -> (Arc<Router>, Arc<Mutex<Option<Instant>>>, InboxReceiver) {
+        let last_received = Arc::new(Mutex::new(None));
+        let (sender, receiver) = inbox(&[(Kind::Header, 8), (Kind::Content, 8), (Kind::Sampling, 8)], &[]);
+        let dispatcher = Dispatcher::new(Arc::clone(&last_received), sender, Arc::new(ErrorLog::new()), Arc::new(MessageQueues::default()), Duration::from_millis(10));
+        (Arc::new(Router::single(None, dispatcher)), last_received, receiver)
+    }
+
+    #[test]
+    fn connection_accepted_during_shutdown_is_dispatched() {
+        let listener = bind(&"127.0.0.1:0".parse().unwrap(), &NetworkConfig::default()).unwrap();
+        let address = listener.local_addr().unwrap();
+        let (router, _, header_receiver) = router();
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let handle = listen(listener, Arc::clone(&shutdown), 1, 8, ConnectionLimits::from_config(&GossipConfig::default()), router).unwrap();
+
+        // the connection accepted after the shutdown request also wakes up the listener
+        shutdown.store(true, Ordering::SeqCst);
+        send(&address, &HeaderMessage::new_request("127.0.0.1:1".to_owned(), false), None, &NetworkConfig::default()).unwrap();
+        handle.join().unwrap();
+
+        match header_receiver.try_recv().unwrap() {
+            Inbound::Header(message) => {
+                assert_eq!("127.0.0.1:1", message.sender());
+                assert_eq!(Some(address.ip()), message.source().map(|source| source.ip()));
+            }
+            message => panic!("Unexpected message {:?}", message),
+        }
+        assert!(send(&address, &HeaderMessage::new_request("127.0.0.1:1".to_owned(), false), None, &NetworkConfig::default()).is_err());
+    }
+
+    #[test]
+    fn waiting_connections_are_taken_from_each_ip_in_turn() {
+        let flooding: Option<IpAddr> = Some("10.0.0.1".parse().unwrap());
+        let other: Option<IpAddr> = Some("10.0.0.2".parse().unwrap());
+        let queue = FairQueue::new(4);
+        for item in 1..=3 {
+            queue.push(flooding, item).unwrap();
+        }
+        queue.push(other, 4).unwrap();
+        // full
+        assert_eq!(Err(5), queue.push(other, 5));
+
+        assert_eq!(Some(1), queue.pop());
+        assert_eq!(Some(4), queue.pop());
+        assert_eq!(Some(2), queue.pop());
+        queue.close();
+        assert_eq!(Some(3), queue.pop());
+        assert_eq!(None, queue.pop());
+    }
+
+    #[test]
+    fn busy_reply_readers_leave_the_responses_to_the_sending_thread() {
+        // the receiver answers each request after a while
+        let receiver = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = receiver.local_addr().unwrap();
+        let answering = std::thread::spawn(move || {
+            let answers: Vec<_> = receiver.incoming().take(3).map(Result::unwrap).map(|mut stream| std::thread::spawn(move || {
+                std::thread::sleep(Duration::from_millis(200));
+                // the request is read to its end, so that closing the connection does not reset it
+                stream.read_to_end(&mut Vec::new()).unwrap();
+                let response = to_wire_bytes(&HeaderMessage::new_response("127.0.0.1:2".to_owned()), None).unwrap();
+                stream.write_all(&(response.len() as u32).to_be_bytes()).unwrap();
+                stream.write_all(&response).unwrap();
+            })).collect();
+            for answer in answers {
+                answer.join().unwrap();
+            }
+        });
+        let (router, _, header_receiver) = router();
+        let readers = ReplyReaders::start(router, Duration::from_secs(1), 1, 1).unwrap();
+
+        // the reader thread and its queue take two of the connections at most, the sending thread reads another one
+        let request = HeaderMessage::new_request("127.0.0.1:1".to_owned(), true);
+        let started = Instant::now();
+        for _ in 0..3 {
+            readers.read(send_for_replies(&address, &request, None, &NetworkConfig::default(), None).unwrap().1);
         }
-        MESSAGE_PROTOCOL_CONTENT_MESSAGE => {
-            let message = ContentMessage::from_bytes(&buffer[1..])?;
-            content_sender.send(message)?;
-            Ok(())
+        assert!(started.elapsed() >= Duration::from_millis(200));
+        answering.join().unwrap();
+
+        for _ in 0..3 {
+            assert!(matches!(header_receiver.recv_until(Some(Instant::now() + Duration::from_secs(1))).unwrap(), Inbound::Header(_)));
         }
-        MESSAGE_PROTOCOL_HEADER_MESSAGE => {
-            let message = HeaderMessage::from_bytes(&buffer[1..])?;
-            header_sender.send(message)?;
-            Ok(())
+    }
+
+    #[test]
+    fn responses_are_written_on_the_connection_of_the_request() {
+        let listener = bind(&"127.0.0.1:0".parse().unwrap(), &NetworkConfig::default()).unwrap();
+        let address = listener.local_addr().unwrap();
+        let (router, _, header_receiver) = router();
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let handle = listen(listener, Arc::clone(&shutdown), 1, 8, ConnectionLimits::from_config(&GossipConfig::default()), router).unwrap();
+
+        let (_, mut stream) = send_for_replies(&address, &HeaderMessage::new_request("127.0.0.1:1".to_owned(), true), None, &NetworkConfig::default(), None).unwrap();
+        let request = match header_receiver.recv_until(Some(Instant::now() + Duration::from_secs(1))).unwrap() {
+            Inbound::Header(request) => request,
+            message => panic!("Unexpected message {:?}", message),
+        };
+        let reply = request.reply_stream().unwrap();
+        reply.send_traced(&HeaderMessage::new_response("127.0.0.1:2".to_owned()), None, None).unwrap();
+        reply.send_traced(&crate::message::NoopMessage, Some(3), None).unwrap();
+        drop(request);
+
+        let buffer = read_until(&mut stream, Instant::now() + Duration::from_secs(1)).unwrap();
+        let replies = split_replies(&buffer).unwrap();
+        assert_eq!(2, replies.len());
+        assert_eq!(MESSAGE_PROTOCOL_HEADER_MESSAGE, replies[0][0]);
+        assert_eq!(&[MESSAGE_PROTOCOL_NOOP_MESSAGE | MESSAGE_FLAG_CLUSTER, 3, 0xf6], replies[1]);
+        assert!(split_replies(&buffer[..buffer.len() - 1]).is_err());
+
+        shutdown.store(true, Ordering::SeqCst);
+        send(&address, &crate::message::NoopMessage, None, &NetworkConfig::default()).unwrap();
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn reply_address_follows_policy() {
+        let sender: SocketAddr = "10.0.0.1:9000".parse().unwrap();
+        let nat: SocketAddr = "192.168.1.1:54321".parse().unwrap();
+        let same_ip: SocketAddr = "10.0.0.1:54321".parse().unwrap();
+        for policy in [ReplyAddress::SelfReported, ReplyAddress::ObservedIp, ReplyAddress::RejectMismatch] {
+            // messages not received from the network, or from the reported IP, are always answered
+            assert_eq!(Some(sender), reply_address(sender, None, policy));
+            assert_eq!(Some(sender), reply_address(sender, Some(same_ip), policy));
         }
-        _ => Err(format!("Unknown protocol: {}", protocol))?
+        assert_eq!(Some(sender), reply_address(sender, Some(nat), ReplyAddress::SelfReported));
+        assert_eq!(Some("192.168.1.1:9000".parse().unwrap()), reply_address(sender, Some(nat), ReplyAddress::ObservedIp));
+        assert_eq!(None, reply_address(sender, Some(nat), ReplyAddress::RejectMismatch));
+    }
+
+    #[test]
+    fn sender_is_accounted_at_observed_ip() {
+        assert_eq!("10.0.0.1:9000", observed_sender("10.0.0.1:9000", None));
+        assert_eq!("10.0.0.1:9000", observed_sender("10.0.0.1:9000", Some("10.0.0.1:54321".parse().unwrap())));
+        // a node reporting the address of a peer of another host is accounted at its own IP
+        assert_eq!("192.168.1.1:9000", observed_sender("10.0.0.1:9000", Some("192.168.1.1:54321".parse().unwrap())));
+        assert_eq!("not an address", observed_sender("not an address", Some("192.168.1.1:54321".parse().unwrap())));
+    }
+
+    #[test]
+    fn wire_format_is_unchanged() {
+        // protocol byte, cluster id if any, then the CBOR encoding of the message
+        assert_eq!(vec![MESSAGE_PROTOCOL_NOOP_MESSAGE, 0xf6], to_wire_bytes(&crate::message::NoopMessage, None).unwrap());
+        assert_eq!(vec![MESSAGE_PROTOCOL_NOOP_MESSAGE | MESSAGE_FLAG_CLUSTER, 7, 0xf6], to_wire_bytes(&crate::message::NoopMessage, Some(7)).unwrap());
+
+        let mut message = HeaderMessage::new_request("127.0.0.1:1".to_owned(), true);
+        message.set_headers(vec!["digest".to_owned(); 100]);
+        let mut expected = vec![MESSAGE_PROTOCOL_HEADER_MESSAGE | MESSAGE_FLAG_CLUSTER, 3];
+        expected.extend(serde_cbor::to_vec(&message).unwrap());
+        assert_eq!(expected, to_wire_bytes(&message, Some(3)).unwrap());
+        assert_eq!(&expected[2..], &to_wire_bytes(&message, None).unwrap()[1..]);
+    }
+
+    #[test]
+    fn message_for_stopped_handler_is_dropped() {
+        let (router, last_received, header_receiver) = router();
+        drop(header_receiver);
+
+        let bytes = to_wire_bytes(&HeaderMessage::new_request("127.0.0.1:1".to_owned(), false), None).unwrap();
+        assert!(router.route(&bytes, None, None).is_ok());
+        assert!(router.route(&bytes, None, None).is_ok());
+        assert!(last_received.lock().unwrap().is_none());
+        assert!(router.route(&[MESSAGE_PROTOCOL_HEADER_MESSAGE, 0xff], None, None).is_err());
+        // the stopped handler is recorded once, apart from the parse errors
+        let errors = router.errors.as_ref().unwrap().recent();
+        assert_eq!(2, errors.len());
+        assert_eq!(ErrorCategory::Handler, errors[0].category);
+        assert_eq!(ErrorCategory::Parse, errors[1].category);
+        let queues = &router.routes.read().unwrap()[&None].queues;
+        assert!(queues.disconnected.load(Ordering::SeqCst));
+        assert_eq!(0, queues.dropped.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn malformed_buffers_are_rejected() {
+        let (router, last_received, gossip_receiver) = router();
+        // the empty buffer made the routing panic, the other truncated inputs come from the corpus of `fuzz/`
+        let inputs: [&[u8]; 6] = [
+            &[],
+            &[MESSAGE_PROTOCOL_HEADER_MESSAGE | MESSAGE_FLAG_CLUSTER],
+            &[MESSAGE_PROTOCOL_SAMPLING_MESSAGE],
+            &[MESSAGE_PROTOCOL_CONTENT_MESSAGE, 0xbf],
+            &[MESSAGE_PROTOCOL_PING_MESSAGE | MESSAGE_FLAG_REPLY_IN_STREAM, 0xa1, 0x60],
+            &[MESSAGE_PROTOCOL_PONG_MESSAGE, 0x9b, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff],
+        ];
+        for bytes in inputs {
+            assert!(router.route(bytes, None, None).is_err(), "{:?} was accepted", bytes);
+        }
+        assert!(gossip_receiver.try_recv().is_none());
+        assert!(last_received.lock().unwrap().is_none());
+
+        assert_eq!(0, split_replies(&[]).unwrap().len());
+        for bytes in [&[0, 0, 0][..], &[0, 0, 0, 0], &[0xff, 0xff, 0xff, 0xff, 1], &[0, 0, 0, 1, 1, 0]] {
+            assert!(split_replies(bytes).is_err(), "{:?} was accepted", bytes);
+        }
+    }
+
+    #[test]
+    fn messages_over_the_limits_are_rejected() {
+        use crate::message::{MAX_ADDRESS_LENGTH, MAX_DIGEST_LENGTH, MAX_MESSAGE_DIGESTS};
+        let (router, last_received, gossip_receiver) = router();
+        let long_address = "1".repeat(MAX_ADDRESS_LENGTH + 1);
+        let long_digest = "a".repeat(MAX_DIGEST_LENGTH + 1);
+        // serialized without the checks of the sender, as a malicious peer would
+        let wire = |protocol: u8, payload: Vec<u8>| [vec![protocol], payload].concat();
+
+        let mut too_many_headers = HeaderMessage::new_request("127.0.0.1:1".to_owned(), false);
+        too_many_headers.set_headers(vec!["a".to_owned(); MAX_MESSAGE_DIGESTS + 1]);
+        let mut long_header = HeaderMessage::new_request("127.0.0.1:1".to_owned(), false);
+        long_header.set_relayed(vec![long_digest.clone()]);
+        let mut long_provider = ContentMessage::new_response("127.0.0.1:1".to_owned(), HashMap::new());
+        long_provider.set_redirects(HashMap::from([("a".to_owned(), long_address.clone())]));
+        let long_peer = PeerSamplingMessage::new_request("127.0.0.1:1".to_owned(), Some(vec![crate::peer::Peer::unchecked(long_address.clone())]));
+        let violations = [
+            wire(MESSAGE_PROTOCOL_HEADER_MESSAGE, serde_cbor::to_vec(&HeaderMessage::new_request(long_address.clone(), false)).unwrap()),
+            wire(MESSAGE_PROTOCOL_HEADER_MESSAGE, serde_cbor::to_vec(&too_many_headers).unwrap()),
+            wire(MESSAGE_PROTOCOL_HEADER_MESSAGE, serde_cbor::to_vec(&long_header).unwrap()),
+            wire(MESSAGE_PROTOCOL_CONTENT_MESSAGE, serde_cbor::to_vec(&ContentMessage::new_request(long_address.clone(), HashMap::new())).unwrap()),
+            wire(MESSAGE_PROTOCOL_CONTENT_MESSAGE, serde_cbor::to_vec(&ContentMessage::new_request("127.0.0.1:1".to_owned(), HashMap::from([(long_digest.clone(), vec![])]))).unwrap()),
+            wire(MESSAGE_PROTOCOL_CONTENT_MESSAGE, serde_cbor::to_vec(&long_provider).unwrap()),
+            wire(MESSAGE_PROTOCOL_SAMPLING_MESSAGE, serde_cbor::to_vec(&PeerSamplingMessage::new_leave(long_address.clone())).unwrap()),
+            wire(MESSAGE_PROTOCOL_SAMPLING_MESSAGE, serde_cbor::to_vec(&long_peer).unwrap()),
+        ];
+        for bytes in &violations {
+            assert!(router.route(bytes, None, None).is_err());
+        }
+        assert!(gossip_receiver.try_recv().is_none());
+        assert!(last_received.lock().unwrap().is_none());
+        let errors = router.errors.as_ref().unwrap().recent();
+        assert_eq!(violations.len(), errors.len());
+        assert!(errors.iter().all(|error| error.category == ErrorCategory::Parse));
+
+        // such messages are not sent either
+        assert!(to_wire_bytes(&too_many_headers, None).is_err());
+        assert!(to_wire_bytes(&long_peer, None).is_err());
+
+        // messages at the limits are accepted
+        let mut message = HeaderMessage::new_request("1".repeat(MAX_ADDRESS_LENGTH), false);
+        message.set_headers(vec!["a".repeat(MAX_DIGEST_LENGTH); MAX_MESSAGE_DIGESTS]);
+        assert!(router.route(&to_wire_bytes(&message, None).unwrap(), None, None).is_ok());
+        assert!(matches!(gossip_receiver.try_recv().unwrap(), Inbound::Header(_)));
+    }
+
+    #[test]
+    fn received_traffic_is_accounted_at_observed_ip() {
+        let traffic = Arc::new(TrafficLog::new());
+        let dispatcher = Dispatcher::new(Arc::new(Mutex::new(None)), inbox(&[], &[]).0, Arc::new(ErrorLog::new()), Arc::new(MessageQueues::default()), Duration::from_millis(10))
+            .with_traffic(Arc::clone(&traffic));
+        let router = Router::single(None, dispatcher);
+
+        // a node of another host reporting the address of a peer is not heard from as that peer
+        let bytes = to_wire_bytes(&PingMessage::new("10.0.0.1:9000".to_owned()), None).unwrap();
+        router.route(&bytes, Some("192.168.1.1:54321".parse().unwrap()), None).unwrap();
+        assert_eq!(None, traffic.last_seen("10.0.0.1:9000"));
+        assert!(traffic.last_seen("192.168.1.1:9000").is_some());
+        assert_eq!(vec!["192.168.1.1:9000".to_owned()], traffic.traffic().into_iter().map(|traffic| traffic.peer).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn messages_for_stalled_handler_are_bounded() {
+        let queues = Arc::new(MessageQueues::default());
+        let (sender, receiver) = inbox(&[(Kind::Header, 4), (Kind::Content, 4)], &[]);
+        let lengths = sender.detached();
+        let dispatcher = Dispatcher::new(Arc::new(Mutex::new(None)), sender, Arc::new(ErrorLog::new()), Arc::clone(&queues), Duration::from_millis(20));
+        let router = Router::single(None, dispatcher);
+
+        // the content handler does not read its queue
+        let bytes = to_wire_bytes(&ContentMessage::new_request("127.0.0.1:1".to_owned(), HashMap::new()), None).unwrap();
+        let started = Instant::now();
+        for _ in 0..10 {
+            assert!(router.route(&bytes, None, None).is_ok());
+        }
+        assert_eq!(4, lengths.len(Kind::Content));
+        assert_eq!(6, queues.dropped.load(Ordering::SeqCst));
+        assert!(!queues.disconnected.load(Ordering::SeqCst));
+        // each dropped message waited for the queue timeout
+        assert!(started.elapsed() >= Duration::from_millis(6 * 20));
+        // header messages are still queued while the content messages fill their queue, and handled first
+        let header = to_wire_bytes(&HeaderMessage::new_request("127.0.0.1:1".to_owned(), false), None).unwrap();
+        assert!(router.route(&header, None, None).is_ok());
+        assert_eq!(1, lengths.len(Kind::Header));
+        assert_eq!(6, queues.dropped.load(Ordering::SeqCst));
+        assert!(matches!(receiver.try_recv().unwrap(), Inbound::Content(_)));
+        assert!(matches!(receiver.try_recv().unwrap(), Inbound::Header(_)));
+
+        // room is made as soon as the handler reads its queue
+        assert!(router.route(&bytes, None, None).is_ok());
+        assert_eq!(4, lengths.len(Kind::Content));
+        assert_eq!(6, queues.dropped.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn outbound_connections_follow_nodelay_option() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+
+        let stream = connect(&address, &NetworkConfig::default(), None).unwrap();
+        assert!(stream.nodelay().unwrap());
+
+        let mut network = NetworkConfig::default();
+        network.set_nodelay(false);
+        let stream = connect(&address, &network, Some(Duration::from_secs(1))).unwrap();
+        assert!(!stream.nodelay().unwrap());
+    }
+
+    #[cfg(feature = "socket2")]
+    #[test]
+    fn socket2_options_are_applied() {
+        let mut network = NetworkConfig::default();
+        let listener = bind(&"127.0.0.1:0".parse().unwrap(), &network).unwrap();
+        assert!(socket2::SockRef::from(&listener).reuse_address().unwrap());
+        let address = listener.local_addr().unwrap();
+
+        let stream = connect(&address, &network, None).unwrap();
+        assert!(!socket2::SockRef::from(&stream).keepalive().unwrap());
+        network.set_keepalive(Some(30_000));
+        let stream = connect(&address, &network, None).unwrap();
+        assert!(socket2::SockRef::from(&stream).keepalive().unwrap());
+
+        network.set_reuse_address(false);
+        let listener = bind(&"127.0.0.1:0".parse().unwrap(), &network).unwrap();
+        assert!(!socket2::SockRef::from(&listener).reuse_address().unwrap());
     }
 }
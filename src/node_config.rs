@@ -0,0 +1,204 @@
+use std::error::Error;
+use std::net::SocketAddr;
+use serde::{Serialize, Deserialize};
+use crate::config::{PeerSamplingConfig, GossipConfig};
+use crate::peer::Peer;
+
+/// Complete configuration of a node, loaded from a TOML document or from environment variables.
+///
+/// Durations are expressed either in milliseconds or as [humantime](https://docs.rs/humantime) strings such as `"1s 500ms"`.
+/// Missing sections and parameters take their default values; unknown parameters are rejected.
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct NodeConfig {
+    /// Address to which the listener is bound
+    bind_addr: SocketAddr,
+    /// Address advertised to other peers, if different from the bind address
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    public_addr: Option<SocketAddr>,
+    /// Addresses of the peers contacted at start
+    #[serde(default)]
+    bootstrap_peers: Vec<SocketAddr>,
+    /// Peer sampling parameters
+    #[serde(default)]
+    sampling: PeerSamplingConfig,
+    /// Gossip parameters
+    #[serde(default)]
+    gossip: GossipConfig,
+}
+
+/// Separator of nested parameters in environment variable names
+const ENV_NESTING_SEPARATOR: &str = "__";
+
+impl NodeConfig {
+    /// Parses a configuration from a TOML document
+    ///
+    /// # Arguments
+    ///
+    /// * `document` - The TOML document
+    pub fn from_toml_str(document: &str) -> Result<Self, Box<dyn Error>> {
+        Ok(toml::from_str(document)?)
+    }
+
+    /// Reads a configuration from the environment variables starting with the prefix followed by an underscore.
+    /// Nested parameters are separated by a double underscore, e.g. `GOSSIP_SAMPLING__VIEW_SIZE=20`, and the
+    /// bootstrap peers are separated by commas.
+    ///
+    /// # Arguments
+    ///
+    /// * `prefix` - Prefix of the variables, e.g. `GOSSIP`
+    pub fn from_env(prefix: &str) -> Result<Self, Box<dyn Error>> {
+        let prefix = format!("{}_", prefix);
+        let mut document = toml::value::Table::new();
+        // the variables unrelated to the configuration may hold any bytes
+        for (name, value) in std::env::vars_os() {
+            let name = name.to_string_lossy();
+            if let Some(name) = name.strip_prefix(&prefix) {
+                let value = value.into_string()
+                    .map_err(|_| format!("Value of environment variable {}{} is not valid UTF-8", prefix, name))?;
+                if name.contains(char::REPLACEMENT_CHARACTER) {
+                    Err(format!("Name of environment variable {}{} is not valid UTF-8", prefix, name))?
+                }
+                let path: Vec<String> = name.split(ENV_NESTING_SEPARATOR).map(|key| key.to_lowercase()).collect();
+                let value = if path == ["bootstrap_peers"] {
+                    toml::Value::Array(value.split(',')
+                        .map(|peer| peer.trim())
+                        .filter(|peer| !peer.is_empty())
+                        .map(|peer| toml::Value::String(peer.to_owned()))
+                        .collect())
+                }
+                else {
+                    Self::parse_env_value(&value)
+                };
+                Self::insert(&mut document, &path, value)?;
+            }
+        }
+        Ok(toml::Value::Table(document).try_into()?)
+    }
+
+    /// Parses a value as a TOML value, or as a string if it is not one
+    fn parse_env_value(value: &str) -> toml::Value {
+        toml::from_str::<toml::value::Table>(&format!("value = {}", value)).ok()
+            .and_then(|mut table| table.remove("value"))
+            .unwrap_or_else(|| toml::Value::String(value.to_owned()))
+    }
+
+    /// Inserts a value in nested tables
+    fn insert(table: &mut toml::value::Table, path: &[String], value: toml::Value) -> Result<(), Box<dyn Error>> {
+        match path {
+            [key] => {
+                table.insert(key.to_owned(), value);
+                Ok(())
+            }
+            [key, rest @ ..] => {
+                let entry = table.entry(key.to_owned()).or_insert_with(|| toml::Value::Table(toml::value::Table::new()));
+                match entry {
+                    toml::Value::Table(nested) => Self::insert(nested, rest, value),
+                    _ => Err(format!("Parameter {} is not a section", key))?,
+                }
+            }
+            [] => Err("Empty parameter name")?,
+        }
+    }
+
+    /// Returns the address to which the listener is bound
+    pub fn bind_addr(&self) -> &SocketAddr {
+        &self.bind_addr
+    }
+
+    /// Returns the address advertised to other peers
+    pub fn public_addr(&self) -> &SocketAddr {
+        self.public_addr.as_ref().unwrap_or(&self.bind_addr)
+    }
+
+    /// Returns the peers contacted at start, to be returned by the initialization closure of [start](crate::GossipService::start)
+    pub fn bootstrap_peers(&self) -> Vec<Peer> {
+        self.bootstrap_peers.iter().copied().map(Peer::from).collect()
+    }
+
+    pub fn sampling(&self) -> &PeerSamplingConfig {
+        &self.sampling
+    }
+
+    pub fn gossip(&self) -> &GossipConfig {
+        &self.gossip
+    }
+
+    /// Splits the configuration into the bind address, the public address and the protocol parameters
+    pub(crate) fn into_parts(self) -> (SocketAddr, SocketAddr, PeerSamplingConfig, GossipConfig) {
+        let public_addr = *self.public_addr();
+        (self.bind_addr, public_addr, self.sampling, self.gossip)
+    }
+}
+
+/// (De)serialization of durations in milliseconds, also accepting humantime strings
+pub(crate) mod millis {
+    use serde::{Serializer, Deserializer};
+    use serde::de::{self, Visitor};
+
+    struct MillisVisitor;
+
+    impl<'de> Visitor<'de> for MillisVisitor {
+        type Value = u64;
+
+        fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+            formatter.write_str("a duration in milliseconds or a string such as \"1s 500ms\"")
+        }
+
+        fn visit_u64<E: de::Error>(self, value: u64) -> Result<u64, E> {
+            Ok(value)
+        }
+
+        fn visit_i64<E: de::Error>(self, value: i64) -> Result<u64, E> {
+            if value < 0 {
+                Err(E::invalid_value(de::Unexpected::Signed(value), &self))
+            }
+            else {
+                Ok(value as u64)
+            }
+        }
+
+        fn visit_str<E: de::Error>(self, value: &str) -> Result<u64, E> {
+            humantime::parse_duration(value)
+                .map(|duration| duration.as_millis() as u64)
+                .map_err(|e| E::custom(format!("invalid duration {:?}: {}", value, e)))
+        }
+    }
+
+    pub fn serialize<S: Serializer>(value: &u64, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u64(*value)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+        deserializer.deserialize_any(MillisVisitor)
+    }
+}
+
+/// (De)serialization of optional durations in milliseconds
+pub(crate) mod option_millis {
+    use serde::{Serializer, Deserializer};
+
+    pub fn serialize<S: Serializer>(value: &Option<u64>, serializer: S) -> Result<S::Ok, S::Error> {
+        match value {
+            Some(value) => serializer.serialize_some(value),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<u64>, D::Error> {
+        super::millis::deserialize(deserializer).map(Some)
+    }
+}
+
+/// (De)serialization of durations in milliseconds stored as `u128`
+pub(crate) mod millis_u128 {
+    use serde::{Serializer, Deserializer};
+
+    pub fn serialize<S: Serializer>(value: &u128, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u64(*value as u64)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u128, D::Error> {
+        super::millis::deserialize(deserializer).map(u128::from)
+    }
+}
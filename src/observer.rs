@@ -0,0 +1,102 @@
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use crate::update::{RemovalReason, ShutdownReason, UpdateCounters};
+
+/// Summary of a gossip round
+#[derive(Debug, Clone)]
+pub struct RoundSummary {
+    /// The peer selected for the round, if any
+    pub peer: Option<String>,
+    /// The number of headers sent to the peer
+    pub headers_sent: usize,
+    /// The number of new digests learned from other peers since the previous round
+    pub new_digests: u64,
+    /// Whether the header message was sent successfully
+    pub sent: bool,
+    /// Duration of the round, excluding the wait for the next period
+    pub duration: Duration,
+}
+
+/// Summary of a peer sampling round
+#[derive(Debug, Clone)]
+pub struct SamplingSummary {
+    /// The peer selected for the exchange, if any
+    pub peer: Option<String>,
+    /// The number of peers sent to the selected peer (0 when pulling only)
+    pub buffer_size: usize,
+    /// Whether the buffer was sent successfully
+    pub sent: bool,
+    /// The number of peers in the view at the end of the round
+    pub view_size: usize,
+    /// Duration of the round, excluding the wait for the next period
+    pub duration: Duration,
+}
+
+/// Summary of an update removed by its expiration, or by a tombstone or a revocation
+#[derive(Debug, Clone)]
+pub struct ExpirySummary {
+    /// The digest of the update
+    pub digest: String,
+    /// The dissemination counters of the update, see [GossipService::update_counters](crate::GossipService::update_counters)
+    pub counters: UpdateCounters,
+    /// The estimated probability that the update reached every node, see
+    /// [GossipService::coverage_estimate](crate::GossipService::coverage_estimate)
+    pub estimated_coverage: f64,
+    /// Whether the update expired or was removed before
+    pub reason: RemovalReason,
+}
+
+/// Trait for observing the rounds of the protocols, e.g. for research or monitoring.
+///
+/// Methods are called from the protocol threads at the end of each round, outside of any lock.
+pub trait RoundObserver {
+    /// Method called at the end of each gossip round
+    ///
+    /// # Arguments
+    ///
+    /// * `summary` - The summary of the round
+    fn on_gossip_round(&self, summary: RoundSummary);
+
+    /// Method called at the end of each peer sampling round
+    ///
+    /// # Arguments
+    ///
+    /// * `summary` - The summary of the round
+    fn on_sampling_round(&self, summary: SamplingSummary);
+
+    /// Method called when an update expires, for instance to tune the [push count](crate::UpdateExpirationMode::PushCount),
+    /// or is removed by a tombstone or a revocation, see [ExpirySummary::reason]
+    ///
+    /// # Arguments
+    ///
+    /// * `summary` - The summary of the update
+    fn on_update_expired(&self, _summary: ExpirySummary) {}
+
+    /// Method called once when the service is shut down, after its threads are stopped, if the observer is still
+    /// registered. Does nothing by default.
+    ///
+    /// # Arguments
+    ///
+    /// * `reason` - Why the service is shut down
+    fn on_shutdown(&self, _reason: ShutdownReason) {}
+
+    /// Method called when the messages sent to every peer of the view failed during the isolation window, see
+    /// [GossipConfig::set_isolation_detection](crate::GossipConfig::set_isolation_detection). Does nothing by default.
+    ///
+    /// # Arguments
+    ///
+    /// * `peer_count` - Number of peers in the view, none of which could be reached
+    fn on_network_isolated(&self, _peer_count: usize) {}
+
+    /// Method called when a message is exchanged with a peer after the node was
+    /// [isolated](Self::on_network_isolated). Does nothing by default.
+    fn on_network_recovered(&self) {}
+}
+
+/// The observer registered on a service, shared with the protocol threads
+pub(crate) type ObserverSlot = Arc<RwLock<Option<Arc<dyn RoundObserver + Send + Sync>>>>;
+
+/// Returns the registered observer, if any, so that it can be called without holding the lock
+pub(crate) fn current(slot: &ObserverSlot) -> Option<Arc<dyn RoundObserver + Send + Sync>> {
+    slot.read().unwrap().clone()
+}
@@ -0,0 +1,200 @@
+use std::collections::{BTreeMap, HashMap};
+use std::ops::Range;
+use std::time::{Duration, Instant};
+
+/// Time after which an origin without held updates is forgotten if none of its updates was received
+const IDLE_ORIGIN_RETENTION: Duration = Duration::from_secs(600);
+
+/// Maximum number of origins whose next update is remembered
+const MAX_ORIGINS: usize = 4096;
+
+/// Updates held back or released by an [OrderedDelivery]
+#[derive(Debug, PartialEq)]
+pub(crate) enum Released<T> {
+    /// An update to deliver
+    Item(T),
+    /// Numbers of an origin that never arrived in time and were skipped
+    Gap { origin: String, skipped: Range<u64> },
+}
+
+/// Updates of an origin waiting for their predecessors
+struct OriginQueue<T> {
+    /// Number of the next update to deliver
+    next: u64,
+    /// Updates received ahead of their predecessors, by number, with the time they were received
+    held: BTreeMap<u64, (Instant, T)>,
+    /// Time at which an update of the origin was last received
+    last_received: Instant,
+}
+
+/// Delivers the updates of each origin in the order of their numbers, see
+/// [GossipConfig::set_ordered_delivery](crate::GossipConfig::set_ordered_delivery). The numbers of each origin
+/// start at 1; an update whose predecessors do not arrive before the gap timeout is delivered after them.
+/// The origins without held updates are forgotten once idle, or when there are too many of them, and start over
+/// as new origins.
+pub(crate) struct OrderedDelivery<T> {
+    /// Time during which an update waits for its predecessors
+    gap_timeout: Duration,
+    origins: HashMap<String, OriginQueue<T>>,
+}
+
+impl<T> OrderedDelivery<T> {
+    pub fn new(gap_timeout: Duration) -> Self {
+        OrderedDelivery {
+            gap_timeout,
+            origins: HashMap::new(),
+        }
+    }
+
+    /// Adds a received update, and returns the updates that can be delivered. Updates numbered before the next
+    /// expected one, delivered late or replayed, are released right away.
+    ///
+    /// # Arguments
+    ///
+    /// * `origin` - Address of the node that submitted the update
+    /// * `number` - Number of the update among those of its origin
+    /// * `item` - The update
+    pub fn push(&mut self, origin: &str, number: u64, item: T) -> Vec<Released<T>> {
+        let now = Instant::now();
+        if !self.origins.contains_key(origin) {
+            self.forget_idle(now);
+        }
+        let queue = self.origins.entry(origin.to_owned()).or_insert_with(|| OriginQueue { next: 1, held: BTreeMap::new(), last_received: now });
+        queue.last_received = now;
+        if number < queue.next {
+            return vec![Released::Item(item)];
+        }
+        queue.held.entry(number).or_insert((Instant::now(), item));
+        let mut released = Vec::new();
+        Self::release_next(queue, &mut released);
+        released
+    }
+
+    /// Releases the held updates that follow the last delivered one
+    fn release_next(queue: &mut OriginQueue<T>, released: &mut Vec<Released<T>>) {
+        while let Some((_, item)) = queue.held.remove(&queue.next) {
+            released.push(Released::Item(item));
+            queue.next += 1;
+        }
+    }
+
+    /// Forgets the origins without held updates that have been idle for [IDLE_ORIGIN_RETENTION], and the least
+    /// recently active of them if there are still too many origins
+    fn forget_idle(&mut self, now: Instant) {
+        self.origins.retain(|_, queue| !queue.held.is_empty() || now.saturating_duration_since(queue.last_received) < IDLE_ORIGIN_RETENTION);
+        if self.origins.len() >= MAX_ORIGINS {
+            let oldest = self.origins.iter()
+                .filter(|(_, queue)| queue.held.is_empty())
+                .min_by_key(|(_, queue)| queue.last_received)
+                .map(|(origin, _)| origin.to_owned());
+            if let Some(oldest) = oldest {
+                self.origins.remove(&oldest);
+            }
+        }
+    }
+
+    /// Skips the missing predecessors of the updates that waited longer than the gap timeout, and returns the
+    /// gaps and the updates that can then be delivered. Forgets the idle origins.
+    pub fn expire(&mut self, now: Instant) -> Vec<Released<T>> {
+        let mut released = Vec::new();
+        let gap_timeout = self.gap_timeout;
+        for (origin, queue) in self.origins.iter_mut() {
+            while let Some((&number, _)) = queue.held.iter().next().filter(|(_, (received, _))| now.duration_since(*received) >= gap_timeout) {
+                released.push(Released::Gap { origin: origin.clone(), skipped: queue.next..number });
+                queue.next = number;
+                Self::release_next(queue, &mut released);
+            }
+        }
+        self.forget_idle(now);
+        released
+    }
+
+    /// Returns the time at which the first held update stops waiting for its predecessors, if any update is held
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.origins.values()
+            .filter_map(|queue| queue.held.values().map(|(received, _)| *received).min())
+            .min()
+            .map(|received| received + self.gap_timeout)
+    }
+
+    /// Releases all the held updates, skipping the missing ones
+    pub fn drain(&mut self) -> Vec<Released<T>> {
+        let mut released = Vec::new();
+        for (origin, queue) in self.origins.iter_mut() {
+            while let Some((&number, _)) = queue.held.iter().next() {
+                released.push(Released::Gap { origin: origin.clone(), skipped: queue.next..number });
+                queue.next = number;
+                Self::release_next(queue, &mut released);
+            }
+        }
+        released
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn items(released: Vec<Released<u64>>) -> Vec<u64> {
+        released.into_iter().filter_map(|released| match released {
+            Released::Item(item) => Some(item),
+            Released::Gap { .. } => None,
+        }).collect()
+    }
+
+    #[test]
+    fn updates_are_released_in_order() {
+        let mut ordering = OrderedDelivery::new(Duration::from_secs(60));
+        assert!(ordering.push("a", 2, 2).is_empty());
+        assert!(ordering.push("a", 3, 3).is_empty());
+        // other origins are not held back
+        assert_eq!(vec![1], items(ordering.push("b", 1, 1)));
+        assert_eq!(vec![1, 2, 3], items(ordering.push("a", 1, 1)));
+        assert_eq!(vec![2], items(ordering.push("a", 2, 2)));
+        assert!(ordering.next_deadline().is_none());
+    }
+
+    #[test]
+    fn gaps_are_skipped_after_the_timeout() {
+        let mut ordering = OrderedDelivery::new(Duration::from_millis(100));
+        assert!(ordering.push("a", 3, 3).is_empty());
+        assert!(ordering.push("a", 6, 6).is_empty());
+        let deadline = ordering.next_deadline().unwrap();
+        assert!(ordering.expire(deadline - Duration::from_millis(1)).is_empty());
+
+        // both updates waited for their predecessors for longer than the timeout
+        let released = ordering.expire(deadline + Duration::from_millis(10));
+        assert_eq!(vec![
+            Released::Gap { origin: "a".to_owned(), skipped: 1..3 },
+            Released::Item(3),
+            Released::Gap { origin: "a".to_owned(), skipped: 4..6 },
+            Released::Item(6),
+        ], released);
+        assert_eq!(vec![7], items(ordering.push("a", 7, 7)));
+    }
+
+    #[test]
+    fn idle_origins_are_forgotten() {
+        let mut ordering = OrderedDelivery::new(IDLE_ORIGIN_RETENTION * 2);
+        assert_eq!(vec![1], items(ordering.push("a", 1, 1)));
+        assert!(ordering.push("b", 2, 2).is_empty());
+        let later = Instant::now() + IDLE_ORIGIN_RETENTION + Duration::from_secs(1);
+        assert!(ordering.expire(later - Duration::from_secs(2)).is_empty());
+        assert_eq!(2, ordering.origins.len());
+
+        // the origin holding an update is kept until the update is released
+        assert!(ordering.expire(later).is_empty());
+        assert_eq!(vec!["b"], ordering.origins.keys().collect::<Vec<_>>());
+        // forgotten, the origin starts over
+        assert!(ordering.push("a", 2, 2).is_empty());
+    }
+
+    #[test]
+    fn origins_are_bounded() {
+        let mut ordering = OrderedDelivery::new(Duration::from_secs(60));
+        for origin in 0..MAX_ORIGINS + 10 {
+            assert_eq!(vec![1], items(ordering.push(&origin.to_string(), 1, 1)));
+        }
+        assert!(ordering.origins.len() <= MAX_ORIGINS);
+    }
+}
@@ -1,33 +1,109 @@
+use std::cmp::Ordering;
+use std::fmt::{Display, Formatter};
 use std::hash::{Hash, Hasher};
+use std::net::{AddrParseError, SocketAddr};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use serde::{Serialize, Deserialize};
+use crate::error::AddressError;
 
-/// Information about a peer
+/// Maximum length (bytes) of the metadata of a peer
+pub const MAX_PEER_METADATA: usize = 256;
+
+/// Information about a peer. Peers are identified, compared and ordered by their address only.
+///
+/// The serialized representation is a map with the `address` (string) and `age` (integer) of the peer, sent in
+/// the views exchanged between nodes. The fields added since are optional: they are absent if not set, and
+/// missing fields are read as not set, so that the peers of older and newer nodes are still understood.
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Peer {
     /// Socket address of the peer
     address: String,
     /// Age of the peer
     age: u16,
+    /// Wall-clock time (milliseconds since the Unix epoch) at which the peer was last known with age 0.
+    /// Absent in peers received from older nodes.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    refreshed: Option<u64>,
+    /// Application-defined metadata advertised by the peer. Absent in peers received from older nodes.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    metadata: Option<Vec<u8>>,
 }
 
 impl Peer {
-    /// Creates a new peer with the specified address and age 0
+    /// Creates a new peer with the specified address and age 0, without checking the address
     ///
     /// # Arguments
     ///
     /// * `address` - Network address of peer
+    #[deprecated(note = "use Peer::try_new, which checks the address")]
     pub fn new(address: String) -> Peer {
-        Peer {address, age: 0}
+        Self::unchecked(address)
+    }
+
+    /// Creates a new peer with age 0, failing if the address is not an IP address and a port such as `127.0.0.1:9000`
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - Network address of peer
+    pub fn try_new(address: &str) -> Result<Peer, AddressError> {
+        address.parse::<SocketAddr>().map(Peer::from).map_err(|_| AddressError::new(address))
+    }
+
+    /// Creates a peer whose address is known to be valid, such as the address of the node itself
+    pub(crate) fn unchecked(address: String) -> Peer {
+        Peer {address, age: 0, refreshed: None, metadata: None}
+    }
+
+    /// Creates a peer with all its fields set, for the fixtures of the wire format
+    #[cfg(test)]
+    pub(crate) fn with_fields(address: &str, age: u16, refreshed: Option<u64>, metadata: Option<Vec<u8>>) -> Peer {
+        Peer {address: address.to_owned(), age, refreshed, metadata}
+    }
+
+    /// Returns whether the address of the peer is a socket address. Peers received from other nodes are not
+    /// checked when they are deserialized, and invalid ones are dropped before they enter the view.
+    pub(crate) fn is_valid(&self) -> bool {
+        self.socket_addr().is_ok()
+    }
+
+    /// Recreates a peer of an exported view, whose last refresh was `staleness` ago
+    pub(crate) fn restore(address: String, age: u16, staleness: Option<Duration>) -> Peer {
+        let refreshed = staleness
+            .and_then(|staleness| SystemTime::now().checked_sub(staleness))
+            .and_then(|refreshed| refreshed.duration_since(UNIX_EPOCH).ok())
+            .map(|refreshed| refreshed.as_millis() as u64);
+        Peer {address, age, refreshed, metadata: None}
+    }
+
+    /// Sets the metadata advertised by the peer
+    pub(crate) fn set_metadata(&mut self, metadata: Option<Vec<u8>>) {
+        self.metadata = metadata;
+    }
+
+    /// Returns the application-defined metadata advertised by the peer, see [PeerSamplingConfig::set_metadata](crate::PeerSamplingConfig::set_metadata)
+    pub fn metadata(&self) -> Option<&[u8]> {
+        self.metadata.as_deref()
+    }
+
+    /// Marks the peer as fresh at the current time
+    pub(crate) fn refresh(&mut self) {
+        self.refreshed = SystemTime::now().duration_since(UNIX_EPOCH).ok().map(|now| now.as_millis() as u64);
     }
 
-    /// Increments the age of peer by one
+    /// Returns the time elapsed since the peer was last known to be fresh, if known.
+    /// Clock differences between nodes are clamped to zero.
+    pub fn staleness(&self) -> Option<Duration> {
+        let refreshed = UNIX_EPOCH + Duration::from_millis(self.refreshed?);
+        Some(SystemTime::now().duration_since(refreshed).unwrap_or_default())
+    }
+
+    /// Increments the age of peer by one cycle
     pub fn increment_age(&mut self) {
-        if self.age < u16::max_value() {
-            self.age += 1;
-        }
+        self.age = self.age.saturating_add(1);
     }
 
-    /// Returns the age of peer
+    /// Returns the age of peer, i.e. the number of peer sampling cycles since it was last known to be fresh.
+    /// Since the unit is cycles, ages are not comparable between nodes with different sampling periods, see [Peer::staleness].
     pub fn age(&self) -> u16 {
         self.age
     }
@@ -35,6 +111,17 @@ impl Peer {
     /// Returns the address of peer
     pub fn address(&self) -> &str { &self.address }
 
+    /// Returns the socket address of the peer. Fails only for the peers received from other nodes, which are not
+    /// checked when they are deserialized.
+    pub fn socket_addr(&self) -> Result<SocketAddr, AddrParseError> {
+        self.address.parse()
+    }
+
+}
+impl From<SocketAddr> for Peer {
+    fn from(address: SocketAddr) -> Self {
+        Peer::unchecked(address.to_string())
+    }
 }
 impl Eq for Peer {}
 impl PartialEq for Peer {
@@ -46,4 +133,59 @@ impl Hash for Peer {
     fn hash<H: Hasher>(&self, state: &mut H) {
         self.address.hash(state)
     }
+}
+impl Ord for Peer {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.address.cmp(&other.address)
+    }
+}
+impl PartialOrd for Peer {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+/// Displays the address of the peer
+impl Display for Peer {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.address)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+    use super::Peer;
+
+    #[test]
+    fn peer_round_trips_through_socket_address() {
+        let address: SocketAddr = "127.0.0.1:9000".parse().unwrap();
+        let peer = Peer::from(address);
+        assert_eq!(address, peer.socket_addr().unwrap());
+        assert_eq!("127.0.0.1:9000", peer.to_string());
+        assert_eq!(peer, Peer::try_new(&peer.to_string()).unwrap());
+
+        let v6: SocketAddr = "[::1]:9000".parse().unwrap();
+        assert_eq!(v6, Peer::from(v6).to_string().parse::<SocketAddr>().unwrap());
+        assert!(Peer::unchecked("not an address".to_owned()).socket_addr().is_err());
+    }
+
+    #[test]
+    fn peers_are_sorted_by_address() {
+        let mut peers: Vec<Peer> = ["127.0.0.3:1", "127.0.0.1:2", "127.0.0.2:1", "127.0.0.1:1"].iter()
+            .map(|address| Peer::try_new(address).unwrap())
+            .collect();
+        peers[0].increment_age();
+        peers.sort();
+        assert_eq!(vec!["127.0.0.1:1", "127.0.0.1:2", "127.0.0.2:1", "127.0.0.3:1"], peers.iter().map(Peer::address).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn serialized_peer_holds_the_fields_that_are_set() {
+        let peer = Peer::try_new("127.0.0.1:9000").unwrap();
+        assert_eq!(r#"{"address":"127.0.0.1:9000","age":0}"#, serde_json::to_string(&peer).unwrap());
+        let full = Peer::with_fields("127.0.0.1:9000", 3, Some(1_600_000_000_000), Some(vec![1]));
+        assert_eq!(r#"{"address":"127.0.0.1:9000","age":3,"refreshed":1600000000000,"metadata":[1]}"#, serde_json::to_string(&full).unwrap());
+        let read: Peer = serde_json::from_str(r#"{"address":"127.0.0.1:9001","age":2}"#).unwrap();
+        assert_eq!((2, None, None), (read.age(), read.staleness(), read.metadata()));
+    }
 }
\ No newline at end of file
@@ -0,0 +1,213 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::net::IpAddr;
+use std::time::{Duration, Instant};
+use crate::memory::Usage;
+use crate::logging::targets::NETWORK;
+
+/// Violations of the peers that sent invalid data, and the peers that are quarantined because of them. The peers are
+/// identified by their address, except the connections that were too slow to deliver a message, whose violations are
+/// recorded against their IP address, apart from those of the peers: a peer address and an IP address never share
+/// a count.
+pub(crate) struct Quarantine {
+    /// Number of violations after which a peer is quarantined, zero to disable quarantine
+    threshold: u32,
+    /// Length (milliseconds) of the quarantine, also the time after which a violation is forgotten
+    cooldown: u64,
+    /// Number of recent violations of each peer, with the time of the last one
+    violations: HashMap<String, (u32, Instant)>,
+    /// End of the quarantine of each quarantined peer
+    quarantined: HashMap<String, Instant>,
+    /// Number of recent slow connections of each IP address, with the time of the last one
+    connection_violations: HashMap<IpAddr, (u32, Instant)>,
+    /// End of the quarantine of each IP address whose connections are refused
+    quarantined_ips: HashMap<IpAddr, Instant>,
+}
+
+impl Quarantine {
+    pub fn new(threshold: u32, cooldown: u64) -> Self {
+        Quarantine {
+            threshold,
+            cooldown,
+            violations: HashMap::new(),
+            quarantined: HashMap::new(),
+            connection_violations: HashMap::new(),
+            quarantined_ips: HashMap::new(),
+        }
+    }
+
+    pub fn memory(&self) -> Usage {
+        let peers = Usage::of_strings(self.violations.keys().chain(self.quarantined.keys()));
+        let ips = Usage::of_sizes(self.connection_violations.keys().chain(self.quarantined_ips.keys())
+            .map(|_| std::mem::size_of::<IpAddr>()));
+        Usage {
+            count: peers.count + ips.count,
+            bytes: peers.bytes + ips.bytes,
+        }
+    }
+
+    /// Changes the threshold and the cooldown; peers already quarantined keep the end of their quarantine
+    pub fn configure(&mut self, threshold: u32, cooldown: u64) {
+        self.threshold = threshold;
+        self.cooldown = cooldown;
+    }
+
+    /// Records that the peer sent invalid data. Returns the end of its quarantine if the violation
+    /// puts the peer in quarantine.
+    pub fn record_violation(&mut self, peer: &str) -> Option<Instant> {
+        let until = Self::record(self.threshold, self.cooldown, &mut self.violations, &mut self.quarantined, peer.to_owned())?;
+        node_log!(Warn, NETWORK, "Peer {} is quarantined for {} ms", peer, self.cooldown);
+        Some(until)
+    }
+
+    /// Records that a connection from the IP address did not deliver its message in time. Returns the end of the
+    /// quarantine of the IP address if the violation puts it in quarantine.
+    pub fn record_connection_violation(&mut self, ip: IpAddr) -> Option<Instant> {
+        let until = Self::record(self.threshold, self.cooldown, &mut self.connection_violations, &mut self.quarantined_ips, ip)?;
+        node_log!(Warn, NETWORK, "Connections from {} are refused for {} ms", ip, self.cooldown);
+        Some(until)
+    }
+
+    /// Counts a violation in `violations`, moving the offender to `quarantined` once it reaches the threshold
+    fn record<K: Eq + Hash + Clone>(threshold: u32, cooldown: u64, violations: &mut HashMap<K, (u32, Instant)>, quarantined: &mut HashMap<K, Instant>, offender: K) -> Option<Instant> {
+        if threshold == 0 {
+            return None;
+        }
+        let now = Instant::now();
+        let cooldown = Duration::from_millis(cooldown);
+        let (count, last) = violations.entry(offender.clone()).or_insert((0, now));
+        if now.duration_since(*last) > cooldown {
+            *count = 0;
+        }
+        *count += 1;
+        *last = now;
+        if *count < threshold {
+            return None;
+        }
+        violations.remove(&offender);
+        let until = now + cooldown;
+        quarantined.insert(offender, until);
+        Some(until)
+    }
+
+    pub fn is_quarantined(&self, peer: &str) -> bool {
+        self.quarantined.get(peer).is_some_and(|until| *until > Instant::now())
+    }
+
+    /// Returns whether the connections of the IP address are refused
+    pub fn is_ip_quarantined(&self, ip: &IpAddr) -> bool {
+        self.quarantined_ips.get(ip).is_some_and(|until| *until > Instant::now())
+    }
+
+    /// Returns the peers currently quarantined, followed by the IP addresses whose connections are refused
+    pub fn quarantined(&self) -> Vec<String> {
+        let now = Instant::now();
+        self.quarantined.iter()
+            .filter(|(_, until)| **until > now)
+            .map(|(peer, _)| peer.to_owned())
+            .chain(self.quarantined_ips.iter()
+                .filter(|(_, until)| **until > now)
+                .map(|(ip, _)| ip.to_string()))
+            .collect()
+    }
+
+    /// Forgets the ended quarantines and the violations older than the cooldown
+    pub fn prune(&mut self) {
+        let now = Instant::now();
+        let cooldown = Duration::from_millis(self.cooldown);
+        self.quarantined.retain(|_, until| *until > now);
+        self.violations.retain(|_, (_, last)| now.duration_since(*last) <= cooldown);
+        self.quarantined_ips.retain(|_, until| *until > now);
+        self.connection_violations.retain(|_, (_, last)| now.duration_since(*last) <= cooldown);
+    }
+
+    pub fn clear(&mut self) {
+        self.violations.clear();
+        self.quarantined.clear();
+        self.connection_violations.clear();
+        self.quarantined_ips.clear();
+    }
+}
+
+/// Time after which the mismatches of a digest are forgotten
+const MISMATCH_RETENTION: Duration = Duration::from_secs(600);
+
+/// Digests whose received content did not match, with the peers that sent the content
+pub(crate) struct DigestMismatches {
+    /// Time of the last mismatch of each digest from each peer
+    failed: HashMap<(String, String), Instant>,
+    /// Number of distinct peers that sent mismatching content for each digest, with the time of the last mismatch
+    counts: HashMap<String, (u32, Instant)>,
+}
+
+impl DigestMismatches {
+    pub fn new() -> Self {
+        DigestMismatches {
+            failed: HashMap::new(),
+            counts: HashMap::new(),
+        }
+    }
+
+    pub fn memory(&self) -> Usage {
+        Usage::of_sizes(self.failed.keys().map(|(digest, peer)| digest.len() + peer.len())
+            .chain(self.counts.keys().map(String::len)))
+    }
+
+    /// Records that the content of the digest received from the peer did not match, and returns the number
+    /// of distinct peers that sent mismatching content for the digest. Only the peers whose address was verified
+    /// are counted: the mismatch of another peer only prevents the digest from being requested from it again.
+    ///
+    /// # Arguments
+    ///
+    /// * `digest` - The digest whose content did not match
+    /// * `peer` - The address the peer is accounted at, see [observed_sender](crate::network::observed_sender)
+    /// * `verified` - Whether the address reported by the peer is the one its connection came from
+    pub fn record(&mut self, digest: &str, peer: &str, verified: bool) -> u32 {
+        let now = Instant::now();
+        self.failed.retain(|_, last| now.duration_since(*last) <= MISMATCH_RETENTION);
+        self.counts.retain(|_, (_, last)| now.duration_since(*last) <= MISMATCH_RETENTION);
+        let first = self.failed.insert((digest.to_owned(), peer.to_owned()), now).is_none();
+        let (count, last) = self.counts.entry(digest.to_owned()).or_insert((0, now));
+        if first && verified {
+            *count += 1;
+        }
+        *last = now;
+        *count
+    }
+
+    /// Returns whether content of the digest that did not match was received from the peer less than
+    /// `cooldown` milliseconds ago
+    pub fn is_failed(&self, digest: &str, peer: &str, cooldown: u64) -> bool {
+        self.failed.get(&(digest.to_owned(), peer.to_owned()))
+            .is_some_and(|last| last.elapsed() < Duration::from_millis(cooldown))
+    }
+
+    /// Forgets the mismatches of the digest
+    pub fn forget(&mut self, digest: &str) {
+        self.failed.retain(|(failed, _), _| failed != digest);
+        self.counts.remove(digest);
+    }
+
+    pub fn clear(&mut self) {
+        self.failed.clear();
+        self.counts.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DigestMismatches;
+
+    #[test]
+    fn mismatches_are_counted_once_per_verified_peer() {
+        let mut mismatches = DigestMismatches::new();
+        assert_eq!(1, mismatches.record("digest", "127.0.0.1:9000", true));
+        // the same peer sending mismatching content again
+        assert_eq!(1, mismatches.record("digest", "127.0.0.1:9000", true));
+        // a peer whose reported address does not match its connection
+        assert_eq!(1, mismatches.record("digest", "192.168.1.1:9000", false));
+        assert!(mismatches.is_failed("digest", "192.168.1.1:9000", 60_000));
+        assert_eq!(2, mismatches.record("digest", "127.0.0.1:9001", true));
+        assert_eq!(1, mismatches.record("other digest", "127.0.0.1:9001", true));
+    }
+}
@@ -0,0 +1,69 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::time::Instant;
+use crate::memory::Usage;
+use crate::logging::targets::GOSSIP;
+
+/// Time (milliseconds) after which a relayed digest is forgotten
+const RELAY_RETENTION: u128 = 60_000;
+
+/// Maximum number of relayed digests remembered by a node
+const MAX_RELAYED_DIGESTS: usize = 10_000;
+
+/// Digests advertised by peers whose content is not stored by the node, with the first peer that advertised each
+/// of them, see [GossipConfig::set_relay_headers](crate::GossipConfig::set_relay_headers)
+pub(crate) struct RelayedDigests {
+    providers: HashMap<String, (SocketAddr, Instant)>,
+}
+
+impl RelayedDigests {
+    pub fn new() -> Self {
+        RelayedDigests {
+            providers: HashMap::new(),
+        }
+    }
+
+    pub fn memory(&self) -> Usage {
+        Usage::of_strings(self.providers.keys())
+    }
+
+    /// Records the peer that advertised a digest, unless another peer already did
+    pub fn record(&mut self, digest: &str, provider: SocketAddr) {
+        if !self.providers.contains_key(digest) {
+            self.prune();
+            if self.providers.len() >= MAX_RELAYED_DIGESTS {
+                node_log!(Debug, GOSSIP, "Not relaying digest {}, too many relayed digests", digest);
+                return;
+            }
+            self.providers.insert(digest.to_owned(), (provider, Instant::now()));
+        }
+    }
+
+    /// Returns the peer from which the content of the digest can be requested
+    pub fn provider(&self, digest: &str) -> Option<SocketAddr> {
+        self.providers.get(digest)
+            .filter(|(_, seen)| seen.elapsed().as_millis() < RELAY_RETENTION)
+            .map(|(provider, _)| *provider)
+    }
+
+    /// Returns the relayed digests, forgetting those for which `stored` returns true
+    pub fn digests<F>(&mut self, stored: F) -> Vec<String>
+    where F: Fn(&String) -> bool
+    {
+        self.prune();
+        self.providers.retain(|digest, _| !stored(digest));
+        self.providers.keys().cloned().collect()
+    }
+
+    pub fn remove(&mut self, digest: &str) {
+        self.providers.remove(digest);
+    }
+
+    pub fn clear(&mut self) {
+        self.providers.clear();
+    }
+
+    fn prune(&mut self) {
+        self.providers.retain(|_, (_, seen)| seen.elapsed().as_millis() < RELAY_RETENTION);
+    }
+}
@@ -0,0 +1,93 @@
+use std::collections::{HashMap, VecDeque};
+use crate::message::MAX_DIGEST_LENGTH;
+
+/// Time (milliseconds) after which a revocation expires when the updates never expire
+pub(crate) const REVOCATION_LIFETIME: u128 = 600_000;
+
+/// Maximum number of revocations remembered for updates that were not received yet
+const MAX_PENDING_REVOCATIONS: usize = 1024;
+
+/// Returns the digest revoked by a revocation, given its content, `None` if it is not a valid digest
+pub(crate) fn revoked_digest(content: &[u8]) -> Option<&str> {
+    if content.is_empty() || content.len() > MAX_DIGEST_LENGTH {
+        return None;
+    }
+    std::str::from_utf8(content).ok()
+}
+
+/// Revocations received before the update they revoke, with the origin of each revocation, so that the update is
+/// not inserted when it arrives from the same origin. The oldest are forgotten when there are too many of them.
+#[derive(Default)]
+pub(crate) struct PendingRevocations {
+    origins: HashMap<String, String>,
+    order: VecDeque<String>,
+}
+
+impl PendingRevocations {
+    /// Remembers the revocation of a digest by an origin
+    pub fn insert(&mut self, digest: &str, origin: &str) {
+        if self.origins.contains_key(digest) {
+            return;
+        }
+        if self.order.len() >= MAX_PENDING_REVOCATIONS {
+            if let Some(oldest) = self.order.pop_front() {
+                self.origins.remove(&oldest);
+            }
+        }
+        self.origins.insert(digest.to_owned(), origin.to_owned());
+        self.order.push_back(digest.to_owned());
+    }
+
+    /// Forgets the revocation of a digest and returns whether it was revoked by the origin
+    pub fn take(&mut self, digest: &str, origin: Option<&str>) -> bool {
+        if self.origins.get(digest).map(String::as_str) != origin || origin.is_none() {
+            return false;
+        }
+        self.origins.remove(digest);
+        self.order.retain(|pending| pending != digest);
+        true
+    }
+
+    pub fn digests(&self) -> impl Iterator<Item = &String> {
+        self.order.iter()
+    }
+
+    pub fn clear(&mut self) {
+        self.origins.clear();
+        self.order.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn revoked_digest_is_validated() {
+        assert_eq!(Some("abc"), revoked_digest(b"abc"));
+        assert_eq!(None, revoked_digest(b""));
+        assert_eq!(None, revoked_digest(&[0xff]));
+        assert_eq!(None, revoked_digest(&[b'a'; MAX_DIGEST_LENGTH + 1]));
+    }
+
+    #[test]
+    fn pending_revocation_applies_to_its_origin_only() {
+        let mut pending = PendingRevocations::default();
+        pending.insert("digest", "origin");
+        assert!(!pending.take("digest", None));
+        assert!(!pending.take("digest", Some("other")));
+        assert!(pending.take("digest", Some("origin")));
+        assert!(!pending.take("digest", Some("origin")));
+    }
+
+    #[test]
+    fn oldest_pending_revocations_are_forgotten() {
+        let mut pending = PendingRevocations::default();
+        for digest in 0..=MAX_PENDING_REVOCATIONS {
+            pending.insert(&digest.to_string(), "origin");
+        }
+        assert!(!pending.take("0", Some("origin")));
+        assert!(pending.take("1", Some("origin")));
+        assert_eq!(MAX_PENDING_REVOCATIONS - 1, pending.digests().count());
+    }
+}
@@ -0,0 +1,453 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Instant;
+use crate::config::{ExpirationStart, GossipConfig};
+use crate::coverage::coverage_probability;
+use crate::error_log::{ErrorCategory, ErrorLog};
+use crate::fetch::TimedOutRequests;
+use crate::gossip::ContentRequest;
+use crate::message::gossip::{ContentMessage, HeaderMessage};
+use crate::message::MAX_MESSAGE_DIGESTS;
+use crate::observer::{ExpirySummary, ObserverSlot, RoundSummary};
+use crate::peer::Peer;
+use crate::quarantine::Quarantine;
+use crate::relay::RelayedDigests;
+use crate::sampler::PeerSampler;
+use crate::snapshot::Snapshot;
+use crate::stats::GossipStats;
+use crate::traffic::MessageSender;
+use crate::update::{RemovalReason, UpdateCounters, UpdateDecorator};
+use crate::usefulness::PeerUsefulness;
+use crate::warning::RateLimitedWarning;
+use crate::logging::targets::GOSSIP;
+
+/// A gossip round of a service, run by the timer thread once it is due. The state is shared with the message
+/// handling thread of the service.
+pub(crate) struct GossipRound<S> {
+    /// Address of the node
+    pub(crate) node_address: String,
+    /// Sampler providing the peers of the rounds
+    pub(crate) peer_sampling: Arc<Mutex<Box<dyn PeerSampler>>>,
+    /// Active and expired updates
+    pub(crate) updates: Arc<RwLock<UpdateDecorator>>,
+    /// Peers to which a pull probe was sent and whose header response is expected
+    pub(crate) header_probes: Arc<Mutex<HashMap<SocketAddr, Instant>>>,
+    /// Peers with which a header exchange was initiated and has not completed yet
+    pub(crate) in_flight: Arc<Mutex<HashMap<SocketAddr, Instant>>>,
+    /// Content requests sent for new digests and not answered yet
+    pub(crate) content_requests: Arc<Mutex<HashMap<String, ContentRequest>>>,
+    /// Content requests that were not answered in time, whose late response is still accepted
+    pub(crate) timed_out: Arc<Mutex<TimedOutRequests>>,
+    /// Digests advertised by peers whose content is not stored
+    pub(crate) relayed: Arc<Mutex<RelayedDigests>>,
+    /// Recent exchanges with each peer and new digests learned from it
+    pub(crate) usefulness: Arc<Mutex<PeerUsefulness>>,
+    /// Peers sending invalid data, with which no exchange is initiated
+    pub(crate) quarantine: Arc<Mutex<Quarantine>>,
+    /// Peers of the view, published by the sampler
+    pub(crate) published_peers: Arc<Snapshot<Peer>>,
+    pub(crate) stats: Arc<Mutex<GossipStats>>,
+    pub(crate) errors: Arc<ErrorLog>,
+    /// Observer notified of the updates expired by the round
+    pub(crate) observer: ObserverSlot,
+    /// Sender of the messages of the rounds
+    pub(crate) sender: Arc<S>,
+    /// Warning logged while the rounds find no peer
+    pub(crate) no_peer_warning: RateLimitedWarning,
+}
+
+impl<S: MessageSender> GossipRound<S> {
+    /// Runs a round: the contents whose request was not answered are requested from their fallback peer, then a
+    /// header request is sent to each selected peer, with the active headers for the first one when pushing.
+    ///
+    /// Returns the summary of the round, without the number of new digests.
+    ///
+    /// # Arguments
+    ///
+    /// * `gossip_config` - The configuration at the start of the round
+    pub(crate) fn run(&mut self, gossip_config: &GossipConfig) -> RoundSummary {
+        let round_start = Instant::now();
+        self.stats.lock().unwrap().gossip_rounds += 1;
+        let mut summary = RoundSummary {
+            peer: None,
+            headers_sent: 0,
+            new_digests: 0,
+            sent: false,
+            duration: std::time::Duration::default(),
+        };
+        self.retry_content_requests(gossip_config);
+
+        // the first peer receives the pushed headers, the others are only probed for theirs
+        let fanout = if gossip_config.is_pull() { gossip_config.pull_fanout().max(1) } else { 1 };
+        let mut peers = if gossip_config.uniform_peer_selection() {
+            self.peer_sampling.lock().unwrap().sample_peers(fanout)
+        }
+        else {
+            self.peer_sampling.lock().unwrap().get_peers(fanout)
+        };
+        {
+            // no exchange is initiated with quarantined peers
+            let mut quarantine = self.quarantine.lock().unwrap();
+            quarantine.prune();
+            peers.retain(|peer| !quarantine.is_quarantined(peer.address()));
+        }
+        if gossip_config.useful_peer_bias() > 0.0 {
+            peers = self.usefulness.lock().unwrap().select(peers, &self.published_peers.load(), gossip_config.useful_peer_bias());
+        }
+        if peers.is_empty() {
+            match self.no_peer_warning.occurred() {
+                Some(0) => node_log!(Warn, GOSSIP, "No peer found for gossiping"),
+                Some(suppressed) => node_log!(Warn, GOSSIP, "No peer found for gossiping, {} more rounds without peer", suppressed),
+                None => node_log!(Debug, GOSSIP, "No peer found for gossiping"),
+            }
+            self.stats.lock().unwrap().rounds_without_peer += 1;
+        }
+        else if self.no_peer_warning.ended() {
+            node_log!(Info, GOSSIP, "Found a peer for gossiping");
+        }
+        for (index, peer) in peers.iter().enumerate() {
+            let first = index == 0;
+            if first {
+                summary.peer = Some(peer.address().to_owned());
+            }
+            let in_flight = peer.socket_addr().is_ok_and(|peer_address| {
+                let timeout = gossip_config.in_flight_timeout() as u128;
+                let mut in_flight = self.in_flight.lock().unwrap();
+                in_flight.retain(|_, sent| sent.elapsed().as_millis() <= timeout);
+                in_flight.contains_key(&peer_address)
+            });
+            if in_flight {
+                node_log!(Debug, GOSSIP, "Skipping round, exchange with {} still in flight", peer.address());
+                self.stats.lock().unwrap().skipped_header_rounds += 1;
+            }
+            else if let Ok(peer_address) = peer.socket_addr() {
+                let mut message = HeaderMessage::new_request(self.node_address.clone(), gossip_config.is_pull())
+                    .with_cluster(gossip_config.cluster_name().map(str::to_owned));
+                if first && gossip_config.is_push() {
+                    // send active headers
+                    let mut updates = self.updates.write().unwrap();
+
+                    if updates.active_count() > 0 {
+                        // a message never carries more headers than its receiver accepts
+                        let max_headers = gossip_config.max_headers_per_push().map_or(MAX_MESSAGE_DIGESTS, |max| max.min(MAX_MESSAGE_DIGESTS));
+                        let active_headers = updates.active_headers_for_push(Some(max_headers));
+                        message.set_headers(active_headers);
+                        let unpushed = updates.clear_expired();
+                        self.stats.lock().unwrap().updates_expired_unpushed += unpushed as u64;
+                        updates.publish();
+                    }
+                    // also taken without active updates, since tombstones remove updates between rounds
+                    let removed = updates.take_removed();
+                    if gossip_config.relay_headers() {
+                        message.set_relayed(self.relayed.lock().unwrap().digests(|digest| !updates.is_new(digest)));
+                    }
+                    message.set_tombstones(updates.tombstones());
+                    drop(updates);
+                    report_removed(removed, cluster_size(gossip_config, &self.published_peers), &self.stats, &self.observer);
+                }
+
+                // a request with nothing to push and no probe is never answered
+                if !message.wants_response() && message.headers().is_empty() && message.relayed().is_empty() && message.tombstones().is_empty() && !gossip_config.empty_pushes() {
+                    node_log!(Trace, GOSSIP, "Nothing to push to {}", peer.address());
+                    self.stats.lock().unwrap().skipped_empty_pushes += 1;
+                    continue;
+                }
+
+                node_log!(Debug, GOSSIP, "Will send header request with {:?}, relaying {:?}", message.headers(), message.relayed());
+                if first {
+                    summary.headers_sent = message.headers().len();
+                }
+
+                if message.wants_response() {
+                    // register the probe before sending so that a fast response is not ignored
+                    let window = probe_window(gossip_config);
+                    let mut header_probes = self.header_probes.lock().unwrap();
+                    header_probes.retain(|_, sent| sent.elapsed().as_millis() <= window);
+                    header_probes.insert(peer_address, Instant::now());
+                }
+                if message.wants_response() || !message.headers().is_empty() || !message.relayed().is_empty() {
+                    self.in_flight.lock().unwrap().insert(peer_address, Instant::now());
+                }
+
+                // the pushed headers consumed a push of their update even if the request cannot be sent
+                match self.sender.send(&peer_address, &message, gossip_config.cluster_id(), gossip_config.network()) {
+                    Ok(written) => {
+                        node_log!(Trace, GOSSIP, "Sent header request - {} bytes to {:?}", written, peer_address);
+                        // a push is complete once the peer has received it, since no response is expected
+                        if !message.wants_response() {
+                            self.in_flight.lock().unwrap().remove(&peer_address);
+                        }
+                        summary.sent |= first;
+                        self.usefulness.lock().unwrap().record_exchange(peer.address());
+                        if gossip_config.expiration_start() == ExpirationStart::OnFirstPush && !message.headers().is_empty() {
+                            self.updates.write().unwrap().start_expiration(message.headers());
+                        }
+                    },
+                    Err(e) => {
+                        node_log!(Error, GOSSIP, "Error sending header request: {:?}", e);
+                        self.errors.record(ErrorCategory::Send, Some(peer.address()), format!("header request: {}", e));
+                        self.peer_sampling.lock().unwrap().report_failure(peer.address());
+                        self.header_probes.lock().unwrap().remove(&peer_address);
+                        self.in_flight.lock().unwrap().remove(&peer_address);
+                    }
+                }
+            }
+        }
+        summary.duration = round_start.elapsed();
+        summary
+    }
+
+    /// Requests the content of the digests whose first request was not answered from their fallback peer, and
+    /// forgets the requests without fallback. The peer of a request that timed out may still answer it.
+    fn retry_content_requests(&self, gossip_config: &GossipConfig) {
+        let retries = {
+            let timeout = gossip_config.content_request_timeout() as u128;
+            let now = Instant::now();
+            let mut retries: HashMap<SocketAddr, HashMap<String, Vec<u8>>> = HashMap::new();
+            let mut content_requests = self.content_requests.lock().unwrap();
+            let mut timed_out = self.timed_out.lock().unwrap();
+            content_requests.retain(|digest, request| {
+                if request.sent.elapsed().as_millis() <= timeout {
+                    return true;
+                }
+                self.stats.lock().unwrap().content_request_timeouts += 1;
+                timed_out.record(digest.to_owned(), request.peer);
+                match request.fallback.take() {
+                    Some(fallback) => {
+                        retries.entry(fallback).or_default().insert(digest.to_owned(), vec![]);
+                        request.peer = fallback;
+                        request.sent = now;
+                        true
+                    }
+                    None => false,
+                }
+            });
+            retries
+        };
+        for (peer_address, digests) in retries {
+            node_log!(Debug, GOSSIP, "Requesting {} digests from fallback peer {}", digests.len(), peer_address);
+            let content_request = ContentMessage::new_request(self.node_address.clone(), digests);
+            if let Err(e) = self.sender.send(&peer_address, &content_request, gossip_config.cluster_id(), gossip_config.network()) {
+                node_log!(Error, GOSSIP, "Error sending content request: {:?}", e);
+                self.errors.record(ErrorCategory::Send, Some(&peer_address.to_string()), format!("content request: {}", e));
+            }
+        }
+    }
+}
+
+/// Number of nodes assumed by the coverage estimates: the configured cluster size, or the node and its view
+pub(crate) fn cluster_size(gossip_config: &GossipConfig, published_peers: &Snapshot<Peer>) -> usize {
+    gossip_config.cluster_size().unwrap_or_else(|| published_peers.load().len() + 1)
+}
+
+/// Counts the expired updates in the stats and reports the removed updates to the observer, if any
+pub(crate) fn report_removed(removed: Vec<(String, UpdateCounters, RemovalReason)>, cluster_size: usize, stats: &Mutex<GossipStats>, observer: &ObserverSlot) {
+    if removed.is_empty() {
+        return;
+    }
+    {
+        // the updates removed by tombstones or revocations are counted with them
+        let mut stats = stats.lock().unwrap();
+        for (_, counters, _) in removed.iter().filter(|(_, _, reason)| *reason == RemovalReason::Expired) {
+            stats.updates_expired += 1;
+            stats.expired_update_pushes += counters.pushes;
+            stats.expired_update_requesters += counters.distinct_requesters as u64;
+        }
+    }
+    if let Some(observer) = crate::observer::current(observer) {
+        for (digest, counters, reason) in removed {
+            let estimated_coverage = coverage_probability(cluster_size, counters.pushes);
+            observer.on_update_expired(ExpirySummary { digest, counters, estimated_coverage, reason });
+        }
+    }
+}
+
+/// Time (milliseconds) during which the header response to a pull probe is accepted
+pub(crate) fn probe_window(gossip_config: &GossipConfig) -> u128 {
+    2 * (gossip_config.gossip_period() + gossip_config.gossip_deviation()) as u128
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use crate::config::UpdateExpirationMode;
+    use crate::message::{Message, MESSAGE_PROTOCOL_HEADER_MESSAGE};
+    use crate::sampler::StaticSampler;
+    use crate::traffic::RecordingSender;
+    use crate::update::Update;
+    use crate::warning::WARNING_INTERVAL;
+
+    /// Returns the header messages sent by the round, with their receiver
+    fn headers(round: &GossipRound<RecordingSender>) -> Vec<(SocketAddr, HeaderMessage)> {
+        round.sender.messages(MESSAGE_PROTOCOL_HEADER_MESSAGE)
+    }
+
+    fn peers(ports: &[u16]) -> Vec<Peer> {
+        ports.iter().map(|port| Peer::try_new(&format!("127.0.0.1:{}", port)).unwrap()).collect()
+    }
+
+    /// Round of a node with a fixed view, whose messages are recorded
+    fn new_round(view: Vec<Peer>, gossip_config: &GossipConfig) -> GossipRound<RecordingSender> {
+        GossipRound {
+            node_address: "127.0.0.1:9000".to_owned(),
+            peer_sampling: Arc::new(Mutex::new(Box::new(StaticSampler::new(view)))),
+            updates: Arc::new(RwLock::new(UpdateDecorator::new(gossip_config.update_expiration().clone(), Arc::clone(gossip_config.digester())))),
+            header_probes: Arc::new(Mutex::new(HashMap::new())),
+            in_flight: Arc::new(Mutex::new(HashMap::new())),
+            content_requests: Arc::new(Mutex::new(HashMap::new())),
+            timed_out: Arc::new(Mutex::new(TimedOutRequests::new())),
+            relayed: Arc::new(Mutex::new(RelayedDigests::new())),
+            usefulness: Arc::new(Mutex::new(PeerUsefulness::new())),
+            quarantine: Arc::new(Mutex::new(Quarantine::new(gossip_config.quarantine_threshold(), gossip_config.quarantine_cooldown()))),
+            published_peers: Arc::new(Snapshot::new()),
+            stats: Arc::new(Mutex::new(GossipStats::default())),
+            errors: Arc::new(ErrorLog::new()),
+            observer: Arc::new(RwLock::new(None)),
+            sender: Arc::new(RecordingSender::default()),
+            no_peer_warning: RateLimitedWarning::new(WARNING_INTERVAL),
+        }
+    }
+
+    fn insert(round: &GossipRound<RecordingSender>, content: &[u8]) -> String {
+        let update = Update::new(content.to_vec());
+        let digest = update.digest().to_owned();
+        round.updates.write().unwrap().insert_update(update).unwrap();
+        digest
+    }
+
+    #[test]
+    fn push_round_sends_active_headers_to_one_peer() {
+        let gossip_config = GossipConfig::new(true, false, 100, UpdateExpirationMode::None);
+        let mut round = new_round(peers(&[9001, 9002]), &gossip_config);
+        let digest = insert(&round, b"pushed");
+
+        let summary = round.run(&gossip_config);
+        let sent = headers(&round);
+        assert_eq!(1, sent.len());
+        let (receiver, message) = &sent[0];
+        assert_eq!(Some(receiver.to_string()), summary.peer);
+        assert_eq!(&vec![digest], message.headers());
+        assert!(!message.wants_response());
+        assert_eq!(1, summary.headers_sent);
+        assert!(summary.sent);
+        // no response is expected: the exchange completed with the push
+        assert!(round.in_flight.lock().unwrap().is_empty());
+        assert!(round.header_probes.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn pushed_headers_are_limited_to_what_a_message_carries() {
+        let mut gossip_config = GossipConfig::new(true, false, 100, UpdateExpirationMode::None);
+        let mut round = new_round(peers(&[9001]), &gossip_config);
+        for i in 0..=MAX_MESSAGE_DIGESTS {
+            insert(&round, i.to_string().as_bytes());
+        }
+
+        round.run(&gossip_config);
+        // a lower configured limit applies
+        gossip_config.set_max_headers_per_push(10);
+        round.run(&gossip_config);
+        let sent = headers(&round);
+        assert_eq!(vec![MAX_MESSAGE_DIGESTS, 10], sent.iter().map(|(_, message)| message.headers().len()).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn push_round_without_update_sends_nothing() {
+        let gossip_config = GossipConfig::new(true, false, 100, UpdateExpirationMode::None);
+        let mut round = new_round(peers(&[9001]), &gossip_config);
+
+        let summary = round.run(&gossip_config);
+        assert!(round.sender.sent.lock().unwrap().is_empty());
+        assert!(!summary.sent);
+        assert_eq!(1, round.stats.lock().unwrap().skipped_empty_pushes);
+    }
+
+    #[test]
+    fn pull_round_probes_the_peers_without_pushing() {
+        let mut gossip_config = GossipConfig::new(false, true, 100, UpdateExpirationMode::None);
+        gossip_config.set_pull_fanout(2);
+        let mut round = new_round(peers(&[9001, 9002, 9003]), &gossip_config);
+        insert(&round, b"not pushed");
+
+        let summary = round.run(&gossip_config);
+        let sent = headers(&round);
+        assert_eq!(2, sent.len());
+        assert_ne!(sent[0].0, sent[1].0);
+        assert!(sent.iter().all(|(_, message)| message.wants_response() && message.headers().is_empty()));
+        assert_eq!(0, summary.headers_sent);
+        let header_probes = round.header_probes.lock().unwrap();
+        assert!(sent.iter().all(|(receiver, _)| header_probes.contains_key(receiver)));
+    }
+
+    #[test]
+    fn round_with_empty_view_sends_nothing() {
+        let gossip_config = GossipConfig::new(true, true, 100, UpdateExpirationMode::None);
+        let mut round = new_round(Vec::new(), &gossip_config);
+        insert(&round, b"unsent");
+
+        let summary = round.run(&gossip_config);
+        assert_eq!(None, summary.peer);
+        assert!(!summary.sent);
+        assert!(round.sender.sent.lock().unwrap().is_empty());
+        let stats = round.stats.lock().unwrap();
+        assert_eq!(1, stats.gossip_rounds);
+        assert_eq!(1, stats.rounds_without_peer);
+    }
+
+    #[test]
+    fn pushes_decrement_the_push_count() {
+        let gossip_config = GossipConfig::new(true, false, 100, UpdateExpirationMode::PushCount(2));
+        let mut round = new_round(peers(&[9001]), &gossip_config);
+        let digest = insert(&round, b"pushed twice");
+
+        for _ in 0..2 {
+            assert!(round.updates.read().unwrap().is_active(&digest));
+            round.run(&gossip_config);
+        }
+        // the update expired with its last push
+        assert_eq!(2, headers(&round).iter().filter(|(_, message)| message.headers().contains(&digest)).count());
+        assert!(round.updates.read().unwrap().is_expired(&digest));
+        assert_eq!(1, round.stats.lock().unwrap().updates_expired);
+
+        round.run(&gossip_config);
+        assert_eq!(2, headers(&round).len());
+    }
+
+    #[test]
+    fn failed_push_consumes_a_push() {
+        let gossip_config = GossipConfig::new(true, false, 100, UpdateExpirationMode::PushCount(1));
+        let mut round = new_round(peers(&[9001]), &gossip_config);
+        let digest = insert(&round, b"pushed once");
+        round.sender.failing.store(true, std::sync::atomic::Ordering::SeqCst);
+
+        let summary = round.run(&gossip_config);
+        assert!(!summary.sent);
+        assert!(round.sender.sent.lock().unwrap().is_empty());
+        assert_eq!(1, round.errors.recent().len());
+        assert!(round.updates.read().unwrap().is_expired(&digest));
+    }
+
+    #[test]
+    fn unanswered_content_request_is_sent_to_the_fallback_peer() {
+        let mut gossip_config = GossipConfig::new(true, false, 100, UpdateExpirationMode::None);
+        gossip_config.set_content_request_timeout(100);
+        let mut round = new_round(Vec::new(), &gossip_config);
+        let fallback: SocketAddr = "127.0.0.1:9002".parse().unwrap();
+        round.content_requests.lock().unwrap().insert("digest".to_owned(), ContentRequest {
+            peer: "127.0.0.1:9001".parse().unwrap(),
+            sent: Instant::now() - Duration::from_millis(200),
+            fallback: Some(fallback),
+        });
+
+        round.run(&gossip_config);
+        let sent = round.sender.sent.lock().unwrap();
+        assert_eq!(1, sent.len());
+        assert_eq!(fallback, sent[0].0);
+        let request = ContentMessage::from_bytes(&sent[0].1[1..]).unwrap();
+        assert!(request.content_ref().contains_key("digest"));
+        assert_eq!(Some(&fallback), round.content_requests.lock().unwrap().get("digest").map(|request| &request.peer));
+        assert_eq!(1, round.stats.lock().unwrap().content_request_timeouts);
+    }
+}
@@ -0,0 +1,425 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::{Receiver, Sender};
+use std::time::{Duration, Instant};
+use rand::seq::SliceRandom;
+use crate::bootstrap::BootstrapSource;
+use crate::command::{Command, Timer};
+use crate::config::PeerSamplingConfig;
+use crate::message::sampling::PeerSamplingMessage;
+use crate::peer::Peer;
+use crate::snapshot::Snapshot;
+use crate::view_stats::SamplingStats;
+use crate::memory::SamplerMemory;
+use crate::logging::targets::SAMPLING;
+use crate::error::AlreadyStarted;
+
+/// Time during which a peer of a static view is not selected after a message could not be sent to it
+const FAILED_PEER_BACKOFF: Duration = Duration::from_secs(1);
+
+/// Maintains the view of a node, from which the gossip layer selects the peers it exchanges updates with.
+///
+/// The default implementation is the [Gossip-based Peer Sampling](https://infoscience.epfl.ch/record/109297/files/all.pdf)
+/// service configured by a [PeerSamplingConfig]; [StaticSampler] keeps a fixed list of peers. Other view maintenance
+/// algorithms can be used with [GossipService::with_sampler](crate::GossipService::with_sampler).
+///
+/// Methods are called with the sampler locked, from the threads of the node: they should return quickly, and the
+/// view should be [published](ViewPublisher::publish) after each change so that it can be read without waiting.
+/// A sampler can have its messages handled and its rounds run by the threads of the node, see [SamplerTasks].
+pub trait PeerSampler: Send {
+    /// Starts maintaining the view, see [SamplerContext]. The samplers of the crate return an [AlreadyStarted]
+    /// error when started again, even after shutdown, unless they were [reset](Self::reset).
+    fn init(&mut self, context: SamplerContext) -> Result<(), Box<dyn Error>>;
+
+    /// Returns up to `count` distinct peers for the next gossip round; none before [init](Self::init) or after
+    /// [shutdown](Self::shutdown), which [state](Self::state) tells apart from an empty view
+    fn get_peers(&mut self, count: usize) -> Vec<Peer>;
+
+    /// Returns up to `count` distinct peers of the view chosen uniformly at random, without the side effects that
+    /// [get_peers](Self::get_peers) may have, such as consuming a queue of new peers. Used by the gossip rounds with
+    /// [uniform peer selection](crate::GossipConfig::set_uniform_peer_selection); defaults to `get_peers`.
+    fn sample_peers(&mut self, count: usize) -> Vec<Peer> {
+        self.get_peers(count)
+    }
+
+    /// Stops maintaining the view; called after the listener is stopped, when the sender of the message channel
+    /// is dropped, and once the threads of the node running the [tasks](SamplerTasks) of the sampler are stopped
+    fn shutdown(&mut self) -> Result<(), Box<dyn Error>>;
+
+    /// Returns a sampler [shut down](Self::shutdown) by a failed start to [SamplingState::NotStarted], so that the
+    /// node can be started again; called once the failed start is cleaned up
+    fn reset(&mut self) {}
+
+    /// Stops initiating exchanges with other peers, before the node leaves the network; received messages are still handled
+    fn stop_sampling(&mut self) -> Result<(), Box<dyn Error>> {
+        Ok(())
+    }
+
+    /// Notifies the peers that the node is leaving the network, before shutdown
+    fn leave(&self) {}
+
+    /// Called when a message could not be sent to a peer of the view
+    fn report_failure(&self, _address: &str) {}
+
+    /// Called when a peer of the view left the keepalive pings unanswered, see
+    /// [GossipConfig::set_keepalive](crate::GossipConfig::set_keepalive); handled as a failure to send by default
+    fn report_unreachable(&self, address: &str) {
+        self.report_failure(address);
+    }
+
+    /// Called when a peer is quarantined for sending invalid data; the gossip layer does not select it before the
+    /// end of its quarantine anyway
+    fn quarantine(&self, _address: &str, _until: Instant) {}
+
+    /// Returns the [bootstrap source](SamplerContext::bootstrap) kept by the sampler, if it bootstraps. When the
+    /// node [bootstraps again](crate::GossipService::rebootstrap), the source is resolved without the sampler
+    /// locked, and its peers are [merged](Self::merge_peers) into the view.
+    fn bootstrap_source(&self) -> Option<Arc<Mutex<BootstrapSource>>> {
+        None
+    }
+
+    /// Adds peers to the view of a started sampler, when the node [joins](crate::GossipService::join) or
+    /// bootstraps again; samplers with a fixed view ignore them
+    fn merge_peers(&mut self, _peers: Vec<Peer>) {}
+
+    /// Starts a round immediately, if the sampler has rounds; a [paused](Self::set_paused) sampler also starts it
+    fn trigger_round(&self) {}
+
+    /// Stops or resumes initiating rounds, if the sampler has rounds; received messages are still handled
+    fn set_paused(&self, _paused: bool) {}
+
+    /// Returns whether the sampler is started and its threads, if any, are alive
+    fn is_running(&self) -> bool;
+
+    /// Returns whether the sampler was started or shut down. The default implementation cannot tell a stopped
+    /// sampler from one that was never started, and reports [SamplingState::NotStarted] for both.
+    fn state(&self) -> SamplingState {
+        if self.is_running() { SamplingState::Running } else { SamplingState::NotStarted }
+    }
+
+    /// Returns whether the node takes part in the network, see [HealthReport::ready](crate::HealthReport::ready)
+    fn is_ready(&self) -> bool;
+
+    /// Returns the time at which the view was last refreshed from another peer, if the sampler exchanges views
+    fn last_exchange(&self) -> Option<Instant> {
+        None
+    }
+
+    /// Returns the peer sampling parameters, used to cross-check the gossip configuration, if any
+    fn config(&self) -> Option<&PeerSamplingConfig> {
+        None
+    }
+
+    /// Returns the number of nodes of the network estimated by the sampler, if it estimates it
+    fn estimated_cluster_size(&self) -> Option<f64> {
+        None
+    }
+
+    /// Returns the indicators of the quality of the view, if the sampler exchanges views
+    fn sampling_stats(&self) -> Option<SamplingStats> {
+        None
+    }
+
+    /// Returns the sizes of the structures of the sampler, see [GossipService::memory_report](crate::GossipService::memory_report)
+    fn memory_report(&self) -> Option<SamplerMemory> {
+        None
+    }
+}
+
+/// Lifecycle of a [PeerSampler], see [GossipService::sampling_state](crate::GossipService::sampling_state)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SamplingState {
+    /// [PeerSampler::init] was not called yet: the view is empty
+    NotStarted,
+    /// The view is maintained
+    Running,
+    /// [PeerSampler::shutdown] was called: the view is cleared and the sampler cannot be started again, unless it
+    /// is [reset](PeerSampler::reset) after a failed start
+    ShutDown,
+}
+
+/// Everything a [PeerSampler] is given when the node starts
+pub struct SamplerContext {
+    /// Address of the node
+    pub address: SocketAddr,
+    /// Peers of the bootstrap source resolved at start, or of the [imported view](crate::GossipService::import_view)
+    pub initial_peers: Option<Vec<Peer>>,
+    /// Source given to [GossipService::start](crate::GossipService::start), kept to bootstrap the node again
+    pub bootstrap: Arc<Mutex<BootstrapSource>>,
+    /// Peer sampling messages received by the node, unless the sampler [handles](SamplerTasks::handle_messages)
+    /// them on the message handling thread of the node; the channel is closed when the listener stops. The receiver
+    /// must be kept until then: the node is reported [degraded](crate::HealthReport::degraded) if messages are
+    /// received after it is dropped, and messages are dropped while the channel is full.
+    pub messages: Receiver<PeerSamplingMessage>,
+    /// Runs the tasks of the sampler on the threads of the node
+    pub tasks: SamplerTasks,
+    /// Publishes the view, see [GossipService::peers](crate::GossipService::peers)
+    pub view: ViewPublisher,
+}
+
+/// Handles the peer sampling messages received by a node
+pub(crate) type MessageHandler = Box<dyn FnMut(PeerSamplingMessage) + Send>;
+
+/// Runs a round of a sampler and returns the delay until the next one
+pub(crate) type SamplerRound = Box<dyn FnMut() -> Duration + Send>;
+
+/// Tasks registered by a sampler, taken by the node once the sampler is started
+#[derive(Default)]
+pub(crate) struct TaskSlots {
+    handler: Mutex<Option<MessageHandler>>,
+    rounds: Mutex<Option<(Duration, SamplerRound)>>,
+}
+
+impl TaskSlots {
+    /// Returns the message handler registered by the sampler, if any
+    pub fn take_handler(&self) -> Option<MessageHandler> {
+        self.handler.lock().unwrap().take()
+    }
+
+    /// Returns the rounds registered by the sampler, with the delay until the first one, if any
+    pub fn take_rounds(&self) -> Option<(Duration, SamplerRound)> {
+        self.rounds.lock().unwrap().take()
+    }
+}
+
+/// Runs the tasks of a [PeerSampler] on the threads of the node, so that the sampler needs no thread of its own: the
+/// received messages are handled on the thread handling the gossip messages, and the rounds are run on the thread
+/// running the gossip rounds. The tasks are registered during [init](PeerSampler::init), and should return quickly
+/// as the other messages and rounds of the node wait for them.
+pub struct SamplerTasks {
+    slots: Arc<TaskSlots>,
+    commands: Sender<(Timer, Command)>,
+}
+
+impl SamplerTasks {
+    /// Creates the tasks of a sampler, whose rounds are sent commands on the given channel
+    pub(crate) fn new(commands: Sender<(Timer, Command)>) -> (Self, Arc<TaskSlots>) {
+        let slots = Arc::new(TaskSlots::default());
+        (SamplerTasks { slots: Arc::clone(&slots), commands }, slots)
+    }
+
+    /// Handles the received peer sampling messages with the given handler, instead of sending them on the
+    /// [messages](SamplerContext::messages) channel
+    pub fn handle_messages<F>(&self, handler: F)
+    where F: FnMut(PeerSamplingMessage) + Send + 'static
+    {
+        self.slots.handler.lock().unwrap().replace(Box::new(handler));
+    }
+
+    /// Runs the rounds of the sampler: the first one after the given delay, and each following one after the delay
+    /// returned by the previous one. Returns the control of the rounds.
+    pub fn run_rounds<F>(&self, first: Duration, round: F) -> RoundControl
+    where F: FnMut() -> Duration + Send + 'static
+    {
+        self.slots.rounds.lock().unwrap().replace((first, Box::new(round)));
+        RoundControl {
+            commands: self.commands.clone(),
+        }
+    }
+}
+
+/// Controls the rounds of a sampler run by the node, see [SamplerTasks::run_rounds]
+pub struct RoundControl {
+    commands: Sender<(Timer, Command)>,
+}
+
+impl RoundControl {
+    /// Starts a round immediately, also while the rounds are paused
+    pub fn trigger(&self) {
+        self.send(Command::ForceRound);
+    }
+
+    /// Stops or resumes the scheduled rounds
+    pub fn set_paused(&self, paused: bool) {
+        self.send(if paused { Command::Pause } else { Command::Resume });
+    }
+
+    /// Stops the rounds; a round in progress completes
+    pub fn stop(&self) {
+        self.send(Command::Shutdown);
+    }
+
+    fn send(&self, command: Command) {
+        if self.commands.send((Timer::Sampling, command)).is_err() {
+            node_log!(Debug, SAMPLING, "Rounds of the node have stopped, ignoring {:?}", command);
+        }
+    }
+}
+
+/// Publishes the view of a [PeerSampler] for the readers of [GossipService::peers](crate::GossipService::peers)
+#[derive(Clone)]
+pub struct ViewPublisher {
+    snapshot: Arc<Snapshot<Peer>>,
+}
+
+impl ViewPublisher {
+    pub(crate) fn new(snapshot: Arc<Snapshot<Peer>>) -> Self {
+        ViewPublisher {
+            snapshot,
+        }
+    }
+
+    pub(crate) fn snapshot(&self) -> Arc<Snapshot<Peer>> {
+        Arc::clone(&self.snapshot)
+    }
+
+    /// Replaces the published view
+    pub fn publish(&self, peers: Vec<Peer>) {
+        self.snapshot.store(peers);
+    }
+}
+
+/// A view made of a fixed list of peers, without peer sampling: no message is exchanged to maintain it.
+/// Peers that recently could not be reached are not selected, unless all of them failed.
+pub struct StaticSampler {
+    /// The configured peers, to which the initial peers are added at start
+    peers: Vec<Peer>,
+    /// The configuration the sampler was created from, if any
+    config: Option<PeerSamplingConfig>,
+    /// Peers to which a message could not be sent, with the time of the failure
+    failed: Mutex<HashMap<String, Instant>>,
+    /// Publishes the view while started
+    view: Option<ViewPublisher>,
+    /// Whether the sampler was started or shut down
+    state: SamplingState,
+}
+
+impl StaticSampler {
+    /// Creates a sampler returning the given peers
+    ///
+    /// # Arguments
+    ///
+    /// * `peers` - The peers of the node; the address of the node itself and invalid addresses are ignored
+    pub fn new(mut peers: Vec<Peer>) -> Self {
+        peers.retain(|peer| {
+            if !peer.is_valid() {
+                node_log!(Warn, SAMPLING, "Ignoring peer with invalid address {:?}", peer.address());
+            }
+            peer.is_valid()
+        });
+        StaticSampler {
+            peers,
+            config: None,
+            failed: Mutex::new(HashMap::new()),
+            view: None,
+            state: SamplingState::NotStarted,
+        }
+    }
+
+    /// Creates the sampler of a [static view](PeerSamplingConfig::static_view) configuration
+    pub(crate) fn from_config(config: PeerSamplingConfig) -> Self {
+        let mut sampler = Self::new(config.static_peers().unwrap_or_default().to_vec());
+        sampler.config = Some(config);
+        sampler
+    }
+}
+
+impl PeerSampler for StaticSampler {
+    fn init(&mut self, context: SamplerContext) -> Result<(), Box<dyn Error>> {
+        if self.state != SamplingState::NotStarted {
+            Err(AlreadyStarted)?
+        }
+        // received peer sampling messages are discarded
+        context.tasks.handle_messages(drop);
+        let address = context.address.to_string();
+        let mut peers: Vec<Peer> = Vec::new();
+        for peer in self.peers.iter().cloned().chain(context.initial_peers.unwrap_or_default()) {
+            if peer.address() != address && !peers.iter().any(|known| known.address() == peer.address()) {
+                peers.push(peer);
+            }
+        }
+        node_log!(Info, SAMPLING, "Static view of {} peers, peer sampling is disabled", peers.len());
+        self.peers = peers;
+        context.view.publish(self.peers.clone());
+        self.view = Some(context.view);
+        self.state = SamplingState::Running;
+        Ok(())
+    }
+
+    fn get_peers(&mut self, count: usize) -> Vec<Peer> {
+        let mut failed = self.failed.lock().unwrap();
+        failed.retain(|_, time| time.elapsed() < FAILED_PEER_BACKOFF);
+        let mut available: Vec<&Peer> = self.peers.iter().filter(|peer| !failed.contains_key(peer.address())).collect();
+        if available.is_empty() {
+            available = self.peers.iter().collect();
+        }
+        available.choose_multiple(&mut rand::thread_rng(), count).map(|peer| (*peer).clone()).collect()
+    }
+
+    fn shutdown(&mut self) -> Result<(), Box<dyn Error>> {
+        if let Some(view) = self.view.take() {
+            view.publish(Vec::new());
+        }
+        if self.state == SamplingState::Running {
+            self.state = SamplingState::ShutDown;
+        }
+        Ok(())
+    }
+
+    fn reset(&mut self) {
+        if self.state == SamplingState::ShutDown {
+            self.state = SamplingState::NotStarted;
+        }
+    }
+
+    fn report_failure(&self, address: &str) {
+        self.failed.lock().unwrap().insert(address.to_owned(), Instant::now());
+    }
+
+    fn is_running(&self) -> bool {
+        self.view.is_some()
+    }
+
+    fn state(&self) -> SamplingState {
+        self.state
+    }
+
+    fn is_ready(&self) -> bool {
+        self.view.is_some()
+    }
+
+    fn config(&self) -> Option<&PeerSamplingConfig> {
+        self.config.as_ref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn start_sampler(peers: &[&str]) -> StaticSampler {
+        let mut sampler = StaticSampler::new(peers.iter().map(|address| Peer::unchecked(address.to_string())).collect());
+        sampler.init(SamplerContext {
+            address: "127.0.0.1:9103".parse().unwrap(),
+            initial_peers: None,
+            bootstrap: Arc::new(Mutex::new(BootstrapSource::default())),
+            messages: std::sync::mpsc::channel().1,
+            tasks: SamplerTasks::new(std::sync::mpsc::channel().0).0,
+            view: ViewPublisher::new(Arc::new(Snapshot::new())),
+        }).unwrap();
+        sampler
+    }
+
+    #[test]
+    fn failed_peers_are_skipped() {
+        let mut sampler = start_sampler(&["127.0.0.1:9103", "127.4.0.1:1", "127.4.0.2:1"]);
+        assert_eq!(2, sampler.get_peers(5).len());
+        sampler.report_failure("127.4.0.1:1");
+        for _ in 0..10 {
+            assert_eq!(vec![Peer::try_new("127.4.0.2:1").unwrap()], sampler.get_peers(1));
+        }
+        // all peers failed: any of them may be selected
+        sampler.report_failure("127.4.0.2:1");
+        assert_eq!(2, sampler.get_peers(2).len());
+        sampler.failed.lock().unwrap().insert("127.4.0.1:1".to_owned(), Instant::now() - FAILED_PEER_BACKOFF);
+        assert_eq!(vec![Peer::try_new("127.4.0.1:1").unwrap()], sampler.get_peers(1));
+    }
+
+    #[test]
+    fn invalid_peers_are_ignored() {
+        let mut sampler = start_sampler(&["127.4.0.1:1", "not an address", "127.4.0.2", ""]);
+        assert_eq!(vec![Peer::try_new("127.4.0.1:1").unwrap()], sampler.get_peers(5));
+    }
+}
@@ -1,17 +1,34 @@
 use std::sync::{Arc, Mutex};
-use std::thread::JoinHandle;
-use std::sync::atomic::AtomicBool;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::net::SocketAddr;
 use rand::Rng;
 use rand::seq::SliceRandom;
 use std::error::Error;
-use std::sync::mpsc::Receiver;
-use std::collections::{HashSet, VecDeque};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::time::{Duration, Instant};
 use std::iter::FromIterator;
 use crate::PeerSamplingConfig;
-use crate::peer::Peer;
+use crate::config::NetworkConfig;
+use crate::peer::{Peer, MAX_PEER_METADATA};
 use crate::message::sampling::PeerSamplingMessage;
-use crate::message::{NoopMessage, MessageType};
+use crate::message::{MessageType, MESSAGE_PROTOCOL_SAMPLING_MESSAGE};
+use crate::observer::{ObserverSlot, SamplingSummary};
+use crate::stats::GossipStats;
+use crate::quarantine::Quarantine;
+use crate::snapshot::Snapshot;
+use crate::error_log::{ErrorCategory, ErrorLog};
+use crate::traffic::{MessageSender, TrafficLog};
+use crate::estimate::SizeEstimator;
+use crate::view_stats::{SamplingStats, ViewStats};
+use crate::sampler::{PeerSampler, RoundControl, SamplerContext, SamplingState};
+use crate::error::AlreadyStarted;
+use crate::bootstrap::BootstrapSource;
+use crate::command::round_delay;
+use crate::warning::{RateLimitedWarning, WARNING_INTERVAL};
+use crate::memory::{SamplerMemory, Usage};
+use crate::logging::targets::SAMPLING;
+#[cfg(test)]
+use crate::sampler::ViewPublisher;
 
 /// Peer sampling service to by used by application
 pub struct PeerSamplingService {
@@ -19,12 +36,44 @@ pub struct PeerSamplingService {
     address: SocketAddr,
     /// Protocol parameters
     config: PeerSamplingConfig,
+    /// Cluster stamped on the sent messages
+    cluster_id: Option<u8>,
+    /// Name of the cluster, carried in the sent messages
+    cluster_name: Option<String>,
+    /// Options of the outbound connections
+    network: NetworkConfig,
     /// View containing a list of other peers
     view: Arc<Mutex<View>>,
-    // Handles for activity threads
-    thread_handles: Vec<JoinHandle<()>>,
-    /// Handle for shutting down threads
-    shutdown: Arc<AtomicBool>,
+    /// Peers of the view, published after each change of the view
+    published: Arc<Snapshot<Peer>>,
+    /// Rounds initiating exchanges, run by the node while the service is sampling
+    rounds: Option<RoundControl>,
+    /// Observer of the sampling rounds
+    observer: ObserverSlot,
+    /// Peers to which a request expecting a response was sent, with the time it was sent
+    pull_requests: Arc<Mutex<HashMap<String, Instant>>>,
+    /// Activity counters
+    stats: Arc<Mutex<GossipStats>>,
+    /// Recent errors of the protocol threads, shared with the gossip service
+    errors: Arc<ErrorLog>,
+    /// Traffic with each peer, shared with the gossip service
+    traffic: Arc<TrafficLog>,
+    /// Peers sending invalid data, shared with the gossip service
+    quarantine: Arc<Mutex<Quarantine>>,
+    /// Time at which a buffer from another peer was last merged into the view
+    last_exchange: Arc<Mutex<Option<Instant>>>,
+    /// Whether the node was started without initial peers
+    seed: bool,
+    /// Source of the initial peers, resolved again when the node bootstraps again
+    bootstrap: Arc<Mutex<BootstrapSource>>,
+    /// Estimation of the size of the network, if enabled
+    estimator: Option<Arc<Mutex<SizeEstimator>>>,
+    /// Whether the node is leaving, after which it stops advertising itself
+    leaving: Arc<AtomicBool>,
+    /// Statistics of the view at the end of the sampling cycles
+    view_stats: Arc<Mutex<ViewStats>>,
+    /// Whether the service was started or shut down
+    state: SamplingState,
 }
 
 impl PeerSamplingService {
@@ -33,209 +82,625 @@ impl PeerSamplingService {
     /// # Arguments
     ///
     /// * `config` - The parameters for the peer sampling protocol [PeerSamplingConfig]
-    pub fn new(address: SocketAddr, config: PeerSamplingConfig) -> PeerSamplingService {
+    /// * `cluster_id` - Cluster stamped on the sent messages
+    /// * `cluster_name` - Name of the cluster; messages from other clusters are dropped
+    /// * `observer` - Observer of the sampling rounds
+    /// * `stats` - Activity counters
+    /// * `quarantine` - Peers sending invalid data, whose messages are ignored
+    pub fn new(address: SocketAddr, config: PeerSamplingConfig, cluster_id: Option<u8>, cluster_name: Option<String>, observer: ObserverSlot, stats: Arc<Mutex<GossipStats>>, quarantine: Arc<Mutex<Quarantine>>) -> PeerSamplingService {
+        let estimator = config.size_estimation()
+            .map(|epoch_rounds| Arc::new(Mutex::new(SizeEstimator::new(&address.to_string(), epoch_rounds))));
         PeerSamplingService {
             address,
-            view: Arc::new(Mutex::new(View::new(address.to_string()))),
+            view: Arc::new(Mutex::new(View {
+                immediate_queue: config.queue_new_peers_immediately(),
+                ..View::with_max_queue(address.to_string(), config.max_peer_queue())
+            })),
+            published: Arc::new(Snapshot::new()),
             config,
-            thread_handles: Vec::new(),
-            shutdown: Arc::new(AtomicBool::new(false)),
+            cluster_id,
+            cluster_name,
+            network: NetworkConfig::default(),
+            errors: Arc::new(ErrorLog::new()),
+            traffic: Arc::new(TrafficLog::new()),
+            rounds: None,
+            observer,
+            pull_requests: Arc::new(Mutex::new(HashMap::new())),
+            stats,
+            quarantine,
+            last_exchange: Arc::new(Mutex::new(None)),
+            seed: false,
+            bootstrap: Arc::new(Mutex::new(BootstrapSource::default())),
+            estimator,
+            leaving: Arc::new(AtomicBool::new(false)),
+            view_stats: Arc::new(Mutex::new(ViewStats::new())),
+            state: SamplingState::NotStarted,
         }
     }
 
-    /// Initializes service
-    ///
-    /// # Arguments
+    /// Time (milliseconds) during which the response to a request is accepted
+    fn response_window(config: &PeerSamplingConfig) -> u128 {
+        (config.sampling_period() + config.sampling_deviation()) as u128
+    }
+
+    /// Returns up to `count` distinct random peers for the client application.
+    /// The peers are pseudo-random peers from the set of all peers.
+    /// The local view is built using [Gossip-Based Peer Sampling].
     ///
-    /// * `initial_peer` - A closure returning the initial peer for starting the protocol
-    pub fn init(&mut self, initial_peer: Box<dyn FnOnce() -> Option<Vec<Peer>>>, receiver: Receiver<PeerSamplingMessage>) {
-        // get address of initial peer
-        if let Some(initial_peers) = initial_peer() {
-            let mut view = self.view.lock().unwrap();
-            for peer in initial_peers {
-                if peer.address() != &self.address.to_string() {
-                    view.peers.push(peer);
-                }
+    /// The peers newly added to the view are returned first, and are then consumed; see
+    /// [sample_peers](Self::sample_peers) for a selection without side effects. No peer is returned before the
+    /// service is started or after it is shut down.
+    pub fn get_peers(&mut self, count: usize) -> Vec<Peer> {
+        self.view.lock().unwrap().get_peers(count)
+    }
+
+    /// Returns up to `count` distinct peers of the view chosen uniformly at random. Unlike
+    /// [get_peers](Self::get_peers), the queue of new peers is left untouched.
+    pub fn sample_peers(&self, count: usize) -> Vec<Peer> {
+        self.view.lock().unwrap().sample_peers(count)
+    }
+
+    /// Sets the options of the outbound connections, used by the threads started afterwards
+    pub fn set_network(&mut self, network: NetworkConfig) {
+        self.network = network;
+    }
+
+    /// Sets the log of recent errors, used by the threads started afterwards
+    pub fn set_error_log(&mut self, errors: Arc<ErrorLog>) {
+        self.errors = errors;
+    }
+
+    /// Sets the log of the traffic with each peer, used by the threads started afterwards
+    pub fn set_traffic(&mut self, traffic: Arc<TrafficLog>) {
+        self.traffic = traffic;
+    }
+
+    /// Adds the given peers to the view, except the node itself, the peers already in the view and the invalid peers
+    fn add_peers(&self, peers: Vec<Peer>) {
+        let mut view = self.view.lock().unwrap();
+        let previous: HashSet<String> = view.peers.iter().map(|peer| peer.address().to_owned()).collect();
+        for peer in peers {
+            if !peer.is_valid() {
+                node_log!(Warn, SAMPLING, "Ignoring peer with invalid address {:?}", peer.address());
             }
+            else if peer.address() != self.address.to_string() && !view.peers.iter().any(|known| known.address() == peer.address()) {
+                view.peers.push(peer);
+            }
+        }
+        let max_queue = view.max_queue.unwrap_or(self.config.view_size());
+        view.update_queue(&previous, max_queue);
+        self.published.store(view.peers.clone());
+    }
+
+    /// Returns the peers in the node view, as published after the last change of the view.
+    /// Reading the peers does not contend with the protocol threads.
+    pub fn peers(&self) -> Arc<Vec<Peer>> {
+        self.published.load()
+    }
+}
+
+impl PeerSampler for PeerSamplingService {
+    /// Initializes the view with the initial peers, and registers the handler of the received messages and the
+    /// rounds initiating exchanges, run by the threads of the node. An [AlreadyStarted] error is returned if the
+    /// service was started before.
+    fn init(&mut self, context: SamplerContext) -> Result<(), Box<dyn Error>> {
+        if self.state != SamplingState::NotStarted {
+            Err(AlreadyStarted)?
+        }
+        self.published = context.view.snapshot();
+        let initial_peers = context.initial_peers;
+        self.seed = initial_peers.as_ref().is_none_or(|peers| peers.is_empty());
+        if let Some(initial_peers) = initial_peers {
+            self.add_peers(initial_peers);
         }
+        self.bootstrap = context.bootstrap;
 
         // handle received messages
-        let receiver_handle = self.start_receiver(receiver);
-        self.thread_handles.push(receiver_handle);
+        context.tasks.handle_messages(self.message_handler());
 
         // start peer sampling
-        let sampling_handle = self.start_sampling_activity();
-        self.thread_handles.push(sampling_handle);
+        let (first, round) = self.sampling_rounds();
+        self.rounds = Some(context.tasks.run_rounds(first, round));
+        self.state = SamplingState::Running;
 
-        log::info!("All activity threads were started");
+        node_log!(Info, SAMPLING, "Started peer sampling");
+        Ok(())
     }
 
-    /// Returns a random peer for the client application.
-    /// The peer is pseudo-random peer from the set of all peers.
-    /// The local view is built using [Gossip-Based Peer Sampling].
-    pub fn get_peer(&mut self) -> Option<Peer> {
-        self.view.lock().unwrap().get_peer()
+    fn get_peers(&mut self, count: usize) -> Vec<Peer> {
+        PeerSamplingService::get_peers(self, count)
+    }
+
+    fn sample_peers(&mut self, count: usize) -> Vec<Peer> {
+        PeerSamplingService::sample_peers(self, count)
+    }
+
+    fn bootstrap_source(&self) -> Option<Arc<Mutex<BootstrapSource>>> {
+        Some(Arc::clone(&self.bootstrap))
     }
 
-    /// Returns a copy of the list of peers in the node view
-    pub fn peers(&self) -> Vec<Peer> {
-        self.view.lock().unwrap()
-            .peers.iter().map(|peer| peer.clone())
-            .collect()
+    fn merge_peers(&mut self, peers: Vec<Peer>) {
+        node_log!(Info, SAMPLING, "Adding {} peers to the view", peers.len());
+        self.add_peers(peers);
     }
 
-    /// Stops the threads related to peer sampling activity
-    pub fn shutdown(&mut self) -> Result<(), Box<dyn Error>> {
-        // request shutdown
-        self.shutdown.store(true, std::sync::atomic::Ordering::SeqCst);
+    /// Stops the rounds initiating exchanges and clears the view
+    fn shutdown(&mut self) -> Result<(), Box<dyn Error>> {
+        self.stop_sampling()?;
         {
             let mut view = self.view.lock().unwrap();
             view.peers.clear();
             view.queue.clear();
-            crate::network::send(&view.host_address.parse()?, Box::new(NoopMessage))?;
-        }
-        // wait for termination
-        let mut join_error = false;
-        for handle in self.thread_handles.drain(..) {
-            if let Err(e) = handle.join() {
-                log::error!("Error joining thread: {:?}", e);
-                join_error = true;
-            }
+            view.staged.clear();
         }
-        log::info!("All activity threads were stopped");
-        if join_error {
-            Err("An error occurred during thread joining")?
+        self.published.store(Vec::new());
+        if self.state == SamplingState::Running {
+            self.state = SamplingState::ShutDown;
         }
-        else {
-            Ok(())
+        node_log!(Info, SAMPLING, "Stopped peer sampling");
+        Ok(())
+    }
+
+    fn reset(&mut self) {
+        if self.state == SamplingState::ShutDown {
+            self.state = SamplingState::NotStarted;
+        }
+    }
+
+    /// Stops initiating exchanges with other peers; received messages are still handled
+    fn stop_sampling(&mut self) -> Result<(), Box<dyn Error>> {
+        if let Some(rounds) = self.rounds.take() {
+            rounds.stop();
+        }
+        Ok(())
+    }
+
+    fn trigger_round(&self) {
+        if let Some(rounds) = &self.rounds {
+            rounds.trigger();
+        }
+    }
+
+    fn set_paused(&self, paused: bool) {
+        if let Some(rounds) = &self.rounds {
+            rounds.set_paused(paused);
+        }
+    }
+
+    /// Stops advertising the node in the buffers it sends, so that the exchanges until shutdown do not refresh
+    /// it in the views of its peers, and notifies the peers of the view that the node is leaving, if graceful
+    /// leave is enabled. Notifications are best effort and stop once the configured time budget is exhausted.
+    fn leave(&self) {
+        self.leaving.store(true, Ordering::SeqCst);
+        let budget = match self.config.graceful_leave() {
+            Some(budget) => Duration::from_millis(budget),
+            None => return,
+        };
+        let deadline = Instant::now() + budget;
+        let peers = self.peers();
+        let mut notified = 0;
+        for peer in peers.iter() {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining == Duration::from_millis(0) {
+                node_log!(Warn, SAMPLING, "Leave budget exhausted after notifying {} of {} peers", notified, peers.len());
+                break;
+            }
+            match peer.socket_addr() {
+                Ok(remote_address) => {
+                    match crate::network::send_with_timeout(&remote_address, &PeerSamplingMessage::new_leave(self.address.to_string()).with_cluster(self.cluster_name.clone()), self.cluster_id, &self.network, remaining) {
+                        Ok(written) => {
+                            self.traffic.record_sent(peer.address(), MESSAGE_PROTOCOL_SAMPLING_MESSAGE, written);
+                            notified += 1;
+                        }
+                        Err(e) => node_log!(Debug, SAMPLING, "Could not notify {} of leave: {}", peer.address(), e),
+                    }
+                }
+                Err(_) => {
+                    node_log!(Error, SAMPLING, "Could not parse peer address {}", peer.address());
+                    self.errors.record(ErrorCategory::Parse, Some(peer.address()), "invalid peer address".to_owned());
+                }
+            }
         }
+        node_log!(Info, SAMPLING, "Notified {} peers of leave", notified);
+    }
+
+    /// Removes a quarantined peer from the view; it is not added back before the end of its quarantine
+    fn quarantine(&self, address: &str, until: Instant) {
+        let mut view = self.view.lock().unwrap();
+        view.remove_departed(address, until);
+        self.published.store(view.peers.clone());
+    }
+
+    /// Removes an unreachable peer from the view, as if it had left: it is not added back during the leave quarantine
+    fn report_unreachable(&self, address: &str) {
+        self.quarantine(address, Instant::now() + Duration::from_millis(self.config.leave_quarantine()));
+    }
+
+    /// Returns whether the rounds of the service are started; the node reports its own threads
+    fn is_running(&self) -> bool {
+        self.rounds.is_some()
+    }
+
+    fn state(&self) -> SamplingState {
+        self.state
+    }
+
+    /// Returns whether a buffer from another peer was merged into the view, or the node was started without initial peers
+    fn is_ready(&self) -> bool {
+        self.seed || self.last_exchange().is_some()
+    }
+
+    /// Returns the time at which a buffer from another peer was last merged into the view
+    fn last_exchange(&self) -> Option<Instant> {
+        *self.last_exchange.lock().unwrap()
     }
 
+    fn config(&self) -> Option<&PeerSamplingConfig> {
+        Some(&self.config)
+    }
+
+    fn estimated_cluster_size(&self) -> Option<f64> {
+        self.estimator.as_ref().and_then(|estimator| estimator.lock().unwrap().estimate())
+    }
+
+    fn sampling_stats(&self) -> Option<SamplingStats> {
+        Some(self.view_stats.lock().unwrap().stats())
+    }
+
+    fn memory_report(&self) -> Option<SamplerMemory> {
+        let mut memory = self.view.lock().unwrap().memory();
+        memory.pull_requests = Usage::of_strings(self.pull_requests.lock().unwrap().keys());
+        Some(memory)
+    }
+}
+
+impl PeerSamplingService {
     /// Builds the view to be exchanged with another peer
     ///
     /// # Arguments
     ///
     /// * `config` - The configuration parameters
+    /// * `advertise_self` - Whether the node starts the buffer with a fresh descriptor of its own
     /// * `view` - The current view
-    fn build_buffer(address: String, config: &PeerSamplingConfig, view: &mut View) -> Vec<Peer> {
-        let mut buffer = vec![ Peer::new(address) ];
+    fn build_buffer(address: String, config: &PeerSamplingConfig, advertise_self: bool, view: &mut View) -> Vec<Peer> {
+        let mut buffer = Vec::new();
+        if advertise_self {
+            let mut myself = Peer::unchecked(address.clone());
+            myself.refresh();
+            myself.set_metadata(config.metadata().map(<[u8]>::to_vec));
+            buffer.push(myself);
+        }
         view.permute();
         view.move_oldest_to_end(config.healing_factor());
         buffer.append(&mut view.head(config.view_size()));
+        if !advertise_self {
+            buffer.retain(|peer| peer.address() != address);
+        }
+        buffer.truncate(config.max_view_message_peers());
         buffer
     }
 
-    /// Creates a thread for handling messages
-    ///
-    /// # Arguments
-    ///
-    /// * `receiver` - The channel used for receiving incoming messages
-    fn start_receiver(&self, receiver: Receiver<PeerSamplingMessage>) -> JoinHandle<()>{
+    /// Returns the handler of the received messages, called from the message handling thread of the node
+    fn message_handler(&self) -> impl FnMut(PeerSamplingMessage) + Send + 'static {
         let address = self.address.to_string();
         let sampling_config = self.config.clone();
+        let cluster_id = self.cluster_id;
+        let cluster_name = self.cluster_name.clone();
+        let network = self.network.clone();
         let view_arc = self.view.clone();
-        std::thread::Builder::new().name(format!("{} - gbps receiver", &address)).spawn(move|| {
-            log::info!("Started message handling thread");
-            while let Ok(message) = receiver.recv() {
-                log::debug!("Received: {:?}", message);
-                let mut view = view_arc.lock().unwrap();
-                if let MessageType::Request = message.message_type() {
-                    if sampling_config.is_pull() {
-                        let buffer = Self::build_buffer(address.clone(), &sampling_config, &mut view);
-                        log::debug!("Built response buffer: {:?}", buffer);
-                        if let Ok(remote_address) = message.sender().parse::<SocketAddr>() {
-                            match crate::network::send(&remote_address, Box::new(PeerSamplingMessage::new_response(address.clone(), Some(buffer)))) {
-                                Ok(written) => log::trace!("Buffer sent successfully ({} bytes)", written),
-                                Err(e) => log::error!("Error sending buffer: {}", e),
+        let published_arc = Arc::clone(&self.published);
+        let pull_requests_arc = Arc::clone(&self.pull_requests);
+        let stats_arc = Arc::clone(&self.stats);
+        let errors_arc = Arc::clone(&self.errors);
+        let traffic = Arc::clone(&self.traffic);
+        let quarantine_arc = Arc::clone(&self.quarantine);
+        let last_exchange_arc = Arc::clone(&self.last_exchange);
+        let estimator = self.estimator.clone();
+        let leaving = Arc::clone(&self.leaving);
+        move |message: PeerSamplingMessage| {
+            node_log!(Debug, SAMPLING, "Received: {:?}", message);
+            stats_arc.lock().unwrap().sampling_messages_received += 1;
+            if !crate::message::same_cluster(cluster_name.as_deref(), message.cluster()) {
+                node_log!(Debug, SAMPLING, "Dropping message from {} of cluster {:?}", message.sender(), message.cluster());
+                stats_arc.lock().unwrap().foreign_cluster_messages += 1;
+                return;
+            }
+            // the violations of the sender are accounted at the IP its connection came from
+            let observed = crate::network::observed_sender(message.sender(), message.source());
+            if quarantine_arc.lock().unwrap().is_quarantined(&observed) {
+                node_log!(Debug, SAMPLING, "Ignoring message from quarantined peer {}", message.sender());
+                stats_arc.lock().unwrap().quarantined_messages += 1;
+                return;
+            }
+            // the address the sender is answered at
+            let reply_address = message.sender().parse::<SocketAddr>().ok()
+                .map(|sender| crate::network::reply_address(sender, message.source(), network.reply_address()));
+            if let Some(None) = reply_address {
+                node_log!(Warn, SAMPLING, "Dropping message from {} received from {:?}", message.sender(), message.source());
+                stats_arc.lock().unwrap().sender_mismatches += 1;
+                return;
+            }
+            let mut view = view_arc.lock().unwrap();
+            if message.is_leaving() {
+                // leave messages are not authenticated: a node only removes a peer of the host it connects from
+                if observed != message.sender() {
+                    node_log!(Warn, SAMPLING, "Ignoring leave of {} received from {:?}", message.sender(), message.source());
+                    stats_arc.lock().unwrap().sender_mismatches += 1;
+                    return;
+                }
+                node_log!(Info, SAMPLING, "Peer {} is leaving", message.sender());
+                view.remove_departed(message.sender(), Instant::now() + Duration::from_millis(sampling_config.leave_quarantine()));
+                published_arc.store(view.peers.clone());
+                return;
+            }
+            if let MessageType::Request = message.message_type() {
+                if sampling_config.is_pull() {
+                    let advertise_self = sampling_config.advertise_self() && !leaving.load(Ordering::SeqCst);
+                    let buffer = Self::build_buffer(address.clone(), &sampling_config, advertise_self, &mut view);
+                    node_log!(Debug, SAMPLING, "Built response buffer: {:?}", buffer);
+                    if let Some(remote_address) = reply_address.flatten() {
+                        // the estimate is answered with the state before the exchange, and merged once sent
+                        let exchange = estimator.as_ref().zip(message.estimate());
+                        let estimate = exchange.map(|(estimator, _)| estimator.lock().unwrap().state());
+                        match traffic.respond(message.reply_stream(), &remote_address, &PeerSamplingMessage::new_response(address.clone(), Some(buffer)).with_cluster(cluster_name.clone()).with_estimate(estimate), cluster_id, &network) {
+                            Ok(written) => {
+                                node_log!(Trace, SAMPLING, "Buffer sent successfully ({} bytes)", written);
+                                stats_arc.lock().unwrap().sampling_messages_sent += 1;
+                                if let Some((estimator, remote)) = exchange {
+                                    estimator.lock().unwrap().merge(message.sender(), remote);
+                                }
+                            },
+                            Err(e) => {
+                                node_log!(Error, SAMPLING, "Error sending buffer: {}", e);
+                                errors_arc.record(ErrorCategory::Send, Some(message.sender()), format!("view response: {}", e));
                             }
                         }
-                        else {
-                            log::error!("Could not parse sender address {}", &message.sender());
-                        }
+                    }
+                    else {
+                        node_log!(Error, SAMPLING, "Could not parse sender address {}", &message.sender());
+                        errors_arc.record(ErrorCategory::Parse, Some(message.sender()), "invalid sender address".to_owned());
                     }
                 }
+            }
+
+            // only merge responses to a request that is still outstanding
+            let solicited = match message.message_type() {
+                MessageType::Request => true,
+                MessageType::Response => pull_requests_arc.lock().unwrap().remove(message.sender())
+                    .is_some_and(|sent| sent.elapsed().as_millis() <= Self::response_window(&sampling_config)),
+            };
+            if let (MessageType::Response, true, Some((estimator, remote))) = (message.message_type(), solicited, estimator.as_ref().zip(message.estimate())) {
+                estimator.lock().unwrap().merge(message.sender(), remote);
+            }
 
-                if let Some(buffer) = message.view() {
-                    view.select(sampling_config.view_size(), sampling_config.healing_factor(), sampling_config.swapping_factor(), &buffer);
+            if let Some(buffer) = message.view() {
+                if !solicited {
+                    node_log!(Warn, SAMPLING, "Rejected unsolicited response from {}", message.sender());
+                    stats_arc.lock().unwrap().rejected_view_merges += 1;
+                }
+                else if buffer.len() > sampling_config.max_view_message_peers() {
+                    node_log!(Warn, SAMPLING, "Rejected buffer of {} peers from {} (maximum is {})", buffer.len(), message.sender(), sampling_config.max_view_message_peers());
+                    stats_arc.lock().unwrap().rejected_view_merges += 1;
+                    if let Some(until) = quarantine_arc.lock().unwrap().record_violation(&observed) {
+                        view.remove_departed(&observed, until);
+                    }
                 }
                 else {
-                    log::warn!("received a response with an empty buffer");
+                    // do not let a single buffer dominate the view
+                    let max_incoming_buffer = sampling_config.max_incoming_buffer();
+                    let buffer = if buffer.len() > max_incoming_buffer {
+                        node_log!(Warn, SAMPLING, "Truncated buffer of {} peers from {} to {} peers", buffer.len(), message.sender(), max_incoming_buffer);
+                        stats_arc.lock().unwrap().truncated_view_buffers += 1;
+                        &buffer[..max_incoming_buffer]
+                    }
+                    else {
+                        &buffer[..]
+                    };
+                    let invalid = view.select(sampling_config.view_size(), sampling_config.healing_factor(), sampling_config.swapping_factor(), sampling_config.max_peer_age(), buffer);
+                    if invalid > 0 {
+                        node_log!(Warn, SAMPLING, "Dropped {} peers with invalid addresses from the buffer of {}", invalid, message.sender());
+                        stats_arc.lock().unwrap().invalid_peers += invalid as u64;
+                    }
+                    last_exchange_arc.lock().unwrap().replace(Instant::now());
                 }
-
-                view.increase_age();
             }
-            log::info!("Message handling thread exiting");
-        }).unwrap()
+            else {
+                node_log!(Debug, SAMPLING, "Received a response with an empty buffer");
+            }
+
+            view.increase_age();
+            published_arc.store(view.peers.clone());
+        }
     }
 
-    /// Creates a thread that periodically executes the peer sampling
-    fn start_sampling_activity(&self) -> JoinHandle<()> {
-        let address = self.address.to_string();
+    /// Returns the sampling cycle of the service, sending its messages with the given sender
+    fn sampling_cycle<S>(&self, sender: Arc<S>) -> SamplingCycle<S> {
+        SamplingCycle {
+            address: self.address.to_string(),
+            config: self.config.clone(),
+            cluster_id: self.cluster_id,
+            cluster_name: self.cluster_name.clone(),
+            network: self.network.clone(),
+            view: Arc::clone(&self.view),
+            published: Arc::clone(&self.published),
+            pull_requests: Arc::clone(&self.pull_requests),
+            stats: Arc::clone(&self.stats),
+            errors: Arc::clone(&self.errors),
+            sender,
+            // the estimate is only exchanged with pull, both peers averaging their values
+            estimator: self.estimator.clone().filter(|_| self.config.is_pull()),
+            leaving: Arc::clone(&self.leaving),
+            view_stats: Arc::clone(&self.view_stats),
+            no_peer_warning: RateLimitedWarning::new(WARNING_INTERVAL),
+        }
+    }
+
+    /// Returns the delay until the first round, spreading the initial contacts of nodes started together, and the
+    /// rounds of the service, run by the timer thread of the node
+    fn sampling_rounds(&self) -> (Duration, impl FnMut() -> Duration + Send + 'static) {
         let config = self.config.clone();
-        let view_arc = self.view.clone();
-        let shutdown_requested = Arc::clone(&self.shutdown);
-        std::thread::Builder::new().name(format!("{} - gbps sampling", address)).spawn(move || {
-            log::info!("Started peer sampling thread");
-            loop {
-                // Compute time for sleep cycle
-                let deviation =
-                    if config.sampling_deviation() == 0 { 0 }
-                    else { rand::thread_rng().gen_range(0, config.sampling_deviation()) };
-                let sleep_time = config.sampling_period() + deviation;
-                std::thread::sleep(std::time::Duration::from_millis(sleep_time));
-
-                let mut view = view_arc.lock().unwrap();
-                if let Some(peer) = view.select_peer() {
-                    if config.is_push() {
-                        let buffer = Self::build_buffer(address.clone(), &config, &mut view);
-                        // send local view
-                        if let Ok(remote_address) = &peer.address().parse::<SocketAddr>() {
-                            match crate::network::send(remote_address, Box::new(PeerSamplingMessage::new_request(address.clone(), Some(buffer)))) {
-                                Ok(written) => log::trace!("Buffer sent successfully ({} bytes)", written),
-                                Err(e) => log::error!("Error sending buffer: {}", e),
-                            }
-                        }
-                        else {
-                            log::error!("Could not parse sender address {}", &peer.address());
+        let observer = Arc::clone(&self.observer);
+        let mut cycle = self.sampling_cycle(Arc::clone(&self.traffic));
+        let max_jitter = (config.sampling_period() as f64 * config.initial_jitter()) as u64;
+        let mut first = round_delay(config.sampling_period(), config.sampling_deviation());
+        if max_jitter > 0 {
+            first += Duration::from_millis(rand::thread_rng().gen_range(0, max_jitter));
+        }
+        let round = move || {
+            let summary = cycle.run();
+            if let Some(observer) = crate::observer::current(&observer) {
+                observer.on_sampling_round(summary);
+            }
+            // the next round is due after the delay, counted from the start of this one
+            round_delay(config.sampling_period(), config.sampling_deviation())
+        };
+        (first, round)
+    }
+}
+
+/// A peer sampling cycle of a service, run by the timer thread of the node once it is due. The view is shared with
+/// the handler of the received messages.
+pub(crate) struct SamplingCycle<S> {
+    /// Address of the node
+    address: String,
+    config: PeerSamplingConfig,
+    cluster_id: Option<u8>,
+    cluster_name: Option<String>,
+    network: NetworkConfig,
+    view: Arc<Mutex<View>>,
+    /// Peers of the view, published at the end of the cycle
+    published: Arc<Snapshot<Peer>>,
+    /// Peers to which a request expecting a response was sent, with the time it was sent
+    pull_requests: Arc<Mutex<HashMap<String, Instant>>>,
+    stats: Arc<Mutex<GossipStats>>,
+    errors: Arc<ErrorLog>,
+    /// Sender of the messages of the cycles
+    sender: Arc<S>,
+    /// Estimation of the size of the network, exchanged with pull only
+    estimator: Option<Arc<Mutex<SizeEstimator>>>,
+    /// Whether the node is leaving, after which it stops advertising itself
+    leaving: Arc<AtomicBool>,
+    view_stats: Arc<Mutex<ViewStats>>,
+    /// Warning logged while the cycles find no peer
+    no_peer_warning: RateLimitedWarning,
+}
+
+impl<S: MessageSender> SamplingCycle<S> {
+    /// Runs a cycle: a peer of the view is selected and sent a buffer of the view when pushing, or an empty
+    /// request when pulling only, then the view ages and is published.
+    ///
+    /// Returns the summary of the cycle.
+    pub(crate) fn run(&mut self) -> SamplingSummary {
+        let round_start = std::time::Instant::now();
+        let mut summary = SamplingSummary {
+            peer: None,
+            buffer_size: 0,
+            sent: false,
+            view_size: 0,
+            duration: std::time::Duration::default(),
+        };
+        let estimate = self.estimator.as_ref().map(|estimator| {
+            let mut estimator = estimator.lock().unwrap();
+            estimator.round();
+            estimator.state()
+        });
+        let mut view = self.view.lock().unwrap();
+        if let Some(peer) = view.select_peer() {
+            if self.no_peer_warning.ended() {
+                node_log!(Info, SAMPLING, "Found a peer for sampling");
+            }
+            summary.peer = Some(peer.address().to_owned());
+            if self.config.is_pull() {
+                // register the request before sending so that a fast response is not rejected
+                let window = PeerSamplingService::response_window(&self.config);
+                let mut pull_requests = self.pull_requests.lock().unwrap();
+                pull_requests.retain(|_, sent| sent.elapsed().as_millis() <= window);
+                pull_requests.insert(peer.address().to_owned(), Instant::now());
+            }
+            if self.config.is_push() {
+                let advertise_self = self.config.advertise_self() && !self.leaving.load(Ordering::SeqCst);
+                let buffer = PeerSamplingService::build_buffer(self.address.clone(), &self.config, advertise_self, &mut view);
+                summary.buffer_size = buffer.len();
+                // send local view
+                if let Ok(remote_address) = &peer.socket_addr() {
+                    match self.sender.send(remote_address, &PeerSamplingMessage::new_request(self.address.clone(), Some(buffer)).with_cluster(self.cluster_name.clone()).with_estimate(estimate), self.cluster_id, &self.network) {
+                        Ok(written) => {
+                            node_log!(Trace, SAMPLING, "Buffer sent successfully ({} bytes)", written);
+                            summary.sent = true;
+                            self.stats.lock().unwrap().sampling_messages_sent += 1;
+                        },
+                        Err(e) => {
+                            node_log!(Error, SAMPLING, "Error sending buffer: {}", e);
+                            self.errors.record(ErrorCategory::Send, Some(peer.address()), format!("view request: {}", e));
                         }
                     }
-                    else {
-                        // send empty view to trigger response
-                        if let Ok(remote_address) = &peer.address().parse::<SocketAddr>() {
-                            match crate::network::send(remote_address, Box::new(PeerSamplingMessage::new_request(address.clone(), None))) {
-                                Ok(written) => log::trace!("Empty view sent successfully ({} bytes)", written),
-                                Err(e) => log::error!("Error sending empty view: {}", e),
-                            }
-                        }
-                        else {
-                            log::error!("Could not parse sender address {}", &peer.address());
+                }
+                else {
+                    node_log!(Error, SAMPLING, "Could not parse sender address {}", &peer.address());
+                    self.errors.record(ErrorCategory::Parse, Some(peer.address()), "invalid peer address".to_owned());
+                }
+            }
+            else {
+                // send empty view to trigger response
+                if let Ok(remote_address) = &peer.socket_addr() {
+                    match self.sender.send(remote_address, &PeerSamplingMessage::new_request(self.address.clone(), None).with_cluster(self.cluster_name.clone()).with_estimate(estimate), self.cluster_id, &self.network) {
+                        Ok(written) => {
+                            node_log!(Trace, SAMPLING, "Empty view sent successfully ({} bytes)", written);
+                            summary.sent = true;
+                            self.stats.lock().unwrap().sampling_messages_sent += 1;
+                        },
+                        Err(e) => {
+                            node_log!(Error, SAMPLING, "Error sending empty view: {}", e);
+                            self.errors.record(ErrorCategory::Send, Some(peer.address()), format!("view request: {}", e));
                         }
                     }
-                    view.increase_age();
                 }
                 else {
-                    log::warn!("No peer found for sampling")
-                }
-
-                // check for shutdown request
-                if shutdown_requested.load(std::sync::atomic::Ordering::SeqCst) {
-                    break;
+                    node_log!(Error, SAMPLING, "Could not parse sender address {}", &peer.address());
+                    self.errors.record(ErrorCategory::Parse, Some(peer.address()), "invalid peer address".to_owned());
                 }
             }
+            if !summary.sent {
+                self.pull_requests.lock().unwrap().remove(peer.address());
+            }
+            view.increase_age();
+        }
+        else {
+            match self.no_peer_warning.occurred() {
+                Some(0) => node_log!(Warn, SAMPLING, "No peer found for sampling"),
+                Some(suppressed) => node_log!(Warn, SAMPLING, "No peer found for sampling, {} more cycles without peer", suppressed),
+                None => node_log!(Debug, SAMPLING, "No peer found for sampling"),
+            }
+        }
+        let max_queue = view.max_queue.unwrap_or(self.config.view_size());
+        view.promote_staged(max_queue);
+        summary.view_size = view.peers.len();
+        self.view_stats.lock().unwrap().record_cycle(&view.peers, summary.peer.as_deref());
+        self.stats.lock().unwrap().sampling_cycles += 1;
+        self.published.store(view.peers.clone());
+        drop(view);
 
-            log::info!("Peer sampling thread exiting");
-        }).unwrap()
+        summary.duration = round_start.elapsed();
+        summary
     }
 }
 
 /// The view at each node
-struct View {
+pub(crate) struct View {
     /// The address of the node
     host_address: String,
     /// The list of peers in the node view
-    peers: Vec<Peer>,
-    /// The queue from which peer are retrieved for the application layer
+    pub(crate) peers: Vec<Peer>,
+    /// The queue from which peer are retrieved for the application layer, newest last
     queue: VecDeque<Peer>,
+    /// Maximum number of queued peers, the view size if not set
+    max_queue: Option<usize>,
+    /// Peers that entered the view since the last cycle, queued if they are still in the view at the next one
+    staged: Vec<Peer>,
+    /// Whether peers are queued as soon as they enter the view, instead of being staged
+    immediate_queue: bool,
+    /// Peers that left the network, with the end of their quarantine
+    departed: HashMap<String, Instant>,
 }
 impl View {
     /// Creates a new view with the node's address
@@ -243,25 +708,59 @@ impl View {
     /// # Arguments
     ///
     /// * `address` - Addres of peer
-    fn new(host_address: String) -> View {
+    pub(crate) fn new(host_address: String) -> View {
         View {
             host_address,
             peers: vec![],
             queue: VecDeque::new(),
+            max_queue: None,
+            staged: Vec::new(),
+            immediate_queue: false,
+            departed: HashMap::new(),
         }
     }
 
-    /// Randomly select a peer for exchanging views at each cycle
-    fn select_peer(&self) -> Option<Peer> {
-        if self.peers.is_empty() {
-            None
+    /// Creates a new view whose queue for the application holds at most `max_queue` peers
+    fn with_max_queue(host_address: String, max_queue: usize) -> View {
+        View {
+            max_queue: Some(max_queue),
+            ..View::new(host_address)
         }
-        else {
-            let selected_peer = rand::thread_rng().gen_range(0, self.peers.len());
-            Some(self.peers[selected_peer].clone())
+    }
+
+    /// Returns the sizes of the peers held by the view
+    fn memory(&self) -> SamplerMemory {
+        SamplerMemory {
+            view: Usage::of_peers(&self.peers),
+            queued_peers: Usage::of_peers(self.queue.iter().chain(&self.staged)),
+            departed_peers: Usage::of_strings(self.departed.keys()),
+            pull_requests: Usage::default(),
         }
     }
 
+    /// Removes a peer that left the network and keeps it out of the view until the end of its quarantine
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - Address of the departed peer
+    /// * `until` - End of the quarantine
+    fn remove_departed(&mut self, address: &str, until: Instant) {
+        self.peers.retain(|peer| peer.address() != address);
+        self.queue.retain(|peer| peer.address() != address);
+        self.staged.retain(|peer| peer.address() != address);
+        self.departed.insert(address.to_owned(), until);
+    }
+
+    /// Randomly select a peer for exchanging views at each cycle
+    fn select_peer(&self) -> Option<Peer> {
+        self.sample_peers(1).pop()
+    }
+
+    /// Returns up to `count` distinct peers of the view chosen uniformly at random, leaving the queue untouched
+    fn sample_peers(&self, count: usize) -> Vec<Peer> {
+        self.peers.choose_multiple(&mut rand::thread_rng(), count).cloned().collect()
+    }
+
     /// Randomly reorder the current view
     fn permute(&mut self) {
         self.peers.shuffle(&mut rand::thread_rng());
@@ -283,7 +782,7 @@ impl View {
             let mut new_view_start = vec![];
             let mut new_view_end = vec![];
             for peer in &self.peers {
-                if h_oldest_peers.contains(&peer) {
+                if h_oldest_peers.contains(peer) {
                     new_view_end.push(peer.clone());
                 }
                 else {
@@ -291,7 +790,7 @@ impl View {
                 }
             }
             new_view_start.append(&mut new_view_end);
-            std::mem::replace(&mut self.peers, new_view_start);
+            self.peers = new_view_start;
         }
     }
 
@@ -316,27 +815,47 @@ impl View {
         }
     }
 
-    /// Merge a view received received from a peer with the current view
+    /// Merge a view received received from a peer with the current view, and returns the number of peers of the
+    /// buffer skipped because their address is invalid
     ///
     /// # Arguments
     ///
     /// * `c` - The size of the view
     /// * `h` - The healing parameter
     /// * `s` - The swap parameter
+    /// * `max_age` - The maximum age of peers kept in the view
     /// * `buffer` - The view received
-    fn select(&mut self, c:usize, h: usize, s: usize, buffer: &Vec<Peer>) {
+    pub(crate) fn select(&mut self, c:usize, h: usize, s: usize, max_age: Option<u16>, buffer: &[Peer]) -> usize {
         let my_address = self.host_address.clone();
-        // Add received peers to current view, omitting the node's own address
+        let previous: HashSet<String> = self.peers.iter().map(|peer| peer.address().to_owned()).collect();
+        let now = Instant::now();
+        self.departed.retain(|_, until| *until > now);
+        // Add received peers to current view, omitting invalid peers, the node's own address and departed peers
+        let invalid = buffer.iter().filter(|peer| !peer.is_valid()).count();
+        let departed = &self.departed;
+        let peers = &mut self.peers;
         buffer.iter()
-            .filter(|peer| peer.address() != my_address)
-            .for_each(|peer| self.peers.push(peer.clone()));
+            .filter(|peer| peer.is_valid() && peer.address() != my_address && !departed.contains_key(peer.address()))
+            .for_each(|peer| {
+                let mut peer = peer.clone();
+                if peer.age() == 0 {
+                    peer.refresh();
+                }
+                if peer.metadata().is_some_and(|metadata| metadata.len() > MAX_PEER_METADATA) {
+                    node_log!(Warn, SAMPLING, "Dropping oversized metadata of peer {}", peer.address());
+                    peer.set_metadata(None);
+                }
+                peers.push(peer)
+            });
         // Perform peer selection algorithm
         self.remove_duplicates();
+        self.remove_aged(max_age);
         self.remove_old_items(c, h);
         self.remove_head(c, s);
         self.remove_at_random(c);
         // Update peer queue for application layer
-        self.update_queue();
+        self.update_queue(&previous, self.max_queue.unwrap_or(c));
+        invalid
     }
 
     /// Removes duplicates peers from the view and keep the most recent one
@@ -355,7 +874,18 @@ impl View {
             }
         });
         let new_view = Vec::from_iter(unique_peers);
-        std::mem::replace(&mut self.peers, new_view);
+        self.peers = new_view;
+    }
+
+    /// Removes the peers older than the maximum age
+    ///
+    /// # Arguments
+    ///
+    /// * `max_age` - The maximum age of peers, if any
+    fn remove_aged(&mut self, max_age: Option<u16>) {
+        if let Some(max_age) = max_age {
+            self.peers.retain(|peer| peer.age() <= max_age);
+        }
     }
 
     /// Removes the oldest items from the view based on the healing parameter
@@ -373,11 +903,11 @@ impl View {
             kept_peers.truncate(kept_peers.len() - removal_count);
             let mut new_view = vec![];
             for peer in &self.peers {
-                if kept_peers.contains(&peer) {
+                if kept_peers.contains(peer) {
                     new_view.push(peer.clone());
                 }
             }
-            std::mem::replace(&mut self.peers, new_view);
+            self.peers = new_view;
         }
     }
 
@@ -407,40 +937,448 @@ impl View {
         }
     }
 
-    /// Update peer queue by adding peers that appeared in the view
+    /// Queues the peers staged since the previous cycle that are still in the view, called at the end of each cycle
+    ///
+    /// # Arguments
+    ///
+    /// * `max_size` - Maximum number of queued peers, the oldest ones are dropped beyond it
+    fn promote_staged(&mut self, max_size: usize) {
+        if self.staged.is_empty() {
+            return;
+        }
+        let current: HashSet<&str> = self.peers.iter().map(Peer::address).collect();
+        let promoted: Vec<Peer> = std::mem::take(&mut self.staged).into_iter()
+            .filter(|peer| current.contains(peer.address()))
+            .collect();
+        // a promoted peer that is already queued moves to the back
+        let addresses: HashSet<&str> = promoted.iter().map(Peer::address).collect();
+        self.queue.retain(|queued| !addresses.contains(queued.address()));
+        self.queue.extend(promoted);
+        self.trim_queue(max_size);
+    }
+
+    /// Update peer queue by adding peers that appeared in the view, or staging them until the next cycle,
     /// and removing those that were removed.
-    fn update_queue(&mut self) {
-
-        // compute index of removed peers
-        let removed_peers = self.queue.iter().enumerate()
-            .filter(|(_, peer)| !self.peers.contains(peer))
-            .map(|(index, _)| index)
-            .collect::<Vec<usize>>();
-
-        // compute new peers
-        let added_peers = self.peers.iter()
-            .filter(|peer| !self.queue.contains(peer))
-            .map(|peer| peer.to_owned())
+    ///
+    /// # Arguments
+    ///
+    /// * `previous` - Addresses of the peers of the view before the change
+    /// * `max_size` - Maximum number of queued peers, the oldest ones are dropped beyond it
+    fn update_queue(&mut self, previous: &HashSet<String>, max_size: usize) {
+        let current: HashSet<&str> = self.peers.iter().map(Peer::address).collect();
+        let added: Vec<Peer> = self.peers.iter()
+            .filter(|peer| !previous.contains(peer.address()))
+            .cloned()
+            .collect();
+
+        // a peer added again moves to the back instead of being queued twice
+        self.queue.retain(|peer| current.contains(peer.address()) && !added.contains(peer));
+        if self.immediate_queue {
+            self.queue.extend(added);
+        }
+        else {
+            self.staged.retain(|peer| current.contains(peer.address()) && !added.contains(peer));
+            self.staged.extend(added);
+        }
+        self.trim_queue(max_size);
+    }
+
+    /// Drops the oldest queued peers beyond the maximum
+    fn trim_queue(&mut self, max_size: usize) {
+        if self.queue.len() > max_size {
+            let dropped = self.queue.len() - max_size;
+            self.queue.drain(..dropped);
+            node_log!(Debug, SAMPLING, "Dropped {} queued peers not taken by the application", dropped);
+        }
+    }
+
+    /// Returns up to `count` distinct peers for use in the application layer.
+    /// Newly added peers are taken from the queue first, the rest are selected at random from the view.
+    pub fn get_peers(&mut self, count: usize) -> Vec<Peer> {
+        let mut selected = Vec::with_capacity(count);
+        while selected.len() < count {
+            match self.queue.pop_front() {
+                Some(peer) => if !selected.contains(&peer) { selected.push(peer) },
+                None => break,
+            }
+        }
+        let remaining = self.peers.iter()
+            .filter(|peer| !selected.contains(peer))
+            .cloned()
             .collect::<Vec<Peer>>();
+        let missing = count - selected.len();
+        selected.extend(remaining.choose_multiple(&mut rand::thread_rng(), missing).cloned());
+        selected
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sampler::{MessageHandler, SamplerTasks, TaskSlots};
+    use crate::traffic::RecordingSender;
+
+    fn start_service(address: &str) -> (PeerSamplingService, MessageHandler) {
+        start_service_with_peers(address, None)
+    }
+
+    fn new_service(address: &str) -> PeerSamplingService {
+        PeerSamplingService::new(
+            address.parse().unwrap(),
+            PeerSamplingConfig::new(true, true, 100, 10, 1, 4),
+            None,
+            None,
+            Arc::new(std::sync::RwLock::new(None)),
+            Arc::new(Mutex::new(GossipStats::default())),
+            Arc::new(Mutex::new(Quarantine::new(0, 0)))
+        )
+    }
+
+    fn context(address: &str, initial_peers: Option<Vec<Peer>>) -> (SamplerContext, Arc<TaskSlots>) {
+        let (tasks, slots) = SamplerTasks::new(std::sync::mpsc::channel().0);
+        let context = SamplerContext {
+            address: address.parse().unwrap(),
+            initial_peers,
+            bootstrap: Arc::new(Mutex::new(BootstrapSource::default())),
+            messages: std::sync::mpsc::channel().1,
+            tasks,
+            view: ViewPublisher::new(Arc::new(Snapshot::new())),
+        };
+        (context, slots)
+    }
+
+    /// Starts a service, and returns it with the handler of its messages
+    fn start_service_with_peers(address: &str, initial_peers: Option<Vec<Peer>>) -> (PeerSamplingService, MessageHandler) {
+        let mut service = new_service(address);
+        let (context, slots) = context(address, initial_peers);
+        service.init(context).unwrap();
+        (service, slots.take_handler().unwrap())
+    }
+
+    fn forged_buffer() -> Vec<Peer> {
+        (1..=5).map(|i| Peer::try_new(&format!("127.1.0.{}:1", i)).unwrap()).collect()
+    }
 
-        // removed old peers by descending index
-        removed_peers.iter().rev().for_each(|index| { self.queue.remove(*index); });
+    fn view_with_new_peers(count: usize) -> View {
+        let mut view = View { immediate_queue: true, ..View::new("127.0.0.1:9104".to_owned()) };
+        view.peers = (1..=count).map(|i| Peer::try_new(&format!("127.5.0.{}:1", i)).unwrap()).collect();
+        view.update_queue(&HashSet::new(), count);
+        view
+    }
 
-        // add new peers
-        for peer in added_peers {
-            self.queue.push_back(peer);
+    /// Cycle of a service whose view holds the given peers, whose messages are recorded
+    fn new_cycle(config: PeerSamplingConfig, peers: Vec<Peer>) -> SamplingCycle<RecordingSender> {
+        let service = PeerSamplingService::new(
+            "127.0.0.1:9106".parse().unwrap(),
+            config,
+            None,
+            None,
+            Arc::new(std::sync::RwLock::new(None)),
+            Arc::new(Mutex::new(GossipStats::default())),
+            Arc::new(Mutex::new(Quarantine::new(0, 0)))
+        );
+        service.view.lock().unwrap().peers = peers;
+        service.sampling_cycle(Arc::new(RecordingSender::default()))
+    }
+
+    fn sent_requests(cycle: &SamplingCycle<RecordingSender>) -> Vec<(SocketAddr, PeerSamplingMessage)> {
+        cycle.sender.messages(MESSAGE_PROTOCOL_SAMPLING_MESSAGE)
+    }
+
+    #[test]
+    fn push_cycle_sends_a_buffer_of_the_view() {
+        let mut cycle = new_cycle(PeerSamplingConfig::new(true, false, 100, 10, 1, 4), forged_buffer());
+
+        let summary = cycle.run();
+        let sent = sent_requests(&cycle);
+        assert_eq!(1, sent.len());
+        let (receiver, request) = &sent[0];
+        assert_eq!(Some(receiver.to_string()), summary.peer);
+        assert_eq!(MessageType::Request, *request.message_type());
+        let buffer = request.view().as_ref().unwrap();
+        // the node advertises itself with peers of the view
+        assert_eq!("127.0.0.1:9106", buffer[0].address());
+        assert!(buffer.len() > 1);
+        assert_eq!(buffer.len(), summary.buffer_size);
+        assert!(summary.sent);
+        // no response is expected
+        assert!(cycle.pull_requests.lock().unwrap().is_empty());
+        assert!(cycle.published.load().iter().all(|peer| peer.age() == 1));
+        assert_eq!(1, cycle.stats.lock().unwrap().sampling_messages_sent);
+    }
+
+    #[test]
+    fn pull_cycle_sends_an_empty_request() {
+        let mut cycle = new_cycle(PeerSamplingConfig::new(false, true, 100, 10, 1, 4), forged_buffer());
+
+        let summary = cycle.run();
+        let sent = sent_requests(&cycle);
+        assert_eq!(1, sent.len());
+        let (receiver, request) = &sent[0];
+        assert!(request.view().is_none());
+        assert_eq!(0, summary.buffer_size);
+        assert!(summary.sent);
+        // the response of the peer is expected
+        assert!(cycle.pull_requests.lock().unwrap().contains_key(&receiver.to_string()));
+    }
+
+    #[test]
+    fn cycle_with_empty_view_sends_nothing() {
+        let mut cycle = new_cycle(PeerSamplingConfig::new(true, true, 100, 10, 1, 4), Vec::new());
+
+        let summary = cycle.run();
+        assert_eq!(None, summary.peer);
+        assert!(!summary.sent);
+        assert_eq!(0, summary.view_size);
+        assert!(cycle.sender.sent.lock().unwrap().is_empty());
+        assert_eq!(1, cycle.stats.lock().unwrap().sampling_cycles);
+    }
+
+    #[test]
+    fn service_is_started_once() {
+        let address = "127.0.0.1:9105";
+        let mut service = new_service(address);
+        // before start, the view is empty whatever the initial peers
+        assert_eq!(SamplingState::NotStarted, service.state());
+        assert!(PeerSampler::get_peers(&mut service, 5).is_empty());
+        assert!(service.peers().is_empty());
+
+        let (started, _) = context(address, Some(forged_buffer()));
+        service.init(started).unwrap();
+        assert_eq!(SamplingState::Running, service.state());
+        assert_eq!(5, service.peers().len());
+        let (again, _) = context(address, None);
+        assert!(service.init(again).unwrap_err().is::<AlreadyStarted>());
+        assert_eq!(5, service.peers().len());
+
+        service.shutdown().unwrap();
+        assert_eq!(SamplingState::ShutDown, service.state());
+        assert!(PeerSampler::get_peers(&mut service, 5).is_empty());
+        assert!(service.peers().is_empty());
+        let (again, _) = context(address, Some(forged_buffer()));
+        assert!(service.init(again).unwrap_err().is::<AlreadyStarted>());
+        assert!(service.peers().is_empty());
+    }
+
+    #[test]
+    fn reset_service_can_be_started_again() {
+        let address = "127.0.0.1:9108";
+        let mut service = new_service(address);
+        let (started, _) = context(address, Some(forged_buffer()));
+        service.init(started).unwrap();
+        service.shutdown().unwrap();
+
+        service.reset();
+        assert_eq!(SamplingState::NotStarted, service.state());
+        let (again, _) = context(address, Some(forged_buffer()));
+        service.init(again).unwrap();
+        assert_eq!(SamplingState::Running, service.state());
+        assert_eq!(5, service.peers().len());
+    }
+
+    #[test]
+    fn sampled_peers_are_distinct() {
+        let view = view_with_new_peers(10);
+        for count in [0, 1, 5, 10, 20] {
+            let sampled = view.sample_peers(count);
+            assert_eq!(count.min(10), sampled.len());
+            assert_eq!(sampled.len(), sampled.iter().collect::<HashSet<_>>().len());
+            assert!(sampled.iter().all(|peer| view.peers.contains(peer)));
         }
     }
 
-    /// Returns a random peer for use in the application layer.
-    /// The peer is selected from the queue of newly added peers if available,
-    /// otherwise at random from the view.
-    pub fn get_peer(&mut self) -> Option<Peer> {
-        if let Some(peer) = self.queue.pop_front() {
-            Some(peer)
+    #[test]
+    fn sampling_leaves_the_queue_untouched() {
+        let mut view = view_with_new_peers(10);
+        let queue = view.queue.clone();
+        for _ in 0..10 {
+            view.sample_peers(3);
         }
-        else {
-            self.select_peer()
+        assert_eq!(queue, view.queue);
+
+        // selecting peers for the application consumes the queue
+        view.get_peers(3);
+        assert_eq!(7, view.queue.len());
+    }
+
+    #[test]
+    fn queue_stays_bounded_under_churn() {
+        let mut view = View::with_max_queue("127.0.0.1:9104".to_owned(), 5);
+        let mut rng = rand::thread_rng();
+        for _ in 0..200 {
+            // each round replaces part of the view with peers taken from a small pool, so that peers come back
+            let buffer: Vec<Peer> = (0..8)
+                .map(|_| Peer::try_new(&format!("127.5.0.{}:1", rng.gen_range(1, 30))).unwrap())
+                .collect();
+            view.select(10, 2, 4, None, &buffer);
+            assert!(view.queue.len() <= 5, "{} queued peers", view.queue.len());
+            assert_eq!(view.queue.len(), view.queue.iter().collect::<HashSet<_>>().len());
+            assert!(view.queue.iter().all(|peer| view.peers.contains(peer)));
+        }
+    }
+
+    #[test]
+    fn peer_added_again_moves_to_the_back_of_the_queue() {
+        let peers: Vec<Peer> = (1..=3).map(|i| Peer::try_new(&format!("127.5.0.{}:1", i)).unwrap()).collect();
+        let mut view = View { immediate_queue: true, ..View::new("127.0.0.1:9104".to_owned()) };
+        view.peers = peers.clone();
+        view.update_queue(&HashSet::new(), 10);
+
+        // the first peer leaves the view and comes back
+        let previous = peers[1..].iter().map(|peer| peer.address().to_owned()).collect();
+        view.update_queue(&previous, 10);
+        assert_eq!(vec![peers[1].clone(), peers[2].clone(), peers[0].clone()], Vec::from(view.queue.clone()));
+
+        // the oldest queued peers are dropped beyond the maximum
+        let previous = view.peers.iter().map(|peer| peer.address().to_owned()).collect();
+        view.peers.push(Peer::try_new("127.5.0.4:1").unwrap());
+        view.update_queue(&previous, 2);
+        assert_eq!(vec!["127.5.0.1:1", "127.5.0.4:1"], view.queue.iter().map(Peer::address).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn new_peers_are_queued_after_a_cycle() {
+        let mut cycle = new_cycle(PeerSamplingConfig::new(true, false, 100, 3, 0, 0), Vec::new());
+        let buffer = |ports: std::ops::Range<u16>| ports.map(|port| Peer::try_new(&format!("127.5.0.1:{}", port)).unwrap()).collect::<Vec<_>>();
+        // the merges of the buffers received between two cycles do not queue the peers staged by earlier merges
+        for ports in [1..3, 3..4, 1..2] {
+            cycle.view.lock().unwrap().select(3, 0, 0, None, &buffer(ports));
         }
+        cycle.view.lock().unwrap().select(3, 0, 0, None, &[]);
+        assert!(cycle.view.lock().unwrap().queue.is_empty());
+        assert_eq!(3, cycle.view.lock().unwrap().staged.len());
+
+        // the peers still in the view at the end of the cycle are queued
+        cycle.run();
+        let mut view = cycle.view.lock().unwrap();
+        assert_eq!(3, view.queue.len());
+        assert!(view.staged.is_empty());
+
+        // a peer that enters and leaves the view within a cycle is never queued
+        view.peers.clear();
+        view.queue.clear();
+        view.select(3, 0, 0, None, &buffer(10..11));
+        view.peers.clear();
+        view.select(3, 0, 0, None, &buffer(20..21));
+        view.select(3, 0, 0, None, &[]);
+        assert!(view.queue.is_empty());
+        drop(view);
+        cycle.run();
+        let mut view = cycle.view.lock().unwrap();
+        assert_eq!(vec!["127.5.0.1:20"], view.queue.iter().map(Peer::address).collect::<Vec<_>>());
+        let selected: Vec<String> = (0..10).flat_map(|_| view.get_peers(3)).map(|peer| peer.address().to_owned()).collect();
+        assert!(!selected.contains(&"127.5.0.1:10".to_owned()), "{:?}", selected);
+    }
+
+    #[test]
+    fn new_peers_are_queued_immediately_if_configured() {
+        let mut view = View { immediate_queue: true, ..View::new("127.0.0.1:9104".to_owned()) };
+        let buffer: Vec<Peer> = (1..4).map(|port| Peer::try_new(&format!("127.5.0.1:{}", port)).unwrap()).collect();
+        view.select(3, 0, 0, None, &buffer);
+        assert_eq!(3, view.queue.len());
+        assert!(view.staged.is_empty());
+    }
+
+    #[test]
+    fn unsolicited_response_is_not_merged() {
+        let (mut service, mut handle) = start_service("127.0.0.1:9100");
+        handle(PeerSamplingMessage::new_response("127.1.0.1:1".to_owned(), Some(forged_buffer())));
+
+        assert!(service.peers().is_empty());
+        assert_eq!(1, service.stats.lock().unwrap().rejected_view_merges);
+
+        service.shutdown().unwrap();
+    }
+
+    #[test]
+    fn solicited_response_is_merged_once() {
+        let (mut service, mut handle) = start_service("127.0.0.1:9101");
+        service.pull_requests.lock().unwrap().insert("127.1.0.1:1".to_owned(), Instant::now());
+        handle(PeerSamplingMessage::new_response("127.1.0.1:1".to_owned(), Some(forged_buffer())));
+        assert_eq!(5, service.peers().len());
+
+        // a second response to the same request is rejected
+        handle(PeerSamplingMessage::new_response("127.1.0.1:1".to_owned(), Some(vec![Peer::try_new("127.1.0.9:1").unwrap()])));
+        assert!(!service.peers().iter().any(|peer| peer.address() == "127.1.0.9:1"));
+        assert_eq!(1, service.stats.lock().unwrap().rejected_view_merges);
+
+        service.shutdown().unwrap();
+    }
+
+    #[test]
+    fn invalid_peers_do_not_enter_the_view() {
+        let (mut service, mut handle) = start_service("127.0.0.1:9107");
+        let mut buffer: Vec<Peer> = ["garbage", "127.1.0.9", "[::1]:port", ""].map(|address| Peer::unchecked(address.to_owned())).into();
+        buffer.push(Peer::try_new("127.1.0.2:1").unwrap());
+        handle(PeerSamplingMessage::new_request("127.1.0.1:1".to_owned(), Some(buffer)));
+
+        assert_eq!(vec![Peer::try_new("127.1.0.2:1").unwrap()], *service.peers());
+        assert!(service.get_peers(10).iter().all(Peer::is_valid));
+        assert_eq!(4, service.stats.lock().unwrap().invalid_peers);
+
+        service.shutdown().unwrap();
+    }
+
+    #[test]
+    fn oversized_buffer_does_not_dominate_view() {
+        let existing: Vec<Peer> = (1..=10).map(|i| Peer::try_new(&format!("127.2.0.{}:1", i)).unwrap()).collect();
+        let (mut service, mut handle) = start_service_with_peers("127.0.0.1:9102", Some(existing.clone()));
+
+        let oversized: Vec<Peer> = (0..1000).map(|i| Peer::try_new(&format!("127.3.{}.{}:1", i / 250, i % 250 + 1)).unwrap()).collect();
+        handle(PeerSamplingMessage::new_request("127.3.0.1:1".to_owned(), Some(oversized)));
+
+        let peers = service.peers();
+        let retained = peers.iter().filter(|peer| existing.contains(peer)).count();
+        assert!(retained >= 5, "only {} existing peers retained in {:?}", retained, peers);
+        assert_eq!(1, service.stats.lock().unwrap().truncated_view_buffers);
+
+        service.shutdown().unwrap();
+    }
+
+    #[test]
+    fn leave_is_accepted_from_the_host_of_the_peer() {
+        let existing: Vec<Peer> = (1..=3).map(|i| Peer::try_new(&format!("127.2.0.{}:1", i)).unwrap()).collect();
+        let (mut service, mut handle) = start_service_with_peers("127.0.0.1:9109", Some(existing));
+        let leave = |source: &str| {
+            let mut message = PeerSamplingMessage::new_leave("127.2.0.1:1".to_owned());
+            message.set_source(Some(source.parse().unwrap()));
+            message
+        };
+
+        // another host cannot evict the peer
+        handle(leave("127.9.0.1:50000"));
+        assert!(service.peers().iter().any(|peer| peer.address() == "127.2.0.1:1"));
+        assert_eq!(1, service.stats.lock().unwrap().sender_mismatches);
+
+        handle(leave("127.2.0.1:50000"));
+        assert!(!service.peers().iter().any(|peer| peer.address() == "127.2.0.1:1"));
+
+        service.shutdown().unwrap();
+    }
+
+    #[test]
+    fn leaving_node_stops_advertising_itself() {
+        use crate::message::Message;
+        use std::io::Read;
+
+        let (mut service, mut handle) = start_service("127.0.0.1:9105");
+        let peer = std::net::TcpListener::bind("127.0.0.1:9106").unwrap();
+        let mut exchange = || {
+            handle(PeerSamplingMessage::new_request("127.0.0.1:9106".to_owned(), Some(Vec::new())));
+            let mut bytes = Vec::new();
+            peer.accept().unwrap().0.read_to_end(&mut bytes).unwrap();
+            let response = PeerSamplingMessage::from_bytes(&bytes[1..]).unwrap();
+            response.view().clone().unwrap_or_default()
+        };
+
+        let buffer = exchange();
+        assert!(buffer.iter().any(|peer| peer.address() == "127.0.0.1:9105" && peer.age() == 0));
+
+        // the exchanges after shutdown was requested no longer refresh the node in the views of its peers
+        service.leave();
+        let buffer = exchange();
+        assert!(!buffer.iter().any(|peer| peer.address() == "127.0.0.1:9105"));
+
+        service.shutdown().unwrap();
     }
 }
@@ -0,0 +1,27 @@
+use std::sync::{Arc, RwLock};
+
+/// A list published by the protocol threads after each change, and read without contending with them.
+/// The lock only guards the swap of the published list: readers get a cheap copy of the pointer, and
+/// the list is built before the lock is taken by the writer.
+pub(crate) struct Snapshot<T> {
+    current: RwLock<Arc<Vec<T>>>,
+}
+
+impl<T> Snapshot<T> {
+    pub fn new() -> Self {
+        Snapshot {
+            current: RwLock::new(Arc::new(Vec::new())),
+        }
+    }
+
+    /// Returns the last published list
+    pub fn load(&self) -> Arc<Vec<T>> {
+        Arc::clone(&self.current.read().unwrap())
+    }
+
+    /// Replaces the published list
+    pub fn store(&self, items: Vec<T>) {
+        let items = Arc::new(items);
+        *self.current.write().unwrap() = items;
+    }
+}
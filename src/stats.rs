@@ -0,0 +1,149 @@
+use std::time::{Duration, SystemTime};
+
+/// Counters describing the activity of a gossip service
+#[derive(Debug, Clone, Default)]
+pub struct GossipStats {
+    /// Number of header responses sent in reply to pull probes
+    pub header_responses_sent: u64,
+    /// Number of header requests that did not ask for a response and were not answered
+    pub suppressed_header_responses: u64,
+    /// Number of header responses ignored because no probe was outstanding for their sender
+    pub ignored_header_responses: u64,
+    /// Number of gossip rounds skipped because the exchange with the selected peer was still in flight
+    pub skipped_header_rounds: u64,
+    /// Number of header requests not sent because they had nothing to push and asked for no response,
+    /// see [GossipConfig::set_empty_pushes](crate::GossipConfig::set_empty_pushes)
+    pub skipped_empty_pushes: u64,
+    /// Number of peer sampling and header messages dropped because they came from another cluster
+    pub foreign_cluster_messages: u64,
+    /// Number of gossip rounds during which the view had no peer to gossip with
+    pub rounds_without_peer: u64,
+    /// Number of gossip rounds run since start, the rounds skipped while idle excluded
+    pub gossip_rounds: u64,
+    /// Number of peer sampling cycles run since start
+    pub sampling_cycles: u64,
+    /// Number of times the node was started, see [GossipConfig::set_epoch_file](crate::GossipConfig::set_epoch_file);
+    /// zero before start
+    pub epoch: u64,
+    /// Time at which the node was started, `None` before start
+    pub started_at: Option<SystemTime>,
+    /// Time since the node was started, zero before start
+    pub uptime: Duration,
+    /// Number of updates that expired before being pushed to any peer
+    pub updates_expired_unpushed: u64,
+    /// Number of updates that expired, see [RoundObserver::on_update_expired](crate::RoundObserver::on_update_expired)
+    pub updates_expired: u64,
+    /// Number of rounds in which the digests of the expired updates were pushed, in total
+    pub expired_update_pushes: u64,
+    /// Number of distinct peers that requested the content of the expired updates, in total
+    pub expired_update_requesters: u64,
+    /// Number of received updates whose content did not match their digest
+    pub digest_verification_failures: u64,
+    /// Number of digests no longer requested because their content did not match too many times
+    pub poisoned_digests: u64,
+    /// Number of messages ignored because their sender was quarantined
+    pub quarantined_messages: u64,
+    /// Number of messages dropped because the IP reported by their sender was not the one of their connection,
+    /// see [NetworkConfig::set_reply_address](crate::NetworkConfig::set_reply_address), and of leave messages
+    /// received from another host than the one of the leaving peer
+    pub sender_mismatches: u64,
+    /// Number of updates removed or never requested because a peer advertised them as expired,
+    /// see [GossipConfig::set_tombstones](crate::GossipConfig::set_tombstones)
+    pub tombstoned_updates: u64,
+    /// Number of updates removed or never requested because a revocation was received,
+    /// see [GossipService::revoke](crate::GossipService::revoke)
+    pub revoked_updates: u64,
+    /// Number of new digests learned from header messages
+    pub digests_learned: u64,
+    /// Number of advertised digests whose content was not requested because the fetch policy declined them
+    pub declined_fetches: u64,
+    /// Number of content requests sent again to another peer because the requested peer relayed the digest
+    pub redirected_content_requests: u64,
+    /// Number of received header messages waiting for the header handling thread
+    pub queued_header_messages: u64,
+    /// Number of received content messages waiting for the content handling thread
+    pub queued_content_messages: u64,
+    /// Number of received messages dropped because the queue of their handling thread stayed full,
+    /// see [GossipConfig::set_queue_capacities](crate::GossipConfig::set_queue_capacities)
+    pub dropped_messages: u64,
+    /// Number of connections closed because they did not deliver their message within the connection deadline,
+    /// see [GossipConfig::set_connection_limits](crate::GossipConfig::set_connection_limits)
+    pub slow_connections: u64,
+    /// Number of connections closed without being read, because their IP address had too many connections or was quarantined
+    pub refused_connections: u64,
+    /// Number of connections opened to send messages to peers, one per message; fewer with
+    /// [in-stream replies](crate::NetworkConfig::set_reply_in_stream), whose responses need no connection
+    pub connections_opened: u64,
+    /// Number of keepalive pings sent, see [GossipConfig::set_keepalive](crate::GossipConfig::set_keepalive)
+    pub keepalive_pings_sent: u64,
+    /// Number of peers removed from the view because they left the keepalive pings unanswered
+    pub unreachable_peers: u64,
+    /// Number of content requests waiting for their response
+    pub pending_content_requests: u64,
+    /// Number of content requests abandoned because their response did not arrive in time
+    pub content_request_timeouts: u64,
+    /// Number of peer sampling buffers that were not merged into the view, because they were
+    /// unsolicited responses or exceeded the maximum size
+    pub rejected_view_merges: u64,
+    /// Number of peers of received buffers dropped because their address is not a socket address
+    pub invalid_peers: u64,
+    /// Number of peer sampling buffers that were truncated before being merged into the view
+    pub truncated_view_buffers: u64,
+    /// Number of deliveries attempted again because the handler failed,
+    /// see [GossipConfig::set_acknowledged_delivery](crate::GossipConfig::set_acknowledged_delivery)
+    pub delivery_retries: u64,
+    /// Number of peer sampling messages sent, requests and responses
+    pub sampling_messages_sent: u64,
+    /// Number of peer sampling messages received, requests and responses
+    pub sampling_messages_received: u64,
+    /// Number of bytes of update content received in content responses
+    pub content_bytes_received: u64,
+    /// Number of deliveries skipped because the update was submitted by the node itself,
+    /// see [GossipConfig::set_suppress_own_origin_redelivery](crate::GossipConfig::set_suppress_own_origin_redelivery)
+    pub suppressed_own_updates: u64,
+    /// Number of header responses that reused the digests of the previous response, the active updates being unchanged
+    pub header_cache_hits: u64,
+    /// Number of entries of the content responses ignored because their digest was not requested, see
+    /// [GossipConfig::set_accept_unrequested_content](crate::GossipConfig::set_accept_unrequested_content)
+    pub unrequested_content_ignored: u64,
+    /// Number of times the node was isolated from its view, see
+    /// [GossipConfig::set_isolation_detection](crate::GossipConfig::set_isolation_detection)
+    pub network_isolations: u64,
+    /// Number of attempts to bootstrap again while the node was isolated
+    pub isolation_rebootstraps: u64,
+    /// Current length (milliseconds) of the gossip period, longer than the configured one while the node is
+    /// idle, see [GossipConfig::set_idle_backoff](crate::GossipConfig::set_idle_backoff)
+    pub effective_gossip_period: u64,
+    /// Time between the first advertisement of a new digest and the reception of its content
+    pub propagation_histogram: LatencyHistogram,
+}
+
+/// Upper bounds (exclusive, in milliseconds) of the [LatencyHistogram] buckets; the last bucket counts larger values
+pub const LATENCY_BUCKETS_MILLIS: [u64; 5] = [10, 50, 250, 1000, 5000];
+
+/// A latency histogram with fixed buckets, see [LATENCY_BUCKETS_MILLIS]
+#[derive(Debug, Clone, Default)]
+pub struct LatencyHistogram {
+    counts: [u64; LATENCY_BUCKETS_MILLIS.len() + 1],
+}
+
+impl LatencyHistogram {
+    /// Adds a sample to the histogram
+    pub fn record(&mut self, latency: Duration) {
+        let millis = latency.as_millis();
+        let bucket = LATENCY_BUCKETS_MILLIS.iter()
+            .position(|bound| millis < *bound as u128)
+            .unwrap_or(LATENCY_BUCKETS_MILLIS.len());
+        self.counts[bucket] += 1;
+    }
+
+    /// Returns the number of samples in each bucket
+    pub fn counts(&self) -> &[u64] {
+        &self.counts
+    }
+
+    /// Returns the total number of samples
+    pub fn total(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+}
@@ -0,0 +1,558 @@
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::error::Error;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex, RwLock};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+use serde::Serialize;
+use crate::clock::{Clock, SystemClock};
+use crate::config::NetworkConfig;
+use crate::wire_trace::{Direction, WireTrace, WireTracer};
+use crate::message::{Message, MESSAGE_PROTOCOL_CONTENT_MESSAGE, MESSAGE_PROTOCOL_HEADER_MESSAGE, MESSAGE_PROTOCOL_PING_MESSAGE, MESSAGE_PROTOCOL_PONG_MESSAGE, MESSAGE_PROTOCOL_SAMPLING_MESSAGE};
+use crate::memory::Usage;
+use crate::network::{ReplyReaders, ReplyStream};
+use crate::peer::Peer;
+#[cfg(test)]
+use crate::message::gossip::ContentMessage;
+
+/// Number of peers whose traffic is tracked; the peer without traffic for the longest time is forgotten first
+pub const MAX_TRAFFIC_PEERS: usize = 1024;
+
+/// Length of the window over which the traffic is reported
+pub const TRAFFIC_WINDOW: Duration = Duration::from_secs(60);
+
+/// Number of periods of the window, which moves by one period at a time
+const TRAFFIC_BUCKETS: u32 = 6;
+
+/// Number of messages and their size on the wire
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TrafficCounters {
+    pub messages: u64,
+    /// Bytes on the wire, protocol byte and cluster id included
+    pub bytes: u64,
+}
+
+impl TrafficCounters {
+    fn add(&mut self, other: &TrafficCounters) {
+        self.messages += other.messages;
+        self.bytes += other.bytes;
+    }
+}
+
+/// Traffic in one direction, for each protocol
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MessageTraffic {
+    /// Peer sampling messages
+    pub sampling: TrafficCounters,
+    /// Gossip header messages
+    pub header: TrafficCounters,
+    /// Gossip content messages
+    pub content: TrafficCounters,
+    /// Keepalive pings and pongs, see [GossipConfig::set_keepalive](crate::GossipConfig::set_keepalive)
+    pub keepalive: TrafficCounters,
+}
+
+impl MessageTraffic {
+    /// Returns the traffic of all the protocols
+    pub fn total(&self) -> TrafficCounters {
+        let mut total = self.sampling;
+        total.add(&self.header);
+        total.add(&self.content);
+        total.add(&self.keepalive);
+        total
+    }
+
+    fn add(&mut self, other: &MessageTraffic) {
+        self.sampling.add(&other.sampling);
+        self.header.add(&other.header);
+        self.content.add(&other.content);
+        self.keepalive.add(&other.keepalive);
+    }
+
+    fn record(&mut self, protocol: u8, bytes: usize) {
+        let counters = match protocol {
+            MESSAGE_PROTOCOL_SAMPLING_MESSAGE => &mut self.sampling,
+            MESSAGE_PROTOCOL_HEADER_MESSAGE => &mut self.header,
+            MESSAGE_PROTOCOL_CONTENT_MESSAGE => &mut self.content,
+            MESSAGE_PROTOCOL_PING_MESSAGE | MESSAGE_PROTOCOL_PONG_MESSAGE => &mut self.keepalive,
+            _ => return,
+        };
+        counters.messages += 1;
+        counters.bytes += bytes as u64;
+    }
+}
+
+/// Traffic exchanged with a peer over the last [TRAFFIC_WINDOW], see [GossipService::traffic](crate::GossipService::traffic)
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PeerTraffic {
+    /// Address of the peer; received messages are counted for the address their sender advertises
+    pub peer: String,
+    /// Messages sent to the peer
+    pub sent: MessageTraffic,
+    /// Messages received from the peer
+    pub received: MessageTraffic,
+}
+
+impl PeerTraffic {
+    /// Returns the bytes sent to and received from the peer
+    pub fn bytes(&self) -> u64 {
+        self.sent.total().bytes + self.received.total().bytes
+    }
+}
+
+/// Sends the messages initiated by the protocol rounds, see [TrafficLog::send]; the rounds are tested with a sender
+/// recording the messages instead. Generic over the message, which is serialized by the sender, so the rounds take
+/// their sender as a type parameter rather than as a trait object.
+pub(crate) trait MessageSender {
+    fn send<M>(&self, address: &SocketAddr, message: &M, cluster_id: Option<u8>, network: &NetworkConfig) -> Result<usize, Box<dyn Error>>
+    where M: Message + Serialize;
+}
+
+impl MessageSender for TrafficLog {
+    fn send<M>(&self, address: &SocketAddr, message: &M, cluster_id: Option<u8>, network: &NetworkConfig) -> Result<usize, Box<dyn Error>>
+    where M: Message + Serialize
+    {
+        TrafficLog::send(self, address, message, cluster_id, network)
+    }
+}
+
+/// Records the messages instead of sending them
+#[cfg(test)]
+#[derive(Default)]
+pub(crate) struct RecordingSender {
+    /// Receivers and wire bytes of the messages
+    pub(crate) sent: Mutex<Vec<(SocketAddr, Vec<u8>)>>,
+    /// Whether the sends fail, as if the receivers were not listening
+    pub(crate) failing: std::sync::atomic::AtomicBool,
+}
+
+#[cfg(test)]
+impl MessageSender for RecordingSender {
+    fn send<M>(&self, address: &SocketAddr, message: &M, cluster_id: Option<u8>, _network: &NetworkConfig) -> Result<usize, Box<dyn Error>>
+    where M: Message + Serialize
+    {
+        if self.failing.load(std::sync::atomic::Ordering::SeqCst) {
+            Err(format!("{} is not listening", address))?
+        }
+        let bytes = crate::network::to_wire_bytes(message, cluster_id)?;
+        let written = bytes.len();
+        self.sent.lock().unwrap().push((*address, bytes));
+        Ok(written)
+    }
+}
+
+#[cfg(test)]
+impl RecordingSender {
+    /// Returns the messages of a protocol that were sent, with their receiver
+    pub(crate) fn messages<M>(&self, protocol: u8) -> Vec<(SocketAddr, M)>
+    where M: Message + serde::de::DeserializeOwned
+    {
+        self.sent.lock().unwrap().iter()
+            .filter(|(_, bytes)| bytes[0] & crate::message::MASK_MESSAGE_PROTOCOL == protocol)
+            .map(|(address, bytes)| (*address, M::from_bytes(&bytes[1..]).unwrap()))
+            .collect()
+    }
+}
+
+/// Traffic of a peer during one period of the window
+struct TrafficBucket {
+    start: Instant,
+    sent: MessageTraffic,
+    received: MessageTraffic,
+}
+
+/// Time at which each peer was last heard from, for at most [MAX_TRAFFIC_PEERS] peers: the peer heard from the
+/// longest time ago is forgotten first
+struct LastSeen {
+    /// Time at which each peer was last heard from, with the number of that record
+    peers: HashMap<String, (u64, Instant)>,
+    /// Peers by number of their last record, the one heard from the longest time ago first
+    order: BTreeMap<u64, String>,
+    recorded: u64,
+}
+
+impl LastSeen {
+    fn new() -> Self {
+        LastSeen {
+            peers: HashMap::new(),
+            order: BTreeMap::new(),
+            recorded: 0,
+        }
+    }
+
+    fn record(&mut self, peer: &str, now: Instant) {
+        match self.peers.remove(peer) {
+            Some((number, _)) => {
+                self.order.remove(&number);
+            }
+            None if self.peers.len() >= MAX_TRAFFIC_PEERS => {
+                if let Some((_, oldest)) = self.order.pop_first() {
+                    self.peers.remove(&oldest);
+                }
+            }
+            None => (),
+        }
+        self.recorded += 1;
+        self.order.insert(self.recorded, peer.to_owned());
+        self.peers.insert(peer.to_owned(), (self.recorded, now));
+    }
+
+    fn get(&self, peer: &str) -> Option<Instant> {
+        self.peers.get(peer).map(|(_, seen)| *seen)
+    }
+
+    fn memory(&self) -> Usage {
+        Usage::of_sizes(self.peers.keys().map(|peer| peer.len() + std::mem::size_of::<Instant>()))
+    }
+}
+
+/// Traffic of the protocol threads with each peer, over a sliding window
+pub(crate) struct TrafficLog {
+    peers: Mutex<HashMap<String, VecDeque<TrafficBucket>>>,
+    /// Tracer of the raw messages, see [GossipService::set_wire_trace](crate::GossipService::set_wire_trace)
+    wire_trace: WireTrace,
+    /// Threads reading the responses written on the connections of the requests, for a node that asks for them
+    replies: RwLock<Option<Arc<ReplyReaders>>>,
+    /// Number of connections opened to send messages
+    connections_opened: AtomicU64,
+    /// Time at which each peer was last heard from
+    last_seen: Mutex<LastSeen>,
+    /// Time of the first failure to send to each peer since the last successful exchange with it, for the peers
+    /// whose sends are failing
+    failing_since: Mutex<HashMap<String, Instant>>,
+    clock: Arc<dyn Clock>,
+    /// Decides whether each received content message is handled, so that tests can drop some of them
+    #[cfg(test)]
+    interceptor: RwLock<Option<ContentInterceptor>>,
+}
+
+/// Returns whether a received content message is handled, see [TrafficLog::set_interceptor]
+#[cfg(test)]
+pub(crate) type ContentInterceptor = Box<dyn Fn(&ContentMessage) -> bool + Send + Sync>;
+
+impl TrafficLog {
+    pub fn new() -> Self {
+        TrafficLog {
+            peers: Mutex::new(HashMap::new()),
+            wire_trace: WireTrace::new(),
+            replies: RwLock::new(None),
+            connections_opened: AtomicU64::new(0),
+            last_seen: Mutex::new(LastSeen::new()),
+            failing_since: Mutex::new(HashMap::new()),
+            clock: Arc::new(SystemClock),
+            #[cfg(test)]
+            interceptor: RwLock::new(None),
+        }
+    }
+
+    #[cfg(test)]
+    pub fn with_clock(clock: Arc<dyn Clock>) -> Self {
+        TrafficLog {
+            peers: Mutex::new(HashMap::new()),
+            wire_trace: WireTrace::new(),
+            replies: RwLock::new(None),
+            connections_opened: AtomicU64::new(0),
+            last_seen: Mutex::new(LastSeen::new()),
+            failing_since: Mutex::new(HashMap::new()),
+            clock,
+            interceptor: RwLock::new(None),
+        }
+    }
+
+    pub fn memory(&self) -> Usage {
+        let peers = Usage::of_sizes(self.peers.lock().unwrap().iter()
+            .map(|(peer, buckets)| peer.len() + buckets.len() * std::mem::size_of::<TrafficBucket>()));
+        let last_seen = self.last_seen.lock().unwrap().memory();
+        // the peers heard from are mostly the peers with traffic
+        Usage { count: peers.count, bytes: peers.bytes + last_seen.bytes }
+    }
+
+    fn period() -> Duration {
+        TRAFFIC_WINDOW / TRAFFIC_BUCKETS
+    }
+
+    /// Sends a message, see [network::send](crate::network::send), traces it and counts it if it was sent.
+    ///
+    /// Once the responses are [read on the connections](Self::set_reply_readers), the receiver is asked to write its
+    /// responses on the connection of the message, which a reader thread, or the sending thread while they are all
+    /// busy, reads until the receiver closes it.
+    pub fn send<M>(&self, address: &SocketAddr, message: &M, cluster_id: Option<u8>, network: &NetworkConfig) -> Result<usize, Box<dyn Error>>
+    where M: Message + Serialize
+    {
+        let result = self.send_unrecorded(address, message, cluster_id, network);
+        self.record_outcome(address, result.is_ok());
+        result
+    }
+
+    fn send_unrecorded<M>(&self, address: &SocketAddr, message: &M, cluster_id: Option<u8>, network: &NetworkConfig) -> Result<usize, Box<dyn Error>>
+    where M: Message + Serialize
+    {
+        let trace = |bytes: &[u8]| self.wire_trace.trace(Direction::Outbound, address, bytes);
+        let trace = self.wire_trace.is_enabled().then_some(&trace as &dyn Fn(&[u8]));
+        let replies = self.replies.read().unwrap().clone();
+        let written = match replies {
+            Some(readers) => {
+                let (written, stream) = crate::network::send_for_replies(address, message, cluster_id, network, trace)?;
+                readers.read(stream);
+                written
+            }
+            None => crate::network::send_traced(address, message, cluster_id, network, trace)?,
+        };
+        self.connections_opened.fetch_add(1, Ordering::SeqCst);
+        self.record_sent(&address.to_string(), message.protocol(), written);
+        Ok(written)
+    }
+
+    /// Sends a response to a message: on the connection of the message if its sender reads the responses on it,
+    /// see [send](Self::send) otherwise
+    ///
+    /// # Arguments
+    ///
+    /// * `reply` - Connection on which the sender of the message reads the responses, if any
+    /// * `address` - Address at which the sender of the message is answered otherwise
+    /// * `message` - The response
+    /// * `cluster_id` - Cluster of the sender, if any
+    /// * `network` - Options of the connection
+    pub fn respond<M>(&self, reply: Option<&ReplyStream>, address: &SocketAddr, message: &M, cluster_id: Option<u8>, network: &NetworkConfig) -> Result<usize, Box<dyn Error>>
+    where M: Message + Serialize
+    {
+        let Some(reply) = reply else {
+            return self.send(address, message, cluster_id, network);
+        };
+        let trace = |bytes: &[u8]| self.wire_trace.trace(Direction::Outbound, address, bytes);
+        let trace = self.wire_trace.is_enabled().then_some(&trace as &dyn Fn(&[u8]));
+        let written = reply.send_traced(message, cluster_id, trace);
+        self.record_outcome(address, written.is_ok());
+        let written = written?;
+        self.record_sent(&address.to_string(), message.protocol(), written);
+        Ok(written)
+    }
+
+    /// Starts or ends the failure streak of the sends to a peer
+    fn record_outcome(&self, address: &SocketAddr, sent: bool) {
+        let mut failing_since = self.failing_since.lock().unwrap();
+        if sent {
+            failing_since.remove(&address.to_string());
+        }
+        else {
+            failing_since.entry(address.to_string()).or_insert_with(|| self.clock.now());
+        }
+    }
+
+    /// Returns the time since which the sends to every peer of the view are failing: the latest of the first failures
+    /// to send to each of them since a message was last sent to it or received from it. `None` if the view is empty, or
+    /// if a peer of the view is not failing. The peers that left the view are forgotten.
+    pub fn failing_since(&self, view: &[Peer]) -> Option<Instant> {
+        let mut failing_since = self.failing_since.lock().unwrap();
+        failing_since.retain(|peer, _| view.iter().any(|known| known.address() == peer));
+        if view.is_empty() || failing_since.len() < view.len() {
+            return None;
+        }
+        failing_since.values().max().copied()
+    }
+
+    /// Reads the responses to the messages sent afterwards on the connections of the messages, or stops doing so
+    ///
+    /// # Arguments
+    ///
+    /// * `replies` - Threads reading and dispatching the responses
+    pub fn set_reply_readers(&self, replies: Option<ReplyReaders>) {
+        *self.replies.write().unwrap() = replies.map(Arc::new);
+    }
+
+    /// Returns the number of connections opened by [send](Self::send)
+    pub fn connections_opened(&self) -> u64 {
+        self.connections_opened.load(Ordering::SeqCst)
+    }
+
+    /// Installs or removes the tracer of the raw messages
+    pub fn set_wire_trace(&self, tracer: Option<WireTracer>) {
+        self.wire_trace.set(tracer);
+    }
+
+    /// Installs or removes the interceptor of the received content messages
+    #[cfg(test)]
+    pub fn set_interceptor(&self, interceptor: Option<ContentInterceptor>) {
+        *self.interceptor.write().unwrap() = interceptor;
+    }
+
+    /// Returns whether a received content message is handled, unless the interceptor drops it
+    #[cfg(test)]
+    pub fn intercept(&self, message: &ContentMessage) -> bool {
+        self.interceptor.read().unwrap().as_ref().is_none_or(|interceptor| interceptor(message))
+    }
+
+    /// Traces a message received from the network, before it is parsed
+    pub fn trace_received(&self, source: &SocketAddr, bytes: &[u8]) {
+        self.wire_trace.trace(Direction::Inbound, source, bytes);
+    }
+
+    /// Counts a message sent to a peer
+    pub fn record_sent(&self, peer: &str, protocol: u8, bytes: usize) {
+        self.record(peer, |bucket| bucket.sent.record(protocol, bytes));
+    }
+
+    /// Counts a message received from a peer, which was heard from at that time
+    pub fn record_received(&self, peer: &str, protocol: u8, bytes: usize) {
+        self.record(peer, |bucket| bucket.received.record(protocol, bytes));
+        self.last_seen.lock().unwrap().record(peer, self.clock.now());
+        self.failing_since.lock().unwrap().remove(peer);
+    }
+
+    /// Returns the time at which a message was last received from a peer, if any was received from it
+    pub fn last_seen(&self, peer: &str) -> Option<Instant> {
+        self.last_seen.lock().unwrap().get(peer)
+    }
+
+    fn record<F: FnOnce(&mut TrafficBucket)>(&self, peer: &str, f: F) {
+        let now = self.clock.now();
+        let mut peers = self.peers.lock().unwrap();
+        if !peers.contains_key(peer) && peers.len() >= MAX_TRAFFIC_PEERS {
+            Self::prune(&mut peers, now);
+            if peers.len() >= MAX_TRAFFIC_PEERS {
+                // forget the peer without traffic for the longest time
+                let idle = peers.iter()
+                    .min_by_key(|(_, buckets)| buckets.back().map(|bucket| bucket.start))
+                    .map(|(idle, _)| idle.to_owned());
+                if let Some(idle) = idle {
+                    peers.remove(&idle);
+                }
+            }
+        }
+        let buckets = peers.entry(peer.to_owned()).or_default();
+        let current = buckets.back().is_some_and(|bucket| now.saturating_duration_since(bucket.start) < Self::period());
+        if !current {
+            buckets.push_back(TrafficBucket { start: now, sent: MessageTraffic::default(), received: MessageTraffic::default() });
+        }
+        f(buckets.back_mut().unwrap());
+    }
+
+    /// Removes the periods that left the window, and the peers without traffic in the window
+    fn prune(peers: &mut HashMap<String, VecDeque<TrafficBucket>>, now: Instant) {
+        peers.retain(|_, buckets| {
+            while buckets.front().is_some_and(|bucket| now.saturating_duration_since(bucket.start) >= TRAFFIC_WINDOW) {
+                buckets.pop_front();
+            }
+            !buckets.is_empty()
+        });
+    }
+
+    /// Returns the traffic of the peers over the window, the peers with the most bytes first
+    pub fn traffic(&self) -> Vec<PeerTraffic> {
+        let mut peers = self.peers.lock().unwrap();
+        Self::prune(&mut peers, self.clock.now());
+        let mut traffic: Vec<PeerTraffic> = peers.iter()
+            .map(|(peer, buckets)| {
+                let mut traffic = PeerTraffic { peer: peer.to_owned(), ..PeerTraffic::default() };
+                for bucket in buckets {
+                    traffic.sent.add(&bucket.sent);
+                    traffic.received.add(&bucket.received);
+                }
+                traffic
+            })
+            .collect();
+        drop(peers);
+        traffic.sort_by(|a, b| b.bytes().cmp(&a.bytes()).then_with(|| a.peer.cmp(&b.peer)));
+        traffic
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+
+    #[test]
+    fn traffic_leaves_the_window() {
+        let clock = Arc::new(ManualClock::new());
+        let log = TrafficLog::with_clock(clock.clone());
+        log.record_sent("a", MESSAGE_PROTOCOL_HEADER_MESSAGE, 10);
+        clock.advance(30_000);
+        log.record_received("a", MESSAGE_PROTOCOL_CONTENT_MESSAGE, 1000);
+        log.record_sent("b", MESSAGE_PROTOCOL_SAMPLING_MESSAGE, 20);
+
+        let traffic = log.traffic();
+        assert_eq!(vec!["a", "b"], traffic.iter().map(|traffic| traffic.peer.as_str()).collect::<Vec<_>>());
+        assert_eq!(TrafficCounters { messages: 1, bytes: 10 }, traffic[0].sent.header);
+        assert_eq!(TrafficCounters { messages: 1, bytes: 1000 }, traffic[0].received.content);
+        assert_eq!(1010, traffic[0].bytes());
+        assert_eq!(TrafficCounters { messages: 1, bytes: 20 }, traffic[1].sent.total());
+
+        // the first header left the window
+        clock.advance(30_000);
+        let traffic = log.traffic();
+        assert_eq!(TrafficCounters::default(), traffic[0].sent.total());
+        assert_eq!(1000, traffic[0].bytes());
+        clock.advance(30_000);
+        assert!(log.traffic().is_empty());
+    }
+
+    #[test]
+    fn failure_streak_ends_with_exchange_with_the_peer() {
+        let clock = Arc::new(ManualClock::new());
+        let log = TrafficLog::with_clock(clock.clone());
+        let (a, b): (SocketAddr, SocketAddr) = ("127.0.0.1:9000".parse().unwrap(), "127.0.0.1:9001".parse().unwrap());
+        let view = vec![Peer::try_new(&a.to_string()).unwrap(), Peer::try_new(&b.to_string()).unwrap()];
+        assert_eq!(None, log.failing_since(&view));
+        assert_eq!(None, log.failing_since(&[]));
+        log.record_outcome(&a, false);
+        clock.advance(1_000);
+        // the sends to the other peer of the view do not fail yet
+        assert_eq!(None, log.failing_since(&view));
+        log.record_outcome(&b, false);
+        let both_failing = clock.now();
+        clock.advance(1_000);
+        // later failures do not move the start of the streak
+        log.record_outcome(&a, false);
+        log.record_outcome(&b, false);
+        assert_eq!(Some(both_failing), log.failing_since(&view));
+
+        // a message from another node does not end the streak, one from a peer of the view does
+        log.record_received("127.0.0.1:9002", MESSAGE_PROTOCOL_SAMPLING_MESSAGE, 10);
+        assert_eq!(Some(both_failing), log.failing_since(&view));
+        log.record_received(&a.to_string(), MESSAGE_PROTOCOL_SAMPLING_MESSAGE, 10);
+        assert_eq!(None, log.failing_since(&view));
+        log.record_outcome(&a, false);
+        log.record_outcome(&a, true);
+        assert_eq!(None, log.failing_since(&view));
+
+        // a peer that left the view is forgotten
+        log.record_outcome(&a, false);
+        assert_eq!(Some(both_failing), log.failing_since(&view[1..]));
+        assert_eq!(None, log.failing_since(&view));
+    }
+
+    #[test]
+    fn idle_peers_are_forgotten_first() {
+        let clock = Arc::new(ManualClock::new());
+        let log = TrafficLog::with_clock(clock.clone());
+        for peer in 0..MAX_TRAFFIC_PEERS {
+            log.record_sent(&peer.to_string(), MESSAGE_PROTOCOL_HEADER_MESSAGE, 1);
+        }
+        clock.advance(20_000);
+        for peer in 1..MAX_TRAFFIC_PEERS {
+            log.record_sent(&peer.to_string(), MESSAGE_PROTOCOL_HEADER_MESSAGE, 1);
+        }
+        log.record_sent("new", MESSAGE_PROTOCOL_HEADER_MESSAGE, 1);
+        let traffic = log.traffic();
+        assert_eq!(MAX_TRAFFIC_PEERS, traffic.len());
+        assert!(traffic.iter().any(|traffic| traffic.peer == "new"));
+        assert!(!traffic.iter().any(|traffic| traffic.peer == "0"));
+    }
+
+    #[test]
+    fn peers_heard_from_the_longest_time_ago_are_forgotten_first() {
+        let log = TrafficLog::new();
+        for peer in 0..MAX_TRAFFIC_PEERS {
+            log.record_received(&peer.to_string(), MESSAGE_PROTOCOL_PONG_MESSAGE, 1);
+        }
+        // heard from again, the first peer is now the most recent
+        log.record_received("0", MESSAGE_PROTOCOL_PONG_MESSAGE, 1);
+        log.record_received("new", MESSAGE_PROTOCOL_PONG_MESSAGE, 1);
+
+        assert!(log.last_seen("0").is_some());
+        assert!(log.last_seen("new").is_some());
+        assert_eq!(None, log.last_seen("1"));
+        assert!(log.last_seen("2").is_some());
+    }
+}
@@ -1,28 +1,116 @@
-use std::collections::HashMap;
+use std::collections::{BinaryHeap, HashMap, HashSet, VecDeque};
+use std::cmp::Reverse;
+use std::time::{Duration, Instant, SystemTime};
 use std::error::Error;
-use crate::config::UpdateExpirationValue;
+use std::sync::Arc;
+use crate::config::{ExpirationStart, UpdateExpirationValue};
 use crate::UpdateExpirationMode;
+use crate::digest::{Blake3Digester, Digester};
+use crate::snapshot::Snapshot;
+use crate::clock::{Clock, SystemClock};
+use crate::memory::{MemoryReport, Usage};
+use crate::revocation::{PendingRevocations, REVOCATION_LIFETIME};
+use crate::update_export::StoredUpdate;
+use serde::{Serialize, Deserialize};
 
 /// A generic update for sending data as binary content
+#[derive(Clone)]
 pub struct Update {
     /// Message content
     content: Vec<u8>,
     /// Content digest
     digest: String,
+    /// Position of the update among those submitted by its origin, with [ordered delivery](crate::GossipConfig::set_ordered_delivery)
+    origin_sequence: Option<OriginSequence>,
+    /// Identifier of the node that submitted the update, with an [origin id](crate::GossipConfig::set_origin_id)
+    origin: Option<String>,
+    /// Whether the update revokes the update whose digest is its content, see [GossipService::revoke](crate::GossipService::revoke)
+    revocation: bool,
+}
+
+/// Kinds of updates whose digest covers more than their content, ending the digested bytes
+const DIGEST_KIND_SEQUENCED: u8 = 1;
+const DIGEST_KIND_REVOCATION: u8 = 2;
+
+/// Identifies an update among the updates submitted by a node with [ordered delivery](crate::GossipConfig::set_ordered_delivery).
+/// The sequence is covered by the digest of the update, so that a peer cannot renumber the updates it relays.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OriginSequence {
+    /// Address of the node that submitted the update
+    pub origin: String,
+    /// Number of the update among those of its origin, starting at 1
+    pub number: u64,
 }
 
 impl Update {
-    /// Creates a new update with specified content
+    /// Creates a new update with specified content, identified by its [BLAKE3](Blake3Digester) digest
     ///
     /// # Arguments
     ///
     /// * `content` - Message content
-    /// * `digest` - Content digest
     pub fn new(content: Vec<u8>) -> Self {
-        let digest = blake3::hash(&content).to_hex().to_string();
+        Self::new_with(&Blake3Digester, content)
+    }
+
+    /// Creates a new update with specified content, identified by the digest computed by the digester
+    ///
+    /// # Arguments
+    ///
+    /// * `digester` - Digest function
+    /// * `content` - Message content
+    pub fn new_with(digester: &dyn Digester, content: Vec<u8>) -> Self {
+        let digest = digester.digest(&content);
+        Update {
+            content,
+            digest,
+            origin_sequence: None,
+            origin: None,
+            revocation: false,
+        }
+    }
+
+    /// Creates an update numbered among the updates of its origin, identified by the digest of its content followed by
+    /// its sequence, or by the digest of its content if it has no sequence
+    pub(crate) fn new_sequenced(digester: &dyn Digester, content: Vec<u8>, origin_sequence: Option<OriginSequence>) -> Self {
+        let Some(sequence) = origin_sequence else {
+            return Self::new_with(digester, content);
+        };
+        Update {
+            digest: bound_digest(digester, &content, &sequence.origin, &sequence.number.to_be_bytes(), DIGEST_KIND_SEQUENCED),
+            content,
+            origin_sequence: Some(sequence),
+            origin: None,
+            revocation: false,
+        }
+    }
+
+    /// Creates an update revoking the update with the given digest, identified by the digest of the revoked digest
+    /// followed by the origin of the revocation, so that neither the origin nor the kind of the update can be changed
+    pub(crate) fn new_revocation(digester: &dyn Digester, revoked: Vec<u8>, origin: Option<String>) -> Self {
+        Update {
+            digest: bound_digest(digester, &revoked, origin.as_deref().unwrap_or_default(), &[], DIGEST_KIND_REVOCATION),
+            content: revoked,
+            origin_sequence: None,
+            origin,
+            revocation: true,
+        }
+    }
+
+    /// Numbers the update among the updates of its origin, which changes its digest, see [new_sequenced](Self::new_sequenced)
+    pub(crate) fn into_sequenced(self, digester: &dyn Digester, origin_sequence: OriginSequence) -> Self {
+        let mut update = Self::new_sequenced(digester, self.content, Some(origin_sequence));
+        update.origin = self.origin;
+        update
+    }
+
+    /// Creates an update with a digest that was received along with the content and is not verified
+    pub(crate) fn with_digest(content: Vec<u8>, digest: String) -> Self {
         Update {
             content,
             digest,
+            origin_sequence: None,
+            origin: None,
+            revocation: false,
         }
     }
 
@@ -33,6 +121,51 @@ impl Update {
     pub fn digest(&self) -> &String {
         &self.digest
     }
+
+    /// Returns the position of the update among those submitted by its origin, if it was submitted with ordered delivery
+    pub fn origin_sequence(&self) -> Option<&OriginSequence> {
+        self.origin_sequence.as_ref()
+    }
+
+    pub(crate) fn set_origin_sequence(&mut self, origin_sequence: Option<OriginSequence>) {
+        self.origin_sequence = origin_sequence;
+    }
+
+    /// Returns the identifier of the node that submitted the update, if it had an
+    /// [origin id](crate::GossipConfig::set_origin_id). The origin is not covered by the digest of the update.
+    pub fn origin(&self) -> Option<&str> {
+        self.origin.as_deref()
+    }
+
+    pub(crate) fn set_origin(&mut self, origin: Option<String>) {
+        self.origin = origin;
+    }
+
+    pub(crate) fn is_revocation(&self) -> bool {
+        self.revocation
+    }
+
+    /// Marks an update received without verifying its digest as a revocation
+    pub(crate) fn set_revocation(&mut self, revocation: bool) {
+        self.revocation = revocation;
+    }
+
+    /// Returns the digest of the update revoked by the update, `None` if it is not a valid revocation
+    pub(crate) fn revoked(&self) -> Option<&str> {
+        self.revocation.then(|| crate::revocation::revoked_digest(&self.content)).flatten()
+    }
+}
+
+/// Returns the digest of a content followed by the fields of the update covered by its digest: the length of the
+/// origin and the kind of the update end the digested bytes, so that the content and the fields cannot be shifted
+fn bound_digest(digester: &dyn Digester, content: &[u8], origin: &str, fields: &[u8], kind: u8) -> String {
+    let mut digested = Vec::with_capacity(content.len() + origin.len() + fields.len() + 9);
+    digested.extend_from_slice(content);
+    digested.extend_from_slice(origin.as_bytes());
+    digested.extend_from_slice(fields);
+    digested.extend_from_slice(&(origin.len() as u64).to_be_bytes());
+    digested.push(kind);
+    digester.digest(&digested)
 }
 
 /// Trait for receiving updates from the gossip protocol.
@@ -45,45 +178,459 @@ pub trait UpdateHandler {
     ///
     /// * `update` - The update that has been received
     fn on_update(&self, update: Update);
+
+    /// Method called instead of [on_update](Self::on_update) with
+    /// [acknowledged delivery](crate::GossipConfig::set_acknowledged_delivery). The update is recorded as
+    /// delivered only if it returns `Ok`, otherwise it is delivered again later. Calls `on_update` by default.
+    ///
+    /// # Arguments
+    ///
+    /// * `update` - The update that has been received
+    fn try_on_update(&self, update: Update) -> Result<(), crate::HandlerError> {
+        self.on_update(update);
+        Ok(())
+    }
+
+    /// Method called when a peer first advertises the digest of an unknown update, before its content is
+    /// requested. It is called at most once per digest while the content is expected, from the header handling
+    /// thread, never concurrently with the other methods of the handler, and should return quickly. Does nothing by
+    /// default.
+    ///
+    /// # Arguments
+    ///
+    /// * `digest` - The digest of the advertised update
+    /// * `from` - The address of the peer that advertised it
+    fn on_header(&self, _digest: &str, _from: &str) {}
+
+    /// Method called with [ordered delivery](crate::GossipConfig::set_ordered_delivery) when updates of an origin
+    /// did not arrive before the gap timeout, and the following updates are delivered without them. Called from
+    /// the message handling thread, before the update that follows the gap. Does nothing by default.
+    ///
+    /// # Arguments
+    ///
+    /// * `origin` - The address of the node that submitted the missing updates
+    /// * `skipped` - The numbers of the missing updates
+    fn on_sequence_gap(&self, _origin: &str, _skipped: std::ops::Range<u64>) {}
+
+    /// Method called once before the handler is dropped by the service, so that it can release its resources.
+    /// It is not called concurrently with the other methods of the handler, and no method is called afterwards.
+    /// Does nothing by default.
+    ///
+    /// # Arguments
+    ///
+    /// * `reason` - Why the handler is dropped
+    fn on_shutdown(&self, _reason: ShutdownReason) {}
+}
+
+/// Why an [UpdateHandler] or a [RoundObserver](crate::RoundObserver) is dropped by the service
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ShutdownReason {
+    /// The service was [shut down](crate::GossipService::shutdown) while all its threads were running
+    Requested,
+    /// The service was shut down after one of its threads stopped, given by name, for example after a panic
+    Failed(String),
+    /// The service could not be [started](crate::GossipService::start)
+    StartFailed,
+    /// The handler was [replaced](crate::GossipService::set_update_handler); only given to update handlers
+    Replaced,
+}
+
+/// Why an update stopped being active, see [ExpirySummary](crate::ExpirySummary)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RemovalReason {
+    /// The update expired, see [UpdateExpirationMode]
+    Expired,
+    /// The update was removed by a [tombstone](crate::GossipConfig::set_tombstones) or a
+    /// [revocation](crate::GossipService::revoke) before it expired
+    Removed,
+}
+
+/// State of an update known to the node, see [GossipService::update_state](crate::GossipService::update_state)
+#[derive(Debug, Clone, PartialEq)]
+pub enum UpdateState {
+    /// The update is active
+    Active {
+        /// Address of the peer from which the content was received, `None` if the update was submitted locally
+        provider: Option<String>,
+    },
+    /// The update expired
+    Expired,
+}
+
+/// Outcome of [GossipService::submit_or_refresh](crate::GossipService::submit_or_refresh)
+#[derive(Debug, Clone, PartialEq)]
+pub enum SubmitOutcome {
+    /// The update was inserted, or queued until start, and is broadcast
+    Inserted(String),
+    /// The update was already active, or queued until start, and its expiration restarted
+    Refreshed(String),
+}
+
+impl SubmitOutcome {
+    /// Returns the digest of the update
+    pub fn digest(&self) -> &str {
+        match self {
+            SubmitOutcome::Inserted(digest) | SubmitOutcome::Refreshed(digest) => digest,
+        }
+    }
+}
+
+/// Outcome of the submission of an item of a batch, see [GossipService::submit_batch](crate::GossipService::submit_batch)
+#[derive(Debug, Clone, PartialEq)]
+pub enum BatchItem {
+    /// The update was inserted, or queued until start, and is broadcast
+    Inserted(String),
+    /// The update was already active or expired, or appeared earlier in the batch
+    Duplicate(String),
+}
+
+impl BatchItem {
+    /// Returns the digest of the update
+    pub fn digest(&self) -> &str {
+        match self {
+            BatchItem::Inserted(digest) | BatchItem::Duplicate(digest) => digest,
+        }
+    }
+
+    /// Returns whether the update was inserted
+    pub fn is_inserted(&self) -> bool {
+        matches!(self, BatchItem::Inserted(_))
+    }
+}
+
+/// Maximum number of distinct peers counted as requesters of an update
+pub const MAX_TRACKED_REQUESTERS: usize = 64;
+
+/// Maximum number of expired updates whose counters are kept
+const MAX_EXPIRED_COUNTERS: usize = 1000;
+
+/// Dissemination counters of an update, see [GossipService::update_counters](crate::GossipService::update_counters)
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct UpdateCounters {
+    /// Number of rounds in which the digest of the update was pushed
+    pub pushes: u64,
+    /// Number of content requests answered with the update
+    pub content_requests: u64,
+    /// Number of distinct peers that requested the content, counted up to [MAX_TRACKED_REQUESTERS]
+    pub distinct_requesters: usize,
+    /// Time at which the content was last requested
+    pub last_request: Option<SystemTime>,
+}
+
+/// An active update with its expiration and delivery state
+struct ActiveUpdate {
+    update: Update,
+    /// Peer from which the content was first received, `None` for local updates
+    provider: Option<String>,
+    expiration: UpdateExpirationValue,
+    /// Insertion rank of the update
+    sequence: u64,
+    /// Generation of the last handler to which the update was delivered, zero if none
+    delivered: u64,
+    /// Generation of the last handler that acknowledged the update, zero if none
+    acknowledged: u64,
+    /// Whether the update was pushed before it expired
+    pushed: bool,
+    /// Last round in which the update was pushed, zero if none
+    last_advertised: u64,
+    /// Dissemination counters, the number of pushes included
+    counters: UpdateCounters,
+    /// Peers that requested the content, at most [MAX_TRACKED_REQUESTERS]
+    requesters: HashSet<String>,
+}
+
+impl ActiveUpdate {
+    /// Rank of the update when the number of pushed headers is limited, lowest first: updates pushed fewer
+    /// than [FRESH_PUSH_ROUNDS] times come first, newest first, then the others, least recently pushed first
+    fn push_priority(&self) -> (bool, u64, u64) {
+        if self.counters.pushes < FRESH_PUSH_ROUNDS {
+            (false, self.counters.pushes, u64::MAX - self.sequence)
+        }
+        else {
+            (true, self.last_advertised, self.sequence)
+        }
+    }
+}
+
+/// Digests of the removed updates, looked up by digest and forgotten oldest first
+#[derive(Default)]
+struct RemovedDigests {
+    digests: HashSet<String>,
+    /// The digests in the order they were removed
+    order: VecDeque<String>,
+}
+impl RemovedDigests {
+    fn contains(&self, digest: &str) -> bool {
+        self.digests.contains(digest)
+    }
+    fn insert(&mut self, digest: String) {
+        if self.digests.insert(digest.clone()) {
+            self.order.push_back(digest);
+        }
+    }
+    /// Forgets a digest, and returns whether it was known
+    fn remove(&mut self, digest: &str) -> bool {
+        let removed = self.digests.remove(digest);
+        if removed {
+            self.order.retain(|known| known != digest);
+        }
+        removed
+    }
+    /// Forgets the `count` oldest digests
+    fn forget_oldest(&mut self, count: usize) {
+        for digest in self.order.drain(..count.min(self.order.len())) {
+            self.digests.remove(&digest);
+        }
+    }
+    fn len(&self) -> usize {
+        self.order.len()
+    }
+    fn clear(&mut self) {
+        self.digests.clear();
+        self.order.clear();
+    }
 }
 
+/// Number of rounds during which a new update is pushed before the older ones
+const FRESH_PUSH_ROUNDS: u64 = 3;
+
 /// A decorator for handling operations around updates
 pub struct UpdateDecorator {
     /// Active updates
-    active_updates: HashMap<String, (Update, UpdateExpirationValue)>,
+    active_updates: HashMap<String, ActiveUpdate>,
+    /// Insertion rank of the next update
+    next_sequence: u64,
+    /// Number of push rounds so far
+    push_round: u64,
     /// Removed/expired updates
-    removed_updates: Vec<String>,
+    removed_updates: RemovedDigests,
+    /// Expiration deadlines of the updates expiring after a duration, earliest first
+    deadlines: BinaryHeap<Reverse<(Instant, String)>>,
+    /// Digests of the updates expiring by recency, in insertion order
+    recency_queue: VecDeque<String>,
+    /// Number of updates expiring by recency kept once the expiration mode changed to another strategy
+    recency_limit: usize,
+    /// Digests of updates whose push count reached zero and that are waiting for removal
+    pending_expired: Vec<String>,
+    /// Number of entries examined when clearing expired updates
+    #[cfg(test)]
+    examined: usize,
     /// Strategy for expiring updates
     expiration_mode: UpdateExpirationMode,
+    /// Event from which the duration of updates expiring after a duration is counted
+    expiration_start: ExpirationStart,
     /// Number of digests of expired updates that are kept
     max_expired_size: usize,
     /// Margin for cleanup of expired updates
     max_expired_margin: f64,
+    /// Digest function of the updates
+    digester: Arc<dyn Digester>,
+    /// Digests of the active updates, published after changes
+    published: Arc<Snapshot<String>>,
+    /// Whether the active updates changed since they were last published
+    changed: bool,
+    /// Counters of the most recently removed updates
+    expired_counters: HashMap<String, UpdateCounters>,
+    /// Digests of the updates whose counters are kept after removal, oldest first
+    expired_counters_order: VecDeque<String>,
+    /// Updates removed since the last call to [take_removed](Self::take_removed), with their counters
+    newly_removed: Vec<(String, UpdateCounters, RemovalReason)>,
+    /// Digests of the recently expired updates advertised to the peers, with the time they were removed, oldest first
+    tombstones: VecDeque<(Instant, String)>,
+    /// Maximum number of tombstones advertised, zero if they are disabled
+    max_tombstones: usize,
+    /// Time during which a tombstone is advertised
+    tombstone_window: Duration,
+    /// Revocations of the updates that were not received yet
+    pending_revocations: PendingRevocations,
+    /// Time source of the expiration
+    clock: Arc<dyn Clock>,
 }
 impl UpdateDecorator {
-    pub fn new(expiration_mode: UpdateExpirationMode) -> Self {
+    pub fn new(expiration_mode: UpdateExpirationMode, digester: Arc<dyn Digester>) -> Self {
+        Self::with_clock(expiration_mode, digester, Arc::new(SystemClock))
+    }
+
+    /// Creates a decorator whose updates expire against the given clock
+    pub(crate) fn with_clock(expiration_mode: UpdateExpirationMode, digester: Arc<dyn Digester>, clock: Arc<dyn Clock>) -> Self {
         Self{
             active_updates: HashMap::new(),
-            removed_updates: Vec::new(),
+            next_sequence: 0,
+            push_round: 0,
+            removed_updates: RemovedDigests::default(),
+            deadlines: BinaryHeap::new(),
+            recency_queue: VecDeque::new(),
+            recency_limit: 0,
+            pending_expired: Vec::new(),
+            #[cfg(test)]
+            examined: 0,
             expiration_mode,
+            expiration_start: ExpirationStart::OnInsert,
             max_expired_size: 10000,
-            max_expired_margin: 0.5
+            max_expired_margin: 0.5,
+            digester,
+            published: Arc::new(Snapshot::new()),
+            changed: false,
+            expired_counters: HashMap::new(),
+            expired_counters_order: VecDeque::new(),
+            newly_removed: Vec::new(),
+            tombstones: VecDeque::new(),
+            max_tombstones: 0,
+            tombstone_window: Duration::ZERO,
+            pending_revocations: PendingRevocations::default(),
+            clock,
         }
     }
+
+    /// Creates an update identified by the digest computed with the configured digester
+    pub fn create_update(&self, content: Vec<u8>) -> Update {
+        Update::new_with(self.digester.as_ref(), content)
+    }
+    /// Changes the expiration strategy of the updates inserted from now on.
+    /// Active updates keep the expiration they were inserted with: those expiring by recency stay bounded by
+    /// the count of the previous mode.
+    pub fn set_expiration_mode(&mut self, expiration_mode: UpdateExpirationMode) {
+        if let UpdateExpirationMode::MostRecent(size, margin) = self.expiration_mode {
+            self.recency_limit = Self::recency_size(size, margin);
+        }
+        self.expiration_mode = expiration_mode;
+    }
+
+    /// Changes the event from which the duration of the updates inserted from now on is counted
+    pub fn set_expiration_start(&mut self, expiration_start: ExpirationStart) {
+        self.expiration_start = expiration_start;
+    }
+
+    /// Sets the number of recently expired digests advertised as tombstones, and the time during which each is
+    /// advertised, see [GossipConfig::set_tombstones](crate::GossipConfig::set_tombstones)
+    pub fn set_tombstones(&mut self, max_tombstones: usize, tombstone_window: Duration) {
+        self.max_tombstones = max_tombstones.min(crate::message::MAX_MESSAGE_DIGESTS);
+        self.tombstone_window = tombstone_window;
+        self.prune_tombstones();
+    }
+
+    /// Returns the tombstones to advertise, oldest first
+    pub fn tombstones(&self) -> Vec<String> {
+        let now = self.clock.now();
+        self.tombstones.iter()
+            .filter(|(removed, _)| now.duration_since(*removed) < self.tombstone_window)
+            .map(|(_, digest)| digest.clone())
+            .collect()
+    }
+
+    /// Removes the active updates and forgets the unknown digests advertised as tombstones by a peer, so that
+    /// they are not requested anymore, and advertises them in turn.
+    ///
+    /// Returns the number of digests that were active or unknown.
+    pub fn apply_tombstones(&mut self, digests: &[String]) -> usize {
+        let mut applied = 0;
+        for digest in digests {
+            if self.active_updates.contains_key(digest) {
+                self.remove_active(digest.clone(), RemovalReason::Removed);
+                applied += 1;
+            }
+            else if !self.removed_updates.contains(digest) {
+                self.removed_updates.insert(digest.clone());
+                self.record_tombstone(digest.clone());
+                applied += 1;
+            }
+        }
+        applied
+    }
+
+    /// Removes an update revoked by its origin if it is active, and returns whether it was removed. The revocation of
+    /// an update that was not received yet is remembered, see [take_revoked](Self::take_revoked); the revocations
+    /// of the updates of other origins are ignored.
+    pub fn apply_revocation(&mut self, revoked: &str, origin: &str) -> bool {
+        match self.active_updates.get(revoked) {
+            Some(active) if active.update.origin() == Some(origin) => self.apply_tombstones(&[revoked.to_owned()]) > 0,
+            Some(_) => false,
+            None => {
+                if self.is_new(revoked) {
+                    self.pending_revocations.insert(revoked, origin);
+                }
+                false
+            }
+        }
+    }
+
+    /// Returns whether a received update was revoked by its origin before it arrived, in which case it is removed
+    /// instead of being inserted
+    pub fn take_revoked(&mut self, update: &Update) -> bool {
+        if !self.pending_revocations.take(update.digest(), update.origin()) {
+            return false;
+        }
+        self.apply_tombstones(&[update.digest().to_owned()]);
+        true
+    }
+
+    /// Records the removal of an update for advertisement, if tombstones are enabled
+    fn record_tombstone(&mut self, digest: String) {
+        if self.max_tombstones > 0 {
+            self.tombstones.push_back((self.clock.now(), digest));
+            self.prune_tombstones();
+        }
+    }
+
+    /// Forgets the tombstones beyond the maximum number or older than the window
+    fn prune_tombstones(&mut self) {
+        let now = self.clock.now();
+        while self.tombstones.len() > self.max_tombstones
+            || self.tombstones.front().is_some_and(|(removed, _)| now.duration_since(*removed) >= self.tombstone_window) {
+            self.tombstones.pop_front();
+        }
+    }
+
+    /// Starts the duration of the given updates expiring after a duration, unless it has already started
+    pub fn start_expiration(&mut self, digests: &[String]) {
+        let now = self.clock.now();
+        for digest in digests {
+            if let Some(active) = self.active_updates.get_mut(digest) {
+                if let UpdateExpirationValue::DurationMillis(start @ None, ttl) = &mut active.expiration {
+                    *start = Some(now);
+                    self.deadlines.push(Reverse((now + Duration::from_millis(*ttl as u64), digest.clone())));
+                }
+            }
+        }
+    }
+
     pub fn active_count(&self) -> usize {
         self.active_updates.len()
     }
 
+    pub fn removed_count(&self) -> usize {
+        self.removed_updates.len()
+    }
+
+    /// Publishes the digests of the active updates if they changed since they were last published
+    pub fn publish(&mut self) {
+        if self.changed {
+            self.published.store(self.active_headers());
+            self.changed = false;
+        }
+    }
+
+    /// Returns the digests of the active updates, as published by [publish](Self::publish)
+    pub fn published_digests(&self) -> Arc<Snapshot<String>> {
+        Arc::clone(&self.published)
+    }
+
     pub fn active_headers(&self) -> Vec<String> {
-        self.active_updates.iter().map(|(header, _)| header.to_owned()).collect()
+        self.active_updates.keys().map(|header| header.to_owned()).collect()
     }
 
-    pub fn is_new(&self, digest: &String) -> bool {
-        !self.active_updates.contains_key(digest) && !self.removed_updates.contains(&digest)
+    /// Returns the digests of the active updates as last [published](Self::publish), without copying them, if the
+    /// active updates did not change since. `None` if a change is not published yet.
+    pub fn cached_headers(&self) -> Option<Arc<Vec<String>>> {
+        (!self.changed).then(|| self.published.load())
     }
 
-    pub fn is_expired(&self, digest: &String) -> bool {
-        self.removed_updates.contains(&digest)
+    pub fn is_new(&self, digest: &str) -> bool {
+        !self.active_updates.contains_key(digest) && !self.removed_updates.contains(digest)
+    }
+
+    pub fn is_expired(&self, digest: &str) -> bool {
+        self.removed_updates.contains(digest)
     }
 
     pub fn is_active(&self, digest: &String) -> bool {
@@ -91,64 +638,263 @@ impl UpdateDecorator {
     }
 
     pub fn get_update(&self, digest: &str) -> Option<&Update> {
-        self.active_updates.get(digest).map_or(None, |(update, _)| Some(update))
+        self.active_updates.get(digest).map(|active| &active.update)
     }
 
-    pub fn insert_update(&mut self, update: Update) -> Result<(), Box<dyn Error>> {
-        if let None = self.active_updates.insert(update.digest().to_owned(), (update, UpdateExpirationValue::new(self.expiration_mode.clone()))) {
-            Ok(())
+    /// Records that an active update was delivered to the handler of the given generation
+    pub fn mark_delivered(&mut self, digest: &str, generation: u64) {
+        if let Some(active) = self.active_updates.get_mut(digest) {
+            active.delivered = generation;
         }
-        else {
+    }
+
+    /// Records that an active update was acknowledged by the handler of the given generation
+    pub fn mark_acknowledged(&mut self, digest: &str, generation: u64) {
+        if let Some(active) = self.active_updates.get_mut(digest) {
+            active.acknowledged = generation;
+        }
+    }
+
+    /// Whether an update is active and was acknowledged by the handler of the given generation
+    pub fn is_acknowledged(&self, digest: &str, generation: u64) -> bool {
+        self.active_updates.get(digest).is_some_and(|active| active.acknowledged == generation)
+    }
+
+    /// Returns, in insertion order, the active updates not yet delivered to the handler of the given generation,
+    /// or not acknowledged by it if `unacknowledged` is set, and marks them as delivered to it
+    pub fn take_undelivered(&mut self, generation: u64, unacknowledged: bool) -> Vec<Update> {
+        let mut undelivered: Vec<&mut ActiveUpdate> = self.active_updates.values_mut()
+            .filter(|active| if unacknowledged { active.acknowledged != generation } else { active.delivered != generation })
+            .collect();
+        undelivered.sort_by_key(|active| active.sequence);
+        undelivered.into_iter()
+            .map(|active| {
+                active.delivered = generation;
+                active.update.clone()
+            })
+            .collect()
+    }
+
+    /// Returns, in insertion order, the active updates with whether the handler of the given generation received them,
+    /// or acknowledged them if `acknowledged` is set; none was received if there is no handler
+    pub fn stored_updates(&self, generation: Option<u64>, acknowledged: bool) -> Vec<StoredUpdate> {
+        let mut active: Vec<&ActiveUpdate> = self.active_updates.values().collect();
+        active.sort_by_key(|active| active.sequence);
+        active.into_iter()
+            .map(|active| StoredUpdate {
+                update: active.update.clone(),
+                provider: active.provider.clone(),
+                delivered: generation.is_some_and(|generation| generation == if acknowledged { active.acknowledged } else { active.delivered }),
+            })
+            .collect()
+    }
+
+    /// Returns the state of the update with the given digest, `None` if it is unknown
+    pub fn update_state(&self, digest: &str) -> Option<UpdateState> {
+        match self.active_updates.get(digest) {
+            Some(active) => Some(UpdateState::Active { provider: active.provider.clone() }),
+            None if self.removed_updates.contains(digest) => Some(UpdateState::Expired),
+            None => None,
+        }
+    }
+
+    /// Records a content request answered with the given updates
+    pub fn record_request<'a, I>(&mut self, digests: I, requester: &str)
+    where I: IntoIterator<Item = &'a String>
+    {
+        let now = SystemTime::now();
+        for digest in digests {
+            if let Some(active) = self.active_updates.get_mut(digest) {
+                active.counters.content_requests += 1;
+                active.counters.last_request = Some(now);
+                if active.requesters.len() < MAX_TRACKED_REQUESTERS && active.requesters.insert(requester.to_owned()) {
+                    active.counters.distinct_requesters = active.requesters.len();
+                }
+            }
+        }
+    }
+
+    /// Returns the counters of an active update, or of a recently removed one
+    pub fn update_counters(&self, digest: &str) -> Option<UpdateCounters> {
+        match self.active_updates.get(digest) {
+            Some(active) => Some(active.counters.clone()),
+            None => self.expired_counters.get(digest).cloned(),
+        }
+    }
+
+    /// Returns the counters of the active updates in insertion order, followed by those of the recently
+    /// removed updates, oldest first
+    pub fn all_counters(&self) -> Vec<(String, UpdateCounters)> {
+        let mut active: Vec<&ActiveUpdate> = self.active_updates.values().collect();
+        active.sort_by_key(|active| active.sequence);
+        active.into_iter()
+            .map(|active| (active.update.digest().to_owned(), active.counters.clone()))
+            .chain(self.expired_counters_order.iter()
+                .filter_map(|digest| self.expired_counters.get(digest).map(|counters| (digest.to_owned(), counters.clone()))))
+            .collect()
+    }
+
+    /// Returns, in insertion order, the digests of the active updates received from the given peer
+    pub fn updates_from(&self, peer: &str) -> Vec<String> {
+        let mut received: Vec<&ActiveUpdate> = self.active_updates.values()
+            .filter(|active| active.provider.as_deref() == Some(peer))
+            .collect();
+        received.sort_by_key(|active| active.sequence);
+        received.into_iter().map(|active| active.update.digest().to_owned()).collect()
+    }
+
+    /// Inserts an update submitted locally
+    pub fn insert_update(&mut self, update: Update) -> Result<(), Box<dyn Error>> {
+        self.insert_update_from(update, None)
+    }
+
+    /// Inserts an update, recording the peer from which it was received. An update that is already
+    /// active keeps its first provider.
+    pub fn insert_update_from(&mut self, update: Update, provider: Option<String>) -> Result<(), Box<dyn Error>> {
+        let digest = update.digest().to_owned();
+        if self.active_updates.contains_key(&digest) {
             Err("Update already existed")?
         }
+        let now = self.clock.now();
+        let start = (self.expiration_start == ExpirationStart::OnInsert).then_some(now);
+        // revocations expire even when the updates never do
+        let expiration_mode = match self.expiration_mode {
+            UpdateExpirationMode::None if update.is_revocation() => UpdateExpirationMode::DurationMillis(REVOCATION_LIFETIME),
+            _ => self.expiration_mode.clone(),
+        };
+        let expiration_value = UpdateExpirationValue::new(expiration_mode.clone(), start);
+        let expired = expiration_value.has_expired(now);
+        let deadline = match expiration_value {
+            UpdateExpirationValue::DurationMillis(Some(start), ttl) => Some(start + Duration::from_millis(ttl as u64)),
+            _ => None,
+        };
+        let active = ActiveUpdate {
+            update,
+            provider,
+            expiration: expiration_value,
+            sequence: self.next_sequence,
+            delivered: 0,
+            acknowledged: 0,
+            pushed: false,
+            last_advertised: 0,
+            counters: UpdateCounters::default(),
+            requesters: HashSet::new(),
+        };
+        self.active_updates.insert(digest.clone(), active);
+        self.changed = true;
+        self.next_sequence += 1;
+        match expiration_mode {
+            UpdateExpirationMode::DurationMillis(_) => if let Some(deadline) = deadline {
+                self.deadlines.push(Reverse((deadline, digest)))
+            },
+            UpdateExpirationMode::MostRecent(_, _) => self.recency_queue.push_back(digest),
+            UpdateExpirationMode::PushCount(_) if expired => self.pending_expired.push(digest),
+            _ => (),
+        }
+        Ok(())
     }
 
     pub fn clear(&mut self) {
+        self.changed |= !self.active_updates.is_empty();
         self.active_updates.clear();
         self.removed_updates.clear();
+        self.deadlines.clear();
+        self.recency_queue.clear();
+        self.pending_expired.clear();
+        self.expired_counters.clear();
+        self.expired_counters_order.clear();
+        self.newly_removed.clear();
+        self.tombstones.clear();
+        self.pending_revocations.clear();
     }
 
-    pub fn active_headers_for_push(&mut self) -> Vec<String> {
-        let mut headers = Vec::new();
-        self.active_updates.iter_mut()
-            .for_each(|(digest, (_, expiration))| {
-                expiration.increase_push_count();
-                headers.push(digest.clone());
-            });
+    /// Returns the headers to push in a new round, at most `max_headers` if set, and counts the push of
+    /// the returned headers only. When the headers are limited, the updates pushed fewer than
+    /// [FRESH_PUSH_ROUNDS] times are selected first, newest first, and the others in turn.
+    pub fn active_headers_for_push(&mut self, max_headers: Option<usize>) -> Vec<String> {
+        self.push_round += 1;
+        let round = self.push_round;
+        let now = self.clock.now();
+        let mut selected: Vec<(&String, &mut ActiveUpdate)> = self.active_updates.iter_mut().collect();
+        if let Some(max_headers) = max_headers.filter(|max_headers| *max_headers < selected.len()) {
+            selected.sort_unstable_by_key(|(_, active)| active.push_priority());
+            selected.truncate(max_headers);
+        }
+        let mut headers = Vec::with_capacity(selected.len());
+        for (digest, active) in selected {
+            let was_expired = active.expiration.has_expired(now);
+            active.pushed |= !was_expired;
+            active.counters.pushes += 1;
+            active.last_advertised = round;
+            active.expiration.increase_push_count();
+            if !was_expired && active.expiration.has_expired(now) {
+                self.pending_expired.push(digest.clone());
+            }
+            headers.push(digest.clone());
+        }
         headers
     }
 
-    pub fn clear_expired(&mut self) {
-        match self.expiration_mode {
-            UpdateExpirationMode::None => (),
-            UpdateExpirationMode::MostRecent(size, margin) => {
-                let max_size = size + (size as f64 * margin) as usize;
-                if self.active_updates.len() > max_size {
-                    let removal_count = self.active_updates.len() - max_size;
-
-                    let mut removal_keys: Vec<(String, std::time::Instant)> = Vec::new();
-                    for(digest, (_, expiration_value)) in &self.active_updates {
-                        match expiration_value {
-                            UpdateExpirationValue::MostRecent(created) => removal_keys.push((digest.to_owned(), created.clone())),
-                            _ => (),
-                        }
-                    }
-                    // sort from oldest to more recent
-                    removal_keys.sort_by_key(|(_, created)| *created);
-                    for i in 0..removal_count {
-                        self.active_updates.remove(&removal_keys[i].0);
-                        self.removed_updates.push(removal_keys[i].0.clone());
+    /// Removes the expired updates. Only the entries that are due are examined: updates expiring by
+    /// duration are taken from a queue ordered by deadline, updates expiring by push count are recorded
+    /// when their count reaches zero, and updates expiring by recency are taken in insertion order.
+    ///
+    /// Returns the number of removed updates that were never pushed.
+    pub fn clear_expired(&mut self) -> usize {
+        let mut unpushed = 0;
+
+        // updates whose push count reached zero
+        for digest in std::mem::take(&mut self.pending_expired) {
+            #[cfg(test)]
+            { self.examined += 1; }
+            unpushed += self.remove_active(digest, RemovalReason::Expired) as usize;
+        }
+
+        // updates whose duration elapsed
+        let now = self.clock.now();
+        while let Some(Reverse((deadline, _))) = self.deadlines.peek() {
+            #[cfg(test)]
+            { self.examined += 1; }
+            if *deadline > now {
+                break;
+            }
+            if let Some(Reverse((_, digest))) = self.deadlines.pop() {
+                // the deadline of a refreshed update was pushed again
+                let refreshed = self.active_updates.get(&digest).is_some_and(|active| !active.expiration.has_expired(now));
+                if !refreshed {
+                    unpushed += self.remove_active(digest, RemovalReason::Expired) as usize;
+                }
+            }
+        }
+
+        // least recent updates beyond the configured count
+        if let UpdateExpirationMode::MostRecent(size, margin) = self.expiration_mode {
+            let max_size = Self::recency_size(size, margin);
+            while self.active_updates.len() > max_size {
+                match self.recency_queue.pop_front() {
+                    Some(digest) => {
+                        #[cfg(test)]
+                        { self.examined += 1; }
+                        unpushed += self.remove_active(digest, RemovalReason::Expired) as usize;
                     }
+                    None => break,
                 }
-            },
-            UpdateExpirationMode::PushCount(_) | UpdateExpirationMode::DurationMillis(_) => {
-                let expired_keys: Vec<String> = self.active_updates.iter()
-                    .filter(|(_, (_, expiration_value))| expiration_value.has_expired())
-                    .map(|(digest, (_, _))| digest.to_owned())
-                    .collect();
-                for key in expired_keys {
-                    self.active_updates.remove(&key);
-                    self.removed_updates.push(key.clone());
+            }
+            // updates removed otherwise, by tombstones or revocations, leave their digest in the queue
+            if self.recency_queue.len() > 2 * max_size.max(self.active_updates.len()) {
+                let active_updates = &self.active_updates;
+                self.recency_queue.retain(|digest| active_updates.contains_key(digest));
+            }
+        }
+        // updates inserted while they expired by recency, before the mode changed
+        else if self.recency_queue.len() > self.recency_limit {
+            let active_updates = &self.active_updates;
+            self.recency_queue.retain(|digest| active_updates.contains_key(digest));
+            while self.recency_queue.len() > self.recency_limit {
+                if let Some(digest) = self.recency_queue.pop_front() {
+                    #[cfg(test)]
+                    { self.examined += 1; }
+                    unpushed += self.remove_active(digest, RemovalReason::Expired) as usize;
                 }
             }
         }
@@ -156,7 +902,495 @@ impl UpdateDecorator {
         let margin_size = (self.max_expired_size as f64 * self.max_expired_margin) as usize;
         let max_expired = self.max_expired_size + margin_size;
         if self.removed_updates.len() > max_expired && margin_size > 0 {
-            self.removed_updates.drain(0..margin_size);
+            self.removed_updates.forget_oldest(margin_size);
         }
+        unpushed
+    }
+
+    /// Records the sizes of the active and removed updates
+    pub fn memory(&self, report: &mut MemoryReport) {
+        report.active_updates = Usage::of_sizes(self.active_updates.iter()
+            .map(|(digest, active)| digest.len() + active.update.content().len() + active.provider.as_ref().map_or(0, String::len)));
+        report.removed_digests = Usage::of_strings(&self.removed_updates.order);
+        report.tombstones = Usage::of_strings(self.tombstones.iter().map(|(_, digest)| digest));
+        report.pending_revocations = Usage::of_strings(self.pending_revocations.digests());
+        report.expired_counters = Usage::of_strings(self.expired_counters.keys());
+        let deadlines = Usage::of_strings(self.deadlines.iter().map(|Reverse((_, digest))| digest));
+        let recency = Usage::of_strings(&self.recency_queue);
+        report.expiration_queues = Usage {
+            count: deadlines.count + recency.count + self.pending_expired.len(),
+            bytes: deadlines.bytes + recency.bytes + Usage::of_strings(&self.pending_expired).bytes,
+        };
+    }
+
+    /// Returns the updates removed by [clear_expired](Self::clear_expired) or by tombstones since the last call, with
+    /// their counters
+    pub fn take_removed(&mut self) -> Vec<(String, UpdateCounters, RemovalReason)> {
+        std::mem::take(&mut self.newly_removed)
     }
-}
\ No newline at end of file
+
+    /// Restarts the expiration of an active update as if it had just been inserted: its push count or its duration
+    /// starts over, unless the duration has not started yet, and it becomes the most recent update. Returns whether
+    /// the update is active.
+    pub fn refresh(&mut self, digest: &str) -> bool {
+        let now = self.clock.now();
+        let Some(active) = self.active_updates.get_mut(digest) else {
+            return false;
+        };
+        let start = match active.expiration {
+            UpdateExpirationValue::DurationMillis(None, _) => None,
+            _ => Some(now),
+        };
+        active.expiration = UpdateExpirationValue::new(self.expiration_mode.clone(), start);
+        match self.expiration_mode {
+            // the previous deadline is skipped once due, see clear_expired
+            UpdateExpirationMode::DurationMillis(ttl) if start.is_some() => {
+                self.deadlines.push(Reverse((now + Duration::from_millis(ttl as u64), digest.to_owned())));
+            }
+            UpdateExpirationMode::MostRecent(_, _) => {
+                self.recency_queue.retain(|queued| queued != digest);
+                self.recency_queue.push_back(digest.to_owned());
+            }
+            _ => (),
+        }
+        // a refreshed update expires with the current mode only
+        if !matches!(self.expiration_mode, UpdateExpirationMode::MostRecent(_, _)) {
+            self.recency_queue.retain(|queued| queued != digest);
+        }
+        self.pending_expired.retain(|pending| pending != digest);
+        true
+    }
+
+    /// Forgets the digest of a removed update and stops advertising its tombstone, so that the update can be
+    /// inserted again. Returns whether the update was removed.
+    pub fn forget_removed(&mut self, digest: &str) -> bool {
+        self.tombstones.retain(|(_, known)| known != digest);
+        self.removed_updates.remove(digest)
+    }
+
+    /// Treats an update that is not active as removed, so that its content is not requested anymore
+    pub fn mark_removed(&mut self, digest: String) {
+        if self.is_new(&digest) {
+            self.removed_updates.insert(digest);
+        }
+    }
+
+    /// Returns the number of updates kept when they expire by recency, including the margin
+    fn recency_size(size: usize, margin: f64) -> usize {
+        size + (size as f64 * margin) as usize
+    }
+
+    /// Moves an update from the active updates to the removed updates, and returns whether it was never pushed
+    fn remove_active(&mut self, digest: String, reason: RemovalReason) -> bool {
+        match self.active_updates.remove(&digest) {
+            Some(active) => {
+                self.changed = true;
+                if self.expired_counters_order.len() == MAX_EXPIRED_COUNTERS {
+                    if let Some(oldest) = self.expired_counters_order.pop_front() {
+                        self.expired_counters.remove(&oldest);
+                    }
+                }
+                self.newly_removed.push((digest.clone(), active.counters.clone(), reason));
+                self.expired_counters.insert(digest.clone(), active.counters);
+                self.expired_counters_order.push_back(digest.clone());
+                self.record_tombstone(digest.clone());
+                self.removed_updates.insert(digest);
+                !active.pushed
+            }
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+
+    fn decorator_with(expiration_mode: UpdateExpirationMode, count: usize) -> UpdateDecorator {
+        decorator_with_clock(expiration_mode, count).0
+    }
+
+    fn decorator_with_clock(expiration_mode: UpdateExpirationMode, count: usize) -> (UpdateDecorator, Arc<ManualClock>) {
+        let clock = Arc::new(ManualClock::new());
+        let mut decorator = UpdateDecorator::with_clock(expiration_mode, crate::digest::default_digester(), Arc::clone(&clock) as Arc<dyn Clock>);
+        for i in 0..count {
+            decorator.insert_update(Update::new(i.to_string().into_bytes())).unwrap();
+        }
+        (decorator, clock)
+    }
+
+    #[test]
+    fn recency_queue_forgets_updates_removed_by_tombstones() {
+        let mut decorator = decorator_with(UpdateExpirationMode::MostRecent(10, 0.0), 0);
+        for round in 0..20 {
+            let digests: Vec<String> = (0..5).map(|i| {
+                let update = Update::new(format!("{}-{}", round, i).into_bytes());
+                let digest = update.digest().clone();
+                decorator.insert_update(update).unwrap();
+                digest
+            }).collect();
+            decorator.apply_tombstones(&digests);
+            decorator.clear_expired();
+        }
+        let mut report = MemoryReport::default();
+        decorator.memory(&mut report);
+        assert_eq!(0, report.active_updates.count);
+        assert!(report.expiration_queues.count <= 20, "{} entries", report.expiration_queues.count);
+    }
+
+    #[test]
+    fn revocations_expire_when_updates_never_do() {
+        let digester = crate::digest::default_digester();
+        let (mut decorator, clock) = decorator_with_clock(UpdateExpirationMode::None, 1);
+        let update = Update::new(b"0".to_vec());
+        let revocation = Update::new_revocation(digester.as_ref(), update.digest().as_bytes().to_vec(), Some("origin".to_owned()));
+        let revocation_digest = revocation.digest().clone();
+        decorator.insert_update(revocation).unwrap();
+        clock.advance(REVOCATION_LIFETIME as u64);
+        decorator.clear_expired();
+        assert!(decorator.is_expired(&revocation_digest));
+        assert!(decorator.is_active(update.digest()));
+    }
+
+    #[test]
+    fn revocations_apply_to_the_updates_of_their_origin() {
+        let mut decorator = decorator_with(UpdateExpirationMode::None, 0);
+        let mut update = Update::new(b"active".to_vec());
+        update.set_origin(Some("origin".to_owned()));
+        let active = update.digest().clone();
+        decorator.insert_update(update).unwrap();
+        assert!(!decorator.apply_revocation(&active, "other"));
+        assert!(decorator.apply_revocation(&active, "origin"));
+        assert!(decorator.is_expired(&active));
+
+        // revoked before it is received
+        let mut update = Update::new(b"pending".to_vec());
+        decorator.apply_revocation(update.digest(), "origin");
+        assert!(!decorator.take_revoked(&update));
+        update.set_origin(Some("origin".to_owned()));
+        assert!(decorator.take_revoked(&update));
+        assert!(decorator.is_expired(update.digest()));
+    }
+
+    #[test]
+    fn expired_updates_are_advertised_as_tombstones() {
+        let (mut decorator, clock) = decorator_with_clock(UpdateExpirationMode::DurationMillis(20), 3);
+        decorator.set_tombstones(2, Duration::from_millis(100));
+        clock.advance(30);
+        decorator.clear_expired();
+        // only the most recent ones are kept
+        assert_eq!(2, decorator.tombstones().len());
+
+        let unknown = Update::new(b"unknown".to_vec()).digest().clone();
+        decorator.insert_update(Update::new(b"active".to_vec())).unwrap();
+        let active = Update::new(b"active".to_vec()).digest().clone();
+        assert_eq!(2, decorator.apply_tombstones(&[unknown.clone(), active.clone(), unknown.clone()]));
+        assert!(!decorator.is_new(&unknown));
+        assert!(decorator.is_expired(&active));
+        assert_eq!(vec![unknown, active], decorator.tombstones());
+
+        clock.advance(100);
+        assert!(decorator.tombstones().is_empty());
+    }
+
+    #[test]
+    fn refreshed_updates_expire_later() {
+        let digest = Update::new(b"0".to_vec()).digest().clone();
+
+        let (mut decorator, clock) = decorator_with_clock(UpdateExpirationMode::DurationMillis(20), 1);
+        clock.advance(15);
+        assert!(decorator.refresh(&digest));
+        clock.advance(15);
+        decorator.clear_expired();
+        assert!(decorator.is_active(&digest));
+        clock.advance(5);
+        decorator.clear_expired();
+        assert!(decorator.is_expired(&digest));
+        assert!(!decorator.refresh(&digest));
+
+        let mut decorator = decorator_with(UpdateExpirationMode::PushCount(2), 1);
+        decorator.active_headers_for_push(None);
+        decorator.active_headers_for_push(None);
+        // due for removal, but refreshed first
+        assert!(decorator.refresh(&digest));
+        decorator.clear_expired();
+        decorator.active_headers_for_push(None);
+        decorator.clear_expired();
+        assert!(decorator.is_active(&digest));
+        decorator.active_headers_for_push(None);
+        decorator.clear_expired();
+        assert!(decorator.is_expired(&digest));
+
+        let mut decorator = decorator_with(UpdateExpirationMode::MostRecent(2, 0.0), 2);
+        assert!(decorator.refresh(&digest));
+        decorator.insert_update(Update::new(b"2".to_vec())).unwrap();
+        decorator.clear_expired();
+        assert!(decorator.is_active(&digest));
+        assert!(decorator.is_expired(Update::new(b"1".to_vec()).digest()));
+    }
+
+    #[test]
+    fn forgotten_updates_can_be_inserted_again() {
+        let (mut decorator, clock) = decorator_with_clock(UpdateExpirationMode::DurationMillis(20), 1);
+        decorator.set_tombstones(2, Duration::from_millis(100));
+        let digest = Update::new(b"0".to_vec()).digest().clone();
+        clock.advance(30);
+        decorator.clear_expired();
+        assert_eq!(vec![digest.clone()], decorator.tombstones());
+
+        assert!(decorator.forget_removed(&digest));
+        assert!(!decorator.forget_removed(&digest));
+        assert!(decorator.is_new(&digest));
+        assert!(decorator.tombstones().is_empty());
+        decorator.insert_update(Update::new(b"0".to_vec())).unwrap();
+        assert!(decorator.is_active(&digest));
+    }
+
+    #[test]
+    fn clear_expired_does_not_scan_live_updates() {
+        let mut decorator = decorator_with(UpdateExpirationMode::DurationMillis(3_600_000), 100_000);
+        decorator.clear_expired();
+        assert_eq!(1, decorator.examined);
+        assert_eq!(100_000, decorator.active_count());
+
+        let mut decorator = decorator_with(UpdateExpirationMode::MostRecent(100_000, 0.5), 100_000);
+        decorator.clear_expired();
+        assert_eq!(0, decorator.examined);
+
+        let mut decorator = decorator_with(UpdateExpirationMode::PushCount(10), 100_000);
+        decorator.active_headers_for_push(None);
+        decorator.clear_expired();
+        assert_eq!(0, decorator.examined);
+    }
+
+    #[test]
+    fn undelivered_updates_are_taken_once_in_order() {
+        let mut decorator = decorator_with(UpdateExpirationMode::None, 5);
+        decorator.mark_delivered(Update::new(b"2".to_vec()).digest(), 1);
+        let contents: Vec<Vec<u8>> = decorator.take_undelivered(1, false).iter().map(|update| update.content().to_vec()).collect();
+        assert_eq!(vec![b"0".to_vec(), b"1".to_vec(), b"3".to_vec(), b"4".to_vec()], contents);
+        assert!(decorator.take_undelivered(1, false).is_empty());
+        assert_eq!(5, decorator.take_undelivered(2, false).len());
+    }
+
+    #[test]
+    fn unacknowledged_updates_are_taken_again() {
+        let mut decorator = decorator_with(UpdateExpirationMode::None, 3);
+        assert_eq!(3, decorator.take_undelivered(1, true).len());
+        decorator.mark_acknowledged(Update::new(b"1".to_vec()).digest(), 1);
+        assert!(decorator.is_acknowledged(Update::new(b"1".to_vec()).digest(), 1));
+        // delivered but not acknowledged
+        let contents: Vec<Vec<u8>> = decorator.take_undelivered(1, true).iter().map(|update| update.content().to_vec()).collect();
+        assert_eq!(vec![b"0".to_vec(), b"2".to_vec()], contents);
+        assert!(decorator.take_undelivered(1, false).is_empty());
+    }
+
+    #[test]
+    fn expiration_mode_change_applies_to_new_updates() {
+        let (mut decorator, clock) = decorator_with_clock(UpdateExpirationMode::DurationMillis(20), 2);
+        decorator.set_expiration_mode(UpdateExpirationMode::None);
+        decorator.insert_update(Update::new(b"kept".to_vec())).unwrap();
+        clock.advance(30);
+        decorator.clear_expired();
+        assert_eq!(1, decorator.active_count());
+        assert!(decorator.is_active(Update::new(b"kept".to_vec()).digest()));
+    }
+
+    #[test]
+    fn updates_expiring_by_recency_stay_bounded_after_mode_change() {
+        let (mut decorator, clock) = decorator_with_clock(UpdateExpirationMode::MostRecent(2, 0.0), 4);
+        decorator.set_expiration_mode(UpdateExpirationMode::DurationMillis(20));
+        decorator.insert_update(Update::new(b"new".to_vec())).unwrap();
+        decorator.clear_expired();
+        assert_eq!(3, decorator.active_count());
+        assert!(decorator.is_expired(Update::new(b"1".to_vec()).digest()));
+        assert!(decorator.is_active(Update::new(b"2".to_vec()).digest()));
+
+        clock.advance(30);
+        decorator.clear_expired();
+        assert_eq!(2, decorator.active_count());
+        assert!(decorator.is_expired(Update::new(b"new".to_vec()).digest()));
+        assert!(decorator.is_active(Update::new(b"3".to_vec()).digest()));
+        // nothing is examined once the queue is within the bound
+        decorator.examined = 0;
+        decorator.clear_expired();
+        assert_eq!(0, decorator.examined);
+    }
+
+    #[test]
+    fn expiration_waits_for_start() {
+        let (mut decorator, clock) = decorator_with_clock(UpdateExpirationMode::DurationMillis(20), 0);
+        decorator.set_expiration_start(ExpirationStart::OnFirstPush);
+        decorator.insert_update(Update::new(b"pushed".to_vec())).unwrap();
+        decorator.insert_update(Update::new(b"held".to_vec())).unwrap();
+        clock.advance(30);
+        decorator.clear_expired();
+        assert_eq!(2, decorator.active_count());
+
+        let pushed = Update::new(b"pushed".to_vec()).digest().to_owned();
+        decorator.start_expiration(std::slice::from_ref(&pushed));
+        decorator.clear_expired();
+        assert_eq!(2, decorator.active_count());
+        clock.advance(10);
+        // starting again does not push the deadline back
+        decorator.start_expiration(std::slice::from_ref(&pushed));
+        clock.advance(10);
+        decorator.clear_expired();
+        assert!(decorator.is_expired(&pushed));
+        assert!(decorator.is_active(Update::new(b"held".to_vec()).digest()));
+    }
+
+    #[test]
+    fn clear_expired_removes_due_updates() {
+        let (mut decorator, clock) = decorator_with_clock(UpdateExpirationMode::DurationMillis(20), 10);
+        clock.advance(30);
+        decorator.insert_update(Update::new(b"recent".to_vec())).unwrap();
+        decorator.clear_expired();
+        assert_eq!(1, decorator.active_count());
+        assert!(decorator.is_expired(Update::new(b"0".to_vec()).digest()));
+        assert!(decorator.is_active(Update::new(b"recent".to_vec()).digest()));
+
+        let mut decorator = decorator_with(UpdateExpirationMode::MostRecent(4, 0.5), 10);
+        decorator.clear_expired();
+        assert_eq!(6, decorator.active_count());
+        assert_eq!(4, decorator.examined);
+        assert!(decorator.is_expired(Update::new(b"3".to_vec()).digest()));
+        assert!(decorator.is_active(Update::new(b"4".to_vec()).digest()));
+
+        let mut decorator = decorator_with(UpdateExpirationMode::PushCount(2), 10);
+        decorator.active_headers_for_push(None);
+        decorator.clear_expired();
+        assert_eq!(10, decorator.active_count());
+        decorator.active_headers_for_push(None);
+        decorator.clear_expired();
+        assert_eq!(0, decorator.active_count());
+        assert_eq!(10, decorator.examined);
+    }
+
+    #[test]
+    fn duration_expires_exactly_at_deadline() {
+        let (mut decorator, clock) = decorator_with_clock(UpdateExpirationMode::DurationMillis(20), 1);
+        clock.advance(19);
+        decorator.clear_expired();
+        assert_eq!(1, decorator.active_count());
+        // pushed just before its deadline
+        assert_eq!(1, decorator.active_headers_for_push(None).len());
+
+        clock.advance(1);
+        assert_eq!(0, decorator.clear_expired());
+        assert_eq!(0, decorator.active_count());
+        assert!(decorator.is_expired(Update::new(b"0".to_vec()).digest()));
+    }
+
+    #[test]
+    fn zero_duration_expires_on_first_clear() {
+        let (mut decorator, clock) = decorator_with_clock(UpdateExpirationMode::DurationMillis(0), 3);
+        assert_eq!(3, decorator.active_count());
+        // expired updates are not counted as pushed
+        decorator.active_headers_for_push(None);
+        assert_eq!(3, decorator.clear_expired());
+        assert_eq!(0, decorator.active_count());
+
+        // with a deferred start, the update only expires once started
+        decorator.set_expiration_start(ExpirationStart::OnFirstPush);
+        let update = Update::new(b"deferred".to_vec());
+        decorator.insert_update(update.clone()).unwrap();
+        clock.advance(1_000);
+        decorator.clear_expired();
+        assert!(decorator.is_active(update.digest()));
+        decorator.start_expiration(std::slice::from_ref(update.digest()));
+        decorator.clear_expired();
+        assert!(decorator.is_expired(update.digest()));
+    }
+
+    #[test]
+    fn limited_push_rotates_through_active_updates() {
+        let mut decorator = decorator_with(UpdateExpirationMode::None, 100);
+        let mut advertised = std::collections::HashSet::new();
+        for _ in 0..10 {
+            let headers = decorator.active_headers_for_push(Some(10));
+            assert_eq!(10, headers.len());
+            advertised.extend(headers);
+        }
+        assert_eq!(100, advertised.len());
+
+        // a new update is pushed before the others
+        let update = Update::new(b"new".to_vec());
+        decorator.insert_update(update.clone()).unwrap();
+        assert_eq!(update.digest(), &decorator.active_headers_for_push(Some(10))[0]);
+        assert!(decorator.active_headers_for_push(Some(10)).contains(update.digest()));
+    }
+
+    #[test]
+    fn push_count_applies_to_pushed_headers_only() {
+        let mut decorator = decorator_with(UpdateExpirationMode::PushCount(1), 10);
+        decorator.active_headers_for_push(Some(4));
+        decorator.clear_expired();
+        assert_eq!(6, decorator.active_count());
+        assert!(decorator.is_expired(Update::new(b"9".to_vec()).digest()));
+        assert!(decorator.is_active(Update::new(b"0".to_vec()).digest()));
+    }
+
+    #[test]
+    fn counters_outlive_expiration() {
+        let mut decorator = decorator_with(UpdateExpirationMode::PushCount(1), 1);
+        let digest = Update::new(b"0".to_vec()).digest().to_owned();
+        for peer in 0..MAX_TRACKED_REQUESTERS + 10 {
+            decorator.record_request([&digest], &format!("127.0.0.1:{}", peer));
+        }
+        decorator.record_request([&digest], "127.0.0.1:0");
+        decorator.active_headers_for_push(None);
+        decorator.clear_expired();
+
+        assert!(decorator.is_expired(&digest));
+        let counters = decorator.update_counters(&digest).unwrap();
+        assert_eq!(1, counters.pushes);
+        assert_eq!(MAX_TRACKED_REQUESTERS as u64 + 11, counters.content_requests);
+        assert_eq!(MAX_TRACKED_REQUESTERS, counters.distinct_requesters);
+        assert_eq!(vec![(digest, counters)], decorator.all_counters());
+    }
+
+    #[test]
+    fn first_provider_is_kept() {
+        let mut decorator = decorator_with(UpdateExpirationMode::None, 0);
+        let update = Update::new(b"provided".to_vec());
+        decorator.insert_update_from(update.clone(), Some("127.0.0.1:1".to_owned())).unwrap();
+        assert!(decorator.insert_update_from(update.clone(), Some("127.0.0.1:2".to_owned())).is_err());
+        assert_eq!(Some(UpdateState::Active { provider: Some("127.0.0.1:1".to_owned()) }), decorator.update_state(update.digest()));
+        assert_eq!(vec![update.digest().to_owned()], decorator.updates_from("127.0.0.1:1"));
+        assert!(decorator.updates_from("127.0.0.1:2").is_empty());
+    }
+
+    #[test]
+    fn cached_headers_are_invalidated_on_insert() {
+        let mut decorator = decorator_with(UpdateExpirationMode::None, 3);
+        assert!(decorator.cached_headers().is_none());
+        decorator.publish();
+        let cached = decorator.cached_headers().unwrap();
+        assert_eq!(3, cached.len());
+        assert!(Arc::ptr_eq(&cached, &decorator.cached_headers().unwrap()));
+
+        decorator.insert_update(Update::new(b"new".to_vec())).unwrap();
+        assert!(decorator.cached_headers().is_none());
+        decorator.publish();
+        let cached = decorator.cached_headers().unwrap();
+        assert_eq!(4, cached.len());
+        assert!(cached.contains(Update::new(b"new".to_vec()).digest()));
+    }
+
+    #[test]
+    fn cached_headers_are_invalidated_on_expiry() {
+        let (mut decorator, clock) = decorator_with_clock(UpdateExpirationMode::DurationMillis(20), 3);
+        decorator.publish();
+        assert_eq!(3, decorator.cached_headers().unwrap().len());
+        decorator.clear_expired();
+        assert_eq!(3, decorator.cached_headers().unwrap().len());
+
+        clock.advance(30);
+        decorator.clear_expired();
+        assert!(decorator.cached_headers().is_none());
+        decorator.publish();
+        assert!(decorator.cached_headers().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,116 @@
+use std::error::Error;
+use serde::{Serialize, Deserialize};
+use crate::digest::Digester;
+use crate::update::{OriginSequence, Update};
+
+/// The active updates of a node, as exported by [GossipService::export_updates](crate::GossipService::export_updates)
+#[derive(Serialize, Deserialize)]
+struct UpdatesDocument {
+    updates: Vec<ExportedUpdate>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ExportedUpdate {
+    content: Vec<u8>,
+    digest: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    origin_sequence: Option<OriginSequence>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    origin: Option<String>,
+    #[serde(default)]
+    revocation: bool,
+    /// Peer from which the content was first received, `None` for local updates
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    provider: Option<String>,
+    /// Whether the handler received the update, or acknowledged it with acknowledged delivery
+    #[serde(default)]
+    delivered: bool,
+}
+
+/// An active update with the peer from which it was received and whether the handler is done with it
+#[derive(Clone)]
+pub(crate) struct StoredUpdate {
+    pub update: Update,
+    pub provider: Option<String>,
+    pub delivered: bool,
+}
+
+/// Serializes the active updates of a node to a CBOR document
+pub(crate) fn export(updates: Vec<StoredUpdate>) -> Result<Vec<u8>, Box<dyn Error>> {
+    let document = UpdatesDocument {
+        updates: updates.into_iter()
+            .map(|stored| ExportedUpdate {
+                digest: stored.update.digest().to_owned(),
+                origin_sequence: stored.update.origin_sequence().cloned(),
+                origin: stored.update.origin().map(str::to_owned),
+                revocation: stored.update.is_revocation(),
+                content: stored.update.content().to_vec(),
+                provider: stored.provider,
+                delivered: stored.delivered,
+            })
+            .collect(),
+    };
+    Ok(serde_cbor::to_vec(&document)?)
+}
+
+/// Parses the updates exported by a node. Returns an error if the digest of an update does not match its content.
+pub(crate) fn import(document: &[u8], digester: &dyn Digester) -> Result<Vec<StoredUpdate>, Box<dyn Error>> {
+    let document: UpdatesDocument = serde_cbor::from_slice(document)?;
+    let mut updates = Vec::with_capacity(document.updates.len());
+    for exported in document.updates {
+        // as for received contents, the digest of a revocation covers its origin
+        let update = if exported.revocation {
+            Update::new_revocation(digester, exported.content, exported.origin)
+        }
+        else {
+            let mut update = Update::new_sequenced(digester, exported.content, exported.origin_sequence);
+            update.set_origin(exported.origin);
+            update
+        };
+        if *update.digest() != exported.digest {
+            Err(format!("Digest of update {} does not match its content", exported.digest))?
+        }
+        updates.push(StoredUpdate { update, provider: exported.provider, delivered: exported.delivered });
+    }
+    Ok(updates)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::digest::Blake3Digester;
+
+    #[test]
+    fn updates_are_exported_and_imported() {
+        let sequenced = Update::new_sequenced(&Blake3Digester, b"2".to_vec(), Some(OriginSequence { origin: "127.0.0.1:9000".to_owned(), number: 1 }));
+        let exported = export(vec![
+            StoredUpdate { update: Update::new(b"1".to_vec()), provider: None, delivered: false },
+            StoredUpdate { update: sequenced.clone(), provider: Some("127.0.0.1:9001".to_owned()), delivered: true },
+        ]).unwrap();
+
+        let imported = import(&exported, &Blake3Digester).unwrap();
+        assert_eq!(2, imported.len());
+        assert_eq!(Update::new(b"1".to_vec()).digest(), imported[0].update.digest());
+        assert_eq!((None, false), (imported[0].provider.as_deref(), imported[0].delivered));
+        assert_eq!(sequenced.digest(), imported[1].update.digest());
+        assert_eq!(sequenced.origin_sequence(), imported[1].update.origin_sequence());
+        assert_eq!((Some("127.0.0.1:9001"), true), (imported[1].provider.as_deref(), imported[1].delivered));
+    }
+
+    #[test]
+    fn altered_contents_are_refused() {
+        let document = UpdatesDocument {
+            updates: vec![ExportedUpdate {
+                content: b"altered".to_vec(),
+                digest: Update::new(b"original".to_vec()).digest().to_owned(),
+                origin_sequence: None,
+                origin: None,
+                revocation: false,
+                provider: None,
+                delivered: false,
+            }],
+        };
+        assert!(import(&serde_cbor::to_vec(&document).unwrap(), &Blake3Digester).is_err());
+        assert!(import(b"not a document", &Blake3Digester).is_err());
+    }
+}
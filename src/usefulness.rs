@@ -0,0 +1,193 @@
+use std::collections::HashMap;
+use std::time::Instant;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use crate::peer::Peer;
+use crate::memory::Usage;
+
+/// Weight below which the history of a peer is forgotten
+const FORGOTTEN_WEIGHT: f64 = 0.01;
+
+/// What the node knows about a peer of its view, see [GossipService::peer_info](crate::GossipService::peer_info)
+#[derive(Debug, Clone, PartialEq)]
+pub struct PeerInfo {
+    /// Address of the peer
+    pub address: String,
+    /// Age of the peer in the view
+    pub age: u16,
+    /// Number of recent gossip exchanges initiated with the peer, decayed at each round
+    pub exchanges: f64,
+    /// Number of new digests recently learned from the peer, decayed at each round
+    pub new_digests: f64,
+    /// New digests learned per exchange, `None` without recent exchange
+    pub usefulness: Option<f64>,
+    /// Time at which a message was last received from the peer, `None` if none was received
+    pub last_seen: Option<Instant>,
+}
+
+/// Recent exchanges and new digests of a peer
+#[derive(Default)]
+struct History {
+    exchanges: f64,
+    new_digests: f64,
+}
+
+impl History {
+    fn usefulness(&self) -> Option<f64> {
+        if self.exchanges >= FORGOTTEN_WEIGHT { Some(self.new_digests / self.exchanges) } else { None }
+    }
+}
+
+/// Usefulness of the peers the node gossips with, biasing the selection of the peers of a round toward the peers
+/// from which new digests were recently learned, see
+/// [GossipConfig::set_useful_peer_bias](crate::GossipConfig::set_useful_peer_bias)
+pub(crate) struct PeerUsefulness {
+    histories: HashMap<String, History>,
+    rng: StdRng,
+}
+
+impl PeerUsefulness {
+    pub fn new() -> Self {
+        Self::with_rng(StdRng::from_entropy())
+    }
+
+    fn with_rng(rng: StdRng) -> Self {
+        PeerUsefulness {
+            histories: HashMap::new(),
+            rng,
+        }
+    }
+
+    pub fn memory(&self) -> Usage {
+        Usage::of_strings(self.histories.keys())
+    }
+
+    /// Records that a gossip exchange was initiated with a peer
+    pub fn record_exchange(&mut self, peer: &str) {
+        self.histories.entry(peer.to_owned()).or_default().exchanges += 1.0;
+    }
+
+    /// Records the digests first learned from a peer
+    pub fn record_new_digests(&mut self, peer: &str, count: usize) {
+        if count > 0 {
+            self.histories.entry(peer.to_owned()).or_default().new_digests += count as f64;
+        }
+    }
+
+    /// Ages the history of the peers at the end of a round, so that the bias toward a peer does not last once it
+    /// stops being useful
+    ///
+    /// # Arguments
+    ///
+    /// * `decay` - Factor applied to the exchanges and new digests of each peer
+    pub fn decay(&mut self, decay: f64) {
+        self.histories.retain(|_, history| {
+            history.exchanges *= decay;
+            history.new_digests *= decay;
+            history.exchanges >= FORGOTTEN_WEIGHT || history.new_digests >= FORGOTTEN_WEIGHT
+        });
+    }
+
+    /// Selects the peers of a round. The peers chosen by the sampler are kept, except that with probability `bias`,
+    /// the most useful peer of the view is moved first, so that it receives the pushed headers.
+    ///
+    /// # Arguments
+    ///
+    /// * `sampled` - The peers chosen by the sampler
+    /// * `view` - The peers of the view
+    /// * `bias` - Probability of preferring the most useful peer
+    pub fn select(&mut self, mut sampled: Vec<Peer>, view: &[Peer], bias: f64) -> Vec<Peer> {
+        if bias <= 0.0 || !self.rng.gen_bool(bias.min(1.0)) {
+            return sampled;
+        }
+        let most_useful = view.iter()
+            .filter_map(|peer| self.histories.get(peer.address()).and_then(History::usefulness).map(|usefulness| (peer, usefulness)))
+            .filter(|(_, usefulness)| *usefulness > 0.0)
+            .max_by(|(_, a), (_, b)| a.total_cmp(b))
+            .map(|(peer, _)| peer.clone());
+        if let Some(peer) = most_useful {
+            let fanout = sampled.len().max(1);
+            sampled.retain(|sampled| *sampled != peer);
+            sampled.insert(0, peer);
+            sampled.truncate(fanout);
+        }
+        sampled
+    }
+
+    pub fn clear(&mut self) {
+        self.histories.clear();
+    }
+
+    /// Returns what is known about the peers of the view
+    ///
+    /// # Arguments
+    ///
+    /// * `view` - The peers of the view
+    /// * `last_seen` - Returns the time at which a message was last received from a peer
+    pub fn peer_info<F>(&self, view: &[Peer], last_seen: F) -> Vec<PeerInfo>
+    where F: Fn(&str) -> Option<Instant>
+    {
+        view.iter()
+            .map(|peer| {
+                let history = self.histories.get(peer.address());
+                PeerInfo {
+                    address: peer.address().to_owned(),
+                    age: peer.age(),
+                    exchanges: history.map_or(0.0, |history| history.exchanges),
+                    new_digests: history.map_or(0.0, |history| history.new_digests),
+                    usefulness: history.and_then(History::usefulness),
+                    last_seen: last_seen(peer.address()),
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::seq::SliceRandom;
+
+    /// Runs 100 rounds in which only the second of three peers has new digests, and returns how many times each
+    /// peer received the pushed headers
+    fn contacts(bias: f64) -> [usize; 3] {
+        let view: Vec<Peer> = (1..=3).map(|port| Peer::try_new(&format!("127.0.0.1:{}", port)).unwrap()).collect();
+        let mut sampler = StdRng::seed_from_u64(7);
+        let mut usefulness = PeerUsefulness::with_rng(StdRng::seed_from_u64(11));
+        let mut contacts = [0; 3];
+        for _ in 0..100 {
+            let sampled = vec![view.choose(&mut sampler).unwrap().clone()];
+            let selected = usefulness.select(sampled, &view, bias);
+            let index = view.iter().position(|peer| *peer == selected[0]).unwrap();
+            contacts[index] += 1;
+            usefulness.record_exchange(selected[0].address());
+            usefulness.record_new_digests(selected[0].address(), if index == 1 { 1 } else { 0 });
+            usefulness.decay(0.95);
+        }
+        contacts
+    }
+
+    #[test]
+    fn useful_peer_is_contacted_more_often() {
+        let uniform = contacts(0.0);
+        let biased = contacts(0.5);
+        assert!(uniform[1] < 45, "{:?}", uniform);
+        assert!(biased[1] > 60, "{:?}", biased);
+        // the sampler is still followed
+        assert!(biased[0] > 0 && biased[2] > 0, "{:?}", biased);
+    }
+
+    #[test]
+    fn history_decays() {
+        let mut usefulness = PeerUsefulness::with_rng(StdRng::seed_from_u64(0));
+        let peer = Peer::try_new("127.0.0.1:1").unwrap();
+        usefulness.record_exchange(peer.address());
+        usefulness.record_new_digests(peer.address(), 3);
+        assert_eq!(Some(3.0), usefulness.peer_info(std::slice::from_ref(&peer), |_| None)[0].usefulness);
+        for _ in 0..100 {
+            usefulness.decay(0.9);
+        }
+        assert_eq!(None, usefulness.peer_info(&[peer], |_| None)[0].usefulness);
+        assert!(usefulness.histories.is_empty());
+    }
+}
@@ -0,0 +1,110 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+use std::net::SocketAddr;
+use std::time::Duration;
+use serde::{Serialize, Deserialize};
+use crate::peer::Peer;
+use crate::logging::targets::SAMPLING;
+
+/// The view of a node, as exported by [GossipService::export_view](crate::GossipService::export_view)
+#[derive(Serialize, Deserialize)]
+struct ViewDocument {
+    /// Address of the node
+    node: String,
+    peers: Vec<ExportedPeer>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ExportedPeer {
+    address: String,
+    #[serde(default)]
+    age: u16,
+    /// Milliseconds since the peer was last known to be fresh, if known
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    staleness_millis: Option<u64>,
+}
+
+/// Serializes the view of a node to a JSON document
+pub(crate) fn export(node: &SocketAddr, peers: &[Peer]) -> Result<String, Box<dyn Error>> {
+    let document = ViewDocument {
+        node: node.to_string(),
+        peers: peers.iter()
+            .map(|peer| ExportedPeer {
+                address: peer.address().to_owned(),
+                age: peer.age(),
+                staleness_millis: peer.staleness().map(|staleness| staleness.as_millis() as u64),
+            })
+            .collect(),
+    };
+    Ok(serde_json::to_string_pretty(&document)?)
+}
+
+/// Parses a view exported by a node. Returns an error if an address is invalid; the address of the
+/// importing node and duplicates are skipped, and only the first `max_peers` peers are kept.
+pub(crate) fn import(node: &SocketAddr, json: &str, max_peers: Option<usize>) -> Result<Vec<Peer>, Box<dyn Error>> {
+    let document: ViewDocument = serde_json::from_str(json)?;
+    let mut peers: Vec<Peer> = Vec::with_capacity(document.peers.len());
+    for exported in document.peers {
+        let address = match exported.address.parse::<SocketAddr>() {
+            Ok(address) => address,
+            Err(e) => Err(format!("Invalid peer address {}: {}", exported.address, e))?,
+        };
+        if address == *node || peers.iter().any(|peer| peer.address() == exported.address) {
+            continue;
+        }
+        peers.push(Peer::restore(exported.address, exported.age, exported.staleness_millis.map(Duration::from_millis)));
+    }
+    if let Some(max_peers) = max_peers.filter(|max_peers| peers.len() > *max_peers) {
+        node_log!(Warn, SAMPLING, "Imported view truncated from {} to {} peers", peers.len(), max_peers);
+        peers.truncate(max_peers);
+    }
+    Ok(peers)
+}
+
+/// Counts describing the view of a node, see [GossipService::view_summary](crate::GossipService::view_summary)
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ViewSummary {
+    /// Number of peers in the view
+    pub peer_count: usize,
+    /// Number of peers of each age
+    pub ages: BTreeMap<u16, usize>,
+    /// Number of peers whose freshness is unknown, received from older nodes
+    pub unknown_staleness: usize,
+    /// Longest time since a peer of the view was last known to be fresh
+    pub max_staleness: Option<Duration>,
+}
+
+impl ViewSummary {
+    pub(crate) fn new(peers: &[Peer]) -> Self {
+        let mut summary = ViewSummary {
+            peer_count: peers.len(),
+            ..Default::default()
+        };
+        for peer in peers {
+            *summary.ages.entry(peer.age()).or_default() += 1;
+            match peer.staleness() {
+                Some(staleness) => summary.max_staleness = summary.max_staleness.max(Some(staleness)),
+                None => summary.unknown_staleness += 1,
+            }
+        }
+        summary
+    }
+}
+
+impl fmt::Display for ViewSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} peers", self.peer_count)?;
+        if !self.ages.is_empty() {
+            let ages: Vec<String> = self.ages.iter().map(|(age, count)| format!("{}: {}", age, count)).collect();
+            write!(f, ", ages {{{}}}", ages.join(", "))?;
+        }
+        if let Some(staleness) = self.max_staleness {
+            write!(f, ", stalest {} ms", staleness.as_millis())?;
+        }
+        if self.unknown_staleness > 0 {
+            write!(f, ", {} of unknown staleness", self.unknown_staleness)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,130 @@
+use std::collections::{HashSet, VecDeque};
+use crate::peer::Peer;
+
+/// Number of peer sampling cycles over which the churn and the distinct peers of the view are computed
+pub const SAMPLING_STATS_WINDOW: usize = 10;
+
+/// Indicators of the quality of the view maintained by peer sampling, see
+/// [GossipService::sampling_stats](crate::GossipService::sampling_stats).
+///
+/// The in-degree of the nodes, which shows whether the overlay is balanced, cannot be measured locally: these
+/// are proxies for it. A healthy view keeps a low average age, replaces part of its peers at each cycle, and sees
+/// many more distinct peers than it holds over a few cycles.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SamplingStats {
+    /// Number of sampling cycles since start
+    pub cycles: u64,
+    /// Average age of the peers of the view at the end of the last cycle
+    pub average_age: f64,
+    /// Average fraction of the view replaced between two consecutive cycles, over the last
+    /// [SAMPLING_STATS_WINDOW] cycles
+    pub churn: f64,
+    /// Number of distinct peers that were in the view at the end of one of the last [SAMPLING_STATS_WINDOW] cycles
+    pub distinct_peers: usize,
+    /// Number of cycles in which no peer could be selected for an exchange, because the view was empty
+    pub empty_selections: u64,
+    /// Address of the peer selected for an exchange in the last cycle that selected one
+    pub last_contacted: Option<String>,
+}
+
+/// Statistics of the view, updated at the end of each sampling cycle
+pub(crate) struct ViewStats {
+    cycles: u64,
+    empty_selections: u64,
+    average_age: f64,
+    last_contacted: Option<String>,
+    /// Addresses of the peers of the view at the end of each of the last cycles, the most recent last
+    views: VecDeque<HashSet<String>>,
+    /// Fraction of the view replaced at each of the last cycles
+    churn: VecDeque<f64>,
+}
+
+impl ViewStats {
+    pub fn new() -> Self {
+        ViewStats {
+            cycles: 0,
+            empty_selections: 0,
+            average_age: 0.0,
+            last_contacted: None,
+            views: VecDeque::with_capacity(SAMPLING_STATS_WINDOW + 1),
+            churn: VecDeque::with_capacity(SAMPLING_STATS_WINDOW + 1),
+        }
+    }
+
+    /// Records the view at the end of a cycle
+    ///
+    /// # Arguments
+    ///
+    /// * `peers` - The peers of the view
+    /// * `selected` - The peer selected for an exchange, if any
+    pub fn record_cycle(&mut self, peers: &[Peer], selected: Option<&str>) {
+        self.cycles += 1;
+        match selected {
+            Some(selected) => self.last_contacted = Some(selected.to_owned()),
+            None => self.empty_selections += 1,
+        }
+        self.average_age = if peers.is_empty() {
+            0.0
+        }
+        else {
+            peers.iter().map(|peer| peer.age() as f64).sum::<f64>() / peers.len() as f64
+        };
+        let view: HashSet<String> = peers.iter().map(|peer| peer.address().to_owned()).collect();
+        if let Some(previous) = self.views.back() {
+            let replaced = view.iter().filter(|address| !previous.contains(*address)).count();
+            let churn = if view.is_empty() { 0.0 } else { replaced as f64 / view.len() as f64 };
+            if self.churn.len() == SAMPLING_STATS_WINDOW {
+                self.churn.pop_front();
+            }
+            self.churn.push_back(churn);
+        }
+        if self.views.len() == SAMPLING_STATS_WINDOW {
+            self.views.pop_front();
+        }
+        self.views.push_back(view);
+    }
+
+    pub fn stats(&self) -> SamplingStats {
+        SamplingStats {
+            cycles: self.cycles,
+            average_age: self.average_age,
+            churn: if self.churn.is_empty() { 0.0 } else { self.churn.iter().sum::<f64>() / self.churn.len() as f64 },
+            distinct_peers: self.views.iter().flatten().collect::<HashSet<_>>().len(),
+            empty_selections: self.empty_selections,
+            last_contacted: self.last_contacted.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn view(addresses: &[u16]) -> Vec<Peer> {
+        addresses.iter().map(|port| Peer::try_new(&format!("127.0.0.1:{}", port)).unwrap()).collect()
+    }
+
+    #[test]
+    fn churn_and_distinct_peers_follow_the_window() {
+        let mut stats = ViewStats::new();
+        stats.record_cycle(&[], None);
+        stats.record_cycle(&view(&[1, 2, 3, 4]), Some("127.0.0.1:3"));
+        // half of the view is replaced
+        stats.record_cycle(&view(&[1, 2, 5, 6]), Some("127.0.0.1:1"));
+        let current = stats.stats();
+        assert_eq!(3, current.cycles);
+        assert_eq!(1, current.empty_selections);
+        assert_eq!(Some("127.0.0.1:1".to_owned()), current.last_contacted);
+        assert_eq!(6, current.distinct_peers);
+        assert_eq!((1.0 + 0.5) / 2.0, current.churn);
+
+        // a stable view forgets the peers that left the window
+        for _ in 0..SAMPLING_STATS_WINDOW {
+            stats.record_cycle(&view(&[1, 2, 5, 6]), Some("127.0.0.1:5"));
+        }
+        let current = stats.stats();
+        assert_eq!(4, current.distinct_peers);
+        assert_eq!(0.0, current.churn);
+        assert_eq!(0.0, current.average_age);
+    }
+}
@@ -0,0 +1,67 @@
+use std::time::{Duration, Instant};
+
+/// Minimum time between two warnings about a condition that lasts, such as an empty view
+pub(crate) const WARNING_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Warning about a condition that can last for many rounds. It is logged when the condition appears, then at most
+/// once per interval until the condition ends, so that an isolated node does not flood the logs.
+pub(crate) struct RateLimitedWarning {
+    interval: Duration,
+    /// Time of the last warning, while the condition lasts
+    last_logged: Option<Instant>,
+    /// Occurrences of the condition since the last warning
+    suppressed: u64,
+}
+
+impl RateLimitedWarning {
+    pub fn new(interval: Duration) -> Self {
+        RateLimitedWarning {
+            interval,
+            last_logged: None,
+            suppressed: 0,
+        }
+    }
+
+    /// Records an occurrence of the condition. Returns the number of occurrences suppressed since the last warning
+    /// if this one must be logged, `None` otherwise.
+    pub fn occurred(&mut self) -> Option<u64> {
+        match self.last_logged {
+            Some(last_logged) if last_logged.elapsed() < self.interval => {
+                self.suppressed += 1;
+                None
+            }
+            _ => {
+                self.last_logged = Some(Instant::now());
+                Some(std::mem::take(&mut self.suppressed))
+            }
+        }
+    }
+
+    /// Records that the condition is over, returning whether it was ongoing
+    pub fn ended(&mut self) -> bool {
+        self.suppressed = 0;
+        self.last_logged.take().is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn warnings_are_rate_limited_until_the_condition_ends() {
+        let mut warning = RateLimitedWarning::new(Duration::from_millis(100));
+        assert!(!warning.ended());
+        assert_eq!(Some(0), warning.occurred());
+        assert_eq!(None, warning.occurred());
+        assert_eq!(None, warning.occurred());
+        std::thread::sleep(Duration::from_millis(120));
+        assert_eq!(Some(2), warning.occurred());
+        assert_eq!(None, warning.occurred());
+
+        // the next occurrence is logged right away
+        assert!(warning.ended());
+        assert!(!warning.ended());
+        assert_eq!(Some(0), warning.occurred());
+    }
+}
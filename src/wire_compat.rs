@@ -0,0 +1,131 @@
+//! Golden files of the wire format, in `tests/fixtures/wire/<version>/<message>.bin`.
+//!
+//! The canonical messages must serialize to the fixtures of [WIRE_FORMAT_VERSION], and the fixtures of every
+//! version, including those recorded by older releases, must still be parsed. A change of the format is announced
+//! by increasing the version and recording its fixtures, with the `GOSSIP_REGENERATE_WIRE_FIXTURES` environment
+//! variable set; the fixtures of the previous versions are kept.
+//!
+//! `v0` holds the messages of release 0.0.4, before the optional fields were added.
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use crate::estimate::SizeEstimator;
+use crate::message::{Message, NoopMessage, MASK_MESSAGE_PROTOCOL, MESSAGE_FLAG_CLUSTER, MESSAGE_PROTOCOL_CONTENT_MESSAGE, MESSAGE_PROTOCOL_HEADER_MESSAGE, MESSAGE_PROTOCOL_NOOP_MESSAGE, MESSAGE_PROTOCOL_PING_MESSAGE, MESSAGE_PROTOCOL_PONG_MESSAGE, MESSAGE_PROTOCOL_SAMPLING_MESSAGE};
+use crate::message::gossip::{ContentMessage, HeaderMessage, PingMessage, PongMessage};
+use crate::message::sampling::PeerSamplingMessage;
+use crate::network::to_wire_bytes;
+use crate::peer::Peer;
+use crate::update::OriginSequence;
+
+/// Version of the format produced by the current code
+const WIRE_FORMAT_VERSION: &str = "v1";
+
+/// Environment variable requesting the fixtures of the current version to be recorded
+const REGENERATE_VARIABLE: &str = "GOSSIP_REGENERATE_WIRE_FIXTURES";
+
+/// Sender of the canonical messages
+const SENDER: &str = "127.0.0.1:9000";
+
+fn fixtures_dir() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests").join("fixtures").join("wire")
+}
+
+fn view() -> Vec<Peer> {
+    vec![
+        Peer::with_fields("127.0.0.1:9001", 0, None, None),
+        Peer::with_fields("127.0.0.1:9002", 2, Some(1_600_000_000_000), Some(b"zone-a".to_vec())),
+    ]
+}
+
+/// Returns the canonical instance of each message, serialized as sent on the wire. Maps hold a single entry,
+/// since their order is not deterministic.
+fn canonical_messages() -> Vec<(&'static str, Vec<u8>)> {
+    let mut header_request = HeaderMessage::new_request(SENDER.to_owned(), true);
+    header_request.set_headers(vec!["digest-1".to_owned(), "digest-2".to_owned()]);
+    let mut header_response = HeaderMessage::new_response(SENDER.to_owned()).with_cluster(Some("cluster".to_owned()));
+    header_response.set_headers(vec!["digest-3".to_owned()]);
+    header_response.set_relayed(vec!["digest-4".to_owned()]);
+    header_response.set_tombstones(vec!["digest-5".to_owned()]);
+    header_response.set_observed(Some("127.0.0.1:9001".parse().unwrap()));
+    let mut content_response = ContentMessage::new_response(SENDER.to_owned(), HashMap::from([("digest-1".to_owned(), b"content".to_vec())]));
+    content_response.set_redirects(HashMap::from([("digest-2".to_owned(), "127.0.0.1:9002".to_owned())]));
+    content_response.set_sequences(HashMap::from([("digest-1".to_owned(), OriginSequence { origin: SENDER.to_owned(), number: 7 })]));
+    let sampling_response = PeerSamplingMessage::new_response(SENDER.to_owned(), Some(view()))
+        .with_cluster(Some("cluster".to_owned()))
+        .with_estimate(Some(SizeEstimator::new(SENDER, 10).state()));
+
+    vec![
+        ("sampling_request", to_wire_bytes(&PeerSamplingMessage::new_request(SENDER.to_owned(), Some(view())), None).unwrap()),
+        ("sampling_response", to_wire_bytes(&sampling_response, Some(3)).unwrap()),
+        ("sampling_leave", to_wire_bytes(&PeerSamplingMessage::new_leave(SENDER.to_owned()), None).unwrap()),
+        ("header_request", to_wire_bytes(&header_request, None).unwrap()),
+        ("header_response", to_wire_bytes(&header_response, Some(3)).unwrap()),
+        ("content_request", to_wire_bytes(&ContentMessage::new_request(SENDER.to_owned(), HashMap::from([("digest-1".to_owned(), Vec::new())])), None).unwrap()),
+        ("content_response", to_wire_bytes(&content_response, None).unwrap()),
+        ("ping", to_wire_bytes(&PingMessage::new(SENDER.to_owned()), None).unwrap()),
+        ("pong", to_wire_bytes(&PongMessage::new(SENDER.to_owned()), None).unwrap()),
+        ("noop", to_wire_bytes(&NoopMessage, None).unwrap()),
+    ]
+}
+
+/// Parses a message as received on the wire, checking the fields shared by the canonical messages of all versions
+fn parse(name: &str, bytes: &[u8]) {
+    let payload = if bytes[0] & MESSAGE_FLAG_CLUSTER != 0 { &bytes[2..] } else { &bytes[1..] };
+    match bytes[0] & MASK_MESSAGE_PROTOCOL {
+        MESSAGE_PROTOCOL_SAMPLING_MESSAGE => {
+            let message = PeerSamplingMessage::from_bytes(payload).unwrap();
+            assert_eq!(SENDER, message.sender());
+            let view = message.view().clone().unwrap_or_default();
+            assert!(message.is_leaving() || view.iter().any(|peer| peer.address() == "127.0.0.1:9001"), "{}: {:?}", name, view);
+        }
+        MESSAGE_PROTOCOL_HEADER_MESSAGE => {
+            let message = HeaderMessage::from_bytes(payload).unwrap();
+            assert_eq!(SENDER, message.sender());
+            assert!(!message.headers().is_empty(), "{}", name);
+        }
+        MESSAGE_PROTOCOL_CONTENT_MESSAGE => {
+            let message = ContentMessage::from_bytes(payload).unwrap();
+            assert_eq!(SENDER, message.sender());
+            assert!(message.content_ref().contains_key("digest-1"), "{}", name);
+        }
+        MESSAGE_PROTOCOL_PING_MESSAGE => assert_eq!(SENDER, PingMessage::from_bytes(payload).unwrap().sender()),
+        MESSAGE_PROTOCOL_PONG_MESSAGE => assert_eq!(SENDER, PongMessage::from_bytes(payload).unwrap().sender()),
+        MESSAGE_PROTOCOL_NOOP_MESSAGE => (),
+        protocol => panic!("{}: unknown protocol {}", name, protocol),
+    }
+}
+
+#[test]
+fn current_format_matches_fixtures() {
+    let dir = fixtures_dir().join(WIRE_FORMAT_VERSION);
+    if std::env::var_os(REGENERATE_VARIABLE).is_some() {
+        std::fs::create_dir_all(&dir).unwrap();
+        for (name, bytes) in canonical_messages() {
+            std::fs::write(dir.join(format!("{}.bin", name)), bytes).unwrap();
+        }
+    }
+    for (name, bytes) in canonical_messages() {
+        let path = dir.join(format!("{}.bin", name));
+        let recorded = std::fs::read(&path).unwrap_or_else(|e| panic!("missing fixture {}: {}", path.display(), e));
+        assert!(recorded == bytes,
+            "the wire format of {} changed: if intended, increase WIRE_FORMAT_VERSION and record the fixtures with {} set\nrecorded: {:02x?}\ncurrent:  {:02x?}",
+            name, REGENERATE_VARIABLE, recorded, bytes);
+    }
+}
+
+#[test]
+fn fixtures_of_all_versions_are_parsed() {
+    let mut versions: Vec<PathBuf> = std::fs::read_dir(fixtures_dir()).unwrap().map(|entry| entry.unwrap().path()).collect();
+    versions.sort();
+    assert!(versions.iter().any(|version| version.ends_with(WIRE_FORMAT_VERSION)));
+    for version in versions {
+        let mut count = 0;
+        for entry in std::fs::read_dir(&version).unwrap() {
+            let path = entry.unwrap().path();
+            let name = path.display().to_string();
+            parse(&name, &std::fs::read(&path).unwrap());
+            count += 1;
+        }
+        assert!(count > 0, "no fixture in {}", version.display());
+    }
+}
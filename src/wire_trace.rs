@@ -0,0 +1,116 @@
+use std::fmt::Write as _;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, RwLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+use crate::logging::targets::MONITOR;
+
+/// Direction of a traced message
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    /// A message received from a peer
+    Inbound,
+    /// A message sent to a peer
+    Outbound,
+}
+
+/// Callback receiving the raw bytes of the messages, see [GossipService::set_wire_trace](crate::GossipService::set_wire_trace).
+/// It is called with the direction of the message, the address of the peer and the bytes on the wire, protocol byte
+/// and cluster id included, from the thread sending or receiving the message.
+pub type WireTracer = Box<dyn Fn(Direction, &SocketAddr, &[u8]) + Send + Sync>;
+
+/// Number of bytes on each line of a hexdump
+const HEXDUMP_LINE: usize = 16;
+
+/// Returns a tracer appending a timestamped hexdump of every message to a file, created or truncated.
+///
+/// Each message starts with a comment line holding the time since the Unix epoch in microseconds, the direction,
+/// the peer and the length, followed by lines of 16 bytes preceded by their offset, as read by `text2pcap`.
+///
+/// # Arguments
+///
+/// * `path` - The file to write
+pub fn hexdump_tracer<P: AsRef<Path>>(path: P) -> std::io::Result<WireTracer> {
+    let file = Mutex::new(BufWriter::new(File::create(path)?));
+    Ok(Box::new(move |direction, peer, bytes| {
+        let dump = hexdump(SystemTime::now(), direction, peer, bytes);
+        let mut file = file.lock().unwrap();
+        if let Err(e) = file.write_all(dump.as_bytes()).and_then(|_| file.flush()) {
+            node_log!(Warn, MONITOR, "Could not write wire trace: {}", e);
+        }
+    }))
+}
+
+/// Formats a message as a timestamped hexdump
+fn hexdump(time: SystemTime, direction: Direction, peer: &SocketAddr, bytes: &[u8]) -> String {
+    let micros = time.duration_since(UNIX_EPOCH).unwrap_or_default().as_micros();
+    let direction = match direction {
+        Direction::Inbound => "inbound from",
+        Direction::Outbound => "outbound to",
+    };
+    let mut dump = format!("# {}.{:06} {} {} {} bytes\n", micros / 1_000_000, micros % 1_000_000, direction, peer, bytes.len());
+    for (line, chunk) in bytes.chunks(HEXDUMP_LINE).enumerate() {
+        let _ = write!(dump, "{:06x}", line * HEXDUMP_LINE);
+        for byte in chunk {
+            let _ = write!(dump, " {:02x}", byte);
+        }
+        dump.push('\n');
+    }
+    dump.push('\n');
+    dump
+}
+
+/// The tracer of a service, checked before each message is traced
+pub(crate) struct WireTrace {
+    /// Whether a tracer is installed, read without locking
+    enabled: AtomicBool,
+    tracer: RwLock<Option<WireTracer>>,
+}
+
+impl WireTrace {
+    pub fn new() -> Self {
+        WireTrace {
+            enabled: AtomicBool::new(false),
+            tracer: RwLock::new(None),
+        }
+    }
+
+    /// Installs or removes the tracer
+    pub fn set(&self, tracer: Option<WireTracer>) {
+        let mut current = self.tracer.write().unwrap();
+        self.enabled.store(tracer.is_some(), Ordering::SeqCst);
+        *current = tracer;
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    /// Hands a message to the tracer, if any
+    pub fn trace(&self, direction: Direction, peer: &SocketAddr, bytes: &[u8]) {
+        if self.is_enabled() {
+            if let Some(tracer) = self.tracer.read().unwrap().as_ref() {
+                tracer(direction, peer, bytes);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn messages_are_dumped_in_lines_of_16_bytes() {
+        let time = UNIX_EPOCH + Duration::from_micros(1_500_000_042);
+        let bytes: Vec<u8> = (0..20).collect();
+        let dump = hexdump(time, Direction::Outbound, &"127.0.0.1:9000".parse().unwrap(), &bytes);
+        assert_eq!("# 1500.000042 outbound to 127.0.0.1:9000 20 bytes\n\
+            000000 00 01 02 03 04 05 06 07 08 09 0a 0b 0c 0d 0e 0f\n\
+            000010 10 11 12 13\n\n", dump);
+    }
+}
@@ -0,0 +1,112 @@
+mod common;
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use gossip::{GossipConfig, GossipService, HandlerError, Update, UpdateExpirationMode, UpdateHandler};
+use common::{start_static_node, static_node, wait_for, NoopUpdateHandler};
+
+/// Fails while `failing` is set, and records the contents of every attempted and acknowledged delivery
+#[derive(Clone, Default)]
+struct AckHandler {
+    failing: Arc<AtomicBool>,
+    attempts: Arc<Mutex<Vec<Vec<u8>>>>,
+    acknowledged: Arc<Mutex<Vec<Vec<u8>>>>,
+}
+impl UpdateHandler for AckHandler {
+    fn on_update(&self, _update: Update) {
+        panic!("updates must be delivered with try_on_update");
+    }
+
+    fn try_on_update(&self, update: Update) -> Result<(), HandlerError> {
+        self.attempts.lock().unwrap().push(update.content().to_vec());
+        if self.failing.swap(false, Ordering::SeqCst) {
+            return Err(HandlerError::new("storage unavailable"));
+        }
+        self.acknowledged.lock().unwrap().push(update.content().to_vec());
+        Ok(())
+    }
+}
+
+fn gossip_config(retry_delay: u64) -> GossipConfig {
+    let mut gossip_config = GossipConfig::new(true, true, 100, UpdateExpirationMode::None);
+    gossip_config.set_acknowledged_delivery(true, retry_delay);
+    gossip_config
+}
+
+fn start_source(port: u16, peer: u16) -> GossipService<NoopUpdateHandler> {
+    start_static_node(port, &[peer], gossip_config(100), NoopUpdateHandler)
+}
+
+fn start_receiver(port: u16, peer: u16, retry_delay: u64, handler: AckHandler) -> GossipService<AckHandler> {
+    start_static_node(port, &[peer], gossip_config(retry_delay), handler)
+}
+
+#[test]
+fn failed_delivery_is_retried() {
+    let source = start_source(9000, 9001);
+    let handler = AckHandler::default();
+    handler.failing.store(true, Ordering::SeqCst);
+    let receiver = start_receiver(9001, 9000, 100, handler.clone());
+
+    source.submit(b"update".to_vec()).unwrap();
+    wait_for(|| !handler.acknowledged.lock().unwrap().is_empty(), "update was not acknowledged");
+    assert_eq!(vec![b"update".to_vec(), b"update".to_vec()], *handler.attempts.lock().unwrap());
+    assert_eq!(1, receiver.stats().delivery_retries);
+
+    // acknowledged updates are not replayed to the same handler
+    assert_eq!(0, receiver.replay_active().unwrap());
+
+    receiver.shutdown().unwrap();
+    source.shutdown().unwrap();
+}
+
+#[test]
+fn unacknowledged_updates_are_delivered_again() {
+    let source = start_source(9002, 9003);
+    let handler = AckHandler::default();
+    handler.failing.store(true, Ordering::SeqCst);
+    // the failed delivery is not retried during the test
+    let receiver = start_receiver(9003, 9002, 60_000, handler.clone());
+
+    source.submit(b"update".to_vec()).unwrap();
+    wait_for(|| !handler.attempts.lock().unwrap().is_empty(), "update was not delivered");
+    assert!(handler.acknowledged.lock().unwrap().is_empty());
+
+    // a replay delivers the updates not acknowledged by the handler
+    assert_eq!(1, receiver.replay_active().unwrap());
+    wait_for(|| !handler.acknowledged.lock().unwrap().is_empty(), "update was not delivered again");
+
+    receiver.shutdown().unwrap();
+    source.shutdown().unwrap();
+}
+
+#[test]
+fn persisted_unacknowledged_updates_are_delivered_after_restart() {
+    let source = start_source(9004, 9005);
+    let handler = AckHandler::default();
+    handler.failing.store(true, Ordering::SeqCst);
+    let receiver = start_receiver(9005, 9004, 60_000, handler.clone());
+
+    // the first delivery fails and is not retried during the test, the second one is acknowledged
+    source.submit(b"failed".to_vec()).unwrap();
+    wait_for(|| !handler.attempts.lock().unwrap().is_empty(), "update was not delivered");
+    source.submit(b"acknowledged".to_vec()).unwrap();
+    wait_for(|| !handler.acknowledged.lock().unwrap().is_empty(), "update was not acknowledged");
+    let persisted = receiver.export_updates().unwrap();
+    receiver.shutdown().unwrap();
+    // no peer sends the updates again
+    source.shutdown().unwrap();
+
+    let handler = AckHandler::default();
+    let mut receiver: GossipService<AckHandler> = static_node(9005, &[9004], gossip_config(60_000));
+    assert_eq!(2, receiver.import_updates(&persisted).unwrap());
+    receiver.start_isolated(Box::new(handler.clone())).unwrap();
+    wait_for(|| !handler.acknowledged.lock().unwrap().is_empty(), "update was not delivered after restart");
+    assert_eq!(2, receiver.active_digests().len());
+    std::thread::sleep(std::time::Duration::from_millis(200));
+    assert_eq!(vec![b"failed".to_vec()], *handler.attempts.lock().unwrap());
+
+    // updates are only imported before start
+    assert!(receiver.import_updates(&persisted).is_err());
+    receiver.shutdown().unwrap();
+}
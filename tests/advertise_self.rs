@@ -0,0 +1,45 @@
+mod common;
+
+use std::time::Duration;
+use gossip::{GossipConfig, GossipService, PeerSamplingConfig, UpdateExpirationMode};
+use common::{start_sampling_node, wait_for_within, NoopUpdateHandler};
+
+const CLUSTER: [u16; 4] = [9000, 9001, 9002, 9003];
+const CLIENT: u16 = 9004;
+
+fn start_node(port: u16, sampling_config: PeerSamplingConfig, contacts: &[u16]) -> GossipService<NoopUpdateHandler> {
+    start_sampling_node(port, contacts, sampling_config, GossipConfig::new(true, true, 100, UpdateExpirationMode::None), NoopUpdateHandler)
+}
+
+#[test]
+fn node_not_advertising_itself_stays_out_of_views() {
+    let cluster: Vec<_> = CLUSTER.iter().enumerate()
+        .map(|(i, port)| start_node(*port, PeerSamplingConfig::new(true, true, 100, 10, 1, 4), if i == 0 { &[] } else { &CLUSTER[..1] }))
+        .collect();
+    let mut sampling_config = PeerSamplingConfig::new(true, true, 100, 10, 1, 4);
+    sampling_config.set_advertise_self(false);
+    let client = start_node(CLIENT, sampling_config, &CLUSTER[..1]);
+    let client_address = client.address().to_string();
+
+    let updates: Vec<Vec<u8>> = cluster.iter().map(|node| format!("update from {}", node.address()).into_bytes()).collect();
+    for (node, update) in cluster.iter().zip(&updates) {
+        node.submit(update.clone()).unwrap();
+    }
+
+    let client_in_views = || cluster.iter().any(|node| node.peers().iter().any(|peer| peer.address() == client_address));
+    wait_for_within(|| {
+        assert!(!client_in_views());
+        updates.iter().all(|update| client.is_active(update.clone()))
+    }, Duration::from_secs(10), "updates did not reach the client");
+    // the client keeps exchanging views with the cluster
+    for _ in 0..50 {
+        assert!(!client_in_views());
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    assert!(client.peers().len() > 1);
+
+    client.shutdown().unwrap();
+    for node in cluster {
+        node.shutdown().unwrap();
+    }
+}
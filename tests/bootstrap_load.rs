@@ -0,0 +1,54 @@
+mod common;
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use gossip::{GossipConfig, GossipService, Peer, PeerSamplingConfig, Update, UpdateExpirationMode};
+use common::MapUpdatingHandler;
+
+#[test]
+fn nodes_bootstrapping_from_one_seed_converge() {
+    let peer_messages = Arc::new(Mutex::new(HashMap::new()));
+    let sampling_period = 500;
+    let gossip_period = 300;
+    let peer_count = 50;
+    let seed = "127.0.0.1:9000";
+
+    let mut instances = Vec::with_capacity(peer_count);
+    for port in 9000..(9000 + peer_count) {
+        let address = format!("127.0.0.1:{}", port);
+        let mut sampling_config = PeerSamplingConfig::new(true, true, sampling_period, 20, 2, 8);
+        sampling_config.set_initial_jitter(0.5);
+        let mut gossip_config = GossipConfig::new(true, true, gossip_period, UpdateExpirationMode::None);
+        gossip_config.set_listener_workers(if port == 9000 { 8 } else { 2 });
+
+        let mut service = GossipService::new(address.parse().unwrap(), sampling_config, gossip_config);
+        let init_handler = Box::new(move || if port == 9000 { None } else { Some(vec![Peer::try_new(seed).unwrap()]) });
+        service.start(init_handler, Box::new(MapUpdatingHandler::new(address, Arc::clone(&peer_messages)))).unwrap();
+        instances.push(service);
+    }
+
+    // wait for peer sampling initialization
+    std::thread::sleep(std::time::Duration::from_millis(sampling_period * 3));
+
+    let message = "bootstrap".as_bytes().to_vec();
+    let digest = Update::new(message.clone()).digest().to_owned();
+    instances[peer_count - 1].submit(message).unwrap();
+
+    // wait for broadcast
+    std::thread::sleep(std::time::Duration::from_millis(gossip_period * 10));
+
+    let peer_messages = peer_messages.lock().unwrap();
+    for instance in &instances[..peer_count - 1] {
+        let received = peer_messages.get(&instance.address().to_string())
+            .is_some_and(|digests| digests.contains(&digest));
+        assert!(received, "{} did not receive the update", instance.address());
+    }
+
+    drop(peer_messages);
+    let handles: Vec<_> = instances.into_iter()
+        .map(|instance| std::thread::spawn(move || instance.shutdown().unwrap()))
+        .collect();
+    for handle in handles {
+        handle.join().unwrap();
+    }
+}
@@ -0,0 +1,138 @@
+mod common;
+
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+use gossip::{BootstrapSource, GossipConfig, GossipService, Peer, PeerSamplingConfig, UpdateExpirationMode};
+use common::{sampling_node, wait_for, NoopUpdateHandler};
+
+fn new_service(port: u16) -> GossipService<NoopUpdateHandler> {
+    sampling_node(port, PeerSamplingConfig::new(true, true, 100, 10, 1, 4), GossipConfig::new(true, true, 100, UpdateExpirationMode::None))
+}
+
+fn wait_for_peer(service: &GossipService<NoopUpdateHandler>, peer: &str) {
+    wait_for(|| service.peers().iter().any(|known| known.address() == peer), &format!("{} not in the view", peer));
+}
+
+#[test]
+fn every_source_kind_is_accepted() {
+    let mut seed = new_service(9000);
+    seed.start_isolated(Box::new(NoopUpdateHandler)).unwrap();
+
+    // a list of peers
+    let mut from_list = new_service(9001);
+    from_list.start(vec![Peer::try_new("127.0.0.1:9000").unwrap()], Box::new(NoopUpdateHandler)).unwrap();
+    wait_for_peer(&from_list, "127.0.0.1:9000");
+
+    // a boxed closure, as a concrete type or as a trait object
+    let mut from_closure = new_service(9002);
+    from_closure.start(Box::new(|| Some(vec![Peer::try_new("127.0.0.1:9000").unwrap()])), Box::new(NoopUpdateHandler)).unwrap();
+    wait_for_peer(&from_closure, "127.0.0.1:9000");
+    let mut from_trait_object = new_service(9003);
+    let closure: Box<dyn Fn() -> Option<Vec<Peer>> + Send> = Box::new(|| None);
+    from_trait_object.start(closure, Box::new(NoopUpdateHandler)).unwrap();
+
+    // an explicit source
+    let mut from_source = new_service(9004);
+    from_source.start(BootstrapSource::Static(vec![Peer::try_new("127.0.0.1:9000").unwrap()]), Box::new(NoopUpdateHandler)).unwrap();
+    wait_for_peer(&from_source, "127.0.0.1:9000");
+
+    // a closure of the former signature, called once when the source is first resolved
+    let mut from_once = new_service(9005);
+    let calls = Arc::new(AtomicUsize::new(0));
+    let once_calls = Arc::clone(&calls);
+    #[allow(deprecated)]
+    let source = BootstrapSource::once(Box::new(move || {
+        once_calls.fetch_add(1, Ordering::SeqCst);
+        Some(vec![Peer::try_new("127.0.0.1:9000").unwrap()])
+    }));
+    assert_eq!(0, calls.load(Ordering::SeqCst));
+    from_once.start(source, Box::new(NoopUpdateHandler)).unwrap();
+    assert_eq!(1, calls.load(Ordering::SeqCst));
+    wait_for_peer(&from_once, "127.0.0.1:9000");
+    from_once.rebootstrap().unwrap();
+    assert_eq!(1, calls.load(Ordering::SeqCst));
+
+    for service in [from_once, from_source, from_trait_object, from_closure, from_list, seed] {
+        service.shutdown().unwrap();
+    }
+}
+
+#[test]
+fn source_is_resolved_again_when_bootstrapping_again() {
+    let mut first = new_service(9020);
+    first.start_isolated(Box::new(NoopUpdateHandler)).unwrap();
+    let mut second = new_service(9021);
+    second.start_isolated(Box::new(NoopUpdateHandler)).unwrap();
+
+    let calls = Arc::new(AtomicUsize::new(0));
+    let seeds = Arc::new(Mutex::new(vec![Peer::try_new("127.0.0.1:9020").unwrap()]));
+    let mut node = new_service(9022);
+    assert!(node.rebootstrap().is_err());
+    let (source_calls, source_seeds) = (Arc::clone(&calls), Arc::clone(&seeds));
+    node.start(Box::new(move || {
+        source_calls.fetch_add(1, Ordering::SeqCst);
+        Some(source_seeds.lock().unwrap().clone())
+    }), Box::new(NoopUpdateHandler)).unwrap();
+    assert_eq!(1, calls.load(Ordering::SeqCst));
+    wait_for_peer(&node, "127.0.0.1:9020");
+
+    // the seeds changed while the node was running
+    *seeds.lock().unwrap() = vec![Peer::try_new("127.0.0.1:9021").unwrap()];
+    node.rebootstrap().unwrap();
+    assert_eq!(2, calls.load(Ordering::SeqCst));
+    wait_for_peer(&node, "127.0.0.1:9021");
+
+    node.shutdown().unwrap();
+    second.shutdown().unwrap();
+    first.shutdown().unwrap();
+}
+
+#[test]
+fn source_is_resolved_without_the_sampler_locked() {
+    let (release, released) = std::sync::mpsc::channel::<()>();
+    let released = Mutex::new(released);
+    let calls = Arc::new(AtomicUsize::new(0));
+    let source_calls = Arc::clone(&calls);
+    let mut node = new_service(9030);
+    // the source blocks when the node bootstraps again, until released
+    node.start(Box::new(move || {
+        if source_calls.fetch_add(1, Ordering::SeqCst) > 0 {
+            released.lock().unwrap().recv().unwrap();
+        }
+        None
+    }), Box::new(NoopUpdateHandler)).unwrap();
+    let node = Arc::new(node);
+
+    let bootstrapping = Arc::clone(&node);
+    let rebootstrap = std::thread::spawn(move || bootstrapping.rebootstrap().unwrap());
+    wait_for(|| calls.load(Ordering::SeqCst) == 2, "the source was not resolved again");
+    // the sampler is not locked while the source is resolved
+    let (state_tx, state) = std::sync::mpsc::channel();
+    let reading = Arc::clone(&node);
+    std::thread::spawn(move || state_tx.send(reading.sampling_state()).unwrap());
+    assert!(state.recv_timeout(Duration::from_secs(5)).is_ok(), "the sampler was locked during the bootstrap");
+
+    release.send(()).unwrap();
+    rebootstrap.join().unwrap();
+    node.shutdown().unwrap();
+}
+
+#[cfg(feature = "dns")]
+#[test]
+fn peers_are_resolved_from_host_name() {
+    let mut seed = new_service(9010);
+    seed.start_isolated(Box::new(NoopUpdateHandler)).unwrap();
+
+    let mut node = new_service(9011);
+    node.start(BootstrapSource::Dns("localhost".to_owned(), 9010), Box::new(NoopUpdateHandler)).unwrap();
+    wait_for_peer(&node, "127.0.0.1:9010");
+
+    // a host name that cannot be resolved fails the start
+    let mut unresolved = new_service(9012);
+    assert!(unresolved.start(BootstrapSource::Dns("unresolved.invalid".to_owned(), 9010), Box::new(NoopUpdateHandler)).is_err());
+    assert!(!unresolved.health().running);
+
+    node.shutdown().unwrap();
+    seed.shutdown().unwrap();
+}
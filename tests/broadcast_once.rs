@@ -0,0 +1,51 @@
+mod common;
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use gossip::{GossipConfig, PeerSamplingConfig, Peer, GossipService, Update, UpdateExpirationMode};
+use common::MapUpdatingHandler;
+
+#[test]
+fn broadcast_once_through_seed() {
+    let sampling_period = 500;
+    let gossip_period = 300;
+    let received = Arc::new(Mutex::new(HashMap::new()));
+
+    let seed = "127.0.0.1:9000";
+    let mut seed_service = GossipService::new(
+        seed.parse().unwrap(),
+        PeerSamplingConfig::new(true, true, sampling_period, 10, 1, 4),
+        GossipConfig::new(true, true, gossip_period, UpdateExpirationMode::None)
+    );
+    seed_service.start_isolated(
+        Box::new(MapUpdatingHandler::new(seed.to_owned(), Arc::clone(&received)))
+    ).unwrap();
+
+    let member = "127.0.0.1:9001";
+    let mut member_service = GossipService::new(
+        member.parse().unwrap(),
+        PeerSamplingConfig::new(true, true, sampling_period, 10, 1, 4),
+        GossipConfig::new(true, true, gossip_period, UpdateExpirationMode::None)
+    );
+    member_service.start_with_peers(
+        vec![Peer::try_new(seed).unwrap()],
+        Box::new(MapUpdatingHandler::new(member.to_owned(), Arc::clone(&received)))
+    ).unwrap();
+
+    // wait for peer discovery
+    std::thread::sleep(std::time::Duration::from_millis(sampling_period * 3));
+
+    let content = "one-shot broadcast".as_bytes().to_vec();
+    let digest = gossip::broadcast_once(seed.parse().unwrap(), content.clone(), std::time::Duration::from_secs(5)).unwrap();
+    assert_eq!(Update::new(content).digest(), &digest);
+
+    // wait for propagation
+    std::thread::sleep(std::time::Duration::from_millis(gossip_period * 5));
+
+    let received = received.lock().unwrap();
+    assert!(received.get(seed).unwrap().contains(&digest));
+    assert!(received.get(member).unwrap().contains(&digest));
+
+    seed_service.shutdown().unwrap();
+    member_service.shutdown().unwrap();
+}
@@ -0,0 +1,166 @@
+mod common;
+
+use std::sync::{Arc, Mutex};
+use gossip::{ChunkedUpdateHandler, GossipConfig, GossipService, Peer, PeerSamplingConfig, Update, UpdateExpirationMode, UpdateHandler};
+use common::{start_static_node, wait_for, NoopUpdateHandler};
+
+// handler storing the full content of received messages
+struct ContentRecordingHandler {
+    contents: Arc<Mutex<Vec<Vec<u8>>>>,
+}
+impl UpdateHandler for ContentRecordingHandler {
+    fn on_update(&self, update: Update) {
+        self.contents.lock().unwrap().push(update.content().to_vec());
+    }
+}
+
+fn payload(size: usize) -> Vec<u8> {
+    (0..size).map(|i| (i * 31 % 251) as u8).collect()
+}
+
+#[test]
+fn chunks_reassembled_out_of_order() {
+    let contents = Arc::new(Mutex::new(Vec::new()));
+    let handler = ChunkedUpdateHandler::new(ContentRecordingHandler { contents: Arc::clone(&contents) }, std::time::Duration::from_secs(10));
+    let payload = payload(100_000);
+
+    let mut parts = gossip::split_into_chunks(&payload, 8 * 1024);
+    assert_eq!(14, parts.len());
+    // deliver the manifest first and the chunks in reverse order
+    handler.on_update(Update::new(parts.pop().unwrap()));
+    for part in parts.into_iter().rev() {
+        handler.on_update(Update::new(part));
+    }
+    // regular updates are passed through
+    handler.on_update(Update::new("not chunked".as_bytes().to_vec()));
+
+    let contents = contents.lock().unwrap();
+    assert_eq!(2, contents.len());
+    assert_eq!(payload, contents[0]);
+    assert_eq!("not chunked".as_bytes(), &contents[1][..]);
+    assert_eq!(0, handler.pending_count());
+}
+
+#[test]
+fn incomplete_chunks_expire() {
+    let contents = Arc::new(Mutex::new(Vec::new()));
+    let handler = ChunkedUpdateHandler::new(ContentRecordingHandler { contents: Arc::clone(&contents) }, std::time::Duration::from_millis(100));
+    let mut parts = gossip::split_into_chunks(&payload(1000), 100);
+    let manifest = parts.pop().unwrap();
+    let missing = parts.pop().unwrap();
+    for part in parts {
+        handler.on_update(Update::new(part));
+    }
+    std::thread::sleep(std::time::Duration::from_millis(200));
+
+    // the earlier chunks were dropped so the update can no longer be reassembled
+    handler.on_update(Update::new(missing));
+    handler.on_update(Update::new(manifest));
+    assert!(contents.lock().unwrap().is_empty());
+    assert_eq!(2, handler.pending_count());
+}
+
+/// Two payloads of two blocks of 100 bytes sharing their first block
+fn sharing_payloads() -> (Vec<u8>, Vec<u8>) {
+    let shared = payload(100);
+    ([shared.as_slice(), &[1; 100]].concat(), [shared.as_slice(), &[2; 100]].concat())
+}
+
+#[test]
+fn manifests_sharing_a_chunk_are_reassembled() {
+    let (first, second) = sharing_payloads();
+    let (first_parts, second_parts) = (gossip::split_into_chunks(&first, 100), gossip::split_into_chunks(&second, 100));
+    let contents = Arc::new(Mutex::new(Vec::new()));
+    let handler = ChunkedUpdateHandler::new(ContentRecordingHandler { contents: Arc::clone(&contents) }, std::time::Duration::from_secs(10));
+
+    // both manifests are pending when the shared chunk, delivered once, arrives
+    handler.on_update(Update::new(first_parts[2].clone()));
+    handler.on_update(Update::new(second_parts[2].clone()));
+    handler.on_update(Update::new(first_parts[1].clone()));
+    handler.on_update(Update::new(second_parts[1].clone()));
+    handler.on_update(Update::new(first_parts[0].clone()));
+
+    let mut received = contents.lock().unwrap().clone();
+    received.sort();
+    assert_eq!(vec![first, second], received);
+    assert_eq!(0, handler.pending_count());
+}
+
+#[test]
+fn manifest_received_after_reassembly_reuses_shared_chunk() {
+    let (first, second) = sharing_payloads();
+    let (first_parts, second_parts) = (gossip::split_into_chunks(&first, 100), gossip::split_into_chunks(&second, 100));
+    let contents = Arc::new(Mutex::new(Vec::new()));
+    let handler = ChunkedUpdateHandler::new(ContentRecordingHandler { contents: Arc::clone(&contents) }, std::time::Duration::from_secs(10));
+
+    for part in first_parts {
+        handler.on_update(Update::new(part));
+    }
+    assert_eq!(vec![first], *contents.lock().unwrap());
+    // the shared chunk is not delivered again
+    handler.on_update(Update::new(second_parts[1].clone()));
+    handler.on_update(Update::new(second_parts[2].clone()));
+
+    assert_eq!(second, contents.lock().unwrap()[1]);
+    assert_eq!(0, handler.pending_count());
+}
+
+#[test]
+fn expired_shared_chunk_is_submitted_again() {
+    let (first, second) = sharing_payloads();
+    let first_parts = gossip::split_into_chunks(&first, 100);
+    // updates expire during the gossip rounds, which need a peer
+    let peer: GossipService<NoopUpdateHandler> = start_static_node(9027, &[], GossipConfig::new(true, true, 100, UpdateExpirationMode::None), NoopUpdateHandler);
+    let service: GossipService<NoopUpdateHandler> = start_static_node(9026, &[9027], GossipConfig::new(true, true, 100, UpdateExpirationMode::DurationMillis(200)), NoopUpdateHandler);
+
+    service.submit_chunked(first, 100).unwrap();
+    wait_for(|| service.is_expired(first_parts[0].clone()), "shared chunk did not expire");
+    service.submit_chunked(second, 100).unwrap();
+    let active = service.is_active(first_parts[0].clone());
+    service.shutdown().unwrap();
+    peer.shutdown().unwrap();
+
+    assert!(active);
+}
+
+#[test]
+fn large_update_propagates() {
+    let sampling_period = 500;
+    let gossip_period = 300;
+    let payload = payload(1024 * 1024);
+    let addresses = ["127.0.0.1:9020", "127.0.0.1:9021", "127.0.0.1:9022"];
+
+    let mut services = Vec::new();
+    let mut received = Vec::new();
+    for (i, address) in addresses.iter().enumerate() {
+        let contents = Arc::new(Mutex::new(Vec::new()));
+        let mut service = GossipService::new(
+            address.parse().unwrap(),
+            PeerSamplingConfig::new(true, true, sampling_period, 10, 1, 4),
+            GossipConfig::new(true, true, gossip_period, UpdateExpirationMode::None)
+        );
+        let seed = addresses[0].to_owned();
+        service.start(
+            Box::new(move || if i == 0 { None } else { Some(vec![Peer::try_new(&seed).unwrap()]) }),
+            Box::new(ChunkedUpdateHandler::new(ContentRecordingHandler { contents: Arc::clone(&contents) }, std::time::Duration::from_secs(30)))
+        ).unwrap();
+        services.push(service);
+        received.push(contents);
+    }
+
+    // wait for peer discovery
+    std::thread::sleep(std::time::Duration::from_millis(sampling_period * 4));
+
+    services[2].submit_chunked(payload.clone(), 64 * 1024).unwrap();
+    std::thread::sleep(std::time::Duration::from_millis(gossip_period * 10));
+
+    for contents in &received[..2] {
+        let contents = contents.lock().unwrap();
+        assert_eq!(1, contents.len());
+        assert!(contents[0] == payload);
+    }
+
+    for service in services {
+        service.shutdown().unwrap();
+    }
+}
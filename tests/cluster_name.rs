@@ -0,0 +1,70 @@
+mod common;
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use gossip::{GossipConfig, GossipService, PeerSamplingConfig, Update, UpdateExpirationMode};
+use common::{start_sampling_node, MapUpdatingHandler};
+
+fn start_node(port: u16, cluster: Option<&str>, contacts: &[u16], received: &Arc<Mutex<HashMap<String, Vec<String>>>>) -> GossipService<MapUpdatingHandler> {
+    let mut gossip_config = GossipConfig::new(true, true, 100, UpdateExpirationMode::None);
+    if let Some(cluster) = cluster {
+        gossip_config.set_cluster_name(cluster.to_owned());
+    }
+    let handler = MapUpdatingHandler::new(format!("127.0.0.1:{}", port), Arc::clone(received));
+    start_sampling_node(port, contacts, PeerSamplingConfig::new(true, true, 100, 10, 1, 4), gossip_config, handler)
+}
+
+#[test]
+fn clusters_do_not_mix() {
+    let received = Arc::new(Mutex::new(HashMap::new()));
+    let red = ["127.0.0.1:9000", "127.0.0.1:9001"];
+    let blue = ["127.0.0.1:9010", "127.0.0.1:9011"];
+    let mut red_nodes = vec![start_node(9000, Some("red"), &[], &received), start_node(9001, Some("red"), &[9000], &received)];
+    // the second blue node bootstraps from a red node by mistake
+    let mut blue_nodes = vec![start_node(9010, Some("blue"), &[], &received), start_node(9011, Some("blue"), &[9000], &received)];
+    std::thread::sleep(std::time::Duration::from_millis(1000));
+
+    let red_update = "red update".as_bytes().to_vec();
+    let blue_update = "blue update".as_bytes().to_vec();
+    red_nodes[0].submit(red_update.clone()).unwrap();
+    blue_nodes[1].submit(blue_update.clone()).unwrap();
+    std::thread::sleep(std::time::Duration::from_millis(1000));
+
+    let red_digest = Update::new(red_update).digest().to_owned();
+    let received = received.lock().unwrap();
+    // only the red update was received, by the red node
+    assert_eq!(Some(&vec![red_digest]), received.get(red[1]));
+    assert_eq!(1, received.len());
+    assert!(!red_nodes[0].is_active(blue_update));
+
+    for node in &red_nodes {
+        assert!(node.peers().iter().all(|peer| !blue.contains(&peer.address())), "{:?}", node.peers());
+    }
+    // the mistaken bootstrap address stays in the view of the blue node, but no red peer is learned through it
+    for node in &blue_nodes {
+        assert!(node.peers().iter().all(|peer| peer.address() != red[1]), "{:?}", node.peers());
+    }
+    assert!(red_nodes[0].stats().foreign_cluster_messages > 0);
+
+    for node in blue_nodes.iter_mut().chain(red_nodes.iter_mut()) {
+        node.shutdown().unwrap();
+    }
+}
+
+#[test]
+fn unnamed_nodes_interoperate() {
+    let received = Arc::new(Mutex::new(HashMap::new()));
+    let named = start_node(9020, Some("red"), &[], &received);
+    let unnamed = start_node(9021, None, &[9020], &received);
+    std::thread::sleep(std::time::Duration::from_millis(500));
+
+    let content = "shared update".as_bytes().to_vec();
+    named.submit(content.clone()).unwrap();
+    std::thread::sleep(std::time::Duration::from_millis(500));
+
+    assert_eq!(Some(&vec![Update::new(content).digest().to_owned()]), received.lock().unwrap().get("127.0.0.1:9021"));
+    assert_eq!(0, named.stats().foreign_cluster_messages);
+
+    unnamed.shutdown().unwrap();
+    named.shutdown().unwrap();
+}
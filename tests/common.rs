@@ -1,12 +1,15 @@
+#![allow(dead_code)]
+
 use std::error::Error;
-use gossip::{UpdateHandler, Update, GossipService};
+use gossip::{GossipConfig, GossipService, Peer, PeerSamplingConfig, UpdateHandler, Update};
 use std::collections::HashMap;
 use std::sync::{Mutex, Arc};
+use std::time::{Duration, Instant};
 
 // noop handler
 pub struct NoopUpdateHandler;
 impl UpdateHandler for NoopUpdateHandler {
-    fn on_update(&self, update: Update) {}
+    fn on_update(&self, _update: Update) {}
 }
 // text message handler
 pub struct TextMessageHandler {id: String}
@@ -36,11 +39,76 @@ impl MapUpdatingHandler {
 }
 impl UpdateHandler for MapUpdatingHandler {
     fn on_update(&self, update: Update) {
-        self.map.lock().unwrap().entry(self.id.clone()).or_insert(Vec::new()).push(update.digest().clone());
+        self.map.lock().unwrap().entry(self.id.clone()).or_default().push(update.digest().clone());
+    }
+}
+
+// handler recording the contents of the delivered updates
+#[derive(Clone, Default)]
+pub struct RecordingHandler(pub Arc<Mutex<Vec<Vec<u8>>>>);
+impl RecordingHandler {
+    pub fn received(&self) -> Vec<Vec<u8>> {
+        self.0.lock().unwrap().clone()
+    }
+}
+impl UpdateHandler for RecordingHandler {
+    fn on_update(&self, update: Update) {
+        self.0.lock().unwrap().push(update.content().to_vec());
+    }
+}
+
+// peer listening on a local port
+pub fn peer(port: u16) -> Peer {
+    Peer::try_new(&format!("127.0.0.1:{}", port)).unwrap()
+}
+
+// node on a local port whose view is made of the nodes on the given local ports, not started yet
+pub fn static_node<T>(port: u16, peers: &[u16], gossip_config: GossipConfig) -> GossipService<T>
+where T: UpdateHandler + Send + 'static
+{
+    let peers = peers.iter().map(|port| peer(*port)).collect();
+    GossipService::new(format!("127.0.0.1:{}", port).parse().unwrap(), PeerSamplingConfig::static_view(peers), gossip_config)
+}
+
+// started node with a static view, see static_node
+pub fn start_static_node<T>(port: u16, peers: &[u16], gossip_config: GossipConfig, handler: T) -> GossipService<T>
+where T: UpdateHandler + Send + 'static
+{
+    let mut service = static_node(port, peers, gossip_config);
+    service.start_isolated(Box::new(handler)).unwrap();
+    service
+}
+
+// node on a local port sampling its view, not started yet
+pub fn sampling_node<T>(port: u16, sampling_config: PeerSamplingConfig, gossip_config: GossipConfig) -> GossipService<T>
+where T: UpdateHandler + Send + 'static
+{
+    GossipService::new(format!("127.0.0.1:{}", port).parse().unwrap(), sampling_config, gossip_config)
+}
+
+// started node sampling its view, joining the nodes on the given local ports, see sampling_node
+pub fn start_sampling_node<T>(port: u16, contacts: &[u16], sampling_config: PeerSamplingConfig, gossip_config: GossipConfig, handler: T) -> GossipService<T>
+where T: UpdateHandler + Send + 'static
+{
+    let mut service = sampling_node(port, sampling_config, gossip_config);
+    service.start_with_peers(contacts.iter().map(|port| peer(*port)).collect(), Box::new(handler)).unwrap();
+    service
+}
+
+// waits up to five seconds for the condition, failing with the message
+pub fn wait_for(condition: impl Fn() -> bool, message: &str) {
+    wait_for_within(condition, Duration::from_secs(5), message);
+}
+
+// waits up to the timeout for the condition, failing with the message
+pub fn wait_for_within(condition: impl Fn() -> bool, timeout: Duration, message: &str) {
+    let deadline = Instant::now() + timeout;
+    while !condition() {
+        assert!(Instant::now() < deadline, "{}", message);
+        std::thread::sleep(Duration::from_millis(10));
     }
 }
 
-#[allow(dead_code)]
 pub fn configure_logging(level: log::LevelFilter) -> Result<(), Box<dyn Error>>{
 
     use log4rs::encode::pattern::PatternEncoder;
@@ -0,0 +1,131 @@
+mod common;
+
+use gossip::{ConfigWarning, DisseminationMode, GossipConfig, GossipService, Peer, PeerSamplingConfig, UpdateExpirationMode};
+use common::NoopUpdateHandler;
+
+#[test]
+fn consistent_configuration_has_no_warning() {
+    assert!(GossipConfig::default().check(&PeerSamplingConfig::default()).is_empty());
+}
+
+#[test]
+fn short_expiration_is_reported() {
+    let mut service = GossipService::new(
+        "127.0.0.1:9000".parse().unwrap(),
+        PeerSamplingConfig::new(true, true, 1000, 10, 1, 4),
+        GossipConfig::new(true, true, 500, UpdateExpirationMode::DurationMillis(100))
+    );
+    assert!(service.config_warnings().is_empty());
+    service.start_isolated(Box::new(NoopUpdateHandler)).unwrap();
+    assert_eq!(vec![ConfigWarning::ExpirationShorterThanRounds { expiration: 100, gossip_period: 500 }], service.config_warnings());
+
+    // the warning is cleared when the configuration is fixed
+    service.update_config(|config| config.set_update_expiration(UpdateExpirationMode::DurationMillis(5000))).unwrap();
+    assert!(service.config_warnings().is_empty());
+
+    service.shutdown().unwrap();
+}
+
+#[test]
+fn slow_sampling_is_reported() {
+    let mut service = GossipService::new(
+        "127.0.0.1:9010".parse().unwrap(),
+        PeerSamplingConfig::new(true, true, 2000, 10, 1, 4),
+        GossipConfig::new(true, true, 10, UpdateExpirationMode::None)
+    );
+    service.start_isolated(Box::new(NoopUpdateHandler)).unwrap();
+    assert_eq!(vec![ConfigWarning::SlowSampling { sampling_period: 2000, gossip_period: 10 }], service.config_warnings());
+    service.shutdown().unwrap();
+}
+
+#[test]
+fn rounds_without_peer_are_counted() {
+    let mut service = GossipService::new(
+        "127.0.0.1:9020".parse().unwrap(),
+        PeerSamplingConfig::new(true, true, 1000, 10, 1, 4),
+        GossipConfig::new(true, true, 50, UpdateExpirationMode::None)
+    );
+    service.start_isolated(Box::new(NoopUpdateHandler)).unwrap();
+    std::thread::sleep(std::time::Duration::from_millis(300));
+    assert!(service.stats().rounds_without_peer >= 2);
+    service.shutdown().unwrap();
+}
+
+#[test]
+fn updates_expired_before_push_are_counted() {
+    let mut seed = GossipService::new(
+        "127.0.0.1:9030".parse().unwrap(),
+        PeerSamplingConfig::new(true, true, 100, 10, 1, 4),
+        GossipConfig::new(true, true, 300, UpdateExpirationMode::DurationMillis(20))
+    );
+    seed.start_isolated(Box::new(NoopUpdateHandler)).unwrap();
+    let mut node = GossipService::new(
+        "127.0.0.1:9031".parse().unwrap(),
+        PeerSamplingConfig::new(true, true, 100, 10, 1, 4),
+        GossipConfig::new(true, true, 300, UpdateExpirationMode::None)
+    );
+    node.start_with_peers(vec![Peer::try_new("127.0.0.1:9030").unwrap()], Box::new(NoopUpdateHandler)).unwrap();
+
+    seed.submit("short lived".as_bytes().to_vec()).unwrap();
+    std::thread::sleep(std::time::Duration::from_millis(1000));
+
+    assert_eq!(1, seed.stats().updates_expired_unpushed);
+    assert!(!node.is_active("short lived".as_bytes().to_vec()));
+
+    node.shutdown().unwrap();
+    seed.shutdown().unwrap();
+}
+
+#[test]
+fn mode_mismatch_is_reported() {
+    let mut service = GossipService::new(
+        "127.0.0.1:9040".parse().unwrap(),
+        PeerSamplingConfig::new(true, true, 100, 10, 1, 4),
+        GossipConfig::new(false, true, 100, UpdateExpirationMode::None)
+    );
+    service.start_isolated(Box::new(NoopUpdateHandler)).unwrap();
+    assert_eq!(vec![ConfigWarning::ModeMismatch { gossip: DisseminationMode::Pull, sampling: DisseminationMode::PushPull }], service.config_warnings());
+    let health = service.health();
+    assert_eq!(Some(DisseminationMode::Pull), health.gossip_mode);
+    assert_eq!(Some(DisseminationMode::PushPull), health.sampling_mode);
+
+    // strict checks reject a change that keeps the mismatch
+    assert!(service.update_config(|config| config.set_strict_checks(true)).is_err());
+    service.update_config(|config| {
+        config.set_strict_checks(true);
+        config.set_push_pull(true, true);
+    }).unwrap();
+    assert!(service.config_warnings().is_empty());
+    assert_eq!(Some(DisseminationMode::PushPull), service.health().gossip_mode);
+
+    service.shutdown().unwrap();
+
+    // a static view is not a mismatch
+    let gossip_config = GossipConfig::new(true, false, 100, UpdateExpirationMode::None);
+    assert!(gossip_config.check(&PeerSamplingConfig::new(false, false, 100, 10, 1, 4)).is_empty());
+}
+
+#[test]
+fn strict_checks_refuse_to_start() {
+    let mut gossip_config = GossipConfig::new(true, false, 100, UpdateExpirationMode::None);
+    gossip_config.set_strict_checks(true);
+    let mut service = GossipService::new("127.0.0.1:9050".parse().unwrap(), PeerSamplingConfig::new(true, true, 100, 10, 1, 4), gossip_config);
+    let error = service.start_isolated(Box::new(NoopUpdateHandler)).unwrap_err();
+    assert!(error.to_string().contains("push mode"), "{}", error);
+    assert!(!service.health().running);
+    // the address was not bound
+    std::net::TcpListener::bind("127.0.0.1:9050").unwrap();
+}
+
+#[test]
+fn disabled_gossip_is_refused() {
+    let mut service = GossipService::new(
+        "127.0.0.1:9060".parse().unwrap(),
+        PeerSamplingConfig::new(true, true, 100, 10, 1, 4),
+        GossipConfig::new(false, false, 100, UpdateExpirationMode::None)
+    );
+    assert_eq!(None, service.health().gossip_mode);
+    assert!(service.start_isolated(Box::new(NoopUpdateHandler)).is_err());
+    assert!(!service.health().running);
+    std::net::TcpListener::bind("127.0.0.1:9060").unwrap();
+}
@@ -0,0 +1,67 @@
+mod common;
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use gossip::{Digester, GossipConfig, GossipService, Peer, PeerSamplingConfig, Sha256Digester, Update, UpdateExpirationMode};
+use common::MapUpdatingHandler;
+
+fn start_pair(first: &'static str, second: &'static str, first_digester: Arc<dyn Digester>, second_digester: Arc<dyn Digester>, received: &Arc<Mutex<HashMap<String, Vec<String>>>>) -> (GossipService<MapUpdatingHandler>, GossipService<MapUpdatingHandler>) {
+    let mut services = Vec::new();
+    for (address, digester, contact) in [(first, first_digester, None), (second, second_digester, Some(first))] {
+        let mut gossip_config = GossipConfig::new(true, true, 200, UpdateExpirationMode::None);
+        gossip_config.set_digester(digester);
+        let mut service = GossipService::new(
+            address.parse().unwrap(),
+            PeerSamplingConfig::new(true, true, 100, 10, 1, 4),
+            gossip_config
+        );
+        service.start(
+            Box::new(move || contact.map(|contact| vec![Peer::try_new(contact).unwrap()])),
+            Box::new(MapUpdatingHandler::new(address.to_owned(), Arc::clone(received)))
+        ).unwrap();
+        services.push(service);
+    }
+    let second = services.pop().unwrap();
+    (services.pop().unwrap(), second)
+}
+
+#[test]
+fn sha256_cluster_propagates_updates() {
+    let received = Arc::new(Mutex::new(HashMap::new()));
+    let (first, second) = start_pair("127.0.0.1:9000", "127.0.0.1:9001", Arc::new(Sha256Digester), Arc::new(Sha256Digester), &received);
+    std::thread::sleep(std::time::Duration::from_millis(500));
+
+    let content = "sha256 update".as_bytes().to_vec();
+    first.submit(content.clone()).unwrap();
+    std::thread::sleep(std::time::Duration::from_millis(1000));
+
+    let digest = Update::new_with(&Sha256Digester, content.clone()).digest().to_owned();
+    assert_eq!(64, digest.len());
+    assert_ne!(Update::new(content.clone()).digest(), &digest);
+    assert_eq!(Some(&vec![digest]), received.lock().unwrap().get("127.0.0.1:9001"));
+    assert!(second.is_active(content));
+
+    second.shutdown().unwrap();
+    first.shutdown().unwrap();
+}
+
+#[test]
+fn mismatched_digesters_do_not_propagate() {
+    let received = Arc::new(Mutex::new(HashMap::new()));
+    let (first, second) = start_pair("127.0.0.1:9010", "127.0.0.1:9011", Arc::new(Sha256Digester), Arc::new(gossip::Blake3Digester), &received);
+    std::thread::sleep(std::time::Duration::from_millis(500));
+
+    let content = "mismatched update".as_bytes().to_vec();
+    first.submit(content.clone()).unwrap();
+    std::thread::sleep(std::time::Duration::from_millis(1000));
+
+    // the content is dropped by the receiver and the sender keeps working
+    assert_eq!(None, received.lock().unwrap().get("127.0.0.1:9011"));
+    assert!(!second.is_active(content.clone()));
+    assert!(first.is_active(content));
+    assert!(first.health().running);
+    assert!(second.health().running);
+
+    second.shutdown().unwrap();
+    first.shutdown().unwrap();
+}
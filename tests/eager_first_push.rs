@@ -0,0 +1,49 @@
+mod common;
+
+use std::time::{Duration, Instant};
+use gossip::{GossipConfig, GossipService, UpdateExpirationMode};
+use common::{start_static_node, NoopUpdateHandler};
+
+fn start_node(port: u16, peer: u16, eager: bool) -> GossipService<NoopUpdateHandler> {
+    let mut gossip_config = GossipConfig::new(true, false, 5_000, UpdateExpirationMode::None);
+    gossip_config.set_eager_first_push(eager);
+    start_static_node(port, &[peer], gossip_config, NoopUpdateHandler)
+}
+
+/// Submits an update on A and returns how long it took to reach B, if it did within a second
+fn first_push_latency(a: &GossipService<NoopUpdateHandler>, b: &GossipService<NoopUpdateHandler>, content: &[u8]) -> Option<Duration> {
+    let submitted = Instant::now();
+    a.submit(content.to_vec()).unwrap();
+    while submitted.elapsed() < Duration::from_secs(1) {
+        if b.is_active(content.to_vec()) {
+            return Some(submitted.elapsed());
+        }
+        std::thread::sleep(Duration::from_millis(2));
+    }
+    None
+}
+
+#[test]
+fn submitted_update_is_pushed_right_away() {
+    let a = start_node(9000, 9001, true);
+    let b = start_node(9001, 9000, false);
+
+    for content in [b"first".as_slice(), b"second"] {
+        let latency = first_push_latency(&a, &b, content);
+        assert!(latency.is_some_and(|latency| latency < Duration::from_millis(200)), "update reached B after {:?}", latency);
+    }
+
+    b.shutdown().unwrap();
+    a.shutdown().unwrap();
+}
+
+#[test]
+fn submitted_update_waits_for_scheduled_round_by_default() {
+    let a = start_node(9002, 9003, false);
+    let b = start_node(9003, 9002, false);
+
+    assert_eq!(None, first_push_latency(&a, &b, b"update"));
+
+    b.shutdown().unwrap();
+    a.shutdown().unwrap();
+}
@@ -0,0 +1,71 @@
+mod common;
+
+use std::sync::Mutex;
+use std::time::Duration;
+use gossip::{GossipConfig, GossipService, PeerSamplingConfig, UpdateExpirationMode};
+use common::{start_sampling_node, wait_for_within, NoopUpdateHandler};
+
+const PERIOD: u64 = 20;
+
+// records the warnings with the name of the thread logging them
+struct WarningLogger {
+    warnings: Mutex<Vec<(String, String)>>,
+}
+impl log::Log for WarningLogger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        metadata.level() <= log::Level::Warn
+    }
+    fn log(&self, record: &log::Record) {
+        if self.enabled(record.metadata()) {
+            let thread = std::thread::current().name().unwrap_or_default().to_owned();
+            self.warnings.lock().unwrap().push((thread, record.args().to_string()));
+        }
+    }
+    fn flush(&self) {}
+}
+
+static LOGGER: WarningLogger = WarningLogger { warnings: Mutex::new(Vec::new()) };
+
+/// Returns the number of warnings about an empty view logged by the threads of a node
+fn no_peer_warnings(port: u16) -> usize {
+    let address = format!("127.0.0.1:{}", port);
+    LOGGER.warnings.lock().unwrap().iter()
+        .filter(|(thread, message)| thread.starts_with(&address) && message.starts_with(&format!("[{}] No peer found", address)))
+        .count()
+}
+
+fn start_node(port: u16, gossip_config: GossipConfig, contacts: &[u16]) -> GossipService<NoopUpdateHandler> {
+    let _ = log::set_logger(&LOGGER).map(|()| log::set_max_level(log::LevelFilter::Warn));
+    start_sampling_node(port, contacts, PeerSamplingConfig::new(true, true, PERIOD, 10, 1, 4), gossip_config, NoopUpdateHandler)
+}
+
+#[test]
+fn isolated_node_does_not_flood_logs() {
+    let node = start_node(9000, GossipConfig::new(true, true, PERIOD, UpdateExpirationMode::None), &[]);
+    std::thread::sleep(Duration::from_millis(30 * PERIOD));
+
+    // every round finds no peer, but the warning is logged once for gossip and once for sampling
+    assert!(node.stats().rounds_without_peer >= 10, "{:?}", node.stats());
+    assert!(no_peer_warnings(9000) <= 2, "{:?}", LOGGER.warnings.lock().unwrap());
+
+    node.shutdown().unwrap();
+}
+
+#[test]
+fn empty_view_backoff_ends_when_a_peer_appears() {
+    let mut gossip_config = GossipConfig::new(true, true, PERIOD, UpdateExpirationMode::None);
+    gossip_config.set_empty_view_backoff(50);
+    let seed = start_node(9001, gossip_config, &[]);
+    std::thread::sleep(Duration::from_millis(30 * PERIOD));
+    assert!(seed.stats().rounds_without_peer <= 2, "{:?}", seed.stats());
+
+    // the joining node does not pull: the update reaches it only if the seed gossips at its base period again
+    let joining = start_node(9002, GossipConfig::new(false, true, PERIOD, UpdateExpirationMode::None), &[9001]);
+    let update = b"pushed by the seed".to_vec();
+    seed.submit(update.clone()).unwrap();
+    wait_for_within(|| joining.is_active(update.clone()), Duration::from_millis(25 * PERIOD), "the seed was still backing off");
+    assert!(no_peer_warnings(9001) <= 2, "{:?}", LOGGER.warnings.lock().unwrap());
+
+    joining.shutdown().unwrap();
+    seed.shutdown().unwrap();
+}
@@ -0,0 +1,53 @@
+mod common;
+
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+use gossip::{GossipConfig, GossipService, PeerSamplingConfig, UpdateExpirationMode};
+use common::{sampling_node, start_sampling_node, NoopUpdateHandler};
+
+fn start_node(epoch_file: &Path) -> GossipService<NoopUpdateHandler> {
+    let mut gossip_config = GossipConfig::new(true, true, 20, UpdateExpirationMode::None);
+    gossip_config.set_epoch_file(epoch_file);
+    start_sampling_node(9000, &[], PeerSamplingConfig::new(true, true, 20, 10, 1, 4), gossip_config, NoopUpdateHandler)
+}
+
+#[test]
+fn restarted_node_reports_a_new_epoch() {
+    let epoch_file = std::env::temp_dir().join(format!("gossip-epoch-test-{}", std::process::id()));
+    let _ = std::fs::remove_file(&epoch_file);
+
+    let before_start = SystemTime::now();
+    let service = start_node(&epoch_file);
+    std::thread::sleep(Duration::from_millis(300));
+    let stats = service.stats();
+    assert_eq!(1, stats.epoch);
+    assert!(stats.started_at.unwrap() >= before_start);
+    assert!(stats.uptime >= Duration::from_millis(300));
+    assert!(stats.gossip_rounds > 0);
+    assert!(stats.sampling_cycles > 0);
+    assert_eq!(1, service.health().epoch);
+    let first_uptime = stats.uptime;
+    service.shutdown().unwrap();
+
+    let service = start_node(&epoch_file);
+    let stats = service.stats();
+    assert_eq!(2, stats.epoch);
+    assert_eq!(2, service.health().epoch);
+    assert!(stats.uptime < first_uptime);
+    service.shutdown().unwrap();
+
+    std::fs::remove_file(&epoch_file).unwrap();
+}
+
+#[test]
+fn epoch_is_zero_before_start() {
+    let service: GossipService<NoopUpdateHandler> = sampling_node(
+        9001,
+        PeerSamplingConfig::new(true, true, 20, 10, 1, 4),
+        GossipConfig::new(true, true, 20, UpdateExpirationMode::None)
+    );
+    let stats = service.stats();
+    assert_eq!(0, stats.epoch);
+    assert_eq!(None, stats.started_at);
+    assert_eq!(None, service.health().uptime_seconds);
+}
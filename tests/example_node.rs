@@ -0,0 +1,52 @@
+mod common;
+
+use std::sync::Arc;
+use common::wait_for;
+
+#[allow(dead_code)]
+#[path = "../examples/node.rs"]
+mod node;
+
+fn args(line: &str) -> impl Iterator<Item = String> + '_ {
+    line.split_whitespace().map(str::to_owned)
+}
+
+#[test]
+fn arguments_are_parsed() {
+    let options = node::parse_args(args("--bind 127.0.0.1:7000 --seed 10.0.0.1:7000 --seed 10.0.0.2:7000 --period 500")).unwrap();
+    assert_eq!(node::Options {
+        bind: "127.0.0.1:7000".parse().unwrap(),
+        advertise: None,
+        seeds: vec!["10.0.0.1:7000".to_owned(), "10.0.0.2:7000".to_owned()],
+        period: 500,
+    }, options);
+    assert_eq!(1000, node::parse_args(args("--bind 127.0.0.1:7000")).unwrap().period);
+
+    assert!(node::parse_args(args("--seed 10.0.0.1:7000")).is_err());
+    assert!(node::parse_args(args("--bind 127.0.0.1:7000 --period")).is_err());
+    assert!(node::parse_args(args("--bind 127.0.0.1:7000 --verbose")).is_err());
+
+    // the peers are given a routable address
+    let options = node::parse_args(args("--bind 0.0.0.0:7000 --advertise 10.0.0.2:7000")).unwrap();
+    assert_eq!(Some("10.0.0.2:7000".parse().unwrap()), options.advertise);
+    assert!(node::parse_args(args("--bind 0.0.0.0:7000")).is_err());
+    assert!(node::parse_args(args("--bind 127.0.0.1:7000 --advertise 0.0.0.0:7000")).is_err());
+}
+
+#[test]
+fn message_goes_through_example_nodes() {
+    let first = Arc::new(node::start_node(&node::parse_args(args("--bind 127.0.0.1:9000 --period 100")).unwrap()).unwrap());
+    let second = node::start_node(&node::parse_args(args("--bind 0.0.0.0:9001 --advertise 127.0.0.1:9001 --seed 127.0.0.1:9000 --period 100")).unwrap()).unwrap();
+
+    second.submit(b"hello from the command line".to_vec()).unwrap();
+    wait_for(|| first.is_active(b"hello from the command line".to_vec()), "message was not received");
+
+    // shut down from another thread while the service is in use, as the signal handler does
+    let signaled = Arc::clone(&first);
+    let handler = std::thread::spawn(move || signaled.shutdown().is_ok());
+    first.wait_for_shutdown();
+    assert!(handler.join().unwrap());
+    assert!(!first.health().running);
+
+    second.shutdown().unwrap();
+}
@@ -31,13 +31,12 @@ fn all_updates_received() {
         PeerSamplingConfig::new(push, pull, sampling_period, c, h, s),
         GossipConfig::new(push, pull, gossip_period, update_expiration.clone())
     );
-    service_1.start(
-        Box::new(move|| { None }),
+    service_1.start_isolated(
         Box::new(NoopUpdateHandler)
-    );
+    ).unwrap();
 
     // create second peer
-    let init_handler = Box::new(move|| { Some(vec![Peer::new(initial_peer.to_owned())]) });
+    let init_handler = Box::new(move|| { Some(vec![Peer::try_new(initial_peer).unwrap()]) });
 
     // create and initiate the gossip service
     let mut service_2 = GossipService::new(
@@ -48,7 +47,7 @@ fn all_updates_received() {
     service_2.start(
         init_handler,
         Box::new(NoopUpdateHandler)
-    );
+    ).unwrap();
 
     // initializing peer sampling
     std::thread::sleep(std::time::Duration::from_millis(sampling_period * 2));
@@ -56,7 +55,7 @@ fn all_updates_received() {
     for i in 0..size {
         let message = format!("MSGID {}", i).as_bytes().to_vec();
         let update = Update::new(message.clone());
-        service_2.submit(update.content().to_vec());
+        service_2.submit(update.content().to_vec()).unwrap();
         messages.push(message);
         std::thread::sleep(std::time::Duration::from_millis(20));
     }
@@ -70,7 +69,7 @@ fn all_updates_received() {
     for i in size..2*size {
         let message = format!("MSGID {}", i).as_bytes().to_vec();
         let update = Update::new(message.clone());
-        service_2.submit(update.content().to_vec());
+        service_2.submit(update.content().to_vec()).unwrap();
         std::thread::sleep(std::time::Duration::from_millis(20));
     }
 
@@ -80,6 +79,6 @@ fn all_updates_received() {
         assert!(service_2.is_expired(message));
     }
 
-    service_1.shutdown();
-    service_2.shutdown();
+    service_1.shutdown().unwrap();
+    service_2.shutdown().unwrap();
 }
@@ -11,7 +11,7 @@ fn all_updates_received() {
     common::configure_logging(log::LevelFilter::Info).unwrap();
 
     // messages received by each peer
-    let mut peer_messages = Arc::new(Mutex::new(HashMap::new()));
+    let peer_messages = Arc::new(Mutex::new(HashMap::new()));
 
     // algorithm parameters
     let gossip_period = 400;
@@ -39,15 +39,14 @@ fn all_updates_received() {
         PeerSamplingConfig::new(push, pull, sampling_period, c, h, s),
         GossipConfig::new(push, pull, gossip_period, update_expiration.clone())
     );
-    service.start(no_peer_handler, Box::new(MapUpdatingHandler::new(init_peer.to_owned(), Arc::clone(&peer_messages))));
+    service.start(no_peer_handler, Box::new(MapUpdatingHandler::new(init_peer.to_owned(), Arc::clone(&peer_messages)))).unwrap();
     instances.push(service);
 
-    let mut port = 9001;
-    for i in 1..peer_count {
+    for port in 9001..(9000 + peer_count) {
         // peer socket address
         let address = format!("127.0.0.1:{}", port);
         // closure for retrieving the address of the first contact peer
-        let init_handler = Box::new(move|| { Some(vec![Peer::new(init_peer.to_owned())]) });
+        let init_handler = Box::new(move|| { Some(vec![Peer::try_new(init_peer).unwrap()]) });
 
         // create and initiate the gossip service
         let mut ipv4_service = GossipService::new(
@@ -55,10 +54,8 @@ fn all_updates_received() {
             PeerSamplingConfig::new(push, pull, sampling_period, c, h, s),
             GossipConfig::new(push, pull, gossip_period, update_expiration.clone())
         );
-        ipv4_service.start(init_handler, Box::new(MapUpdatingHandler::new(address.clone(), Arc::clone(&peer_messages))));
+        ipv4_service.start(init_handler, Box::new(MapUpdatingHandler::new(address.clone(), Arc::clone(&peer_messages)))).unwrap();
         instances.push(ipv4_service);
-
-        port += 1;
     }
 
     // wait for peer sampling initialization
@@ -73,9 +70,9 @@ fn all_updates_received() {
         let my_digest = update.digest().clone();
         all_messages.push(update);
         let selected_peer = rand::thread_rng().gen_range(0, instances.len());
-        instances[selected_peer].submit(message);
+        instances[selected_peer].submit(message).unwrap();
         {
-            peer_messages.lock().unwrap().entry(instances[selected_peer].address().to_string()).or_insert(Vec::new()).push(my_digest);
+            peer_messages.lock().unwrap().entry(instances[selected_peer].address().to_string()).or_default().push(my_digest);
         }
         std::thread::sleep(std::time::Duration::from_millis(200));
     }
@@ -92,7 +89,7 @@ fn all_updates_received() {
         }
     }
 
-    for mut instance in instances {
-        instance.shutdown();
+    for instance in instances {
+        instance.shutdown().unwrap();
     }
 }
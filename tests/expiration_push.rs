@@ -31,13 +31,12 @@ fn all_updates_received() {
         PeerSamplingConfig::new(push, pull, sampling_period, c, h, s),
         GossipConfig::new(push, pull, gossip_period, update_expiration.clone())
     );
-    service_1.start(
-        Box::new(move|| { None }),
+    service_1.start_isolated(
         Box::new(NoopUpdateHandler)
-    );
+    ).unwrap();
 
     // create second peer
-    let init_handler = Box::new(move|| { Some(vec![Peer::new(initial_peer.to_owned())]) });
+    let init_handler = Box::new(move|| { Some(vec![Peer::try_new(initial_peer).unwrap()]) });
 
     // create and initiate the gossip service
     let mut service_2 = GossipService::new(
@@ -48,7 +47,7 @@ fn all_updates_received() {
     service_2.start(
         init_handler,
         Box::new(NoopUpdateHandler)
-    );
+    ).unwrap();
 
     // initializing peer sampling
     std::thread::sleep(std::time::Duration::from_millis(sampling_period * 2));
@@ -58,18 +57,19 @@ fn all_updates_received() {
     for i in 0..message_count {
         let message = format!("MSGID {}", i).as_bytes().to_vec();
         let update = Update::new(message.clone());
-        service_2.submit(update.content().to_vec());
+        service_2.submit(update.content().to_vec()).unwrap();
         messages.push(message);
         std::thread::sleep(std::time::Duration::from_millis(20));
     }
 
-    // wait for messages expiration
-    std::thread::sleep(std::time::Duration::from_millis(gossip_period * (push_count + 1)));
-
-    for message in messages {
-        assert!(service_2.is_expired(message));
+    // trigger the rounds pushing the messages until they expire, in less time than the scheduled rounds would take
+    let deadline = std::time::Instant::now() + std::time::Duration::from_millis(gossip_period * 2);
+    while !messages.iter().all(|message| service_2.is_expired(message.clone())) {
+        assert!(std::time::Instant::now() < deadline, "messages did not expire");
+        service_2.trigger_gossip_round().unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(20));
     }
 
-    service_1.shutdown();
-    service_2.shutdown();
+    service_1.shutdown().unwrap();
+    service_2.shutdown().unwrap();
 }
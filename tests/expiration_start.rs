@@ -0,0 +1,44 @@
+mod common;
+
+use std::time::{Duration, Instant};
+use gossip::{ExpirationStart, GossipConfig, GossipService, PeerSamplingConfig, Update, UpdateExpirationMode, UpdateState};
+use common::{start_sampling_node, wait_for, wait_for_within, NoopUpdateHandler};
+
+const TTL: u128 = 500;
+
+fn start_node(port: u16, contacts: &[u16], expiration_start: ExpirationStart) -> GossipService<NoopUpdateHandler> {
+    let mut gossip_config = GossipConfig::new(true, true, 100, UpdateExpirationMode::DurationMillis(TTL));
+    gossip_config.set_expiration_start(expiration_start);
+    start_sampling_node(port, contacts, PeerSamplingConfig::new(true, true, 100, 10, 1, 4), gossip_config, NoopUpdateHandler)
+}
+
+// submits an update on an isolated node, and checks that it expires shortly after reaching a peer that joins later
+fn expires_after_propagation(port: u16, peer_port: u16, expiration_start: ExpirationStart) {
+    let service = start_node(port, &[], expiration_start);
+    service.submit(b"held".to_vec()).unwrap();
+    let digest = Update::new(b"held".to_vec()).digest().to_owned();
+
+    // well beyond the time to live, the update was never sent
+    std::thread::sleep(Duration::from_millis(TTL as u64 * 4));
+    assert!(service.is_active(b"held".to_vec()));
+
+    let peer = start_node(peer_port, &[port], ExpirationStart::OnInsert);
+    wait_for(|| peer.is_active(b"held".to_vec()), "update did not reach the peer");
+    let received = Instant::now();
+    wait_for_within(|| service.update_state(&digest) == Some(UpdateState::Expired),
+        Duration::from_secs(3), "update did not expire after propagation");
+    assert!(received.elapsed() < Duration::from_millis(TTL as u64 * 3), "expired after {:?}", received.elapsed());
+
+    peer.shutdown().unwrap();
+    service.shutdown().unwrap();
+}
+
+#[test]
+fn expiration_starts_on_first_push() {
+    expires_after_propagation(9000, 9001, ExpirationStart::OnFirstPush);
+}
+
+#[test]
+fn expiration_starts_on_first_delivery() {
+    expires_after_propagation(9010, 9011, ExpirationStart::OnFirstDelivery);
+}
@@ -0,0 +1,88 @@
+mod common;
+
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use gossip::{FetchPolicy, GossipConfig, GossipService, PeerSamplingConfig, Update, UpdateExpirationMode};
+use common::{start_sampling_node, wait_for_within, NoopUpdateHandler};
+
+// declines one digest until told otherwise, and counts the calls for it
+struct DecliningPolicy {
+    declined: String,
+    accept_all: AtomicBool,
+    calls: AtomicU64,
+}
+impl FetchPolicy for DecliningPolicy {
+    fn should_fetch(&self, digest: &str, _from: &str) -> bool {
+        if digest != self.declined {
+            return true;
+        }
+        self.calls.fetch_add(1, Ordering::SeqCst);
+        self.accept_all.load(Ordering::SeqCst)
+    }
+}
+
+fn start_node(port: u16, contacts: &[u16], gossip_config: GossipConfig) -> GossipService<NoopUpdateHandler> {
+    start_sampling_node(port, contacts, PeerSamplingConfig::new(true, true, 200, 10, 1, 4), gossip_config, NoopUpdateHandler)
+}
+
+fn wait_active(service: &GossipService<NoopUpdateHandler>, content: &[u8]) {
+    wait_for_within(|| service.is_active(content.to_vec()), Duration::from_secs(10), &format!("update was not received by {}", service.address()));
+}
+
+fn declining_policy(content: &[u8]) -> Arc<DecliningPolicy> {
+    Arc::new(DecliningPolicy {
+        declined: Update::new(content.to_vec()).digest().to_owned(),
+        accept_all: AtomicBool::new(false),
+        calls: AtomicU64::new(0),
+    })
+}
+
+#[test]
+fn declined_digests_are_not_fetched() {
+    let policy = declining_policy(b"unwanted");
+    let mut config = GossipConfig::new(true, true, 100, UpdateExpirationMode::None);
+    config.set_fetch_policy(Some(policy.clone()));
+
+    let sender = start_node(9000, &[], GossipConfig::new(true, true, 100, UpdateExpirationMode::None));
+    let receiver = start_node(9001, &[9000], config);
+    sender.submit(b"unwanted".to_vec()).unwrap();
+    sender.submit(b"wanted".to_vec()).unwrap();
+
+    // accepted digests are fetched
+    wait_active(&receiver, b"wanted");
+    // let more rounds advertise the declined digest
+    std::thread::sleep(Duration::from_millis(1000));
+
+    assert!(!receiver.is_active(b"unwanted".to_vec()));
+    // the declined update is neither active nor expired
+    assert_eq!(None, receiver.update_state(&policy.declined));
+    // the policy is not consulted again for a declined digest
+    assert_eq!(1, policy.calls.load(Ordering::SeqCst));
+    assert_eq!(1, receiver.stats().declined_fetches);
+
+    receiver.shutdown().unwrap();
+    sender.shutdown().unwrap();
+}
+
+#[test]
+fn declined_digests_are_reconsidered_after_ttl() {
+    let policy = declining_policy(b"later");
+    let mut config = GossipConfig::new(true, true, 100, UpdateExpirationMode::None);
+    config.set_fetch_policy(Some(policy.clone()));
+    config.set_declined_fetch_ttl(200);
+
+    let sender = start_node(9010, &[], GossipConfig::new(true, true, 100, UpdateExpirationMode::None));
+    let receiver = start_node(9011, &[9010], config);
+    sender.submit(b"later".to_vec()).unwrap();
+
+    wait_for_within(|| policy.calls.load(Ordering::SeqCst) >= 2, Duration::from_secs(10), "declined digest was not reconsidered");
+    assert!(!receiver.is_active(b"later".to_vec()));
+
+    // the changed policy applies once the digest is reconsidered
+    policy.accept_all.store(true, Ordering::SeqCst);
+    wait_active(&receiver, b"later");
+
+    receiver.shutdown().unwrap();
+    sender.shutdown().unwrap();
+}
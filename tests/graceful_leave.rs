@@ -0,0 +1,55 @@
+mod common;
+
+use gossip::{GossipConfig, PeerSamplingConfig, Peer, GossipService};
+use common::NoopUpdateHandler;
+
+const SAMPLING_PERIOD: u64 = 300;
+const NODE_COUNT: usize = 5;
+
+fn contains(service: &GossipService<NoopUpdateHandler>, address: &str) -> bool {
+    service.peers().iter().any(|peer| peer.address() == address)
+}
+
+#[test]
+fn departed_node_is_removed_from_views() {
+    let addresses: Vec<String> = (0..NODE_COUNT).map(|i| format!("127.0.0.1:{}", 9000 + i)).collect();
+    let mut services = Vec::new();
+    for address in &addresses {
+        let mut sampling_config = PeerSamplingConfig::new(true, true, SAMPLING_PERIOD, 10, 1, 4);
+        sampling_config.set_graceful_leave(Some(1000));
+        let mut service = GossipService::new(
+            address.parse().unwrap(),
+            sampling_config,
+            GossipConfig::new(true, true, 200, gossip::UpdateExpirationMode::None)
+        );
+        let seed = addresses[0].clone();
+        service.start_with_peers(
+            vec![Peer::try_new(&seed).unwrap()],
+            Box::new(NoopUpdateHandler)
+        ).unwrap();
+        services.push(service);
+    }
+
+    // wait for views to converge
+    std::thread::sleep(std::time::Duration::from_millis(SAMPLING_PERIOD * 10));
+    let leaving_address = addresses[NODE_COUNT - 1].clone();
+    assert!(services[..NODE_COUNT - 1].iter().any(|service| contains(service, &leaving_address)));
+
+    let leaving = services.pop().unwrap();
+    leaving.shutdown().unwrap();
+
+    std::thread::sleep(std::time::Duration::from_millis(SAMPLING_PERIOD));
+    for service in &services {
+        assert!(!contains(service, &leaving_address), "{} still has departed peer", service.address());
+    }
+
+    // buffers still containing the departed node do not reinsert it
+    std::thread::sleep(std::time::Duration::from_millis(SAMPLING_PERIOD * 3));
+    for service in &services {
+        assert!(!contains(service, &leaving_address), "{} reinserted departed peer", service.address());
+    }
+
+    for service in services {
+        service.shutdown().unwrap();
+    }
+}
@@ -0,0 +1,49 @@
+mod common;
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use gossip::{GossipConfig, GossipService, PeerSamplingConfig, Update, UpdateExpirationMode, UpdateHandler};
+use common::{start_sampling_node, wait_for_within};
+
+// records the callbacks in the order they are called
+struct EventHandler {
+    events: Arc<Mutex<Vec<String>>>,
+}
+impl UpdateHandler for EventHandler {
+    fn on_update(&self, update: Update) {
+        self.events.lock().unwrap().push(format!("update {}", update.digest()));
+    }
+    fn on_header(&self, digest: &str, from: &str) {
+        self.events.lock().unwrap().push(format!("header {} from {}", digest, from));
+    }
+}
+
+fn start_node(port: u16, contacts: &[u16], events: &Arc<Mutex<Vec<String>>>) -> GossipService<EventHandler> {
+    start_sampling_node(port, contacts, PeerSamplingConfig::new(true, true, 200, 10, 1, 4),
+        GossipConfig::new(true, true, 100, UpdateExpirationMode::None), EventHandler { events: Arc::clone(events) })
+}
+
+#[test]
+fn header_is_announced_before_update() {
+    let sender_events = Arc::new(Mutex::new(Vec::new()));
+    let receiver_events = Arc::new(Mutex::new(Vec::new()));
+    let sender = start_node(9000, &[], &sender_events);
+    let receiver = start_node(9001, &[9000], &receiver_events);
+
+    let content = b"announced".to_vec();
+    let digest = Update::new(content.clone()).digest().to_owned();
+    sender.submit(content).unwrap();
+
+    let update_event = format!("update {}", digest);
+    wait_for_within(|| receiver_events.lock().unwrap().contains(&update_event), Duration::from_secs(10), "update was not received");
+    // let more rounds advertise the digest
+    std::thread::sleep(Duration::from_millis(500));
+
+    let events = receiver_events.lock().unwrap().clone();
+    assert_eq!(vec![format!("header {} from 127.0.0.1:9000", digest), update_event], events);
+    // local updates are not announced
+    assert!(sender_events.lock().unwrap().is_empty());
+
+    receiver.shutdown().unwrap();
+    sender.shutdown().unwrap();
+}
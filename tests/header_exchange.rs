@@ -0,0 +1,166 @@
+mod common;
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use gossip::{GossipConfig, PeerSamplingConfig, Peer, GossipService, Update, UpdateExpirationMode};
+use common::MapUpdatingHandler;
+
+const SAMPLING_PERIOD: u64 = 500;
+const GOSSIP_PERIOD: u64 = 300;
+
+fn start_pair(address_1: &'static str, address_2: &'static str, gossip_1: GossipConfig, gossip_2: GossipConfig, received: &Arc<Mutex<HashMap<String, Vec<String>>>>)
+    -> (GossipService<MapUpdatingHandler>, GossipService<MapUpdatingHandler>) {
+
+    let mut service_1 = GossipService::new(
+        address_1.parse().unwrap(),
+        PeerSamplingConfig::new(true, true, SAMPLING_PERIOD, 10, 1, 4),
+        gossip_1
+    );
+    service_1.start_isolated(
+        Box::new(MapUpdatingHandler::new(address_1.to_owned(), Arc::clone(received)))
+    ).unwrap();
+
+    let mut service_2 = GossipService::new(
+        address_2.parse().unwrap(),
+        PeerSamplingConfig::new(true, true, SAMPLING_PERIOD, 10, 1, 4),
+        gossip_2
+    );
+    service_2.start_with_peers(
+        vec![Peer::try_new(address_1).unwrap()],
+        Box::new(MapUpdatingHandler::new(address_2.to_owned(), Arc::clone(received)))
+    ).unwrap();
+
+    // wait for peer discovery
+    std::thread::sleep(std::time::Duration::from_millis(SAMPLING_PERIOD * 3));
+
+    (service_1, service_2)
+}
+
+fn received_by(received: &Arc<Mutex<HashMap<String, Vec<String>>>>, address: &str, content: &[u8]) -> bool {
+    received.lock().unwrap().get(address)
+        .is_some_and(|digests| digests.contains(Update::new(content.to_vec()).digest()))
+}
+
+#[test]
+fn push_only_cluster_sends_no_header_responses() {
+    let received = Arc::new(Mutex::new(HashMap::new()));
+    let (service_1, service_2) = start_pair(
+        "127.0.0.1:9000", "127.0.0.1:9001",
+        GossipConfig::new(true, false, GOSSIP_PERIOD, UpdateExpirationMode::None),
+        GossipConfig::new(true, false, GOSSIP_PERIOD, UpdateExpirationMode::None),
+        &received
+    );
+
+    let content = "push only".as_bytes();
+    service_1.submit(content.to_vec()).unwrap();
+    std::thread::sleep(std::time::Duration::from_millis(GOSSIP_PERIOD * 5));
+
+    assert!(received_by(&received, "127.0.0.1:9001", content));
+    for service in &[&service_1, &service_2] {
+        let stats = service.stats();
+        assert_eq!(0, stats.header_responses_sent);
+        assert_eq!(0, stats.ignored_header_responses);
+    }
+
+    service_1.shutdown().unwrap();
+    service_2.shutdown().unwrap();
+}
+
+#[test]
+fn push_requests_are_not_answered_by_pulling_peer() {
+    let received = Arc::new(Mutex::new(HashMap::new()));
+    let (service_1, service_2) = start_pair(
+        "127.0.0.1:9010", "127.0.0.1:9011",
+        GossipConfig::new(true, true, GOSSIP_PERIOD, UpdateExpirationMode::None),
+        GossipConfig::new(true, false, GOSSIP_PERIOD, UpdateExpirationMode::None),
+        &received
+    );
+
+    let content = "pushed to a pulling peer".as_bytes();
+    service_2.submit(content.to_vec()).unwrap();
+    std::thread::sleep(std::time::Duration::from_millis(GOSSIP_PERIOD * 5));
+
+    assert!(received_by(&received, "127.0.0.1:9010", content));
+    assert_eq!(0, service_1.stats().header_responses_sent);
+    assert_eq!(0, service_2.stats().header_responses_sent);
+
+    service_1.shutdown().unwrap();
+    service_2.shutdown().unwrap();
+}
+
+#[test]
+fn pull_only_cluster_answers_probes() {
+    let received = Arc::new(Mutex::new(HashMap::new()));
+    let (service_1, service_2) = start_pair(
+        "127.0.0.1:9020", "127.0.0.1:9021",
+        GossipConfig::new(false, true, GOSSIP_PERIOD, UpdateExpirationMode::None),
+        GossipConfig::new(false, true, GOSSIP_PERIOD, UpdateExpirationMode::None),
+        &received
+    );
+
+    let content = "pull only".as_bytes();
+    service_1.submit(content.to_vec()).unwrap();
+    std::thread::sleep(std::time::Duration::from_millis(GOSSIP_PERIOD * 5));
+
+    assert!(received_by(&received, "127.0.0.1:9021", content));
+    assert!(service_1.stats().header_responses_sent > 0);
+    assert_eq!(0, service_2.stats().ignored_header_responses);
+
+    service_1.shutdown().unwrap();
+    service_2.shutdown().unwrap();
+}
+
+fn sent_headers(service: &GossipService<MapUpdatingHandler>) -> u64 {
+    service.traffic().iter().map(|peer| peer.sent.header.messages).sum()
+}
+
+#[test]
+fn idle_push_only_node_sends_no_empty_pushes() {
+    let received = Arc::new(Mutex::new(HashMap::new()));
+    let (service_1, service_2) = start_pair(
+        "127.0.0.1:9030", "127.0.0.1:9031",
+        GossipConfig::new(true, true, GOSSIP_PERIOD, UpdateExpirationMode::None),
+        GossipConfig::new(true, false, GOSSIP_PERIOD, UpdateExpirationMode::None),
+        &received
+    );
+    std::thread::sleep(std::time::Duration::from_millis(GOSSIP_PERIOD * 3));
+
+    // the idle node has nothing to push, and its peer has nothing to answer
+    assert!(service_2.stats().skipped_empty_pushes > 0);
+    assert_eq!(0, sent_headers(&service_2));
+    assert_eq!(0, service_1.stats().header_responses_sent);
+    assert_eq!(0, service_1.stats().suppressed_header_responses);
+
+    // an update is still pushed once submitted
+    let content = "pushed after idle rounds".as_bytes();
+    service_2.submit(content.to_vec()).unwrap();
+    std::thread::sleep(std::time::Duration::from_millis(GOSSIP_PERIOD * 5));
+    assert!(received_by(&received, "127.0.0.1:9030", content));
+    assert!(sent_headers(&service_2) > 0);
+    assert_eq!(0, service_1.stats().header_responses_sent);
+
+    service_1.shutdown().unwrap();
+    service_2.shutdown().unwrap();
+}
+
+#[test]
+fn empty_pushes_are_not_answered() {
+    let received = Arc::new(Mutex::new(HashMap::new()));
+    let mut push_only = GossipConfig::new(true, false, GOSSIP_PERIOD, UpdateExpirationMode::None);
+    push_only.set_empty_pushes(true);
+    let (service_1, service_2) = start_pair(
+        "127.0.0.1:9040", "127.0.0.1:9041",
+        GossipConfig::new(true, true, GOSSIP_PERIOD, UpdateExpirationMode::None),
+        push_only,
+        &received
+    );
+    std::thread::sleep(std::time::Duration::from_millis(GOSSIP_PERIOD * 3));
+
+    assert_eq!(0, service_2.stats().skipped_empty_pushes);
+    assert!(sent_headers(&service_2) > 0);
+    assert_eq!(0, service_1.stats().header_responses_sent);
+    assert!(service_1.stats().suppressed_header_responses > 0);
+
+    service_1.shutdown().unwrap();
+    service_2.shutdown().unwrap();
+}
@@ -0,0 +1,60 @@
+mod common;
+
+use std::time::Duration;
+use gossip::{GossipConfig, GossipService, Peer, PeerSamplingConfig, UpdateExpirationMode};
+use common::NoopUpdateHandler;
+
+#[test]
+fn isolated_node_is_not_ready() {
+    let mut service = GossipService::new(
+        "127.0.0.1:9000".parse().unwrap(),
+        PeerSamplingConfig::new(true, true, 200, 10, 1, 4),
+        GossipConfig::new(true, true, 200, UpdateExpirationMode::None)
+    );
+    // the bootstrap peer is unreachable
+    service.start_with_peers(vec![Peer::try_new("127.1.0.1:1").unwrap()], Box::new(NoopUpdateHandler)).unwrap();
+    std::thread::sleep(Duration::from_millis(500));
+
+    let health = service.health();
+    assert!(health.running);
+    assert!(health.listener_bound);
+    assert!(!health.ready);
+    assert_eq!(None, health.seconds_since_last_successful_exchange);
+    assert_eq!(None, health.seconds_since_last_received_message);
+
+    service.shutdown().unwrap();
+    let health = service.health();
+    assert!(!health.running);
+    assert!(!health.listener_bound);
+    assert!(!health.ready);
+}
+
+#[test]
+fn nodes_are_ready_after_exchange() {
+    let mut seed = GossipService::new(
+        "127.0.0.1:9010".parse().unwrap(),
+        PeerSamplingConfig::new(true, true, 100, 10, 1, 4),
+        GossipConfig::new(true, true, 200, UpdateExpirationMode::None)
+    );
+    seed.start_isolated(Box::new(NoopUpdateHandler)).unwrap();
+    let mut node = GossipService::new(
+        "127.0.0.1:9011".parse().unwrap(),
+        PeerSamplingConfig::new(true, true, 100, 10, 1, 4),
+        GossipConfig::new(true, true, 200, UpdateExpirationMode::None)
+    );
+    node.start_with_peers(vec![Peer::try_new("127.0.0.1:9010").unwrap()], Box::new(NoopUpdateHandler)).unwrap();
+    std::thread::sleep(Duration::from_millis(1000));
+
+    for service in [&seed, &node] {
+        let health = service.health();
+        assert!(health.running);
+        assert!(health.ready);
+        assert_eq!(1, health.peer_count);
+        assert!(health.seconds_since_last_successful_exchange.is_some());
+        assert!(health.seconds_since_last_received_message.is_some());
+        assert!(!health.degraded);
+    }
+
+    node.shutdown().unwrap();
+    seed.shutdown().unwrap();
+}
@@ -0,0 +1,65 @@
+mod common;
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use gossip::{GossipConfig, GossipService, RoundObserver, RoundSummary, SamplingSummary, UpdateExpirationMode};
+use common::{start_static_node, wait_for_within, NoopUpdateHandler};
+
+const GOSSIP_PERIOD: u64 = 100;
+const PORTS: [u16; 2] = [9000, 9001];
+
+// counts the gossip rounds during which a header request was sent
+#[derive(Default)]
+struct RoundCounter {
+    rounds: AtomicU64,
+}
+impl RoundObserver for RoundCounter {
+    fn on_gossip_round(&self, summary: RoundSummary) {
+        if summary.sent {
+            self.rounds.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+    fn on_sampling_round(&self, _summary: SamplingSummary) {}
+}
+
+fn start_node(port: u16, idle: bool) -> GossipService<NoopUpdateHandler> {
+    let mut config = GossipConfig::new(true, true, GOSSIP_PERIOD, UpdateExpirationMode::None);
+    if idle {
+        config.set_idle_backoff(3, 2, 16 * GOSSIP_PERIOD);
+    }
+    start_static_node(port, &PORTS, config, NoopUpdateHandler)
+}
+
+fn wait_active(service: &GossipService<NoopUpdateHandler>, content: &[u8], timeout: Duration) {
+    wait_for_within(|| service.is_active(content.to_vec()), timeout, &format!("update was not received within {:?}", timeout));
+}
+
+#[test]
+fn idle_nodes_gossip_less_often() {
+    let first = start_node(PORTS[0], true);
+    let second = start_node(PORTS[1], true);
+    first.submit(b"converged".to_vec()).unwrap();
+    wait_active(&second, b"converged", Duration::from_secs(5));
+
+    // let the period stretch to its maximum
+    std::thread::sleep(Duration::from_millis(4000));
+    assert_eq!(16 * GOSSIP_PERIOD, second.stats().effective_gossip_period);
+    let counter = Arc::new(RoundCounter::default());
+    first.set_round_observer(Some(counter.clone()));
+    second.set_round_observer(Some(counter.clone()));
+    std::thread::sleep(Duration::from_millis(3000));
+    // about 20 rounds per node without idle backoff
+    let idle_rounds = counter.rounds.load(Ordering::SeqCst);
+    assert!(idle_rounds <= 8, "{} rounds while idle", idle_rounds);
+
+    // a new update snaps the period back
+    let submitted = Instant::now();
+    first.submit(b"new".to_vec()).unwrap();
+    wait_active(&second, b"new", Duration::from_millis(4 * GOSSIP_PERIOD));
+    assert!(submitted.elapsed() < Duration::from_millis(4 * GOSSIP_PERIOD));
+    assert_eq!(GOSSIP_PERIOD, first.stats().effective_gossip_period);
+
+    second.shutdown().unwrap();
+    first.shutdown().unwrap();
+}
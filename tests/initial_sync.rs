@@ -0,0 +1,55 @@
+mod common;
+
+use std::time::{Duration, Instant};
+use gossip::{GossipConfig, GossipService, NotStarted, Peer, SyncPolicy, UpdateExpirationMode};
+use common::{start_static_node, static_node, wait_for_within, NoopUpdateHandler};
+
+fn update(i: usize) -> Vec<u8> {
+    format!("update {}", i).into_bytes()
+}
+
+#[test]
+fn node_is_ready_after_fetching_the_active_updates() {
+    let seed = start_static_node(9000, &[], GossipConfig::new(true, true, 100, UpdateExpirationMode::None), NoopUpdateHandler);
+    for i in 0..50 {
+        seed.submit(update(i)).unwrap();
+    }
+
+    let mut gossip_config = GossipConfig::new(true, true, 100, UpdateExpirationMode::None);
+    gossip_config.set_initial_sync(SyncPolicy::FromBootstrap { timeout: 10_000 });
+    let mut node: GossipService<NoopUpdateHandler> = static_node(9001, &[9000], gossip_config);
+    assert!(node.wait_initial_sync().unwrap_err().is::<NotStarted>());
+    node.start_isolated(Box::new(NoopUpdateHandler)).unwrap();
+
+    wait_for_within(|| node.health().ready, Duration::from_secs(10), "node is not ready");
+    assert_eq!(50, node.health().active_updates);
+    assert!((0..50).all(|i| node.is_active(update(i))));
+    assert!(node.wait_initial_sync().unwrap());
+
+    node.shutdown().unwrap();
+    seed.shutdown().unwrap();
+}
+
+#[test]
+fn sync_times_out_without_answer() {
+    let mut gossip_config = GossipConfig::new(true, true, 100, UpdateExpirationMode::None);
+    gossip_config.set_initial_sync(SyncPolicy::FromBootstrap { timeout: 300 });
+    let mut node: GossipService<NoopUpdateHandler> = static_node(9010, &[], gossip_config);
+    // the bootstrap peer is unreachable
+    node.start_with_peers(vec![Peer::try_new("127.1.0.1:1").unwrap()], Box::new(NoopUpdateHandler)).unwrap();
+    assert!(!node.health().ready);
+
+    let started = Instant::now();
+    assert!(!node.wait_initial_sync().unwrap());
+    assert!(started.elapsed() < Duration::from_secs(2));
+    assert!(node.health().ready);
+
+    node.shutdown().unwrap();
+}
+
+#[test]
+fn initial_sync_requires_pull() {
+    let mut gossip_config = GossipConfig::new(true, false, 100, UpdateExpirationMode::None);
+    gossip_config.set_initial_sync(SyncPolicy::FromBootstrap { timeout: 300 });
+    assert!(gossip_config.validate().is_err());
+}
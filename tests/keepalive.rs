@@ -0,0 +1,47 @@
+mod common;
+
+use std::time::{Duration, Instant};
+use gossip::{GossipConfig, GossipService, PeerSamplingConfig, UpdateExpirationMode};
+use common::{start_sampling_node, wait_for, NoopUpdateHandler};
+
+/// Starts a node whose periods are too long for gossip or sampling to tell that a peer is gone during the test
+fn start_node(port: u16, contacts: &[u16], keepalive: Option<(u64, u32)>) -> GossipService<NoopUpdateHandler> {
+    let mut gossip_config = GossipConfig::new(true, true, 60_000, UpdateExpirationMode::None);
+    if let Some((interval, max_missed)) = keepalive {
+        gossip_config.set_keepalive(interval, max_missed);
+    }
+    start_sampling_node(port, contacts, PeerSamplingConfig::new(true, true, 60_000, 10, 1, 4), gossip_config, NoopUpdateHandler)
+}
+
+fn last_seen(node: &GossipService<NoopUpdateHandler>, peer: &str) -> Option<Instant> {
+    node.peer_info().into_iter().find(|info| info.address == peer).and_then(|info| info.last_seen)
+}
+
+#[test]
+fn unanswered_pings_remove_a_dead_peer_from_the_view() {
+    let peer = "127.0.0.1:9001";
+    let b = start_node(9001, &[], None);
+    let a = start_node(9000, &[9001], Some((100, 3)));
+
+    // the pongs of the live peer keep it in the view
+    wait_for(|| last_seen(&a, peer).is_some(), "no pong received");
+    let first = last_seen(&a, peer).unwrap();
+    std::thread::sleep(Duration::from_millis(500));
+    let second = last_seen(&a, peer).expect("peer removed while alive");
+    assert!(second > first);
+    assert!(a.stats().keepalive_pings_sent > 0);
+    assert_eq!(0, a.stats().unreachable_peers);
+
+    b.shutdown().unwrap();
+    let stopped = Instant::now();
+    wait_for(|| {
+        // the peer is no longer heard from
+        assert!(last_seen(&a, peer).is_none_or(|seen| seen < stopped));
+        a.peers().iter().all(|known| known.address() != peer)
+    }, "dead peer was not removed");
+    // well before the sampling period would have aged the peer out
+    assert!(stopped.elapsed() < Duration::from_secs(2));
+    assert_eq!(1, a.stats().unreachable_peers);
+
+    a.shutdown().unwrap();
+}
@@ -0,0 +1,66 @@
+mod common;
+
+use std::cell::Cell;
+use std::time::{Duration, Instant};
+use gossip::{GossipConfig, GossipService, PeerSamplingConfig, Update, UpdateExpirationMode};
+use common::{start_sampling_node, wait_for_within, NoopUpdateHandler};
+
+fn start_pair(first: u16, second: u16, verify: bool) -> (GossipService<NoopUpdateHandler>, GossipService<NoopUpdateHandler>) {
+    let start_node = |port: u16, contacts: &[u16]| {
+        let mut gossip_config = GossipConfig::new(true, true, 100, UpdateExpirationMode::None);
+        gossip_config.set_verify_content_digests(verify);
+        // content messages of several megabytes take longer than the default connection deadline to read in debug builds
+        gossip_config.set_connection_limits(60_000, 64);
+        start_sampling_node(port, contacts, PeerSamplingConfig::new(true, true, 100, 10, 1, 4), gossip_config, NoopUpdateHandler)
+    };
+    (start_node(first, &[]), start_node(second, &[first]))
+}
+
+// submits large updates on the first node, and returns the longest time for which the updates of the second
+// node could not be read while they were received
+fn transfer_large_updates(first: &GossipService<NoopUpdateHandler>, second: &GossipService<NoopUpdateHandler>, count: usize, size: usize) -> Duration {
+    let digests: Vec<String> = (0..count)
+        .map(|index| {
+            let mut content = vec![0; size];
+            content[..8].copy_from_slice(&(index as u64).to_be_bytes());
+            let digest = Update::new(content.clone()).digest().to_owned();
+            first.submit(content).unwrap();
+            digest
+        })
+        .collect();
+
+    let longest_read = Cell::new(Duration::default());
+    wait_for_within(|| {
+        let read_start = Instant::now();
+        let received = digests.iter().filter(|digest| second.update_state(digest).is_some()).count();
+        longest_read.set(longest_read.get().max(read_start.elapsed()));
+        received == count
+    }, Duration::from_secs(120), "large updates were not all received");
+    longest_read.get()
+}
+
+#[test]
+fn large_updates_do_not_block_other_handlers() {
+    let (first, second) = start_pair(9000, 9001, true);
+    std::thread::sleep(Duration::from_millis(300));
+
+    // 100 updates of 1 MB
+    let longest_read = transfer_large_updates(&first, &second, 100, 1 << 20);
+    assert!(longest_read < Duration::from_millis(500), "updates were locked for {:?}", longest_read);
+    assert_eq!(0, second.stats().digest_verification_failures);
+
+    second.shutdown().unwrap();
+    first.shutdown().unwrap();
+}
+
+#[test]
+fn unverified_large_updates_are_received() {
+    let (first, second) = start_pair(9010, 9011, false);
+    std::thread::sleep(Duration::from_millis(300));
+
+    transfer_large_updates(&first, &second, 10, 1 << 16);
+    assert_eq!(10, second.health().active_updates);
+
+    second.shutdown().unwrap();
+    first.shutdown().unwrap();
+}
@@ -0,0 +1,67 @@
+mod common;
+
+use gossip::{GossipConfig, GossipService, JoinState, NotStarted, PeerSamplingConfig, SamplingState, UpdateExpirationMode};
+use common::{peer, sampling_node, start_static_node, wait_for, RecordingHandler};
+
+fn new_service(port: u16) -> GossipService<RecordingHandler> {
+    sampling_node(port, PeerSamplingConfig::new(true, true, 60_000, 10, 1, 4), GossipConfig::new(true, true, 100, UpdateExpirationMode::None))
+}
+
+#[test]
+fn node_listens_before_joining() {
+    let received = RecordingHandler::default();
+    let mut listening = new_service(9000);
+    assert_eq!(JoinState::NotStarted, listening.join_state());
+    listening.start_listening(Box::new(received.clone())).unwrap();
+
+    let health = listening.health();
+    assert_eq!(JoinState::ListeningOnly, health.join_state);
+    assert!(health.listener_bound);
+    assert!(health.running);
+    assert!(!health.ready);
+    assert_eq!(SamplingState::NotStarted, listening.sampling_state());
+
+    // a pushed header is fetched and delivered before the node joins
+    let pushing = start_static_node(9001, &[9000], GossipConfig::new(true, true, 100, UpdateExpirationMode::None), RecordingHandler::default());
+    pushing.submit(b"pushed".to_vec()).unwrap();
+    wait_for(|| !received.received().is_empty(), "pushed update was not delivered");
+    assert_eq!(vec![b"pushed".to_vec()], received.received());
+    assert_eq!(JoinState::ListeningOnly, listening.join_state());
+
+    listening.join(vec![peer(9001)]).unwrap();
+    assert_eq!(JoinState::Joined, listening.join_state());
+    assert_eq!(JoinState::Joined, listening.health().join_state);
+    assert_eq!(SamplingState::Running, listening.sampling_state());
+    assert!(listening.peers().iter().any(|peer| peer.address() == "127.0.0.1:9001"));
+
+    // joining again merges the peers into the view
+    listening.join(vec![peer(9002)]).unwrap();
+    let addresses: Vec<String> = listening.peers().iter().map(|peer| peer.address().to_owned()).collect();
+    assert!(addresses.contains(&"127.0.0.1:9001".to_owned()), "{:?}", addresses);
+    assert!(addresses.contains(&"127.0.0.1:9002".to_owned()), "{:?}", addresses);
+
+    // updates submitted once joined are gossiped as usual
+    listening.submit(b"joined".to_vec()).unwrap();
+    wait_for(|| pushing.is_active(b"joined".to_vec()), "update of the joined node was not disseminated");
+
+    listening.shutdown().unwrap();
+    pushing.shutdown().unwrap();
+    assert_eq!(JoinState::ShutDown, listening.join_state());
+}
+
+#[test]
+fn listening_node_can_be_shut_down() {
+    let mut service = new_service(9010);
+    service.start_listening(Box::new(RecordingHandler::default())).unwrap();
+    service.shutdown().unwrap();
+    assert_eq!(JoinState::ShutDown, service.health().join_state);
+    assert!(!service.health().running);
+    assert_eq!(SamplingState::NotStarted, service.sampling_state());
+}
+
+#[test]
+fn join_requires_listening() {
+    let mut service = new_service(9020);
+    assert!(service.join(vec![peer(9021)]).unwrap_err().is::<NotStarted>());
+    assert_eq!(JoinState::NotStarted, service.join_state());
+}
@@ -0,0 +1,66 @@
+mod common;
+
+use std::sync::Mutex;
+use gossip::{log_targets, GossipConfig, PeerSamplingConfig, UpdateExpirationMode};
+use common::{start_sampling_node, wait_for, NoopUpdateHandler};
+
+const NODES: [&str; 2] = ["127.0.0.1:9000", "127.0.0.1:9001"];
+
+/// Records the target and the message of every log record of the library
+struct CapturingLogger {
+    records: Mutex<Vec<(String, String)>>,
+}
+impl log::Log for CapturingLogger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        metadata.target().starts_with("gossip")
+    }
+    fn log(&self, record: &log::Record) {
+        if self.enabled(record.metadata()) {
+            self.records.lock().unwrap().push((record.target().to_owned(), record.args().to_string()));
+        }
+    }
+    fn flush(&self) {}
+}
+
+static LOGGER: CapturingLogger = CapturingLogger { records: Mutex::new(Vec::new()) };
+
+fn logged(target: &str, node: &str, message: &str) -> bool {
+    let prefix = format!("[{}] {}", node, message);
+    LOGGER.records.lock().unwrap().iter().any(|(logged_target, logged)| logged_target == target && logged.starts_with(&prefix))
+}
+
+#[test]
+fn messages_are_logged_under_the_target_of_their_subsystem() {
+    log::set_logger(&LOGGER).map(|()| log::set_max_level(log::LevelFilter::Trace)).unwrap();
+    let start_node = |port: u16, contacts: &[u16]| start_sampling_node(
+        port,
+        contacts,
+        PeerSamplingConfig::new(true, true, 50, 10, 1, 4),
+        GossipConfig::new(true, true, 50, UpdateExpirationMode::None),
+        NoopUpdateHandler
+    );
+    let a = start_node(9000, &[]);
+    let b = start_node(9001, &[9000]);
+    a.submit(b"logged update".to_vec()).unwrap();
+
+    wait_for(|| b.is_active(b"logged update".to_vec()), "update did not propagate");
+    b.shutdown().unwrap();
+    a.shutdown().unwrap();
+
+    for node in NODES {
+        assert!(logged(log_targets::SAMPLING, node, "Started peer sampling"));
+        assert!(logged(log_targets::GOSSIP, node, "Timer thread started"));
+        assert!(logged(log_targets::NETWORK, node, "Started listener thread"));
+        assert!(logged(log_targets::NETWORK, node, "Started message handling thread"));
+        assert!(logged(log_targets::GOSSIP, node, "Shutdown requested"));
+    }
+    assert!(logged(log_targets::UPDATES, NODES[0], "New update for submission"));
+    assert!(logged(log_targets::UPDATES, NODES[1], "New update received"));
+
+    // every message of the library is logged under one of the targets, with the address of its node
+    let targets = [log_targets::SAMPLING, log_targets::GOSSIP, log_targets::NETWORK, log_targets::UPDATES, log_targets::MONITOR];
+    for (target, message) in LOGGER.records.lock().unwrap().iter() {
+        assert!(targets.contains(&target.as_str()), "unexpected target {} of {}", target, message);
+        assert!(NODES.iter().any(|node| message.starts_with(&format!("[{}] ", node))), "no node prefix: {}", message);
+    }
+}
@@ -0,0 +1,92 @@
+mod common;
+
+use std::time::{Duration, Instant};
+use gossip::{GossipConfig, GossipService, MemoryReport, PeerSamplingConfig, UpdateExpirationMode, MAX_TRAFFIC_PEERS};
+use common::{start_sampling_node, wait_for, NoopUpdateHandler};
+
+fn start_node(port: u16, contacts: &[u16], expiration: UpdateExpirationMode) -> GossipService<NoopUpdateHandler> {
+    let mut gossip_config = GossipConfig::new(true, true, 100, expiration);
+    gossip_config.set_tombstones(100, 1000);
+    start_sampling_node(port, contacts, PeerSamplingConfig::new(true, true, 100, 10, 1, 4), gossip_config, NoopUpdateHandler)
+}
+
+#[test]
+fn memory_report_counts_active_updates() {
+    let a = start_node(9000, &[], UpdateExpirationMode::None);
+    let b = start_node(9001, &[9000], UpdateExpirationMode::None);
+    let contents: Vec<Vec<u8>> = (0..10).map(|i| vec![i; 1000]).collect();
+    a.submit_batch(contents.clone()).unwrap();
+
+    wait_for(|| contents.iter().all(|content| b.is_active(content.clone())), "updates did not propagate");
+    for node in [&a, &b] {
+        let report = node.memory_report();
+        assert_eq!(10, report.active_updates.count);
+        assert!(report.active_updates.bytes >= 10 * 1000);
+        assert_eq!(0, report.removed_digests.count);
+        assert!(report.total_bytes() >= report.active_updates.bytes);
+        let sampler = report.sampler.expect("the sampling service reports its view");
+        assert_eq!(1, sampler.view.count);
+    }
+
+    b.shutdown().unwrap();
+    a.shutdown().unwrap();
+}
+
+/// Checks the sizes of the structures of a node against bounds that do not depend on the length of the run
+fn assert_bounded(report: &MemoryReport) {
+    assert!(report.active_updates.count <= 2_000, "{:?}", report);
+    assert!(report.removed_digests.count <= 15_000, "{:?}", report);
+    assert!(report.tombstones.count <= 100, "{:?}", report);
+    assert!(report.expired_counters.count <= 1_000, "{:?}", report);
+    assert!(report.expiration_queues.count <= 4_000, "{:?}", report);
+    assert!(report.pending_deliveries.count <= 1_000, "{:?}", report);
+    assert_eq!(0, report.delivery_retries.count, "{:?}", report);
+    assert!(report.content_requests.count <= 2_000, "{:?}", report);
+    assert!(report.digests_seen.count <= 15_000, "{:?}", report);
+    assert!(report.traffic_peers.count <= MAX_TRAFFIC_PEERS, "{:?}", report);
+    let sampler = report.sampler.as_ref().unwrap();
+    assert!(sampler.view.count <= 10, "{:?}", report);
+    assert!(sampler.queued_peers.count <= 10, "{:?}", report);
+}
+
+/// Two nodes exchanging expiring updates for a few minutes (`GOSSIP_SOAK_SECS`, 180 by default): the sizes of their
+/// structures must stop growing once the structures with the longest retention, the removed digests and the digests
+/// seen, are full.
+#[test]
+#[ignore]
+fn soak_memory_stays_bounded() {
+    let duration = Duration::from_secs(std::env::var("GOSSIP_SOAK_SECS").ok().and_then(|secs| secs.parse().ok()).unwrap_or(180));
+    let warm_up = Duration::from_secs(90).min(duration / 2);
+    let a = start_node(9002, &[], UpdateExpirationMode::DurationMillis(500));
+    let b = start_node(9003, &[9002], UpdateExpirationMode::DurationMillis(500));
+
+    let start = Instant::now();
+    let mut next_report = start;
+    let mut warm_up_bytes = [0usize; 2];
+    let mut round = 0u64;
+    while start.elapsed() < duration {
+        for (i, node) in [&a, &b].iter().enumerate() {
+            let contents = (0..5).map(|j| format!("{}-{}-{}", i, round, j).repeat(20).into_bytes()).collect();
+            node.submit_batch(contents).unwrap();
+        }
+        round += 1;
+        if Instant::now() >= next_report {
+            next_report += Duration::from_secs(1);
+            for (i, node) in [&a, &b].iter().enumerate() {
+                let report = node.memory_report();
+                assert_bounded(&report);
+                if start.elapsed() < warm_up {
+                    warm_up_bytes[i] = warm_up_bytes[i].max(report.total_bytes());
+                }
+                else {
+                    assert!(report.total_bytes() <= warm_up_bytes[i] * 3 / 2,
+                            "node {} grew from {} to {} bytes: {:?}", i, warm_up_bytes[i], report.total_bytes(), report);
+                }
+            }
+        }
+        std::thread::sleep(Duration::from_millis(50));
+    }
+
+    b.shutdown().unwrap();
+    a.shutdown().unwrap();
+}
@@ -0,0 +1,86 @@
+mod common;
+
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use gossip::{ErrorCategory, GossipConfig, GossipService, PeerSamplingConfig, RoundObserver, RoundSummary, SamplingSummary, UpdateExpirationMode};
+use common::{peer, sampling_node, start_sampling_node, wait_for, NoopUpdateHandler};
+
+/// Isolation window of the tested node (milliseconds)
+const WINDOW: u64 = 500;
+
+/// Records the isolation callbacks
+#[derive(Default)]
+struct IsolationObserver {
+    events: Mutex<Vec<&'static str>>,
+}
+
+impl RoundObserver for IsolationObserver {
+    fn on_gossip_round(&self, _summary: RoundSummary) {}
+    fn on_sampling_round(&self, _summary: SamplingSummary) {}
+    fn on_network_isolated(&self, peer_count: usize) {
+        assert!(peer_count > 0);
+        self.events.lock().unwrap().push("isolated");
+    }
+    fn on_network_recovered(&self) {
+        self.events.lock().unwrap().push("recovered");
+    }
+}
+
+fn gossip_config(isolation_window: Option<u64>) -> GossipConfig {
+    let mut gossip_config = GossipConfig::new(true, true, 100, UpdateExpirationMode::None);
+    gossip_config.set_isolation_detection(isolation_window, 300);
+    gossip_config
+}
+
+fn new_service(port: u16, isolation_window: Option<u64>) -> GossipService<NoopUpdateHandler> {
+    sampling_node(port, PeerSamplingConfig::new(true, true, 100, 10, 1, 4), gossip_config(isolation_window))
+}
+
+fn start_node(port: u16, contacts: &[u16], isolation_window: Option<u64>) -> GossipService<NoopUpdateHandler> {
+    start_sampling_node(port, contacts, PeerSamplingConfig::new(true, true, 100, 10, 1, 4), gossip_config(isolation_window), NoopUpdateHandler)
+}
+
+#[test]
+fn isolation_is_reported_until_a_peer_returns() {
+    let first = start_node(9001, &[], None);
+    let second = start_node(9002, &[], None);
+
+    let observer = Arc::new(IsolationObserver::default());
+    let mut node = new_service(9000, Some(WINDOW));
+    node.set_round_observer(Some(observer.clone()));
+    node.start(vec![peer(9001), peer(9002)], Box::new(NoopUpdateHandler)).unwrap();
+    wait_for(|| node.health().ready, "node did not join");
+    assert!(!node.health().network_isolated);
+
+    // every other node leaves
+    first.shutdown().unwrap();
+    second.shutdown().unwrap();
+    let shut_down = Instant::now();
+    wait_for(|| node.health().network_isolated, "isolation was not reported");
+    assert!(shut_down.elapsed() >= Duration::from_millis(WINDOW));
+    assert!(shut_down.elapsed() < Duration::from_millis(WINDOW) + Duration::from_secs(2), "isolation reported late: {:?}", shut_down.elapsed());
+    assert!(node.health().peer_count > 0);
+    assert_eq!(vec!["isolated"], *observer.events.lock().unwrap());
+    assert!(node.recent_errors().iter().any(|error| error.category == ErrorCategory::Send && error.message.starts_with("network isolated")));
+    assert_eq!(1, node.stats().network_isolations);
+
+    // the node bootstraps again while isolated
+    wait_for(|| node.stats().isolation_rebootstraps > 0, "node did not bootstrap again");
+
+    // a returning peer ends the isolation
+    let returning = start_node(9001, &[], None);
+    wait_for(|| !node.health().network_isolated, "isolation was not cleared");
+    assert_eq!(vec!["isolated", "recovered"], *observer.events.lock().unwrap());
+
+    node.shutdown().unwrap();
+    returning.shutdown().unwrap();
+}
+
+#[test]
+fn isolation_is_not_detected_by_default() {
+    let node = start_node(9010, &[9011], None);
+    std::thread::sleep(Duration::from_millis(WINDOW * 2));
+    assert!(!node.health().network_isolated);
+    assert_eq!(0, node.stats().network_isolations);
+    node.shutdown().unwrap();
+}
@@ -0,0 +1,163 @@
+mod common;
+
+use gossip::{ExpirationStart, GossipService, NodeConfig, UpdateExpirationMode};
+use common::NoopUpdateHandler;
+
+const FULL_DOCUMENT: &str = r#"
+bind_addr = "127.0.0.1:9000"
+public_addr = "127.0.0.1:9000"
+bootstrap_peers = ["127.0.0.1:9001", "127.0.0.1:9002"]
+
+[sampling]
+push = true
+pull = false
+sampling_period = "1s 500ms"
+sampling_deviation = 100
+view_size = 20
+healing_factor = 2
+swapping_factor = 8
+max_peer_age = 30
+graceful_leave = "2s"
+leave_quarantine = "1m"
+max_view_message_peers = 1000
+initial_jitter = 0.5
+
+[gossip]
+push = true
+pull = true
+gossip_period = 500
+gossip_deviation = "50ms"
+listener_workers = 2
+accept_backlog = 64
+expiration_start = "on_first_push"
+
+[gossip.network]
+nodelay = false
+keepalive = "30s"
+
+[gossip.update_expiration]
+mode = "duration_millis"
+value = "10s"
+"#;
+
+#[test]
+fn parse_full_document() {
+    let config = NodeConfig::from_toml_str(FULL_DOCUMENT).unwrap();
+    assert_eq!("127.0.0.1:9000", config.bind_addr().to_string());
+    assert_eq!(2, config.bootstrap_peers().len());
+    assert_eq!("127.0.0.1:9002", config.bootstrap_peers()[1].address());
+
+    let sampling = config.sampling();
+    assert!(sampling.is_push());
+    assert!(!sampling.is_pull());
+    assert_eq!(1500, sampling.sampling_period());
+    assert_eq!(100, sampling.sampling_deviation());
+    assert_eq!(20, sampling.view_size());
+    assert_eq!(Some(30), sampling.max_peer_age());
+    assert_eq!(Some(2000), sampling.graceful_leave());
+    assert_eq!(60000, sampling.leave_quarantine());
+    assert_eq!(1000, sampling.max_view_message_peers());
+
+    let gossip = config.gossip();
+    assert_eq!(500, gossip.gossip_period());
+    assert_eq!(50, gossip.gossip_deviation());
+    assert_eq!(2, gossip.listener_workers());
+    assert!(gossip.network().reuse_address());
+    assert!(!gossip.network().nodelay());
+    assert_eq!(Some(30_000), gossip.network().keepalive());
+    assert!(matches!(gossip.update_expiration(), UpdateExpirationMode::DurationMillis(10000)));
+    assert_eq!(ExpirationStart::OnFirstPush, gossip.expiration_start());
+}
+
+#[test]
+fn parse_partial_document_with_defaults() {
+    let config = NodeConfig::from_toml_str(r#"
+        bind_addr = "127.0.0.1:9010"
+
+        [gossip]
+        gossip_period = "200ms"
+    "#).unwrap();
+    assert_eq!("127.0.0.1:9010", config.public_addr().to_string());
+    assert!(config.bootstrap_peers().is_empty());
+    assert_eq!(60000, config.sampling().sampling_period());
+    assert_eq!(30, config.sampling().view_size());
+    assert_eq!(200, config.gossip().gossip_period());
+    assert!(config.gossip().is_pull());
+    assert!(matches!(config.gossip().update_expiration(), UpdateExpirationMode::None));
+}
+
+#[test]
+fn unknown_fields_are_rejected() {
+    assert!(NodeConfig::from_toml_str("bind_addr = \"127.0.0.1:9020\"\nbind_port = 9020").is_err());
+    assert!(NodeConfig::from_toml_str("bind_addr = \"127.0.0.1:9020\"\n[sampling]\nview = 10").is_err());
+    assert!(NodeConfig::from_toml_str("bind_addr = \"127.0.0.1:9020\"\n[gossip]\ngossip_period = \"soon\"").is_err());
+}
+
+#[test]
+fn read_environment() {
+    std::env::set_var("NODE_CONFIG_TEST_BIND_ADDR", "127.0.0.1:9030");
+    std::env::set_var("NODE_CONFIG_TEST_BOOTSTRAP_PEERS", "127.0.0.1:9031, 127.0.0.1:9032");
+    std::env::set_var("NODE_CONFIG_TEST_SAMPLING__VIEW_SIZE", "12");
+    std::env::set_var("NODE_CONFIG_TEST_GOSSIP__GOSSIP_PERIOD", "2s");
+    std::env::set_var("NODE_CONFIG_TEST_GOSSIP__PULL", "false");
+
+    let config = NodeConfig::from_env("NODE_CONFIG_TEST").unwrap();
+    assert_eq!("127.0.0.1:9030", config.bind_addr().to_string());
+    assert_eq!(2, config.bootstrap_peers().len());
+    assert_eq!(12, config.sampling().view_size());
+    assert_eq!(2000, config.gossip().gossip_period());
+    assert!(!config.gossip().is_pull());
+
+    std::env::set_var("NODE_CONFIG_TEST_GOSSIP__PERIOD", "2s");
+    assert!(NodeConfig::from_env("NODE_CONFIG_TEST").is_err());
+}
+
+#[cfg(unix)]
+#[test]
+fn environment_that_is_not_utf8() {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+
+    // unrelated variables are skipped whatever their bytes
+    std::env::set_var("NODE_CONFIG_UNRELATED", OsStr::from_bytes(b"\xff\xfe"));
+    std::env::set_var("NODE_CONFIG_BYTES_BIND_ADDR", "127.0.0.1:9035");
+    assert_eq!("127.0.0.1:9035", NodeConfig::from_env("NODE_CONFIG_BYTES").unwrap().bind_addr().to_string());
+
+    std::env::set_var("NODE_CONFIG_BYTES_GOSSIP__GOSSIP_PERIOD", OsStr::from_bytes(b"\xff"));
+    assert!(NodeConfig::from_env("NODE_CONFIG_BYTES").is_err());
+}
+
+#[test]
+fn start_service_from_config() {
+    let seed_config = NodeConfig::from_toml_str(r#"
+        bind_addr = "127.0.0.1:9040"
+        [sampling]
+        sampling_period = 200
+        view_size = 10
+        [gossip]
+        gossip_period = 200
+    "#).unwrap();
+    let member_config = NodeConfig::from_toml_str(r#"
+        bind_addr = "127.0.0.1:9041"
+        bootstrap_peers = ["127.0.0.1:9040"]
+        [sampling]
+        sampling_period = 200
+        view_size = 10
+        [gossip]
+        gossip_period = 200
+    "#).unwrap();
+
+    let mut services = Vec::new();
+    for config in [seed_config, member_config] {
+        let peers = config.bootstrap_peers();
+        let mut service: GossipService<NoopUpdateHandler> = GossipService::from_config(config);
+        service.start_with_peers(peers, Box::new(NoopUpdateHandler)).unwrap();
+        services.push(service);
+    }
+    std::thread::sleep(std::time::Duration::from_millis(1000));
+
+    assert!(services[0].peers().iter().any(|peer| peer.address() == "127.0.0.1:9041"));
+    for service in services {
+        service.shutdown().unwrap();
+    }
+}
@@ -0,0 +1,38 @@
+mod common;
+
+use std::sync::{Arc, Mutex};
+use gossip::{GossipConfig, GossipService, Update, UpdateExpirationMode, UpdateHandler};
+use common::{start_static_node, wait_for};
+
+/// Records the origins and numbers of the delivered updates
+struct SequenceHandler(Arc<Mutex<Vec<(String, u64)>>>);
+impl UpdateHandler for SequenceHandler {
+    fn on_update(&self, update: Update) {
+        let sequence = update.origin_sequence().expect("update is not numbered");
+        self.0.lock().unwrap().push((sequence.origin.clone(), sequence.number));
+    }
+}
+
+fn start_node(port: u16, peer: u16, handler: SequenceHandler) -> GossipService<SequenceHandler> {
+    let mut gossip_config = GossipConfig::new(true, true, 100, UpdateExpirationMode::None);
+    gossip_config.set_ordered_delivery(true, 5_000);
+    start_static_node(port, &[peer], gossip_config, handler)
+}
+
+#[test]
+fn updates_of_a_node_are_delivered_in_order() {
+    let received = Arc::new(Mutex::new(Vec::new()));
+    let a = start_node(9000, 9001, SequenceHandler(Arc::new(Mutex::new(Vec::new()))));
+    let b = start_node(9001, 9000, SequenceHandler(Arc::clone(&received)));
+
+    // submitted together, the updates are requested in a single content request and received in any order
+    for i in 0..10 {
+        a.submit(format!("update {}", i).into_bytes()).unwrap();
+    }
+    wait_for(|| received.lock().unwrap().len() >= 10, "updates were not delivered");
+    let expected: Vec<(String, u64)> = (1..=10).map(|number| ("127.0.0.1:9000".to_owned(), number)).collect();
+    assert_eq!(expected, *received.lock().unwrap());
+
+    b.shutdown().unwrap();
+    a.shutdown().unwrap();
+}
@@ -0,0 +1,41 @@
+mod common;
+
+use std::net::TcpListener;
+use gossip::{GossipConfig, GossipService, PeerSamplingConfig, Peer, UpdateExpirationMode};
+use common::{wait_for, NoopUpdateHandler};
+
+#[test]
+fn outbound_only_node_joins_through_a_seed_and_exchanges_updates() {
+    let mut seed = GossipService::new(
+        "127.0.0.1:9000".parse().unwrap(),
+        PeerSamplingConfig::new(true, true, 50, 10, 1, 4),
+        GossipConfig::new(true, true, 50, UpdateExpirationMode::None)
+    );
+    seed.start_isolated(Box::new(NoopUpdateHandler)).unwrap();
+
+    // the port of the node stays taken, so that it cannot start a listener
+    let _firewall = TcpListener::bind("127.0.0.1:9001").unwrap();
+    let mut sampling_config = PeerSamplingConfig::new(true, true, 50, 10, 1, 4);
+    sampling_config.set_advertise_self(false);
+    let mut gossip_config = GossipConfig::new(true, true, 50, UpdateExpirationMode::None);
+    gossip_config.set_outbound_only(true);
+    let mut node = GossipService::new("127.0.0.1:9001".parse().unwrap(), sampling_config, gossip_config);
+    node.start_with_peers(vec![Peer::try_new("127.0.0.1:9000").unwrap()], Box::new(NoopUpdateHandler)).unwrap();
+    assert!(node.config_warnings().is_empty());
+
+    seed.submit(b"from the seed".to_vec()).unwrap();
+    wait_for(|| node.is_active(b"from the seed".to_vec()), "update of the seed did not reach the node");
+    node.submit(b"from the node".to_vec()).unwrap();
+    wait_for(|| seed.is_active(b"from the node".to_vec()), "update of the node did not reach the seed");
+
+    let health = node.health();
+    assert!(health.running);
+    assert!(!health.listener_bound);
+    assert_eq!(None, node.listener_address());
+    assert!(node.peers().iter().any(|peer| peer.address() == "127.0.0.1:9000"));
+    // the seed never learns the address of the node, which it could not connect to
+    assert!(seed.peers().is_empty());
+
+    node.shutdown().unwrap();
+    seed.shutdown().unwrap();
+}
@@ -0,0 +1,49 @@
+mod common;
+
+use std::time::Duration;
+use gossip::{GossipConfig, GossipService, UpdateExpirationMode};
+use common::{start_static_node, wait_for, RecordingHandler};
+
+const OWN_UPDATE: &[u8] = b"own update";
+
+fn start_node(port: u16, peers: &[u16], origin_id: Option<&str>, suppress: bool, handler: RecordingHandler) -> GossipService<RecordingHandler> {
+    let mut gossip_config = GossipConfig::new(true, true, 100, UpdateExpirationMode::None);
+    gossip_config.set_origin_id(origin_id.map(str::to_owned));
+    gossip_config.set_suppress_own_origin_redelivery(suppress);
+    start_static_node(port, peers, gossip_config, handler)
+}
+
+/// Runs the scenario on ports `base..base + 3`, and returns the updates delivered to the restarted origin: A submits
+/// an update that reaches B, and is restarted without it, at another address but with the same origin id.
+fn restart_scenario(base: u16, suppress: bool) -> Vec<Vec<u8>> {
+    let (a_port, b_port, restarted_port) = (base, base + 1, base + 2);
+    let a = start_node(a_port, &[b_port], Some("node-a"), suppress, RecordingHandler::default());
+    let b_received = RecordingHandler::default();
+    let b = start_node(b_port, &[a_port, restarted_port], None, suppress, b_received.clone());
+    a.submit(OWN_UPDATE.to_vec()).unwrap();
+    wait_for(|| b.is_active(OWN_UPDATE.to_vec()), "update did not reach B");
+    // the other nodes receive the updates of the origin
+    assert_eq!(vec![OWN_UPDATE.to_vec()], b_received.received());
+    a.shutdown().unwrap();
+
+    let received = RecordingHandler::default();
+    let restarted = start_node(restarted_port, &[b_port], Some("node-a"), suppress, received.clone());
+    wait_for(|| restarted.is_active(OWN_UPDATE.to_vec()), "update did not come back to the origin");
+    std::thread::sleep(Duration::from_millis(300));
+    assert_eq!(suppress as u64, restarted.stats().suppressed_own_updates);
+
+    for service in [restarted, b] {
+        service.shutdown().unwrap();
+    }
+    received.received()
+}
+
+#[test]
+fn own_update_is_not_delivered_again() {
+    assert!(restart_scenario(9000, true).is_empty());
+}
+
+#[test]
+fn own_update_is_delivered_again_without_suppression() {
+    assert_eq!(vec![OWN_UPDATE.to_vec()], restart_scenario(9010, false));
+}
@@ -0,0 +1,46 @@
+mod common;
+
+use gossip::{GossipService, PeerSamplingConfig, GossipConfig, Peer, UpdateExpirationMode};
+use crate::common::NoopUpdateHandler;
+
+#[test]
+fn stale_peer_evicted() {
+    let sampling_period = 200;
+    let max_age = 3;
+
+    let address_1 = "127.0.0.1:9000";
+    let mut service_1 = GossipService::new(
+        address_1.parse().unwrap(),
+        PeerSamplingConfig::new(true, true, sampling_period, 10, 1, 4),
+        GossipConfig::new(true, true, 1000, UpdateExpirationMode::None)
+    );
+    service_1.start_isolated(Box::new(NoopUpdateHandler)).unwrap();
+
+    // the second node also knows a peer that never answers
+    let dead_peer = "127.0.0.1:9009";
+    let address_2 = "127.0.0.1:9001";
+    let mut sampling_config = PeerSamplingConfig::new(true, true, sampling_period, 10, 1, 4);
+    sampling_config.set_max_peer_age(Some(max_age));
+    let mut service_2 = GossipService::new(
+        address_2.parse().unwrap(),
+        sampling_config,
+        GossipConfig::new(true, true, 1000, UpdateExpirationMode::None)
+    );
+    service_2.start_with_peers(
+        vec![Peer::try_new(address_1).unwrap(), Peer::try_new(dead_peer).unwrap()],
+        Box::new(NoopUpdateHandler)
+    ).unwrap();
+    assert!(service_2.peers().contains(&Peer::try_new(dead_peer).unwrap()));
+
+    // the dead peer ages at every cycle and is never refreshed
+    std::thread::sleep(std::time::Duration::from_millis(sampling_period * 4 * max_age as u64));
+
+    let peers = service_2.peers();
+    assert!(!peers.contains(&Peer::try_new(dead_peer).unwrap()));
+    let live_peer = peers.iter().find(|peer| peer.address() == address_1).unwrap();
+    assert!(live_peer.age() <= max_age);
+    assert!(live_peer.staleness().unwrap() < std::time::Duration::from_secs(5));
+
+    service_1.shutdown().unwrap();
+    service_2.shutdown().unwrap();
+}
@@ -0,0 +1,50 @@
+mod common;
+
+use gossip::{GossipConfig, GossipService, Peer, PeerSamplingConfig, UpdateExpirationMode, MAX_PEER_METADATA};
+use common::{start_sampling_node, wait_for, NoopUpdateHandler};
+
+fn start_node(port: u16, contacts: &[u16], metadata: Option<&[u8]>) -> GossipService<NoopUpdateHandler> {
+    let mut sampling_config = PeerSamplingConfig::new(true, true, 100, 10, 1, 4);
+    if let Some(metadata) = metadata {
+        sampling_config.set_metadata(metadata.to_vec()).unwrap();
+    }
+    start_sampling_node(port, contacts, sampling_config, GossipConfig::new(true, true, 100, UpdateExpirationMode::None), NoopUpdateHandler)
+}
+
+#[test]
+fn metadata_propagates_through_views() {
+    // the last node only knows the middle node, which only knows the first one
+    let first = start_node(9000, &[], Some(b"role=ingest"));
+    let middle = start_node(9001, &[9000], None);
+    let last = start_node(9002, &[9001], Some(b"role=query"));
+
+    let is_ingest = |peer: &Peer| peer.metadata() == Some(b"role=ingest".as_slice());
+    wait_for(|| last.get_peer_where(is_ingest).is_some(), "metadata of the first node did not reach the last one");
+    let ingest = last.get_peer_where(is_ingest).unwrap();
+    assert_eq!("127.0.0.1:9000", ingest.address());
+    assert!(last.peers().iter().any(|peer| peer.address() == "127.0.0.1:9001" && peer.metadata().is_none()));
+    assert!(last.get_peer_where(|peer| peer.metadata() == Some(b"role=query".as_slice())).is_none());
+
+    for node in [&last, &middle, &first] {
+        node.shutdown().unwrap();
+    }
+}
+
+#[test]
+fn metadata_is_bounded() {
+    let mut sampling_config = PeerSamplingConfig::default();
+    assert!(sampling_config.set_metadata(vec![0; MAX_PEER_METADATA + 1]).is_err());
+    assert_eq!(None, sampling_config.metadata());
+    sampling_config.set_metadata(vec![0; MAX_PEER_METADATA]).unwrap();
+    assert_eq!(Some(vec![0; MAX_PEER_METADATA].as_slice()), sampling_config.metadata());
+}
+
+#[test]
+fn peers_without_metadata_are_compatible() {
+    // a peer without metadata is encoded as by nodes that do not know about metadata
+    let encoded = serde_cbor::to_vec(&Peer::try_new("127.0.0.1:9010").unwrap()).unwrap();
+    let decoded: Peer = serde_cbor::from_slice(&encoded).unwrap();
+    assert_eq!("127.0.0.1:9010", decoded.address());
+    assert_eq!(None, decoded.metadata());
+    assert!(!String::from_utf8_lossy(&encoded).contains("metadata"));
+}
@@ -0,0 +1,75 @@
+mod common;
+
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+use gossip::{GossipConfig, GossipService, PeerSamplingConfig, UpdateExpirationMode};
+use common::{peer, sampling_node, wait_for_within, NoopUpdateHandler};
+
+const FORWARDED: u16 = 9001;
+const OTHER: u16 = 9002;
+
+/// Forwards the connections accepted on `external` to `internal` like a router would, and returns the number of
+/// forwarded connections
+fn start_forwarder(external: u16, internal: SocketAddr) -> Arc<AtomicUsize> {
+    let listener = TcpListener::bind(("127.0.0.1", external)).unwrap();
+    let forwarded = Arc::new(AtomicUsize::new(0));
+    let count = Arc::clone(&forwarded);
+    std::thread::spawn(move || {
+        for incoming in listener.incoming() {
+            let Ok(mut inbound) = incoming else { continue };
+            let Ok(mut outbound) = TcpStream::connect(internal) else { continue };
+            count.fetch_add(1, Ordering::SeqCst);
+            let (mut inbound_copy, mut outbound_copy) = (inbound.try_clone().unwrap(), outbound.try_clone().unwrap());
+            std::thread::spawn(move || {
+                let _ = std::io::copy(&mut outbound_copy, &mut inbound_copy);
+            });
+            std::thread::spawn(move || {
+                let _ = std::io::copy(&mut inbound, &mut outbound);
+                let _ = outbound.shutdown(std::net::Shutdown::Write);
+            });
+        }
+    });
+    forwarded
+}
+
+fn node(port: u16) -> GossipService<NoopUpdateHandler> {
+    sampling_node(port, PeerSamplingConfig::new(true, true, 100, 10, 1, 4), GossipConfig::new(true, true, 100, UpdateExpirationMode::None))
+}
+
+#[test]
+fn node_behind_port_forwarding_exchanges_updates() {
+    // the node advertises the forwarded port and listens on a port chosen by the system
+    let mut forwarded_node = node(FORWARDED);
+    forwarded_node.set_bind_address("127.0.0.1:0".parse().unwrap());
+    forwarded_node.start_with_peers(vec![peer(OTHER)], Box::new(NoopUpdateHandler)).unwrap();
+    let listener_address = forwarded_node.listener_address().unwrap();
+    assert_ne!(0, listener_address.port());
+    assert_eq!(peer(FORWARDED).address(), forwarded_node.address().to_string());
+    let forwarded = start_forwarder(FORWARDED, listener_address);
+
+    let mut other = node(OTHER);
+    other.start_isolated(Box::new(NoopUpdateHandler)).unwrap();
+
+    let from_forwarded = b"from the forwarded node".to_vec();
+    let from_other = b"from the other node".to_vec();
+    forwarded_node.submit(from_forwarded.clone()).unwrap();
+    other.submit(from_other.clone()).unwrap();
+
+    wait_for_within(
+        || other.is_active(from_forwarded.clone()) && forwarded_node.is_active(from_other.clone()),
+        Duration::from_secs(10),
+        "updates were not exchanged through the forwarded port"
+    );
+    assert!(forwarded.load(Ordering::SeqCst) > 0);
+
+    // the other node only knows the advertised address
+    let peers: Vec<String> = other.peers().iter().map(|peer| peer.address().to_owned()).collect();
+    assert_eq!(vec![peer(FORWARDED).address().to_owned()], peers);
+
+    // the listener is woken up on its actual port
+    other.shutdown().unwrap();
+    forwarded_node.shutdown().unwrap();
+    assert_eq!(None, forwarded_node.listener_address());
+}
@@ -0,0 +1,43 @@
+mod common;
+
+use gossip::{GossipService, PeerSamplingConfig, GossipConfig, Peer, UpdateExpirationMode};
+use crate::common::NoopUpdateHandler;
+
+#[test]
+fn propagation_latency_recorded() {
+    let sampling_period = 500;
+    let gossip_period = 300;
+
+    let address_1 = "127.0.0.1:9000";
+    let mut service_1 = GossipService::new(
+        address_1.parse().unwrap(),
+        PeerSamplingConfig::new(true, true, sampling_period, 10, 1, 4),
+        GossipConfig::new(true, true, gossip_period, UpdateExpirationMode::None)
+    );
+    service_1.start_isolated(Box::new(NoopUpdateHandler)).unwrap();
+
+    let address_2 = "127.0.0.1:9001";
+    let mut service_2 = GossipService::new(
+        address_2.parse().unwrap(),
+        PeerSamplingConfig::new(true, true, sampling_period, 10, 1, 4),
+        GossipConfig::new(true, true, gossip_period, UpdateExpirationMode::None)
+    );
+    service_2.start_with_peers(
+        vec![Peer::try_new(address_1).unwrap()],
+        Box::new(NoopUpdateHandler)
+    ).unwrap();
+
+    // wait for peer discovery
+    std::thread::sleep(std::time::Duration::from_millis(sampling_period * 3));
+    assert_eq!(0, service_2.stats().propagation_histogram.total());
+
+    service_1.submit("measured update".as_bytes().to_vec()).unwrap();
+    std::thread::sleep(std::time::Duration::from_millis(gossip_period * 5));
+
+    let histogram = service_2.stats().propagation_histogram;
+    assert_eq!(1, histogram.total());
+    assert_eq!(gossip::LATENCY_BUCKETS_MILLIS.len() + 1, histogram.counts().len());
+
+    service_1.shutdown().unwrap();
+    service_2.shutdown().unwrap();
+}
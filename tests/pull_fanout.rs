@@ -0,0 +1,63 @@
+mod common;
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+use gossip::{GossipConfig, GossipService, PeerSamplingConfig, RoundObserver, RoundSummary, SamplingSummary, UpdateExpirationMode};
+use common::{peer, sampling_node, start_sampling_node, wait_for_within, NoopUpdateHandler};
+
+const CLUSTER: [u16; 5] = [9000, 9001, 9002, 9003, 9004];
+
+#[derive(Default)]
+struct RoundCounter(AtomicUsize);
+impl RoundObserver for RoundCounter {
+    fn on_gossip_round(&self, _summary: RoundSummary) {
+        self.0.fetch_add(1, Ordering::SeqCst);
+    }
+    fn on_sampling_round(&self, _summary: SamplingSummary) {}
+}
+
+fn content(index: usize) -> Vec<u8> {
+    format!("update {}", index).into_bytes()
+}
+
+// number of gossip rounds until a fresh node pulling from the cluster has received every update
+fn rounds_to_converge(port: u16, pull_fanout: usize) -> usize {
+    let mut gossip_config = GossipConfig::new(false, true, 100, UpdateExpirationMode::None);
+    gossip_config.set_pull_fanout(pull_fanout);
+    let mut node: GossipService<NoopUpdateHandler> = sampling_node(port, PeerSamplingConfig::new(false, false, 200, 10, 1, 4), gossip_config);
+    let counter = Arc::new(RoundCounter::default());
+    node.set_round_observer(Some(counter.clone()));
+    node.start(
+        Box::new(|| Some(CLUSTER.iter().map(|port| peer(*port)).collect())),
+        Box::new(NoopUpdateHandler)
+    ).unwrap();
+
+    wait_for_within(|| (0..CLUSTER.len()).all(|index| node.is_active(content(index))),
+        Duration::from_secs(10), &format!("{} did not converge", port));
+    let rounds = counter.0.load(Ordering::SeqCst);
+    node.shutdown().unwrap();
+    rounds
+}
+
+#[test]
+fn larger_pull_fanout_converges_faster() {
+    // each node of the cluster holds two updates, each update is held by two nodes
+    let mut cluster = Vec::new();
+    for (index, port) in CLUSTER.iter().enumerate() {
+        let service = start_sampling_node(*port, &[], PeerSamplingConfig::new(false, false, 200, 10, 1, 4),
+            GossipConfig::new(false, true, 200, UpdateExpirationMode::None), NoopUpdateHandler);
+        service.submit(content(index)).unwrap();
+        service.submit(content((index + 1) % CLUSTER.len())).unwrap();
+        cluster.push(service);
+    }
+
+    // sum over several joins to smooth out the random peer selection
+    let single: usize = (9010..9013).map(|port| rounds_to_converge(port, 1)).sum();
+    let triple: usize = (9020..9023).map(|port| rounds_to_converge(port, 3)).sum();
+    assert!(triple < single, "fanout 3: {} rounds, fanout 1: {} rounds", triple, single);
+
+    for service in &mut cluster {
+        service.shutdown().unwrap();
+    }
+}
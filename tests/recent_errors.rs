@@ -0,0 +1,25 @@
+mod common;
+
+use gossip::{ErrorCategory, GossipConfig, GossipService, Peer, PeerSamplingConfig, UpdateExpirationMode};
+use common::{sampling_node, wait_for, NoopUpdateHandler};
+
+#[test]
+fn connect_failures_are_recorded() {
+    let mut service: GossipService<NoopUpdateHandler> = sampling_node(
+        9000,
+        PeerSamplingConfig::new(true, true, 100, 10, 1, 4),
+        GossipConfig::new(true, true, 100, UpdateExpirationMode::None)
+    );
+    assert!(service.recent_errors().is_empty());
+    // nothing listens on the bootstrap peer
+    service.start_with_peers(vec![Peer::try_new("127.0.0.1:1").unwrap()], Box::new(NoopUpdateHandler)).unwrap();
+
+    let failed = |request: &str| service.recent_errors().iter()
+        .any(|error| error.category == ErrorCategory::Send && error.message.starts_with(request));
+    wait_for(|| failed("view request") && failed("header request"), "connect failures were not recorded");
+    let errors = service.recent_errors();
+    assert!(errors.iter().all(|error| error.peer.as_deref() == Some("127.0.0.1:1")), "{:?}", errors);
+    assert!(errors.windows(2).all(|pair| pair[0].time <= pair[1].time));
+
+    service.shutdown().unwrap();
+}
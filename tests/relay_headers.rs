@@ -0,0 +1,35 @@
+mod common;
+
+use std::sync::Arc;
+use std::time::Duration;
+use gossip::{FetchPolicy, GossipConfig, UpdateExpirationMode};
+use common::{start_static_node, wait_for_within, NoopUpdateHandler};
+
+// declines every update
+struct DeclineAll;
+impl FetchPolicy for DeclineAll {
+    fn should_fetch(&self, _digest: &str, _from: &str) -> bool {
+        false
+    }
+}
+
+#[test]
+fn declined_updates_are_relayed() {
+    // A - B - C: B declines every update but relays the headers it receives
+    let mut relay_config = GossipConfig::new(true, true, 100, UpdateExpirationMode::None);
+    relay_config.set_fetch_policy(Some(Arc::new(DeclineAll)));
+    relay_config.set_relay_headers(true);
+    let a = start_static_node(9000, &[9001], GossipConfig::new(true, true, 100, UpdateExpirationMode::None), NoopUpdateHandler);
+    let b = start_static_node(9001, &[9000, 9002], relay_config, NoopUpdateHandler);
+    let c = start_static_node(9002, &[9001], GossipConfig::new(true, true, 100, UpdateExpirationMode::None), NoopUpdateHandler);
+
+    a.submit(b"relayed".to_vec()).unwrap();
+    wait_for_within(|| c.is_active(b"relayed".to_vec()), Duration::from_secs(10), "update did not reach C");
+
+    assert!(!b.is_active(b"relayed".to_vec()));
+    assert!(c.stats().redirected_content_requests >= 1);
+
+    c.shutdown().unwrap();
+    b.shutdown().unwrap();
+    a.shutdown().unwrap();
+}
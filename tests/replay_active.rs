@@ -0,0 +1,34 @@
+mod common;
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use gossip::{GossipConfig, GossipService, PeerSamplingConfig, Update, UpdateExpirationMode};
+use common::MapUpdatingHandler;
+
+#[test]
+fn late_handler_receives_active_updates() {
+    let received = Arc::new(Mutex::new(HashMap::new()));
+    let mut service = GossipService::new(
+        "127.0.0.1:9000".parse().unwrap(),
+        PeerSamplingConfig::new(true, true, 300, 10, 1, 4),
+        GossipConfig::new(true, true, 200, UpdateExpirationMode::None)
+    );
+    service.start_isolated(Box::new(MapUpdatingHandler::new("first".to_owned(), Arc::clone(&received)))).unwrap();
+
+    let contents = ["first update".as_bytes(), "second update".as_bytes()];
+    for content in &contents {
+        service.submit(content.to_vec()).unwrap();
+    }
+
+    service.set_update_handler(Box::new(MapUpdatingHandler::new("late".to_owned(), Arc::clone(&received))), true).unwrap();
+    // already replayed updates are not delivered again
+    assert_eq!(0, service.replay_active().unwrap());
+    std::thread::sleep(std::time::Duration::from_millis(200));
+
+    let expected: Vec<String> = contents.iter().map(|content| Update::new(content.to_vec()).digest().to_owned()).collect();
+    let received = received.lock().unwrap();
+    assert_eq!(Some(&expected), received.get("late"));
+    assert_eq!(None, received.get("first"));
+
+    service.shutdown().unwrap();
+}
@@ -0,0 +1,46 @@
+mod common;
+
+use std::time::Duration;
+use gossip::{GossipConfig, GossipService, NetworkConfig, PeerSamplingConfig, UpdateExpirationMode};
+use common::{start_sampling_node, wait_for, NoopUpdateHandler};
+
+fn start_node(port: u16, contacts: &[u16], reply_in_stream: bool) -> GossipService<NoopUpdateHandler> {
+    let mut network = NetworkConfig::default();
+    network.set_reply_in_stream(reply_in_stream);
+    let mut gossip_config = GossipConfig::new(true, true, 50, UpdateExpirationMode::None);
+    gossip_config.set_network(network);
+    start_sampling_node(port, contacts, PeerSamplingConfig::new(true, true, 50, 10, 1, 4), gossip_config, NoopUpdateHandler)
+}
+
+/// Starts two nodes, has each submit an update that must reach the other, and returns the number of connections
+/// they opened for a second of exchanges
+fn run_pair(ports: (u16, u16), reply_in_stream: (bool, bool)) -> u64 {
+    let a = start_node(ports.0, &[], reply_in_stream.0);
+    let b = start_node(ports.1, &[ports.0], reply_in_stream.1);
+    a.submit(format!("from {}", ports.0).into_bytes()).unwrap();
+    b.submit(format!("from {}", ports.1).into_bytes()).unwrap();
+
+    wait_for(
+        || b.is_active(format!("from {}", ports.0).into_bytes()) && a.is_active(format!("from {}", ports.1).into_bytes()),
+        &format!("updates did not propagate between {:?}", ports)
+    );
+    std::thread::sleep(Duration::from_secs(1));
+    let opened = a.stats().connections_opened + b.stats().connections_opened;
+    b.shutdown().unwrap();
+    a.shutdown().unwrap();
+    opened
+}
+
+#[test]
+fn replies_in_stream_open_fewer_connections() {
+    let dialed_back = run_pair((9000, 9001), (false, false));
+    let in_stream = run_pair((9002, 9003), (true, true));
+    assert!(in_stream * 4 < dialed_back * 3, "{} connections with replies in stream, {} without", in_stream, dialed_back);
+}
+
+#[test]
+fn nodes_without_replies_in_stream_still_exchange_updates() {
+    // each node sends its requests with the flag of its own configuration
+    run_pair((9004, 9005), (true, false));
+    run_pair((9006, 9007), (false, true));
+}
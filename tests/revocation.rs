@@ -0,0 +1,85 @@
+mod common;
+
+use std::time::Duration;
+use gossip::{GossipConfig, GossipService, Update, UpdateExpirationMode, UpdateState};
+use common::{start_static_node, wait_for, NoopUpdateHandler, RecordingHandler};
+
+const REVOKED_UPDATE: &[u8] = b"revoked update";
+
+fn start_node(port: u16, peers: &[u16], handler: RecordingHandler) -> GossipService<RecordingHandler> {
+    let mut gossip_config = GossipConfig::new(true, true, 100, UpdateExpirationMode::None);
+    gossip_config.set_revocations(true);
+    gossip_config.set_origin_id(Some(format!("node-{}", port)));
+    start_static_node(port, peers, gossip_config, handler)
+}
+
+#[test]
+fn revoked_update_is_removed_across_the_cluster() {
+    let handlers = [RecordingHandler::default(), RecordingHandler::default(), RecordingHandler::default()];
+    let a = start_node(9000, &[9001, 9002], handlers[0].clone());
+    let b = start_node(9001, &[9000, 9002], handlers[1].clone());
+    let c = start_node(9002, &[9000, 9001], handlers[2].clone());
+    let digest = Update::new(REVOKED_UPDATE.to_vec()).digest().clone();
+
+    a.submit(REVOKED_UPDATE.to_vec()).unwrap();
+    wait_for(|| b.is_active(REVOKED_UPDATE.to_vec()) && c.is_active(REVOKED_UPDATE.to_vec()), "update did not propagate");
+
+    a.revoke(&digest).unwrap();
+    assert!(a.is_expired(REVOKED_UPDATE.to_vec()));
+    assert!(a.revoke(&digest).is_err());
+    wait_for(|| b.is_expired(REVOKED_UPDATE.to_vec()) && c.is_expired(REVOKED_UPDATE.to_vec()), "revocation did not propagate");
+    for node in [&a, &b, &c] {
+        assert_eq!(Some(UpdateState::Expired), node.update_state(&digest));
+        assert_eq!(1, node.stats().revoked_updates);
+    }
+
+    // the revocation is not delivered to the handlers
+    std::thread::sleep(Duration::from_millis(300));
+    for handler in &handlers[1..] {
+        assert_eq!(vec![REVOKED_UPDATE.to_vec()], handler.received());
+    }
+
+    c.shutdown().unwrap();
+    b.shutdown().unwrap();
+    a.shutdown().unwrap();
+}
+
+#[test]
+fn revocations_are_disabled_by_default() {
+    let service = start_static_node(9003, &[9004], GossipConfig::new(true, true, 100, UpdateExpirationMode::None), NoopUpdateHandler);
+    service.submit(REVOKED_UPDATE.to_vec()).unwrap();
+    assert!(service.revoke(Update::new(REVOKED_UPDATE.to_vec()).digest()).is_err());
+    assert!(service.is_active(REVOKED_UPDATE.to_vec()));
+    service.shutdown().unwrap();
+}
+
+#[test]
+fn revocation_from_another_origin_is_ignored() {
+    let a = start_node(9005, &[9006], RecordingHandler::default());
+    let b = start_node(9006, &[9005], RecordingHandler::default());
+    let digest = Update::new(REVOKED_UPDATE.to_vec()).digest().clone();
+
+    a.submit(REVOKED_UPDATE.to_vec()).unwrap();
+    wait_for(|| b.is_active(REVOKED_UPDATE.to_vec()), "update did not propagate");
+
+    // the revocation reaches the origin of the update, which keeps it
+    b.revoke(&digest).unwrap();
+    assert!(b.is_expired(REVOKED_UPDATE.to_vec()));
+    wait_for(|| a.memory_report().active_updates.count == 2, "revocation did not propagate");
+    assert!(a.is_active(REVOKED_UPDATE.to_vec()));
+    assert_eq!(0, a.stats().revoked_updates);
+
+    b.shutdown().unwrap();
+    a.shutdown().unwrap();
+}
+
+#[test]
+fn revocations_require_an_origin_id() {
+    let mut gossip_config = GossipConfig::new(true, true, 100, UpdateExpirationMode::None);
+    gossip_config.set_revocations(true);
+    let service = start_static_node(9007, &[9008], gossip_config, NoopUpdateHandler);
+    service.submit(REVOKED_UPDATE.to_vec()).unwrap();
+    assert!(service.revoke(Update::new(REVOKED_UPDATE.to_vec()).digest()).is_err());
+    assert!(service.is_active(REVOKED_UPDATE.to_vec()));
+    service.shutdown().unwrap();
+}
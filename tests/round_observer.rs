@@ -0,0 +1,202 @@
+mod common;
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use gossip::{ExpirySummary, GossipConfig, GossipService, PeerSamplingConfig, RemovalReason, RoundObserver, RoundSummary, SamplingSummary, Update, UpdateExpirationMode};
+use crate::common::{peer, sampling_node, static_node, wait_for, wait_for_within, NoopUpdateHandler};
+
+// example observer writing one CSV line per round
+struct CsvObserver {
+    node: String,
+    gossip_rows: Mutex<Vec<String>>,
+    sampling_rows: Mutex<Vec<String>>,
+}
+impl CsvObserver {
+    const GOSSIP_HEADER: &'static str = "node,peer,headers_sent,new_digests,sent,duration_us";
+    const SAMPLING_HEADER: &'static str = "node,peer,buffer_size,sent,view_size,duration_us";
+
+    fn new(node: &str) -> Self {
+        CsvObserver {
+            node: node.to_owned(),
+            gossip_rows: Mutex::new(vec![Self::GOSSIP_HEADER.to_owned()]),
+            sampling_rows: Mutex::new(vec![Self::SAMPLING_HEADER.to_owned()]),
+        }
+    }
+}
+impl RoundObserver for CsvObserver {
+    fn on_gossip_round(&self, summary: RoundSummary) {
+        self.gossip_rows.lock().unwrap().push(format!("{},{},{},{},{},{}",
+            self.node, summary.peer.unwrap_or_default(), summary.headers_sent, summary.new_digests, summary.sent, summary.duration.as_micros()));
+    }
+    fn on_sampling_round(&self, summary: SamplingSummary) {
+        self.sampling_rows.lock().unwrap().push(format!("{},{},{},{},{},{}",
+            self.node, summary.peer.unwrap_or_default(), summary.buffer_size, summary.sent, summary.view_size, summary.duration.as_micros()));
+    }
+}
+
+#[test]
+fn rounds_are_observed() {
+    let sampling_period = 300;
+    let gossip_period = 200;
+    let ports = [9000, 9001];
+
+    let mut services = Vec::new();
+    let mut observers = Vec::new();
+    for (i, &port) in ports.iter().enumerate() {
+        let mut service: GossipService<NoopUpdateHandler> = sampling_node(
+            port,
+            PeerSamplingConfig::new(true, true, sampling_period, 10, 1, 4),
+            GossipConfig::new(true, true, gossip_period, UpdateExpirationMode::None)
+        );
+        let observer = Arc::new(CsvObserver::new(&service.address().to_string()));
+        service.set_round_observer(Some(observer.clone()));
+        service.start(
+            Box::new(move || if i == 0 { None } else { Some(vec![peer(ports[0])]) }),
+            Box::new(NoopUpdateHandler)
+        ).unwrap();
+        services.push(service);
+        observers.push(observer);
+    }
+
+    std::thread::sleep(std::time::Duration::from_millis(sampling_period * 3));
+    services[0].submit("observed update".as_bytes().to_vec()).unwrap();
+    std::thread::sleep(std::time::Duration::from_millis(gossip_period * 5));
+
+    for observer in &observers {
+        let gossip_rows = observer.gossip_rows.lock().unwrap();
+        let sampling_rows = observer.sampling_rows.lock().unwrap();
+        // header line and at least one round where a peer was contacted
+        assert!(gossip_rows.iter().skip(1).any(|row| row.split(',').nth(4) == Some("true")));
+        assert!(sampling_rows.iter().skip(1).any(|row| row.split(',').nth(3) == Some("true")));
+    }
+    // the update was pushed by the first node and learned by the second
+    assert!(observers[0].gossip_rows.lock().unwrap().iter().skip(1).any(|row| row.split(',').nth(2) == Some("1")));
+    assert!(observers[1].gossip_rows.lock().unwrap().iter().skip(1).any(|row| row.split(',').nth(3) == Some("1")));
+
+    for service in services {
+        service.shutdown().unwrap();
+    }
+}
+
+// observer keeping the summaries of the expired updates
+#[derive(Default)]
+struct ExpiryObserver {
+    expired: Mutex<Vec<ExpirySummary>>,
+}
+impl RoundObserver for ExpiryObserver {
+    fn on_gossip_round(&self, _summary: RoundSummary) {}
+    fn on_sampling_round(&self, _summary: SamplingSummary) {}
+    fn on_update_expired(&self, summary: ExpirySummary) {
+        self.expired.lock().unwrap().push(summary);
+    }
+}
+
+#[test]
+fn expired_updates_are_summarized() {
+    let ports = [9010, 9011];
+    let mut services = Vec::new();
+    let mut observers = Vec::new();
+    for port in ports {
+        let mut service = static_node(port, &ports, GossipConfig::new(true, true, 100, UpdateExpirationMode::PushCount(3)));
+        let observer = Arc::new(ExpiryObserver::default());
+        service.set_round_observer(Some(observer.clone()));
+        service.start_isolated(Box::new(NoopUpdateHandler)).unwrap();
+        services.push(service);
+        observers.push(observer);
+    }
+
+    let digest = Update::new(b"expiring".to_vec()).digest().to_owned();
+    services[0].submit(b"expiring".to_vec()).unwrap();
+    wait_for_within(|| observers.iter().all(|observer| !observer.expired.lock().unwrap().is_empty()),
+        Duration::from_secs(10), "update did not expire on both nodes");
+
+    // the origin pushed the digest three times, and the other node requested the content
+    let summary = observers[0].expired.lock().unwrap()[0].clone();
+    assert_eq!(digest, summary.digest);
+    assert_eq!(RemovalReason::Expired, summary.reason);
+    assert_eq!(3, summary.counters.pushes);
+    assert_eq!(1, summary.counters.distinct_requesters);
+    // a single peer missed the three pushes with probability e^-3
+    assert!((summary.estimated_coverage - (1.0 - (-3.0f64).exp())).abs() < 1e-9);
+    let stats = services[0].stats();
+    assert_eq!(1, stats.updates_expired);
+    assert_eq!(3, stats.expired_update_pushes);
+    assert_eq!(1, stats.expired_update_requesters);
+    assert_eq!(Some(summary.estimated_coverage), services[0].coverage_estimate(&digest));
+
+    let summary = observers[1].expired.lock().unwrap()[0].clone();
+    assert_eq!(3, summary.counters.pushes);
+    assert_eq!(0, summary.counters.distinct_requesters);
+
+    for service in services {
+        service.shutdown().unwrap();
+    }
+}
+
+#[test]
+fn revoked_updates_are_summarized_as_removed() {
+    let ports = [9030, 9031];
+    let mut gossip_config = GossipConfig::new(true, true, 100, UpdateExpirationMode::None);
+    gossip_config.set_revocations(true);
+    gossip_config.set_origin_id(Some("origin".to_owned()));
+    let mut services = Vec::new();
+    let mut observers = Vec::new();
+    for port in ports {
+        let mut service = static_node(port, &ports, gossip_config.clone());
+        let observer = Arc::new(ExpiryObserver::default());
+        service.set_round_observer(Some(observer.clone()));
+        service.start_isolated(Box::new(NoopUpdateHandler)).unwrap();
+        services.push(service);
+        observers.push(observer);
+    }
+
+    services[0].submit(b"revoked".to_vec()).unwrap();
+    wait_for(|| services[1].is_active(b"revoked".to_vec()), "update did not propagate");
+    let digest = Update::new(b"revoked".to_vec()).digest().to_owned();
+    services[0].revoke(&digest).unwrap();
+    wait_for(|| observers.iter().all(|observer| observer.expired.lock().unwrap().iter().any(|summary| summary.digest == digest)),
+        "removal was not reported on both nodes");
+
+    for (service, observer) in services.iter().zip(&observers) {
+        let summary = observer.expired.lock().unwrap().iter().find(|summary| summary.digest == digest).cloned().unwrap();
+        assert_eq!(RemovalReason::Removed, summary.reason);
+        // removals are not counted as expirations
+        assert_eq!(0, service.stats().updates_expired);
+    }
+
+    for service in services {
+        service.shutdown().unwrap();
+    }
+}
+
+#[test]
+fn paused_node_only_runs_triggered_rounds() {
+    let mut services: Vec<GossipService<NoopUpdateHandler>> = [9020, 9021].iter()
+        .map(|&port| sampling_node(port, PeerSamplingConfig::new(true, true, 100, 10, 1, 4), GossipConfig::new(true, true, 100, UpdateExpirationMode::None)))
+        .collect();
+    assert!(services[0].trigger_gossip_round().is_err());
+    assert!(services[0].pause().is_err());
+    let observer = Arc::new(CsvObserver::new(&services[0].address().to_string()));
+    services[0].set_round_observer(Some(observer.clone()));
+    services[0].start_isolated(Box::new(NoopUpdateHandler)).unwrap();
+    services[1].start_with_peers(vec![peer(9020)], Box::new(NoopUpdateHandler)).unwrap();
+    let rounds = || (observer.gossip_rows.lock().unwrap().len(), observer.sampling_rows.lock().unwrap().len());
+
+    // a round in progress when pausing completes
+    services[0].pause().unwrap();
+    std::thread::sleep(Duration::from_millis(50));
+    let (gossip_rounds, sampling_rounds) = rounds();
+    std::thread::sleep(Duration::from_millis(300));
+    assert_eq!((gossip_rounds, sampling_rounds), rounds());
+
+    services[0].trigger_gossip_round().unwrap();
+    services[0].trigger_sampling_round().unwrap();
+    wait_for(|| rounds() == (gossip_rounds + 1, sampling_rounds + 1), "triggered rounds did not run");
+
+    services[0].resume().unwrap();
+    wait_for(|| rounds().0 >= gossip_rounds + 3 && rounds().1 >= sampling_rounds + 3, "rounds did not resume");
+
+    for service in services {
+        service.shutdown().unwrap();
+    }
+}
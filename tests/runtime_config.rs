@@ -0,0 +1,81 @@
+mod common;
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use gossip::{GossipConfig, GossipService, PeerSamplingConfig, RoundObserver, RoundSummary, SamplingSummary, UpdateExpirationMode};
+use common::NoopUpdateHandler;
+
+struct RoundCounter(AtomicUsize);
+impl RoundObserver for RoundCounter {
+    fn on_gossip_round(&self, _: RoundSummary) {
+        self.0.fetch_add(1, Ordering::SeqCst);
+    }
+    fn on_sampling_round(&self, _: SamplingSummary) {}
+}
+
+#[test]
+fn gossip_period_change_applies_to_next_rounds() {
+    let mut service = GossipService::new(
+        "127.0.0.1:9000".parse().unwrap(),
+        PeerSamplingConfig::new(true, true, 500, 10, 1, 4),
+        GossipConfig::new(true, true, 2000, UpdateExpirationMode::None)
+    );
+    let counter = Arc::new(RoundCounter(AtomicUsize::new(0)));
+    service.set_round_observer(Some(counter.clone()));
+    service.start_isolated(Box::new(NoopUpdateHandler)).unwrap();
+
+    std::thread::sleep(std::time::Duration::from_millis(2500));
+    let slow_rounds = counter.0.load(Ordering::SeqCst);
+    assert!(slow_rounds <= 2, "{} rounds with a 2000ms period", slow_rounds);
+
+    service.update_config(|config| config.set_gossip_period(100)).unwrap();
+    assert_eq!(100, service.gossip_config().gossip_period());
+
+    // the round in progress still uses the previous period
+    std::thread::sleep(std::time::Duration::from_millis(3500));
+    let fast_rounds = counter.0.load(Ordering::SeqCst) - slow_rounds;
+    assert!(fast_rounds >= 10, "{} rounds after switching to a 100ms period", fast_rounds);
+
+    service.shutdown().unwrap();
+}
+
+#[test]
+fn invalid_changes_are_rejected() {
+    let service: GossipService<NoopUpdateHandler> = GossipService::new(
+        "127.0.0.1:9010".parse().unwrap(),
+        PeerSamplingConfig::new(true, true, 500, 10, 1, 4),
+        GossipConfig::new(true, true, 200, UpdateExpirationMode::None)
+    );
+    assert!(service.update_config(|config| config.set_gossip_period(0)).is_err());
+    assert!(service.update_config(|config| config.set_push_pull(false, false)).is_err());
+    assert_eq!(200, service.gossip_config().gossip_period());
+}
+
+#[test]
+fn expiration_change_applies_to_new_updates() {
+    let mut service = GossipService::new(
+        "127.0.0.1:9020".parse().unwrap(),
+        PeerSamplingConfig::new(true, true, 500, 10, 1, 4),
+        GossipConfig::new(true, true, 100, UpdateExpirationMode::None)
+    );
+    service.start_isolated(Box::new(NoopUpdateHandler)).unwrap();
+
+    service.submit(b"before".to_vec()).unwrap();
+    service.update_config(|config| config.set_update_expiration(UpdateExpirationMode::PushCount(1))).unwrap();
+    service.submit(b"after".to_vec()).unwrap();
+
+    // a peer is needed for push rounds
+    let mut peer = GossipService::new(
+        "127.0.0.1:9021".parse().unwrap(),
+        PeerSamplingConfig::new(true, true, 500, 10, 1, 4),
+        GossipConfig::new(true, true, 100, UpdateExpirationMode::None)
+    );
+    peer.start_with_peers(vec![gossip::Peer::try_new("127.0.0.1:9020").unwrap()], Box::new(NoopUpdateHandler)).unwrap();
+    std::thread::sleep(std::time::Duration::from_millis(2000));
+
+    assert!(service.is_active(b"before".to_vec()));
+    assert!(service.is_expired(b"after".to_vec()));
+
+    peer.shutdown().unwrap();
+    service.shutdown().unwrap();
+}
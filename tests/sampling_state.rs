@@ -0,0 +1,61 @@
+mod common;
+
+use gossip::{AlreadyStarted, GossipConfig, GossipService, Peer, PeerSamplingConfig, SamplingState, StaticSampler, UpdateExpirationMode};
+use common::{peer, sampling_node, NoopUpdateHandler};
+
+fn new_service(port: u16) -> GossipService<NoopUpdateHandler> {
+    sampling_node(port, PeerSamplingConfig::new(true, true, 200, 10, 1, 4), GossipConfig::new(true, true, 200, UpdateExpirationMode::None))
+}
+
+fn peers() -> Vec<Peer> {
+    vec![peer(9091), peer(9092)]
+}
+
+#[test]
+fn sampling_state_follows_the_service() {
+    let mut service = new_service(9000);
+    assert_eq!(SamplingState::NotStarted, service.sampling_state());
+    assert_eq!(SamplingState::NotStarted, service.health().sampling_state);
+    assert!(service.peers().is_empty());
+
+    service.start_with_peers(peers(), Box::new(NoopUpdateHandler)).unwrap();
+    assert_eq!(SamplingState::Running, service.sampling_state());
+    assert_eq!(SamplingState::Running, service.health().sampling_state);
+    assert_eq!(2, service.peers().len());
+
+    service.shutdown().unwrap();
+    assert_eq!(SamplingState::ShutDown, service.sampling_state());
+    let health = service.health();
+    assert_eq!(SamplingState::ShutDown, health.sampling_state);
+    assert!(!health.running);
+    assert!(service.peers().is_empty());
+}
+
+#[test]
+fn service_is_started_once() {
+    let mut service = new_service(9010);
+    service.start_with_peers(peers(), Box::new(NoopUpdateHandler)).unwrap();
+    let error = service.start_with_peers(peers(), Box::new(NoopUpdateHandler)).unwrap_err();
+    assert!(error.is::<AlreadyStarted>());
+    assert_eq!(SamplingState::Running, service.sampling_state());
+    assert!(service.health().running);
+
+    service.shutdown().unwrap();
+    let error = service.start_isolated(Box::new(NoopUpdateHandler)).unwrap_err();
+    assert!(error.is::<AlreadyStarted>());
+    assert_eq!(SamplingState::ShutDown, service.sampling_state());
+}
+
+#[test]
+fn static_sampler_reports_its_state() {
+    let mut service = GossipService::with_sampler(
+        "127.0.0.1:9020".parse().unwrap(),
+        Box::new(StaticSampler::new(peers())),
+        GossipConfig::new(true, true, 200, UpdateExpirationMode::None)
+    );
+    assert_eq!(SamplingState::NotStarted, service.sampling_state());
+    service.start_isolated(Box::new(NoopUpdateHandler)).unwrap();
+    assert_eq!(SamplingState::Running, service.sampling_state());
+    service.shutdown().unwrap();
+    assert_eq!(SamplingState::ShutDown, service.sampling_state());
+}
@@ -0,0 +1,70 @@
+mod common;
+
+use std::time::Duration;
+use gossip::{GossipConfig, GossipService, PeerSamplingConfig, UpdateExpirationMode};
+use common::{start_sampling_node, start_static_node, wait_for, wait_for_within, NoopUpdateHandler};
+
+const VIEW_SIZE: usize = 4;
+
+#[test]
+fn view_sees_more_peers_than_it_holds() {
+    // every node joins through the first one
+    let services: Vec<GossipService<NoopUpdateHandler>> = (0..10).map(|i| {
+        let contacts: &[u16] = if i == 0 { &[] } else { &[9000] };
+        start_sampling_node(9000 + i, contacts, sampling_config(), gossip_config(), NoopUpdateHandler)
+    }).collect();
+
+    wait_for_within(
+        || services.iter().all(|service| service.sampling_stats().unwrap().cycles >= 10),
+        Duration::from_secs(10),
+        "sampling cycles did not run"
+    );
+
+    // a node may keep a stable view for a few cycles, but views are renewed across the cluster
+    let stats: Vec<_> = services.iter().map(|service| service.health().sampling_stats.unwrap()).collect();
+    let distinct_peers = stats.iter().map(|stats| stats.distinct_peers).sum::<usize>() as f64 / stats.len() as f64;
+    assert!(distinct_peers > VIEW_SIZE as f64, "{:?}", stats);
+    assert!(stats.iter().all(|stats| stats.churn > 0.0 && stats.average_age > 0.0), "{:?}", stats);
+    // the first node only waits for contacts in its first cycles
+    assert!(services[1..].iter().all(|service| service.sampling_stats().unwrap().empty_selections == 0));
+
+    for service in services {
+        service.shutdown().unwrap();
+    }
+}
+
+#[test]
+fn static_views_have_no_sampling_stats() {
+    let service = start_static_node(9010, &[9011], gossip_config(), NoopUpdateHandler);
+    assert_eq!(None, service.sampling_stats());
+    assert_eq!(None, service.health().sampling_stats);
+    service.shutdown().unwrap();
+}
+
+#[test]
+fn last_contacted_peer_and_received_messages_are_updated() {
+    let services: Vec<GossipService<NoopUpdateHandler>> = [(9012, 9013), (9013, 9012)].iter().map(|&(port, peer)| {
+        start_sampling_node(port, &[peer], sampling_config(), gossip_config(), NoopUpdateHandler)
+    }).collect();
+
+    let received = |service: &GossipService<NoopUpdateHandler>| service.stats().sampling_messages_received;
+    // a node may receive a message before its first sampling round
+    let contacted = |service: &GossipService<NoopUpdateHandler>| service.sampling_stats().unwrap().last_contacted.is_some();
+    wait_for(|| services.iter().all(|service| received(service) > 0 && contacted(service)), "no sampling message received");
+    let first = received(&services[0]);
+    assert_eq!(Some("127.0.0.1:9013".to_owned()), services[0].sampling_stats().unwrap().last_contacted);
+    assert_eq!(Some("127.0.0.1:9012".to_owned()), services[1].sampling_stats().unwrap().last_contacted);
+    wait_for(|| received(&services[0]) > first, "no further sampling message received");
+
+    for service in services {
+        service.shutdown().unwrap();
+    }
+}
+
+fn sampling_config() -> PeerSamplingConfig {
+    PeerSamplingConfig::new(true, true, 100, VIEW_SIZE, 1, 2)
+}
+
+fn gossip_config() -> GossipConfig {
+    GossipConfig::new(true, true, 100, UpdateExpirationMode::None)
+}
@@ -0,0 +1,105 @@
+mod common;
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use gossip::{GossipConfig, GossipService, Peer, PeerSamplingConfig, SharedListener, Update, UpdateExpirationMode};
+use common::MapUpdatingHandler;
+
+fn gossip_config(cluster_id: u8) -> GossipConfig {
+    let mut gossip_config = GossipConfig::new(true, true, 100, UpdateExpirationMode::None);
+    gossip_config.set_cluster_id(cluster_id);
+    gossip_config
+}
+
+fn sampling_config() -> PeerSamplingConfig {
+    PeerSamplingConfig::new(true, true, 100, 10, 1, 4)
+}
+
+#[test]
+fn clusters_sharing_a_port_do_not_mix() {
+    let received = Arc::new(Mutex::new(HashMap::new()));
+    let first_listener = Arc::new(SharedListener::bind("127.0.0.1:9000".parse().unwrap(), 4, 256, &GossipConfig::default()).unwrap());
+    let second_listener = Arc::new(SharedListener::bind("127.0.0.1:9001".parse().unwrap(), 4, 256, &GossipConfig::default()).unwrap());
+
+    let mut services = Vec::new();
+    for (listener, contact) in [(&first_listener, None), (&second_listener, Some("127.0.0.1:9000"))] {
+        for cluster_id in [1, 2] {
+            let mut service = GossipService::new_shared(Arc::clone(listener), sampling_config(), gossip_config(cluster_id));
+            let id = format!("{} - {}", listener.address(), cluster_id);
+            service.start(
+                Box::new(move || contact.map(|contact| vec![Peer::try_new(contact).unwrap()])),
+                Box::new(MapUpdatingHandler::new(id, Arc::clone(&received)))
+            ).unwrap();
+            services.push((cluster_id, service));
+        }
+    }
+    // a node of cluster 1 with its own port
+    let mut own_port = GossipService::new("127.0.0.1:9002".parse().unwrap(), sampling_config(), gossip_config(1));
+    own_port.start_with_peers(
+        vec![Peer::try_new("127.0.0.1:9000").unwrap()],
+        Box::new(MapUpdatingHandler::new("127.0.0.1:9002 - 1".to_owned(), Arc::clone(&received)))
+    ).unwrap();
+    std::thread::sleep(std::time::Duration::from_millis(1000));
+
+    let content = "cluster 1 update".as_bytes().to_vec();
+    services[0].1.submit(content.clone()).unwrap();
+    std::thread::sleep(std::time::Duration::from_millis(1000));
+
+    let digest = Update::new(content).digest().to_owned();
+    let received = received.lock().unwrap();
+    for id in ["127.0.0.1:9001 - 1", "127.0.0.1:9002 - 1"] {
+        assert_eq!(Some(&vec![digest.clone()]), received.get(id), "{}", id);
+    }
+    for id in ["127.0.0.1:9000 - 2", "127.0.0.1:9001 - 2"] {
+        assert_eq!(None, received.get(id), "{}", id);
+    }
+
+    for (cluster_id, service) in &services {
+        let peers = service.peers();
+        assert!(!peers.is_empty());
+        if *cluster_id == 2 {
+            assert!(peers.iter().all(|peer| peer.address() != "127.0.0.1:9002"), "{:?}", peers);
+        }
+    }
+    assert!(services[0].1.peers().iter().any(|peer| peer.address() == "127.0.0.1:9002"));
+    assert!(services.iter().all(|(_, service)| service.health().listener_bound));
+
+    own_port.shutdown().unwrap();
+    for (_, service) in &mut services {
+        service.shutdown().unwrap();
+    }
+    first_listener.shutdown().unwrap();
+    second_listener.shutdown().unwrap();
+}
+
+#[test]
+fn cluster_id_is_registered_once() {
+    let listener = Arc::new(SharedListener::bind("127.0.0.1:9010".parse().unwrap(), 1, 16, &GossipConfig::default()).unwrap());
+    let mut first = GossipService::new_shared(Arc::clone(&listener), sampling_config(), gossip_config(1));
+    first.start_isolated(Box::new(common::NoopUpdateHandler)).unwrap();
+
+    let mut duplicate = GossipService::new_shared(Arc::clone(&listener), sampling_config(), gossip_config(1));
+    assert!(duplicate.start_isolated(Box::new(common::NoopUpdateHandler)).is_err());
+    let mut without_id = GossipService::new_shared(Arc::clone(&listener), sampling_config(), GossipConfig::default());
+    assert!(without_id.start_isolated(Box::new(common::NoopUpdateHandler)).is_err());
+
+    // the failed starts did not unregister the first service
+    assert!(first.health().listener_bound);
+    first.shutdown().unwrap();
+    assert!(!first.health().listener_bound);
+    listener.shutdown().unwrap();
+}
+
+#[test]
+fn listener_applies_the_connection_limits_it_is_bound_with() {
+    let mut config = GossipConfig::default();
+    config.set_connection_limits(2_000, 1);
+    let listener = Arc::new(SharedListener::bind("127.0.0.1:9020".parse().unwrap(), 1, 16, &config).unwrap());
+
+    let _held = std::net::TcpStream::connect("127.0.0.1:9020").unwrap();
+    std::thread::sleep(std::time::Duration::from_millis(100));
+    let mut refused = std::net::TcpStream::connect("127.0.0.1:9020").unwrap();
+    refused.set_read_timeout(Some(std::time::Duration::from_millis(500))).unwrap();
+    assert!(matches!(std::io::Read::read(&mut refused, &mut [0; 1]), Ok(0)));
+    listener.shutdown().unwrap();
+}
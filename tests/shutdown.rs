@@ -0,0 +1,98 @@
+mod common;
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
+use gossip::{GossipConfig, GossipService, NotStarted, PeerSamplingConfig, Update, UpdateExpirationMode, UpdateHandler};
+use common::{peer, sampling_node, NoopUpdateHandler};
+
+fn new_service<T: UpdateHandler + Send + 'static>(port: u16, gossip_period: u64) -> GossipService<T> {
+    sampling_node(port, PeerSamplingConfig::new(true, true, 200, 10, 1, 4), GossipConfig::new(true, true, gossip_period, UpdateExpirationMode::None))
+}
+
+#[test]
+fn shutdown_before_start() {
+    let mut service = new_service(9000, 200);
+    let error = service.shutdown().unwrap_err();
+    assert!(error.downcast_ref::<NotStarted>().is_some());
+
+    // the service can still be started
+    service.start_isolated(Box::new(NoopUpdateHandler)).unwrap();
+    service.shutdown().unwrap();
+}
+
+#[test]
+fn double_shutdown() {
+    let mut service = new_service(9010, 200);
+    service.start_isolated(Box::new(NoopUpdateHandler)).unwrap();
+    service.shutdown().unwrap();
+    service.shutdown().unwrap();
+}
+
+#[test]
+fn shutdown_during_heavy_traffic() {
+    let mut service = new_service(9020, 50);
+    service.start_isolated(Box::new(NoopUpdateHandler)).unwrap();
+
+    // a peer flooding the node with updates
+    let stop = Arc::new(AtomicBool::new(false));
+    let stop_flood = Arc::clone(&stop);
+    let flood = std::thread::spawn(move || {
+        let mut flooding = new_service(9021, 10);
+        flooding.start_with_peers(vec![peer(9020)], Box::new(NoopUpdateHandler)).unwrap();
+        let mut i = 0u64;
+        while !stop_flood.load(Ordering::SeqCst) {
+            flooding.submit(i.to_be_bytes().to_vec()).unwrap();
+            i += 1;
+            std::thread::sleep(std::time::Duration::from_millis(1));
+        }
+        flooding.shutdown().unwrap();
+    });
+
+    std::thread::sleep(std::time::Duration::from_millis(1000));
+    let start = std::time::Instant::now();
+    service.shutdown().unwrap();
+    assert!(start.elapsed() < std::time::Duration::from_secs(2), "shutdown took {:?}", start.elapsed());
+
+    stop.store(true, Ordering::SeqCst);
+    flood.join().unwrap();
+}
+
+/// Handler submitting an update to its own service for each delivered update, once the test is told about it
+struct SubmittingHandler {
+    service: Arc<OnceLock<Arc<GossipService<SubmittingHandler>>>>,
+    delivered: Mutex<Sender<()>>,
+}
+impl UpdateHandler for SubmittingHandler {
+    fn on_update(&self, update: Update) {
+        self.delivered.lock().unwrap().send(()).unwrap();
+        std::thread::sleep(Duration::from_millis(200));
+        let mut content = update.content().to_vec();
+        content.extend_from_slice(b" again");
+        let _ = self.service.get().unwrap().submit(content);
+    }
+}
+
+#[test]
+fn shutdown_while_handler_submits() {
+    let service_slot = Arc::new(OnceLock::new());
+    let (delivered_tx, delivered) = std::sync::mpsc::channel();
+    let mut service = new_service(9030, 50);
+    service.start_isolated(Box::new(SubmittingHandler { service: Arc::clone(&service_slot), delivered: Mutex::new(delivered_tx) })).unwrap();
+    let service = Arc::new(service);
+    service_slot.set(Arc::clone(&service)).ok().unwrap();
+
+    let mut submitter = new_service(9031, 50);
+    submitter.start_with_peers(vec![peer(9030)], Box::new(NoopUpdateHandler)).unwrap();
+    submitter.submit(b"update".to_vec()).unwrap();
+    delivered.recv_timeout(Duration::from_secs(5)).unwrap();
+
+    // the handler submits while the message handling thread is joined
+    let (stopped_tx, stopped) = std::sync::mpsc::channel();
+    let shutdown = Arc::clone(&service);
+    std::thread::spawn(move || stopped_tx.send(shutdown.shutdown().is_ok()).unwrap());
+    assert!(stopped.recv_timeout(Duration::from_secs(5)).expect("shutdown did not complete"));
+
+    submitter.shutdown().unwrap();
+}
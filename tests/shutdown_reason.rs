@@ -0,0 +1,143 @@
+mod common;
+
+use std::sync::{Arc, Mutex};
+use gossip::{GossipConfig, GossipService, PeerSamplingConfig, RoundObserver, RoundSummary, SamplingSummary, ShutdownReason, Update, UpdateExpirationMode, UpdateHandler};
+use common::{sampling_node, start_sampling_node, wait_for, NoopUpdateHandler};
+
+/// Records the callbacks it receives, and fails if one is received after shutdown
+#[derive(Clone, Default)]
+struct ShutdownHandler {
+    updates: Arc<Mutex<usize>>,
+    reasons: Arc<Mutex<Vec<ShutdownReason>>>,
+    panic_on_update: bool,
+}
+
+impl UpdateHandler for ShutdownHandler {
+    fn on_update(&self, _update: Update) {
+        assert!(self.reasons.lock().unwrap().is_empty(), "update delivered after shutdown");
+        *self.updates.lock().unwrap() += 1;
+        if self.panic_on_update {
+            panic!("handler failure");
+        }
+    }
+
+    fn on_shutdown(&self, reason: ShutdownReason) {
+        self.reasons.lock().unwrap().push(reason);
+    }
+}
+
+#[derive(Default)]
+struct RecordingObserver {
+    reasons: Mutex<Vec<ShutdownReason>>,
+}
+
+impl RoundObserver for RecordingObserver {
+    fn on_gossip_round(&self, _summary: RoundSummary) {}
+    fn on_sampling_round(&self, _summary: SamplingSummary) {}
+    fn on_shutdown(&self, reason: ShutdownReason) {
+        self.reasons.lock().unwrap().push(reason);
+    }
+}
+
+fn sampling_config() -> PeerSamplingConfig {
+    PeerSamplingConfig::new(true, true, 50, 10, 1, 4)
+}
+
+fn gossip_config() -> GossipConfig {
+    GossipConfig::new(true, true, 50, UpdateExpirationMode::None)
+}
+
+fn new_service<T: UpdateHandler + Send + 'static>(port: u16) -> GossipService<T> {
+    sampling_node(port, sampling_config(), gossip_config())
+}
+
+#[test]
+fn handler_is_notified_once_on_shutdown() {
+    let handler = ShutdownHandler::default();
+    let observer = Arc::new(RecordingObserver::default());
+    let mut service = new_service(9000);
+    service.set_round_observer(Some(observer.clone()));
+    service.start_isolated(Box::new(handler.clone())).unwrap();
+    assert!(handler.reasons.lock().unwrap().is_empty());
+
+    service.shutdown().unwrap();
+    service.shutdown().unwrap();
+    assert_eq!(vec![ShutdownReason::Requested], *handler.reasons.lock().unwrap());
+    assert_eq!(vec![ShutdownReason::Requested], *observer.reasons.lock().unwrap());
+}
+
+#[test]
+fn replaced_handler_is_notified() {
+    let first = ShutdownHandler::default();
+    let second = ShutdownHandler::default();
+    let mut service = new_service(9010);
+    service.start_isolated(Box::new(first.clone())).unwrap();
+    service.set_update_handler(Box::new(second.clone()), false).unwrap();
+    assert_eq!(vec![ShutdownReason::Replaced], *first.reasons.lock().unwrap());
+    assert!(second.reasons.lock().unwrap().is_empty());
+
+    service.shutdown().unwrap();
+    assert_eq!(vec![ShutdownReason::Replaced], *first.reasons.lock().unwrap());
+    assert_eq!(vec![ShutdownReason::Requested], *second.reasons.lock().unwrap());
+}
+
+#[test]
+fn handler_is_notified_of_a_stopped_thread() {
+    let handler = ShutdownHandler { panic_on_update: true, ..ShutdownHandler::default() };
+    let mut service = new_service(9020);
+    service.start_isolated(Box::new(handler.clone())).unwrap();
+    let peer = start_sampling_node(9021, &[9020], sampling_config(), gossip_config(), NoopUpdateHandler);
+    peer.submit(b"update".to_vec()).unwrap();
+
+    // the message handling thread, which delivers the updates, stops with the panic of the handler
+    wait_for(|| !service.health().running, "the update was not delivered");
+    assert!(service.shutdown().is_err());
+    let reasons = handler.reasons.lock().unwrap().clone();
+    assert!(matches!(&reasons[..], [ShutdownReason::Failed(thread)] if thread.ends_with("message handler")), "{:?}", reasons);
+    assert_eq!(1, *handler.updates.lock().unwrap());
+    peer.shutdown().unwrap();
+}
+
+/// Replaces itself from its first delivery with a handler recording the callbacks it receives
+struct ReplacingHandler {
+    service: Arc<Mutex<Option<GossipService<ReplacingHandler>>>>,
+    recorder: ShutdownHandler,
+    replacement: Option<ShutdownHandler>,
+}
+
+impl UpdateHandler for ReplacingHandler {
+    fn on_update(&self, update: Update) {
+        self.recorder.on_update(update);
+        if let Some(replacement) = &self.replacement {
+            let replacement = ReplacingHandler { service: Arc::clone(&self.service), recorder: replacement.clone(), replacement: None };
+            self.service.lock().unwrap().as_ref().unwrap().set_update_handler(Box::new(replacement), false).unwrap();
+        }
+    }
+
+    fn on_shutdown(&self, reason: ShutdownReason) {
+        self.recorder.on_shutdown(reason);
+    }
+}
+
+#[test]
+fn handler_replaced_from_its_callback_is_notified_after_the_callback() {
+    let first = ShutdownHandler::default();
+    let second = ShutdownHandler::default();
+    let service = Arc::new(Mutex::new(Some(new_service(9030))));
+    let handler = ReplacingHandler { service: Arc::clone(&service), recorder: first.clone(), replacement: Some(second.clone()) };
+    service.lock().unwrap().as_mut().unwrap().start_isolated(Box::new(handler)).unwrap();
+    let peer = start_sampling_node(9031, &[9030], sampling_config(), gossip_config(), NoopUpdateHandler);
+
+    peer.submit(b"first".to_vec()).unwrap();
+    wait_for(|| !first.reasons.lock().unwrap().is_empty(), "the handler was not replaced");
+    assert_eq!(vec![ShutdownReason::Replaced], *first.reasons.lock().unwrap());
+    assert_eq!(1, *first.updates.lock().unwrap());
+
+    peer.submit(b"second".to_vec()).unwrap();
+    wait_for(|| *second.updates.lock().unwrap() == 1, "the update was not delivered to the new handler");
+    let service = service.lock().unwrap().take().unwrap();
+    service.shutdown().unwrap();
+    assert_eq!(vec![ShutdownReason::Requested], *second.reasons.lock().unwrap());
+    assert_eq!(1, *first.updates.lock().unwrap());
+    peer.shutdown().unwrap();
+}
@@ -0,0 +1,37 @@
+mod common;
+
+use std::time::Duration;
+use gossip::{GossipConfig, PeerSamplingConfig, UpdateExpirationMode};
+use common::{start_sampling_node, wait_for_within, NoopUpdateHandler};
+
+const NODE_COUNT: usize = 20;
+
+#[test]
+fn cluster_size_is_estimated() {
+    let mut services = Vec::new();
+    for port in 9000..9000 + NODE_COUNT as u16 {
+        let mut sampling_config = PeerSamplingConfig::new_with_deviation(true, true, 50, 10, 8, 1, 4);
+        sampling_config.set_size_estimation(Some(40));
+        let contacts: &[u16] = if services.is_empty() { &[] } else { &[9000] };
+        services.push(start_sampling_node(port, contacts, sampling_config, GossipConfig::new(true, true, 100, UpdateExpirationMode::None), NoopUpdateHandler));
+    }
+    assert!(services.iter().all(|service| service.estimated_cluster_size().is_none()));
+
+    // the first epochs may end before the views are mixed
+    let within_range = |estimate: Option<f64>| estimate.is_some_and(|estimate| (estimate - NODE_COUNT as f64).abs() <= 0.3 * NODE_COUNT as f64);
+    wait_for_within(|| services.iter().all(|service| within_range(service.estimated_cluster_size())),
+        Duration::from_secs(30), "estimates did not converge");
+
+    for service in services {
+        service.shutdown().unwrap();
+    }
+}
+
+#[test]
+fn estimation_is_disabled_by_default() {
+    let service = start_sampling_node(9050, &[], PeerSamplingConfig::new(true, true, 20, 10, 1, 4),
+        GossipConfig::new(true, true, 100, UpdateExpirationMode::None), NoopUpdateHandler);
+    std::thread::sleep(Duration::from_millis(200));
+    assert!(service.estimated_cluster_size().is_none());
+    service.shutdown().unwrap();
+}
@@ -0,0 +1,126 @@
+mod common;
+
+use std::io::{ErrorKind, Read, Write};
+use std::net::TcpStream;
+use std::time::{Duration, Instant};
+use gossip::{GossipConfig, GossipService, UpdateExpirationMode};
+use common::{start_static_node, NoopUpdateHandler};
+
+fn start_node(port: u16, peer: u16, configure: impl Fn(&mut GossipConfig)) -> GossipService<NoopUpdateHandler> {
+    let mut gossip_config = GossipConfig::new(true, true, 100, UpdateExpirationMode::None);
+    configure(&mut gossip_config);
+    start_static_node(port, &[peer], gossip_config, NoopUpdateHandler)
+}
+
+/// Returns whether the node closed the connection, waiting at most for the read timeout of the stream
+fn is_closed(stream: &mut TcpStream) -> bool {
+    match stream.read(&mut [0; 1]) {
+        Ok(0) => true,
+        Ok(_) => false,
+        Err(e) => e.kind() != ErrorKind::WouldBlock && e.kind() != ErrorKind::TimedOut,
+    }
+}
+
+/// Sends a byte every 100 milliseconds until the node closes the connection, and returns how long it was open
+fn drip_feed(stream: &mut TcpStream, opened: Instant, mut until: impl FnMut(Duration) -> bool) -> Duration {
+    stream.set_read_timeout(Some(Duration::from_millis(100))).unwrap();
+    loop {
+        let elapsed = opened.elapsed();
+        if stream.write_all(&[0]).is_err() || is_closed(stream) || until(elapsed) {
+            return elapsed;
+        }
+    }
+}
+
+#[test]
+fn drip_feeding_connection_is_closed_at_deadline() {
+    let a = start_node(9000, 9001, |config| config.set_connection_limits(1_000, 64));
+    let b = start_node(9001, 9000, |_| ());
+
+    let opened = Instant::now();
+    let mut stream = TcpStream::connect("127.0.0.1:9000").unwrap();
+    let update = b"normal exchange".to_vec();
+    b.submit(update.clone()).unwrap();
+
+    // the update is received while a worker is still reading the slow connection
+    let mut received = None;
+    let open = drip_feed(&mut stream, opened, |elapsed| {
+        if received.is_none() && a.is_active(update.clone()) {
+            received = Some(elapsed);
+        }
+        elapsed > Duration::from_secs(5)
+    });
+    assert!(open >= Duration::from_millis(900), "connection closed after {:?}", open);
+    assert!(open < Duration::from_millis(2_000), "connection closed after {:?}", open);
+    assert!(received.is_some_and(|received| received < open), "update received at {:?}", received);
+    assert_eq!(1, a.stats().slow_connections);
+
+    b.shutdown().unwrap();
+    a.shutdown().unwrap();
+}
+
+#[test]
+fn connections_beyond_maximum_per_ip_are_refused() {
+    let a = start_node(9002, 9003, |config| config.set_connection_limits(2_000, 2));
+
+    // two connections that never complete hold the slots of the IP address
+    let held: Vec<TcpStream> = (0..2).map(|_| TcpStream::connect("127.0.0.1:9002").unwrap()).collect();
+    std::thread::sleep(Duration::from_millis(100));
+    let mut refused = TcpStream::connect("127.0.0.1:9002").unwrap();
+    refused.set_read_timeout(Some(Duration::from_millis(500))).unwrap();
+    assert!(is_closed(&mut refused));
+    assert_eq!(1, a.stats().refused_connections);
+
+    // the slots are released once the connections are closed
+    drop(held);
+    std::thread::sleep(Duration::from_millis(100));
+    let mut accepted = TcpStream::connect("127.0.0.1:9002").unwrap();
+    accepted.set_read_timeout(Some(Duration::from_millis(500))).unwrap();
+    assert!(!is_closed(&mut accepted));
+    assert_eq!(1, a.stats().refused_connections);
+
+    a.shutdown().unwrap();
+}
+
+#[test]
+fn slow_connections_put_their_ip_address_in_quarantine() {
+    let a = start_node(9004, 9005, |config| {
+        config.set_connection_limits(300, 64);
+        config.set_quarantine(1, 60_000);
+    });
+
+    let mut slow = TcpStream::connect("127.0.0.1:9004").unwrap();
+    drip_feed(&mut slow, Instant::now(), |elapsed| elapsed > Duration::from_secs(5));
+    assert_eq!(vec!["127.0.0.1".to_owned()], a.quarantined_peers());
+
+    let mut refused = TcpStream::connect("127.0.0.1:9004").unwrap();
+    refused.set_read_timeout(Some(Duration::from_millis(500))).unwrap();
+    assert!(is_closed(&mut refused));
+    assert!(a.stats().refused_connections >= 1);
+
+    a.shutdown().unwrap();
+}
+
+#[test]
+fn large_message_sent_steadily_outlives_the_deadline() {
+    let a = start_node(9006, 9007, |config| {
+        config.set_connection_limits(300, 64);
+        config.set_quarantine(1, 60_000);
+    });
+
+    // 256 KiB at about 320 KiB per second, which takes more than twice the deadline
+    let mut stream = TcpStream::connect("127.0.0.1:9006").unwrap();
+    let opened = Instant::now();
+    for _ in 0..16 {
+        stream.write_all(&[0; 16 * 1024]).unwrap();
+        std::thread::sleep(Duration::from_millis(50));
+    }
+    stream.shutdown(std::net::Shutdown::Write).unwrap();
+    assert!(opened.elapsed() > Duration::from_millis(600));
+    stream.set_read_timeout(Some(Duration::from_millis(1_000))).unwrap();
+    assert!(is_closed(&mut stream));
+
+    assert_eq!(0, a.stats().slow_connections);
+    assert!(a.quarantined_peers().is_empty());
+    a.shutdown().unwrap();
+}
@@ -0,0 +1,101 @@
+mod common;
+
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use gossip::{GossipConfig, GossipService, PeerSamplingConfig, RoundObserver, RoundSummary, SamplingSummary, Update, UpdateExpirationMode};
+use common::{start_sampling_node, wait_for, NoopUpdateHandler};
+
+const SAMPLING_PERIOD: u64 = 50;
+
+// counts the sampling rounds and records the longest one
+#[derive(Default)]
+struct SamplingCounter {
+    rounds: AtomicU64,
+    longest: Mutex<Duration>,
+}
+impl SamplingCounter {
+    // returns the number of rounds and the longest round since the last call
+    fn take(&self) -> (u64, Duration) {
+        (self.rounds.swap(0, Ordering::SeqCst), std::mem::take(&mut *self.longest.lock().unwrap()))
+    }
+}
+impl RoundObserver for SamplingCounter {
+    fn on_gossip_round(&self, _summary: RoundSummary) {}
+    fn on_sampling_round(&self, summary: SamplingSummary) {
+        self.rounds.fetch_add(1, Ordering::SeqCst);
+        let mut longest = self.longest.lock().unwrap();
+        *longest = (*longest).max(summary.duration);
+    }
+}
+
+fn start_node(port: u16, contacts: &[u16], expiration: UpdateExpirationMode) -> GossipService<NoopUpdateHandler> {
+    start_sampling_node(port, contacts, PeerSamplingConfig::new(true, true, SAMPLING_PERIOD, 10, 1, 4),
+        GossipConfig::new(true, true, 100, expiration), NoopUpdateHandler)
+}
+
+#[test]
+fn readers_do_not_slow_down_sampling() {
+    let first = start_node(9000, &[], UpdateExpirationMode::None);
+    let second = Arc::new(start_node(9001, &[9000], UpdateExpirationMode::None));
+    let counter = Arc::new(SamplingCounter::default());
+    second.set_round_observer(Some(counter.clone()));
+
+    std::thread::sleep(Duration::from_millis(SAMPLING_PERIOD * 4));
+    counter.take();
+    std::thread::sleep(Duration::from_millis(1000));
+    let (quiet_rounds, _) = counter.take();
+
+    // read the peers and the digests in a tight loop
+    let stop = Arc::new(AtomicBool::new(false));
+    let reads = Arc::new(AtomicU64::new(0));
+    let readers: Vec<_> = (0..2).map(|_| {
+        let (service, stop, reads) = (Arc::clone(&second), Arc::clone(&stop), Arc::clone(&reads));
+        std::thread::spawn(move || {
+            while !stop.load(Ordering::SeqCst) {
+                assert!(service.peers().len() <= 10);
+                service.active_digests();
+                reads.fetch_add(1, Ordering::SeqCst);
+            }
+        })
+    }).collect();
+    std::thread::sleep(Duration::from_millis(1000));
+    let (busy_rounds, longest) = counter.take();
+    stop.store(true, Ordering::SeqCst);
+    for reader in readers {
+        reader.join().unwrap();
+    }
+
+    assert!(reads.load(Ordering::SeqCst) > 1000, "only {} reads", reads.load(Ordering::SeqCst));
+    assert!(busy_rounds + 2 >= quiet_rounds, "{} rounds with readers, {} without", busy_rounds, quiet_rounds);
+    assert!(longest < Duration::from_millis(SAMPLING_PERIOD), "longest round took {:?}", longest);
+    assert!(second.peers().iter().any(|peer| peer.address() == "127.0.0.1:9000"));
+
+    second.shutdown().unwrap();
+    first.shutdown().unwrap();
+    assert!(second.peers().is_empty());
+}
+
+#[test]
+fn active_digests_follow_updates() {
+    // expired updates are cleared during the push rounds, which need a peer
+    let peer = start_node(9011, &[], UpdateExpirationMode::None);
+    let service = start_node(9010, &[9011], UpdateExpirationMode::MostRecent(1, 0.));
+    assert!(service.active_digests().is_empty());
+
+    service.submit(b"first".to_vec()).unwrap();
+    let before = service.active_digests();
+    assert_eq!(vec![Update::new(b"first".to_vec()).digest().to_owned()], *before);
+
+    // the published list is replaced, not modified, when the updates change
+    service.submit(b"second".to_vec()).unwrap();
+    assert!(service.active_digests().contains(&Update::new(b"second".to_vec()).digest().to_owned()));
+    assert_eq!(1, before.len());
+
+    wait_for(|| service.active_digests().len() <= 1, "first update did not expire");
+    assert_eq!(vec![Update::new(b"second".to_vec()).digest().to_owned()], *service.active_digests());
+
+    service.shutdown().unwrap();
+    peer.shutdown().unwrap();
+    assert!(service.active_digests().is_empty());
+}
@@ -0,0 +1,45 @@
+mod common;
+
+use gossip::{GossipConfig, GossipService, NetworkConfig, PeerSamplingConfig, UpdateExpirationMode};
+use common::{start_sampling_node, wait_for, NoopUpdateHandler};
+
+fn start_node(port: u16, contacts: &[u16], network: NetworkConfig) -> GossipService<NoopUpdateHandler> {
+    let mut gossip_config = GossipConfig::new(true, true, 100, UpdateExpirationMode::None);
+    gossip_config.set_network(network);
+    start_sampling_node(port, contacts, PeerSamplingConfig::new(true, true, 100, 10, 1, 4), gossip_config, NoopUpdateHandler)
+}
+
+fn wait_for_update(service: &GossipService<NoopUpdateHandler>, content: &[u8]) {
+    wait_for(|| service.is_active(content.to_vec()), "update was not received");
+}
+
+#[test]
+fn node_is_restarted_on_its_port() {
+    let first = start_node(9000, &[], NetworkConfig::default());
+    let second = start_node(9001, &[9000], NetworkConfig::default());
+    first.submit(b"before restart".to_vec()).unwrap();
+    wait_for_update(&second, b"before restart");
+
+    // restart immediately, while connections of the previous run may still be closing
+    first.shutdown().unwrap();
+    let first = start_node(9000, &[], NetworkConfig::default());
+    first.submit(b"after restart".to_vec()).unwrap();
+    wait_for_update(&second, b"after restart");
+
+    first.shutdown().unwrap();
+    second.shutdown().unwrap();
+}
+
+#[test]
+fn updates_are_exchanged_without_nodelay() {
+    let mut network = NetworkConfig::default();
+    network.set_nodelay(false);
+    network.set_keepalive(Some(30_000));
+    let first = start_node(9010, &[], network.clone());
+    let second = start_node(9011, &[9010], network);
+    first.submit(b"delayed".to_vec()).unwrap();
+    wait_for_update(&second, b"delayed");
+
+    first.shutdown().unwrap();
+    second.shutdown().unwrap();
+}
@@ -0,0 +1,31 @@
+mod common;
+
+use gossip::{BindError, GossipService, PeerSamplingConfig, GossipConfig, UpdateExpirationMode};
+use crate::common::NoopUpdateHandler;
+
+fn thread_count() -> usize {
+    std::fs::read_dir("/proc/self/task").map_or(0, |tasks| tasks.count())
+}
+
+#[test]
+fn start_on_used_port() {
+    let address = "127.0.0.1:9000";
+    let listener = std::net::TcpListener::bind(address).unwrap();
+
+    let threads_before = thread_count();
+    let mut service = GossipService::new(
+        address.parse().unwrap(),
+        PeerSamplingConfig::new(true, true, 200, 10, 1, 4),
+        GossipConfig::new(true, true, 200, UpdateExpirationMode::None)
+    );
+    let error = service.start_isolated(Box::new(NoopUpdateHandler)).unwrap_err();
+    let bind_error = error.downcast_ref::<BindError>().expect("error should be a BindError");
+    assert_eq!(std::io::ErrorKind::AddrInUse, bind_error.kind());
+    assert_eq!(&address.parse::<std::net::SocketAddr>().unwrap(), bind_error.address());
+    assert_eq!(threads_before, thread_count());
+
+    // the service can be started once the port is available
+    drop(listener);
+    service.start_isolated(Box::new(NoopUpdateHandler)).unwrap();
+    service.shutdown().unwrap();
+}
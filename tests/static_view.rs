@@ -0,0 +1,102 @@
+mod common;
+
+use std::time::Duration;
+use gossip::{GossipConfig, GossipService, Peer, UpdateExpirationMode};
+use common::{start_static_node, wait_for_within, NoopUpdateHandler};
+
+const PORTS: [u16; 3] = [9000, 9001, 9002];
+
+fn start_node(port: u16) -> GossipService<NoopUpdateHandler> {
+    // the view also holds a peer that is not running
+    let peers: Vec<u16> = PORTS.iter().copied().chain([9009]).collect();
+    start_static_node(port, &peers, GossipConfig::new(true, true, 100, UpdateExpirationMode::None), NoopUpdateHandler)
+}
+
+#[test]
+fn static_cluster_propagates_updates() {
+    let nodes: Vec<_> = PORTS.iter().map(|port| start_node(*port)).collect();
+    for node in &nodes {
+        let health = node.health();
+        assert!(health.running);
+        assert!(health.ready);
+        assert_eq!(3, health.peer_count);
+        assert_eq!(None, health.sampling_mode);
+        assert!(node.config_warnings().is_empty());
+    }
+
+    for (index, node) in nodes.iter().enumerate() {
+        node.submit(format!("update {}", index).into_bytes()).unwrap();
+    }
+    wait_for_within(|| nodes.iter().all(|node| (0..nodes.len()).all(|index| node.is_active(format!("update {}", index).into_bytes()))),
+        Duration::from_secs(10), "updates did not reach every node");
+
+    for node in &nodes {
+        assert_eq!(0, node.stats().sampling_messages_sent);
+        assert_eq!(3, node.peers().len());
+    }
+    for node in nodes {
+        node.shutdown().unwrap();
+    }
+}
+
+#[test]
+fn all_updates_received_with_sampler() {
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+    use rand::Rng;
+    use gossip::{StaticSampler, Update};
+    use common::MapUpdatingHandler;
+
+    // messages received by each peer
+    let peer_messages = Arc::new(Mutex::new(HashMap::new()));
+
+    let gossip_period = 400;
+    let addresses: Vec<String> = (9020..9030).map(|port| format!("127.0.0.1:{}", port)).collect();
+
+    // every node knows all the others, the view is never exchanged
+    let mut instances = vec![];
+    for address in &addresses {
+        let sampler = StaticSampler::new(addresses.iter().map(|address| Peer::try_new(address).unwrap()).collect());
+        let mut service = GossipService::with_sampler(
+            address.parse().unwrap(),
+            Box::new(sampler),
+            GossipConfig::new(true, true, gossip_period, UpdateExpirationMode::None)
+        );
+        service.start_isolated(Box::new(MapUpdatingHandler::new(address.clone(), Arc::clone(&peer_messages)))).unwrap();
+        assert!(service.health().ready);
+        assert_eq!(addresses.len() - 1, service.peers().len());
+        instances.push(service);
+    }
+
+    let message_count = 10;
+    let mut all_messages = Vec::with_capacity(message_count);
+    for i in 0..message_count {
+        let message = format!("MSGID {}", i).as_bytes().to_vec();
+        let update = Update::new(message.clone());
+        let my_digest = update.digest().clone();
+        all_messages.push(update);
+        let selected_peer = rand::thread_rng().gen_range(0, instances.len());
+        instances[selected_peer].submit(message).unwrap();
+        {
+            peer_messages.lock().unwrap().entry(instances[selected_peer].address().to_string()).or_default().push(my_digest);
+        }
+        std::thread::sleep(Duration::from_millis(200));
+    }
+
+    // wait for broadcast
+    std::thread::sleep(Duration::from_millis(gossip_period * 7));
+
+    let peer_messages = peer_messages.lock().unwrap();
+    for instance in &instances {
+        let my_messages = peer_messages.get(&instance.address().to_string()).unwrap();
+        assert_eq!(all_messages.len(), my_messages.len());
+        for update in &all_messages {
+            assert!(my_messages.contains(update.digest()));
+        }
+        assert_eq!(0, instance.stats().sampling_messages_sent);
+    }
+
+    for instance in instances {
+        instance.shutdown().unwrap();
+    }
+}
@@ -0,0 +1,45 @@
+mod common;
+
+use gossip::{BatchItem, GossipConfig, GossipService, NotStarted, Update, UpdateExpirationMode};
+use common::{start_static_node, static_node, wait_for, NoopUpdateHandler};
+
+fn digest(content: &[u8]) -> String {
+    Update::new(content.to_vec()).digest().clone()
+}
+
+#[test]
+fn batch_reports_duplicates_and_propagates_new_updates() {
+    let a = start_static_node(9000, &[9001], GossipConfig::new(true, true, 100, UpdateExpirationMode::None), NoopUpdateHandler);
+    let b = start_static_node(9001, &[9000], GossipConfig::new(true, true, 100, UpdateExpirationMode::None), NoopUpdateHandler);
+    a.submit(b"already active".to_vec()).unwrap();
+
+    let batch: Vec<Vec<u8>> = vec![b"row 1".to_vec(), b"already active".to_vec(), b"row 2".to_vec(), b"row 1".to_vec(), b"row 3".to_vec()];
+    let result = a.submit_batch(batch).unwrap();
+    assert_eq!(vec![
+        BatchItem::Inserted(digest(b"row 1")),
+        BatchItem::Duplicate(digest(b"already active")),
+        BatchItem::Inserted(digest(b"row 2")),
+        BatchItem::Duplicate(digest(b"row 1")),
+        BatchItem::Inserted(digest(b"row 3")),
+    ], result);
+
+    let contents = [&b"row 1"[..], b"row 2", b"row 3", b"already active"];
+    wait_for(|| contents.iter().all(|content| b.is_active(content.to_vec())), "batch did not reach the peer");
+
+    b.shutdown().unwrap();
+    a.shutdown().unwrap();
+}
+
+#[test]
+fn batch_before_start() {
+    let service: GossipService<NoopUpdateHandler> = static_node(9002, &[9003], GossipConfig::new(true, true, 100, UpdateExpirationMode::None));
+    let result = service.submit_batch(vec![b"queued".to_vec(), b"queued".to_vec()]).unwrap();
+    assert_eq!(vec![true, false], result.iter().map(BatchItem::is_inserted).collect::<Vec<_>>());
+
+    let mut gossip_config = GossipConfig::new(true, true, 100, UpdateExpirationMode::None);
+    gossip_config.set_queue_before_start(false);
+    let service: GossipService<NoopUpdateHandler> = static_node(9004, &[9005], gossip_config);
+    let error = service.submit_batch(vec![b"refused".to_vec()]).unwrap_err();
+    assert!(error.inserted().is_empty());
+    assert!(std::error::Error::source(&error).unwrap().is::<NotStarted>());
+}
@@ -0,0 +1,49 @@
+mod common;
+
+use std::time::Duration;
+use gossip::{GossipConfig, GossipService, NotStarted, PeerSamplingConfig, UpdateExpirationMode};
+use common::{peer, sampling_node, wait_for, NoopUpdateHandler};
+
+fn create_node(port: u16, gossip_config: GossipConfig) -> GossipService<NoopUpdateHandler> {
+    sampling_node(port, PeerSamplingConfig::new(true, true, 100, 10, 1, 4), gossip_config)
+}
+
+#[test]
+fn updates_submitted_before_start_are_queued() {
+    let expiration = UpdateExpirationMode::DurationMillis(1000);
+    let mut receiver = create_node(9001, GossipConfig::new(true, true, 100, expiration.clone()));
+    receiver.start_isolated(Box::new(NoopUpdateHandler)).unwrap();
+
+    let mut service = create_node(9000, GossipConfig::new(true, true, 100, expiration));
+    service.submit(b"early".to_vec()).unwrap();
+    assert!(service.submit(b"early".to_vec()).is_err());
+    assert!(!service.is_active(b"early".to_vec()));
+
+    // the update would have expired if its expiration had started at submission
+    std::thread::sleep(Duration::from_millis(1200));
+    service.start_with_peers(vec![peer(9001)], Box::new(NoopUpdateHandler)).unwrap();
+    assert!(service.is_active(b"early".to_vec()));
+
+    wait_for(|| receiver.is_active(b"early".to_vec()), "queued update did not reach the peer");
+
+    service.shutdown().unwrap();
+    receiver.shutdown().unwrap();
+}
+
+#[test]
+fn updates_submitted_before_start_are_rejected() {
+    let mut gossip_config = GossipConfig::new(true, true, 100, UpdateExpirationMode::None);
+    gossip_config.set_queue_before_start(false);
+    let mut service = create_node(9010, gossip_config);
+
+    let error = service.submit(b"early".to_vec()).unwrap_err();
+    assert!(error.downcast_ref::<NotStarted>().is_some());
+    assert!(service.submit_chunked(vec![1; 100], 10).unwrap_err().downcast_ref::<NotStarted>().is_some());
+
+    service.start_isolated(Box::new(NoopUpdateHandler)).unwrap();
+    assert!(!service.is_active(b"early".to_vec()));
+    service.submit(b"early".to_vec()).unwrap();
+    assert!(service.is_active(b"early".to_vec()));
+
+    service.shutdown().unwrap();
+}
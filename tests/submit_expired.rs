@@ -30,8 +30,8 @@ fn submit_expired() {
         PeerSamplingConfig::new_with_deviation(true, true, sampling_period, sampling_deviation, 10, 1, 4),
         GossipConfig::new_with_deviation(true, true, gossip_period, gossip_deviation, expiration_mode)
     );
-    service_2.start(
-        Box::new(move || Some(vec![Peer::new(address_1.to_owned())])),
+    service_2.start_with_peers(
+        vec![Peer::try_new(address_1).unwrap()],
         Box::new(TextMessageHandler::new(address_2.to_owned()))
     ).unwrap();
 
@@ -0,0 +1,65 @@
+mod common;
+
+use std::io::Read;
+use std::net::TcpListener;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use gossip::{GossipConfig, SubmitOutcome, UpdateExpirationMode, UpdateState};
+use common::{start_static_node, wait_for, NoopUpdateHandler};
+
+/// Starts a peer that records the messages it receives, without answering them
+fn start_recording_peer(port: u16) -> Arc<Mutex<Vec<Vec<u8>>>> {
+    let listener = TcpListener::bind(("127.0.0.1", port)).unwrap();
+    let received = Arc::new(Mutex::new(Vec::new()));
+    let recorded = Arc::clone(&received);
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut bytes = Vec::new();
+            if stream.and_then(|mut stream| stream.read_to_end(&mut bytes)).is_ok() {
+                recorded.lock().unwrap().push(bytes);
+            }
+        }
+    });
+    received
+}
+
+fn pushes_received(received: &Mutex<Vec<Vec<u8>>>, digest: &str) -> usize {
+    received.lock().unwrap().iter()
+        .filter(|bytes| bytes.windows(digest.len()).any(|window| window == digest.as_bytes()))
+        .count()
+}
+
+#[test]
+fn refreshed_update_is_pushed_again() {
+    let received = start_recording_peer(9001);
+    let service = start_static_node(9000, &[9001], GossipConfig::new(true, false, 200, UpdateExpirationMode::PushCount(2)), NoopUpdateHandler);
+
+    let digest = match service.submit_or_refresh(b"heartbeat".to_vec()).unwrap() {
+        SubmitOutcome::Inserted(digest) => digest,
+        outcome => panic!("Unexpected outcome {:?}", outcome),
+    };
+    wait_for(|| service.update_counters(&digest).is_some_and(|counters| counters.pushes >= 1), "the update was not pushed");
+    assert_eq!(SubmitOutcome::Refreshed(digest.clone()), service.submit_or_refresh(b"heartbeat".to_vec()).unwrap());
+
+    wait_for(|| service.update_state(&digest) == Some(UpdateState::Expired), "the refreshed update did not expire");
+    std::thread::sleep(Duration::from_millis(100));
+    assert!(pushes_received(&received, &digest) > 2, "{} pushes received", pushes_received(&received, &digest));
+
+    // expired updates are refused by default
+    assert!(service.submit_or_refresh(b"heartbeat".to_vec()).is_err());
+    service.shutdown().unwrap();
+}
+
+#[test]
+fn expired_update_is_inserted_again_if_enabled() {
+    let received = start_recording_peer(9011);
+    let mut gossip_config = GossipConfig::new(true, false, 50, UpdateExpirationMode::PushCount(1));
+    gossip_config.set_refresh_expired(true);
+    let service = start_static_node(9010, &[9011], gossip_config, NoopUpdateHandler);
+
+    let digest = service.submit_or_refresh(b"heartbeat".to_vec()).unwrap().digest().to_owned();
+    wait_for(|| service.update_state(&digest) == Some(UpdateState::Expired), "the update did not expire");
+    assert_eq!(SubmitOutcome::Inserted(digest.clone()), service.submit_or_refresh(b"heartbeat".to_vec()).unwrap());
+    wait_for(|| pushes_received(&received, &digest) >= 2, "the update was not pushed again");
+    service.shutdown().unwrap();
+}
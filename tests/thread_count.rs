@@ -0,0 +1,42 @@
+mod common;
+
+use std::time::Duration;
+use gossip::{GossipConfig, PeerSamplingConfig, UpdateExpirationMode};
+use common::{start_sampling_node, wait_for_within, NoopUpdateHandler};
+
+/// Returns the number of threads of the process whose name starts with the address of a node. Thread names are
+/// truncated by the system, which keeps the address and the separator.
+#[cfg(target_os = "linux")]
+fn node_threads(address: &str) -> usize {
+    let prefix = format!("{} ", address);
+    std::fs::read_dir("/proc/self/task").unwrap()
+        .filter_map(|task| std::fs::read_to_string(task.unwrap().path().join("comm")).ok())
+        .filter(|name| name.starts_with(&prefix))
+        .count()
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn node_runs_on_a_listener_a_message_handler_and_a_timer_thread() {
+    let start_node = |port: u16, workers: usize| {
+        let mut gossip_config = GossipConfig::new(true, true, 100, UpdateExpirationMode::None);
+        gossip_config.set_listener_workers(workers);
+        start_sampling_node(port, &[9000], PeerSamplingConfig::new(true, true, 100, 10, 1, 4), gossip_config, NoopUpdateHandler)
+    };
+    let first = start_node(9000, 1);
+    let second = start_node(9001, 2);
+
+    // updates are still exchanged
+    second.submit(b"threads".to_vec()).unwrap();
+    wait_for_within(|| first.is_active(b"threads".to_vec()), Duration::from_secs(10), "update not received");
+
+    // the listener with its workers, the message handler, which also delivers the updates, and the timer thread
+    // running the gossip, keepalive and peer sampling rounds
+    assert_eq!(3 + 1, node_threads("127.0.0.1:9000"));
+    assert_eq!(3 + 2, node_threads("127.0.0.1:9001"));
+
+    first.shutdown().unwrap();
+    second.shutdown().unwrap();
+    assert_eq!(0, node_threads("127.0.0.1:9000"));
+    assert_eq!(0, node_threads("127.0.0.1:9001"));
+}
@@ -0,0 +1,63 @@
+mod common;
+
+use std::time::Duration;
+use gossip::{GossipConfig, GossipService, Update, UpdateExpirationMode, UpdateState};
+use common::{static_node, wait_for, RecordingHandler};
+
+const DEAD_UPDATE: &[u8] = b"dead update";
+
+fn start_node(port: u16, peers: &[u16], expiration: UpdateExpirationMode, tombstones: bool, submitted: Option<&[u8]>, handler: RecordingHandler) -> GossipService<RecordingHandler> {
+    let mut gossip_config = GossipConfig::new(true, true, 100, expiration);
+    if tombstones {
+        gossip_config.set_tombstones(100, 60_000);
+    }
+    let mut service = static_node(port, peers, gossip_config);
+    if let Some(content) = submitted {
+        service.submit(content.to_vec()).unwrap();
+    }
+    service.start_isolated(Box::new(handler)).unwrap();
+    service
+}
+
+/// Runs the scenario on ports `base..base + 4`, and returns the updates delivered to the new node D: A expires an
+/// update it disseminated to C, then B, which missed the expiration, comes back with the update and meets D.
+fn dead_update_scenario(base: u16, tombstones: bool) -> Vec<Vec<u8>> {
+    let (a_port, b_port, c_port, d_port) = (base, base + 1, base + 2, base + 3);
+    let digest = Update::new(DEAD_UPDATE.to_vec()).digest().clone();
+    let expired = |service: &GossipService<RecordingHandler>| service.update_state(&digest) == Some(UpdateState::Expired);
+
+    let a = start_node(a_port, &[c_port], UpdateExpirationMode::DurationMillis(500), tombstones, Some(DEAD_UPDATE), RecordingHandler::default());
+    let c = start_node(c_port, &[a_port, b_port], UpdateExpirationMode::None, tombstones, None, RecordingHandler::default());
+    wait_for(|| c.is_active(DEAD_UPDATE.to_vec()), "update did not reach C");
+    wait_for(|| expired(&a), "update did not expire on A");
+    if tombstones {
+        wait_for(|| expired(&c), "tombstone did not reach C");
+    }
+
+    let b = start_node(b_port, &[c_port, d_port], UpdateExpirationMode::None, tombstones, Some(DEAD_UPDATE), RecordingHandler::default());
+    if tombstones {
+        wait_for(|| expired(&b), "tombstone did not reach B");
+    }
+
+    let received = RecordingHandler::default();
+    let d = start_node(d_port, &[b_port], UpdateExpirationMode::None, tombstones, None, received.clone());
+    std::thread::sleep(Duration::from_millis(1_000));
+    if tombstones {
+        assert!(expired(&d));
+    }
+
+    for service in [d, b, c, a] {
+        service.shutdown().unwrap();
+    }
+    received.received()
+}
+
+#[test]
+fn expired_update_is_not_disseminated_again() {
+    assert!(dead_update_scenario(9000, true).is_empty());
+}
+
+#[test]
+fn expired_update_is_disseminated_again_without_tombstones() {
+    assert_eq!(vec![DEAD_UPDATE.to_vec()], dead_update_scenario(9010, false));
+}
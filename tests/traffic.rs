@@ -0,0 +1,41 @@
+mod common;
+
+use std::time::Duration;
+use gossip::{GossipConfig, TrafficCounters, UpdateExpirationMode};
+use common::{start_static_node, wait_for_within, NoopUpdateHandler};
+
+const PORTS: [u16; 3] = [9000, 9001, 9002];
+
+#[test]
+fn traffic_is_counted_per_peer() {
+    let nodes: Vec<_> = PORTS.iter()
+        .map(|port| start_static_node(*port, &PORTS, GossipConfig::new(true, true, 100, UpdateExpirationMode::None), NoopUpdateHandler))
+        .collect();
+    assert!(nodes[0].traffic().is_empty());
+
+    nodes[0].submit(vec![7; 10_000]).unwrap();
+    wait_for_within(|| nodes.iter().all(|node| node.is_active(vec![7; 10_000])), Duration::from_secs(10), "update did not reach every node");
+
+    // the peers are chosen at random in each round
+    let exchanged_with_both = || {
+        let traffic = nodes[0].traffic();
+        let mut peers: Vec<&str> = traffic.iter().map(|traffic| traffic.peer.as_str()).collect();
+        peers.sort();
+        peers == ["127.0.0.1:9001", "127.0.0.1:9002"] && traffic.iter().all(|peer| peer.received.header.messages > 0)
+    };
+    wait_for_within(exchanged_with_both, Duration::from_secs(10), "traffic was not recorded with both peers");
+    let traffic = nodes[0].traffic();
+    for peer in &traffic {
+        assert!(peer.sent.header.messages > 0);
+        assert_eq!(TrafficCounters::default(), peer.sent.sampling);
+        assert_eq!(peer.sent.total().bytes + peer.received.total().bytes, peer.bytes());
+    }
+    // the peers with the most bytes come first
+    assert!(traffic.windows(2).all(|pair| pair[0].bytes() >= pair[1].bytes()));
+    // the content of the update was sent to at least one of the peers
+    assert!(traffic.iter().any(|peer| peer.sent.content.bytes > 10_000));
+
+    for node in nodes {
+        node.shutdown().unwrap();
+    }
+}
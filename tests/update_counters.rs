@@ -0,0 +1,42 @@
+mod common;
+
+use std::time::Duration;
+use gossip::{GossipConfig, GossipService, Update, UpdateExpirationMode};
+use common::{start_static_node, wait_for_within, NoopUpdateHandler};
+
+fn start_node(port: u16, neighbours: &[u16]) -> GossipService<NoopUpdateHandler> {
+    start_static_node(port, neighbours, GossipConfig::new(true, true, 100, UpdateExpirationMode::None), NoopUpdateHandler)
+}
+
+#[test]
+fn origin_counts_distinct_requesters() {
+    // B and C only know A, from which they both request the content
+    let a = start_node(9000, &[9001, 9002]);
+    let b = start_node(9001, &[9000]);
+    let c = start_node(9002, &[9000]);
+
+    let digest = Update::new(b"counted".to_vec()).digest().to_owned();
+    a.submit(b"counted".to_vec()).unwrap();
+    wait_for_within(|| b.is_active(b"counted".to_vec()) && c.is_active(b"counted".to_vec()),
+        Duration::from_secs(10), "update did not reach B and C");
+
+    let counters = a.update_counters(&digest).unwrap();
+    assert!(counters.distinct_requesters >= 2, "{:?}", counters);
+    assert!(counters.content_requests >= 2);
+    assert!(counters.pushes >= 1);
+    assert!(counters.last_request.is_some());
+    // the rounds go on meanwhile: the listed counters are at least those read before
+    let stats = a.update_stats();
+    assert_eq!(vec![digest.clone()], stats.iter().map(|(digest, _)| digest.clone()).collect::<Vec<_>>());
+    let listed = &stats[0].1;
+    assert!(listed.pushes >= counters.pushes && listed.content_requests >= counters.content_requests, "{:?}", listed);
+    assert!(listed.distinct_requesters >= counters.distinct_requesters && listed.last_request >= counters.last_request, "{:?}", listed);
+
+    // the receivers did not serve the content
+    assert_eq!(0, b.update_counters(&digest).unwrap().distinct_requesters);
+    assert_eq!(None, b.update_counters("unknown"));
+
+    c.shutdown().unwrap();
+    b.shutdown().unwrap();
+    a.shutdown().unwrap();
+}
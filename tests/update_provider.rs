@@ -0,0 +1,34 @@
+mod common;
+
+use gossip::{GossipConfig, GossipService, Update, UpdateExpirationMode, UpdateState};
+use common::{start_static_node, wait_for, NoopUpdateHandler};
+
+fn start_node(port: u16, neighbors: &[u16]) -> GossipService<NoopUpdateHandler> {
+    start_static_node(port, neighbors, GossipConfig::new(true, true, 100, UpdateExpirationMode::None), NoopUpdateHandler)
+}
+
+#[test]
+fn provider_is_the_immediate_sender() {
+    let (first, middle) = ("127.0.0.1:9000", "127.0.0.1:9001");
+    let nodes = [
+        start_node(9000, &[9001]),
+        start_node(9001, &[9000, 9002]),
+        start_node(9002, &[9001]),
+    ];
+
+    let content = b"provided update".to_vec();
+    nodes[0].submit(content.clone()).unwrap();
+    wait_for(|| nodes[2].is_active(content.clone()), "update did not reach the last node");
+
+    let digest = Update::new(content).digest().to_owned();
+    assert_eq!(Some(UpdateState::Active { provider: None }), nodes[0].update_state(&digest));
+    assert_eq!(Some(UpdateState::Active { provider: Some(first.to_owned()) }), nodes[1].update_state(&digest));
+    assert_eq!(Some(UpdateState::Active { provider: Some(middle.to_owned()) }), nodes[2].update_state(&digest));
+    assert_eq!(vec![digest.clone()], nodes[2].updates_from(middle));
+    assert!(nodes[2].updates_from(first).is_empty());
+    assert_eq!(None, nodes[2].update_state("unknown"));
+
+    for node in &nodes {
+        node.shutdown().unwrap();
+    }
+}
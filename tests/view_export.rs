@@ -0,0 +1,86 @@
+mod common;
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use gossip::{GossipConfig, GossipService, PeerSamplingConfig, UpdateExpirationMode};
+use common::{peer, sampling_node, wait_for_within, NoopUpdateHandler};
+
+fn create_node(port: u16) -> GossipService<NoopUpdateHandler> {
+    sampling_node(port, PeerSamplingConfig::new(true, true, 200, 3, 1, 1), GossipConfig::new(true, true, 100, UpdateExpirationMode::None))
+}
+
+#[test]
+fn imported_view_replaces_bootstrap() {
+    let mut first = create_node(9000);
+    first.start_isolated(Box::new(NoopUpdateHandler)).unwrap();
+    let mut second = create_node(9001);
+    second.start_with_peers(vec![peer(9000)], Box::new(NoopUpdateHandler)).unwrap();
+
+    let exported = second.export_view().unwrap();
+    assert!(exported.contains("\"127.0.0.1:9000\""));
+
+    // the third node is seeded with the view of the second one
+    let mut third = create_node(9002);
+    assert_eq!(1, third.import_view(&exported).unwrap());
+    let bootstrapped = Arc::new(AtomicBool::new(false));
+    let flag = Arc::clone(&bootstrapped);
+    third.start(Box::new(move || {
+        flag.store(true, Ordering::SeqCst);
+        None
+    }), Box::new(NoopUpdateHandler)).unwrap();
+    assert!(third.import_view(&exported).is_err());
+
+    // round trip: the imported peers are exported again
+    let reexported = third.export_view().unwrap();
+    assert!(reexported.contains("\"127.0.0.1:9000\""));
+    assert_eq!(1, third.view_summary().peer_count);
+
+    first.submit(b"seeded".to_vec()).unwrap();
+    wait_for_within(|| third.is_active(b"seeded".to_vec()), Duration::from_secs(10), "update did not reach the seeded node");
+    assert!(!bootstrapped.load(Ordering::SeqCst));
+
+    third.shutdown().unwrap();
+    second.shutdown().unwrap();
+    first.shutdown().unwrap();
+}
+
+#[test]
+fn import_validates_and_caps_the_view() {
+    let service = create_node(9010);
+    let invalid = r#"{"node": "127.0.0.1:9011", "peers": [{"address": "not an address"}]}"#;
+    assert!(service.import_view(invalid).is_err());
+
+    // the own address and the duplicates are skipped, and the view size is 3
+    let view = r#"{"node": "127.0.0.1:9011", "peers": [
+        {"address": "127.0.0.1:9010"},
+        {"address": "127.0.0.1:9012", "age": 2, "staleness_millis": 1500},
+        {"address": "127.0.0.1:9012"},
+        {"address": "127.0.0.1:9013"},
+        {"address": "127.0.0.1:9014"},
+        {"address": "127.0.0.1:9015"}
+    ]}"#;
+    assert_eq!(3, service.import_view(view).unwrap());
+}
+
+#[test]
+fn summary_counts_ages() {
+    let mut service = create_node(9020);
+    let view = r#"{"node": "127.0.0.1:9021", "peers": [
+        {"address": "127.0.0.1:9022", "age": 2, "staleness_millis": 1500},
+        {"address": "127.0.0.1:9023"}
+    ]}"#;
+    service.import_view(view).unwrap();
+    assert_eq!(0, service.view_summary().peer_count);
+    service.start_isolated(Box::new(NoopUpdateHandler)).unwrap();
+
+    let summary = service.view_summary();
+    assert_eq!(2, summary.peer_count);
+    assert_eq!(Some(&1), summary.ages.get(&2));
+    assert_eq!(Some(&1), summary.ages.get(&0));
+    assert_eq!(1, summary.unknown_staleness);
+    assert!(summary.max_staleness.unwrap() >= Duration::from_millis(1500));
+    assert!(summary.to_string().starts_with("2 peers, ages {0: 1, 2: 1}"), "{}", summary);
+
+    service.shutdown().unwrap();
+}
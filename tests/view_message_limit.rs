@@ -0,0 +1,75 @@
+mod common;
+
+use std::sync::{Arc, Mutex};
+use gossip::{GossipConfig, GossipService, Peer, PeerSamplingConfig, RoundObserver, RoundSummary, SamplingSummary, UpdateExpirationMode};
+use common::NoopUpdateHandler;
+
+const SAMPLING_PERIOD: u64 = 300;
+const LARGE_VIEW_SIZE: usize = 20000;
+const PEER_COUNT: usize = 10000;
+
+// peers on loopback addresses where nothing listens
+fn unreachable_peers() -> Vec<Peer> {
+    (0..PEER_COUNT).map(|i| Peer::try_new(&format!("127.1.{}.{}:1", i / 250, i % 250 + 1)).unwrap()).collect()
+}
+
+struct BufferSizes(Mutex<Vec<usize>>);
+impl RoundObserver for BufferSizes {
+    fn on_gossip_round(&self, _: RoundSummary) {}
+    fn on_sampling_round(&self, summary: SamplingSummary) {
+        self.0.lock().unwrap().push(summary.buffer_size);
+    }
+}
+
+#[test]
+fn built_buffers_are_capped() {
+    let mut sampling_config = PeerSamplingConfig::new(true, false, SAMPLING_PERIOD, LARGE_VIEW_SIZE, 1, 1);
+    sampling_config.set_max_view_message_peers(100);
+    let mut service = GossipService::new(
+        "127.0.0.1:9000".parse().unwrap(),
+        sampling_config,
+        GossipConfig::new(true, false, 200, UpdateExpirationMode::None)
+    );
+    let observer = Arc::new(BufferSizes(Mutex::new(Vec::new())));
+    service.set_round_observer(Some(observer.clone()));
+    service.start_with_peers(unreachable_peers(), Box::new(NoopUpdateHandler)).unwrap();
+
+    std::thread::sleep(std::time::Duration::from_millis(SAMPLING_PERIOD * 4));
+    let sizes = observer.0.lock().unwrap().clone();
+    service.shutdown().unwrap();
+
+    assert!(!sizes.is_empty());
+    assert!(sizes.iter().all(|size| *size == 100), "buffer sizes: {:?}", sizes);
+}
+
+#[test]
+fn oversized_buffers_are_rejected() {
+    // node responding with its large view
+    let mut large_config = PeerSamplingConfig::new(false, true, SAMPLING_PERIOD, LARGE_VIEW_SIZE, 1, 1);
+    large_config.set_max_view_message_peers(LARGE_VIEW_SIZE);
+    let mut large = GossipService::new(
+        "127.0.0.1:9010".parse().unwrap(),
+        large_config,
+        GossipConfig::new(true, false, 200, UpdateExpirationMode::None)
+    );
+    large.start_with_peers(unreachable_peers(), Box::new(NoopUpdateHandler)).unwrap();
+
+    // node pulling from it with a small limit
+    let mut small_config = PeerSamplingConfig::new(false, true, SAMPLING_PERIOD, 10, 1, 4);
+    small_config.set_max_view_message_peers(100);
+    let mut small = GossipService::new(
+        "127.0.0.1:9011".parse().unwrap(),
+        small_config,
+        GossipConfig::new(true, false, 200, UpdateExpirationMode::None)
+    );
+    small.start_with_peers(vec![Peer::try_new("127.0.0.1:9010").unwrap()], Box::new(NoopUpdateHandler)).unwrap();
+
+    std::thread::sleep(std::time::Duration::from_millis(SAMPLING_PERIOD * 4));
+
+    let peers = small.peers();
+    assert_eq!(1, peers.len(), "unexpected peers: {:?}", peers);
+    assert_eq!("127.0.0.1:9010", peers[0].address());
+
+    small.shutdown().unwrap();
+    large.shutdown().unwrap();
+}
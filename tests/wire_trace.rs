@@ -0,0 +1,74 @@
+mod common;
+
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use gossip::{Direction, GossipConfig, GossipService, Update, UpdateExpirationMode, WireTracer};
+use common::{start_static_node, wait_for, NoopUpdateHandler};
+
+type Captured = Arc<Mutex<Vec<(Direction, SocketAddr, Vec<u8>)>>>;
+
+fn capturing_tracer(captured: &Captured) -> WireTracer {
+    let captured = Arc::clone(captured);
+    Box::new(move |direction, peer, bytes| captured.lock().unwrap().push((direction, *peer, bytes.to_vec())))
+}
+
+fn start_node(port: u16, peer: u16) -> GossipService<NoopUpdateHandler> {
+    start_static_node(port, &[peer], GossipConfig::new(true, true, 100, UpdateExpirationMode::None), NoopUpdateHandler)
+}
+
+fn contains(bytes: &[u8], pattern: &[u8]) -> bool {
+    bytes.windows(pattern.len()).any(|window| window == pattern)
+}
+
+#[test]
+fn traced_bytes_match_on_both_nodes() {
+    let a = start_node(9000, 9001);
+    let b = start_node(9001, 9000);
+    let (sent, received): (Captured, Captured) = Default::default();
+    a.set_wire_trace(Some(capturing_tracer(&sent)));
+    b.set_wire_trace(Some(capturing_tracer(&received)));
+
+    let update = b"traced update".to_vec();
+    let digest = Update::new(update.clone()).digest().clone();
+    a.submit(update).unwrap();
+
+    // the header message pushing the digest from A to B arrives unchanged
+    let b_address: SocketAddr = "127.0.0.1:9001".parse().unwrap();
+    wait_for(|| {
+        let pushed: Vec<Vec<u8>> = sent.lock().unwrap().iter()
+            .filter(|(direction, peer, bytes)| *direction == Direction::Outbound && *peer == b_address && contains(bytes, digest.as_bytes()))
+            .map(|(_, _, bytes)| bytes.clone())
+            .collect();
+        received.lock().unwrap().iter().any(|(direction, _, bytes)| *direction == Direction::Inbound && pushed.contains(bytes))
+    }, "header message was not traced on both nodes");
+
+    // removing the tracer stops the capture
+    a.set_wire_trace(None);
+    b.set_wire_trace(None);
+    let count = (sent.lock().unwrap().len(), received.lock().unwrap().len());
+    std::thread::sleep(Duration::from_millis(300));
+    assert_eq!(count, (sent.lock().unwrap().len(), received.lock().unwrap().len()));
+
+    b.shutdown().unwrap();
+    a.shutdown().unwrap();
+}
+
+#[test]
+fn hexdump_is_written_to_file() {
+    let path = std::env::temp_dir().join(format!("gossip-wire-trace-{}.txt", std::process::id()));
+    let a = start_node(9002, 9003);
+    let b = start_node(9003, 9002);
+    a.set_wire_trace(Some(gossip::hexdump_tracer(&path).unwrap()));
+    std::thread::sleep(Duration::from_millis(500));
+    a.set_wire_trace(None);
+
+    let dump = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert!(dump.contains(" outbound to 127.0.0.1:9003 "));
+    assert!(dump.contains(" inbound from 127.0.0.1:"));
+    assert!(dump.lines().any(|line| line.starts_with("000000 ")));
+
+    b.shutdown().unwrap();
+    a.shutdown().unwrap();
+}